@@ -0,0 +1,65 @@
+/// Benchmark: AST evaluation vs compiled bytecode on a 500-variable model
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rssdsim::model::expression::compile;
+use rssdsim::model::{Auxiliary, Model, Parameter, Program, SymbolTable};
+use rssdsim::simulation::integrator::evaluate_system;
+use rssdsim::simulation::SimulationState;
+
+const VARIABLES: usize = 500;
+
+/// Chain of auxiliaries, each depending on its predecessor and a parameter
+fn build_model() -> Model {
+    let mut model = Model::new("Benchmark");
+    for i in 0..VARIABLES {
+        model.add_parameter(Parameter::new(&format!("p{}", i), 1.0 + i as f64 * 0.01)).unwrap();
+        let equation = if i == 0 {
+            "p0 * 2".to_string()
+        } else {
+            format!("IF a{prev} > 100 THEN a{prev} * 0.5 ELSE a{prev} * 0.99 + p{i} / (1 + ABS(a{prev}))", prev = i - 1, i = i)
+        };
+        model.add_auxiliary(Auxiliary::new(&format!("a{}", i), &equation)).unwrap();
+    }
+    model.compile().unwrap();
+    model
+}
+
+fn bench_evaluation(c: &mut Criterion) {
+    let model = build_model();
+    let mut group = c.benchmark_group("eval_500_variables");
+
+    let mut state = SimulationState::initialize_from_model(&model).unwrap();
+    group.bench_function("ast", |b| {
+        b.iter(|| {
+            evaluate_system(&model, &mut state, 0.0).unwrap();
+            black_box(state.auxiliaries.get("a499").copied())
+        })
+    });
+
+    let symbols = SymbolTable::from_model(&model);
+    let mut values = vec![0.0; symbols.len()];
+    for (name, param) in &model.parameters {
+        values[symbols.get(name).unwrap()] = param.value;
+    }
+    let programs: Vec<(usize, Program)> = model.evaluation_order.as_ref().unwrap().iter()
+        .map(|name| {
+            let target = symbols.get(name).unwrap();
+            (target, compile(&model.auxiliaries[name].equation, &symbols).unwrap())
+        })
+        .collect();
+    let mut stack = Vec::new();
+
+    group.bench_function("bytecode", |b| {
+        b.iter(|| {
+            for (target, program) in &programs {
+                values[*target] = program.execute_with_stack(&values, 0.0, &mut stack).unwrap();
+            }
+            black_box(values[symbols.get("a499").unwrap()])
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_evaluation);
+criterion_main!(benches);
@@ -0,0 +1,39 @@
+/// Benchmark: whole runs of the standard models with Euler, RK4 and RK45
+
+mod models;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use rssdsim::model::Model;
+use rssdsim::simulation::{IntegrationMethod, SimulationConfig, SimulationEngine};
+
+const METHODS: [(&str, IntegrationMethod); 3] = [
+    ("euler", IntegrationMethod::Euler),
+    ("rk4", IntegrationMethod::RK4),
+    ("rk45", IntegrationMethod::RK45),
+];
+
+fn bench_integrators(c: &mut Criterion) {
+    let models: [(&str, Model); 4] = [
+        ("logistic", models::logistic()),
+        ("predator_prey", models::predator_prey()),
+        ("sir", models::sir()),
+        ("bass_diffusion", models::bass_diffusion()),
+    ];
+
+    for (name, model) in models {
+        let mut group = c.benchmark_group(format!("integrate_{}", name));
+        for (method_name, method) in METHODS {
+            let config = SimulationConfig { integration_method: method, ..SimulationConfig::default() };
+            group.bench_with_input(BenchmarkId::from_parameter(method_name), &config, |b, config| {
+                b.iter(|| {
+                    let mut engine = SimulationEngine::new(model.clone(), config.clone()).unwrap();
+                    black_box(engine.run().unwrap().len())
+                })
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_integrators);
+criterion_main!(benches);
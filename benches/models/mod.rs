@@ -0,0 +1,72 @@
+/// Standard system dynamics models shared by the benchmarks
+
+use rssdsim::model::{Auxiliary, Flow, Model, Parameter, Stock};
+
+fn flows(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| name.to_string()).collect()
+}
+
+/// Logistic growth towards a carrying capacity
+pub fn logistic() -> Model {
+    let mut model = Model::new("Logistic");
+    model.time.stop = 100.0;
+    model.time.dt = 0.25;
+    model.add_parameter(Parameter::new("r", 0.1)).unwrap();
+    model.add_parameter(Parameter::new("K", 1000.0)).unwrap();
+    model.add_stock(Stock::new("Population", "10").with_inflows(flows(&["growth"]))).unwrap();
+    model.add_flow(Flow::new("growth", "r * Population * (1 - Population / K)")).unwrap();
+    model
+}
+
+/// Lotka-Volterra predator-prey cycles
+pub fn predator_prey() -> Model {
+    let mut model = Model::new("Predator-prey");
+    model.time.stop = 200.0;
+    model.time.dt = 0.125;
+    for (name, value) in [("alpha", 0.1), ("beta", 0.02), ("delta", 0.01), ("gamma", 0.1)] {
+        model.add_parameter(Parameter::new(name, value)).unwrap();
+    }
+    model.add_stock(Stock::new("Prey", "40")
+        .with_inflows(flows(&["prey_births"]))
+        .with_outflows(flows(&["predation"]))).unwrap();
+    model.add_stock(Stock::new("Predators", "9")
+        .with_inflows(flows(&["predator_births"]))
+        .with_outflows(flows(&["predator_deaths"]))).unwrap();
+    model.add_flow(Flow::new("prey_births", "alpha * Prey")).unwrap();
+    model.add_flow(Flow::new("predation", "beta * Prey * Predators")).unwrap();
+    model.add_flow(Flow::new("predator_births", "delta * Prey * Predators")).unwrap();
+    model.add_flow(Flow::new("predator_deaths", "gamma * Predators")).unwrap();
+    model
+}
+
+/// SIR epidemic in a closed population
+pub fn sir() -> Model {
+    let mut model = Model::new("SIR");
+    model.time.stop = 160.0;
+    model.time.dt = 0.25;
+    model.add_parameter(Parameter::new("beta", 0.3)).unwrap();
+    model.add_parameter(Parameter::new("gamma", 0.1)).unwrap();
+    model.add_stock(Stock::new("Susceptible", "990").with_outflows(flows(&["infection"]))).unwrap();
+    model.add_stock(Stock::new("Infected", "10")
+        .with_inflows(flows(&["infection"]))
+        .with_outflows(flows(&["recovery"]))).unwrap();
+    model.add_stock(Stock::new("Recovered", "0").with_inflows(flows(&["recovery"]))).unwrap();
+    model.add_auxiliary(Auxiliary::new("population", "Susceptible + Infected + Recovered")).unwrap();
+    model.add_flow(Flow::new("infection", "beta * Susceptible * Infected / population")).unwrap();
+    model.add_flow(Flow::new("recovery", "gamma * Infected")).unwrap();
+    model
+}
+
+/// Bass diffusion of a new product
+pub fn bass_diffusion() -> Model {
+    let mut model = Model::new("Bass diffusion");
+    model.time.stop = 30.0;
+    model.time.dt = 0.0625;
+    model.add_parameter(Parameter::new("p", 0.03)).unwrap();
+    model.add_parameter(Parameter::new("q", 0.38)).unwrap();
+    model.add_parameter(Parameter::new("market", 10000.0)).unwrap();
+    model.add_stock(Stock::new("Potential", "10000").with_outflows(flows(&["adoption"]))).unwrap();
+    model.add_stock(Stock::new("Adopters", "0").with_inflows(flows(&["adoption"]))).unwrap();
+    model.add_flow(Flow::new("adoption", "(p + q * Adopters / market) * Potential")).unwrap();
+    model
+}
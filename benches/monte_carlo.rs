@@ -0,0 +1,40 @@
+/// Benchmark: parallel Monte Carlo over the SIR model as threads are added
+
+// Only the SIR model is used here
+#[allow(dead_code)]
+mod models;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rssdsim::analysis::{MonteCarloConfig, ParallelMonteCarloSimulator, ParameterRange};
+use rssdsim::simulation::SimulationConfig;
+
+const RUNS: usize = 64;
+
+fn bench_monte_carlo(c: &mut Criterion) {
+    let model = models::sir();
+    let simulator = ParallelMonteCarloSimulator::new(
+        vec![
+            ParameterRange::new("beta".to_string(), 0.2, 0.4, 0.3),
+            ParameterRange::new("gamma".to_string(), 0.05, 0.15, 0.1),
+        ],
+        MonteCarloConfig { n_runs: RUNS, seed: Some(42), ..MonteCarloConfig::default() },
+    );
+    let config = SimulationConfig::default();
+
+    let available = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let threads: Vec<usize> = [1, 2, 4, 8, 16].into_iter().filter(|&n| n <= available).collect();
+
+    let mut group = c.benchmark_group("monte_carlo_sir");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(RUNS as u64));
+    for n in threads {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(n).build().unwrap();
+        group.bench_with_input(BenchmarkId::new("threads", n), &n, |b, _| {
+            b.iter(|| pool.install(|| black_box(simulator.run(&model, &config).unwrap().n_runs)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_monte_carlo);
+criterion_main!(benches);
@@ -0,0 +1,75 @@
+/// Benchmark: integrator stage evaluation on a 1000-equation hybrid model,
+/// into a scratch buffer vs into a clone of the whole state
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rssdsim::model::{Auxiliary, Flow, Model, Parameter, Stock};
+use rssdsim::simulation::integrator::{evaluate_into, evaluate_system};
+use rssdsim::simulation::{AgentType, EvaluationScratch, Integrator, RK4Integrator, SimulationState};
+
+const STOCKS: usize = 250;
+const AUXILIARIES_PER_STOCK: usize = 3;
+const AGENTS: usize = 10_000;
+
+/// Stocks draining through a chain of auxiliaries each: 250 flows and 750
+/// auxiliaries, one of them counting the agents
+fn build_model() -> Model {
+    let mut model = Model::new("Benchmark");
+    model.add_parameter(Parameter::new("rate", 0.01)).unwrap();
+    for i in 0..STOCKS {
+        model.add_stock(Stock::new(&format!("s{}", i), "100").with_outflows(vec![format!("f{}", i)])).unwrap();
+        for j in 0..AUXILIARIES_PER_STOCK {
+            let equation = if i == 0 && j == 0 {
+                "s0 * rate * AGENT_COUNT() / 10000".to_string()
+            } else if j == 0 {
+                format!("s{} * rate", i)
+            } else {
+                format!("a{}_{} * 0.99 + s{} / (1 + ABS(a{}_{}))", i, j - 1, (i + 1) % STOCKS, i, j - 1)
+            };
+            model.add_auxiliary(Auxiliary::new(&format!("a{}_{}", i, j), &equation)).unwrap();
+        }
+        let equation = format!("a{}_{}", i, AUXILIARIES_PER_STOCK - 1);
+        model.add_flow(Flow::new(&format!("f{}", i), &equation)).unwrap();
+    }
+    model.compile().unwrap();
+    model
+}
+
+fn bench_stage(c: &mut Criterion) {
+    let model = build_model();
+    let mut state = SimulationState::initialize_from_model(&model).unwrap();
+    let mut person = AgentType::new("Person".to_string());
+    person.add_attribute("age".to_string(), 30.0);
+    person.add_attribute("income".to_string(), 1000.0);
+    state.agents.register_type(person);
+    state.agents.create_agents("Person", AGENTS).unwrap();
+    let stocks = state.stocks.clone();
+    let integrator = RK4Integrator;
+
+    let mut group = c.benchmark_group("stage_1000_equations");
+
+    group.bench_function("clone_state", |b| {
+        b.iter(|| {
+            let mut stage = state.clone();
+            stage.stocks = stocks.clone();
+            evaluate_system(&model, &mut stage, state.time).unwrap();
+            black_box(stage.flows.get("f0").copied())
+        })
+    });
+
+    group.bench_function("scratch", |b| {
+        b.iter(|| {
+            let mut scratch = EvaluationScratch::new(&state);
+            evaluate_into(&model, &state, &stocks, &mut scratch, state.time).unwrap();
+            black_box(scratch.flows.get("f0").copied())
+        })
+    });
+
+    group.bench_function("rk4_step", |b| {
+        b.iter(|| black_box(integrator.step(&model, &state, 0.25).unwrap().time))
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_stage);
+criterion_main!(benches);
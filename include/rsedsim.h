@@ -0,0 +1,45 @@
+/* C interface to the rsedsim system dynamics engine
+ *
+ * Link against the shared library built by `cargo build --release`
+ * (librssdsim.so, librssdsim.dylib or rssdsim.dll). Functions that fail
+ * return NULL or -1; rsedsim_last_error() then says why.
+ */
+
+#ifndef RSEDSIM_H
+#define RSEDSIM_H
+
+#include <stddef.h>
+#include <sys/types.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* A loaded model and the results of its last run */
+typedef struct RsedsimSimulation RsedsimSimulation;
+
+/* Message of the last failed call on this thread, or NULL */
+const char *rsedsim_last_error(void);
+
+/* Load a JSON, YAML, XMILE, InsightMaker or Vensim model file */
+RsedsimSimulation *rsedsim_load_model(const char *path);
+
+/* Run the model; integrator is "euler", "rk4", "rk45", "heun",
+ * "backward-euler" or "bdf", NULL for Euler. Returns 0 on success. */
+int rsedsim_run(RsedsimSimulation *sim, const char *integrator);
+
+/* Copy up to capacity values of a variable from the last run into out and
+ * return the full length of the series; pass out = NULL to size a buffer. */
+ssize_t rsedsim_get_series(const RsedsimSimulation *sim, const char *name, double *out, size_t capacity);
+
+/* Copy the recorded times of the last run, like rsedsim_get_series */
+ssize_t rsedsim_get_times(const RsedsimSimulation *sim, double *out, size_t capacity);
+
+/* Free a simulation; NULL is ignored */
+void rsedsim_free(RsedsimSimulation *sim);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* RSEDSIM_H */
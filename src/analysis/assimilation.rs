@@ -0,0 +1,352 @@
+/// Data assimilation with an Ensemble Kalman Filter
+///
+/// An ensemble of runs with perturbed stocks carries the uncertainty of the
+/// model state. At each observation time the stocks of every member are
+/// nudged towards the measurements, weighted by the ensemble's covariance
+/// between stocks and observed variables (the stochastic EnKF with perturbed
+/// observations). Observed variables may be stocks, flows or auxiliaries.
+/// Past the last observation the ensemble runs on freely as a forecast.
+
+use std::collections::BTreeMap;
+use nalgebra::DMatrix;
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use rand_distr::StandardNormal;
+use crate::error::{ModelError, Result, SimulationError};
+use crate::model::Model;
+use crate::simulation::{SimulationEngine, SimulationConfig, SimulationState, IntegrationMethod};
+use super::calibration::ObservedData;
+
+/// Filter settings
+#[derive(Debug, Clone)]
+pub struct AssimilationConfig {
+    /// Number of ensemble members
+    pub ensemble_size: usize,
+    /// Standard deviation of measurement errors, as a fraction of the observed value
+    pub observation_error: f64,
+    /// Standard deviation of the random model error added to each stock, as a
+    /// fraction of its value per square root of time unit
+    pub process_noise: f64,
+    /// Standard deviation of the initial stocks, as a fraction of their values
+    pub initial_spread: f64,
+    /// Random seed for reproducible ensembles
+    pub seed: Option<u64>,
+    /// Integration method for simulation
+    pub integration_method: IntegrationMethod,
+}
+
+impl Default for AssimilationConfig {
+    fn default() -> Self {
+        Self {
+            ensemble_size: 50,
+            observation_error: 0.05,
+            process_noise: 0.02,
+            initial_spread: 0.1,
+            seed: None,
+            integration_method: IntegrationMethod::RK4,
+        }
+    }
+}
+
+/// One assimilated measurement
+#[derive(Debug, Clone)]
+pub struct Innovation {
+    pub time: f64,
+    pub variable: String,
+    pub observed: f64,
+    /// Ensemble mean and standard deviation of the variable before the update
+    pub forecast: f64,
+    pub forecast_sd: f64,
+}
+
+/// Ensemble statistics over the run
+#[derive(Debug, Clone)]
+pub struct AssimilationResults {
+    pub times: Vec<f64>,
+    /// Ensemble mean of every stock and observed variable at each time; stocks
+    /// are recorded after the update at observation times, flows and
+    /// auxiliaries before it
+    pub mean: BTreeMap<String, Vec<f64>>,
+    /// Ensemble standard deviation, like `mean`
+    pub sd: BTreeMap<String, Vec<f64>>,
+    pub innovations: Vec<Innovation>,
+}
+
+impl AssimilationResults {
+    /// CSV with a `<name>_mean` and `<name>_sd` column per variable
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("time");
+        for name in self.mean.keys() {
+            csv.push_str(&format!(",{}_mean,{}_sd", name, name));
+        }
+        csv.push('\n');
+        for (i, time) in self.times.iter().enumerate() {
+            csv.push_str(&time.to_string());
+            for (name, mean) in &self.mean {
+                csv.push_str(&format!(",{},{}", mean[i], self.sd[name][i]));
+            }
+            csv.push('\n');
+        }
+        csv
+    }
+
+    /// CSV of the assimilated measurements against their forecasts
+    pub fn innovations_csv(&self) -> String {
+        let mut csv = String::from("time,variable,observed,forecast,forecast_sd\n");
+        for innovation in &self.innovations {
+            csv.push_str(&format!(
+                "{},{},{},{},{}\n",
+                innovation.time, innovation.variable, innovation.observed, innovation.forecast, innovation.forecast_sd
+            ));
+        }
+        csv
+    }
+}
+
+/// Stochastic Ensemble Kalman Filter over the stocks of a model
+pub struct EnsembleKalmanFilter {
+    config: AssimilationConfig,
+}
+
+impl EnsembleKalmanFilter {
+    pub fn new(config: AssimilationConfig) -> Self {
+        Self { config }
+    }
+
+    /// Run the ensemble to the model's stop time, assimilating `data` on the way
+    ///
+    /// An observation is assimilated at the first time step at or after its time.
+    pub fn run(&self, model: &Model, data: &ObservedData) -> Result<AssimilationResults> {
+        if self.config.ensemble_size < 2 {
+            return Err(SimulationError::from("The ensemble needs at least 2 members".to_string()).into());
+        }
+        if self.config.observation_error <= 0.0 {
+            return Err(SimulationError::from("Observation error must be positive".to_string()).into());
+        }
+
+        let mut rng = match self.config.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+
+        let sim_config = SimulationConfig {
+            integration_method: self.config.integration_method,
+            ..SimulationConfig::default()
+        };
+        let mut members = (0..self.config.ensemble_size)
+            .map(|_| SimulationEngine::new(model.clone(), sim_config.clone()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let stocks: Vec<String> = members[0].current_state().stocks.keys().cloned().collect();
+        let non_negative: Vec<bool> = stocks.iter()
+            .map(|name| model.stocks.get(name).is_some_and(|stock| stock.non_negative))
+            .collect();
+        for name in data.series.keys() {
+            if observe(members[0].current_state(), name).is_none() {
+                return Err(ModelError::NotFound { kind: "Observed variable", name: name.clone() }.into());
+            }
+        }
+
+        let mut order: Vec<usize> = (0..data.times.len()).collect();
+        order.sort_by(|&a, &b| data.times[a].total_cmp(&data.times[b]));
+
+        let mut recorded: Vec<String> = stocks.clone();
+        recorded.extend(data.series.keys().filter(|name| !stocks.contains(name)).cloned());
+        let mut results = AssimilationResults {
+            times: Vec::new(),
+            mean: recorded.iter().map(|name| (name.clone(), Vec::new())).collect(),
+            sd: recorded.iter().map(|name| (name.clone(), Vec::new())).collect(),
+            innovations: Vec::new(),
+        };
+
+        perturb(&mut members, &stocks, &non_negative, self.config.initial_spread, &mut rng)?;
+
+        let dt = model.time.dt;
+        let mut next = 0;
+        loop {
+            let time = members[0].current_time();
+            while next < order.len() && data.times[order[next]] <= time + dt * 1e-6 {
+                self.analyse(&mut members, &stocks, &non_negative, data, order[next], time, &mut results, &mut rng)?;
+                next += 1;
+            }
+
+            results.times.push(time);
+            for name in &recorded {
+                let values: Vec<f64> = members.iter()
+                    .map(|member| observe(member.current_state(), name).unwrap_or(f64::NAN))
+                    .collect();
+                let (mean, sd) = mean_sd(&values);
+                results.mean.get_mut(name).expect("recorded variable").push(mean);
+                results.sd.get_mut(name).expect("recorded variable").push(sd);
+            }
+
+            if members[0].is_finished() {
+                break;
+            }
+            for member in &mut members {
+                member.step()?;
+            }
+            perturb(&mut members, &stocks, &non_negative, self.config.process_noise * dt.sqrt(), &mut rng)?;
+        }
+
+        Ok(results)
+    }
+
+    /// Update the stocks of every member with the observations in data row `row`
+    #[allow(clippy::too_many_arguments)]
+    fn analyse(
+        &self,
+        members: &mut [SimulationEngine],
+        stocks: &[String],
+        non_negative: &[bool],
+        data: &ObservedData,
+        row: usize,
+        time: f64,
+        results: &mut AssimilationResults,
+        rng: &mut impl Rng,
+    ) -> Result<()> {
+        let observed: Vec<(&String, f64)> = data.series.iter()
+            .map(|(name, values)| (name, values[row]))
+            .filter(|(_, value)| !value.is_nan())
+            .collect();
+        if observed.is_empty() {
+            return Ok(());
+        }
+
+        let n = members.len();
+        let states = DMatrix::from_fn(stocks.len(), n, |i, j| members[j].current_state().stocks[&stocks[i]]);
+        let predicted = DMatrix::from_fn(observed.len(), n, |i, j| {
+            observe(members[j].current_state(), observed[i].0).unwrap_or(f64::NAN)
+        });
+        let error_sd: Vec<f64> = observed.iter()
+            .map(|(_, value)| (self.config.observation_error * value.abs()).max(1e-9))
+            .collect();
+
+        for (i, (name, value)) in observed.iter().enumerate() {
+            let (forecast, forecast_sd) = mean_sd(&predicted.row(i).iter().copied().collect::<Vec<_>>());
+            results.innovations.push(Innovation {
+                time,
+                variable: name.to_string(),
+                observed: *value,
+                forecast,
+                forecast_sd,
+            });
+        }
+
+        let anomalies = |m: &DMatrix<f64>| {
+            let mut m = m.clone();
+            for mut row in m.row_iter_mut() {
+                let mean = row.mean();
+                row.add_scalar_mut(-mean);
+            }
+            m
+        };
+        let (state_anomalies, predicted_anomalies) = (anomalies(&states), anomalies(&predicted));
+        let cross = &state_anomalies * predicted_anomalies.transpose() / (n - 1) as f64;
+        let mut innovation_cov = &predicted_anomalies * predicted_anomalies.transpose() / (n - 1) as f64;
+        for (i, sd) in error_sd.iter().enumerate() {
+            innovation_cov[(i, i)] += sd * sd;
+        }
+
+        // K = cross * innovation_cov^-1, via the transposed system
+        let gain_t = innovation_cov.cholesky()
+            .ok_or_else(|| SimulationError::Failed { time: Some(time), message: "Singular innovation covariance".to_string() })?
+            .solve(&cross.transpose());
+
+        for (j, member) in members.iter_mut().enumerate() {
+            let departures = nalgebra::DVector::from_fn(observed.len(), |i, _| {
+                let noise: f64 = rng.sample(StandardNormal);
+                observed[i].1 + error_sd[i] * noise - predicted[(i, j)]
+            });
+            let update = gain_t.transpose() * departures;
+            for (i, name) in stocks.iter().enumerate() {
+                let value = states[(i, j)] + update[i];
+                member.set_stock(name, if non_negative[i] { value.max(0.0) } else { value })?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Current value of a stock, flow or auxiliary
+fn observe(state: &SimulationState, name: &str) -> Option<f64> {
+    state.stocks.get(name)
+        .or_else(|| state.flows.get(name))
+        .or_else(|| state.auxiliaries.get(name))
+        .copied()
+}
+
+/// Multiply each stock of each member by `1 + scale * z` with standard normal `z`
+fn perturb(
+    members: &mut [SimulationEngine],
+    stocks: &[String],
+    non_negative: &[bool],
+    scale: f64,
+    rng: &mut impl Rng,
+) -> Result<()> {
+    if scale == 0.0 {
+        return Ok(());
+    }
+    for member in members {
+        for (name, &non_negative) in stocks.iter().zip(non_negative) {
+            let noise: f64 = rng.sample(StandardNormal);
+            let value = member.current_state().stocks[name] * (1.0 + scale * noise);
+            member.set_stock(name, if non_negative { value.max(0.0) } else { value })?;
+        }
+    }
+    Ok(())
+}
+
+/// Mean and sample standard deviation
+fn mean_sd(values: &[f64]) -> (f64, f64) {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0).max(1.0);
+    (mean, variance.sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Stock, Flow, Parameter};
+
+    #[test]
+    fn test_filter_tracks_observations() {
+        // The model decays at 0.5; the observed system at 0.3
+        let mut model = Model::new("Decay");
+        model.time.stop = 5.0;
+        model.time.dt = 0.1;
+        model.add_stock(Stock::new("X", "100")).unwrap();
+        model.add_parameter(Parameter::new("k", 0.5)).unwrap();
+        model.add_flow(Flow::new("decay", "k * X")).unwrap();
+        model.stocks.get_mut("X").unwrap().outflows.push("decay".to_string());
+
+        let mut csv = String::from("time,X\n");
+        for i in 0..=6 {
+            let t = i as f64 * 0.5;
+            csv.push_str(&format!("{},{}\n", t, 100.0 * (-0.3 * t).exp()));
+        }
+        let data = ObservedData::from_csv(&csv).unwrap();
+
+        // Process noise large enough to cover the model error between observations
+        let config = AssimilationConfig { process_noise: 0.2, seed: Some(7), ..AssimilationConfig::default() };
+        let unobserved = ObservedData::from_csv("time,Y\n0,1\n").unwrap();
+        let err = EnsembleKalmanFilter::new(config.clone()).run(&model, &unobserved).unwrap_err();
+        assert!(matches!(err, crate::Error::Model(ModelError::NotFound { .. })), "{}", err);
+
+        let results = EnsembleKalmanFilter::new(config).run(&model, &data).unwrap();
+
+        assert_eq!(results.times.len(), 51);
+        assert_eq!(results.innovations.len(), 7);
+        let at = |t: f64| results.times.iter().position(|&time| (time - t).abs() < 1e-9).unwrap();
+        let truth = 100.0 * (-0.3f64 * 3.0).exp();
+        let filtered = results.mean["X"][at(3.0)];
+        let free_run = 100.0 * (-0.5f64 * 3.0).exp();
+        assert!((filtered - truth).abs() < 0.25 * (free_run - truth).abs(), "filtered {} truth {}", filtered, truth);
+        // The forecast past the data spreads out again
+        let relative_sd = |t: f64| results.sd["X"][at(t)] / results.mean["X"][at(t)];
+        assert!(relative_sd(5.0) > relative_sd(3.0));
+        assert!(results.to_csv().starts_with("time,X_mean,X_sd\n0,"));
+    }
+}
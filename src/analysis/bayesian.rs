@@ -0,0 +1,583 @@
+/// Bayesian calibration by Markov chain Monte Carlo
+///
+/// A random-walk Metropolis-Hastings sampler draws parameter values from their
+/// posterior given observed time series: the prior of each parameter times a
+/// Gaussian likelihood of the residuals. Several chains run in parallel from
+/// different starting points; their agreement (split R-hat) and the effective
+/// sample size show whether the draws can be trusted.
+
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use rand_distr::{LogNormal, Normal, StandardNormal};
+use rayon::prelude::*;
+use crate::error::{ModelError, Result, SimulationError};
+use crate::model::Model;
+use crate::simulation::{SimulationEngine, SimulationConfig, IntegrationMethod};
+use super::calibration::ObservedData;
+use super::monte_carlo::MonteCarloSimulator;
+
+/// ln(sqrt(2 pi))
+const LN_SQRT_2PI: f64 = 0.918_938_533_204_672_8;
+
+/// Acceptance rate the proposal step is tuned towards during burn-in
+const TARGET_ACCEPTANCE: f64 = 0.234;
+
+/// Burn-in iterations between adjustments of the proposal step
+const ADAPT_INTERVAL: usize = 50;
+
+/// Prior draws tried when looking for a starting point
+const MAX_START_ATTEMPTS: usize = 100;
+
+/// Prior distribution of a parameter
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Prior {
+    Uniform { min: f64, max: f64 },
+    Normal { mean: f64, sd: f64 },
+    /// `ln x` is normal with mean `mu` and standard deviation `sigma`
+    LogNormal { mu: f64, sigma: f64 },
+}
+
+impl Prior {
+    /// Log probability density at `x`
+    pub fn log_density(&self, x: f64) -> f64 {
+        match *self {
+            Prior::Uniform { min, max } if (min..=max).contains(&x) => -(max - min).ln(),
+            Prior::Normal { mean, sd } => {
+                let z = (x - mean) / sd;
+                -0.5 * z * z - sd.ln() - LN_SQRT_2PI
+            }
+            Prior::LogNormal { mu, sigma } if x > 0.0 => {
+                let z = (x.ln() - mu) / sigma;
+                -0.5 * z * z - (x * sigma).ln() - LN_SQRT_2PI
+            }
+            _ => f64::NEG_INFINITY,
+        }
+    }
+
+    /// A random draw
+    pub fn sample(&self, rng: &mut impl Rng) -> f64 {
+        match *self {
+            Prior::Uniform { min, max } => rng.gen_range(min..=max),
+            Prior::Normal { mean, sd } => Normal::new(mean, sd).map_or(mean, |d| d.sample(rng)),
+            Prior::LogNormal { mu, sigma } => LogNormal::new(mu, sigma).map_or(mu.exp(), |d| d.sample(rng)),
+        }
+    }
+
+    /// Standard deviation, the scale of proposal steps
+    fn spread(&self) -> f64 {
+        match *self {
+            Prior::Uniform { min, max } => (max - min) / 12f64.sqrt(),
+            Prior::Normal { sd, .. } => sd,
+            Prior::LogNormal { mu, sigma } => {
+                let variance = sigma * sigma;
+                (mu + variance / 2.0).exp() * variance.exp_m1().sqrt()
+            }
+        }
+    }
+}
+
+/// The prior of a named parameter, truncated to `[min, max]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParameterPrior {
+    pub name: String,
+    pub prior: Prior,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl ParameterPrior {
+    /// Bounded by the support of `prior`
+    pub fn new(name: &str, prior: Prior) -> Self {
+        let (min, max) = match prior {
+            Prior::Uniform { min, max } => (min, max),
+            Prior::Normal { .. } => (f64::NEG_INFINITY, f64::INFINITY),
+            Prior::LogNormal { .. } => (0.0, f64::INFINITY),
+        };
+        Self { name: name.to_string(), prior, min, max }
+    }
+
+    /// Restrict the prior to `[min, max]` as well
+    pub fn truncated(mut self, min: f64, max: f64) -> Self {
+        self.min = self.min.max(min);
+        self.max = self.max.min(max);
+        self
+    }
+
+    /// Log prior density at `x`, not renormalized for the truncation
+    pub fn log_density(&self, x: f64) -> f64 {
+        if x < self.min || x > self.max {
+            f64::NEG_INFINITY
+        } else {
+            self.prior.log_density(x)
+        }
+    }
+
+    /// A random draw inside the bounds
+    fn sample(&self, rng: &mut impl Rng) -> f64 {
+        let x = self.prior.sample(rng);
+        if x < self.min || x > self.max {
+            // Rejection sampling would stall on a narrow truncation; a draw at the
+            // bound only seeds a chain, which moves away during burn-in
+            x.clamp(self.min, self.max)
+        } else {
+            x
+        }
+    }
+}
+
+/// Sampler settings
+#[derive(Debug, Clone)]
+pub struct McmcConfig {
+    /// Draws kept per chain, after burn-in and thinning
+    pub samples: usize,
+    /// Iterations discarded at the start of each chain while the step is tuned
+    pub burn_in: usize,
+    /// Independent chains, run in parallel
+    pub chains: usize,
+    /// Keep every `thin`-th iteration
+    pub thin: usize,
+    /// Random seed; chain `i` uses `seed + i`
+    pub seed: Option<u64>,
+    /// Standard deviation of the observation errors; None integrates it out of
+    /// each variable's likelihood under a Jeffreys prior
+    pub noise_sd: Option<f64>,
+    /// Integration method for simulation
+    pub integration_method: IntegrationMethod,
+}
+
+impl Default for McmcConfig {
+    fn default() -> Self {
+        Self {
+            samples: 1000,
+            burn_in: 500,
+            chains: 4,
+            thin: 1,
+            seed: None,
+            noise_sd: None,
+            integration_method: IntegrationMethod::RK4,
+        }
+    }
+}
+
+/// The kept draws of one chain
+#[derive(Debug, Clone)]
+pub struct Chain {
+    /// Parameter values of each draw, in the order of `Posterior::names`
+    pub draws: Vec<Vec<f64>>,
+    /// Log posterior density of each draw, up to a constant
+    pub log_posterior: Vec<f64>,
+    /// Fraction of proposals accepted after burn-in
+    pub acceptance_rate: f64,
+}
+
+/// Posterior statistics of one parameter
+#[derive(Debug, Clone)]
+pub struct ParameterSummary {
+    pub name: String,
+    pub mean: f64,
+    pub sd: f64,
+    pub median: f64,
+    /// Equal-tailed credible interval
+    pub lower: f64,
+    pub upper: f64,
+    /// Split potential scale reduction; near 1 once the chains agree
+    pub r_hat: f64,
+    /// Effective number of independent draws
+    pub ess: f64,
+}
+
+/// Draws from the posterior, chain by chain
+#[derive(Debug, Clone)]
+pub struct Posterior {
+    pub names: Vec<String>,
+    pub chains: Vec<Chain>,
+}
+
+impl Posterior {
+    /// Draws of parameter `index`, chain by chain
+    fn traces(&self, index: usize) -> Vec<Vec<f64>> {
+        self.chains.iter()
+            .map(|chain| chain.draws.iter().map(|draw| draw[index]).collect())
+            .collect()
+    }
+
+    /// Draws of `name` from every chain
+    pub fn samples(&self, name: &str) -> Option<Vec<f64>> {
+        let index = self.names.iter().position(|n| n == name)?;
+        Some(self.traces(index).concat())
+    }
+
+    /// Equal-tailed interval holding `level` of the posterior of `name`
+    pub fn credible_interval(&self, name: &str, level: f64) -> Option<(f64, f64)> {
+        let mut values = self.samples(name)?;
+        values.sort_by(f64::total_cmp);
+        let tail = (1.0 - level) / 2.0;
+        Some((
+            MonteCarloSimulator::percentile(&values, tail),
+            MonteCarloSimulator::percentile(&values, 1.0 - tail),
+        ))
+    }
+
+    /// The draw with the highest posterior density and that density
+    pub fn best(&self) -> Option<(&[f64], f64)> {
+        self.chains.iter()
+            .flat_map(|chain| chain.draws.iter().zip(&chain.log_posterior))
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .map(|(draw, &log_posterior)| (draw.as_slice(), log_posterior))
+    }
+
+    /// Statistics and convergence diagnostics of every parameter
+    pub fn summary(&self, level: f64) -> Vec<ParameterSummary> {
+        let tail = (1.0 - level) / 2.0;
+        self.names.iter().enumerate()
+            .map(|(index, name)| {
+                let traces = self.traces(index);
+                let mut values = traces.concat();
+                values.sort_by(f64::total_cmp);
+                ParameterSummary {
+                    name: name.clone(),
+                    mean: mean(&values),
+                    sd: variance(&values).sqrt(),
+                    median: MonteCarloSimulator::percentile(&values, 0.5),
+                    lower: MonteCarloSimulator::percentile(&values, tail),
+                    upper: MonteCarloSimulator::percentile(&values, 1.0 - tail),
+                    r_hat: split_r_hat(&traces),
+                    ess: effective_sample_size(&traces),
+                }
+            })
+            .collect()
+    }
+
+    /// Every draw as CSV: chain, draw index, parameter values, log posterior
+    pub fn to_csv(&self) -> String {
+        let mut csv = format!("chain,draw,{},log_posterior\n", self.names.join(","));
+        for (c, chain) in self.chains.iter().enumerate() {
+            for (i, (draw, log_posterior)) in chain.draws.iter().zip(&chain.log_posterior).enumerate() {
+                let values: Vec<String> = draw.iter().map(|v| v.to_string()).collect();
+                csv.push_str(&format!("{},{},{},{}\n", c, i, values.join(","), log_posterior));
+            }
+        }
+        csv
+    }
+
+    /// The summary as CSV
+    pub fn summary_csv(&self, level: f64) -> String {
+        let mut csv = String::from("parameter,mean,sd,median,lower,upper,r_hat,ess\n");
+        for s in self.summary(level) {
+            csv.push_str(&format!(
+                "{},{},{},{},{},{},{},{}\n",
+                s.name, s.mean, s.sd, s.median, s.lower, s.upper, s.r_hat, s.ess
+            ));
+        }
+        csv
+    }
+}
+
+/// Metropolis-Hastings calibration of parameters against observed data
+pub struct BayesianCalibrator {
+    priors: Vec<ParameterPrior>,
+    data: ObservedData,
+    config: McmcConfig,
+}
+
+impl BayesianCalibrator {
+    pub fn new(priors: Vec<ParameterPrior>, data: ObservedData, config: McmcConfig) -> Self {
+        Self { priors, data, config }
+    }
+
+    /// Gaussian log-likelihood of the observations with the parameters set to `values`
+    pub fn log_likelihood(&self, model: &Model, values: &[f64]) -> Result<f64> {
+        let mut model = model.clone();
+        for (prior, &value) in self.priors.iter().zip(values) {
+            model.parameters.get_mut(&prior.name)
+                .ok_or_else(|| ModelError::NotFound { kind: "Parameter", name: prior.name.clone() })?
+                .value = value;
+        }
+
+        let config = SimulationConfig {
+            integration_method: self.config.integration_method,
+            output_interval: None,
+            ..SimulationConfig::default()
+        };
+        let results = SimulationEngine::new(model, config)?.run()?;
+
+        Ok(self.data.residuals(&results).map_err(ModelError::Invalid)?.values()
+            .map(|residuals| gaussian_log_likelihood(residuals, self.config.noise_sd))
+            .sum())
+    }
+
+    /// Log posterior density up to a constant; runs that fail count as impossible
+    pub fn log_posterior(&self, model: &Model, values: &[f64]) -> f64 {
+        let log_prior: f64 = self.priors.iter().zip(values)
+            .map(|(prior, &value)| prior.log_density(value))
+            .sum();
+        if log_prior == f64::NEG_INFINITY {
+            return log_prior;
+        }
+        match self.log_likelihood(model, values) {
+            Ok(log_likelihood) if !log_likelihood.is_nan() => log_prior + log_likelihood,
+            _ => f64::NEG_INFINITY,
+        }
+    }
+
+    /// Sample the posterior
+    ///
+    /// The first chain starts from the model's parameter values when the prior
+    /// allows them, the others from prior draws.
+    pub fn run(&self, model: &Model) -> Result<Posterior> {
+        let invalid = |message: String| Err(SimulationError::from(message).into());
+        if self.priors.is_empty() {
+            return invalid("No parameters to calibrate".to_string());
+        }
+        if self.config.chains == 0 || self.config.samples == 0 || self.config.thin == 0 {
+            return invalid("MCMC needs at least one chain, one sample and a thinning interval of 1 or more".to_string());
+        }
+        if let Some(sd) = self.config.noise_sd && sd <= 0.0 {
+            return invalid(format!("Noise standard deviation must be positive, got {}", sd));
+        }
+
+        let initial: Vec<f64> = self.priors.iter()
+            .map(|prior| {
+                model.parameters.get(&prior.name)
+                    .map(|p| p.value)
+                    .ok_or_else(|| ModelError::NotFound { kind: "Parameter", name: prior.name.clone() })
+            })
+            .collect::<Result<_, _>>()?;
+        // Surface model and data errors here rather than as rejected proposals
+        self.log_likelihood(model, &initial)?;
+
+        let chains = (0..self.config.chains)
+            .into_par_iter()
+            .map(|i| {
+                let mut rng = match self.config.seed {
+                    Some(seed) => StdRng::seed_from_u64(seed.wrapping_add(i as u64)),
+                    None => StdRng::from_entropy(),
+                };
+                let start = if i == 0 { Some(initial.as_slice()) } else { None };
+                self.run_chain(model, start, &mut rng)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Posterior {
+            names: self.priors.iter().map(|p| p.name.clone()).collect(),
+            chains,
+        })
+    }
+
+    /// A point with finite posterior density: `initial` if possible, else a prior draw
+    fn starting_point(&self, model: &Model, initial: Option<&[f64]>, rng: &mut impl Rng) -> Result<(Vec<f64>, f64), SimulationError> {
+        if let Some(values) = initial {
+            let log_posterior = self.log_posterior(model, values);
+            if log_posterior.is_finite() {
+                return Ok((values.to_vec(), log_posterior));
+            }
+        }
+        for _ in 0..MAX_START_ATTEMPTS {
+            let values: Vec<f64> = self.priors.iter().map(|prior| prior.sample(rng)).collect();
+            let log_posterior = self.log_posterior(model, &values);
+            if log_posterior.is_finite() {
+                return Ok((values, log_posterior));
+            }
+        }
+        Err(format!("No starting point with finite posterior density in {} prior draws", MAX_START_ATTEMPTS).into())
+    }
+
+    fn run_chain(&self, model: &Model, initial: Option<&[f64]>, rng: &mut impl Rng) -> Result<Chain> {
+        let (mut current, mut current_lp) = self.starting_point(model, initial, rng)?;
+
+        // Per-parameter proposal scales, times a step tuned during burn-in
+        let spreads: Vec<f64> = self.priors.iter()
+            .map(|prior| prior.prior.spread().min((prior.max - prior.min) / 12f64.sqrt()))
+            .collect();
+        let mut step = 0.1;
+
+        let McmcConfig { samples, burn_in, thin, .. } = self.config;
+        let mut chain = Chain {
+            draws: Vec::with_capacity(samples),
+            log_posterior: Vec::with_capacity(samples),
+            acceptance_rate: 0.0,
+        };
+        let mut accepted = 0;
+
+        for iteration in 0..burn_in + samples * thin {
+            let proposal: Vec<f64> = current.iter().zip(&spreads)
+                .map(|(&x, &spread)| x + step * spread * rng.sample::<f64, _>(StandardNormal))
+                .collect();
+            let proposal_lp = self.log_posterior(model, &proposal);
+
+            let accept = proposal_lp.is_finite()
+                && (proposal_lp >= current_lp || rng.gen_range(0.0..1.0f64).ln() < proposal_lp - current_lp);
+            if accept {
+                current = proposal;
+                current_lp = proposal_lp;
+                accepted += 1;
+            }
+
+            if iteration < burn_in {
+                if (iteration + 1) % ADAPT_INTERVAL == 0 {
+                    let rate = accepted as f64 / ADAPT_INTERVAL as f64;
+                    step *= (2.0 * (rate - TARGET_ACCEPTANCE)).exp();
+                    accepted = 0;
+                }
+                if iteration + 1 == burn_in {
+                    accepted = 0;
+                }
+            } else if (iteration - burn_in + 1) % thin == 0 {
+                chain.draws.push(current.clone());
+                chain.log_posterior.push(current_lp);
+            }
+        }
+
+        chain.acceptance_rate = accepted as f64 / (samples * thin) as f64;
+        Ok(chain)
+    }
+}
+
+/// Log-likelihood of residuals with independent normal errors
+fn gaussian_log_likelihood(residuals: &[f64], noise_sd: Option<f64>) -> f64 {
+    if residuals.is_empty() {
+        return 0.0;
+    }
+    let n = residuals.len() as f64;
+    let sse: f64 = residuals.iter().map(|r| r * r).sum();
+    match noise_sd {
+        Some(sd) => -0.5 * sse / (sd * sd) - n * (sd.ln() + LN_SQRT_2PI),
+        // Integrating sigma out under p(sigma) ~ 1/sigma leaves SSE^(-n/2), up to a constant
+        None => -0.5 * n * sse.max(f64::MIN_POSITIVE).ln(),
+    }
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// Sample variance
+fn variance(values: &[f64]) -> f64 {
+    if values.len() < 2 {
+        return 0.0;
+    }
+    let m = mean(values);
+    values.iter().map(|v| (v - m).powi(2)).sum::<f64>() / (values.len() - 1) as f64
+}
+
+/// Gelman-Rubin R-hat over the halves of every chain
+fn split_r_hat(chains: &[Vec<f64>]) -> f64 {
+    let n = chains.iter().map(|c| c.len()).min().unwrap_or(0) / 2;
+    if n < 2 {
+        return f64::NAN;
+    }
+    let halves: Vec<&[f64]> = chains.iter()
+        .flat_map(|c| [&c[..n], &c[c.len() - n..]])
+        .collect();
+
+    let within = mean(&halves.iter().map(|h| variance(h)).collect::<Vec<_>>());
+    let between = n as f64 * variance(&halves.iter().map(|h| mean(h)).collect::<Vec<_>>());
+    if within == 0.0 {
+        return if between == 0.0 { 1.0 } else { f64::INFINITY };
+    }
+    let pooled = (n - 1) as f64 / n as f64 * within + between / n as f64;
+    (pooled / within).sqrt()
+}
+
+/// Effective sample size summed over chains, from Geyer's initial positive
+/// sequence of autocorrelations
+fn effective_sample_size(chains: &[Vec<f64>]) -> f64 {
+    chains.iter()
+        .map(|chain| {
+            let n = chain.len();
+            let m = mean(chain);
+            let autocovariance = |lag: usize| -> f64 {
+                chain[..n - lag].iter().zip(&chain[lag..])
+                    .map(|(a, b)| (a - m) * (b - m))
+                    .sum::<f64>() / n as f64
+            };
+            let variance = if n > 0 { autocovariance(0) } else { 0.0 };
+            if variance <= 0.0 {
+                return n.min(1) as f64;
+            }
+
+            // tau = -1 + 2 * sum of positive pair sums rho(2k) + rho(2k + 1)
+            let mut tau = -1.0;
+            let mut lag = 0;
+            while lag + 1 < n {
+                let pair = (autocovariance(lag) + autocovariance(lag + 1)) / variance;
+                if pair <= 0.0 {
+                    break;
+                }
+                tau += 2.0 * pair;
+                lag += 2;
+            }
+            n as f64 / tau.max(1.0)
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Stock, Flow, Parameter};
+
+    #[test]
+    fn test_prior_densities() {
+        let uniform = Prior::Uniform { min: 0.0, max: 4.0 };
+        assert!((uniform.log_density(1.0) - (0.25f64).ln()).abs() < 1e-12);
+        assert_eq!(uniform.log_density(5.0), f64::NEG_INFINITY);
+
+        let normal = Prior::Normal { mean: 0.0, sd: 1.0 };
+        assert!((normal.log_density(0.0) + LN_SQRT_2PI).abs() < 1e-12);
+        assert_eq!(Prior::LogNormal { mu: 0.0, sigma: 1.0 }.log_density(1.0), normal.log_density(0.0));
+
+        let positive = ParameterPrior::new("k", normal).truncated(0.0, f64::INFINITY);
+        assert_eq!(positive.log_density(-0.5), f64::NEG_INFINITY);
+        assert_eq!(positive.log_density(0.5), normal.log_density(0.5));
+
+        let stuck = vec![vec![1.0; 10], vec![1.0; 10]];
+        assert_eq!(split_r_hat(&stuck), 1.0);
+        let apart = vec![vec![0.0, 0.1, 0.0, 0.1], vec![5.0, 5.1, 5.0, 5.1]];
+        assert!(split_r_hat(&apart) > 2.0);
+    }
+
+    #[test]
+    fn test_posterior_of_decay_rate() {
+        let mut model = Model::new("Decay");
+        model.time.stop = 5.0;
+        model.time.dt = 0.1;
+        model.add_stock(Stock::new("X", "100")).unwrap();
+        model.add_parameter(Parameter::new("k", 0.5)).unwrap();
+        model.add_flow(Flow::new("decay", "k * X")).unwrap();
+        model.stocks.get_mut("X").unwrap().outflows.push("decay".to_string());
+
+        // X = 100 exp(-0.3 t) with alternating errors of +-1
+        let mut csv = String::from("time,X\n");
+        for i in 0..=10 {
+            let t = i as f64 * 0.5;
+            let error = if i % 2 == 0 { 1.0 } else { -1.0 };
+            csv.push_str(&format!("{},{}\n", t, 100.0 * (-0.3 * t).exp() + error));
+        }
+        let data = ObservedData::from_csv(&csv).unwrap();
+
+        let config = McmcConfig {
+            samples: 400,
+            burn_in: 400,
+            chains: 2,
+            seed: Some(42),
+            noise_sd: Some(1.0),
+            ..McmcConfig::default()
+        };
+        let unknown = vec![ParameterPrior::new("kk", Prior::Uniform { min: 0.01, max: 1.0 })];
+        let err = BayesianCalibrator::new(unknown, data.clone(), config.clone()).run(&model).unwrap_err();
+        assert!(matches!(err, crate::Error::Model(ModelError::NotFound { .. })), "{}", err);
+
+        let priors = vec![ParameterPrior::new("k", Prior::Uniform { min: 0.01, max: 1.0 })];
+        let posterior = BayesianCalibrator::new(priors, data, config).run(&model).unwrap();
+
+        assert_eq!(posterior.chains.len(), 2);
+        assert_eq!(posterior.chains[0].draws.len(), 400);
+        let summary = &posterior.summary(0.95)[0];
+        assert!((summary.mean - 0.3).abs() < 0.01, "mean {}", summary.mean);
+        assert!(summary.lower < 0.3 && 0.3 < summary.upper, "interval {} to {}", summary.lower, summary.upper);
+        assert!(summary.r_hat < 1.1, "r_hat {}", summary.r_hat);
+        assert!(summary.ess > 20.0, "ess {}", summary.ess);
+        assert!(posterior.to_csv().starts_with("chain,draw,k,log_posterior\n0,0,"));
+    }
+}
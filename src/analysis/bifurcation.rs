@@ -0,0 +1,310 @@
+/// Bifurcation analysis over a parameter range
+///
+/// Sweeps one parameter, following the equilibrium from each value to the
+/// next (natural continuation) and classifying it by the eigenvalues of its
+/// Jacobian. Where the number of unstable eigenvalues changes, or the branch
+/// of equilibria ends, the critical value is located by bisection:
+///
+/// - a fold when a real eigenvalue passes through zero, which is where an
+///   equilibrium meets another one (saddle-node, transcritical, pitchfork) and
+///   where the followed branch ends
+/// - a Hopf bifurcation when a complex pair crosses the imaginary axis and a
+///   limit cycle is born or dies, with the period of the oscillation there
+
+use nalgebra::Complex;
+use crate::error::{ModelError, Result};
+use crate::model::Model;
+use crate::simulation::SimulationState;
+use super::stability::{StabilityAnalysis, StabilityAnalyzer, StabilityType};
+
+/// Bisection steps locating a critical value between two sweep values
+const REFINEMENTS: usize = 40;
+
+/// Eigenvalues with larger real parts count as unstable, as in [`StabilityAnalyzer`]
+const ZERO_REAL_PART: f64 = 1e-8;
+
+/// Kind of change in the equilibrium's eigenvalues
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BifurcationType {
+    /// A real eigenvalue crosses zero, or the branch of equilibria ends
+    Fold,
+    /// A complex pair of eigenvalues crosses the imaginary axis
+    Hopf,
+}
+
+/// Equilibrium at one parameter value
+#[derive(Debug, Clone)]
+pub struct BifurcationPoint {
+    pub value: f64,
+    /// Equilibrium stock values, in the diagram's `stock_names` order
+    pub stocks: Vec<f64>,
+    pub eigenvalues: Vec<Complex<f64>>,
+    pub stability_type: StabilityType,
+}
+
+/// Critical parameter value
+#[derive(Debug, Clone)]
+pub struct Bifurcation {
+    pub kind: BifurcationType,
+    pub value: f64,
+    /// Equilibrium stock values just before the critical value
+    pub stocks: Vec<f64>,
+    /// Period of the oscillation born at a Hopf bifurcation
+    pub period: Option<f64>,
+}
+
+/// Equilibria over the sweep and the bifurcations between them
+#[derive(Debug, Clone)]
+pub struct BifurcationDiagram {
+    pub parameter: String,
+    pub stock_names: Vec<String>,
+    pub points: Vec<BifurcationPoint>,
+    /// Parameter values at which no equilibrium was found
+    pub unsolved: Vec<f64>,
+    pub bifurcations: Vec<Bifurcation>,
+}
+
+/// Sweep of `parameter` from `from` to `to` in `steps` equal steps
+pub struct BifurcationAnalyzer {
+    pub parameter: String,
+    pub from: f64,
+    pub to: f64,
+    pub steps: usize,
+    /// Largest net flow accepted at an equilibrium
+    pub tolerance: f64,
+    pub stability: StabilityAnalyzer,
+}
+
+impl BifurcationAnalyzer {
+    pub fn new(parameter: &str, from: f64, to: f64, steps: usize) -> Self {
+        Self {
+            parameter: parameter.to_string(),
+            from,
+            to,
+            steps: steps.max(1),
+            tolerance: 1e-9,
+            stability: StabilityAnalyzer::default(),
+        }
+    }
+
+    /// Follow the equilibrium of `model` across the sweep
+    ///
+    /// The first value starts from the model's initial stocks, as does any
+    /// value after the followed branch is lost, so a sweep can pick up a
+    /// different branch beyond a fold.
+    pub fn run(&self, model: &Model) -> Result<BifurcationDiagram> {
+        let mut model = model.clone();
+        model.compile()?;
+        if !model.parameters.contains_key(&self.parameter) {
+            return Err(ModelError::NotFound { kind: "Parameter", name: self.parameter.clone() }.into());
+        }
+        if !SimulationState::initialize_from_model(&model)?.conveyors.is_empty() {
+            return Err(ModelError::Invalid("Bifurcation analysis is not supported for models with conveyors or ovens".to_string()).into());
+        }
+
+        let mut stock_names: Vec<String> = model.stocks.keys().cloned().collect();
+        stock_names.sort();
+        let mut diagram = BifurcationDiagram {
+            parameter: self.parameter.clone(),
+            stock_names,
+            points: Vec::new(),
+            unsolved: Vec::new(),
+            bifurcations: Vec::new(),
+        };
+
+        let mut previous: Option<(f64, SimulationState, StabilityAnalysis)> = None;
+        for i in 0..=self.steps {
+            let value = self.from + (self.to - self.from) * i as f64 / self.steps as f64;
+
+            let continued = match &previous {
+                Some((_, state, _)) => self.equilibrium(&mut model, value, Some(state)),
+                None => None,
+            };
+            if let Some((previous_value, state, analysis)) = &previous {
+                let changed = match &continued {
+                    Some((_, next)) => unstable_count(next) != unstable_count(analysis),
+                    None => true,
+                };
+                if changed {
+                    diagram.bifurcations.push(self.refine(&mut model, *previous_value, value, state, analysis));
+                }
+            }
+
+            previous = match continued.or_else(|| self.equilibrium(&mut model, value, None)) {
+                Some((state, analysis)) => {
+                    diagram.points.push(BifurcationPoint {
+                        value,
+                        stocks: diagram.stock_names.iter().map(|name| state.stocks[name.as_str()]).collect(),
+                        eigenvalues: analysis.eigenvalues.clone(),
+                        stability_type: analysis.stability_type.clone(),
+                    });
+                    Some((value, state, analysis))
+                }
+                None => {
+                    diagram.unsolved.push(value);
+                    None
+                }
+            };
+        }
+
+        Ok(diagram)
+    }
+
+    /// Equilibrium and its stability with the parameter at `value`, starting
+    /// Newton's method from `start` or else from the initial stocks
+    fn equilibrium(
+        &self,
+        model: &mut Model,
+        value: f64,
+        start: Option<&SimulationState>,
+    ) -> Option<(SimulationState, StabilityAnalysis)> {
+        model.parameters.get_mut(&self.parameter)?.value = value;
+        let mut state = SimulationState::initialize_from_model(model).ok()?;
+        if let Some(start) = start {
+            state.stocks = start.stocks.clone();
+        }
+        let state = self.stability.solve_equilibrium(model, &state, self.tolerance).ok()?;
+        let analysis = self.stability.analyze(model, &state).ok()?;
+        Some((state, analysis))
+    }
+
+    /// Bisect between `low`, where the equilibrium is `state`, and `high`,
+    /// where it has changed stability or could not be followed
+    fn refine(
+        &self,
+        model: &mut Model,
+        mut low: f64,
+        mut high: f64,
+        state: &SimulationState,
+        analysis: &StabilityAnalysis,
+    ) -> Bifurcation {
+        let mut state = state.clone();
+        let mut analysis = analysis.clone();
+        let mut lost = true;
+        for _ in 0..REFINEMENTS {
+            let middle = 0.5 * (low + high);
+            match self.equilibrium(model, middle, Some(&state)) {
+                Some((next_state, next)) if unstable_count(&next) == unstable_count(&analysis) => {
+                    low = middle;
+                    state = next_state;
+                    analysis = next;
+                }
+                found => {
+                    lost = found.is_none();
+                    high = middle;
+                }
+            }
+        }
+
+        // The eigenvalue nearest the imaginary axis is the one crossing it
+        let critical = analysis.eigenvalues.iter()
+            .min_by(|a, b| a.re.abs().total_cmp(&b.re.abs()))
+            .copied();
+        let period = match critical {
+            Some(eigenvalue) if !lost && eigenvalue.im.abs() > 1e-10 => Some(2.0 * std::f64::consts::PI / eigenvalue.im.abs()),
+            _ => None,
+        };
+        Bifurcation {
+            kind: if period.is_some() { BifurcationType::Hopf } else { BifurcationType::Fold },
+            value: 0.5 * (low + high),
+            stocks: analysis.stock_names.iter().map(|name| state.stocks[name.as_str()]).collect(),
+            period,
+        }
+    }
+}
+
+/// Number of eigenvalues with positive real part
+fn unstable_count(analysis: &StabilityAnalysis) -> usize {
+    analysis.eigenvalues.iter().filter(|e| e.re > ZERO_REAL_PART).count()
+}
+
+impl BifurcationDiagram {
+    /// CSV with one row per equilibrium: the parameter value, the stocks,
+    /// the stability and the eigenvalues in decreasing order of real part
+    pub fn to_csv(&self) -> String {
+        let n = self.stock_names.len();
+        let mut csv = format!("{},{}", self.parameter, self.stock_names.join(","));
+        csv.push_str(",max_real_part,stability");
+        for i in 1..=n {
+            csv.push_str(&format!(",lambda_{}_re,lambda_{}_im", i, i));
+        }
+        csv.push('\n');
+
+        for point in &self.points {
+            let mut eigenvalues = point.eigenvalues.clone();
+            eigenvalues.sort_by(|a, b| b.re.total_cmp(&a.re).then(b.im.total_cmp(&a.im)));
+            let max_real_part = eigenvalues.first().map_or(f64::NAN, |e| e.re);
+
+            csv.push_str(&point.value.to_string());
+            for stock in &point.stocks {
+                csv.push_str(&format!(",{}", stock));
+            }
+            csv.push_str(&format!(",{},{:?}", max_real_part, point.stability_type));
+            for eigenvalue in &eigenvalues {
+                csv.push_str(&format!(",{},{}", eigenvalue.re, eigenvalue.im));
+            }
+            csv.push('\n');
+        }
+        csv
+    }
+
+    /// CSV with one row per bifurcation: its kind, critical value, stocks
+    /// there and, for Hopf bifurcations, the period
+    pub fn bifurcations_csv(&self) -> String {
+        let mut csv = format!("bifurcation,{},{},period\n", self.parameter, self.stock_names.join(","));
+        for bifurcation in &self.bifurcations {
+            csv.push_str(&format!("{:?},{}", bifurcation.kind, bifurcation.value));
+            for stock in &bifurcation.stocks {
+                csv.push_str(&format!(",{}", stock));
+            }
+            csv.push_str(&format!(",{}\n", bifurcation.period.map_or(String::new(), |p| p.to_string())));
+        }
+        csv
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Stock, Flow, Parameter};
+
+    #[test]
+    fn test_hopf_and_fold() {
+        // X' = mu X - Y, Y' = X + mu Y: eigenvalues mu ± i, so a Hopf bifurcation at mu = 0
+        let mut model = Model::new("Oscillator");
+        model.add_stock(Stock::new("X", "0")).unwrap();
+        model.add_stock(Stock::new("Y", "0")).unwrap();
+        model.add_parameter(Parameter::new("mu", -1.0)).unwrap();
+        model.add_flow(Flow::new("dx", "mu * X - Y")).unwrap();
+        model.add_flow(Flow::new("dy", "X + mu * Y")).unwrap();
+        model.stocks.get_mut("X").unwrap().inflows.push("dx".to_string());
+        model.stocks.get_mut("Y").unwrap().inflows.push("dy".to_string());
+
+        let missing = BifurcationAnalyzer::new("nu", -1.0, 1.0, 9).run(&model).unwrap_err();
+        assert!(matches!(missing, crate::Error::Model(ModelError::NotFound { .. })), "{}", missing);
+
+        let diagram = BifurcationAnalyzer::new("mu", -1.0, 1.0, 9).run(&model).unwrap();
+        assert_eq!(diagram.points.len(), 10);
+        assert_eq!(diagram.bifurcations.len(), 1);
+        let hopf = &diagram.bifurcations[0];
+        assert_eq!(hopf.kind, BifurcationType::Hopf);
+        assert!(hopf.value.abs() < 1e-6);
+        assert!((hopf.period.unwrap() - 2.0 * std::f64::consts::PI).abs() < 1e-4);
+        assert!(diagram.to_csv().starts_with("mu,X,Y,max_real_part,stability,lambda_1_re,lambda_1_im,lambda_2_re,lambda_2_im\n-1,0,0,"));
+
+        // X' = -r - X^2 has equilibria X = ±sqrt(-r) only while r < 0
+        let mut model = Model::new("Saddle-node");
+        model.add_stock(Stock::new("X", "1")).unwrap();
+        model.add_parameter(Parameter::new("r", -1.0)).unwrap();
+        model.add_flow(Flow::new("change", "-r - X * X")).unwrap();
+        model.stocks.get_mut("X").unwrap().inflows.push("change".to_string());
+
+        let diagram = BifurcationAnalyzer::new("r", -1.0, 1.0, 10).run(&model).unwrap();
+        assert_eq!(diagram.unsolved.len(), 5);
+        assert_eq!(diagram.bifurcations.len(), 1);
+        let fold = &diagram.bifurcations[0];
+        assert_eq!(fold.kind, BifurcationType::Fold);
+        assert!(fold.value.abs() < 1e-4);
+        assert!(diagram.bifurcations_csv().starts_with("bifurcation,r,X,period\nFold,"));
+    }
+}
@@ -0,0 +1,275 @@
+/// Calibration of model parameters against observed time series
+///
+/// Provides:
+/// - Loading observed data from CSV (a `time` column plus one column per variable),
+///   with times as numbers or ISO dates
+/// - Goodness-of-fit objectives (SSE, MAE, Gaussian likelihood)
+/// - Objective functions for `GradientOptimizer` / `GeneticOptimizer`
+
+use std::collections::BTreeMap;
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use crate::model::Calendar;
+use crate::model::calendar::parse_date;
+use crate::simulation::SimulationResults;
+use super::optimization::ObjectiveFunction;
+
+/// Goodness-of-fit measure minimized during calibration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FitObjective {
+    /// Sum of squared errors
+    Sse,
+    /// Mean absolute error
+    Mae,
+    /// Negative Gaussian log-likelihood with the error variance profiled out
+    Likelihood,
+}
+
+impl FitObjective {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name.to_lowercase().as_str() {
+            "sse" => Ok(FitObjective::Sse),
+            "mae" => Ok(FitObjective::Mae),
+            "likelihood" | "nll" => Ok(FitObjective::Likelihood),
+            _ => Err(format!("Unknown objective '{}' (expected sse, mae or likelihood)", name)),
+        }
+    }
+
+    /// Score the residuals of one variable
+    pub(crate) fn score(&self, residuals: &[f64]) -> f64 {
+        let n = residuals.len() as f64;
+        if residuals.is_empty() {
+            return 0.0;
+        }
+        let sse: f64 = residuals.iter().map(|r| r * r).sum();
+        match self {
+            FitObjective::Sse => sse,
+            FitObjective::Mae => residuals.iter().map(|r| r.abs()).sum::<f64>() / n,
+            FitObjective::Likelihood => {
+                // -ln L at the MLE variance sigma^2 = SSE / n, up to a constant
+                let variance = (sse / n).max(f64::MIN_POSITIVE);
+                0.5 * n * (variance.ln() + 1.0 + (2.0 * std::f64::consts::PI).ln())
+            }
+        }
+    }
+}
+
+/// Observed time series; missing observations are stored as NaN
+#[derive(Debug, Clone)]
+pub struct ObservedData {
+    pub times: Vec<f64>,
+    pub series: BTreeMap<String, Vec<f64>>,
+    /// Whether times were read as dates, and are days since 1970-01-01
+    pub dates: bool,
+}
+
+fn unix_epoch() -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(1970, 1, 1).expect("valid date").and_time(NaiveTime::MIN)
+}
+
+/// The date `days` days after 1970-01-01
+pub fn date_of_days(days: f64) -> NaiveDateTime {
+    unix_epoch() + chrono::Duration::milliseconds((days * 86_400_000.0).round() as i64)
+}
+
+impl ObservedData {
+    /// Parse CSV with a `time` column and one column per observed variable
+    ///
+    /// Empty cells mark missing observations. Times are all numbers or all
+    /// ISO dates, as results of a model with a calendar are written.
+    pub fn from_csv(contents: &str) -> Result<Self, String> {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(contents.as_bytes());
+
+        let headers = reader.headers()
+            .map_err(|e| format!("Failed to read CSV header: {}", e))?
+            .clone();
+        let time_column = headers.iter()
+            .position(|h| h.eq_ignore_ascii_case("time"))
+            .ok_or("Observed data must have a 'time' column")?;
+
+        let mut times = Vec::new();
+        let mut dates = false;
+        let mut columns: Vec<(usize, String, Vec<f64>)> = headers.iter()
+            .enumerate()
+            .filter(|(i, _)| *i != time_column)
+            .map(|(i, name)| (i, name.to_string(), Vec::new()))
+            .collect();
+
+        for (row, record) in reader.records().enumerate() {
+            let record = record.map_err(|e| format!("Failed to read CSV row {}: {}", row + 1, e))?;
+            let parse = |i: usize| -> Result<f64, String> {
+                match record.get(i).unwrap_or("") {
+                    "" => Ok(f64::NAN),
+                    cell => cell.parse()
+                        .map_err(|_| format!("Invalid number '{}' in row {}", cell, row + 1)),
+                }
+            };
+
+            let cell = record.get(time_column).unwrap_or("");
+            let time = match parse_date(cell) {
+                Some(date) if row == 0 || dates => {
+                    dates = true;
+                    (date - unix_epoch()).num_milliseconds() as f64 / 86_400_000.0
+                }
+                _ if dates => return Err(format!("Invalid date '{}' in row {}", cell, row + 1)),
+                _ => parse(time_column)?,
+            };
+            if time.is_nan() {
+                return Err(format!("Missing time in row {}", row + 1));
+            }
+            times.push(time);
+            for (i, _, values) in &mut columns {
+                values.push(parse(*i)?);
+            }
+        }
+
+        if columns.is_empty() {
+            return Err("Observed data has no variable columns".to_string());
+        }
+        if times.is_empty() {
+            return Err("Observed data has no rows".to_string());
+        }
+
+        Ok(Self {
+            times,
+            series: columns.into_iter().map(|(_, name, values)| (name, values)).collect(),
+            dates,
+        })
+    }
+
+    /// Convert times read as dates to the model times `calendar` gives them
+    pub fn in_model_time(mut self, calendar: Option<&Calendar>) -> Result<Self, String> {
+        if self.dates {
+            let calendar = calendar.ok_or("Observed times are dates, but the model has no calendar")?;
+            self.times = self.times.iter().map(|days| calendar.time_at(date_of_days(*days))).collect::<Result<_, _>>()?;
+            self.dates = false;
+        }
+        Ok(self)
+    }
+
+    /// Every recorded variable of a simulation run
+    pub fn from_results(results: &SimulationResults) -> Self {
+        Self {
+            times: results.times.clone(),
+            series: results.columns()
+                .map(|(name, series)| (name.clone(), series.to_vec()))
+                .collect(),
+            dates: false,
+        }
+    }
+
+    /// Simulated values of each observed variable at the observed times
+    ///
+    /// Simulated series are linearly interpolated between output points.
+    pub fn simulated(&self, results: &SimulationResults) -> Result<BTreeMap<String, Vec<f64>>, String> {
+        self.series.keys()
+            .map(|name| {
+                let simulated = results.series(name)
+                    .ok_or_else(|| format!("Observed variable '{}' not found in simulation results", name))?;
+                let values = self.times.iter()
+                    .map(|&time| interpolate(&results.times, simulated, time))
+                    .collect();
+                Ok((name.clone(), values))
+            })
+            .collect()
+    }
+
+    /// Simulated minus observed values of each variable, skipping missing observations
+    pub fn residuals(&self, results: &SimulationResults) -> Result<BTreeMap<String, Vec<f64>>, String> {
+        let simulated = self.simulated(results)?;
+        Ok(self.series.iter()
+            .map(|(name, observed)| {
+                let residuals = simulated[name].iter()
+                    .zip(observed)
+                    .filter(|(_, value)| !value.is_nan())
+                    .map(|(sim, obs)| sim - obs)
+                    .collect();
+                (name.clone(), residuals)
+            })
+            .collect())
+    }
+
+    /// Score simulation results against the observations, summing per-variable scores
+    pub fn evaluate(&self, results: &SimulationResults, objective: FitObjective) -> Result<f64, String> {
+        Ok(self.residuals(results)?.values().map(|residuals| objective.score(residuals)).sum())
+    }
+
+    /// Build an optimizer objective that scores each run against these observations
+    pub fn objective_function(self, objective: FitObjective) -> ObjectiveFunction {
+        Box::new(move |_model, results| self.evaluate(results, objective))
+    }
+}
+
+/// Linear interpolation of `values` at `time`, clamped to the simulated range
+pub(crate) fn interpolate(times: &[f64], values: &[f64], time: f64) -> f64 {
+    let i = times.partition_point(|&t| t < time);
+    if i == 0 {
+        return values.first().copied().unwrap_or(f64::NAN);
+    }
+    if i >= times.len() {
+        return values.last().copied().unwrap_or(f64::NAN);
+    }
+    let (t0, t1) = (times[i - 1], times[i]);
+    let fraction = (time - t0) / (t1 - t0);
+    values[i - 1] + fraction * (values[i] - values[i - 1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::optimization::{GradientOptimizer, OptimizationConfig, ParameterBounds};
+    use crate::model::{Model, Stock, Flow, Parameter};
+    use crate::simulation::{SimulationEngine, SimulationConfig, IntegrationMethod};
+
+    fn decay_model(k: f64) -> Model {
+        let mut model = Model::new("Decay");
+        model.time.stop = 5.0;
+        model.time.dt = 0.1;
+        model.add_stock(Stock::new("X", "100")).unwrap();
+        model.add_parameter(Parameter::new("k", k)).unwrap();
+        model.add_flow(Flow::new("decay", "k * X")).unwrap();
+        model.stocks.get_mut("X").unwrap().outflows.push("decay".to_string());
+        model
+    }
+
+    #[test]
+    fn test_observed_data_parsing() {
+        let data = ObservedData::from_csv("time, X, Y\n0, 1.5,\n1, 2, 3\n").unwrap();
+        assert_eq!(data.times, vec![0.0, 1.0]);
+        assert_eq!(data.series["X"], vec![1.5, 2.0]);
+        assert!(data.series["Y"][0].is_nan());
+
+        assert!(ObservedData::from_csv("t,X\n0,1\n").is_err());
+
+        let dated = ObservedData::from_csv("Time,X\n2024-01-01,1\n2024-03-01,2\n").unwrap();
+        assert!(dated.dates);
+        let monthly = crate::model::Calendar::new(NaiveDate::from_ymd_opt(2023, 12, 1).unwrap(), crate::model::CalendarUnit::Months);
+        assert_eq!(dated.clone().in_model_time(Some(&monthly)).unwrap().times, vec![1.0, 3.0]);
+        assert!(dated.in_model_time(None).is_err());
+        assert!(ObservedData::from_csv("Time,X\n2024-01-01,1\n5,2\n").is_err());
+    }
+
+    #[test]
+    fn test_calibration_recovers_parameter() {
+        let config = SimulationConfig {
+            integration_method: IntegrationMethod::RK4,
+            ..SimulationConfig::default()
+        };
+        let truth = SimulationEngine::new(decay_model(0.3), config).unwrap().run().unwrap();
+
+        let mut csv = String::from("time,X\n");
+        for (time, x) in truth.times.iter().zip(truth.series("X").unwrap()).step_by(5) {
+            csv.push_str(&format!("{},{}\n", time, x));
+        }
+        let data = ObservedData::from_csv(&csv).unwrap();
+        assert!(data.evaluate(&truth, FitObjective::Sse).unwrap() < 1e-9);
+
+        let optimizer = GradientOptimizer::new(
+            OptimizationConfig { max_iterations: 50, ..OptimizationConfig::default() },
+            vec![ParameterBounds::new("k", 0.01, 1.0)],
+        );
+        let result = optimizer.optimize(&decay_model(0.5), data.objective_function(FitObjective::Sse)).unwrap();
+        assert!((result.parameters["k"] - 0.3).abs() < 1e-2, "k = {}", result.parameters["k"]);
+    }
+}
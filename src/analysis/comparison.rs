@@ -0,0 +1,194 @@
+/// Comparison of two simulation runs
+///
+/// Lines a scenario run up against a baseline, variable by variable, giving
+/// the absolute and relative difference at every baseline time and summary
+/// statistics of those differences for policy analysis.
+
+use std::collections::BTreeMap;
+use super::calibration::{interpolate, ObservedData};
+
+/// Differences between a baseline and a scenario run
+#[derive(Debug, Clone)]
+pub struct RunComparison {
+    /// Baseline output times; the scenario is interpolated onto them
+    pub times: Vec<f64>,
+    pub variables: BTreeMap<String, VariableComparison>,
+}
+
+#[derive(Debug, Clone)]
+pub struct VariableComparison {
+    pub baseline: Vec<f64>,
+    pub scenario: Vec<f64>,
+    /// Scenario minus baseline
+    pub absolute: Vec<f64>,
+    /// Absolute difference over the baseline's magnitude; NaN where the baseline is zero
+    pub relative: Vec<f64>,
+    pub summary: DifferenceSummary,
+}
+
+/// Summary statistics of one variable's differences
+#[derive(Debug, Clone, Copy)]
+pub struct DifferenceSummary {
+    /// Largest absolute difference (keeping its sign) and when it occurs
+    pub max_difference: f64,
+    pub time_of_max: f64,
+    pub mean_abs_difference: f64,
+    pub rms_difference: f64,
+    pub final_baseline: f64,
+    pub final_scenario: f64,
+    pub final_difference: f64,
+    pub final_relative: f64,
+}
+
+impl RunComparison {
+    /// Compare `variables`, or every variable the two runs share
+    pub fn new(
+        baseline: &ObservedData,
+        scenario: &ObservedData,
+        variables: Option<&[String]>,
+    ) -> Result<Self, String> {
+        let names: Vec<String> = match variables {
+            Some(variables) => {
+                for name in variables {
+                    for (run, data) in [("baseline", baseline), ("scenario", scenario)] {
+                        if !data.series.contains_key(name) {
+                            return Err(format!("Variable '{}' not found in the {} run", name, run));
+                        }
+                    }
+                }
+                variables.to_vec()
+            }
+            None => baseline.series.keys()
+                .filter(|name| scenario.series.contains_key(*name))
+                .cloned()
+                .collect(),
+        };
+        if names.is_empty() {
+            return Err("The runs have no variables in common".to_string());
+        }
+        if baseline.times.is_empty() || scenario.times.is_empty() {
+            return Err("Cannot compare a run with no output".to_string());
+        }
+
+        let variables = names.into_iter()
+            .map(|name| {
+                let base = baseline.series[&name].clone();
+                let values = &scenario.series[&name];
+                let scen: Vec<f64> = baseline.times.iter()
+                    .map(|&time| interpolate(&scenario.times, values, time))
+                    .collect();
+                let comparison = VariableComparison::new(&baseline.times, base, scen);
+                (name, comparison)
+            })
+            .collect();
+
+        Ok(Self { times: baseline.times.clone(), variables })
+    }
+
+    /// Time series of every variable: baseline, scenario, absolute and relative difference
+    pub fn differences_csv(&self) -> String {
+        let mut csv = String::from("time");
+        for name in self.variables.keys() {
+            csv.push_str(&format!(",{0}_baseline,{0}_scenario,{0}_difference,{0}_relative", name));
+        }
+        csv.push('\n');
+
+        for (i, time) in self.times.iter().enumerate() {
+            csv.push_str(&time.to_string());
+            for comparison in self.variables.values() {
+                for value in [comparison.baseline[i], comparison.scenario[i], comparison.absolute[i], comparison.relative[i]] {
+                    csv.push(',');
+                    csv.push_str(&format_value(value));
+                }
+            }
+            csv.push('\n');
+        }
+        csv
+    }
+
+    /// One row of summary statistics per variable
+    pub fn summary_csv(&self) -> String {
+        let mut csv = String::from(
+            "variable,max_difference,time_of_max,mean_abs_difference,rms_difference,final_baseline,final_scenario,final_difference,final_relative\n",
+        );
+        for (name, comparison) in &self.variables {
+            let s = &comparison.summary;
+            let values = [
+                s.max_difference, s.time_of_max, s.mean_abs_difference, s.rms_difference,
+                s.final_baseline, s.final_scenario, s.final_difference, s.final_relative,
+            ];
+            let values: Vec<String> = values.iter().map(|&v| format_value(v)).collect();
+            csv.push_str(&format!("{},{}\n", name, values.join(",")));
+        }
+        csv
+    }
+}
+
+impl VariableComparison {
+    fn new(times: &[f64], baseline: Vec<f64>, scenario: Vec<f64>) -> Self {
+        let absolute: Vec<f64> = scenario.iter().zip(&baseline).map(|(s, b)| s - b).collect();
+        let relative: Vec<f64> = absolute.iter().zip(&baseline)
+            .map(|(d, b)| if *b == 0.0 { f64::NAN } else { d / b.abs() })
+            .collect();
+
+        // Missing values (empty CSV cells) are left out of the statistics
+        let present: Vec<(f64, f64)> = times.iter().copied()
+            .zip(absolute.iter().copied())
+            .filter(|(_, d)| !d.is_nan())
+            .collect();
+        let n = present.len().max(1) as f64;
+        let (time_of_max, max_difference) = present.iter()
+            .copied()
+            .max_by(|a, b| a.1.abs().total_cmp(&b.1.abs()))
+            .unwrap_or((f64::NAN, f64::NAN));
+
+        let last = baseline.len() - 1;
+        let summary = DifferenceSummary {
+            max_difference,
+            time_of_max,
+            mean_abs_difference: present.iter().map(|(_, d)| d.abs()).sum::<f64>() / n,
+            rms_difference: (present.iter().map(|(_, d)| d * d).sum::<f64>() / n).sqrt(),
+            final_baseline: baseline[last],
+            final_scenario: scenario[last],
+            final_difference: absolute[last],
+            final_relative: relative[last],
+        };
+
+        Self { baseline, scenario, absolute, relative, summary }
+    }
+}
+
+/// Empty for NaN, so missing values stay missing when read back
+fn format_value(value: f64) -> String {
+    if value.is_nan() { String::new() } else { value.to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare_runs() {
+        let baseline = ObservedData::from_csv("Time,Population,births\n0,100,10\n1,110,11\n2,121,12\n").unwrap();
+        // The scenario was recorded twice as often and has an extra variable
+        let scenario = ObservedData::from_csv(
+            "Time,Population,deaths\n0,100,1\n0.5,102,1\n1,104,1\n1.5,106,1\n2,108,1\n",
+        ).unwrap();
+
+        let comparison = RunComparison::new(&baseline, &scenario, None).unwrap();
+        assert_eq!(comparison.variables.keys().collect::<Vec<_>>(), vec!["Population"]);
+
+        let population = &comparison.variables["Population"];
+        assert_eq!(population.scenario, vec![100.0, 104.0, 108.0]);
+        assert_eq!(population.absolute, vec![0.0, -6.0, -13.0]);
+        assert!((population.relative[2] + 13.0 / 121.0).abs() < 1e-12);
+        assert_eq!(population.summary.max_difference, -13.0);
+        assert_eq!(population.summary.time_of_max, 2.0);
+        assert!((population.summary.mean_abs_difference - 19.0 / 3.0).abs() < 1e-12);
+
+        assert!(comparison.differences_csv().starts_with(
+            "time,Population_baseline,Population_scenario,Population_difference,Population_relative\n0,100,100,0,0\n"
+        ));
+        assert!(RunComparison::new(&baseline, &scenario, Some(&["births".to_string()])).is_err());
+    }
+}
@@ -0,0 +1,240 @@
+/// Sensitivity of results to the time step
+///
+/// Results that change when dt is halved are artifacts of the integration
+/// rather than of the model. The model is run at dt, dt/2 and dt/4, and each
+/// variable's largest divergence from the dt/4 run, relative to the largest
+/// value it reaches there, shows how far the results at dt can be trusted.
+/// The two divergences also give the order of convergence: for an error
+/// proportional to dt^p their ratio is 2^p + 1, which tells how small a dt
+/// would bring the error within the threshold.
+
+use rayon::prelude::*;
+use crate::error::{Result, SimulationError};
+use crate::model::Model;
+use crate::simulation::{IntegrationMethod, SimulationConfig, SimulationEngine, SimulationResults};
+
+#[derive(Debug, Clone)]
+pub struct DtSensitivityConfig {
+    /// Relative divergence beyond which results depend on the time step
+    pub threshold: f64,
+    pub integration_method: IntegrationMethod,
+}
+
+impl Default for DtSensitivityConfig {
+    fn default() -> Self {
+        Self {
+            threshold: 0.01,
+            integration_method: IntegrationMethod::Euler,
+        }
+    }
+}
+
+/// How far one variable moves as the time step shrinks
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariableDivergence {
+    pub variable: String,
+    /// Largest relative difference between the runs at dt and dt/4
+    pub coarse: f64,
+    /// Largest relative difference between the runs at dt/2 and dt/4
+    pub half: f64,
+    /// Time of the largest difference between dt and dt/4
+    pub time: f64,
+}
+
+/// What to change so results no longer depend on the time step
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DtRecommendation {
+    /// Results at the current dt are within the threshold
+    Keep,
+    /// The largest dt expected to be within the threshold
+    Dt(f64),
+    /// The error does not shrink steadily with dt, typically because of
+    /// discontinuities; an adaptive integrator controls it directly
+    Adaptive,
+}
+
+#[derive(Debug, Clone)]
+pub struct DtSensitivityReport {
+    pub model: String,
+    pub dt: f64,
+    pub threshold: f64,
+    /// Every recorded variable, most divergent first
+    pub variables: Vec<VariableDivergence>,
+    /// Order of convergence estimated from the most divergent variable
+    pub order: Option<f64>,
+    pub recommendation: DtRecommendation,
+}
+
+pub struct DtSensitivityAnalyzer {
+    pub config: DtSensitivityConfig,
+}
+
+impl DtSensitivityAnalyzer {
+    pub fn new(config: DtSensitivityConfig) -> Self {
+        Self { config }
+    }
+
+    /// Run `model` at its dt, dt/2 and dt/4 and compare the results
+    pub fn run(&self, model: &Model) -> Result<DtSensitivityReport> {
+        let dt = model.time.dt;
+        // Record at the coarsest spacing so every run has the same times
+        let interval = model.time.save_interval.unwrap_or(dt);
+        let runs: Vec<SimulationResults> = [1.0, 2.0, 4.0].into_par_iter()
+            .map(|divisor| {
+                let mut model = model.clone();
+                model.time.dt = dt / divisor;
+                self.simulate(model, interval)
+            })
+            .collect::<Result<_>>()?;
+        let (coarse, half, fine) = (&runs[0], &runs[1], &runs[2]);
+        for run in [half, fine] {
+            let aligned = run.times.len() == coarse.times.len()
+                && run.times.iter().zip(&coarse.times).all(|(a, b)| (a - b).abs() <= dt * 1e-6);
+            if !aligned {
+                return Err(SimulationError::from("Runs at dt, dt/2 and dt/4 recorded different times".to_string()).into());
+            }
+        }
+
+        let mut variables: Vec<VariableDivergence> = fine.columns()
+            .filter_map(|(name, reference)| {
+                let (coarse_series, half_series) = (coarse.series(name)?, half.series(name)?);
+                let (coarse, index) = divergence(coarse_series, reference);
+                let (half, _) = divergence(half_series, reference);
+                Some(VariableDivergence { variable: name.clone(), coarse, half, time: fine.times[index] })
+            })
+            .collect();
+        variables.sort_by(|a, b| b.coarse.total_cmp(&a.coarse).then_with(|| a.variable.cmp(&b.variable)));
+
+        let worst = variables.first();
+        let order = worst.and_then(|v| (v.half > 0.0 && v.coarse / v.half > 2.0).then(|| (v.coarse / v.half - 1.0).log2()));
+        let recommendation = match (worst, order) {
+            (None, _) => DtRecommendation::Keep,
+            (Some(v), _) if v.coarse <= self.config.threshold => DtRecommendation::Keep,
+            (Some(v), Some(p)) if p >= 0.5 => {
+                // Error of the run at dt against the exact solution, then at dt/2^k
+                let error = v.coarse / (1.0 - 4f64.powf(-p));
+                (1..=10)
+                    .find(|&k| error * 2f64.powf(-p * k as f64) <= self.config.threshold)
+                    .map_or(DtRecommendation::Adaptive, |k| DtRecommendation::Dt(dt / 2f64.powi(k)))
+            }
+            _ => DtRecommendation::Adaptive,
+        };
+
+        Ok(DtSensitivityReport {
+            model: model.metadata.name.clone(),
+            dt,
+            threshold: self.config.threshold,
+            variables,
+            order,
+            recommendation,
+        })
+    }
+
+    fn simulate(&self, model: Model, interval: f64) -> Result<SimulationResults> {
+        let config = SimulationConfig {
+            integration_method: self.config.integration_method,
+            output_interval: Some(interval),
+            ..SimulationConfig::default()
+        };
+        SimulationEngine::new(model, config)?.run()
+    }
+}
+
+/// Largest difference of `series` from `reference`, relative to the largest
+/// magnitude of `reference`, and the index it occurs at
+fn divergence(series: &[f64], reference: &[f64]) -> (f64, usize) {
+    let scale = reference.iter().fold(0.0, |m: f64, v| m.max(v.abs()));
+    let (index, difference) = series.iter().zip(reference).map(|(a, b)| (a - b).abs()).enumerate()
+        .fold((0, 0.0), |(i, m), (j, d)| if d > m { (j, d) } else { (i, m) });
+    let relative = if scale > 0.0 { difference / scale } else { difference };
+    (relative, index)
+}
+
+impl DtSensitivityReport {
+    /// Largest relative divergence of any variable
+    pub fn max_divergence(&self) -> f64 {
+        self.variables.first().map_or(0.0, |v| v.coarse)
+    }
+
+    /// Whether any variable diverges beyond the threshold
+    pub fn is_dt_dependent(&self) -> bool {
+        self.max_divergence() > self.threshold
+    }
+
+    /// Plain-text report: the verdict, the recommendation and every variable's divergence
+    pub fn to_text(&self) -> String {
+        let mut text = format!("Time step sensitivity of {} at dt={}\n\n", self.model, self.dt);
+        if self.is_dt_dependent() {
+            text.push_str(&format!("Results depend on the time step: divergence up to {:.3}% (threshold {}%)\n",
+                self.max_divergence() * 100.0, self.threshold * 100.0));
+        } else {
+            text.push_str(&format!("Results are within {}% of those at dt/4\n", self.threshold * 100.0));
+        }
+        if let Some(order) = self.order {
+            text.push_str(&format!("Estimated order of convergence: {:.2}\n", order));
+        }
+        text.push_str(&format!("Recommendation: {}\n\n", self.recommendation));
+        text.push_str("variable                       dt vs dt/4   dt/2 vs dt/4   at t\n");
+        for v in &self.variables {
+            text.push_str(&format!("{:<30} {:>10.3}% {:>13.3}%   {}\n", v.variable, v.coarse * 100.0, v.half * 100.0, v.time));
+        }
+        text
+    }
+
+    /// CSV with a row per variable
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("variable,divergence_dt,divergence_half_dt,time\n");
+        for v in &self.variables {
+            csv.push_str(&format!("{},{},{},{}\n", v.variable, v.coarse, v.half, v.time));
+        }
+        csv
+    }
+}
+
+impl std::fmt::Display for DtRecommendation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DtRecommendation::Keep => f.write_str("keep the current dt"),
+            DtRecommendation::Dt(dt) => write!(f, "use dt={}", dt),
+            DtRecommendation::Adaptive => f.write_str("use an adaptive integrator (rk45)"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Flow, Parameter, Stock};
+
+    #[test]
+    fn test_dt_sensitivity() {
+        // Exponential decay: Euler's error halves with dt, so the order is about 1
+        let mut model = Model::new("Decay");
+        model.time.stop = 10.0;
+        model.time.dt = 0.5;
+        model.add_stock(Stock::new("Tank", "100").with_outflows(vec!["drain".to_string()])).unwrap();
+        model.add_parameter(Parameter::new("rate", 0.3)).unwrap();
+        model.add_flow(Flow::new("drain", "Tank * rate")).unwrap();
+
+        let report = DtSensitivityAnalyzer::new(DtSensitivityConfig::default()).run(&model).unwrap();
+        assert!(report.is_dt_dependent());
+        let tank = report.variables.iter().find(|v| v.variable == "Tank").unwrap();
+        assert!(tank.coarse > tank.half && tank.half > 0.0, "{:?}", tank);
+        let order = report.order.unwrap();
+        assert!((order - 1.0).abs() < 0.3, "{}", order);
+        let DtRecommendation::Dt(dt) = report.recommendation else { panic!("{:?}", report.recommendation) };
+        assert!(dt < 0.5);
+
+        // RK4 is accurate enough at this dt
+        let config = DtSensitivityConfig { integration_method: IntegrationMethod::RK4, ..DtSensitivityConfig::default() };
+        let report = DtSensitivityAnalyzer::new(config).run(&model).unwrap();
+        let tank = report.variables.iter().find(|v| v.variable == "Tank").unwrap();
+        assert!(tank.coarse < 1e-3, "{:?}", tank);
+
+        // A run that cannot be evaluated surfaces the engine's error
+        model.add_flow(Flow::new("leak", "Tank / 0")).unwrap();
+        model.stocks.get_mut("Tank").unwrap().outflows.push("leak".to_string());
+        let err = DtSensitivityAnalyzer::new(DtSensitivityConfig::default()).run(&model).unwrap_err();
+        assert!(matches!(err, crate::Error::Simulation(crate::SimulationError::Evaluation { .. })), "{}", err);
+    }
+}
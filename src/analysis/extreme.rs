@@ -0,0 +1,322 @@
+/// Extreme-conditions testing
+///
+/// A model should behave plausibly however far its inputs are pushed: with no
+/// workforce there is no production, with ten times the demand inventory does
+/// not go negative (Forrester and Senge, 1980). Each parameter in turn is set
+/// to zero, to ten times its value and to its declared minimum and maximum,
+/// and every run is checked for values that are not finite, stocks that go
+/// negative although they never do in the baseline, and values far outside
+/// anything the baseline produces.
+
+use std::collections::HashMap;
+use rayon::prelude::*;
+use crate::error::Result;
+use crate::model::Model;
+use crate::simulation::{IntegrationMethod, SimulationConfig, SimulationEngine, SimulationResults};
+
+#[derive(Debug, Clone)]
+pub struct ExtremeConditionsConfig {
+    /// Factor applied to each parameter's value for the "10x" case
+    pub multiplier: f64,
+    /// A value counts as implausible beyond this many times the largest
+    /// magnitude the variable reaches in the baseline (or 1, if larger)
+    pub plausibility_factor: f64,
+    pub integration_method: IntegrationMethod,
+}
+
+impl Default for ExtremeConditionsConfig {
+    fn default() -> Self {
+        Self {
+            multiplier: 10.0,
+            plausibility_factor: 100.0,
+            integration_method: IntegrationMethod::RK4,
+        }
+    }
+}
+
+/// Value a parameter is pushed to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExtremeCondition {
+    Zero,
+    /// The value times [`ExtremeConditionsConfig::multiplier`]
+    Multiplied,
+    Min,
+    Max,
+}
+
+/// Kind of implausible behavior
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViolationKind {
+    /// NaN or infinite
+    NotFinite,
+    /// A stock below zero that stays non-negative in the baseline
+    NegativeStock,
+    /// Beyond [`ExtremeConditionsConfig::plausibility_factor`] times the baseline
+    Implausible,
+}
+
+/// First time a variable behaves implausibly in a run
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub variable: String,
+    pub kind: ViolationKind,
+    pub time: f64,
+    pub value: f64,
+}
+
+/// One parameter at one extreme and what the run did
+#[derive(Debug, Clone)]
+pub struct ExtremeCase {
+    pub parameter: String,
+    pub condition: ExtremeCondition,
+    pub value: f64,
+    /// Why the run could not finish, if it failed
+    pub error: Option<String>,
+    pub violations: Vec<Violation>,
+}
+
+impl ExtremeCase {
+    pub fn passed(&self) -> bool {
+        self.error.is_none() && self.violations.is_empty()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExtremeConditionsReport {
+    pub model: String,
+    pub cases: Vec<ExtremeCase>,
+}
+
+pub struct ExtremeConditionsTester {
+    pub config: ExtremeConditionsConfig,
+}
+
+impl ExtremeConditionsTester {
+    pub fn new(config: ExtremeConditionsConfig) -> Self {
+        Self { config }
+    }
+
+    /// Run the baseline and every extreme case of every parameter; only a
+    /// failed baseline is an error
+    pub fn run(&self, model: &Model) -> Result<ExtremeConditionsReport> {
+        let baseline = self.simulate(model.clone())?;
+        let reference = Reference::new(&baseline);
+
+        let mut names: Vec<&String> = model.parameters.keys().collect();
+        names.sort();
+        let cases: Vec<(String, ExtremeCondition, f64)> = names.into_iter()
+            .flat_map(|name| {
+                self.conditions(model, name).into_iter().map(move |(condition, value)| (name.clone(), condition, value))
+            })
+            .collect();
+
+        let cases = cases.into_par_iter()
+            .map(|(parameter, condition, value)| {
+                let mut model = model.clone();
+                let param = model.parameters.get_mut(&parameter).expect("parameter of the model");
+                let original = param.value;
+                param.value = value;
+                // Arrayed values scale like the scalar value, or all take the bound
+                if let Some(values) = &mut param.values {
+                    for element in values.iter_mut() {
+                        *element = match condition {
+                            ExtremeCondition::Multiplied if original != 0.0 => *element * value / original,
+                            ExtremeCondition::Multiplied => *element * self.config.multiplier,
+                            _ => value,
+                        };
+                    }
+                }
+
+                match self.simulate(model) {
+                    Ok(results) => ExtremeCase { parameter, condition, value, error: None, violations: reference.violations(&results, &self.config) },
+                    Err(error) => ExtremeCase { parameter, condition, value, error: Some(error.to_string()), violations: Vec::new() },
+                }
+            })
+            .collect();
+
+        Ok(ExtremeConditionsReport { model: model.metadata.name.clone(), cases })
+    }
+
+    /// Distinct extreme values of a parameter, skipping its current value
+    fn conditions(&self, model: &Model, name: &str) -> Vec<(ExtremeCondition, f64)> {
+        let parameter = &model.parameters[name];
+        let candidates = [
+            (ExtremeCondition::Zero, Some(0.0)),
+            (ExtremeCondition::Multiplied, Some(parameter.value * self.config.multiplier)),
+            (ExtremeCondition::Min, parameter.min),
+            (ExtremeCondition::Max, parameter.max),
+        ];
+        let mut conditions: Vec<(ExtremeCondition, f64)> = Vec::new();
+        for (condition, value) in candidates {
+            if let Some(value) = value
+                && value != parameter.value
+                && !conditions.iter().any(|(_, v)| *v == value)
+            {
+                conditions.push((condition, value));
+            }
+        }
+        conditions
+    }
+
+    fn simulate(&self, model: Model) -> Result<SimulationResults> {
+        let config = SimulationConfig {
+            integration_method: self.config.integration_method,
+            ..SimulationConfig::default()
+        };
+        SimulationEngine::new(model, config)?.run()
+    }
+}
+
+/// What the baseline run says is plausible
+struct Reference {
+    /// Largest magnitude of each variable
+    scale: HashMap<String, f64>,
+    /// Stocks that never go negative
+    non_negative: Vec<String>,
+}
+
+impl Reference {
+    fn new(baseline: &SimulationResults) -> Self {
+        let scale = baseline.columns()
+            .map(|(name, series)| {
+                let largest = series.iter().copied().filter(|v| v.is_finite()).fold(0.0, |m: f64, v| m.max(v.abs()));
+                (name.clone(), largest.max(1.0))
+            })
+            .collect();
+        let non_negative = baseline.stocks.iter()
+            .filter(|(_, series)| series.iter().all(|v| *v >= 0.0))
+            .map(|(name, _)| name.clone())
+            .collect();
+        Self { scale, non_negative }
+    }
+
+    /// First violation of each kind for each variable of `results`
+    fn violations(&self, results: &SimulationResults, config: &ExtremeConditionsConfig) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        for (name, series) in results.columns() {
+            let limit = self.scale.get(name).map(|scale| scale * config.plausibility_factor);
+            let stock = self.non_negative.contains(name);
+            let mut first = |kind: ViolationKind, test: &dyn Fn(f64) -> bool| {
+                if let Some(i) = series.iter().position(|&v| test(v)) {
+                    violations.push(Violation { variable: name.clone(), kind, time: results.times[i], value: series[i] });
+                }
+            };
+            first(ViolationKind::NotFinite, &|v| !v.is_finite());
+            if stock {
+                first(ViolationKind::NegativeStock, &|v| v < 0.0);
+            }
+            if let Some(limit) = limit {
+                first(ViolationKind::Implausible, &|v| v.is_finite() && v.abs() > limit);
+            }
+        }
+        violations
+    }
+}
+
+impl ExtremeConditionsReport {
+    pub fn passed(&self) -> usize {
+        self.cases.iter().filter(|case| case.passed()).count()
+    }
+
+    /// Plain-text report: every case with its verdict and violations, then a summary
+    pub fn to_text(&self) -> String {
+        let mut text = format!("Extreme conditions test of {}\n\n", self.model);
+        for case in &self.cases {
+            let verdict = if case.passed() { "PASS" } else { "FAIL" };
+            text.push_str(&format!("{} {} = {} ({})\n", verdict, case.parameter, case.value, case.condition));
+            if let Some(error) = &case.error {
+                text.push_str(&format!("    run failed: {}\n", error));
+            }
+            for v in &case.violations {
+                text.push_str(&format!("    {} {} at t={} ({})\n", v.variable, v.kind, v.time, v.value));
+            }
+        }
+        text.push_str(&format!("\n{} cases: {} passed, {} failed\n", self.cases.len(), self.passed(), self.cases.len() - self.passed()));
+        text
+    }
+
+    /// CSV with a row per violation or failed run, and a row for each case that passed
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("parameter,condition,value,status,variable,violation,time,variable_value\n");
+        for case in &self.cases {
+            let prefix = format!("{},{},{}", case.parameter, case.condition, case.value);
+            if let Some(error) = &case.error {
+                csv.push_str(&format!("{},error,,\"{}\",,\n", prefix, error.replace('"', "\"\"")));
+            } else if case.violations.is_empty() {
+                csv.push_str(&format!("{},pass,,,,\n", prefix));
+            }
+            for v in &case.violations {
+                csv.push_str(&format!("{},fail,{},{},{},{}\n", prefix, v.variable, v.kind, v.time, v.value));
+            }
+        }
+        csv
+    }
+}
+
+impl std::fmt::Display for ExtremeCondition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ExtremeCondition::Zero => "zero",
+            ExtremeCondition::Multiplied => "multiplied",
+            ExtremeCondition::Min => "min",
+            ExtremeCondition::Max => "max",
+        })
+    }
+}
+
+impl std::fmt::Display for ViolationKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ViolationKind::NotFinite => "not finite",
+            ViolationKind::NegativeStock => "negative",
+            ViolationKind::Implausible => "implausible",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Stock, Flow, Parameter};
+
+    #[test]
+    fn test_extreme_conditions() {
+        // Shipments ignore how much inventory is left, so a large demand drives it negative
+        let mut model = Model::new("Warehouse");
+        model.time.stop = 10.0;
+        model.time.dt = 0.5;
+        model.add_stock(Stock::new("Inventory", "100")).unwrap();
+        model.add_parameter(Parameter::new("demand", 5.0).with_range(0.0, 8.0)).unwrap();
+        model.add_parameter(Parameter::new("lead_time", 2.0)).unwrap();
+        model.add_flow(Flow::new("shipments", "demand")).unwrap();
+        model.add_flow(Flow::new("receipts", "demand / lead_time")).unwrap();
+        model.stocks.get_mut("Inventory").unwrap().outflows.push("shipments".to_string());
+        model.stocks.get_mut("Inventory").unwrap().inflows.push("receipts".to_string());
+
+        let report = ExtremeConditionsTester::new(ExtremeConditionsConfig::default()).run(&model).unwrap();
+        let case = |parameter: &str, condition| report.cases.iter()
+            .find(|c| c.parameter == parameter && c.condition == condition)
+            .unwrap();
+
+        // demand: 0, 50 and 8; the declared minimum repeats zero
+        assert_eq!(report.cases.iter().filter(|c| c.parameter == "demand").count(), 3);
+        assert!(case("demand", ExtremeCondition::Zero).passed());
+        assert!(case("demand", ExtremeCondition::Max).passed());
+        let multiplied = case("demand", ExtremeCondition::Multiplied);
+        assert_eq!(multiplied.value, 50.0);
+        assert_eq!(multiplied.violations[0].variable, "Inventory");
+        assert_eq!(multiplied.violations[0].kind, ViolationKind::NegativeStock);
+        assert_eq!(multiplied.violations[0].time, 4.5);
+
+        // A zero lead time divides by zero
+        let zero = case("lead_time", ExtremeCondition::Zero);
+        assert_eq!(zero.error.as_deref(), Some("Error evaluating flow 'receipts' at t=0: Division by zero"));
+        assert!(zero.violations.is_empty());
+        assert!(report.to_text().contains("FAIL demand = 50 (multiplied)\n    Inventory negative at t=4.5"));
+
+        // Only a failing baseline fails the whole test
+        model.parameters.get_mut("lead_time").unwrap().value = 0.0;
+        let err = ExtremeConditionsTester::new(ExtremeConditionsConfig::default()).run(&model).unwrap_err();
+        assert!(matches!(err, crate::Error::Simulation(crate::SimulationError::Evaluation { .. })), "{}", err);
+    }
+}
@@ -0,0 +1,165 @@
+/// Goodness-of-fit statistics of a calibrated run
+///
+/// For each observed variable: error measures (RMSE, MAE, MAPE), the
+/// coefficient of determination, Theil's inequality statistics with the
+/// decomposition of the mean squared error into bias, unequal variation and
+/// unequal covariation (Sterman, 1984), and information criteria from the
+/// Gaussian likelihood for comparing models with different parameter counts.
+
+use crate::simulation::SimulationResults;
+use super::calibration::{FitObjective, ObservedData};
+
+/// Fit of one variable's simulated values to its observations
+#[derive(Debug, Clone)]
+pub struct FitStatistics {
+    pub variable: String,
+    /// Number of observations compared
+    pub n: usize,
+    pub rmse: f64,
+    pub mae: f64,
+    /// Mean absolute percentage error over the nonzero observations
+    pub mape: f64,
+    /// Fraction of the variance of the observations explained: 1 - SSE / SST
+    pub r_squared: f64,
+    /// Theil's inequality coefficient: 0 for a perfect fit, at most 1
+    pub theil_u: f64,
+    /// Fraction of the mean squared error from unequal means (U^M)
+    pub bias_fraction: f64,
+    /// Fraction from unequal standard deviations (U^S)
+    pub variance_fraction: f64,
+    /// Fraction from imperfect correlation (U^C); U^M + U^S + U^C = 1
+    pub covariance_fraction: f64,
+    /// Gaussian log-likelihood with the error variance at its maximum
+    pub log_likelihood: f64,
+    /// Akaike and Bayesian information criteria, counting the calibrated
+    /// parameters plus the error variance
+    pub aic: f64,
+    pub bic: f64,
+}
+
+impl FitStatistics {
+    /// Compare paired `simulated` and `observed` values; `parameters` is the
+    /// number of parameters estimated from the data
+    pub fn new(variable: &str, simulated: &[f64], observed: &[f64], parameters: usize) -> Self {
+        let n = simulated.len().min(observed.len());
+        let (simulated, observed) = (&simulated[..n], &observed[..n]);
+        let count = n as f64;
+
+        let residuals: Vec<f64> = simulated.iter().zip(observed).map(|(s, a)| s - a).collect();
+        let mse = residuals.iter().map(|r| r * r).sum::<f64>() / count;
+        let mae = residuals.iter().map(|r| r.abs()).sum::<f64>() / count;
+
+        let percentages: Vec<f64> = residuals.iter().zip(observed)
+            .filter(|(_, a)| **a != 0.0)
+            .map(|(r, a)| (r / a).abs())
+            .collect();
+        let mape = 100.0 * percentages.iter().sum::<f64>() / percentages.len() as f64;
+
+        let (mean_s, sd_s) = mean_sd(simulated);
+        let (mean_a, sd_a) = mean_sd(observed);
+        let covariance = simulated.iter().zip(observed)
+            .map(|(s, a)| (s - mean_s) * (a - mean_a))
+            .sum::<f64>() / count;
+
+        // MSE = (mean_s - mean_a)^2 + (sd_s - sd_a)^2 + 2 (1 - r) sd_s sd_a
+        let (bias_fraction, variance_fraction, covariance_fraction) = if mse > 0.0 {
+            (
+                (mean_s - mean_a).powi(2) / mse,
+                (sd_s - sd_a).powi(2) / mse,
+                2.0 * (sd_s * sd_a - covariance) / mse,
+            )
+        } else {
+            (0.0, 0.0, 0.0)
+        };
+
+        let root_mean_square = |values: &[f64]| (values.iter().map(|v| v * v).sum::<f64>() / count).sqrt();
+        let theil_u = mse.sqrt() / (root_mean_square(simulated) + root_mean_square(observed));
+
+        let log_likelihood = -FitObjective::Likelihood.score(&residuals);
+        let k = (parameters + 1) as f64;
+
+        Self {
+            variable: variable.to_string(),
+            n,
+            rmse: mse.sqrt(),
+            mae,
+            mape,
+            r_squared: 1.0 - mse / (sd_a * sd_a),
+            theil_u,
+            bias_fraction,
+            variance_fraction,
+            covariance_fraction,
+            log_likelihood,
+            aic: 2.0 * k - 2.0 * log_likelihood,
+            bic: k * count.ln() - 2.0 * log_likelihood,
+        }
+    }
+
+    /// Statistics for every observed variable, skipping missing observations
+    pub fn for_results(data: &ObservedData, results: &SimulationResults, parameters: usize) -> Result<Vec<Self>, String> {
+        let simulated = data.simulated(results)?;
+        Ok(data.series.iter()
+            .map(|(name, observed)| {
+                let (simulated, observed): (Vec<f64>, Vec<f64>) = simulated[name].iter()
+                    .zip(observed)
+                    .filter(|(_, value)| !value.is_nan())
+                    .unzip();
+                Self::new(name, &simulated, &observed, parameters)
+            })
+            .collect())
+    }
+}
+
+/// CSV with one row per variable
+pub fn fit_statistics_csv(statistics: &[FitStatistics]) -> String {
+    let mut csv = String::from("variable,n,rmse,mae,mape,r_squared,theil_u,u_m,u_s,u_c,log_likelihood,aic,bic\n");
+    for s in statistics {
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{},{}\n",
+            s.variable, s.n, s.rmse, s.mae, s.mape, s.r_squared, s.theil_u,
+            s.bias_fraction, s.variance_fraction, s.covariance_fraction,
+            s.log_likelihood, s.aic, s.bic
+        ));
+    }
+    csv
+}
+
+/// Mean and population standard deviation, as in Theil's decomposition
+fn mean_sd(values: &[f64]) -> (f64, f64) {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+    (mean, variance.sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_theil_decomposition() {
+        let observed = [1.0, 2.0, 3.0, 4.0];
+
+        // A constant offset is all bias
+        let shifted = [2.0, 3.0, 4.0, 5.0];
+        let fit = FitStatistics::new("x", &shifted, &observed, 1);
+        assert_eq!(fit.rmse, 1.0);
+        assert!((fit.bias_fraction - 1.0).abs() < 1e-12);
+        assert!(fit.variance_fraction.abs() < 1e-12 && fit.covariance_fraction.abs() < 1e-12);
+        assert!((fit.mape - 100.0 * (1.0 + 0.5 + 1.0 / 3.0 + 0.25) / 4.0).abs() < 1e-9);
+        assert!((fit.r_squared - (1.0 - 1.0 / 1.25)).abs() < 1e-12);
+
+        // Reversed values have the right mean and spread but run the wrong way
+        let reversed = [4.0, 3.0, 2.0, 1.0];
+        let fit = FitStatistics::new("x", &reversed, &observed, 1);
+        assert!(fit.bias_fraction.abs() < 1e-12 && fit.variance_fraction.abs() < 1e-12);
+        assert!((fit.covariance_fraction - 1.0).abs() < 1e-12);
+
+        // More parameters for the same fit cost 2 each in AIC
+        let simpler = FitStatistics::new("x", &shifted, &observed, 0);
+        assert!((FitStatistics::new("x", &shifted, &observed, 1).aic - simpler.aic - 2.0).abs() < 1e-12);
+
+        let perfect = FitStatistics::new("x", &observed, &observed, 1);
+        assert_eq!((perfect.rmse, perfect.r_squared, perfect.theil_u), (0.0, 1.0, 0.0));
+    }
+}
@@ -1,15 +1,43 @@
 /// Analysis module for model validation and sensitivity analysis
 
 pub mod sensitivity;
+pub mod sampling;
 pub mod structure;
+pub mod polarity;
 pub mod monte_carlo;
 pub mod stability;
+pub mod bifurcation;
+pub mod phase_plane;
 pub mod optimization;
+pub mod policy;
 pub mod parallel;
+pub mod calibration;
+pub mod fit;
+pub mod bayesian;
+pub mod assimilation;
+pub mod validation;
+pub mod extreme;
+pub mod dt_sensitivity;
+pub mod comparison;
+pub mod scenarios;
 
 pub use sensitivity::{SensitivityAnalyzer, ParameterRange, ParameterSample, SensitivityResult};
-pub use structure::{StructureAnalyzer, DependencyGraph, FeedbackLoop, Polarity, ElementType};
+pub use sampling::SamplingMethod;
+pub use structure::{StructureAnalyzer, DependencyGraph, FeedbackLoop, Polarity, ElementType, Stratum};
+pub use polarity::SignAnalysis;
 pub use monte_carlo::{MonteCarloSimulator, MonteCarloConfig, MonteCarloResults, TimeSeriesStatistics};
 pub use stability::{StabilityAnalyzer, StabilityAnalysis, StabilityType};
-pub use optimization::{OptimizationResult, GradientOptimizer, GeneticOptimizer, OptimizationConfig};
+pub use bifurcation::{BifurcationAnalyzer, BifurcationDiagram, Bifurcation, BifurcationType};
+pub use phase_plane::{PhasePlane, PhasePortrait};
+pub use optimization::{OptimizationResult, GradientOptimizer, GeneticOptimizer, OptimizationConfig, ParameterBounds, ObjectiveFunction};
+pub use policy::{DecisionVariable, PolicyProblem, Policy, PolicyObjective};
+pub use calibration::{FitObjective, ObservedData};
+pub use fit::{FitStatistics, fit_statistics_csv};
+pub use bayesian::{BayesianCalibrator, McmcConfig, Posterior, Prior, ParameterPrior};
+pub use assimilation::{EnsembleKalmanFilter, AssimilationConfig, AssimilationResults};
 pub use parallel::{ParallelMonteCarloSimulator, ParallelSensitivityAnalyzer};
+pub use validation::{validate_model, ValidationReport};
+pub use extreme::{ExtremeConditionsTester, ExtremeConditionsConfig, ExtremeConditionsReport, ExtremeCase, ExtremeCondition, Violation, ViolationKind};
+pub use dt_sensitivity::{DtSensitivityAnalyzer, DtSensitivityConfig, DtSensitivityReport, DtRecommendation, VariableDivergence};
+pub use comparison::{RunComparison, VariableComparison, DifferenceSummary};
+pub use scenarios::{Scenario, ScenarioTime, run_scenarios};
@@ -11,6 +11,7 @@ use rand::prelude::*;
 use crate::model::Model;
 use crate::simulation::{SimulationEngine, SimulationConfig, SimulationResults};
 use crate::analysis::sensitivity::{ParameterRange, ParameterSample};
+use crate::analysis::sampling::{self, SamplingMethod};
 
 /// Monte Carlo simulation configuration
 #[derive(Debug, Clone)]
@@ -26,6 +27,9 @@ pub struct MonteCarloConfig {
 
     /// Whether to save individual run results or just aggregates
     pub save_individual_runs: bool,
+
+    /// How parameter values are spread over their ranges
+    pub sampling: SamplingMethod,
 }
 
 impl Default for MonteCarloConfig {
@@ -35,6 +39,7 @@ impl Default for MonteCarloConfig {
             seed: None,
             confidence_level: 0.95,
             save_individual_runs: false,
+            sampling: SamplingMethod::Random,
         }
     }
 }
@@ -119,36 +124,22 @@ impl MonteCarloSimulator {
         let mut all_runs: Vec<HashMap<String, Vec<f64>>> = Vec::new();
         let mut time_vec: Option<Vec<f64>> = None;
 
-        // Run simulations
-        for run_idx in 0..self.mc_config.n_runs {
-            // Sample parameters
-            let sample = self.sample_parameters(&mut rng);
+        let samples = sampling::parameter_samples(
+            &self.parameter_ranges, self.mc_config.sampling, self.mc_config.n_runs, &mut rng,
+        )?;
 
+        // Run simulations
+        for (run_idx, sample) in samples.into_iter().enumerate() {
             // Run simulation
             let run_results = self.run_single_simulation(base_model, sim_config, &sample)?;
 
-            // Extract time series
-            let mut run_data = HashMap::new();
-
             // Extract time if first run
             if time_vec.is_none() {
-                time_vec = Some(run_results.states.iter().map(|s| s.time).collect());
+                time_vec = Some(run_results.times.clone());
             }
 
             // Extract all variable time series
-            for state in &run_results.states {
-                for (name, &value) in &state.stocks {
-                    run_data.entry(name.clone()).or_insert_with(Vec::new).push(value);
-                }
-                for (name, &value) in &state.flows {
-                    run_data.entry(name.clone()).or_insert_with(Vec::new).push(value);
-                }
-                for (name, &value) in &state.auxiliaries {
-                    run_data.entry(name.clone()).or_insert_with(Vec::new).push(value);
-                }
-            }
-
-            all_runs.push(run_data);
+            all_runs.push(run_results.to_map());
 
             if (run_idx + 1) % 10 == 0 {
                 eprintln!("Completed {}/{} Monte Carlo runs", run_idx + 1, self.mc_config.n_runs);
@@ -175,18 +166,6 @@ impl MonteCarloSimulator {
         })
     }
 
-    /// Sample parameters uniformly from ranges
-    fn sample_parameters(&self, rng: &mut impl Rng) -> ParameterSample {
-        let mut sample = ParameterSample::new();
-
-        for param_range in &self.parameter_ranges {
-            let value = param_range.sample_uniform(rng);
-            sample.set(param_range.name.clone(), value);
-        }
-
-        sample
-    }
-
     /// Run single simulation with parameter sample
     fn run_single_simulation(
         &self,
@@ -203,7 +182,7 @@ impl MonteCarloSimulator {
 
         // Run simulation
         let mut engine = SimulationEngine::new(model, config.clone())?;
-        engine.run()
+        Ok(engine.run()?)
     }
 
     /// Calculate statistics across all runs
@@ -271,7 +250,7 @@ impl MonteCarloSimulator {
     }
 
     /// Calculate percentile from sorted values
-    fn percentile(sorted_values: &[f64], p: f64) -> f64 {
+    pub(crate) fn percentile(sorted_values: &[f64], p: f64) -> f64 {
         let n = sorted_values.len();
         if n == 0 {
             return 0.0;
@@ -295,31 +274,75 @@ impl MonteCarloSimulator {
         results: &MonteCarloResults,
         variable_name: &str,
     ) -> Result<String, String> {
-        let stats = results.statistics.get(variable_name)
+        results.export_csv(variable_name)
+    }
+}
+
+impl TimeSeriesStatistics {
+    /// Named statistic series, in CSV column order
+    pub fn series(&self) -> [(&'static str, &[f64]); 11] {
+        [
+            ("mean", &self.mean),
+            ("std_dev", &self.std_dev),
+            ("min", &self.min),
+            ("max", &self.max),
+            ("p5", &self.percentile_5),
+            ("p25", &self.percentile_25),
+            ("median", &self.percentile_50),
+            ("p75", &self.percentile_75),
+            ("p95", &self.percentile_95),
+            ("lower_ci", &self.lower_ci),
+            ("upper_ci", &self.upper_ci),
+        ]
+    }
+}
+
+impl MonteCarloResults {
+    /// Export the statistics of one variable to CSV
+    pub fn export_csv(&self, variable_name: &str) -> Result<String, String> {
+        let stats = self.statistics.get(variable_name)
             .ok_or_else(|| format!("Variable '{}' not found in results", variable_name))?;
+        let series = stats.series();
+
+        let mut csv = String::from("time");
+        for (name, _) in &series {
+            csv.push(',');
+            csv.push_str(name);
+        }
+        csv.push('\n');
 
-        let mut csv = String::new();
-
-        // Header
-        csv.push_str("time,mean,std_dev,min,max,p5,p25,median,p75,p95,lower_ci,upper_ci\n");
-
-        // Data rows
-        for i in 0..results.time.len() {
-            csv.push_str(&format!(
-                "{},{},{},{},{},{},{},{},{},{},{},{}\n",
-                results.time[i],
-                stats.mean[i],
-                stats.std_dev[i],
-                stats.min[i],
-                stats.max[i],
-                stats.percentile_5[i],
-                stats.percentile_25[i],
-                stats.percentile_50[i],
-                stats.percentile_75[i],
-                stats.percentile_95[i],
-                stats.lower_ci[i],
-                stats.upper_ci[i],
-            ));
+        for (i, time) in self.time.iter().enumerate() {
+            csv.push_str(&time.to_string());
+            for (_, values) in &series {
+                csv.push_str(&format!(",{}", values[i]));
+            }
+            csv.push('\n');
+        }
+
+        Ok(csv)
+    }
+
+    /// Export every saved run of one variable to CSV, one column per run
+    pub fn export_runs_csv(&self, variable_name: &str) -> Result<String, String> {
+        let runs = self.individual_runs.as_ref()
+            .ok_or("Individual runs were not saved")?;
+        let columns = runs.iter()
+            .map(|run| run.get(variable_name)
+                .ok_or_else(|| format!("Variable '{}' not found in results", variable_name)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut csv = String::from("time");
+        for run in 0..columns.len() {
+            csv.push_str(&format!(",run_{}", run));
+        }
+        csv.push('\n');
+
+        for (i, time) in self.time.iter().enumerate() {
+            csv.push_str(&time.to_string());
+            for column in &columns {
+                csv.push_str(&format!(",{}", column.get(i).copied().unwrap_or(f64::NAN)));
+            }
+            csv.push('\n');
         }
 
         Ok(csv)
@@ -352,6 +375,7 @@ mod tests {
             seed: Some(42),
             confidence_level: 0.95,
             save_individual_runs: false,
+            ..Default::default()
         };
 
         let simulator = MonteCarloSimulator::new(param_ranges, mc_config);
@@ -391,6 +415,7 @@ mod tests {
         let mc_config = MonteCarloConfig {
             n_runs: 5,
             seed: Some(42),
+            save_individual_runs: true,
             ..Default::default()
         };
 
@@ -402,5 +427,56 @@ mod tests {
 
         assert!(csv.contains("time,mean,std_dev"));
         assert!(csv.lines().count() > 1);
+
+        let runs = results.export_runs_csv("S").unwrap();
+        assert!(runs.starts_with("time,run_0,run_1,run_2,run_3,run_4\n"));
+        assert_eq!(runs.lines().count(), csv.lines().count());
+    }
+
+    #[test]
+    fn test_parallel_exports() {
+        let mut model = Model::new("Test");
+        model.time.stop = 5.0;
+        model.add_stock(Stock::new("S", "100").with_inflows(vec!["f".to_string()])).unwrap();
+        model.add_parameter(Parameter::new("r", 0.1)).unwrap();
+        model.add_flow(Flow::new("f", "S * r")).unwrap();
+
+        let run = |confidence_level: f64| {
+            let mc_config = MonteCarloConfig {
+                n_runs: 8,
+                seed: Some(7),
+                confidence_level,
+                save_individual_runs: true,
+                ..Default::default()
+            };
+            let ranges = vec![ParameterRange::new("r".to_string(), 0.05, 0.15, 0.1)];
+            crate::analysis::ParallelMonteCarloSimulator::new(ranges, mc_config)
+                .run(&model, &SimulationConfig::default())
+                .unwrap()
+        };
+        let results = run(0.95);
+        let stats = results.export_csv("S").unwrap();
+        let runs = results.export_runs_csv("S").unwrap();
+
+        // The same seed gives the same runs, whichever thread ran them
+        assert_eq!(run(0.95).export_runs_csv("S").unwrap(), runs);
+
+        let parse = |line: &str| line.split(',').map(|v| v.parse::<f64>().unwrap()).collect::<Vec<_>>();
+        assert_eq!(stats.lines().next(), Some("time,mean,std_dev,min,max,p5,p25,median,p75,p95,lower_ci,upper_ci"));
+        for (stats_row, runs_row) in stats.lines().zip(runs.lines()).skip(1) {
+            let (stats_row, runs_row) = (parse(stats_row), parse(runs_row));
+            assert_eq!(stats_row[0], runs_row[0]);
+            let values = &runs_row[1..];
+            assert_eq!(stats_row[3], values.iter().copied().fold(f64::INFINITY, f64::min));
+            assert_eq!(stats_row[4], values.iter().copied().fold(f64::NEG_INFINITY, f64::max));
+            assert!((stats_row[1] - values.iter().sum::<f64>() / 8.0).abs() < 1e-9);
+        }
+
+        // A lower confidence level narrows the interval at the final time
+        let width = |results: &MonteCarloResults| {
+            let s = &results.statistics["S"];
+            s.upper_ci.last().unwrap() - s.lower_ci.last().unwrap()
+        };
+        assert!(width(&run(0.5)) < width(&results));
     }
 }
@@ -4,7 +4,7 @@
 /// for parameter estimation and model calibration
 
 use crate::model::{Model, Parameter};
-use crate::simulation::{SimulationEngine, SimulationConfig, IntegrationMethod};
+use crate::simulation::{SimulationEngine, SimulationConfig, SimulationResults, IntegrationMethod};
 use rand::Rng;
 use rand::distributions::{Distribution, Uniform};
 use std::collections::HashMap;
@@ -73,8 +73,8 @@ impl ParameterBounds {
     }
 }
 
-/// Objective function type
-pub type ObjectiveFunction = Box<dyn Fn(&Model, &SimulationEngine) -> Result<f64, String>>;
+/// Objective function type, scoring a model run by its results
+pub type ObjectiveFunction = Box<dyn Fn(&Model, &SimulationResults) -> Result<f64, String>>;
 
 /// Gradient-based optimizer using BFGS quasi-Newton method
 pub struct GradientOptimizer {
@@ -256,13 +256,14 @@ impl GradientOptimizer {
         let config = SimulationConfig {
             integration_method: self.config.integration_method,
             output_interval: None,
+            ..SimulationConfig::default()
         };
 
         let mut engine = SimulationEngine::new(model_copy.clone(), config)?;
-        let _results = engine.run()?;
+        let results = engine.run()?;
 
         // Evaluate objective
-        objective(&model_copy, &engine)
+        objective(&model_copy, &results)
     }
 
     /// Compute gradient using finite differences
@@ -481,12 +482,13 @@ impl GeneticOptimizer {
         let config = SimulationConfig {
             integration_method: self.config.integration_method,
             output_interval: None,
+            ..SimulationConfig::default()
         };
 
         let mut engine = SimulationEngine::new(model_copy.clone(), config)?;
-        let _results = engine.run()?;
+        let results = engine.run()?;
 
-        objective(&model_copy, &engine)
+        objective(&model_copy, &results)
     }
 
     /// Tournament selection
@@ -557,8 +559,8 @@ mod tests {
         model.stocks.get_mut("X").unwrap().inflows.push("growth".to_string());
 
         // Objective: minimize difference from target final value
-        let objective: ObjectiveFunction = Box::new(|_model, engine| {
-            let final_x = engine.current_state().stocks.get("X").unwrap_or(&0.0);
+        let objective: ObjectiveFunction = Box::new(|_model, results| {
+            let final_x = results.final_value("X").unwrap_or(0.0);
             let target = 10.0;
             Ok((final_x - target).powi(2))
         });
@@ -10,6 +10,7 @@ use crate::model::Model;
 use crate::simulation::{SimulationEngine, SimulationConfig, SimulationResults};
 use crate::analysis::sensitivity::{ParameterRange, ParameterSample, SensitivityResult};
 use crate::analysis::monte_carlo::{MonteCarloConfig, MonteCarloResults, TimeSeriesStatistics};
+use crate::analysis::sampling;
 use rand::prelude::*;
 use rand::rngs::StdRng;
 
@@ -36,7 +37,7 @@ impl ParallelMonteCarloSimulator {
         let n_runs = self.mc_config.n_runs;
 
         // Generate all parameter samples upfront
-        let samples: Vec<ParameterSample> = self.generate_samples(n_runs);
+        let samples: Vec<ParameterSample> = self.generate_samples(n_runs)?;
 
         // Run simulations in parallel
         let results: Vec<(usize, Result<HashMap<String, Vec<f64>>, String>)> = samples
@@ -53,29 +54,13 @@ impl ParallelMonteCarloSimulator {
     }
 
     /// Generate parameter samples
-    fn generate_samples(&self, n_runs: usize) -> Vec<ParameterSample> {
+    fn generate_samples(&self, n_runs: usize) -> Result<Vec<ParameterSample>, String> {
         let mut rng = match self.mc_config.seed {
             Some(seed) => StdRng::seed_from_u64(seed),
             None => StdRng::from_entropy(),
         };
 
-        (0..n_runs)
-            .map(|_| self.sample_parameters(&mut rng))
-            .collect()
-    }
-
-    /// Sample parameters from ranges
-    fn sample_parameters(&self, rng: &mut StdRng) -> ParameterSample {
-        let mut sample = ParameterSample {
-            values: HashMap::new(),
-        };
-
-        for param_range in &self.parameter_ranges {
-            let value = param_range.sample_uniform(rng);
-            sample.values.insert(param_range.name.clone(), value);
-        }
-
-        sample
+        sampling::parameter_samples(&self.parameter_ranges, self.mc_config.sampling, n_runs, &mut rng)
     }
 
     /// Run a single simulation with given parameters
@@ -379,13 +364,29 @@ impl ParallelMonteCarloSimulator {
     }
 
     /// Get z-score for confidence level
+    ///
+    /// The standard normal quantile at `(1 + confidence) / 2`, by Acklam's
+    /// rational approximation (relative error below 1.2e-9).
     fn z_score(confidence: f64) -> f64 {
-        // Approximate z-scores for common confidence levels
-        match confidence {
-            c if (c - 0.90).abs() < 0.01 => 1.645,
-            c if (c - 0.95).abs() < 0.01 => 1.96,
-            c if (c - 0.99).abs() < 0.01 => 2.576,
-            _ => 1.96,  // Default to 95%
+        const A: [f64; 6] = [-3.969683028665376e1, 2.209460984245205e2, -2.759285104469687e2,
+            1.38357751867269e2, -3.066479806614716e1, 2.506628277459239];
+        const B: [f64; 5] = [-5.447609879822406e1, 1.615858368580409e2, -1.556989798598866e2,
+            6.680131188771972e1, -1.328068155288572e1];
+        const C: [f64; 6] = [-7.784894002430293e-3, -3.223964580411365e-1, -2.400758277161838,
+            -2.549732539343734, 4.374664141464968, 2.938163982698783];
+        const D: [f64; 4] = [7.784695709041462e-3, 3.224671290700398e-1, 2.445134137142996,
+            3.754408661907416];
+
+        let p = (1.0 + confidence.clamp(0.0, 1.0 - 1e-12)) / 2.0;
+        if p > 1.0 - 0.02425 {
+            let q = (-2.0 * (1.0 - p).ln()).sqrt();
+            -(((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+                / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+        } else {
+            let q = p - 0.5;
+            let r = q * q;
+            (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+                / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
         }
     }
 }
@@ -542,12 +543,14 @@ mod tests {
             seed: Some(42),
             confidence_level: 0.95,
             save_individual_runs: false,
+            ..Default::default()
         };
 
         let simulator = ParallelMonteCarloSimulator::new(param_ranges, mc_config);
         let sim_config = SimulationConfig {
             integration_method: IntegrationMethod::Euler,
             output_interval: None,
+            ..SimulationConfig::default()
         };
 
         let results = simulator.run(&model, &sim_config).unwrap();
@@ -555,4 +558,12 @@ mod tests {
         assert_eq!(results.n_runs, 10);
         assert!(results.statistics.contains_key("X"));
     }
+
+    #[test]
+    fn test_z_score() {
+        for (confidence, z) in [(0.5, 0.6744898), (0.9, 1.6448536), (0.95, 1.9599640), (0.99, 2.5758293)] {
+            let score = ParallelMonteCarloSimulator::z_score(confidence);
+            assert!((score - z).abs() < 1e-6, "{}: {}", confidence, score);
+        }
+    }
 }
@@ -0,0 +1,309 @@
+/// Phase-plane analysis of two stocks
+///
+/// For a pair of stocks the state space is a plane: each point has a velocity
+/// given by the two net flows. A phase portrait samples that vector field on
+/// a grid, traces the nullclines where either stock's net flow is zero (their
+/// crossings are the equilibria) and simulates trajectories from a grid of
+/// starting points, showing cycles such as those of predator-prey models.
+///
+/// Any other stocks are held at their initial values for the vector field
+/// and nullclines, and simulated freely along the trajectories.
+
+use crate::error::{ModelError, Result, SimulationError};
+use crate::model::{Expression, Model};
+use crate::simulation::{IntegrationMethod, SimulationConfig, SimulationEngine, SimulationState};
+use crate::simulation::integrator::stock_derivatives;
+
+/// Bisection steps placing a nullcline point between two scanned points
+const REFINEMENTS: usize = 30;
+
+/// A window on the plane of stocks `x` and `y` and how densely to sample it
+#[derive(Debug, Clone)]
+pub struct PhasePlane {
+    pub x: String,
+    pub y: String,
+    pub x_range: (f64, f64),
+    pub y_range: (f64, f64),
+    /// Vector field samples along each axis
+    pub grid: usize,
+    /// Trajectory starting points along each axis
+    pub starts: usize,
+    /// Points scanned along each axis for nullclines
+    pub resolution: usize,
+    pub integration_method: IntegrationMethod,
+}
+
+/// Net flows of the two stocks at one point
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VectorSample {
+    pub x: f64,
+    pub y: f64,
+    pub dx: f64,
+    pub dy: f64,
+}
+
+/// A simulated path through the plane
+#[derive(Debug, Clone)]
+pub struct Trajectory {
+    pub times: Vec<f64>,
+    pub points: Vec<(f64, f64)>,
+}
+
+/// Vector field, nullclines and trajectories of a [`PhasePlane`]
+#[derive(Debug, Clone)]
+pub struct PhasePortrait {
+    pub x: String,
+    pub y: String,
+    pub x_range: (f64, f64),
+    pub y_range: (f64, f64),
+    pub vectors: Vec<VectorSample>,
+    /// Points where the net flow of `x` is zero
+    pub x_nullcline: Vec<(f64, f64)>,
+    /// Points where the net flow of `y` is zero
+    pub y_nullcline: Vec<(f64, f64)>,
+    pub trajectories: Vec<Trajectory>,
+}
+
+impl PhasePlane {
+    pub fn new(x: &str, y: &str, x_range: (f64, f64), y_range: (f64, f64)) -> Self {
+        Self {
+            x: x.to_string(),
+            y: y.to_string(),
+            x_range,
+            y_range,
+            grid: 20,
+            starts: 5,
+            resolution: 200,
+            integration_method: IntegrationMethod::RK4,
+        }
+    }
+
+    /// Sample the vector field, trace the nullclines and simulate the trajectories of `model`
+    pub fn run(&self, model: &Model) -> Result<PhasePortrait> {
+        let mut model = model.clone();
+        model.compile()?;
+        for name in [&self.x, &self.y] {
+            if !model.stocks.contains_key(name) {
+                return Err(ModelError::NotFound { kind: "Stock", name: name.clone() }.into());
+            }
+        }
+        if self.x == self.y {
+            return Err(SimulationError::from("A phase plane needs two different stocks".to_string()).into());
+        }
+        if !(self.x_range.0 < self.x_range.1 && self.y_range.0 < self.y_range.1) {
+            return Err(SimulationError::from("Phase plane ranges must have min < max".to_string()).into());
+        }
+
+        let field = Field {
+            model: &model,
+            state: SimulationState::initialize_from_model(&model)?,
+            stocks: [self.x.clone(), self.y.clone()],
+        };
+
+        let mut vectors = Vec::new();
+        for &x in &spaced(self.x_range, self.grid) {
+            for &y in &spaced(self.y_range, self.grid) {
+                let [dx, dy] = field.velocity(x, y)?;
+                vectors.push(VectorSample { x, y, dx, dy });
+            }
+        }
+
+        let [x_nullcline, y_nullcline] = self.nullclines(&field)?;
+
+        let mut trajectories = Vec::new();
+        for &x in &spaced(self.x_range, self.starts) {
+            for &y in &spaced(self.y_range, self.starts) {
+                trajectories.push(self.trajectory(&model, x, y)?);
+            }
+        }
+
+        Ok(PhasePortrait {
+            x: self.x.clone(),
+            y: self.y.clone(),
+            x_range: self.x_range,
+            y_range: self.y_range,
+            vectors,
+            x_nullcline,
+            y_nullcline,
+            trajectories,
+        })
+    }
+
+    /// Zeros of the net flows of `x` and `y`, found where it changes sign along the
+    /// rows and columns of a scan grid and refined by bisection
+    fn nullclines(&self, field: &Field) -> Result<[Vec<(f64, f64)>; 2], SimulationError> {
+        let xs = spaced(self.x_range, self.resolution + 1);
+        let ys = spaced(self.y_range, self.resolution + 1);
+        let mut values = vec![vec![[0.0; 2]; ys.len()]; xs.len()];
+        for (i, &x) in xs.iter().enumerate() {
+            for (j, &y) in ys.iter().enumerate() {
+                values[i][j] = field.velocity(x, y)?;
+            }
+        }
+
+        let mut nullclines = [Vec::new(), Vec::new()];
+        for (k, nullcline) in nullclines.iter_mut().enumerate() {
+            for (i, &x) in xs.iter().enumerate() {
+                for (j, &y) in ys.iter().enumerate() {
+                    let value = values[i][j][k];
+                    if value == 0.0 {
+                        nullcline.push((x, y));
+                        continue;
+                    }
+                    // Along the column to the next y, and along the row to the next x
+                    if let Some(&next_y) = ys.get(j + 1) && value * values[i][j + 1][k] < 0.0 {
+                        let y = bisect(y, next_y, value, |y| field.velocity(x, y).map(|v| v[k]))?;
+                        nullcline.push((x, y));
+                    }
+                    if let Some(&next_x) = xs.get(i + 1) && value * values[i + 1][j][k] < 0.0 {
+                        let x = bisect(x, next_x, value, |x| field.velocity(x, y).map(|v| v[k]))?;
+                        nullcline.push((x, y));
+                    }
+                }
+            }
+        }
+
+        Ok(nullclines)
+    }
+
+    /// Simulate `model` from `x` and `y` with the other stocks at their initial values
+    fn trajectory(&self, model: &Model, x: f64, y: f64) -> Result<Trajectory> {
+        let mut model = model.clone();
+        for (name, value) in [(&self.x, x), (&self.y, y)] {
+            model.stocks.get_mut(name).expect("checked in run").initial = Expression::Constant(value);
+        }
+        let config = SimulationConfig {
+            integration_method: self.integration_method,
+            ..SimulationConfig::default()
+        };
+        let results = SimulationEngine::new(model, config)?.run()?;
+        let recorded = |name: &str| results.series(name)
+            .ok_or_else(|| SimulationError::from(format!("Stock '{}' not recorded", name)));
+        let (xs, ys) = (recorded(&self.x)?, recorded(&self.y)?);
+        Ok(Trajectory {
+            times: results.times.clone(),
+            points: xs.iter().copied().zip(ys.iter().copied()).collect(),
+        })
+    }
+}
+
+/// Net flows of two stocks with everything else at the initial state
+struct Field<'a> {
+    model: &'a Model,
+    state: SimulationState,
+    stocks: [String; 2],
+}
+
+impl Field<'_> {
+    fn velocity(&self, x: f64, y: f64) -> Result<[f64; 2], SimulationError> {
+        let mut state = self.state.clone();
+        state.stocks.insert(&self.stocks[0], x);
+        state.stocks.insert(&self.stocks[1], y);
+        let derivatives = stock_derivatives(self.model, &state, state.time, &self.stocks)?;
+        Ok([derivatives[0], derivatives[1]])
+    }
+}
+
+/// `count` evenly spaced values from the start to the end of `range`
+fn spaced((min, max): (f64, f64), count: usize) -> Vec<f64> {
+    match count {
+        0 => Vec::new(),
+        1 => vec![0.5 * (min + max)],
+        _ => (0..count).map(|i| min + (max - min) * i as f64 / (count - 1) as f64).collect(),
+    }
+}
+
+/// Zero of `f` between `low` and `high`, where it changes sign; `f_low` is `f(low)`
+fn bisect(
+    mut low: f64,
+    mut high: f64,
+    mut f_low: f64,
+    f: impl Fn(f64) -> Result<f64, SimulationError>,
+) -> Result<f64, SimulationError> {
+    for _ in 0..REFINEMENTS {
+        let middle = 0.5 * (low + high);
+        let value = f(middle)?;
+        if value * f_low > 0.0 {
+            low = middle;
+            f_low = value;
+        } else {
+            high = middle;
+        }
+    }
+    Ok(0.5 * (low + high))
+}
+
+impl PhasePortrait {
+    /// CSV of the vector field: position and both net flows
+    pub fn vectors_csv(&self) -> String {
+        let mut csv = format!("{},{},d{}/dt,d{}/dt\n", self.x, self.y, self.x, self.y);
+        for v in &self.vectors {
+            csv.push_str(&format!("{},{},{},{}\n", v.x, v.y, v.dx, v.dy));
+        }
+        csv
+    }
+
+    /// CSV of nullcline points, labelled with the stock whose net flow is zero
+    pub fn nullclines_csv(&self) -> String {
+        let mut csv = format!("nullcline,{},{}\n", self.x, self.y);
+        for (name, points) in [(&self.x, &self.x_nullcline), (&self.y, &self.y_nullcline)] {
+            for (x, y) in points {
+                csv.push_str(&format!("{},{},{}\n", name, x, y));
+            }
+        }
+        csv
+    }
+
+    /// CSV of every trajectory, numbered from 1
+    pub fn trajectories_csv(&self) -> String {
+        let mut csv = format!("trajectory,time,{},{}\n", self.x, self.y);
+        for (i, trajectory) in self.trajectories.iter().enumerate() {
+            for (time, (x, y)) in trajectory.times.iter().zip(&trajectory.points) {
+                csv.push_str(&format!("{},{},{},{}\n", i + 1, time, x, y));
+            }
+        }
+        csv
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Stock, Flow};
+
+    #[test]
+    fn test_predator_prey_portrait() {
+        // Lotka-Volterra: Prey' = Prey (1 - Predator), Predator' = Predator (Prey - 1)
+        let mut model = Model::new("Predator-prey");
+        model.time.stop = 5.0;
+        model.time.dt = 0.05;
+        model.add_stock(Stock::new("Prey", "1")).unwrap();
+        model.add_stock(Stock::new("Predator", "0.5")).unwrap();
+        model.add_flow(Flow::new("births", "Prey * (1 - Predator)")).unwrap();
+        model.add_flow(Flow::new("growth", "Predator * (Prey - 1)")).unwrap();
+        model.stocks.get_mut("Prey").unwrap().inflows.push("births".to_string());
+        model.stocks.get_mut("Predator").unwrap().inflows.push("growth".to_string());
+
+        let missing = PhasePlane::new("Prey", "Wolves", (0.0, 2.0), (0.0, 2.0)).run(&model).unwrap_err();
+        assert!(matches!(missing, crate::Error::Model(ModelError::NotFound { .. })), "{}", missing);
+
+        let mut plane = PhasePlane::new("Prey", "Predator", (0.0, 2.0), (0.0, 2.0));
+        plane.grid = 3;
+        plane.starts = 2;
+        plane.resolution = 40;
+        let portrait = plane.run(&model).unwrap();
+
+        assert_eq!(portrait.vectors.len(), 9);
+        assert!(portrait.vectors.contains(&VectorSample { x: 2.0, y: 0.0, dx: 2.0, dy: 0.0 }));
+
+        // Prey is steady on Predator = 1 (and Prey = 0), Predator on Prey = 1 (and Predator = 0)
+        assert!(portrait.x_nullcline.iter().all(|&(x, y)| x == 0.0 || (y - 1.0).abs() < 1e-6));
+        assert!(portrait.x_nullcline.iter().any(|&(x, y)| x > 0.5 && (y - 1.0).abs() < 1e-6));
+        assert!(portrait.y_nullcline.iter().all(|&(x, y)| y == 0.0 || (x - 1.0).abs() < 1e-6));
+
+        assert_eq!(portrait.trajectories.len(), 4);
+        assert_eq!(portrait.trajectories[3].points[0], (2.0, 2.0));
+        assert!(portrait.trajectories_csv().starts_with("trajectory,time,Prey,Predator\n1,0,0,0\n"));
+        assert!(portrait.vectors_csv().starts_with("Prey,Predator,dPrey/dt,dPredator/dt\n0,0,0,0\n"));
+    }
+}
@@ -0,0 +1,316 @@
+/// Link polarity inferred from equations
+///
+/// The polarity of a causal link `x -> y` is the sign of `∂y/∂x`. It is found
+/// symbolically by walking the equation of `y`: sums and differences combine
+/// the polarities of their terms, products and quotients scale them by the
+/// sign of the other factor, and monotone functions (MIN, MAX, SQRT, EXP,
+/// lookups, delays, ...) pass them through. Signs of values come from
+/// constants, parameter values and the equations of the variables read, with
+/// stocks taken as non-negative unless they start below zero.
+
+use std::collections::{HashMap, HashSet};
+use crate::model::{Expression, Model};
+use crate::model::expression::{Operator, UnaryOperator, DELAY_FUNCTIONS};
+use super::structure::Polarity;
+
+/// Functions whose output rises and falls with every argument
+const INCREASING_FUNCTIONS: &[&str] = &["MIN", "MAX", "SUM", "MEAN", "VMIN", "VMAX", "SQRT", "EXP", "LN", "LOG", "LOG10"];
+
+/// Signs of model variables, worked out on demand from their equations
+pub struct SignAnalysis<'a> {
+    model: &'a Model,
+    signs: HashMap<String, Polarity>,
+    visiting: HashSet<String>,
+}
+
+impl<'a> SignAnalysis<'a> {
+    pub fn new(model: &'a Model) -> Self {
+        Self { model, signs: HashMap::new(), visiting: HashSet::new() }
+    }
+
+    /// Polarity of the link from `input` to the variable defined by `expr`,
+    /// or None if `expr` does not read `input`
+    pub fn link_polarity(&mut self, expr: &Expression, input: &str) -> Option<Polarity> {
+        match expr {
+            Expression::Constant(_) | Expression::StringLiteral { .. } => None,
+            Expression::Variable(name) | Expression::SubscriptedVariable { name, .. } => {
+                (name == input).then_some(Polarity::Positive)
+            }
+            Expression::UnaryOp { op: UnaryOperator::Negate, expr } => self.link_polarity(expr, input).map(negate),
+            Expression::BinaryOp { op, left, right } => {
+                let (left_link, right_link) = (self.link_polarity(left, input), self.link_polarity(right, input));
+                if left_link.is_none() && right_link.is_none() {
+                    return None;
+                }
+                match op {
+                    Operator::Add => merge(left_link, right_link),
+                    Operator::Subtract => merge(left_link, right_link.map(negate)),
+                    // d(uv) = v du + u dv
+                    Operator::Multiply => {
+                        let (left_sign, right_sign) = (self.sign(left), self.sign(right));
+                        merge(left_link.map(|p| p.combine(&right_sign)), right_link.map(|p| p.combine(&left_sign)))
+                    }
+                    // d(u/v) = du / v - u dv / v²
+                    Operator::Divide => {
+                        let (left_sign, right_sign) = (self.sign(left), self.sign(right));
+                        merge(left_link.map(|p| p.combine(&right_sign)), right_link.map(|p| negate(p.combine(&left_sign))))
+                    }
+                    Operator::Power => self.power_link(left, right, left_link, right_link),
+                    // Comparisons jump rather than vary smoothly
+                    _ => Some(Polarity::Unknown),
+                }
+            }
+            Expression::Conditional { condition, true_expr, false_expr } => {
+                if self.link_polarity(condition, input).is_some() {
+                    return Some(Polarity::Unknown);
+                }
+                merge(self.link_polarity(true_expr, input), self.link_polarity(false_expr, input))
+            }
+            Expression::FunctionCall { name, args, .. } => self.function_link(name, args, input),
+        }
+    }
+
+    /// Sign of the value of the variable `name`
+    pub fn variable_sign(&mut self, name: &str) -> Polarity {
+        if let Some(&sign) = self.signs.get(name) {
+            return sign;
+        }
+        // A variable defined in terms of itself has no sign to find
+        if !self.visiting.insert(name.to_string()) {
+            return Polarity::Unknown;
+        }
+
+        let model = self.model;
+        let sign = if let Some(parameter) = model.parameters.get(name) {
+            sign_of(parameter.value)
+        } else if let Some(stock) = model.stocks.get(name) {
+            if stock.non_negative || self.sign(&stock.initial) == Polarity::Positive {
+                Polarity::Positive
+            } else {
+                Polarity::Unknown
+            }
+        } else if let Some(flow) = model.flows.get(name) {
+            self.sign(&flow.equation)
+        } else if let Some(aux) = model.auxiliaries.get(name) {
+            self.sign(&aux.equation)
+        } else {
+            Polarity::Unknown
+        };
+
+        self.visiting.remove(name);
+        self.signs.insert(name.to_string(), sign);
+        sign
+    }
+
+    /// Sign of the value of `expr`: Positive for non-negative, Negative for non-positive
+    pub fn sign(&mut self, expr: &Expression) -> Polarity {
+        match expr {
+            Expression::Constant(value) => sign_of(*value),
+            Expression::Variable(name) | Expression::SubscriptedVariable { name, .. } => self.variable_sign(name),
+            Expression::StringLiteral { .. } => Polarity::Unknown,
+            Expression::UnaryOp { op: UnaryOperator::Negate, expr } => negate(self.sign(expr)),
+            Expression::BinaryOp { op, left, right } => {
+                let (left, right) = (self.sign(left), self.sign(right));
+                match op {
+                    Operator::Add => same(left, right),
+                    Operator::Subtract => same(left, negate(right)),
+                    Operator::Multiply | Operator::Divide => left.combine(&right),
+                    Operator::Power => if left == Polarity::Positive { Polarity::Positive } else { Polarity::Unknown },
+                    // Comparisons are 1 or 0
+                    _ => Polarity::Positive,
+                }
+            }
+            Expression::Conditional { true_expr, false_expr, .. } => {
+                let true_sign = self.sign(true_expr);
+                same(true_sign, self.sign(false_expr))
+            }
+            Expression::FunctionCall { name, args, .. } => self.function_sign(name, args),
+        }
+    }
+
+    /// `base ^ exponent`, for a constant exponent or a constant base
+    fn power_link(
+        &mut self,
+        base: &Expression,
+        exponent: &Expression,
+        base_link: Option<Polarity>,
+        exponent_link: Option<Polarity>,
+    ) -> Option<Polarity> {
+        match (base_link, exponent_link, base, exponent) {
+            // x^c on x >= 0 rises with x for c > 0 and falls for c < 0
+            (Some(link), None, _, Expression::Constant(c)) if self.sign(base) == Polarity::Positive => {
+                match c.partial_cmp(&0.0) {
+                    Some(std::cmp::Ordering::Greater) => Some(link),
+                    Some(std::cmp::Ordering::Less) => Some(negate(link)),
+                    _ => None,
+                }
+            }
+            // c^x rises with x for c > 1 and falls for 0 < c < 1
+            (None, Some(link), Expression::Constant(c), _) if *c > 0.0 => {
+                if *c > 1.0 {
+                    Some(link)
+                } else if *c < 1.0 {
+                    Some(negate(link))
+                } else {
+                    None
+                }
+            }
+            _ => Some(Polarity::Unknown),
+        }
+    }
+
+    fn function_link(&mut self, name: &str, args: &[Expression], input: &str) -> Option<Polarity> {
+        if let Some((points, x)) = self.graphical_function(name, args) {
+            return self.link_polarity(x, input).map(|link| link.combine(&slope(&points)));
+        }
+        let upper = name.to_uppercase();
+        let upper = upper.as_str();
+        let mut links = args.iter().map(|arg| self.link_polarity(arg, input)).collect::<Vec<_>>();
+        if links.iter().all(Option::is_none) {
+            return None;
+        }
+
+        if INCREASING_FUNCTIONS.contains(&upper) {
+            return links.into_iter().fold(None, merge);
+        }
+        if DELAY_FUNCTIONS.contains(&upper) {
+            // A loop through the delay time or initial value has no fixed polarity
+            return match links.iter().skip(1).any(Option::is_some) {
+                true => Some(Polarity::Unknown),
+                false => links.swap_remove(0),
+            };
+        }
+        match upper {
+            "ABS" if args.len() == 1 => {
+                let sign = self.sign(&args[0]);
+                links[0].map(|link| match sign {
+                    Polarity::Positive => link,
+                    Polarity::Negative => negate(link),
+                    Polarity::Unknown => Polarity::Unknown,
+                })
+            }
+            "POW" if args.len() == 2 => self.power_link(&args[0], &args[1], links[0], links[1]),
+            _ => Some(Polarity::Unknown),
+        }
+    }
+
+    fn function_sign(&mut self, name: &str, args: &[Expression]) -> Polarity {
+        if let Some((points, _)) = self.graphical_function(name, args) {
+            return points.iter().map(|&(_, y)| sign_of(y)).reduce(same).unwrap_or(Polarity::Unknown);
+        }
+        let upper = name.to_uppercase();
+        let signs: Vec<Polarity> = args.iter().map(|arg| self.sign(arg)).collect();
+        let all = |sign: Polarity| !signs.is_empty() && signs.iter().all(|&s| s == sign);
+        match upper.as_str() {
+            "ABS" | "SQRT" | "EXP" | "RANDOM" | "POISSON" | "LOGNORMAL" | "TIME" => Polarity::Positive,
+            // The smallest is negative if any is, the largest positive if any is
+            "MIN" if signs.contains(&Polarity::Negative) => Polarity::Negative,
+            "MAX" if signs.contains(&Polarity::Positive) => Polarity::Positive,
+            "MIN" if all(Polarity::Positive) => Polarity::Positive,
+            "MAX" if all(Polarity::Negative) => Polarity::Negative,
+            "SUM" | "MEAN" | "VMIN" | "VMAX" => signs.into_iter().reduce(same).unwrap_or(Polarity::Unknown),
+            "PROD" => signs.into_iter().reduce(|a, b| a.combine(&b)).unwrap_or(Polarity::Unknown),
+            "POW" if signs.first() == Some(&Polarity::Positive) => Polarity::Positive,
+            name if DELAY_FUNCTIONS.contains(&name) => signs.first().copied().unwrap_or(Polarity::Unknown),
+            _ => Polarity::Unknown,
+        }
+    }
+
+    /// The points and input of a lookup call: `LOOKUP("table", x)`, `table(x)`
+    /// or `WITH_LOOKUP(x, x1, y1, ...)` with constant points, or of a model table
+    fn graphical_function<'e>(&self, name: &str, args: &'e [Expression]) -> Option<(Vec<(f64, f64)>, &'e Expression)> {
+        let lookups = &self.model.lookups;
+        match (name.to_uppercase().as_str(), args) {
+            ("LOOKUP", [table, x]) | ("WITH_LOOKUP", [x, table]) => Some((lookups.get(table.name_argument()?)?.points.clone(), x)),
+            ("WITH_LOOKUP", [x, pairs @ ..]) => {
+                let points = pairs.chunks(2)
+                    .map(|pair| match pair {
+                        [Expression::Constant(x), Expression::Constant(y)] => Some((*x, *y)),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<_>>>()?;
+                Some((points, x))
+            }
+            (_, [x]) => Some((lookups.get(name)?.points.clone(), x)),
+            _ => None,
+        }
+    }
+}
+
+fn sign_of(value: f64) -> Polarity {
+    if value >= 0.0 {
+        Polarity::Positive
+    } else if value < 0.0 {
+        Polarity::Negative
+    } else {
+        Polarity::Unknown
+    }
+}
+
+fn negate(polarity: Polarity) -> Polarity {
+    polarity.combine(&Polarity::Negative)
+}
+
+/// The sign shared by both, if any
+fn same(a: Polarity, b: Polarity) -> Polarity {
+    if a == b { a } else { Polarity::Unknown }
+}
+
+/// Polarity of a sum of terms, any of which may not depend on the input
+fn merge(a: Option<Polarity>, b: Option<Polarity>) -> Option<Polarity> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(same(a, b)),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+/// Whether a graphical function only rises or only falls
+fn slope(points: &[(f64, f64)]) -> Polarity {
+    let rising = points.windows(2).all(|pair| pair[1].1 >= pair[0].1);
+    let falling = points.windows(2).all(|pair| pair[1].1 <= pair[0].1);
+    match (rising, falling) {
+        (true, false) => Polarity::Positive,
+        (false, true) => Polarity::Negative,
+        _ => Polarity::Unknown,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Auxiliary, Parameter, Stock};
+
+    fn link(analysis: &mut SignAnalysis, equation: &str, input: &str) -> Option<Polarity> {
+        analysis.link_polarity(&Expression::parse(equation).unwrap(), input)
+    }
+
+    #[test]
+    fn test_link_polarity() {
+        let mut model = Model::new("Test");
+        model.add_stock(Stock::new("Population", "100")).unwrap();
+        model.add_parameter(Parameter::new("rate", 0.1)).unwrap();
+        model.add_parameter(Parameter::new("offset", -2.0)).unwrap();
+        model.add_auxiliary(Auxiliary::new("gap", "capacity - Population")).unwrap();
+        model.add_lookup(
+            crate::simulation::LookupTable::new("crowding".to_string(), vec![(0.0, 1.0), (1.0, 0.5), (2.0, 0.0)]).unwrap()
+        ).unwrap();
+        let mut analysis = SignAnalysis::new(&model);
+
+        assert_eq!(link(&mut analysis, "Population * rate", "Population"), Some(Polarity::Positive));
+        assert_eq!(link(&mut analysis, "Population * offset", "Population"), Some(Polarity::Negative));
+        assert_eq!(link(&mut analysis, "Population / rate", "rate"), Some(Polarity::Negative));
+        assert_eq!(link(&mut analysis, "rate - Population", "Population"), Some(Polarity::Negative));
+        assert_eq!(link(&mut analysis, "MIN(Population, 10) * rate", "Population"), Some(Polarity::Positive));
+        assert_eq!(link(&mut analysis, "crowding(Population / 100)", "Population"), Some(Polarity::Negative));
+        assert_eq!(link(&mut analysis, "SMOOTH(Population, rate)", "Population"), Some(Polarity::Positive));
+        assert_eq!(link(&mut analysis, "Population ^ 2", "Population"), Some(Polarity::Positive));
+        assert_eq!(link(&mut analysis, "Population * (1 - Population)", "Population"), Some(Polarity::Unknown));
+        assert_eq!(link(&mut analysis, "IF Population > 5 THEN 1 ELSE 0", "Population"), Some(Polarity::Unknown));
+        assert_eq!(link(&mut analysis, "rate * 2", "Population"), None);
+
+        // gap reads an undefined variable, so its sign is unknown
+        assert_eq!(analysis.variable_sign("gap"), Polarity::Unknown);
+        assert_eq!(analysis.variable_sign("Population"), Polarity::Positive);
+    }
+}
@@ -0,0 +1,255 @@
+/// Policy optimization over piecewise-constant schedules
+///
+/// A decision variable is a parameter whose value may change at given times,
+/// e.g. a tax rate set once per decade. Each segment of its schedule becomes a
+/// parameter of its own, copied into the decision variable by an event at the
+/// segment's start, so `GradientOptimizer` and `GeneticOptimizer` search the
+/// whole schedule like any set of scalar parameters.
+
+use std::collections::HashMap;
+use crate::error::{ModelError, Result, SimulationError};
+use crate::model::{Event, EventAction, EventTrigger, Expression, Model, Parameter};
+use crate::simulation::SimulationResults;
+use super::optimization::{ObjectiveFunction, ParameterBounds};
+
+/// A parameter set to a new value at each of `times`, within `[min, max]`
+///
+/// Before the first time the parameter keeps its value in the model.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecisionVariable {
+    pub name: String,
+    pub times: Vec<f64>,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl DecisionVariable {
+    pub fn new(name: &str, times: Vec<f64>, min: f64, max: f64) -> Self {
+        Self { name: name.to_string(), times, min, max }
+    }
+
+    /// Name of the parameter holding the value from `times[segment]`
+    pub fn segment_parameter(&self, segment: usize) -> String {
+        format!("{}_segment_{}", self.name, segment + 1)
+    }
+
+    fn clamp(&self, value: f64) -> f64 {
+        value.max(self.min).min(self.max)
+    }
+}
+
+/// Schedules of several decision variables, searched together
+#[derive(Debug, Clone)]
+pub struct PolicyProblem {
+    pub decisions: Vec<DecisionVariable>,
+}
+
+impl PolicyProblem {
+    pub fn new(decisions: Vec<DecisionVariable>) -> Self {
+        Self { decisions }
+    }
+
+    /// Copy of `model` with a parameter per segment and the events switching between them
+    ///
+    /// Segment parameters start at the decision variable's current value.
+    pub fn prepare(&self, model: &Model) -> Result<Model> {
+        let mut model = model.clone();
+        for decision in &self.decisions {
+            let current = model.parameters.get(&decision.name)
+                .ok_or_else(|| ModelError::NotFound { kind: "Parameter", name: decision.name.clone() })?
+                .value;
+            if decision.times.is_empty() {
+                return Err(ModelError::Invalid(format!("Decision variable '{}' has no times", decision.name)).into());
+            }
+            if decision.times.windows(2).any(|pair| pair[0] >= pair[1]) {
+                return Err(ModelError::Invalid(format!("Times of decision variable '{}' must be increasing", decision.name)).into());
+            }
+
+            for (i, &time) in decision.times.iter().enumerate() {
+                let segment = decision.segment_parameter(i);
+                model.add_parameter(Parameter::new(&segment, decision.clamp(current)))?;
+                let event = Event::new(&event_name(&decision.name, i), EventTrigger::At(time))
+                    .with_action(EventAction::Set { variable: decision.name.clone(), value: Expression::Variable(segment) });
+                model.add_event(event)?;
+            }
+        }
+        Ok(model)
+    }
+
+    /// Bounds of every segment parameter, for the optimizers
+    pub fn bounds(&self) -> Vec<ParameterBounds> {
+        self.decisions.iter()
+            .flat_map(|decision| {
+                (0..decision.times.len())
+                    .map(move |i| ParameterBounds::new(&decision.segment_parameter(i), decision.min, decision.max))
+            })
+            .collect()
+    }
+
+    /// The schedules given optimized segment parameter values
+    pub fn policy(&self, parameters: &HashMap<String, f64>) -> Policy {
+        Policy {
+            decisions: self.decisions.iter()
+                .map(|decision| Schedule {
+                    variable: decision.name.clone(),
+                    times: decision.times.clone(),
+                    values: (0..decision.times.len())
+                        .map(|i| parameters.get(&decision.segment_parameter(i)).copied().unwrap_or(f64::NAN))
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Value of one decision variable from each of its times on
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    pub variable: String,
+    pub times: Vec<f64>,
+    pub values: Vec<f64>,
+}
+
+/// Optimized schedules
+#[derive(Debug, Clone, PartialEq)]
+pub struct Policy {
+    pub decisions: Vec<Schedule>,
+}
+
+impl Policy {
+    /// Copy of `model` with the schedules as events
+    pub fn apply(&self, model: &Model) -> Result<Model> {
+        let mut model = model.clone();
+        for schedule in &self.decisions {
+            for (i, (&time, &value)) in schedule.times.iter().zip(&schedule.values).enumerate() {
+                let event = Event::new(&event_name(&schedule.variable, i), EventTrigger::At(time))
+                    .with_action(EventAction::Set { variable: schedule.variable.clone(), value: Expression::Constant(value) });
+                model.add_event(event)?;
+            }
+        }
+        Ok(model)
+    }
+
+    /// The schedules as an `events:` section for a model file
+    pub fn to_events_yaml(&self) -> String {
+        let mut yaml = String::from("events:\n");
+        for schedule in &self.decisions {
+            for (i, (time, value)) in schedule.times.iter().zip(&schedule.values).enumerate() {
+                yaml.push_str(&format!(
+                    "  - name: {}\n    at: {}\n    actions:\n      - set: {}\n        value: {}\n",
+                    event_name(&schedule.variable, i), time, schedule.variable, value
+                ));
+            }
+        }
+        yaml
+    }
+
+    /// CSV of `variable,time,value` rows
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("variable,time,value\n");
+        for schedule in &self.decisions {
+            for (time, value) in schedule.times.iter().zip(&schedule.values) {
+                csv.push_str(&format!("{},{},{}\n", schedule.variable, time, value));
+            }
+        }
+        csv
+    }
+}
+
+fn event_name(variable: &str, segment: usize) -> String {
+    format!("policy_{}_{}", variable, segment + 1)
+}
+
+/// Summary of a variable's trajectory that a policy is judged by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Statistic {
+    Final,
+    Mean,
+    Max,
+    Min,
+}
+
+/// The quantity a policy optimizes: a statistic of one variable
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicyObjective {
+    pub variable: String,
+    statistic: Statistic,
+    pub maximize: bool,
+}
+
+impl PolicyObjective {
+    /// `metric` is a variable name with an optional `_final`, `_mean`, `_max`
+    /// or `_min` suffix; a bare name means the final value
+    pub fn parse(metric: &str, maximize: bool) -> Self {
+        let suffixes = [("_final", Statistic::Final), ("_mean", Statistic::Mean), ("_max", Statistic::Max), ("_min", Statistic::Min)];
+        let (variable, statistic) = suffixes.iter()
+            .find_map(|(suffix, statistic)| metric.strip_suffix(suffix).map(|variable| (variable, *statistic)))
+            .unwrap_or((metric, Statistic::Final));
+        Self { variable: variable.to_string(), statistic, maximize }
+    }
+
+    /// The statistic of a run
+    pub fn value(&self, results: &SimulationResults) -> Result<f64> {
+        let series = results.series(&self.variable)
+            .ok_or_else(|| ModelError::NotFound { kind: "Objective variable", name: self.variable.clone() })?;
+        if series.is_empty() {
+            return Err(SimulationError::from("Simulation produced no output".to_string()).into());
+        }
+        Ok(match self.statistic {
+            Statistic::Final => series[series.len() - 1],
+            Statistic::Mean => series.iter().sum::<f64>() / series.len() as f64,
+            Statistic::Max => series.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            Statistic::Min => series.iter().copied().fold(f64::INFINITY, f64::min),
+        })
+    }
+
+    /// An optimizer objective, negated when maximizing since the optimizers minimize
+    pub fn objective_function(self) -> ObjectiveFunction {
+        Box::new(move |_model, results| {
+            let value = self.value(results)?;
+            Ok(if self.maximize { -value } else { value })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::optimization::{GradientOptimizer, OptimizationConfig};
+    use crate::model::{Stock, Flow};
+    use crate::simulation::{SimulationEngine, SimulationConfig, IntegrationMethod};
+
+    #[test]
+    fn test_optimal_schedule() {
+        // Earning at rate u * (5 - TIME) pays until time 5 and costs after it
+        let mut model = Model::new("Switch");
+        model.time.stop = 10.0;
+        model.time.dt = 0.5;
+        model.add_stock(Stock::new("Profit", "0")).unwrap();
+        model.add_parameter(Parameter::new("u", 0.5)).unwrap();
+        model.add_flow(Flow::new("earning", "u * (5 - TIME)")).unwrap();
+        model.stocks.get_mut("Profit").unwrap().inflows.push("earning".to_string());
+
+        let problem = PolicyProblem::new(vec![DecisionVariable::new("u", vec![0.0, 5.0], 0.0, 1.0)]);
+        let prepared = problem.prepare(&model).unwrap();
+        let unknown = PolicyProblem::new(vec![DecisionVariable::new("v", vec![0.0], 0.0, 1.0)]);
+        assert!(matches!(unknown.prepare(&model), Err(crate::Error::Model(ModelError::NotFound { .. }))));
+        assert_eq!(prepared.parameters["u_segment_2"].value, 0.5);
+
+        let optimizer = GradientOptimizer::new(
+            OptimizationConfig { max_iterations: 20, ..OptimizationConfig::default() },
+            problem.bounds(),
+        );
+        let objective = PolicyObjective::parse("Profit", true);
+        assert_eq!(objective, PolicyObjective { variable: "Profit".to_string(), statistic: Statistic::Final, maximize: true });
+        let result = optimizer.optimize(&prepared, objective.objective_function()).unwrap();
+
+        let policy = problem.policy(&result.parameters);
+        assert_eq!(policy.decisions[0].values, [1.0, 0.0]);
+        assert!(policy.to_events_yaml().contains("  - name: policy_u_2\n    at: 5\n    actions:\n      - set: u\n        value: 0\n"));
+
+        let config = SimulationConfig { integration_method: IntegrationMethod::RK4, ..SimulationConfig::default() };
+        let results = SimulationEngine::new(policy.apply(&model).unwrap(), config).unwrap().run().unwrap();
+        assert!((PolicyObjective::parse("Profit_final", false).value(&results).unwrap() - 12.5).abs() < 1e-9);
+    }
+}
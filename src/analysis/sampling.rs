@@ -0,0 +1,221 @@
+/// Sampling of parameter spaces for Monte Carlo and sensitivity analysis
+///
+/// Points are drawn in the unit hypercube and scaled onto each
+/// [`ParameterRange`]. Latin Hypercube and Sobol samples cover the space more
+/// evenly than independent random draws, so statistics of the outputs settle
+/// with far fewer runs.
+
+use std::str::FromStr;
+use rand::prelude::*;
+use rand::distributions::{Distribution, Standard};
+use super::sensitivity::{ParameterRange, ParameterSample};
+
+/// How to spread sample points over the parameter ranges
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SamplingMethod {
+    /// Independent uniform draws
+    #[default]
+    Random,
+    /// One point in each of `n` equal slices of every range
+    LatinHypercube,
+    /// Sobol low-discrepancy sequence (deterministic; the seed is not used)
+    Sobol,
+}
+
+impl FromStr for SamplingMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "random" => Ok(Self::Random),
+            "lhs" | "latin-hypercube" => Ok(Self::LatinHypercube),
+            "sobol" => Ok(Self::Sobol),
+            _ => Err(format!("Unknown sampling method '{}' (expected random, lhs or sobol)", s)),
+        }
+    }
+}
+
+/// Primitive polynomials and initial direction numbers for Sobol dimensions
+/// 2 onwards, as `(degree, coefficients, m)` from Joe and Kuo's
+/// new-joe-kuo-6.21201 table; dimension 1 is the van der Corput sequence
+const SOBOL_DIRECTIONS: &[(u32, u32, &[u32])] = &[
+    (1, 0, &[1]),
+    (2, 1, &[1, 3]),
+    (3, 1, &[1, 3, 1]),
+    (3, 2, &[1, 1, 1]),
+    (4, 1, &[1, 1, 3, 3]),
+    (4, 4, &[1, 3, 5, 13]),
+    (5, 2, &[1, 1, 5, 5, 17]),
+    (5, 4, &[1, 1, 5, 5, 5]),
+    (5, 7, &[1, 1, 7, 11, 19]),
+    (5, 11, &[1, 1, 5, 1, 1]),
+    (5, 13, &[1, 1, 1, 3, 11]),
+    (5, 14, &[1, 3, 5, 5, 31]),
+    (6, 1, &[1, 3, 3, 9, 7, 49]),
+    (6, 13, &[1, 1, 1, 15, 21, 21]),
+    (6, 16, &[1, 3, 1, 13, 27, 49]),
+    (6, 19, &[1, 1, 1, 15, 7, 5]),
+    (6, 22, &[1, 3, 1, 15, 13, 25]),
+    (6, 25, &[1, 1, 5, 5, 19, 61]),
+    (7, 1, &[1, 3, 7, 11, 23, 15, 103]),
+    (7, 4, &[1, 3, 7, 13, 13, 15, 69]),
+];
+
+/// Most parameters a Sobol sample can vary at once
+pub const SOBOL_MAX_DIMENSIONS: usize = SOBOL_DIRECTIONS.len() + 1;
+
+const SOBOL_BITS: usize = 32;
+
+/// `n_samples` points in the ranges, drawn with `method`
+pub fn parameter_samples(
+    ranges: &[ParameterRange],
+    method: SamplingMethod,
+    n_samples: usize,
+    rng: &mut impl Rng,
+) -> Result<Vec<ParameterSample>, String> {
+    let points = match method {
+        SamplingMethod::Random => random_points(n_samples, ranges.len(), rng),
+        SamplingMethod::LatinHypercube => latin_hypercube(n_samples, ranges.len(), rng),
+        SamplingMethod::Sobol => sobol(n_samples, ranges.len())?,
+    };
+    Ok(points.iter().map(|point| scale(ranges, point)).collect())
+}
+
+/// Map a point of the unit hypercube onto the ranges
+pub fn scale(ranges: &[ParameterRange], point: &[f64]) -> ParameterSample {
+    let mut sample = ParameterSample::new();
+    for (range, &fraction) in ranges.iter().zip(point) {
+        sample.set(range.name.clone(), range.at_fraction(fraction));
+    }
+    sample
+}
+
+/// Independent uniform points in `[0, 1)^dimensions`
+pub fn random_points(n_samples: usize, dimensions: usize, rng: &mut impl Rng) -> Vec<Vec<f64>> {
+    (0..n_samples)
+        .map(|_| (0..dimensions).map(|_| Standard.sample(rng)).collect())
+        .collect()
+}
+
+/// Latin Hypercube points: each dimension has exactly one point in each of
+/// `n_samples` equal slices, placed at random within the slice
+pub fn latin_hypercube(n_samples: usize, dimensions: usize, rng: &mut impl Rng) -> Vec<Vec<f64>> {
+    // Create permutations for each dimension
+    let permutations: Vec<Vec<usize>> = (0..dimensions)
+        .map(|_| {
+            let mut perm: Vec<usize> = (0..n_samples).collect();
+            perm.shuffle(rng);
+            perm
+        })
+        .collect();
+
+    (0..n_samples)
+        .map(|i| {
+            permutations.iter()
+                .map(|perm| {
+                    let random_val: f64 = Standard.sample(rng);
+                    (perm[i] as f64 + random_val) / n_samples as f64
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// The first `n_samples` points of the Sobol sequence after the origin
+pub fn sobol(n_samples: usize, dimensions: usize) -> Result<Vec<Vec<f64>>, String> {
+    if dimensions > SOBOL_MAX_DIMENSIONS {
+        return Err(format!(
+            "Sobol sampling supports at most {} parameters, got {}",
+            SOBOL_MAX_DIMENSIONS, dimensions
+        ));
+    }
+    if n_samples as u64 >= 1 << SOBOL_BITS {
+        return Err(format!("Sobol sampling supports fewer than 2^{} points", SOBOL_BITS));
+    }
+
+    let directions: Vec<[u32; SOBOL_BITS]> = (0..dimensions).map(sobol_directions).collect();
+    let mut x = vec![0u32; dimensions];
+    // Gray-code order: point i flips the direction number of the lowest zero bit of i - 1
+    Ok((1..=n_samples)
+        .map(|i| {
+            let bit = (i - 1).trailing_ones() as usize;
+            x.iter_mut()
+                .zip(&directions)
+                .map(|(x, v)| {
+                    *x ^= v[bit];
+                    *x as f64 / (1u64 << SOBOL_BITS) as f64
+                })
+                .collect()
+        })
+        .collect())
+}
+
+/// Direction numbers `v_k = m_k / 2^k`, as fixed-point fractions, of dimension `index` (from 0)
+fn sobol_directions(index: usize) -> [u32; SOBOL_BITS] {
+    let mut v = [0u32; SOBOL_BITS];
+    if index == 0 {
+        for (k, v) in v.iter_mut().enumerate() {
+            *v = 1 << (SOBOL_BITS - 1 - k);
+        }
+        return v;
+    }
+
+    let (degree, coefficients, m) = SOBOL_DIRECTIONS[index - 1];
+    let degree = degree as usize;
+    for k in 0..SOBOL_BITS {
+        v[k] = if k < degree {
+            m[k] << (SOBOL_BITS - 1 - k)
+        } else {
+            // v_k = a_1 v_{k-1} ^ ... ^ a_{s-1} v_{k-s+1} ^ v_{k-s} ^ (v_{k-s} >> s)
+            let mut value = v[k - degree] ^ (v[k - degree] >> degree);
+            for j in 1..degree {
+                if (coefficients >> (degree - 1 - j)) & 1 == 1 {
+                    value ^= v[k - j];
+                }
+            }
+            value
+        };
+    }
+    v
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sobol_sequence() {
+        let points = sobol(8, 3).unwrap();
+        // The first points of the standard sequence
+        assert_eq!(points[0], [0.5, 0.5, 0.5]);
+        assert_eq!(points[1], [0.75, 0.25, 0.25]);
+        assert_eq!(points[2], [0.25, 0.75, 0.75]);
+        assert_eq!(points[3], [0.375, 0.375, 0.625]);
+
+        // Every dimension puts one point in each eighth, counting the origin
+        for d in 0..3 {
+            let mut cells: Vec<usize> = std::iter::once(0.0).chain(points.iter().take(7).map(|p| p[d]))
+                .map(|x| (x * 8.0) as usize)
+                .collect();
+            cells.sort();
+            assert_eq!(cells, (0..8).collect::<Vec<_>>());
+        }
+        assert!(sobol(1, SOBOL_MAX_DIMENSIONS + 1).is_err());
+    }
+
+    #[test]
+    fn test_latin_hypercube_strata() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let points = latin_hypercube(10, 2, &mut rng);
+        for d in 0..2 {
+            let mut cells: Vec<usize> = points.iter().map(|p| (p[d] * 10.0) as usize).collect();
+            cells.sort();
+            assert_eq!(cells, (0..10).collect::<Vec<_>>());
+        }
+
+        let ranges = vec![ParameterRange::new("k".to_string(), 10.0, 20.0, 15.0)];
+        let samples = parameter_samples(&ranges, SamplingMethod::Sobol, 3, &mut rng).unwrap();
+        assert_eq!(samples.iter().map(|s| s.get("k").unwrap()).collect::<Vec<_>>(), [15.0, 17.5, 12.5]);
+        assert_eq!("LHS".parse::<SamplingMethod>(), Ok(SamplingMethod::LatinHypercube));
+    }
+}
@@ -2,7 +2,7 @@
 ///
 /// Provides methods for:
 /// - Parameter sweeps (one-at-a-time sensitivity)
-/// - Latin Hypercube Sampling (LHS)
+/// - Latin Hypercube Sampling (LHS) and Sobol sequences
 /// - Morris screening method
 /// - Sobol variance-based sensitivity
 
@@ -10,6 +10,7 @@ use std::collections::HashMap;
 use rand::prelude::*;
 use crate::model::Model;
 use crate::simulation::{SimulationEngine, SimulationConfig, SimulationResults};
+use super::sampling::{self, SamplingMethod};
 
 /// Parameter range for sensitivity analysis
 #[derive(Debug, Clone)]
@@ -68,28 +69,9 @@ pub struct SensitivityResult {
 
 impl SensitivityResult {
     pub fn from_simulation(sample: ParameterSample, results: &SimulationResults) -> Self {
-        let mut outputs = HashMap::new();
+        let outputs = results.to_map();
         let mut metrics = HashMap::new();
 
-        // Extract time series for all variables
-        for state in &results.states {
-            for (name, &value) in &state.stocks {
-                outputs.entry(name.clone())
-                    .or_insert_with(Vec::new)
-                    .push(value);
-            }
-            for (name, &value) in &state.flows {
-                outputs.entry(name.clone())
-                    .or_insert_with(Vec::new)
-                    .push(value);
-            }
-            for (name, &value) in &state.auxiliaries {
-                outputs.entry(name.clone())
-                    .or_insert_with(Vec::new)
-                    .push(value);
-            }
-        }
-
         // Calculate summary metrics
         for (name, series) in &outputs {
             if !series.is_empty() {
@@ -161,6 +143,18 @@ impl SensitivityAnalyzer {
         config: &SimulationConfig,
         n_samples: usize,
         seed: Option<u64>,
+    ) -> Result<(), String> {
+        self.sample_space(base_model, config, SamplingMethod::LatinHypercube, n_samples, seed)
+    }
+
+    /// Run the model at `n_samples` points of the parameter space drawn with `method`
+    pub fn sample_space(
+        &mut self,
+        base_model: &Model,
+        config: &SimulationConfig,
+        method: SamplingMethod,
+        n_samples: usize,
+        seed: Option<u64>,
     ) -> Result<(), String> {
         self.results.clear();
 
@@ -170,7 +164,7 @@ impl SensitivityAnalyzer {
             StdRng::from_entropy()
         };
 
-        let samples = self.generate_lhs_samples(n_samples, &mut rng);
+        let samples = sampling::parameter_samples(&self.parameter_ranges, method, n_samples, &mut rng)?;
 
         for sample in samples {
             let result = self.run_simulation(base_model, config, &sample)?;
@@ -257,38 +251,6 @@ impl SensitivityAnalyzer {
         morris_indices
     }
 
-    /// Generate Latin Hypercube samples
-    fn generate_lhs_samples(&self, n_samples: usize, rng: &mut impl Rng) -> Vec<ParameterSample> {
-        let n_params = self.parameter_ranges.len();
-        let mut samples = Vec::new();
-
-        // Create permutations for each parameter
-        let mut permutations: Vec<Vec<usize>> = Vec::new();
-        for _ in 0..n_params {
-            let mut perm: Vec<usize> = (0..n_samples).collect();
-            perm.shuffle(rng);
-            permutations.push(perm);
-        }
-
-        // Generate samples
-        for i in 0..n_samples {
-            let mut sample = ParameterSample::new();
-
-            for (j, param_range) in self.parameter_ranges.iter().enumerate() {
-                use rand::distributions::{Distribution, Standard};
-                let perm_index = permutations[j][i];
-                let random_val: f64 = Standard.sample(rng);
-                let fraction = (perm_index as f64 + random_val) / n_samples as f64;
-                let value = param_range.at_fraction(fraction);
-                sample.set(param_range.name.clone(), value);
-            }
-
-            samples.push(sample);
-        }
-
-        samples
-    }
-
     /// Generate Morris trajectory
     fn generate_morris_trajectory(&self, n_levels: usize, rng: &mut impl Rng) -> Vec<ParameterSample> {
         let mut trajectory = Vec::new();
@@ -420,7 +382,9 @@ mod tests {
 
         let analyzer = SensitivityAnalyzer::new(ranges);
         let mut rng = StdRng::seed_from_u64(42);
-        let samples = analyzer.generate_lhs_samples(10, &mut rng);
+        let samples = sampling::parameter_samples(
+            &analyzer.parameter_ranges, SamplingMethod::LatinHypercube, 10, &mut rng,
+        ).unwrap();
 
         assert_eq!(samples.len(), 10);
 
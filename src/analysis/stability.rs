@@ -5,8 +5,8 @@
 
 use crate::model::Model;
 use crate::simulation::{SimulationState, SimulationEngine, SimulationConfig, IntegrationMethod};
+use crate::simulation::integrator::{numerical_jacobian, stock_derivatives};
 use nalgebra::{DMatrix, DVector, Complex};
-use std::collections::HashMap;
 
 /// Stability classification of an equilibrium point
 #[derive(Debug, Clone, PartialEq)]
@@ -115,120 +115,7 @@ impl StabilityAnalyzer {
         state: &SimulationState,
         stock_names: &[String],
     ) -> Result<DMatrix<f64>, String> {
-        let n = stock_names.len();
-        let mut jacobian = DMatrix::zeros(n, n);
-
-        // Compute derivatives d(flow_i)/d(stock_j)
-        let base_derivatives = self.compute_derivatives(model, state, stock_names)?;
-
-        for (j, stock_name) in stock_names.iter().enumerate() {
-            // Perturb stock j
-            let mut perturbed_state = state.clone();
-            let original_value = state.stocks.get(stock_name)
-                .ok_or_else(|| format!("Stock '{}' not found", stock_name))?;
-
-            perturbed_state.stocks.insert(stock_name.clone(), original_value + self.epsilon);
-
-            // Compute derivatives at perturbed state
-            let perturbed_derivatives = self.compute_derivatives(model, &perturbed_state, stock_names)?;
-
-            // Finite difference: (f(x + h) - f(x)) / h
-            for (i, _) in stock_names.iter().enumerate() {
-                let base_deriv = base_derivatives[i];
-                let pert_deriv = perturbed_derivatives[i];
-                jacobian[(i, j)] = (pert_deriv - base_deriv) / self.epsilon;
-            }
-        }
-
-        Ok(jacobian)
-    }
-
-    /// Compute derivatives (d(stock)/dt) for all stocks at given state
-    fn compute_derivatives(
-        &self,
-        model: &Model,
-        state: &SimulationState,
-        stock_names: &[String],
-    ) -> Result<Vec<f64>, String> {
-        use crate::model::expression::EvaluationContext;
-
-        let mut derivatives = Vec::with_capacity(stock_names.len());
-
-        // Evaluate auxiliaries first
-        let mut eval_state = state.clone();
-        let mut auxiliaries = HashMap::new();
-
-        const MAX_PASSES: usize = 20;
-        for pass in 0..MAX_PASSES {
-            let mut changed = false;
-            let mut temp_state = eval_state.clone();
-            temp_state.auxiliaries = auxiliaries.clone();
-
-            for (name, aux) in &model.auxiliaries {
-                let mut aux_state = temp_state.clone();
-                let mut context = EvaluationContext::new(model, &mut aux_state, state.time);
-
-                match aux.equation.evaluate(&mut context) {
-                    Ok(value) => {
-                        if let Some(&old_value) = auxiliaries.get(name) {
-                            if (value - old_value).abs() > 1e-10 {
-                                changed = true;
-                            }
-                        } else {
-                            changed = true;
-                        }
-                        auxiliaries.insert(name.clone(), value);
-                    }
-                    Err(_) if pass < 5 => continue,
-                    Err(e) => return Err(format!("Error evaluating auxiliary '{}': {}", name, e)),
-                }
-            }
-
-            if !changed && pass > 0 {
-                break;
-            }
-        }
-        eval_state.auxiliaries = auxiliaries;
-
-        // Evaluate flows
-        let mut flows = HashMap::new();
-        for (name, flow) in &model.flows {
-            let mut flow_state = eval_state.clone();
-            let mut context = EvaluationContext::new(model, &mut flow_state, state.time);
-            let value = flow.equation.evaluate(&mut context)
-                .map_err(|e| format!("Error evaluating flow '{}': {}", name, e))?;
-            flows.insert(name.clone(), value);
-        }
-
-        // Compute derivatives for each stock
-        for stock_name in stock_names {
-            let stock = model.stocks.get(stock_name)
-                .ok_or_else(|| format!("Stock '{}' not found", stock_name))?;
-
-            let mut derivative = 0.0;
-
-            // Add inflows
-            for inflow_name in &stock.inflows {
-                if let Some(&flow_value) = flows.get(inflow_name) {
-                    derivative += flow_value;
-                } else {
-                    return Err(format!("Inflow '{}' not found", inflow_name));
-                }
-            }
-
-            // Subtract outflows
-            for outflow_name in &stock.outflows {
-                if let Some(&flow_value) = flows.get(outflow_name) {
-                    derivative -= flow_value;
-                } else {
-                    return Err(format!("Outflow '{}' not found", outflow_name));
-                }
-            }
-
-            derivatives.push(derivative);
-        }
-
-        Ok(derivatives)
+        Ok(numerical_jacobian(model, state, stock_names, self.epsilon)?)
     }
 
     /// Compute eigenvalues of a matrix
@@ -278,6 +165,51 @@ impl StabilityAnalyzer {
         }
     }
 
+    /// Solve for stock values at which every net flow is zero, starting from `state`
+    ///
+    /// Newton's method on the numerical Jacobian with time held at
+    /// `state.time`. Stocks that no flow can move keep their values.
+    pub fn solve_equilibrium(
+        &self,
+        model: &Model,
+        state: &SimulationState,
+        tolerance: f64,
+    ) -> Result<SimulationState, String> {
+        const MAX_ITERATIONS: usize = 50;
+
+        let mut stock_names: Vec<String> = model.stocks.keys().cloned().collect();
+        stock_names.sort();
+        let mut current = state.clone();
+
+        for _ in 0..MAX_ITERATIONS {
+            let derivatives = stock_derivatives(model, &current, current.time, &stock_names)?;
+            if derivatives.iter().all(|d| d.abs() < tolerance) {
+                return Ok(current);
+            }
+
+            // Least squares handles stocks whose rows of the Jacobian are zero
+            let jacobian = self.compute_jacobian(model, &current, &stock_names)?;
+            let step = jacobian.svd(true, true)
+                .solve(&DVector::from_vec(derivatives), 1e-12)
+                .map_err(|e| format!("Failed to find equilibrium: {}", e))?;
+
+            for (i, name) in stock_names.iter().enumerate() {
+                let stock = &model.stocks[name];
+                let value = current.stocks[name] - step[i];
+                let value = if stock.non_negative { value.max(0.0) } else { value };
+                current.stocks.insert(name.clone(), value);
+            }
+        }
+
+        let residual = stock_derivatives(model, &current, current.time, &stock_names)?
+            .into_iter()
+            .fold(0.0, |max: f64, d| max.max(d.abs()));
+        Err(format!(
+            "Failed to find equilibrium: largest net flow is still {:.3e} after {} Newton iterations",
+            residual, MAX_ITERATIONS
+        ))
+    }
+
     /// Find equilibrium point through simulation
     pub fn find_equilibrium(
         &self,
@@ -289,6 +221,7 @@ impl StabilityAnalyzer {
         let config = SimulationConfig {
             integration_method: IntegrationMethod::RK4,
             output_interval: None,
+            ..SimulationConfig::default()
         };
 
         let mut engine = SimulationEngine::new(model.clone(), config)?;
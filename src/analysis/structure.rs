@@ -3,11 +3,13 @@
 /// Provides:
 /// - Dependency graph construction
 /// - Feedback loop identification
-/// - Loop polarity analysis
+/// - Loop polarity analysis (link polarities from [`super::polarity`])
 /// - Structural dominance analysis
 
 use std::collections::{HashMap, HashSet, VecDeque};
 use crate::model::{Model, Expression};
+use crate::model::expression::DELAY_FUNCTIONS;
+use super::polarity::SignAnalysis;
 
 /// Type of model element
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -116,28 +118,30 @@ impl DependencyGraph {
             graph.add_node(GraphNode::new(name.clone(), ElementType::Parameter));
         }
 
-        // Add edges from flow equations
+        // Add causal edges from each equation's inputs to the variable it defines,
+        // matching the flow -> stock direction below so feedback loops close;
+        // each link's polarity comes from the sign of the equation's slope
+        let mut signs = SignAnalysis::new(model);
         for (flow_name, flow) in &model.flows {
             let dependencies = Self::extract_dependencies(&flow.equation);
-            let from_node = GraphNode::new(flow_name.clone(), ElementType::Flow);
+            let to_node = GraphNode::new(flow_name.clone(), ElementType::Flow);
 
             for dep in dependencies {
-                let to_node = graph.find_node(&dep);
-                if let Some(to) = to_node {
-                    graph.add_edge(from_node.clone(), to, Polarity::Unknown);
+                if let Some(from) = graph.find_node(&dep) {
+                    let polarity = signs.link_polarity(&flow.equation, &dep).unwrap_or(Polarity::Unknown);
+                    graph.add_edge(from, to_node.clone(), polarity);
                 }
             }
         }
 
-        // Add edges from auxiliary equations
         for (aux_name, aux) in &model.auxiliaries {
             let dependencies = Self::extract_dependencies(&aux.equation);
-            let from_node = GraphNode::new(aux_name.clone(), ElementType::Auxiliary);
+            let to_node = GraphNode::new(aux_name.clone(), ElementType::Auxiliary);
 
             for dep in dependencies {
-                let to_node = graph.find_node(&dep);
-                if let Some(to) = to_node {
-                    graph.add_edge(from_node.clone(), to, Polarity::Unknown);
+                if let Some(from) = graph.find_node(&dep) {
+                    let polarity = signs.link_polarity(&aux.equation, &dep).unwrap_or(Polarity::Unknown);
+                    graph.add_edge(from, to_node.clone(), polarity);
                 }
             }
         }
@@ -269,7 +273,7 @@ impl DependencyGraph {
     }
 
     /// Extract variable names from expression
-    fn extract_dependencies(expr: &Expression) -> HashSet<String> {
+    pub(crate) fn extract_dependencies(expr: &Expression) -> HashSet<String> {
         let mut deps = HashSet::new();
 
         match expr {
@@ -279,6 +283,10 @@ impl DependencyGraph {
             Expression::SubscriptedVariable { name, .. } => {
                 deps.insert(name.clone());
             }
+            Expression::StringLiteral { literal } => {
+                // Quoted variable name; lookup table names never match a node
+                deps.insert(literal.clone());
+            }
             Expression::BinaryOp { left, right, .. } => {
                 deps.extend(Self::extract_dependencies(left));
                 deps.extend(Self::extract_dependencies(right));
@@ -302,6 +310,201 @@ impl DependencyGraph {
         deps
     }
 
+    /// Extract the variables an expression needs within the same timestep
+    ///
+    /// The input of a stateful delay/smoothing function is read from the delay's
+    /// stored state rather than the current value, so it does not form an
+    /// algebraic dependency.
+    fn extract_algebraic_dependencies(expr: &Expression) -> HashSet<String> {
+        match expr {
+            Expression::FunctionCall { name, args, .. }
+                if DELAY_FUNCTIONS.contains(&name.to_uppercase().as_str()) =>
+            {
+                let mut deps = HashSet::new();
+                for arg in args.iter().skip(1) {
+                    deps.extend(Self::extract_algebraic_dependencies(arg));
+                }
+                deps
+            }
+            Expression::BinaryOp { left, right, .. } => {
+                let mut deps = Self::extract_algebraic_dependencies(left);
+                deps.extend(Self::extract_algebraic_dependencies(right));
+                deps
+            }
+            Expression::UnaryOp { expr, .. } => Self::extract_algebraic_dependencies(expr),
+            Expression::FunctionCall { args, .. } => {
+                let mut deps = HashSet::new();
+                for arg in args {
+                    deps.extend(Self::extract_algebraic_dependencies(arg));
+                }
+                deps
+            }
+            Expression::Conditional { condition, true_expr, false_expr } => {
+                let mut deps = Self::extract_algebraic_dependencies(condition);
+                deps.extend(Self::extract_algebraic_dependencies(true_expr));
+                deps.extend(Self::extract_algebraic_dependencies(false_expr));
+                deps
+            }
+            _ => Self::extract_dependencies(expr),
+        }
+    }
+
+    /// Build a graph of the same-timestep dependencies between auxiliaries and flows
+    ///
+    /// Stocks and parameters are omitted since their values are known at the
+    /// start of every step.
+    pub fn algebraic_from_model(model: &Model) -> Self {
+        Self::equation_graph(model, Self::extract_algebraic_dependencies)
+    }
+
+    /// Graph over auxiliaries and flows with edges produced by `extract`
+    fn equation_graph(model: &Model, extract: fn(&Expression) -> HashSet<String>) -> Self {
+        let mut graph = Self::new();
+
+        for name in model.flows.keys() {
+            graph.add_node(GraphNode::new(name.clone(), ElementType::Flow));
+        }
+        for name in model.auxiliaries.keys() {
+            graph.add_node(GraphNode::new(name.clone(), ElementType::Auxiliary));
+        }
+
+        let equations = model.flows.iter()
+            .map(|(name, flow)| (GraphNode::new(name.clone(), ElementType::Flow), &flow.equation))
+            .chain(model.auxiliaries.iter()
+                .map(|(name, aux)| (GraphNode::new(name.clone(), ElementType::Auxiliary), &aux.equation)));
+
+        for (from_node, equation) in equations {
+            for dep in extract(equation) {
+                if let Some(to) = graph.find_node(&dep) {
+                    graph.add_edge(from_node.clone(), to, Polarity::Unknown);
+                }
+            }
+        }
+
+        graph
+    }
+
+    /// Auxiliaries and flows in the order they must be evaluated within a timestep
+    ///
+    /// Returns an error naming the variables involved if the model contains an
+    /// algebraic loop (a cycle not broken by a stock or delay).
+    pub fn evaluation_order(model: &Model) -> Result<Vec<GraphNode>, String> {
+        // Prefer evaluating delay inputs before the delay so it sees the current
+        // input; only when that forms a cycle let the delay use the previous value
+        let order = Self::equation_graph(model, Self::extract_dependencies)
+            .topological_sort()
+            .or_else(|_| Self::algebraic_from_model(model).topological_sort());
+
+        // Edges point from a variable to what it depends on, so dependencies
+        // come last in topological order
+        let mut order = order?;
+        order.reverse();
+        Ok(order)
+    }
+
+    /// Stocks, plus the auxiliaries and flows their initial values use, in the
+    /// order they must be evaluated at the start of a run
+    ///
+    /// Returns an error naming the variables involved if initial values
+    /// depend on each other in a cycle.
+    pub fn initialization_order(model: &Model) -> Result<Vec<GraphNode>, String> {
+        let equation = |name: &str| {
+            if let Some(stock) = model.stocks.get(name) {
+                Some((ElementType::Stock, &stock.initial))
+            } else if let Some(flow) = model.flows.get(name) {
+                Some((ElementType::Flow, &flow.equation))
+            } else {
+                model.auxiliaries.get(name).map(|aux| (ElementType::Auxiliary, &aux.equation))
+            }
+        };
+
+        // Only the auxiliaries and flows some initial value needs
+        let mut graph = Self::new();
+        let mut pending: Vec<String> = model.stocks.keys().cloned().collect();
+        let mut seen = HashSet::new();
+        while let Some(name) = pending.pop() {
+            if !seen.insert(name.clone()) {
+                continue;
+            }
+            let Some((element_type, expr)) = equation(&name) else { continue };
+            let node = GraphNode::new(name, element_type);
+            graph.add_node(node.clone());
+            for dep in Self::extract_dependencies(expr) {
+                if let Some((dep_type, _)) = equation(&dep) {
+                    graph.add_edge(node.clone(), GraphNode::new(dep.clone(), dep_type), Polarity::Unknown);
+                    pending.push(dep);
+                }
+            }
+        }
+
+        let mut order = graph.topological_sort()
+            .map_err(|e| e.replace("Model contains circular dependencies", "Initialization cycle"))?;
+        order.reverse();
+        Ok(order)
+    }
+
+    /// The equations of `order` grouped into strata that can each be
+    /// evaluated in parallel, in turn
+    ///
+    /// An equation is placed after the equations it reads in the same step,
+    /// and before any whose previous value it reads (those later in `order`).
+    /// Equations that advance delays, random streams or conveyors are kept in
+    /// the relative order `order` gives them, one per stratum, so evaluating
+    /// the strata gives exactly the results of evaluating `order` in turn.
+    pub fn evaluation_strata(model: &Model, order: &[String]) -> Vec<Stratum> {
+        let position: HashMap<&str, usize> = order.iter()
+            .enumerate()
+            .map(|(i, name)| (name.as_str(), i))
+            .collect();
+        // Flows whose rate a conveyor or oven adjusts as it is evaluated
+        let conveyor_flows: HashSet<&String> = model.stocks.values()
+            .filter(|stock| !stock.kind.is_reservoir())
+            .flat_map(|stock| stock.inflows.iter().chain(&stock.outflows))
+            .collect();
+
+        let mut levels = vec![0; order.len()];
+        let mut last_sequential = None;
+        let mut sequential = vec![false; order.len()];
+        for (i, name) in order.iter().enumerate() {
+            let equation = match (model.auxiliaries.get(name), model.flows.get(name)) {
+                (Some(aux), _) => &aux.equation,
+                (None, Some(flow)) => &flow.equation,
+                (None, None) => continue,
+            };
+
+            let dependencies: Vec<usize> = Self::extract_dependencies(equation).iter()
+                .filter_map(|dep| position.get(dep.as_str()).copied())
+                .filter(|&j| j != i)
+                .collect();
+            let mut level = dependencies.iter()
+                .filter(|&&j| j < i)
+                .map(|&j| levels[j] + 1)
+                .fold(levels[i], usize::max);
+            sequential[i] = equation.advances_state() || conveyor_flows.contains(name);
+            if sequential[i] {
+                level = last_sequential.map_or(level, |last: usize| level.max(last + 1));
+                last_sequential = Some(level);
+            }
+            levels[i] = level;
+
+            // Previous values must be read before they are replaced
+            for j in dependencies.into_iter().filter(|&j| j > i) {
+                levels[j] = levels[j].max(level + 1);
+            }
+        }
+
+        let mut strata = vec![Stratum::default(); levels.iter().max().map_or(0, |max| max + 1)];
+        for (i, name) in order.iter().enumerate() {
+            let stratum = &mut strata[levels[i]];
+            if sequential[i] {
+                stratum.sequential.push(name.clone());
+            } else {
+                stratum.parallel.push(name.clone());
+            }
+        }
+        strata
+    }
+
     /// Topological sort for evaluation order
     pub fn topological_sort(&self) -> Result<Vec<GraphNode>, String> {
         let mut in_degree: HashMap<GraphNode, usize> = HashMap::new();
@@ -317,18 +520,23 @@ impl DependencyGraph {
             *in_degree.entry(edge.to.clone()).or_insert(0) += 1;
         }
 
-        // Add nodes with no dependencies
-        for (node, &degree) in &in_degree {
-            if degree == 0 {
-                queue.push_back(node.clone());
-            }
-        }
+        // Add nodes with no dependencies. Ties are broken by name so the
+        // order, and with it which equation draws which random number, is
+        // the same every time a model is compiled
+        let mut roots: Vec<&GraphNode> = in_degree.iter()
+            .filter(|&(_, &degree)| degree == 0)
+            .map(|(node, _)| node)
+            .collect();
+        roots.sort_by(|a, b| a.name.cmp(&b.name));
+        queue.extend(roots.into_iter().cloned());
 
         // Process queue
         while let Some(node) = queue.pop_front() {
             result.push(node.clone());
 
             if let Some(neighbors) = self.adjacency.get(&node) {
+                let mut neighbors: Vec<&GraphNode> = neighbors.iter().collect();
+                neighbors.sort_by(|a, b| a.name.cmp(&b.name));
                 for neighbor in neighbors {
                     if let Some(degree) = in_degree.get_mut(neighbor) {
                         *degree -= 1;
@@ -341,11 +549,61 @@ impl DependencyGraph {
         }
 
         if result.len() != self.nodes.len() {
-            Err("Model contains circular dependencies".to_string())
+            let cycle = self.find_cycle(&in_degree);
+            Err(format!(
+                "Model contains circular dependencies: {}",
+                cycle.iter().map(|n| n.name.as_str()).collect::<Vec<_>>().join(" -> ")
+            ))
         } else {
             Ok(result)
         }
     }
+
+    /// Recover one cycle from the nodes left unprocessed by a topological sort
+    fn find_cycle(&self, in_degree: &HashMap<GraphNode, usize>) -> Vec<GraphNode> {
+        // Every remaining node still has a remaining predecessor, so walking
+        // backwards along edges must eventually revisit a node
+        let remaining: HashSet<&GraphNode> = in_degree.iter()
+            .filter(|(_, degree)| **degree > 0)
+            .map(|(node, _)| node)
+            .collect();
+
+        let Some(mut current) = remaining.iter().min_by_key(|n| &n.name).copied() else {
+            return Vec::new();
+        };
+
+        let mut path: Vec<&GraphNode> = Vec::new();
+        loop {
+            if let Some(pos) = path.iter().position(|n| *n == current) {
+                // Path was built backwards, so flip it into dependency direction
+                let mut cycle: Vec<GraphNode> = path[pos..].iter().rev().map(|n| (*n).clone()).collect();
+                // Start from the alphabetically first node so reports are stable
+                let start = (0..cycle.len()).min_by_key(|&i| &cycle[i].name).unwrap_or(0);
+                cycle.rotate_left(start);
+                cycle.push(cycle[0].clone());
+                return cycle;
+            }
+            path.push(current);
+
+            match self.edges.iter()
+                .filter(|e| &e.to == current && remaining.contains(&e.from))
+                .map(|e| &e.from)
+                .min_by_key(|n| &n.name)
+            {
+                Some(prev) => current = prev,
+                None => return path.into_iter().cloned().collect(),
+            }
+        }
+    }
+}
+
+/// Auxiliaries and flows that depend on none of each other, from [`DependencyGraph::evaluation_strata`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stratum {
+    /// Equations that advance delays, random streams or conveyors, to evaluate in this order
+    pub sequential: Vec<String>,
+    /// Equations that can be evaluated in any order, or at once
+    pub parallel: Vec<String>,
 }
 
 /// Model structure analyzer
@@ -427,9 +685,62 @@ impl StructureAnalyzer {
             }
         }
 
+        let undetermined: Vec<_> = self.feedback_loops.iter()
+            .filter(|l| l.polarity == Polarity::Unknown)
+            .collect();
+        if !undetermined.is_empty() {
+            report.push_str("\n=== Loops of Undetermined Polarity ===\n");
+            for (i, loop_item) in undetermined.iter().enumerate().take(10) {
+                report.push_str(&format!("\nU{} (length {}):\n", i + 1, loop_item.length));
+                for node in &loop_item.nodes {
+                    report.push_str(&format!("  -> {} ({:?})\n", node.name, node.element_type));
+                }
+            }
+        }
+
         report
     }
 
+    /// Export loops as JSON for machine consumption
+    ///
+    /// Loops are labelled like the text report: `R<n>` reinforcing,
+    /// `B<n>` balancing and `U<n>` for undetermined polarity.
+    pub fn export_json(&self) -> serde_json::Value {
+        let mut counters = HashMap::new();
+        let loops: Vec<serde_json::Value> = self.feedback_loops.iter()
+            .map(|loop_item| {
+                let prefix = match loop_item.polarity {
+                    Polarity::Positive => "R",
+                    Polarity::Negative => "B",
+                    Polarity::Unknown => "U",
+                };
+                let counter = counters.entry(prefix).or_insert(0);
+                *counter += 1;
+
+                serde_json::json!({
+                    "id": format!("{}{}", prefix, counter),
+                    "polarity": format!("{:?}", loop_item.polarity),
+                    "length": loop_item.length,
+                    "contains_stock": loop_item.contains_stock(),
+                    "nodes": loop_item.nodes.iter()
+                        .map(|node| serde_json::json!({
+                            "name": node.name,
+                            "type": format!("{:?}", node.element_type),
+                        }))
+                        .collect::<Vec<_>>(),
+                })
+            })
+            .collect();
+
+        serde_json::json!({
+            "nodes": self.graph.nodes.len(),
+            "edges": self.graph.edges.len(),
+            "reinforcing": self.reinforcing_loops().len(),
+            "balancing": self.balancing_loops().len(),
+            "loops": loops,
+        })
+    }
+
     /// Export graph to DOT format for visualization
     pub fn export_dot(&self) -> String {
         let mut dot = String::new();
@@ -438,20 +749,20 @@ impl StructureAnalyzer {
         dot.push_str("  node [shape=box];\n\n");
 
         // Nodes with different shapes
-        for node in &self.graph.nodes {
+        for node in self.sorted_nodes() {
             let shape = match node.element_type {
                 ElementType::Stock => "box",
                 ElementType::Flow => "ellipse",
                 ElementType::Auxiliary => "diamond",
                 ElementType::Parameter => "plaintext",
             };
-            dot.push_str(&format!("  \"{}\" [shape={}];\n", node.name, shape));
+            dot.push_str(&format!("  \"{}\" [shape={}];\n", node.name.replace('"', "\\\""), shape));
         }
 
-        dot.push_str("\n");
+        dot.push('\n');
 
         // Edges with polarity
-        for edge in &self.graph.edges {
+        for edge in self.sorted_edges() {
             let style = match edge.polarity {
                 Polarity::Positive => "solid",
                 Polarity::Negative => "dashed",
@@ -459,19 +770,83 @@ impl StructureAnalyzer {
             };
             dot.push_str(&format!(
                 "  \"{}\" -> \"{}\" [style={}];\n",
-                edge.from.name, edge.to.name, style
+                edge.from.name.replace('"', "\\\""), edge.to.name.replace('"', "\\\""), style
             ));
         }
 
         dot.push_str("}\n");
         dot
     }
+
+    /// Export graph as a Mermaid flowchart, ready to paste into Markdown
+    ///
+    /// Shapes follow the DOT export; links are labelled `+` or `−` by polarity
+    /// and drawn dotted when the polarity is unknown.
+    pub fn export_mermaid(&self) -> String {
+        let nodes = self.sorted_nodes();
+        // Mermaid ids must be plain words, so number the nodes and show names as labels
+        let ids: HashMap<&str, usize> = nodes.iter().enumerate().map(|(i, n)| (n.name.as_str(), i)).collect();
+
+        let mut chart = String::from("flowchart LR\n");
+        for (i, node) in nodes.iter().enumerate() {
+            let label = node.name.replace('"', "#quot;");
+            let shape = match node.element_type {
+                ElementType::Stock => format!("[\"{}\"]", label),
+                ElementType::Flow => format!("([\"{}\"])", label),
+                ElementType::Auxiliary => format!("{{\"{}\"}}", label),
+                ElementType::Parameter => format!("(\"{}\")", label),
+            };
+            chart.push_str(&format!("    n{}{}\n", i, shape));
+        }
+
+        for edge in self.sorted_edges() {
+            let link = match edge.polarity {
+                Polarity::Positive => "-->|+|",
+                Polarity::Negative => "-->|−|",
+                Polarity::Unknown => "-.->",
+            };
+            chart.push_str(&format!("    n{} {} n{}\n", ids[edge.from.name.as_str()], link, ids[edge.to.name.as_str()]));
+        }
+        chart
+    }
+
+    /// Export the dependency graph itself (not its loops) as JSON
+    pub fn export_graph_json(&self) -> serde_json::Value {
+        let nodes: Vec<serde_json::Value> = self.sorted_nodes().iter()
+            .map(|node| serde_json::json!({
+                "name": node.name,
+                "type": format!("{:?}", node.element_type),
+            }))
+            .collect();
+        let edges: Vec<serde_json::Value> = self.sorted_edges().iter()
+            .map(|edge| serde_json::json!({
+                "from": edge.from.name,
+                "to": edge.to.name,
+                "polarity": format!("{:?}", edge.polarity),
+            }))
+            .collect();
+
+        serde_json::json!({ "nodes": nodes, "edges": edges })
+    }
+
+    /// Nodes by name, so exports are stable from run to run
+    fn sorted_nodes(&self) -> Vec<&GraphNode> {
+        let mut nodes: Vec<&GraphNode> = self.graph.nodes.iter().collect();
+        nodes.sort_by(|a, b| a.name.cmp(&b.name));
+        nodes
+    }
+
+    fn sorted_edges(&self) -> Vec<&GraphEdge> {
+        let mut edges: Vec<&GraphEdge> = self.graph.edges.iter().collect();
+        edges.sort_by(|a, b| (&a.from.name, &a.to.name).cmp(&(&b.from.name, &b.to.name)));
+        edges
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::model::{Stock, Flow, Parameter};
+    use crate::model::{Stock, Flow, Auxiliary, Parameter};
 
     #[test]
     fn test_dependency_graph() {
@@ -501,9 +876,71 @@ mod tests {
 
         let analyzer = StructureAnalyzer::new(&model);
 
-        // May or may not find loops depending on graph construction details
-        // Just check that it doesn't crash
-        println!("Found {} feedback loops", analyzer.feedback_loops.len());
+        assert_eq!(analyzer.feedback_loops.len(), 1);
+        let names: HashSet<&str> = analyzer.feedback_loops[0].nodes.iter()
+            .map(|n| n.name.as_str())
+            .collect();
+        assert_eq!(names, HashSet::from(["Population", "births"]));
+
+        let json = analyzer.export_json();
+        assert_eq!(json["loops"][0]["length"], 2);
+        assert!(analyzer.export_dot().contains("\"Population\" -> \"births\""));
+    }
+
+    #[test]
+    fn test_sir_loop_polarities() {
+        let model = crate::io::load_model("examples/sir_epidemic.yaml").unwrap();
+        let analyzer = StructureAnalyzer::new(&model);
+        let loop_with = |names: &[&str]| analyzer.feedback_loops.iter()
+            .find(|l| l.length == names.len() && names.iter().all(|n| l.nodes.iter().any(|node| node.name == *n)))
+            .unwrap()
+            .polarity;
+
+        // Contagion reinforces; depletion of susceptibles and recovery balance
+        assert_eq!(loop_with(&["Infected", "infection_rate"]), Polarity::Positive);
+        assert_eq!(loop_with(&["Susceptible", "infection_rate"]), Polarity::Negative);
+        assert_eq!(loop_with(&["Infected", "recovery_rate"]), Polarity::Negative);
+        assert!(analyzer.feedback_loops.iter().all(|l| l.polarity != Polarity::Unknown));
+    }
+
+    #[test]
+    fn test_loop_json_labels() {
+        let model = crate::io::load_model("examples/sir_epidemic.yaml").unwrap();
+        let json = StructureAnalyzer::new(&model).export_json();
+        assert_eq!((json["reinforcing"].as_u64(), json["balancing"].as_u64()), (Some(2), Some(4)));
+        let mut ids: Vec<&str> = json["loops"].as_array().unwrap().iter().map(|l| l["id"].as_str().unwrap()).collect();
+        ids.sort();
+        assert_eq!(ids, ["B1", "B2", "B3", "B4", "R1", "R2"]);
+
+        // A loop through a function of unknown slope gets its own label and section
+        let mut model = Model::new("Test");
+        model.add_stock(Stock::new("Level", "1").with_inflows(vec!["change".to_string()])).unwrap();
+        model.add_flow(Flow::new("change", "SIN(Level)")).unwrap();
+        let analyzer = StructureAnalyzer::new(&model);
+        assert_eq!(analyzer.export_json()["loops"][0]["id"], "U1");
+        assert!(analyzer.generate_report().contains("=== Loops of Undetermined Polarity ===\n\nU1 (length 2):"));
+    }
+
+    #[test]
+    fn test_mermaid_and_graph_json() {
+        let mut model = Model::new("Test");
+        let mut stock = Stock::new("Population", "100");
+        stock.inflows.push("births".to_string());
+        model.add_stock(stock).unwrap();
+        model.add_parameter(Parameter::new("growth_rate", 0.1)).unwrap();
+        model.add_flow(Flow::new("births", "Population * growth_rate")).unwrap();
+
+        let analyzer = StructureAnalyzer::new(&model);
+        let mermaid = analyzer.export_mermaid();
+        let lines: Vec<&str> = mermaid.lines().collect();
+        // Nodes are numbered by name: Population, births, growth_rate
+        assert_eq!(lines[..4], ["flowchart LR", "    n0[\"Population\"]", "    n1([\"births\"])", "    n2(\"growth_rate\")"]);
+        assert!(lines.contains(&"    n1 -->|+| n0"));
+
+        let json = analyzer.export_graph_json();
+        assert_eq!(json["nodes"].as_array().unwrap().len(), 3);
+        assert_eq!(json["edges"].as_array().unwrap().len(), 3);
+        assert_eq!(json["edges"][0]["from"], "Population");
     }
 
     #[test]
@@ -521,4 +958,97 @@ mod tests {
             Polarity::Positive
         );
     }
+
+    #[test]
+    fn test_evaluation_order_respects_dependencies() {
+        let mut model = Model::new("Test");
+        model.add_stock(Stock::new("Population", "100")).unwrap();
+        model.add_flow(Flow::new("births", "Population * effective_rate")).unwrap();
+        model.add_auxiliary(Auxiliary::new("effective_rate", "base_rate * 2")).unwrap();
+        model.add_auxiliary(Auxiliary::new("base_rate", "0.01")).unwrap();
+
+        let order: Vec<String> = DependencyGraph::evaluation_order(&model).unwrap()
+            .into_iter().map(|n| n.name).collect();
+        let pos = |name: &str| order.iter().position(|n| n == name).unwrap();
+
+        assert_eq!(order.len(), 3);
+        assert!(pos("base_rate") < pos("effective_rate"));
+        assert!(pos("effective_rate") < pos("births"));
+    }
+
+    #[test]
+    fn test_evaluation_order_reports_algebraic_loop() {
+        let mut model = Model::new("Test");
+        model.add_auxiliary(Auxiliary::new("a", "b + 1")).unwrap();
+        model.add_auxiliary(Auxiliary::new("b", "c * 2")).unwrap();
+        model.add_auxiliary(Auxiliary::new("c", "a - 1")).unwrap();
+        model.add_auxiliary(Auxiliary::new("d", "a")).unwrap();
+
+        let err = DependencyGraph::evaluation_order(&model).unwrap_err();
+        assert!(err.contains("a -> b -> c -> a"), "{}", err);
+    }
+
+    #[test]
+    fn test_evaluation_order_delay_breaks_loop() {
+        let mut model = Model::new("Test");
+        model.add_auxiliary(Auxiliary::new("perceived", "SMOOTH(actual, 5)")).unwrap();
+        model.add_auxiliary(Auxiliary::new("actual", "perceived * 2")).unwrap();
+
+        assert!(DependencyGraph::evaluation_order(&model).is_ok());
+    }
+
+    #[test]
+    fn test_evaluation_strata() {
+        let mut model = Model::new("Test");
+        model.add_stock(Stock::new("Population", "100")).unwrap();
+        model.add_flow(Flow::new("births", "Population * effective_rate + noise")).unwrap();
+        model.add_auxiliary(Auxiliary::new("effective_rate", "base_rate * 2")).unwrap();
+        model.add_auxiliary(Auxiliary::new("base_rate", "0.01")).unwrap();
+        model.add_auxiliary(Auxiliary::new("noise", "RANDOM()")).unwrap();
+        model.add_auxiliary(Auxiliary::new("shock", "NORMAL(0, 1)")).unwrap();
+        model.add_auxiliary(Auxiliary::new("perceived", "SMOOTH(actual, 5)")).unwrap();
+        model.add_auxiliary(Auxiliary::new("actual", "perceived * 2")).unwrap();
+        model.compile().unwrap();
+
+        let order = model.evaluation_order.clone().unwrap();
+        let strata = DependencyGraph::evaluation_strata(&model, &order);
+        let level = |name: &str| strata.iter()
+            .position(|s| s.sequential.iter().chain(&s.parallel).any(|n| n == name))
+            .unwrap();
+
+        assert_eq!(strata.iter().map(|s| s.sequential.len() + s.parallel.len()).sum::<usize>(), order.len());
+        assert_eq!((level("base_rate"), level("effective_rate")), (0, 1));
+        assert!(level("births") > level("noise"));
+        assert!(level("actual") > level("perceived"));
+
+        // State-advancing equations keep their evaluation order, one per stratum
+        let sequential: Vec<&String> = strata.iter().flat_map(|s| &s.sequential).collect();
+        let in_order: Vec<&String> = order.iter()
+            .filter(|name| ["noise", "shock", "perceived"].contains(&name.as_str()))
+            .collect();
+        assert_eq!(sequential, in_order);
+        assert!(strata.iter().all(|s| s.sequential.len() <= 1));
+    }
+
+    #[test]
+    fn test_initialization_order() {
+        let mut model = Model::new("Test");
+        model.add_stock(Stock::new("Workers", "Jobs * participation")).unwrap();
+        model.add_stock(Stock::new("Jobs", "1000")).unwrap();
+        model.add_auxiliary(Auxiliary::new("participation", "0.5 + Jobs / 10000")).unwrap();
+        model.add_auxiliary(Auxiliary::new("unrelated", "unrelated_too")).unwrap();
+        model.add_auxiliary(Auxiliary::new("unrelated_too", "unrelated")).unwrap();
+
+        let order: Vec<String> = DependencyGraph::initialization_order(&model).unwrap()
+            .into_iter().map(|n| n.name).collect();
+        assert_eq!(order, vec!["Jobs", "participation", "Workers"]);
+
+        // The loop between the unrelated auxiliaries does not affect initial values
+        let state = crate::simulation::SimulationState::initialize_from_model(&model).unwrap();
+        assert_eq!(state.stocks["Workers"], 600.0);
+
+        model.add_stock(Stock::new("Hires", "Workers / Hires")).unwrap();
+        let err = DependencyGraph::initialization_order(&model).unwrap_err();
+        assert_eq!(err, "Initialization cycle: Hires -> Hires");
+    }
 }
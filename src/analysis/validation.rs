@@ -0,0 +1,296 @@
+/// Static model validation
+///
+/// Checks a model's structure without simulating it: references to variables
+/// that do not exist, stocks wired to missing flows, elements nothing uses,
+/// algebraic loops that no stock or delay breaks and initial values that
+/// depend on each other.
+
+use std::collections::{BTreeSet, HashSet};
+use crate::model::{Event, EventAction, EventTrigger, Expression, Model};
+use crate::simulation::AgentRule;
+use super::structure::DependencyGraph;
+
+/// Problems found by [`validate_model`]
+#[derive(Debug, Clone, Default)]
+pub struct ValidationReport {
+    /// Problems that stop the model from simulating
+    pub errors: Vec<String>,
+    /// Likely modelling mistakes that do not stop a run
+    pub warnings: Vec<String>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// Validate every equation and connection in `model`
+pub fn validate_model(model: &Model) -> ValidationReport {
+    let mut report = ValidationReport::default();
+
+    let mut defined: HashSet<&str> = model.stocks.keys()
+        .chain(model.flows.keys())
+        .chain(model.auxiliaries.keys())
+        .chain(model.parameters.keys())
+        .chain(model.lookups.keys())
+        .chain(model.lookups_2d.keys())
+        .chain(model.data.keys())
+        .map(String::as_str)
+        .collect();
+    defined.extend(agent_names(model));
+    // Queues, servers and sinks, named by the DES_ functions
+    defined.extend(model.des.iter().flat_map(|des| des.node_names()).map(String::as_str));
+
+    // Flows connected to each stock
+    let mut connected: HashSet<&str> = HashSet::new();
+    for name in sorted(model.stocks.keys()) {
+        let stock = &model.stocks[name];
+        for (kind, flows) in [("inflow", &stock.inflows), ("outflow", &stock.outflows)] {
+            for flow in flows {
+                if model.flows.contains_key(flow) {
+                    connected.insert(flow);
+                } else {
+                    report.errors.push(format!(
+                        "Stock '{}' references non-existent {} '{}'", name, kind, flow
+                    ));
+                }
+            }
+        }
+    }
+
+    // Flows broken into entities by discrete-event generators
+    for generator in model.des.iter().flat_map(|des| &des.generators) {
+        if let Some(flow) = generator.flow.as_deref().filter(|flow| model.flows.contains_key(*flow)) {
+            connected.insert(flow);
+        }
+    }
+
+    // References made by every equation
+    let equations = model.stocks.iter().map(|(name, stock)| (name, &stock.initial))
+        .chain(model.flows.iter().map(|(name, flow)| (name, &flow.equation)))
+        .chain(model.auxiliaries.iter().map(|(name, aux)| (name, &aux.equation)))
+        .chain(model.events.iter().flat_map(|event| event_expressions(event).map(move |expr| (&event.name, expr))))
+        .chain(model.constraints.iter().map(|constraint| (&constraint.name, &constraint.condition)));
+
+    let mut referenced: HashSet<String> = HashSet::new();
+    let mut undefined: BTreeSet<(String, String)> = BTreeSet::new();
+    for (name, equation) in equations {
+        for dep in DependencyGraph::extract_dependencies(equation) {
+            if !is_defined(&dep, equation, &defined) {
+                undefined.insert((name.clone(), dep.clone()));
+            }
+            referenced.insert(dep);
+        }
+    }
+    for (name, dep) in undefined {
+        report.errors.push(format!("'{}' references undefined variable '{}'", name, dep));
+    }
+    // Agent rules and transitions read SD variables too
+    for expression in agent_expressions(model) {
+        referenced.extend(DependencyGraph::extract_dependencies(&expression));
+    }
+
+    for event in &model.events {
+        for action in &event.actions {
+            let target = action.target();
+            let known = model.stocks.contains_key(target)
+                || model.parameters.contains_key(target)
+                || model.expanded_arrays.contains_key(target);
+            if !known {
+                report.errors.push(format!("Event '{}' changes undefined variable '{}'", event.name, target));
+            }
+        }
+    }
+
+    for group in &model.conservation {
+        for stock in group.stocks.iter().filter(|stock| !model.stocks.contains_key(*stock)) {
+            report.errors.push(format!("Conserved group '{}' includes undefined stock '{}'", group.name, stock));
+        }
+        for flow in group.boundary.iter().filter(|flow| !model.flows.contains_key(*flow)) {
+            report.errors.push(format!("Conserved group '{}' has undefined boundary flow '{}'", group.name, flow));
+        }
+        for leak in group.leaks(model) {
+            report.warnings.push(format!("Conserved group '{}': {}", group.name, leak));
+        }
+    }
+
+    let is_used = |name: &str| {
+        referenced.contains(name)
+            || referenced.iter().any(|r| name.strip_prefix(r.as_str()).is_some_and(|rest| rest.starts_with('_')))
+    };
+    for name in sorted(model.parameters.keys()) {
+        if !is_used(name) {
+            report.warnings.push(format!("Parameter '{}' is never used", name));
+        }
+    }
+    for name in sorted(model.auxiliaries.keys()) {
+        if !is_used(name) {
+            report.warnings.push(format!("Auxiliary '{}' is never used", name));
+        }
+    }
+    for name in sorted(model.flows.keys()) {
+        if !connected.contains(name.as_str()) && !is_used(name) {
+            report.warnings.push(format!("Flow '{}' is not connected to any stock", name));
+        }
+    }
+
+    if let Err(e) = DependencyGraph::algebraic_from_model(model).topological_sort() {
+        report.errors.push(e.replace("Model contains circular dependencies", "Algebraic loop"));
+    }
+    if let Err(e) = DependencyGraph::initialization_order(model) {
+        report.errors.push(e);
+    }
+
+    report
+}
+
+/// Whether a name used in `equation` resolves when the model is simulated
+fn is_defined(name: &str, equation: &Expression, defined: &HashSet<&str>) -> bool {
+    if defined.contains(name) || name.eq_ignore_ascii_case("TIME") {
+        return true;
+    }
+
+    // `Population[North]` resolves to the flattened `Population_North`
+    is_subscripted(name, equation)
+        && defined.iter().any(|d| d.strip_prefix(name).is_some_and(|rest| rest.starts_with('_')))
+}
+
+fn is_subscripted(name: &str, expr: &Expression) -> bool {
+    match expr {
+        Expression::SubscriptedVariable { name: n, .. } => n == name,
+        Expression::BinaryOp { left, right, .. } => is_subscripted(name, left) || is_subscripted(name, right),
+        Expression::UnaryOp { expr, .. } => is_subscripted(name, expr),
+        Expression::FunctionCall { args, .. } => args.iter().any(|arg| is_subscripted(name, arg)),
+        Expression::Conditional { condition, true_expr, false_expr } => {
+            is_subscripted(name, condition) || is_subscripted(name, true_expr) || is_subscripted(name, false_expr)
+        }
+        _ => false,
+    }
+}
+
+fn event_expressions(event: &Event) -> impl Iterator<Item = &Expression> {
+    let condition = match &event.trigger {
+        EventTrigger::When(condition) => Some(condition),
+        EventTrigger::At(_) => None,
+    };
+    condition.into_iter().chain(event.actions.iter().map(|action| match action {
+        EventAction::Set { value, .. } => value,
+        EventAction::Add { amount, .. } => amount,
+    }))
+}
+
+/// Agent types, states and attributes, named by the AGENT_ functions
+fn agent_names(model: &Model) -> Vec<&str> {
+    fn rule_targets<'a>(rules: &'a [AgentRule], names: &mut Vec<&'a str>) {
+        for rule in rules {
+            match rule {
+                AgentRule::SetAttribute { attribute, .. } => names.push(attribute),
+                AgentRule::Conditional { then_rules, else_rules, .. } => {
+                    rule_targets(then_rules, names);
+                    rule_targets(else_rules, names);
+                }
+                AgentRule::Die => {}
+            }
+        }
+    }
+
+    let mut names = Vec::new();
+    for population in model.hybrid.iter().flat_map(|hybrid| &hybrid.agents) {
+        let agent_type = &population.agent_type;
+        names.push(agent_type.name.as_str());
+        names.extend(agent_type.initial_attributes.keys().chain(agent_type.distributions.keys()).map(String::as_str));
+        rule_targets(&agent_type.rules, &mut names);
+        if let Some(machine) = &agent_type.state_machine {
+            names.extend(machine.states.iter().map(String::as_str));
+        }
+    }
+    names
+}
+
+/// Expressions of every agent rule and state transition that parse; the
+/// others are reported when the model is loaded
+fn agent_expressions(model: &Model) -> Vec<Expression> {
+    fn rule_sources<'a>(rules: &'a [AgentRule], sources: &mut Vec<&'a str>) {
+        for rule in rules {
+            match rule {
+                AgentRule::SetAttribute { expression, .. } => sources.push(expression),
+                AgentRule::Conditional { condition, then_rules, else_rules } => {
+                    sources.push(condition);
+                    rule_sources(then_rules, sources);
+                    rule_sources(else_rules, sources);
+                }
+                AgentRule::Die => {}
+            }
+        }
+    }
+
+    let mut sources = Vec::new();
+    for population in model.hybrid.iter().flat_map(|hybrid| &hybrid.agents) {
+        rule_sources(&population.agent_type.rules, &mut sources);
+        if let Some(machine) = &population.agent_type.state_machine {
+            sources.extend(machine.transitions.iter().map(|transition| transition.chance.expression()));
+        }
+    }
+    sources.into_iter().filter_map(|source| Expression::parse(source).ok()).collect()
+}
+
+fn sorted<'a>(names: impl Iterator<Item = &'a String>) -> Vec<&'a String> {
+    let mut names: Vec<&String> = names.collect();
+    names.sort();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Auxiliary, Flow, Parameter, Stock};
+
+    fn population_model() -> Model {
+        let mut model = Model::new("Test");
+        model.add_stock(Stock::new("Population", "100").with_inflows(vec!["births".to_string()])).unwrap();
+        model.add_parameter(Parameter::new("birth_rate", 0.1)).unwrap();
+        model.add_flow(Flow::new("births", "Population * birth_rate")).unwrap();
+        model
+    }
+
+    #[test]
+    fn test_valid_model_has_no_findings() {
+        let report = validate_model(&population_model());
+        assert!(report.is_valid());
+        assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+    }
+
+    #[test]
+    fn test_reports_undefined_and_unused() {
+        let mut model = population_model();
+        model.add_auxiliary(Auxiliary::new("pressure", "Population / capacity + TIME")).unwrap();
+        model.add_parameter(Parameter::new("spare", 1.0)).unwrap();
+        model.add_flow(Flow::new("drift", "1")).unwrap();
+        model.stocks.get_mut("Population").unwrap().outflows.push("deaths".to_string());
+
+        let report = validate_model(&model);
+        assert_eq!(report.errors, vec![
+            "Stock 'Population' references non-existent outflow 'deaths'".to_string(),
+            "'pressure' references undefined variable 'capacity'".to_string(),
+        ]);
+        assert_eq!(report.warnings, vec![
+            "Parameter 'spare' is never used".to_string(),
+            "Auxiliary 'pressure' is never used".to_string(),
+            "Flow 'drift' is not connected to any stock".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn test_reports_algebraic_loop() {
+        let mut model = population_model();
+        model.add_auxiliary(Auxiliary::new("a", "b + birth_rate")).unwrap();
+        model.add_auxiliary(Auxiliary::new("b", "a * 2")).unwrap();
+        model.add_auxiliary(Auxiliary::new("smoothed", "SMOOTH(a, 3)")).unwrap();
+        model.add_flow(Flow::new("feedback", "smoothed")).unwrap();
+        model.stocks.get_mut("Population").unwrap().inflows.push("feedback".to_string());
+
+        let report = validate_model(&model);
+        assert_eq!(report.errors, vec!["Algebraic loop: a -> b -> a".to_string()]);
+    }
+}
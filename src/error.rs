@@ -0,0 +1,305 @@
+/// Errors returned by the public library API
+///
+/// Entry points an embedding application calls (loading a model, building
+/// and running an engine, writing results) return [`Error`]. Each kind of
+/// failure has its own enum carrying what a user needs to find the problem:
+/// the file, the variable and its equation, the simulated time.
+
+use std::path::PathBuf;
+
+/// Something that went wrong loading, building or running a model
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+
+    #[error(transparent)]
+    Model(#[from] ModelError),
+
+    #[error(transparent)]
+    Simulation(#[from] SimulationError),
+
+    #[error(transparent)]
+    Io(#[from] IoError),
+}
+
+/// A file that could not be read as a model, data or checkpoint file
+///
+/// Format parsers leave `path` unset; [`load_model`](crate::load_model)
+/// fills it in with [`ParseError::in_file`].
+#[derive(Debug, thiserror::Error)]
+pub enum ParseError {
+    /// The file is not well formed, or not a model, in its format
+    #[error("{}{message}", file_prefix(path))]
+    Syntax { path: Option<PathBuf>, message: String },
+
+    /// An equation in the file does not parse
+    #[error("{}{error}", file_prefix(path))]
+    Equation { path: Option<PathBuf>, error: EquationError },
+}
+
+/// An equation that does not parse, and where in it the problem is
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("Error in equation for '{variable}': {message} at position {position}")]
+pub struct EquationError {
+    /// The variable the equation defines
+    pub variable: String,
+    pub equation: String,
+    /// Character offset of the problem within `equation`
+    pub position: usize,
+    pub message: String,
+}
+
+/// A model that cannot be simulated as defined
+#[derive(Debug, thiserror::Error)]
+pub enum ModelError {
+    /// Two elements of one kind share a name
+    #[error("{kind} '{name}' already exists")]
+    Duplicate { kind: &'static str, name: String },
+
+    /// A name that should refer to an element of the model does not
+    #[error("{kind} '{name}' not found")]
+    NotFound { kind: &'static str, name: String },
+
+    /// A stock's initial value, or what it depends on, failed to evaluate
+    #[error("Error initializing '{variable}': {message}")]
+    Initialization { variable: String, message: String },
+
+    /// Anything else wrong with the structure or equations, such as a
+    /// circular dependency or a subscript that does not fit
+    #[error("{0}")]
+    Invalid(String),
+}
+
+/// A valid model that failed while running
+#[derive(Debug, thiserror::Error)]
+pub enum SimulationError {
+    /// An auxiliary or flow equation failed to evaluate
+    #[error("Error evaluating {kind} '{variable}' at t={time}: {message}")]
+    Evaluation {
+        /// "auxiliary" or "flow"
+        kind: &'static str,
+        variable: String,
+        /// The equation as the model holds it
+        equation: String,
+        time: f64,
+        message: String,
+    },
+
+    /// A stock, flow or auxiliary became NaN, infinite or too large
+    #[error("{0}")]
+    NonFinite(Box<NonFiniteValue>),
+
+    /// The run was stopped through its cancellation token
+    #[error("Simulation cancelled at t={time}")]
+    Cancelled { time: f64 },
+
+    /// Any other failure, at the time the run had reached when known
+    #[error("{message}{}", time.map(|t| format!(" (at t={})", t)).unwrap_or_default())]
+    Failed { time: Option<f64>, message: String },
+}
+
+/// Where a NaN, infinite or too large value came from
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("{kind} '{variable}' became {value} at t={time} in {equation}{}", trace(inputs, chain))]
+pub struct NonFiniteValue {
+    /// "stock", "flow" or "auxiliary"
+    pub kind: &'static str,
+    /// The first variable to go bad from good inputs
+    pub variable: String,
+    /// Its equation, or the net flow of a stock
+    pub equation: String,
+    pub time: f64,
+    pub value: f64,
+    /// Each variable the equation reads, with its value at `time`
+    pub inputs: Vec<(String, f64)>,
+    /// Auxiliaries and flows upstream of the variable, nearest first, as
+    /// "name = value from equation"
+    pub chain: Vec<String>,
+}
+
+/// Reading or writing files and results
+#[derive(Debug, thiserror::Error)]
+pub enum IoError {
+    #[error("Failed to read {}: {source}", path.display())]
+    Read { path: PathBuf, source: std::io::Error },
+
+    #[error("Failed to write {}: {source}", path.display())]
+    Write { path: PathBuf, source: std::io::Error },
+
+    /// Results or a model could not be written in the requested format
+    #[error("{0}")]
+    Output(String),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl ParseError {
+    /// Say which file the error is in
+    pub fn in_file(self, file: impl Into<PathBuf>) -> Self {
+        let file = Some(file.into());
+        match self {
+            ParseError::Syntax { message, .. } => ParseError::Syntax { path: file, message },
+            ParseError::Equation { error, .. } => ParseError::Equation { path: file, error },
+        }
+    }
+}
+
+fn file_prefix(path: &Option<PathBuf>) -> String {
+    path.as_ref().map(|path| format!("{}: ", path.display())).unwrap_or_default()
+}
+
+fn trace(inputs: &[(String, f64)], chain: &[String]) -> String {
+    let mut text = String::new();
+    if !inputs.is_empty() {
+        let inputs: Vec<String> = inputs.iter().map(|(name, value)| format!("{} = {}", name, value)).collect();
+        text.push_str(&format!("\n  inputs: {}", inputs.join(", ")));
+    }
+    for link in chain {
+        text.push_str(&format!("\n  fed by {}", link));
+    }
+    text
+}
+
+impl EquationError {
+    /// The equation with a caret under the character parsing stopped at
+    pub fn underline(&self) -> String {
+        format!("{}\n{}^", self.equation, " ".repeat(self.position))
+    }
+}
+
+impl SimulationError {
+    /// The simulated time the error happened at, if known
+    pub fn time(&self) -> Option<f64> {
+        match self {
+            SimulationError::Evaluation { time, .. } | SimulationError::Cancelled { time } => Some(*time),
+            SimulationError::NonFinite(value) => Some(value.time),
+            SimulationError::Failed { time, .. } => *time,
+        }
+    }
+
+    /// Record `time` as when the error happened, unless it already says
+    pub fn at(self, time: f64) -> Self {
+        match self {
+            SimulationError::Failed { time: None, message } => SimulationError::Failed { time: Some(time), message },
+            error => error,
+        }
+    }
+}
+
+// Most of the crate still reports errors as plain messages. These let `?`
+// pass typed errors into those functions and messages into typed ones.
+
+impl From<String> for SimulationError {
+    fn from(message: String) -> Self {
+        SimulationError::Failed { time: None, message }
+    }
+}
+
+impl From<String> for ParseError {
+    fn from(message: String) -> Self {
+        ParseError::Syntax { path: None, message }
+    }
+}
+
+impl From<&str> for ParseError {
+    fn from(message: &str) -> Self {
+        message.to_string().into()
+    }
+}
+
+impl From<EquationError> for ParseError {
+    fn from(error: EquationError) -> Self {
+        ParseError::Equation { path: None, error }
+    }
+}
+
+impl From<ModelError> for ParseError {
+    fn from(error: ModelError) -> Self {
+        error.to_string().into()
+    }
+}
+
+impl From<String> for ModelError {
+    fn from(message: String) -> Self {
+        ModelError::Invalid(message)
+    }
+}
+
+impl From<ParseError> for String {
+    fn from(error: ParseError) -> Self {
+        error.to_string()
+    }
+}
+
+impl From<EquationError> for String {
+    fn from(error: EquationError) -> Self {
+        error.to_string()
+    }
+}
+
+impl From<IoError> for String {
+    fn from(error: IoError) -> Self {
+        error.to_string()
+    }
+}
+
+impl From<SimulationError> for String {
+    fn from(error: SimulationError) -> Self {
+        error.to_string()
+    }
+}
+
+impl From<ModelError> for String {
+    fn from(error: ModelError) -> Self {
+        error.to_string()
+    }
+}
+
+impl From<Error> for String {
+    fn from(error: Error) -> Self {
+        error.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Flow, Model, Stock};
+    use crate::simulation::{SimulationConfig, SimulationEngine};
+
+    #[test]
+    fn test_errors_say_what_failed() {
+        let missing = std::env::temp_dir().join("rssdsim_no_such_model.yaml");
+        match crate::io::load_model(&missing) {
+            Err(Error::Io(IoError::Read { path, source })) => {
+                assert_eq!(path, missing);
+                assert_eq!(source.kind(), std::io::ErrorKind::NotFound);
+            }
+            other => panic!("expected an I/O error, got {:?}", other.map(|_| ())),
+        }
+
+        let mut model = Model::new("Test");
+        model.time.stop = 10.0;
+        model.add_stock(Stock::new("Population", "100").with_outflows(vec!["deaths".to_string()])).unwrap();
+        let err = model.add_stock(Stock::new("Population", "1")).unwrap_err();
+        assert!(matches!(&err, ModelError::Duplicate { kind: "Stock", name } if name == "Population"));
+        // The delay time goes negative after t=5
+        model.add_flow(Flow::new("deaths", "DELAYP(Population, 5 - TIME, 0) * 0.01")).unwrap();
+
+        let config = SimulationConfig { output_variables: Some(vec!["Land".to_string()]), ..SimulationConfig::default() };
+        let err = SimulationEngine::new(model.clone(), config).err().unwrap();
+        assert!(matches!(err, Error::Model(ModelError::NotFound { kind: "Output variable", .. })));
+        assert_eq!(String::from(err), "Output variable 'Land' not found");
+
+        let err = SimulationEngine::new(model, SimulationConfig::default()).unwrap().run().unwrap_err();
+        match err {
+            Error::Simulation(SimulationError::Evaluation { kind, variable, equation, time, .. }) => {
+                assert_eq!((kind, variable.as_str()), ("flow", "deaths"));
+                assert!(equation.contains("DELAYP"), "{}", equation);
+                assert!(time > 5.0 && time < 7.0, "{}", time);
+            }
+            other => panic!("expected an evaluation error, got {}", other),
+        }
+    }
+}
@@ -0,0 +1,253 @@
+/// C ABI for embedding the engine in C, C++, Julia or FMI wrappers
+///
+/// A simulation is an opaque handle: load a model file, run it, copy out
+/// the series you need, then free it. Functions that fail return NULL or -1
+/// and leave a message for `rsedsim_last_error`. The declarations are in
+/// `include/rsedsim.h`.
+///
+/// ```c
+/// RsedsimSimulation *sim = rsedsim_load_model("sir.yaml");
+/// if (!sim || rsedsim_run(sim, "rk4") != 0) {
+///     fprintf(stderr, "%s\n", rsedsim_last_error());
+/// }
+/// ssize_t n = rsedsim_get_series(sim, "Infected", NULL, 0);
+/// double *infected = malloc(n * sizeof(double));
+/// rsedsim_get_series(sim, "Infected", infected, n);
+/// rsedsim_free(sim);
+/// ```
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::ptr;
+use crate::model::Model;
+use crate::simulation::{IntegrationMethod, SimulationConfig, SimulationEngine, SimulationResults};
+
+/// A loaded model and the results of its last run
+pub struct RsedsimSimulation {
+    model: Model,
+    results: Option<SimulationResults>,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: impl Into<String>) {
+    // Interior NULs cannot cross into C; cut the message there
+    let mut message = message.into();
+    message.truncate(message.find('\0').unwrap_or(message.len()));
+    LAST_ERROR.with(|last| *last.borrow_mut() = CString::new(message).ok());
+}
+
+/// Read a C string argument, recording an error if it is NULL or not UTF-8
+unsafe fn read_str<'a>(s: *const c_char, what: &str) -> Option<&'a str> {
+    if s.is_null() {
+        set_last_error(format!("{} is NULL", what));
+        return None;
+    }
+    // SAFETY: the caller passes a NUL-terminated string that outlives the call
+    match unsafe { CStr::from_ptr(s) }.to_str() {
+        Ok(s) => Some(s),
+        Err(_) => {
+            set_last_error(format!("{} is not valid UTF-8", what));
+            None
+        }
+    }
+}
+
+/// Message of the last failed call on this thread, or NULL if none failed
+///
+/// The string stays valid until the next failing call on the same thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn rsedsim_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |message| message.as_ptr()))
+}
+
+/// Load a model file (any format `load_model` reads); NULL on failure
+///
+/// # Safety
+///
+/// `path` must be NULL or a NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rsedsim_load_model(path: *const c_char) -> *mut RsedsimSimulation {
+    let Some(path) = (unsafe { read_str(path, "path") }) else {
+        return ptr::null_mut();
+    };
+    match crate::io::load_model(path) {
+        Ok(model) => Box::into_raw(Box::new(RsedsimSimulation { model, results: None })),
+        Err(e) => {
+            set_last_error(e.to_string());
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Run the model with `integrator` (NULL for Euler); 0 on success, -1 on failure
+///
+/// # Safety
+///
+/// `sim` must come from `rsedsim_load_model` and not be freed; `integrator`
+/// must be NULL or a NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rsedsim_run(sim: *mut RsedsimSimulation, integrator: *const c_char) -> c_int {
+    // SAFETY: the caller passes a live handle
+    let Some(sim) = (unsafe { sim.as_mut() }) else {
+        set_last_error("simulation is NULL");
+        return -1;
+    };
+    let integration_method = if integrator.is_null() {
+        IntegrationMethod::Euler
+    } else {
+        let Some(name) = (unsafe { read_str(integrator, "integrator") }) else {
+            return -1;
+        };
+        match name.parse() {
+            Ok(method) => method,
+            Err(e) => {
+                set_last_error(e);
+                return -1;
+            }
+        }
+    };
+
+    let config = SimulationConfig { integration_method, ..SimulationConfig::default() };
+    match SimulationEngine::new(sim.model.clone(), config).and_then(|mut engine| engine.run()) {
+        Ok(results) => {
+            sim.results = Some(results);
+            0
+        }
+        Err(e) => {
+            set_last_error(e.to_string());
+            -1
+        }
+    }
+}
+
+/// Copy up to `capacity` values into `out`, returning how many there are in all
+unsafe fn copy_series(values: &[f64], out: *mut f64, capacity: usize) -> isize {
+    if !out.is_null() {
+        let n = values.len().min(capacity);
+        // SAFETY: the caller passes room for `capacity` values at `out`
+        unsafe { ptr::copy_nonoverlapping(values.as_ptr(), out, n) };
+    }
+    values.len() as isize
+}
+
+/// Copy the series of variable `name` from the last run into `out`
+///
+/// Writes at most `capacity` values and returns the length of the series,
+/// so a first call with `out` NULL sizes the buffer. Returns -1 if the model
+/// has not been run or did not record `name`.
+///
+/// # Safety
+///
+/// `sim` must be a live handle, `name` a NUL-terminated string, and `out`
+/// NULL or valid for writing `capacity` doubles.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rsedsim_get_series(
+    sim: *const RsedsimSimulation,
+    name: *const c_char,
+    out: *mut f64,
+    capacity: usize,
+) -> isize {
+    let Some(name) = (unsafe { read_str(name, "name") }) else {
+        return -1;
+    };
+    // SAFETY: the caller passes a live handle
+    let results = match unsafe { sim.as_ref() }.map(|sim| sim.results.as_ref()) {
+        Some(Some(results)) => results,
+        Some(None) => {
+            set_last_error("the model has not been run");
+            return -1;
+        }
+        None => {
+            set_last_error("simulation is NULL");
+            return -1;
+        }
+    };
+    match results.series(name) {
+        Some(values) => unsafe { copy_series(values, out, capacity) },
+        None => {
+            set_last_error(format!("Variable '{}' was not recorded", name));
+            -1
+        }
+    }
+}
+
+/// Copy the recorded times of the last run into `out`, like `rsedsim_get_series`
+///
+/// # Safety
+///
+/// `sim` must be a live handle and `out` NULL or valid for writing
+/// `capacity` doubles.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rsedsim_get_times(sim: *const RsedsimSimulation, out: *mut f64, capacity: usize) -> isize {
+    // SAFETY: the caller passes a live handle
+    match unsafe { sim.as_ref() }.and_then(|sim| sim.results.as_ref()) {
+        Some(results) => unsafe { copy_series(&results.times, out, capacity) },
+        None => {
+            set_last_error("the model has not been run");
+            -1
+        }
+    }
+}
+
+/// Free a simulation; NULL is ignored
+///
+/// # Safety
+///
+/// `sim` must be NULL or a handle from `rsedsim_load_model` not yet freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rsedsim_free(sim: *mut RsedsimSimulation) {
+    if !sim.is_null() {
+        // SAFETY: the handle was created by Box::into_raw in rsedsim_load_model
+        drop(unsafe { Box::from_raw(sim) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_c_api_round_trip() {
+        let path = std::env::temp_dir().join("rssdsim_ffi_model.yaml");
+        std::fs::write(&path, r#"
+model:
+  name: Decay
+  time: { start: 0, stop: 10, dt: 1 }
+  stocks:
+    - name: Stock
+      initial: 100
+      outflows: [drain]
+  flows:
+    - name: drain
+      equation: "Stock * 0.1"
+"#).unwrap();
+        let c_path = CString::new(path.to_str().unwrap()).unwrap();
+        let stock = CString::new("Stock").unwrap();
+        let missing = CString::new("Land").unwrap();
+
+        unsafe {
+            let sim = rsedsim_load_model(c_path.as_ptr());
+            assert!(!sim.is_null());
+            assert_eq!(rsedsim_get_series(sim, stock.as_ptr(), ptr::null_mut(), 0), -1);
+
+            let rk4 = CString::new("rk4").unwrap();
+            assert_eq!(rsedsim_run(sim, rk4.as_ptr()), 0);
+            let n = rsedsim_get_series(sim, stock.as_ptr(), ptr::null_mut(), 0);
+            assert_eq!(n, 11);
+            let mut values = vec![0.0; n as usize];
+            assert_eq!(rsedsim_get_series(sim, stock.as_ptr(), values.as_mut_ptr(), values.len()), n);
+            assert_eq!(values[0], 100.0);
+            assert!((values[10] - 100.0 * (-1.0f64).exp()).abs() < 1e-3, "{:?}", values);
+
+            assert_eq!(rsedsim_get_series(sim, missing.as_ptr(), ptr::null_mut(), 0), -1);
+            let error = CStr::from_ptr(rsedsim_last_error()).to_str().unwrap();
+            assert_eq!(error, "Variable 'Land' was not recorded");
+            rsedsim_free(sim);
+        }
+
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,153 @@
+/// Time series input for data variables
+///
+/// Series are read from wide tables: a time column and one column per
+/// variable, as CSV or (with the `with-parquet` feature) Parquet. Spreadsheets
+/// (with the `with-xlsx` feature) are read from a time row or column and a
+/// starting cell instead.
+
+use std::path::Path;
+use crate::model::{DataVariable, Model};
+
+/// Read the series for every data variable in `model`
+///
+/// Relative file paths are resolved against `base_dir`, normally the
+/// directory holding the model file. Variables that already have points are
+/// left alone.
+pub fn load_data(model: &mut Model, base_dir: &Path) -> Result<(), String> {
+    for data in model.data.values_mut() {
+        if !data.points.is_empty() {
+            continue;
+        }
+        let path = base_dir.join(&data.file);
+        let points = read_series(&path, data)
+            .map_err(|e| format!("Data variable '{}': {}", data.name, e))?;
+        data.set_points(points)?;
+    }
+    Ok(())
+}
+
+/// Read the (time, value) points for `data` from a CSV, Parquet or spreadsheet file
+pub fn read_series(path: &Path, data: &DataVariable) -> Result<Vec<(f64, f64)>, String> {
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+    let (headers, rows) = match extension.as_str() {
+        "parquet" => read_parquet(path)?,
+        "xlsx" | "xlsm" | "xlsb" | "xls" | "ods" => {
+            let time_at = data.time_column.as_deref()
+                .ok_or("a spreadsheet needs 'time_column', the column or row holding the times")?;
+            let cell = data.cell.as_deref()
+                .ok_or("a spreadsheet needs 'cell', the first value cell")?;
+            return super::xlsx_reader::read_series(path, data.sheet.as_deref(), time_at, cell);
+        }
+        _ => read_csv(path)?,
+    };
+
+    let index = |name: &str| {
+        headers.iter().position(|h| h == name)
+            .ok_or_else(|| format!("{} has no column '{}'", path.display(), name))
+    };
+    let time = match &data.time_column {
+        Some(column) => index(column)?,
+        None => 0,
+    };
+    let value = index(data.value_column())?;
+
+    rows.iter()
+        .filter(|row| !row[value].is_nan())
+        .map(|row| Ok((row[time], row[value])))
+        .collect()
+}
+
+type Table = (Vec<String>, Vec<Vec<f64>>);
+
+/// Read a CSV file with a header row; empty cells become NaN
+fn read_csv(path: &Path) -> Result<Table, String> {
+    let mut reader = csv::Reader::from_path(path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let headers = reader.headers()
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?
+        .iter()
+        .map(|h| h.trim().to_string())
+        .collect();
+
+    let mut rows = Vec::new();
+    for (line, record) in reader.records().enumerate() {
+        let record = record.map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let row = record.iter()
+            .map(|cell| match cell.trim() {
+                "" => Ok(f64::NAN),
+                cell => cell.parse().map_err(|_| format!(
+                    "{} row {}: '{}' is not a number", path.display(), line + 2, cell
+                )),
+            })
+            .collect::<Result<Vec<f64>, String>>()?;
+        rows.push(row);
+    }
+    Ok((headers, rows))
+}
+
+#[cfg(feature = "with-parquet")]
+fn read_parquet(path: &Path) -> Result<Table, String> {
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{Float32Type, Float64Type, Int32Type, Int64Type};
+    use arrow_schema::DataType;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    let file = std::fs::File::open(path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let reader = ParquetRecordBatchReaderBuilder::try_new(file)
+        .and_then(|builder| builder.build())
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+    let mut headers = Vec::new();
+    let mut rows = Vec::new();
+    for batch in reader {
+        let batch = batch.map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        headers = batch.schema().fields().iter().map(|f| f.name().clone()).collect();
+
+        let mut columns = Vec::new();
+        for column in batch.columns() {
+            let values: Vec<f64> = match column.data_type() {
+                DataType::Float64 => column.as_primitive::<Float64Type>().iter().map(|v| v.unwrap_or(f64::NAN)).collect(),
+                DataType::Float32 => column.as_primitive::<Float32Type>().iter().map(|v| v.map_or(f64::NAN, f64::from)).collect(),
+                DataType::Int64 => column.as_primitive::<Int64Type>().iter().map(|v| v.map_or(f64::NAN, |v| v as f64)).collect(),
+                DataType::Int32 => column.as_primitive::<Int32Type>().iter().map(|v| v.map_or(f64::NAN, f64::from)).collect(),
+                other => return Err(format!("{}: unsupported column type {}", path.display(), other)),
+            };
+            columns.push(values);
+        }
+        rows.extend((0..batch.num_rows()).map(|i| columns.iter().map(|c| c[i]).collect()));
+    }
+    Ok((headers, rows))
+}
+
+#[cfg(not(feature = "with-parquet"))]
+fn read_parquet(_path: &Path) -> Result<Table, String> {
+    Err("Parquet support not enabled. Compile with --features with-parquet".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_csv_series() {
+        let dir = std::env::temp_dir().join(format!("rssdsim_data_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("economy.csv"), "year,gdp,temperature\n2000,10,14.1\n2001,12,\n2002,16,14.4\n").unwrap();
+
+        let mut model = Model::new("Economy");
+        model.add_data(DataVariable::new("gdp", "economy.csv")).unwrap();
+        model.add_data(DataVariable::new("temp", "economy.csv").with_column("temperature").with_time_column("year")).unwrap();
+        load_data(&mut model, &dir).unwrap();
+
+        assert_eq!(model.data["gdp"].points, vec![(2000.0, 10.0), (2001.0, 12.0), (2002.0, 16.0)]);
+        // Missing cells are skipped rather than read as zero
+        assert_eq!(model.data["temp"].points, vec![(2000.0, 14.1), (2002.0, 14.4)]);
+
+        model.add_data(DataVariable::new("missing", "economy.csv")).unwrap();
+        let err = load_data(&mut model, &dir).unwrap_err();
+        assert!(err.contains("no column 'missing'"), "{}", err);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -13,6 +13,10 @@ use hdf5::File;
 use std::path::Path;
 #[cfg(feature = "with-hdf5")]
 use crate::simulation::SimulationResults;
+#[cfg(feature = "with-hdf5")]
+use crate::analysis::MonteCarloResults;
+#[cfg(feature = "with-hdf5")]
+use crate::simulation::AgentSnapshots;
 
 #[cfg(feature = "with-hdf5")]
 pub struct HDF5Writer;
@@ -28,13 +32,13 @@ impl HDF5Writer {
             .map_err(|e| format!("Failed to create HDF5 file: {}", e))?;
 
         // Extract time series
-        let time_values: Vec<f64> = results.states.iter().map(|s| s.time).collect();
+        let time_values = &results.times;
 
         // Create time dataset
         file.new_dataset::<f64>()
             .create("time", time_values.len())
             .map_err(|e| format!("Failed to create time dataset: {}", e))?
-            .write(&time_values)
+            .write(time_values)
             .map_err(|e| format!("Failed to write time data: {}", e))?;
 
         // Create stocks group
@@ -42,19 +46,14 @@ impl HDF5Writer {
             .create_group("stocks")
             .map_err(|e| format!("Failed to create stocks group: {}", e))?;
 
-        if !results.states.is_empty() {
+        if !results.is_empty() {
             // Write stock variables
-            for stock_name in results.states[0].stocks.keys() {
-                let values: Vec<f64> = results.states
-                    .iter()
-                    .map(|s| *s.stocks.get(stock_name).unwrap_or(&0.0))
-                    .collect();
-
+            for (stock_name, values) in results.stocks.iter() {
                 stocks_group
                     .new_dataset::<f64>()
                     .create(stock_name, values.len())
                     .map_err(|e| format!("Failed to create dataset for '{}': {}", stock_name, e))?
-                    .write(&values)
+                    .write(values)
                     .map_err(|e| format!("Failed to write values for '{}': {}", stock_name, e))?;
             }
 
@@ -64,17 +63,12 @@ impl HDF5Writer {
                 .map_err(|e| format!("Failed to create flows group: {}", e))?;
 
             // Write flow variables
-            for flow_name in results.states[0].flows.keys() {
-                let values: Vec<f64> = results.states
-                    .iter()
-                    .map(|s| *s.flows.get(flow_name).unwrap_or(&0.0))
-                    .collect();
-
+            for (flow_name, values) in results.flows.iter() {
                 flows_group
                     .new_dataset::<f64>()
                     .create(flow_name, values.len())
                     .map_err(|e| format!("Failed to create dataset for '{}': {}", flow_name, e))?
-                    .write(&values)
+                    .write(values)
                     .map_err(|e| format!("Failed to write values for '{}': {}", flow_name, e))?;
             }
 
@@ -84,17 +78,12 @@ impl HDF5Writer {
                 .map_err(|e| format!("Failed to create auxiliaries group: {}", e))?;
 
             // Write auxiliary variables
-            for aux_name in results.states[0].auxiliaries.keys() {
-                let values: Vec<f64> = results.states
-                    .iter()
-                    .map(|s| *s.auxiliaries.get(aux_name).unwrap_or(&0.0))
-                    .collect();
-
+            for (aux_name, values) in results.auxiliaries.iter() {
                 aux_group
                     .new_dataset::<f64>()
                     .create(aux_name, values.len())
                     .map_err(|e| format!("Failed to create dataset for '{}': {}", aux_name, e))?
-                    .write(&values)
+                    .write(values)
                     .map_err(|e| format!("Failed to write values for '{}': {}", aux_name, e))?;
             }
         }
@@ -124,34 +113,29 @@ impl HDF5Writer {
         let file = File::create(path)
             .map_err(|e| format!("Failed to create HDF5 file: {}", e))?;
 
-        let time_values: Vec<f64> = results.states.iter().map(|s| s.time).collect();
+        let time_values = &results.times;
 
         // Create time dataset with compression
         file.new_dataset::<f64>()
             .gzip(compression_level)
             .create("time", time_values.len())
             .map_err(|e| format!("Failed to create compressed time dataset: {}", e))?
-            .write(&time_values)
+            .write(time_values)
             .map_err(|e| format!("Failed to write time data: {}", e))?;
 
         let stocks_group = file
             .create_group("stocks")
             .map_err(|e| format!("Failed to create stocks group: {}", e))?;
 
-        if !results.states.is_empty() {
+        if !results.is_empty() {
             // Write compressed stock variables
-            for stock_name in results.states[0].stocks.keys() {
-                let values: Vec<f64> = results.states
-                    .iter()
-                    .map(|s| *s.stocks.get(stock_name).unwrap_or(&0.0))
-                    .collect();
-
+            for (stock_name, values) in results.stocks.iter() {
                 stocks_group
                     .new_dataset::<f64>()
                     .gzip(compression_level)
                     .create(stock_name, values.len())
                     .map_err(|e| format!("Failed to create dataset for '{}': {}", stock_name, e))?
-                    .write(&values)
+                    .write(values)
                     .map_err(|e| format!("Failed to write values for '{}': {}", stock_name, e))?;
             }
 
@@ -160,24 +144,109 @@ impl HDF5Writer {
                 .create_group("flows")
                 .map_err(|e| format!("Failed to create flows group: {}", e))?;
 
-            for flow_name in results.states[0].flows.keys() {
-                let values: Vec<f64> = results.states
-                    .iter()
-                    .map(|s| *s.flows.get(flow_name).unwrap_or(&0.0))
-                    .collect();
-
+            for (flow_name, values) in results.flows.iter() {
                 flows_group
                     .new_dataset::<f64>()
                     .gzip(compression_level)
                     .create(flow_name, values.len())
                     .map_err(|e| format!("Failed to create dataset for '{}': {}", flow_name, e))?
-                    .write(&values)
+                    .write(values)
                     .map_err(|e| format!("Failed to write values for '{}': {}", flow_name, e))?;
             }
         }
 
         Ok(())
     }
+
+    /// Write Monte Carlo statistics to HDF5 file
+    ///
+    /// Each variable gets a group holding one dataset per statistic.
+    pub fn write_monte_carlo<P: AsRef<Path>>(
+        results: &MonteCarloResults,
+        path: P,
+    ) -> Result<(), String> {
+        let file = File::create(path)
+            .map_err(|e| format!("Failed to create HDF5 file: {}", e))?;
+
+        file.new_dataset::<f64>()
+            .create("time", results.time.len())
+            .map_err(|e| format!("Failed to create time dataset: {}", e))?
+            .write(&results.time)
+            .map_err(|e| format!("Failed to write time data: {}", e))?;
+
+        for (name, stats) in &results.statistics {
+            let group = file
+                .create_group(name)
+                .map_err(|e| format!("Failed to create group '{}': {}", name, e))?;
+
+            for (statistic, values) in stats.series() {
+                group
+                    .new_dataset::<f64>()
+                    .create(statistic, values.len())
+                    .map_err(|e| format!("Failed to create dataset '{}/{}': {}", name, statistic, e))?
+                    .write(values)
+                    .map_err(|e| format!("Failed to write values for '{}/{}': {}", name, statistic, e))?;
+            }
+        }
+
+        file.new_attr::<u64>()
+            .create("n_runs")
+            .map_err(|e| format!("Failed to create n_runs attribute: {}", e))?
+            .write_scalar(&(results.n_runs as u64))
+            .map_err(|e| format!("Failed to write n_runs: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Write agent snapshots: a dataset per column, and an `attributes`
+    /// group with a dataset per attribute
+    pub fn write_agents<P: AsRef<Path>>(
+        snapshots: &AgentSnapshots,
+        path: P,
+    ) -> Result<(), String> {
+        use hdf5::types::VarLenUnicode;
+
+        let file = File::create(path)
+            .map_err(|e| format!("Failed to create HDF5 file: {}", e))?;
+        let n = snapshots.len();
+
+        file.new_dataset::<f64>()
+            .create("time", n)
+            .map_err(|e| format!("Failed to create time dataset: {}", e))?
+            .write(&snapshots.time)
+            .map_err(|e| format!("Failed to write time data: {}", e))?;
+        file.new_dataset::<u64>()
+            .create("agent_id", n)
+            .map_err(|e| format!("Failed to create agent_id dataset: {}", e))?
+            .write(&snapshots.agent_id)
+            .map_err(|e| format!("Failed to write agent ids: {}", e))?;
+
+        for (name, values) in [("agent_type", &snapshots.agent_type), ("state", &snapshots.state)] {
+            let values = values.iter()
+                .map(|value| value.parse::<VarLenUnicode>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| format!("Failed to encode '{}': {}", name, e))?;
+            file.new_dataset::<VarLenUnicode>()
+                .create(name, n)
+                .map_err(|e| format!("Failed to create {} dataset: {}", name, e))?
+                .write(&values)
+                .map_err(|e| format!("Failed to write {} data: {}", name, e))?;
+        }
+
+        let group = file
+            .create_group("attributes")
+            .map_err(|e| format!("Failed to create attributes group: {}", e))?;
+        for (name, values) in snapshots.attributes.iter().zip(&snapshots.values) {
+            group
+                .new_dataset::<f64>()
+                .create(name.as_str(), n)
+                .map_err(|e| format!("Failed to create dataset for '{}': {}", name, e))?
+                .write(values)
+                .map_err(|e| format!("Failed to write values for '{}': {}", name, e))?;
+        }
+
+        Ok(())
+    }
 }
 
 // Stub implementation when feature is not enabled
@@ -203,6 +272,20 @@ impl HDF5Writer {
     {
         Err("HDF5 support not enabled. Compile with --features with-hdf5".to_string())
     }
+
+    pub fn write_monte_carlo<P>(_results: &crate::analysis::MonteCarloResults, _path: P) -> Result<(), String>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        Err("HDF5 support not enabled. Compile with --features with-hdf5".to_string())
+    }
+
+    pub fn write_agents<P>(_snapshots: &crate::simulation::AgentSnapshots, _path: P) -> Result<(), String>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        Err("HDF5 support not enabled. Compile with --features with-hdf5".to_string())
+    }
 }
 
 #[cfg(all(test, feature = "with-hdf5"))]
@@ -260,4 +343,30 @@ mod tests {
 
         let _ = std::fs::remove_file(temp_file);
     }
+
+    #[test]
+    fn test_hdf5_write_agents() {
+        use hdf5::types::VarLenUnicode;
+
+        let snapshots = AgentSnapshots {
+            time: vec![0.0, 1.0],
+            agent_type: vec!["Firm".to_string(), "Household".to_string()],
+            agent_id: vec![1, 2],
+            state: vec!["open".to_string(), String::new()],
+            attributes: vec!["wealth".to_string()],
+            values: vec![vec![10.0, 2.5]],
+        };
+        let temp_file = std::env::temp_dir().join(format!("rssdsim_agents_{}.h5", std::process::id()));
+        HDF5Writer::write_agents(&snapshots, &temp_file).unwrap();
+
+        let file = File::open(&temp_file).unwrap();
+        assert_eq!(file.dataset("time").unwrap().read_raw::<f64>().unwrap(), vec![0.0, 1.0]);
+        assert_eq!(file.dataset("agent_id").unwrap().read_raw::<u64>().unwrap(), vec![1, 2]);
+        let agent_type: Vec<String> = file.dataset("agent_type").unwrap().read_raw::<VarLenUnicode>().unwrap()
+            .iter().map(|value| value.as_str().to_string()).collect();
+        assert_eq!(agent_type, ["Firm", "Household"]);
+        let wealth = file.group("attributes").unwrap().dataset("wealth").unwrap().read_raw::<f64>().unwrap();
+        assert_eq!(wealth, vec![10.0, 2.5]);
+        let _ = std::fs::remove_file(temp_file);
+    }
 }
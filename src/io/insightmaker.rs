@@ -1,11 +1,22 @@
 /// InsightMaker format parser
 ///
 /// InsightMaker uses a JSON-based format with a specific structure
-/// that differs from standard XMILE but contains similar SD concepts
-
-use serde::{Deserialize, Serialize};
+/// that differs from standard XMILE but contains similar SD concepts.
+/// Attributes may use InsightMaker's own names (`InitialValue`, `FlowRate`,
+/// `TimeLength`, ...) and, as in its exports, numbers and flags may be
+/// strings. Equations refer to primitives as `[Name]`. Ghosts stand for the
+/// primitive they copy and folders only group primitives. Converters with
+/// `Data` become lookup tables read at their `Source`, and settings `Macros`
+/// define constants (`k <- 0.5`) and functions (`sq(x) <- x^2`) that are
+/// expanded into the equations using them. Agent-based primitives are not
+/// supported and are reported as errors.
+
+use serde::{Deserialize, Deserializer, Serialize};
+use serde::de::Error as _;
 use std::collections::HashMap;
+use crate::error::ParseError;
 use crate::model::*;
+use crate::simulation::LookupTable;
 
 /// InsightMaker top-level structure
 #[derive(Debug, Deserialize, Serialize)]
@@ -22,17 +33,25 @@ pub struct InsightMakerModel {
 
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct InsightMakerSettings {
-    #[serde(default)]
+    #[serde(default, alias = "TimeStart", deserialize_with = "lenient_number")]
     pub start: f64,
 
-    #[serde(default = "default_stop")]
+    #[serde(default = "default_stop", deserialize_with = "lenient_number")]
     pub stop: f64,
 
-    #[serde(default = "default_dt")]
+    /// Run length, InsightMaker's alternative to `stop`
+    #[serde(default, alias = "TimeLength", deserialize_with = "lenient_optional_number")]
+    pub length: Option<f64>,
+
+    #[serde(default = "default_dt", alias = "TimeStep", deserialize_with = "lenient_number")]
     pub dt: f64,
 
-    #[serde(default)]
+    #[serde(default, alias = "TimeUnits")]
     pub time_units: Option<String>,
+
+    /// One definition per line, `name <- expression` or `name(x, y) <- expression`
+    #[serde(default, alias = "Macros")]
+    pub macros: Option<String>,
 }
 
 fn default_stop() -> f64 { 100.0 }
@@ -45,15 +64,17 @@ pub struct InsightMakerPrimitive {
     #[serde(rename = "type")]
     pub primitive_type: String,
 
+    /// Links and other connectors have no name
+    #[serde(default)]
     pub name: String,
 
-    #[serde(default)]
+    #[serde(default, alias = "InitialValue", deserialize_with = "lenient_text")]
     pub value: Option<String>, // Initial value or equation
 
-    #[serde(default)]
+    #[serde(default, alias = "Equation", alias = "FlowRate", deserialize_with = "lenient_text")]
     pub equation: Option<String>,
 
-    #[serde(default)]
+    #[serde(default, alias = "Units")]
     pub units: Option<String>,
 
     #[serde(default)]
@@ -61,53 +82,308 @@ pub struct InsightMakerPrimitive {
 
     #[serde(default)]
     pub outflows: Vec<String>,
+
+    /// Stock a flow drains, by id
+    #[serde(default)]
+    pub source: Option<String>,
+
+    /// Stock a flow fills, by id
+    #[serde(default)]
+    pub target: Option<String>,
+
+    /// Input of a converter (a primitive id, or `Time`), or the primitive a ghost copies
+    #[serde(default, rename = "Source")]
+    pub input: Option<String>,
+
+    /// Converter points, `x,y;x,y;...`
+    #[serde(default, alias = "Data")]
+    pub data: Option<String>,
+
+    /// `Linear` or `Discrete` for converters
+    #[serde(default, alias = "Interpolation")]
+    pub interpolation: Option<String>,
+
+    /// Stocks kept from going below zero, or flows kept from reversing
+    #[serde(default, alias = "NonNegative", alias = "OnlyPositive", deserialize_with = "lenient_flag")]
+    pub non_negative: bool,
+}
+
+/// A string, or a number or flag InsightMaker would have written as one
+fn lenient_text<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    Ok(match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::String(text) => Some(text),
+        serde_json::Value::Number(number) => Some(number.to_string()),
+        serde_json::Value::Bool(flag) => Some(flag.to_string()),
+        _ => None,
+    })
+}
+
+fn lenient_optional_number<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f64>, D::Error> {
+    lenient_text(deserializer)?
+        .map(|text| text.trim().parse::<f64>().map_err(|_| D::Error::custom(format!("expected a number, found '{}'", text))))
+        .transpose()
+}
+
+fn lenient_number<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    lenient_optional_number(deserializer)?.ok_or_else(|| D::Error::custom("expected a number"))
+}
+
+fn lenient_flag<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    Ok(lenient_text(deserializer)?.is_some_and(|text| text.trim().eq_ignore_ascii_case("true")))
+}
+
+/// Primitives that only draw or group parts of the diagram
+const LAYOUT_TYPES: &[&str] = &["Folder", "Link", "Text", "Picture", "Button", "Display"];
+
+/// InsightMaker's time functions, each giving the time in the model's units
+const TIME_FUNCTIONS: &[&str] = &["Time", "Seconds", "Minutes", "Hours", "Days", "Weeks", "Months", "Years"];
+
+/// Units as the unit checker reads them; empty and `Unitless` mean none
+fn units(units: &Option<String>) -> Option<String> {
+    let units = units.as_deref()?.trim();
+    (!units.is_empty() && !units.eq_ignore_ascii_case("unitless")).then(|| units.to_string())
+}
+
+/// `[Birth Rate] * 2` -> `Birth Rate * 2`
+fn strip_brackets(equation: &str) -> String {
+    let mut output = String::new();
+    let mut rest = equation;
+    while let Some(open) = rest.find('[') {
+        let (before, after) = rest.split_at(open);
+        output.push_str(before);
+        // A bracket after a name or a closing bracket is a subscript
+        let subscript = before.trim_end().chars().last()
+            .is_some_and(|c| c.is_alphanumeric() || c == '_' || c == ']' || c == ')');
+        match after.find(']') {
+            Some(close) if !subscript => {
+                output.push_str(after[1..close].trim());
+                rest = &after[close + 1..];
+            }
+            _ => {
+                output.push('[');
+                rest = &after[1..];
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Function macros, by name, as their parameters and body
+struct Macros {
+    functions: HashMap<String, (Vec<String>, Expression)>,
+}
+
+impl Macros {
+    /// Parse macro definitions into the functions and the constants they define
+    fn parse(text: &str) -> Result<(Self, Vec<(String, Expression)>), ParseError> {
+        let mut functions = HashMap::new();
+        let mut constants = Vec::new();
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') || line.starts_with("//") {
+                continue;
+            }
+            let (head, body) = line.split_once("<-")
+                .ok_or_else(|| format!("Macro '{}' is not of the form 'name <- expression'", line))?;
+            let head = head.trim();
+            match head.split_once('(') {
+                Some((name, parameters)) => {
+                    let name = name.trim();
+                    let parameters = parameters.trim_end().strip_suffix(')')
+                        .ok_or_else(|| format!("Macro '{}' has an unclosed parameter list", name))?
+                        .split(',').map(|p| p.trim().to_string()).filter(|p| !p.is_empty()).collect();
+                    let body = Expression::parse_equation(name, &strip_brackets(body))?;
+                    functions.insert(name.to_string(), (parameters, body));
+                }
+                None => constants.push((head.to_string(), Expression::parse_equation(head, &strip_brackets(body))?)),
+            }
+        }
+        Ok((Self { functions }, constants))
+    }
+
+    /// Translate InsightMaker functions into the simulator's and expand macro calls
+    fn expand(&self, expr: &Expression, depth: usize) -> Result<Expression, String> {
+        let expand = |expr: &Expression| self.expand(expr, depth).map(Box::new);
+        Ok(match expr {
+            Expression::Constant(_) | Expression::Variable(_) | Expression::StringLiteral { .. }
+            | Expression::SubscriptedVariable { .. } => expr.clone(),
+            Expression::BinaryOp { op, left, right } => Expression::BinaryOp { op: *op, left: expand(left)?, right: expand(right)? },
+            Expression::UnaryOp { op, expr } => Expression::UnaryOp { op: *op, expr: expand(expr)? },
+            Expression::Conditional { condition, true_expr, false_expr } => Expression::Conditional {
+                condition: expand(condition)?,
+                true_expr: expand(true_expr)?,
+                false_expr: expand(false_expr)?,
+            },
+            Expression::FunctionCall { name, args, .. } => {
+                let args = args.iter().map(|arg| self.expand(arg, depth)).collect::<Result<Vec<_>, _>>()?;
+                let call = |name: &str, args: Vec<Expression>| Expression::FunctionCall { name: name.to_string(), args, site: None };
+                if let Some((parameters, body)) = self.functions.get(name) {
+                    if parameters.len() != args.len() {
+                        return Err(format!("macro '{}' expects {} arguments, got {}", name, parameters.len(), args.len()));
+                    }
+                    if depth >= 32 {
+                        return Err(format!("macro '{}' calls itself without end", name));
+                    }
+                    let bindings: HashMap<&str, &Expression> = parameters.iter().map(String::as_str).zip(&args).collect();
+                    return self.expand(&substitute(body, &bindings), depth + 1);
+                }
+                match (name.as_str(), args.len()) {
+                    ("IfThenElse", 3) => {
+                        let mut args = args.into_iter();
+                        let mut next = || Box::new(args.next().unwrap_or(Expression::Constant(0.0)));
+                        Expression::Conditional { condition: next(), true_expr: next(), false_expr: next() }
+                    }
+                    (name, 0) if TIME_FUNCTIONS.contains(&name) => call("TIME", args),
+                    ("Rand", 0) => call("RANDOM", args),
+                    ("Rand", 2) => call("UNIFORM", args),
+                    ("RandNormal", 2) => call("NORMAL", args),
+                    _ => call(name, args),
+                }
+            }
+        })
+    }
 }
 
-pub fn parse_insightmaker(json: &str) -> Result<Model, String> {
+/// `body` with its parameters replaced by the arguments of a call
+fn substitute(body: &Expression, bindings: &HashMap<&str, &Expression>) -> Expression {
+    let boxed = |expr: &Expression| Box::new(substitute(expr, bindings));
+    match body {
+        Expression::Variable(name) => bindings.get(name.as_str()).map_or_else(|| body.clone(), |arg| (*arg).clone()),
+        Expression::Constant(_) | Expression::StringLiteral { .. } | Expression::SubscriptedVariable { .. } => body.clone(),
+        Expression::BinaryOp { op, left, right } => Expression::BinaryOp { op: *op, left: boxed(left), right: boxed(right) },
+        Expression::UnaryOp { op, expr } => Expression::UnaryOp { op: *op, expr: boxed(expr) },
+        Expression::FunctionCall { name, args, .. } => Expression::FunctionCall {
+            name: name.clone(),
+            args: args.iter().map(|arg| substitute(arg, bindings)).collect(),
+            site: None,
+        },
+        Expression::Conditional { condition, true_expr, false_expr } => Expression::Conditional {
+            condition: boxed(condition),
+            true_expr: boxed(true_expr),
+            false_expr: boxed(false_expr),
+        },
+    }
+}
+
+/// Converter points written as `x,y;x,y;...`
+fn parse_points(name: &str, data: &str) -> Result<Vec<(f64, f64)>, String> {
+    let mut points = data.split(';')
+        .map(str::trim)
+        .filter(|point| !point.is_empty())
+        .map(|point| {
+            let (x, y) = point.split_once(',')
+                .ok_or_else(|| format!("Converter '{}': point '{}' is not 'x,y'", name, point))?;
+            let number = |text: &str| text.trim().parse::<f64>()
+                .map_err(|_| format!("Converter '{}': '{}' is not a number", name, text.trim()));
+            Ok((number(x)?, number(y)?))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    points.sort_by(|a, b| a.0.total_cmp(&b.0));
+    Ok(points)
+}
+
+pub fn parse_insightmaker(json: &str) -> Result<Model, ParseError> {
     let im_model: InsightMakerModel = serde_json::from_str(json)
         .map_err(|e| format!("Failed to parse InsightMaker JSON: {}", e))?;
 
     let mut model = Model::new(&im_model.name);
 
     // Set time configuration
-    model.time.start = im_model.settings.start;
-    model.time.stop = im_model.settings.stop;
-    model.time.dt = im_model.settings.dt;
-    model.time.units = im_model.settings.time_units;
+    let settings = &im_model.settings;
+    model.time.start = settings.start;
+    model.time.stop = settings.length.map_or(settings.stop, |length| settings.start + length);
+    model.time.dt = settings.dt;
+    model.time.units = units(&settings.time_units);
+
+    let (macros, constants) = Macros::parse(settings.macros.as_deref().unwrap_or(""))?;
+    let equation = |name: &str, text: &str| -> Result<Expression, ParseError> {
+        let parsed = Expression::parse_equation(name, &strip_brackets(text))?;
+        Ok(macros.expand(&parsed, 0).map_err(|e| format!("Equation for '{}': {}", name, e))?)
+    };
+
+    for prim in &im_model.primitives {
+        match prim.primitive_type.as_str() {
+            "Stock" | "Flow" | "Variable" | "Converter" | "Parameter" | "Constant" | "Ghost" => {}
+            kind if LAYOUT_TYPES.contains(&kind) => {}
+            kind => return Err(format!("InsightMaker {} primitives are not supported ('{}')", kind, prim.name).into()),
+        }
+    }
 
-    // Build ID to name mapping for references
+    // Build ID to name mapping for references; a ghost has the name of
+    // the primitive it copies
     let mut id_to_name: HashMap<String, String> = HashMap::new();
     for prim in &im_model.primitives {
-        id_to_name.insert(prim.id.clone(), prim.name.clone());
+        if prim.primitive_type != "Ghost" {
+            id_to_name.insert(prim.id.clone(), prim.name.clone());
+        }
+    }
+    for ghost in im_model.primitives.iter().filter(|prim| prim.primitive_type == "Ghost") {
+        let original = ghost.input.as_ref().and_then(|id| id_to_name.get(id))
+            .ok_or_else(|| format!("Ghost '{}' copies a primitive that does not exist", ghost.id))?;
+        id_to_name.insert(ghost.id.clone(), original.clone());
     }
 
-    // First pass: collect parameters and constants
-    let mut params = Vec::new();
+    // Macro constants, then variables: plain numbers become parameters
+    for (name, expr) in constants {
+        match expr {
+            Expression::Constant(value) => model.add_parameter(Parameter::new(&name, value))?,
+            expr => {
+                let expr = macros.expand(&expr, 0).map_err(|e| format!("Macro '{}': {}", name, e))?;
+                model.add_auxiliary(Auxiliary::new(&name, "0").with_equation(expr))?
+            }
+        }
+    }
     for prim in &im_model.primitives {
-        match prim.primitive_type.as_str() {
-            "Variable" | "Constant" | "Parameter" => {
-                if let Some(ref val_str) = prim.value {
-                    if let Ok(val) = val_str.parse::<f64>() {
-                        params.push((prim.name.clone(), val, prim.units.clone()));
-                    }
+        let text = prim.equation.as_ref().or(prim.value.as_ref());
+        match (prim.primitive_type.as_str(), text) {
+            ("Variable" | "Converter" | "Parameter" | "Constant", Some(text)) if prim.data.is_none() => {
+                if let Ok(value) = text.trim().parse::<f64>() {
+                    let mut param = Parameter::new(&prim.name, value);
+                    param.units = units(&prim.units);
+                    model.add_parameter(param)?;
+                } else {
+                    let aux = Auxiliary {
+                        name: prim.name.clone(),
+                        equation: equation(&prim.name, text)?,
+                        units: units(&prim.units),
+                        dimensions: None,
+                    };
+                    model.add_auxiliary(aux)?;
                 }
             }
             _ => {}
         }
     }
 
-    // Add parameters
-    for (name, value, units) in params {
-        let param = Parameter {
-            name: name.clone(),
-            value,
-            units,
-            description: None,
+    // Converters: graphical functions of their source
+    for prim in im_model.primitives.iter().filter(|prim| prim.primitive_type == "Converter") {
+        let Some(data) = &prim.data else { continue };
+        let interpolation = match prim.interpolation.as_deref().unwrap_or("Linear") {
+            "Linear" => Interpolation::Linear,
+            "Discrete" => Interpolation::Step,
+            other => return Err(format!("Converter '{}' has unknown interpolation '{}'", prim.name, other).into()),
+        };
+        let input = match prim.input.as_deref() {
+            None | Some("Time") => Expression::FunctionCall { name: "TIME".to_string(), args: Vec::new(), site: None },
+            Some(id) => Expression::Variable(id_to_name.get(id).cloned()
+                .ok_or_else(|| format!("Converter '{}' reads a primitive that does not exist", prim.name))?),
         };
-        model.add_parameter(param)?;
+        let table = format!("{}_lookup", prim.name);
+        model.add_lookup(LookupTable::new(table.clone(), parse_points(&prim.name, data)?)?.with_interpolation(interpolation))?;
+        let aux = Auxiliary {
+            name: prim.name.clone(),
+            equation: Expression::FunctionCall {
+                name: "LOOKUP".to_string(),
+                args: vec![Expression::StringLiteral { literal: table }, input],
+                site: None,
+            },
+            units: units(&prim.units),
+            dimensions: None,
+        };
+        model.add_auxiliary(aux)?;
     }
 
-    // Second pass: add stocks
+    // Stocks, connected through their own flow lists or the flows' ends
     for prim in &im_model.primitives {
         if prim.primitive_type == "Stock" {
             let initial_expr = if let Some(ref eq) = prim.equation {
@@ -118,31 +394,37 @@ pub fn parse_insightmaker(json: &str) -> Result<Model, String> {
                 "0".to_string()
             };
 
-            // Replace IDs with names in flow references
-            let inflows: Vec<String> = prim.inflows.iter()
-                .filter_map(|id| id_to_name.get(id).cloned())
-                .collect();
-
-            let outflows: Vec<String> = prim.outflows.iter()
-                .filter_map(|id| id_to_name.get(id).cloned())
-                .collect();
+            let connected = |own: &[String], end: fn(&InsightMakerPrimitive) -> &Option<String>| -> Vec<String> {
+                let ends_here = im_model.primitives.iter()
+                    .filter(|flow| flow.primitive_type == "Flow")
+                    .filter(|flow| end(flow).as_ref().and_then(|id| id_to_name.get(id)) == Some(&prim.name))
+                    .map(|flow| &flow.id);
+                let mut names: Vec<String> = Vec::new();
+                for name in own.iter().chain(ends_here).filter_map(|id| id_to_name.get(id)) {
+                    if !names.contains(name) {
+                        names.push(name.clone());
+                    }
+                }
+                names
+            };
 
             let stock = Stock {
                 name: prim.name.clone(),
-                initial: Expression::parse(&initial_expr)?,
-                inflows,
-                outflows,
-                units: prim.units.clone(),
-                non_negative: false,
+                initial: equation(&prim.name, &initial_expr)?,
+                inflows: connected(&prim.inflows, |flow| &flow.target),
+                outflows: connected(&prim.outflows, |flow| &flow.source),
+                units: units(&prim.units),
+                non_negative: prim.non_negative,
                 max_value: None,
                 dimensions: None,
+                kind: StockKind::Reservoir,
             };
 
             model.add_stock(stock)?;
         }
     }
 
-    // Third pass: add flows
+    // Flows
     for prim in &im_model.primitives {
         if prim.primitive_type == "Flow" {
             let eq = if let Some(ref equation) = prim.equation {
@@ -155,43 +437,24 @@ pub fn parse_insightmaker(json: &str) -> Result<Model, String> {
 
             let flow = Flow {
                 name: prim.name.clone(),
-                equation: Expression::parse(&eq)?,
-                units: prim.units.clone(),
+                equation: equation(&prim.name, &eq)?,
+                units: units(&prim.units),
+                dimensions: None,
+                leak: false,
+                non_negative: prim.non_negative,
             };
 
             model.add_flow(flow)?;
         }
     }
 
-    // Fourth pass: add auxiliaries (converters/variables)
-    for prim in &im_model.primitives {
-        match prim.primitive_type.as_str() {
-            "Converter" | "Variable" => {
-                // Skip if already added as parameter
-                if model.parameters.contains_key(&prim.name) {
-                    continue;
-                }
-
-                if let Some(ref eq) = prim.equation {
-                    let aux = Auxiliary {
-                        name: prim.name.clone(),
-                        equation: Expression::parse(eq)?,
-                        units: prim.units.clone(),
-                    };
-
-                    model.add_auxiliary(aux)?;
-                }
-            }
-            _ => {}
-        }
-    }
-
     Ok(model)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::simulation::{SimulationConfig, SimulationEngine};
 
     #[test]
     fn test_parse_insightmaker_simple() {
@@ -233,4 +496,39 @@ mod tests {
         assert_eq!(model.flows.len(), 1);
         assert_eq!(model.parameters.len(), 1);
     }
+
+    #[test]
+    fn test_parse_insightmaker_fixtures() {
+        let fixture = |name: &str| crate::io::load_model(format!("tests/fixtures/insightmaker/{}", name)).unwrap();
+        let run = |model: Model| SimulationEngine::new(model, SimulationConfig::default()).unwrap().run().unwrap();
+
+        // Settings given as InsightMaker writes them, with string numbers and a run length
+        let settings = fixture("settings_units.json");
+        assert_eq!((settings.time.start, settings.time.stop, settings.time.dt), (2000.0, 2010.0, 0.5));
+        assert_eq!(settings.time.units.as_deref(), Some("Years"));
+        assert_eq!(settings.stocks["Trees"].units.as_deref(), Some("Trees"));
+        assert!(settings.stocks["Trees"].non_negative && settings.flows["Logging"].non_negative);
+        assert_eq!(settings.parameters["Growth Rate"].units, None);
+        assert_eq!(settings.stocks["Trees"].outflows, vec!["Logging".to_string()]);
+
+        // A converter read at the time, and another reading a ghost of a folder's stock
+        let converters = fixture("converters_ghosts.json");
+        assert_eq!(converters.lookups["Rainfall_lookup"].lookup(2.0).unwrap(), 10.0);
+        assert_eq!(converters.auxiliaries["Runoff"].equation.to_string(), "LOOKUP(\"Runoff_lookup\", Soil Water)");
+        assert_eq!(converters.stocks["Soil Water"].inflows, vec!["Infiltration".to_string()]);
+        let runoff = run(converters).final_value("Runoff").unwrap();
+        assert!(runoff > 0.0 && runoff < 1.0, "{}", runoff);
+
+        // Macro constants and functions expanded into equations
+        let macros = fixture("macros.json");
+        assert_eq!(macros.parameters["capacity"].value, 500.0);
+        assert_eq!(macros.flows["Growth"].equation.to_string(),
+            "((rate * Population) * (1 - (Population / capacity)))");
+        assert!(run(macros).final_value("Population").unwrap() < 500.0);
+
+        let agents = crate::io::load_model("tests/fixtures/insightmaker/agents.json").unwrap_err();
+        assert!(agents.to_string().contains("Agent primitives are not supported"), "{}", agents);
+        // Native JSON models are not mistaken for InsightMaker ones
+        assert!(!crate::io::load_model("examples/bank_account.json").unwrap().stocks.is_empty());
+    }
 }
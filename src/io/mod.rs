@@ -1,7 +1,8 @@
 /// I/O module - model and results serialization
 
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use crate::error::{IoError, ParseError, Result};
 use crate::model::Model;
 use crate::simulation::SimulationResults;
 
@@ -9,60 +10,129 @@ pub mod parser;
 pub mod writer;
 pub mod xmile;
 pub mod insightmaker;
+pub mod vensim;
 pub mod netcdf_writer;
 pub mod hdf5_writer;
+pub mod parquet_writer;
+pub mod ranges;
+pub mod data;
+pub mod xlsx_reader;
+pub mod scenarios;
+pub mod modules;
+pub mod patch;
 
 pub use parser::ModelParser;
-pub use writer::ResultWriter;
-pub use netcdf_writer::NetCDFWriter;
+pub use writer::{ResultWriter, CsvSink};
+pub use netcdf_writer::{NetCDFWriter, NetCDFSink};
 pub use hdf5_writer::HDF5Writer;
+pub use parquet_writer::ParquetWriter;
+pub use ranges::{load_parameter_ranges, load_priors, load_decisions};
+pub use data::load_data;
+pub use scenarios::load_scenarios;
+pub use patch::load_patch;
 
 /// Load model from file (auto-detect format)
-pub fn load_model<P: AsRef<Path>>(path: P) -> Result<Model, String> {
-    let path = path.as_ref();
+///
+/// Modules, spreadsheet inputs (GET_XLS_* equations) and data variables are
+/// read from their files, relative to the model's directory.
+pub fn load_model<P: AsRef<Path>>(path: P) -> Result<Model> {
+    load_module(path.as_ref(), &mut Vec::new())
+}
+
+/// Load the model at `path`, included by the files being loaded in `loading`
+fn load_module(path: &Path, loading: &mut Vec<PathBuf>) -> Result<Model> {
     let contents = fs::read_to_string(path)
-        .map_err(|e| format!("Failed to read file: {}", e))?;
+        .map_err(|source| IoError::Read { path: path.to_path_buf(), source })?;
+    let in_file = |error: ParseError| error.in_file(path);
+    let mut model = parse_model_file(path, &contents).map_err(in_file)?;
+    if !model.modules.is_empty() {
+        loading.push(path.canonicalize().unwrap_or_else(|_| path.to_path_buf()));
+        let resolved = modules::resolve(&mut model, path, loading);
+        loading.pop();
+        resolved?;
+    }
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+    xlsx_reader::resolve(&mut model, base_dir).map_err(|e| in_file(e.into()))?;
+    if !model.data.is_empty() {
+        load_data(&mut model, base_dir).map_err(|e| in_file(e.into()))?;
+    }
+    Ok(model)
+}
 
+fn parse_model_file(path: &Path, contents: &str) -> Result<Model, ParseError> {
     let extension = path.extension()
         .and_then(|s| s.to_str())
         .ok_or("No file extension")?;
 
     match extension {
-        "json" => {
-            // Try InsightMaker format first, fall back to standard JSON
-            if let Ok(model) = insightmaker::parse_insightmaker(&contents) {
-                return Ok(model);
-            }
-
-            let json_model: parser::JsonModel = serde_json::from_str(&contents)
-                .map_err(|e| format!("Failed to parse JSON: {}", e))?;
-            parser::JsonModel::to_model(json_model)
-        }
+        "json" => parse_json(contents),
         "yaml" | "yml" => {
-            parser::parse_yaml(&contents)
+            parser::parse_yaml(contents)
         }
         "xmile" | "stmx" | "itmx" | "xml" => {
-            xmile::parse_xmile(&contents)
+            xmile::parse_xmile(contents)
         }
-        _ => Err(format!("Unsupported file format: {}", extension)),
+        "mdl" => {
+            let mut model = vensim::parse_vensim(contents)?;
+            if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
+                model.metadata.name = stem.to_string();
+            }
+            Ok(model)
+        }
+        _ => Err(format!("Unsupported file format: {}", extension).into()),
+    }
+}
+
+/// Parse a model given as JSON, in InsightMaker or the native format
+pub fn parse_json(contents: &str) -> Result<Model, ParseError> {
+    let document: serde_json::Value = serde_json::from_str(contents)
+        .map_err(|e| format!("Failed to parse JSON: {}", e))?;
+    // InsightMaker models list primitives; native ones have a `model` section
+    if document.get("model").is_none() && document.get("primitives").is_some() {
+        return insightmaker::parse_insightmaker(contents);
     }
+
+    let json_model: parser::JsonModel = serde_json::from_value(document)
+        .map_err(|e| format!("Failed to parse JSON: {}", e))?;
+    parser::JsonModel::to_model(json_model)
+}
+
+/// Export model to file (format chosen by extension)
+pub fn export_model<P: AsRef<Path>>(model: &Model, path: P) -> Result<(), IoError> {
+    let path = path.as_ref();
+    let extension = path.extension()
+        .and_then(|s| s.to_str())
+        .ok_or_else(|| IoError::Output("No file extension".to_string()))?;
+
+    let contents = match extension {
+        "mdl" => vensim::write_vensim(model).map_err(IoError::Output)?,
+        _ => return Err(IoError::Output(format!("Unsupported export format: {} (supported: mdl)", extension))),
+    };
+
+    fs::write(path, contents).map_err(|source| IoError::Write { path: path.to_path_buf(), source })
 }
 
 /// Write results to CSV file
-pub fn write_csv<P: AsRef<Path>>(results: &SimulationResults, path: P) -> Result<(), String> {
-    writer::CsvWriter::write_file(results, path)
+pub fn write_csv<P: AsRef<Path>>(results: &SimulationResults, path: P) -> Result<(), IoError> {
+    writer::CsvWriter::write_file(results, path).map_err(IoError::Output)
 }
 
 /// Write results to NetCDF file
 #[cfg(feature = "with-netcdf")]
-pub fn write_netcdf<P: AsRef<Path>>(results: &SimulationResults, path: P) -> Result<(), String> {
-    netcdf_writer::NetCDFWriter::write(results, path)
+pub fn write_netcdf<P: AsRef<Path>>(results: &SimulationResults, path: P) -> Result<(), IoError> {
+    netcdf_writer::NetCDFWriter::write(results, path).map_err(IoError::Output)
+}
+
+/// Write results to Parquet file
+#[cfg(feature = "with-parquet")]
+pub fn write_parquet<P: AsRef<Path>>(results: &SimulationResults, path: P) -> Result<(), IoError> {
+    parquet_writer::ParquetWriter::write(results, path).map_err(IoError::Output)
 }
 
 /// Write results to HDF5 file
 #[cfg(feature = "with-hdf5")]
-pub fn write_hdf5<P: AsRef<Path>>(results: &SimulationResults, path: P) -> Result<(), String> {
-    hdf5_writer::HDF5Writer::write(results, path)
+pub fn write_hdf5<P: AsRef<Path>>(results: &SimulationResults, path: P) -> Result<(), IoError> {
+    hdf5_writer::HDF5Writer::write(results, path).map_err(IoError::Output)
 }
 
 /// Write results to HDF5 file with compression
@@ -71,6 +141,6 @@ pub fn write_hdf5_compressed<P: AsRef<Path>>(
     results: &SimulationResults,
     path: P,
     compression_level: u8,
-) -> Result<(), String> {
-    hdf5_writer::HDF5Writer::write_compressed(results, path, compression_level)
+) -> Result<(), IoError> {
+    hdf5_writer::HDF5Writer::write_compressed(results, path, compression_level).map_err(IoError::Output)
 }
@@ -0,0 +1,266 @@
+/// Flattening of modules into the models that include them
+///
+/// Each module's file is loaded with its own modules, spreadsheet inputs and
+/// data, then its elements are added to the including model under the
+/// module's namespace. A flattened name the including model already uses is
+/// an error, as is a module that includes itself. Behavior tests of a module
+/// are left out; agents and discrete-event elements cannot come from one.
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use crate::error::{ParseError, Result};
+use crate::model::{Auxiliary, EventAction, EventTrigger, Expression, Model, StockKind};
+
+/// Flatten every module of `model`, loaded from `path`; `loading` holds the
+/// files being loaded, this one included
+pub(crate) fn resolve(model: &mut Model, path: &Path, loading: &mut Vec<PathBuf>) -> Result<()> {
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+    let in_file = |message: String| ParseError::from(message).in_file(path);
+    for module in std::mem::take(&mut model.modules) {
+        let file = base_dir.join(&module.file);
+        let canonical = file.canonicalize()
+            .map_err(|e| in_file(format!("Module '{}': cannot read {}: {}", module.name, file.display(), e)))?;
+        if loading.contains(&canonical) {
+            return Err(in_file(format!("Module '{}' includes {}, which includes it", module.name, file.display())).into());
+        }
+        let submodel = super::load_module(&file, loading)?;
+        flatten(model, &module.name, &module.inputs, &module.outputs, submodel)
+            .map_err(|e| in_file(format!("Module '{}': {}", module.name, e)))?;
+    }
+    Ok(())
+}
+
+/// Every name a model gives its variables, lookup tables and data
+fn element_names(model: &Model) -> impl Iterator<Item = &String> {
+    model.stocks.keys()
+        .chain(model.flows.keys())
+        .chain(model.auxiliaries.keys())
+        .chain(model.parameters.keys())
+        .chain(model.lookups.keys())
+        .chain(model.lookups_2d.keys())
+        .chain(model.data.keys())
+}
+
+/// Add the elements of `submodel` to `model` under `namespace`
+///
+/// `inputs` gives equations in `model` for parameters or auxiliaries of
+/// `submodel`; `outputs` adds auxiliaries to `model` reading variables of
+/// `submodel`, by auxiliary name.
+pub fn flatten(
+    model: &mut Model,
+    namespace: &str,
+    inputs: &BTreeMap<String, String>,
+    outputs: &BTreeMap<String, String>,
+    mut submodel: Model,
+) -> Result<(), String> {
+    if submodel.hybrid.is_some() || submodel.des.is_some() {
+        return Err("agents and discrete-event elements cannot be included".to_string());
+    }
+    let qualify = |name: &str| format!("{}.{}", namespace, name);
+
+    let local: HashSet<String> = element_names(&submodel).cloned().collect();
+    let existing: HashSet<&String> = element_names(model).collect();
+    let mut names: Vec<&String> = local.iter().collect();
+    names.sort();
+    if let Some(name) = names.into_iter().map(|name| qualify(name)).find(|name| existing.contains(name)) {
+        return Err(format!("'{}' is already defined", name));
+    }
+    for (name, variable) in outputs {
+        if existing.contains(name) || name.starts_with(&qualify("")) {
+            return Err(format!("output '{}' is already defined", name));
+        }
+        if !local.contains(variable) {
+            return Err(format!("output '{}' reads undefined variable '{}'", name, variable));
+        }
+    }
+
+    let tables: HashSet<String> = submodel.lookups.keys().cloned().collect();
+    let rename = |name: &str| local.contains(name).then(|| qualify(name));
+    let rename_table = |name: &str| tables.contains(name).then(|| qualify(name));
+    let renamed = |expr: &Expression| expr.rename_variables(&rename, &rename_table);
+    let renamed_name = |name: &String| rename(name).unwrap_or_else(|| name.clone());
+
+    // An input takes the place of the module's own parameter or auxiliary
+    for (port, equation) in inputs {
+        let (units, dimensions) = match (submodel.parameters.remove(port), submodel.auxiliaries.remove(port)) {
+            (Some(parameter), _) => (parameter.units, parameter.dimensions),
+            (None, Some(aux)) => (aux.units, aux.dimensions),
+            (None, None) => return Err(format!("there is no parameter or auxiliary '{}' to take as an input", port)),
+        };
+        let name = qualify(port);
+        let equation = Expression::parse_equation(&name, equation).map_err(|e| e.to_string())?;
+        let mut input = Auxiliary::new(&name, "0").with_equation(equation);
+        input.units = units;
+        input.dimensions = dimensions;
+        model.add_auxiliary(input).map_err(|e| e.to_string())?;
+    }
+
+    for (_, mut stock) in submodel.stocks {
+        stock.name = qualify(&stock.name);
+        stock.initial = renamed(&stock.initial);
+        stock.inflows = stock.inflows.iter().map(renamed_name).collect();
+        stock.outflows = stock.outflows.iter().map(renamed_name).collect();
+        stock.kind = match stock.kind {
+            StockKind::Reservoir => StockKind::Reservoir,
+            StockKind::Conveyor { transit_time, capacity } => StockKind::Conveyor {
+                transit_time: renamed(&transit_time),
+                capacity: capacity.as_ref().map(renamed),
+            },
+            StockKind::Oven { cook_time, capacity, fill_time } => StockKind::Oven {
+                cook_time: renamed(&cook_time),
+                capacity: capacity.as_ref().map(renamed),
+                fill_time: fill_time.as_ref().map(renamed),
+            },
+        };
+        model.add_stock(stock).map_err(|e| e.to_string())?;
+    }
+    for (_, mut flow) in submodel.flows {
+        flow.name = qualify(&flow.name);
+        flow.equation = renamed(&flow.equation);
+        model.add_flow(flow).map_err(|e| e.to_string())?;
+    }
+    for (_, mut aux) in submodel.auxiliaries {
+        aux.name = qualify(&aux.name);
+        aux.equation = renamed(&aux.equation);
+        model.add_auxiliary(aux).map_err(|e| e.to_string())?;
+    }
+    for (_, mut parameter) in submodel.parameters {
+        parameter.name = qualify(&parameter.name);
+        model.add_parameter(parameter).map_err(|e| e.to_string())?;
+    }
+    for (_, mut lookup) in submodel.lookups {
+        lookup.name = qualify(&lookup.name);
+        model.add_lookup(lookup).map_err(|e| e.to_string())?;
+    }
+    for (_, mut lookup) in submodel.lookups_2d {
+        lookup.name = qualify(&lookup.name);
+        model.add_lookup_2d(lookup).map_err(|e| e.to_string())?;
+    }
+    for (_, mut data) in submodel.data {
+        data.name = qualify(&data.name);
+        model.add_data(data).map_err(|e| e.to_string())?;
+    }
+
+    // Dimensions are shared, so one defined in both must have the same elements
+    for (name, dimension) in submodel.dimensions {
+        match model.dimensions.get(&name) {
+            Some(existing) if existing.elements != dimension.elements => {
+                return Err(format!("dimension '{}' has different elements", name));
+            }
+            Some(_) => {}
+            None => model.add_dimension(dimension).map_err(|e| e.to_string())?,
+        }
+    }
+
+    for mut event in submodel.events {
+        event.name = qualify(&event.name);
+        if let EventTrigger::When(condition) = &event.trigger {
+            event.trigger = EventTrigger::When(renamed(condition));
+        }
+        for action in &mut event.actions {
+            *action = match action {
+                EventAction::Set { variable, value } => EventAction::Set { variable: renamed_name(variable), value: renamed(value) },
+                EventAction::Add { stock, amount } => EventAction::Add { stock: renamed_name(stock), amount: renamed(amount) },
+            };
+        }
+        model.add_event(event).map_err(|e| e.to_string())?;
+    }
+    for mut constraint in submodel.constraints {
+        constraint.name = qualify(&constraint.name);
+        constraint.condition = renamed(&constraint.condition);
+        model.add_constraint(constraint).map_err(|e| e.to_string())?;
+    }
+    for mut group in submodel.conservation {
+        group.name = qualify(&group.name);
+        group.stocks = group.stocks.iter().map(renamed_name).collect();
+        group.boundary = group.boundary.iter().map(renamed_name).collect();
+        model.add_conserved_group(group).map_err(|e| e.to_string())?;
+    }
+
+    for (name, variable) in outputs {
+        let output = Auxiliary::new(name, "0").with_equation(Expression::Variable(qualify(variable)));
+        model.add_auxiliary(output).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use crate::io::load_model;
+    use crate::simulation::{SimulationConfig, SimulationEngine};
+
+    #[test]
+    fn test_modules_are_flattened() {
+        let dir = std::env::temp_dir().join(format!("rssdsim_modules_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // The same tank twice, each filled at the rate the including model gives it
+        fs::write(dir.join("tank.yaml"), r#"
+model:
+  name: Tank
+  time: { start: 0, stop: 1, dt: 1 }
+  stocks:
+    - { name: Level, initial: 0, inflows: [filling] }
+  flows:
+    - { name: filling, equation: "rate" }
+  parameters:
+    - { name: rate, value: 1 }
+"#).unwrap();
+        fs::write(dir.join("plant.yaml"), r#"
+model:
+  name: Plant
+  time: { start: 0, stop: 4, dt: 1 }
+  parameters:
+    - { name: supply, value: 3 }
+  modules:
+    - { name: a, file: tank.yaml, inputs: { rate: supply }, outputs: { a_level: Level } }
+    - { name: b, file: tank.yaml, inputs: { rate: "a.Level / 2" } }
+"#).unwrap();
+        fs::write(dir.join("loop.yaml"), r#"
+model:
+  name: Loop
+  time: { start: 0, stop: 4, dt: 1 }
+  modules:
+    - { name: again, file: loop.yaml }
+"#).unwrap();
+
+        let model = load_model(dir.join("plant.yaml")).unwrap();
+        assert!(model.stocks.contains_key("a.Level") && model.flows.contains_key("b.filling"));
+        assert!(!model.parameters.contains_key("a.rate"));
+        let results = SimulationEngine::new(model, SimulationConfig::default()).unwrap().run().unwrap();
+        assert_eq!(results.get_variable_series("a.Level").unwrap(), vec![0.0, 3.0, 6.0, 9.0, 12.0]);
+        assert_eq!(results.get_variable_series("b.Level").unwrap(), vec![0.0, 0.0, 1.5, 4.5, 9.0]);
+
+        let error = load_model(dir.join("loop.yaml")).unwrap_err().to_string();
+        assert!(error.contains("includes"), "{}", error);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_module_lookup_calls_are_renamed() {
+        let dir = std::env::temp_dir().join(format!("rssdsim_module_lookup_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("effect.yaml"), r#"
+model:
+  name: Effect
+  time: { start: 0, stop: 1, dt: 1 }
+  auxiliaries:
+    - { name: effect, equation: "table(TIME)" }
+  lookups:
+    - { name: table, points: [[0, 1.0], [4, 3.0]] }
+"#).unwrap();
+        fs::write(dir.join("plant.yaml"), r#"
+model:
+  name: Plant
+  time: { start: 0, stop: 4, dt: 1 }
+  modules:
+    - { name: m, file: effect.yaml }
+"#).unwrap();
+
+        let model = load_model(dir.join("plant.yaml")).unwrap();
+        assert_eq!(model.auxiliaries["m.effect"].equation.to_string(), "m.table(TIME)");
+        let results = SimulationEngine::new(model, SimulationConfig::default()).unwrap().run().unwrap();
+        assert_eq!(results.get_variable_series("m.effect").unwrap(), vec![0.0, 1.0, 1.5, 2.0, 2.5]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -13,6 +13,10 @@ use netcdf::{create, types};
 use std::path::Path;
 #[cfg(feature = "with-netcdf")]
 use crate::simulation::SimulationResults;
+#[cfg(feature = "with-netcdf")]
+use crate::analysis::MonteCarloResults;
+#[cfg(feature = "with-netcdf")]
+use crate::simulation::{ResultSink, SimulationState};
 
 #[cfg(feature = "with-netcdf")]
 pub struct NetCDFWriter;
@@ -28,8 +32,8 @@ impl NetCDFWriter {
             .map_err(|e| format!("Failed to create NetCDF file: {}", e))?;
 
         // Extract time series
-        let n_steps = results.states.len();
-        let time_values: Vec<f64> = results.states.iter().map(|s| s.time).collect();
+        let n_steps = results.len();
+        let time_values = &results.times;
 
         // Define dimensions
         file.add_dimension("time", n_steps)
@@ -48,17 +52,12 @@ impl NetCDFWriter {
             .map_err(|e| format!("Failed to add time long_name: {}", e))?;
 
         time_var
-            .put_values(&time_values, None, None)
+            .put_values(time_values, None, None)
             .map_err(|e| format!("Failed to write time values: {}", e))?;
 
         // Add stock variables
-        if !results.states.is_empty() {
-            for stock_name in results.states[0].stocks.keys() {
-                let values: Vec<f64> = results.states
-                    .iter()
-                    .map(|s| *s.stocks.get(stock_name).unwrap_or(&0.0))
-                    .collect();
-
+        if !results.is_empty() {
+            for (stock_name, values) in results.stocks.iter() {
                 let mut var = file
                     .add_variable::<f64>(stock_name, &["time"])
                     .map_err(|e| format!("Failed to add variable '{}': {}", stock_name, e))?;
@@ -68,17 +67,12 @@ impl NetCDFWriter {
                 var.add_attribute("variable_type", "stock")
                     .map_err(|e| format!("Failed to add attribute: {}", e))?;
 
-                var.put_values(&values, None, None)
+                var.put_values(values, None, None)
                     .map_err(|e| format!("Failed to write values for '{}': {}", stock_name, e))?;
             }
 
             // Add flow variables
-            for flow_name in results.states[0].flows.keys() {
-                let values: Vec<f64> = results.states
-                    .iter()
-                    .map(|s| *s.flows.get(flow_name).unwrap_or(&0.0))
-                    .collect();
-
+            for (flow_name, values) in results.flows.iter() {
                 let mut var = file
                     .add_variable::<f64>(flow_name, &["time"])
                     .map_err(|e| format!("Failed to add variable '{}': {}", flow_name, e))?;
@@ -88,17 +82,12 @@ impl NetCDFWriter {
                 var.add_attribute("variable_type", "flow")
                     .map_err(|e| format!("Failed to add attribute: {}", e))?;
 
-                var.put_values(&values, None, None)
+                var.put_values(values, None, None)
                     .map_err(|e| format!("Failed to write values for '{}': {}", flow_name, e))?;
             }
 
             // Add auxiliary variables
-            for aux_name in results.states[0].auxiliaries.keys() {
-                let values: Vec<f64> = results.states
-                    .iter()
-                    .map(|s| *s.auxiliaries.get(aux_name).unwrap_or(&0.0))
-                    .collect();
-
+            for (aux_name, values) in results.auxiliaries.iter() {
                 let mut var = file
                     .add_variable::<f64>(aux_name, &["time"])
                     .map_err(|e| format!("Failed to add variable '{}': {}", aux_name, e))?;
@@ -108,7 +97,7 @@ impl NetCDFWriter {
                 var.add_attribute("variable_type", "auxiliary")
                     .map_err(|e| format!("Failed to add attribute: {}", e))?;
 
-                var.put_values(&values, None, None)
+                var.put_values(values, None, None)
                     .map_err(|e| format!("Failed to write values for '{}': {}", aux_name, e))?;
             }
         }
@@ -121,6 +110,141 @@ impl NetCDFWriter {
 
         Ok(())
     }
+
+    /// Write Monte Carlo statistics to NetCDF file
+    ///
+    /// Each statistic is stored as `<variable>_<statistic>` over the time dimension.
+    pub fn write_monte_carlo<P: AsRef<Path>>(
+        results: &MonteCarloResults,
+        path: P,
+    ) -> Result<(), String> {
+        let mut file = create(path)
+            .map_err(|e| format!("Failed to create NetCDF file: {}", e))?;
+
+        file.add_dimension("time", results.time.len())
+            .map_err(|e| format!("Failed to add time dimension: {}", e))?;
+
+        let mut time_var = file
+            .add_variable::<f64>("time", &["time"])
+            .map_err(|e| format!("Failed to add time variable: {}", e))?;
+        time_var
+            .put_values(&results.time, None, None)
+            .map_err(|e| format!("Failed to write time values: {}", e))?;
+
+        let mut names: Vec<&String> = results.statistics.keys().collect();
+        names.sort();
+
+        for name in names {
+            for (statistic, values) in results.statistics[name].series() {
+                let var_name = format!("{}_{}", name, statistic);
+                let mut var = file
+                    .add_variable::<f64>(&var_name, &["time"])
+                    .map_err(|e| format!("Failed to add variable '{}': {}", var_name, e))?;
+
+                var.add_attribute("long_name", name.clone())
+                    .map_err(|e| format!("Failed to add attribute: {}", e))?;
+                var.add_attribute("statistic", statistic)
+                    .map_err(|e| format!("Failed to add attribute: {}", e))?;
+
+                var.put_values(values, None, None)
+                    .map_err(|e| format!("Failed to write values for '{}': {}", var_name, e))?;
+            }
+        }
+
+        file.add_attribute("title", "System Dynamics Monte Carlo Results")
+            .map_err(|e| format!("Failed to add global attribute: {}", e))?;
+        file.add_attribute("creator", "rssdsim")
+            .map_err(|e| format!("Failed to add global attribute: {}", e))?;
+        file.add_attribute("n_runs", results.n_runs as i64)
+            .map_err(|e| format!("Failed to add global attribute: {}", e))?;
+
+        Ok(())
+    }
+}
+
+/// Streaming NetCDF output along an unlimited time dimension
+///
+/// Variables are defined from the first recorded state; each later point
+/// appends one record, so only the current state is held in memory.
+#[cfg(feature = "with-netcdf")]
+pub struct NetCDFSink {
+    file: netcdf::MutableFile,
+    variables: Vec<(String, &'static str)>,
+    records: usize,
+}
+
+#[cfg(feature = "with-netcdf")]
+impl NetCDFSink {
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let mut file = create(path)
+            .map_err(|e| format!("Failed to create NetCDF file: {}", e))?;
+
+        file.add_unlimited_dimension("time")
+            .map_err(|e| format!("Failed to add time dimension: {}", e))?;
+        file.add_attribute("title", "System Dynamics Simulation Results")
+            .map_err(|e| format!("Failed to add global attribute: {}", e))?;
+        file.add_attribute("creator", "rssdsim")
+            .map_err(|e| format!("Failed to add global attribute: {}", e))?;
+
+        Ok(Self { file, variables: Vec::new(), records: 0 })
+    }
+
+    fn define_variables(&mut self, state: &SimulationState) -> Result<(), String> {
+        let mut time_var = self.file
+            .add_variable::<f64>("time", &["time"])
+            .map_err(|e| format!("Failed to add time variable: {}", e))?;
+        time_var
+            .add_attribute("long_name", "simulation time")
+            .map_err(|e| format!("Failed to add time long_name: {}", e))?;
+
+        for (names, variable_type) in [(&state.stocks, "stock"), (&state.flows, "flow"), (&state.auxiliaries, "auxiliary")] {
+            let mut names: Vec<&String> = names.keys().collect();
+            names.sort();
+            for name in names {
+                let mut var = self.file
+                    .add_variable::<f64>(name, &["time"])
+                    .map_err(|e| format!("Failed to add variable '{}': {}", name, e))?;
+                var.add_attribute("long_name", name.clone())
+                    .map_err(|e| format!("Failed to add attribute: {}", e))?;
+                var.add_attribute("variable_type", variable_type)
+                    .map_err(|e| format!("Failed to add attribute: {}", e))?;
+                self.variables.push((name.clone(), variable_type));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn put(&mut self, name: &str, value: f64) -> Result<(), String> {
+        let mut var = self.file.variable_mut(name)
+            .ok_or_else(|| format!("Variable '{}' not defined", name))?;
+        var.put_values(&[value], Some(&[self.records]), Some(&[1]))
+            .map_err(|e| format!("Failed to write values for '{}': {}", name, e))
+    }
+}
+
+#[cfg(feature = "with-netcdf")]
+impl ResultSink for NetCDFSink {
+    fn record(&mut self, time: f64, state: &SimulationState) -> Result<(), String> {
+        if self.records == 0 {
+            self.define_variables(state)?;
+        }
+
+        self.put("time", time)?;
+        for i in 0..self.variables.len() {
+            let (name, variable_type) = self.variables[i].clone();
+            let values = match variable_type {
+                "stock" => &state.stocks,
+                "flow" => &state.flows,
+                _ => &state.auxiliaries,
+            };
+            let value = *values.get(&name).unwrap_or(&0.0);
+            self.put(&name, value)?;
+        }
+
+        self.records += 1;
+        Ok(())
+    }
 }
 
 // Stub implementation when feature is not enabled
@@ -135,6 +259,33 @@ impl NetCDFWriter {
     {
         Err("NetCDF support not enabled. Compile with --features with-netcdf".to_string())
     }
+
+    pub fn write_monte_carlo<P>(_results: &crate::analysis::MonteCarloResults, _path: P) -> Result<(), String>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        Err("NetCDF support not enabled. Compile with --features with-netcdf".to_string())
+    }
+}
+
+#[cfg(not(feature = "with-netcdf"))]
+pub struct NetCDFSink;
+
+#[cfg(not(feature = "with-netcdf"))]
+impl NetCDFSink {
+    pub fn create<P>(_path: P) -> Result<Self, String>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        Err("NetCDF support not enabled. Compile with --features with-netcdf".to_string())
+    }
+}
+
+#[cfg(not(feature = "with-netcdf"))]
+impl crate::simulation::ResultSink for NetCDFSink {
+    fn record(&mut self, _time: f64, _state: &crate::simulation::SimulationState) -> Result<(), String> {
+        Err("NetCDF support not enabled. Compile with --features with-netcdf".to_string())
+    }
 }
 
 #[cfg(all(test, feature = "with-netcdf"))]
@@ -0,0 +1,258 @@
+/// Apache Parquet output writer for simulation results
+///
+/// Results are written in long (tidy) format with one row per observation:
+/// `time: f64, variable: utf8, value: f64, run_id: u64`. This loads directly
+/// into pandas (`pd.read_parquet`) or polars (`pl.read_parquet`) and pivots
+/// easily to wide format.
+
+#[cfg(feature = "with-parquet")]
+use arrow_array::{ArrayRef, Float64Array, RecordBatch, StringArray, UInt64Array};
+#[cfg(feature = "with-parquet")]
+use arrow_schema::{DataType, Field, Schema};
+#[cfg(feature = "with-parquet")]
+use parquet::arrow::ArrowWriter;
+#[cfg(feature = "with-parquet")]
+use parquet::basic::Compression;
+#[cfg(feature = "with-parquet")]
+use parquet::file::properties::WriterProperties;
+#[cfg(feature = "with-parquet")]
+use std::path::Path;
+#[cfg(feature = "with-parquet")]
+use std::sync::Arc;
+#[cfg(feature = "with-parquet")]
+use crate::simulation::SimulationResults;
+#[cfg(feature = "with-parquet")]
+use crate::analysis::MonteCarloResults;
+#[cfg(feature = "with-parquet")]
+use crate::simulation::AgentSnapshots;
+
+/// Column buffers for one long-format table
+#[cfg(feature = "with-parquet")]
+#[derive(Default)]
+struct LongTable {
+    time: Vec<f64>,
+    variable: Vec<String>,
+    value: Vec<f64>,
+    run_id: Vec<u64>,
+}
+
+#[cfg(feature = "with-parquet")]
+impl LongTable {
+    fn push_series(&mut self, times: &[f64], variable: &str, values: &[f64], run_id: u64) {
+        for (time, value) in times.iter().zip(values) {
+            self.time.push(*time);
+            self.variable.push(variable.to_string());
+            self.value.push(*value);
+            self.run_id.push(run_id);
+        }
+    }
+
+    fn into_batch(self) -> Result<RecordBatch, String> {
+        let schema = Schema::new(vec![
+            Field::new("time", DataType::Float64, false),
+            Field::new("variable", DataType::Utf8, false),
+            Field::new("value", DataType::Float64, false),
+            Field::new("run_id", DataType::UInt64, false),
+        ]);
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(Float64Array::from(self.time)),
+            Arc::new(StringArray::from(self.variable)),
+            Arc::new(Float64Array::from(self.value)),
+            Arc::new(UInt64Array::from(self.run_id)),
+        ];
+
+        RecordBatch::try_new(Arc::new(schema), columns)
+            .map_err(|e| format!("Failed to build record batch: {}", e))
+    }
+}
+
+#[cfg(feature = "with-parquet")]
+pub struct ParquetWriter;
+
+#[cfg(feature = "with-parquet")]
+impl ParquetWriter {
+    /// Write simulation results to a Parquet file (run_id is always 0)
+    pub fn write<P: AsRef<Path>>(
+        results: &SimulationResults,
+        path: P,
+    ) -> Result<(), String> {
+        let mut table = LongTable::default();
+
+        let mut columns: Vec<(&String, &[f64])> = results.columns().collect();
+        columns.sort_by_key(|(name, _)| *name);
+        for (name, values) in columns {
+            table.push_series(&results.times, name, values, 0);
+        }
+
+        Self::write_table(table, path)
+    }
+
+    /// Write every individual Monte Carlo run to a Parquet file
+    ///
+    /// Requires the results to have been produced with
+    /// `save_individual_runs` enabled.
+    pub fn write_monte_carlo<P: AsRef<Path>>(
+        results: &MonteCarloResults,
+        path: P,
+    ) -> Result<(), String> {
+        let runs = results.individual_runs.as_ref()
+            .ok_or("Monte Carlo results have no individual runs (enable save_individual_runs)")?;
+
+        let mut table = LongTable::default();
+        for (run_id, run) in runs.iter().enumerate() {
+            let mut names: Vec<&String> = run.keys().collect();
+            names.sort();
+            for name in names {
+                table.push_series(&results.time, name, &run[name], run_id as u64);
+            }
+        }
+
+        Self::write_table(table, path)
+    }
+
+    /// Write agent snapshots to a Parquet file, one row per agent and time:
+    /// `time, agent_type, agent_id, state`, then a column per attribute
+    pub fn write_agents<P: AsRef<Path>>(
+        snapshots: &AgentSnapshots,
+        path: P,
+    ) -> Result<(), String> {
+        let mut fields = vec![
+            Field::new("time", DataType::Float64, false),
+            Field::new("agent_type", DataType::Utf8, false),
+            Field::new("agent_id", DataType::UInt64, false),
+            Field::new("state", DataType::Utf8, false),
+        ];
+        let mut columns: Vec<ArrayRef> = vec![
+            Arc::new(Float64Array::from(snapshots.time.clone())),
+            Arc::new(StringArray::from(snapshots.agent_type.clone())),
+            Arc::new(UInt64Array::from(snapshots.agent_id.clone())),
+            Arc::new(StringArray::from(snapshots.state.clone())),
+        ];
+        for (name, values) in snapshots.attributes.iter().zip(&snapshots.values) {
+            fields.push(Field::new(name.as_str(), DataType::Float64, false));
+            columns.push(Arc::new(Float64Array::from(values.clone())));
+        }
+
+        let batch = RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)
+            .map_err(|e| format!("Failed to build record batch: {}", e))?;
+        Self::write_batch(batch, path)
+    }
+
+    fn write_table<P: AsRef<Path>>(table: LongTable, path: P) -> Result<(), String> {
+        Self::write_batch(table.into_batch()?, path)
+    }
+
+    fn write_batch<P: AsRef<Path>>(batch: RecordBatch, path: P) -> Result<(), String> {
+        let file = std::fs::File::create(path)
+            .map_err(|e| format!("Failed to create Parquet file: {}", e))?;
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+        let mut writer = ArrowWriter::try_new(file, batch.schema(), Some(properties))
+            .map_err(|e| format!("Failed to create Parquet writer: {}", e))?;
+
+        writer.write(&batch)
+            .map_err(|e| format!("Failed to write Parquet data: {}", e))?;
+        writer.close()
+            .map_err(|e| format!("Failed to finalize Parquet file: {}", e))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "with-parquet"))]
+pub struct ParquetWriter;
+
+#[cfg(not(feature = "with-parquet"))]
+impl ParquetWriter {
+    pub fn write<P>(_results: &crate::simulation::SimulationResults, _path: P) -> Result<(), String>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        Err("Parquet support not enabled. Compile with --features with-parquet".to_string())
+    }
+
+    pub fn write_monte_carlo<P>(_results: &crate::analysis::MonteCarloResults, _path: P) -> Result<(), String>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        Err("Parquet support not enabled. Compile with --features with-parquet".to_string())
+    }
+
+    pub fn write_agents<P>(_snapshots: &crate::simulation::AgentSnapshots, _path: P) -> Result<(), String>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        Err("Parquet support not enabled. Compile with --features with-parquet".to_string())
+    }
+}
+
+#[cfg(all(test, feature = "with-parquet"))]
+mod tests {
+    use super::*;
+    use crate::model::{Model, Stock, Flow, Parameter};
+    use crate::simulation::{SimulationEngine, SimulationConfig};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    #[test]
+    fn test_parquet_write() {
+        let mut model = Model::new("Test");
+        model.time.start = 0.0;
+        model.time.stop = 10.0;
+        model.time.dt = 1.0;
+
+        model.add_stock(Stock::new("Population", "100")).unwrap();
+        model.add_parameter(Parameter::new("growth_rate", 0.1)).unwrap();
+        model.add_flow(Flow::new("growth", "Population * growth_rate")).unwrap();
+        model.stocks.get_mut("Population").unwrap().inflows.push("growth".to_string());
+
+        let config = SimulationConfig::default();
+        let mut engine = SimulationEngine::new(model, config).unwrap();
+        let results = engine.run().unwrap();
+
+        let temp_file = std::env::temp_dir().join("rssdsim_test_output.parquet");
+        ParquetWriter::write(&results, &temp_file).unwrap();
+
+        let file = std::fs::File::open(&temp_file).unwrap();
+        let reader = ParquetRecordBatchReaderBuilder::try_new(file).unwrap().build().unwrap();
+        let batches: Vec<RecordBatch> = reader.map(|b| b.unwrap()).collect();
+        let rows: usize = batches.iter().map(|b| b.num_rows()).sum();
+
+        // Population and growth over 11 time points
+        assert_eq!(rows, 2 * results.times.len());
+        assert_eq!(batches[0].schema().field(1).name(), "variable");
+
+        let _ = std::fs::remove_file(temp_file);
+    }
+
+    #[test]
+    fn test_parquet_write_agents() {
+        let snapshots = AgentSnapshots {
+            time: vec![0.0, 1.0],
+            agent_type: vec!["Firm".to_string(), "Household".to_string()],
+            agent_id: vec![1, 2],
+            state: vec!["open".to_string(), String::new()],
+            attributes: vec!["wealth".to_string()],
+            values: vec![vec![10.0, 2.5]],
+        };
+        let temp_file = std::env::temp_dir().join(format!("rssdsim_agents_{}.parquet", std::process::id()));
+        ParquetWriter::write_agents(&snapshots, &temp_file).unwrap();
+
+        let file = std::fs::File::open(&temp_file).unwrap();
+        let reader = ParquetRecordBatchReaderBuilder::try_new(file).unwrap().build().unwrap();
+        let batches: Vec<RecordBatch> = reader.map(|b| b.unwrap()).collect();
+        let _ = std::fs::remove_file(temp_file);
+
+        let batch = &batches[0];
+        let names: Vec<&str> = batch.schema_ref().fields().iter().map(|field| field.name().as_str()).collect();
+        assert_eq!(names, ["time", "agent_type", "agent_id", "state", "wealth"]);
+        let column = |name: &str| batch.column_by_name(name).unwrap().clone();
+        let agent_type = column("agent_type");
+        let agent_type = agent_type.as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!((agent_type.value(0), agent_type.value(1)), ("Firm", "Household"));
+        let ids = column("agent_id");
+        assert_eq!(ids.as_any().downcast_ref::<UInt64Array>().unwrap().values(), &[1, 2]);
+        let wealth = column("wealth");
+        assert_eq!(wealth.as_any().downcast_ref::<Float64Array>().unwrap().values(), &[10.0, 2.5]);
+    }
+}
@@ -1,11 +1,13 @@
 /// Model parsers for JSON and YAML formats
 
 use serde::{Deserialize, Serialize};
+use crate::error::ParseError;
 use crate::model::*;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use super::modules::flatten;
 
 pub trait ModelParser {
-    fn parse(contents: &str) -> Result<Model, String>;
+    fn parse(contents: &str) -> Result<Model, ParseError>;
 }
 
 /// JSON model format
@@ -14,7 +16,7 @@ pub struct JsonModel {
     pub model: JsonModelContent,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct JsonModelContent {
     pub name: String,
     #[serde(default)]
@@ -28,6 +30,125 @@ pub struct JsonModelContent {
     pub auxiliaries: Vec<JsonAuxiliary>,
     #[serde(default)]
     pub parameters: Vec<JsonParameter>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lookups: Vec<JsonLookup>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lookups_2d: Vec<JsonLookup2D>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dimensions: Vec<JsonDimension>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<JsonEvent>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub constraints: Vec<JsonConstraint>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub conservation: Vec<ConservedGroup>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hybrid: Option<HybridConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub des: Option<DesConfig>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub modules: Vec<Module>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub templates: Vec<JsonTemplate>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub instances: Vec<JsonInstance>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tests: Vec<JsonTest>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub data: Vec<DataVariable>,
+}
+
+/// Structure instantiated any number of times by `instances`, such as an
+/// aging chain or supply line; its parameters are defaults instances can bind
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JsonTemplate {
+    pub name: String,
+    #[serde(default)]
+    pub stocks: Vec<JsonStock>,
+    #[serde(default)]
+    pub flows: Vec<JsonFlow>,
+    #[serde(default)]
+    pub auxiliaries: Vec<JsonAuxiliary>,
+    #[serde(default)]
+    pub parameters: Vec<JsonParameter>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lookups: Vec<JsonLookup>,
+}
+
+/// Copy of a template whose elements are prefixed with `name`, like a module
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JsonInstance {
+    pub name: String,
+    pub template: String,
+    /// Numbers or equations bound to parameters or auxiliaries of the template
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub inputs: BTreeMap<String, serde_json::Value>,
+    /// Auxiliaries of the model reading variables of the copy, by auxiliary name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub outputs: BTreeMap<String, String>,
+}
+
+/// Event fired at time `at` or when the `when` condition becomes true
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonEvent {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub at: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub when: Option<String>,
+    #[serde(default)]
+    pub repeat: bool,
+    #[serde(default)]
+    pub actions: Vec<JsonEventAction>,
+}
+
+/// `set` a parameter or stock to `value`, or `add` `value` to a stock
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonEventAction {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub set: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub add: Option<String>,
+    pub value: serde_json::Value,
+}
+
+/// Reality check such as `ASSERT(Inventory >= 0)`, named after its condition
+/// unless given a `name`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JsonConstraint {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub condition: String,
+    #[serde(default)]
+    pub action: ConstraintAction,
+}
+
+/// Expected behavior of `variable`: within `min`/`max` at time `at`, or at
+/// every point (between `from` and `to`), or settled from `steady_by` on to
+/// within a relative rate of change of `tolerance`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JsonTest {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub variable: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub at: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub steady_by: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tolerance: Option<f64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -40,6 +161,30 @@ pub struct JsonStock {
     pub outflows: Vec<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub units: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dimensions: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conveyor: Option<JsonConveyor>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub oven: Option<JsonOven>,
+}
+
+/// Conveyor settings of a stock; values are numbers or equations
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonConveyor {
+    pub transit_time: serde_json::Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capacity: Option<serde_json::Value>,
+}
+
+/// Oven settings of a stock; values are numbers or equations
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonOven {
+    pub cook_time: serde_json::Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capacity: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fill_time: Option<serde_json::Value>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -48,6 +193,17 @@ pub struct JsonFlow {
     pub equation: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub units: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dimensions: Option<Vec<String>>,
+    /// Leakage outflow of a conveyor
+    #[serde(default)]
+    pub leak: bool,
+    /// Clamp negative rates to zero (uniflow)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub non_negative: Option<bool>,
+    /// Allow negative rates; the opposite of `non_negative`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub biflow: Option<bool>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -56,45 +212,119 @@ pub struct JsonAuxiliary {
     pub equation: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub units: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dimensions: Option<Vec<String>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JsonParameter {
     pub name: String,
-    pub value: f64,
+    /// Value of every element unless `values` is given; with a `schedule`, the
+    /// factor its values are multiplied by, 1 unless given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<f64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub units: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dimensions: Option<Vec<String>>,
+    /// One value per element for arrayed parameters
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub values: Option<Vec<f64>>,
+    /// Plausible range, tried by extreme-conditions tests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+    /// Planned values as (time, value) breakpoints
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<Schedule>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonLookup {
+    pub name: String,
+    /// (x, y) points sorted by x
+    pub points: Vec<(f64, f64)>,
+    /// `linear`, `step` or `spline` between points
+    #[serde(default)]
+    pub interpolation: Interpolation,
+    /// `hold` (or `clamp`), `linear` or `error` beyond the first and last points
+    #[serde(default)]
+    pub extrapolation: Extrapolation,
+}
+
+/// Table of two inputs: `values` has one row per `x` value, one value per `y` value
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonLookup2D {
+    pub name: String,
+    pub x: Vec<f64>,
+    pub y: Vec<f64>,
+    pub values: Vec<Vec<f64>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonDimension {
+    pub name: String,
+    pub elements: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub maps_to: Option<DimensionMapping>,
 }
 
 impl JsonModel {
-    pub fn to_model(json: JsonModel) -> Result<Model, String> {
+    pub fn to_model(json: JsonModel) -> Result<Model, ParseError> {
         let mut model = Model::new(&json.model.name);
         model.metadata.description = json.model.description;
         model.time = json.model.time;
 
+        for dimension in json.model.dimensions {
+            let mut d = Dimension::new(&dimension.name, dimension.elements);
+            d.maps_to = dimension.maps_to;
+            model.add_dimension(d)?;
+        }
+
         // Add parameters first (they might be referenced in initial values)
         for param in json.model.parameters {
+            let schedule = param.schedule
+                .map(|schedule| Schedule::new(schedule.points, schedule.interpolation))
+                .transpose()
+                .map_err(|e| format!("Parameter '{}': {}", param.name, e))?;
             let p = Parameter {
+                value: param.value.unwrap_or(if schedule.is_some() { 1.0 } else { 0.0 }),
                 name: param.name,
-                value: param.value,
                 units: param.units,
                 description: param.description,
+                dimensions: param.dimensions,
+                values: param.values,
+                min: param.min,
+                max: param.max,
+                schedule,
             };
             model.add_parameter(p)?;
         }
 
         // Add stocks
         for stock in json.model.stocks {
-            let initial_expr = match stock.initial {
-                serde_json::Value::Number(n) => {
-                    Expression::parse(&n.to_string())?
-                }
-                serde_json::Value::String(s) => {
-                    Expression::parse(&s)?
+            let initial_expr = parse_value(&stock.name, &stock.initial)?;
+
+            let optional = |value: &Option<serde_json::Value>| {
+                value.as_ref().map(|value| parse_value(&stock.name, value)).transpose()
+            };
+            let kind = match (&stock.conveyor, &stock.oven) {
+                (None, None) => StockKind::Reservoir,
+                (Some(conveyor), None) => StockKind::Conveyor {
+                    transit_time: parse_value(&stock.name, &conveyor.transit_time)?,
+                    capacity: optional(&conveyor.capacity)?,
+                },
+                (None, Some(oven)) => StockKind::Oven {
+                    cook_time: parse_value(&stock.name, &oven.cook_time)?,
+                    capacity: optional(&oven.capacity)?,
+                    fill_time: optional(&oven.fill_time)?,
+                },
+                (Some(_), Some(_)) => {
+                    return Err(format!("Stock '{}' cannot be both a conveyor and an oven", stock.name).into());
                 }
-                _ => return Err("Initial value must be number or string".to_string()),
             };
 
             let s = Stock {
@@ -105,17 +335,29 @@ impl JsonModel {
                 units: stock.units,
                 non_negative: false,
                 max_value: None,
-                dimensions: None,
+                dimensions: stock.dimensions,
+                kind,
             };
             model.add_stock(s)?;
         }
 
         // Add flows
         for flow in json.model.flows {
+            let non_negative = match (flow.non_negative, flow.biflow) {
+                (Some(non_negative), Some(biflow)) if non_negative == biflow => {
+                    return Err(format!("Flow '{}' sets conflicting 'non_negative' and 'biflow'", flow.name).into());
+                }
+                (Some(non_negative), _) => non_negative,
+                (None, Some(biflow)) => !biflow,
+                (None, None) => false,
+            };
             let f = Flow {
+                equation: Expression::parse_equation(&flow.name, &flow.equation)?,
                 name: flow.name,
-                equation: Expression::parse(&flow.equation)?,
                 units: flow.units,
+                dimensions: flow.dimensions,
+                leak: flow.leak,
+                non_negative,
             };
             model.add_flow(f)?;
         }
@@ -123,22 +365,167 @@ impl JsonModel {
         // Add auxiliaries
         for aux in json.model.auxiliaries {
             let a = Auxiliary {
+                equation: Expression::parse_equation(&aux.name, &aux.equation)?,
                 name: aux.name,
-                equation: Expression::parse(&aux.equation)?,
                 units: aux.units,
+                dimensions: aux.dimensions,
             };
             model.add_auxiliary(a)?;
         }
 
+        for event in json.model.events {
+            let trigger = match (event.at, &event.when) {
+                (Some(at), None) => EventTrigger::At(at),
+                (None, Some(when)) => EventTrigger::When(Expression::parse_equation(&event.name, when)?),
+                _ => return Err(format!("Event '{}' needs exactly one of 'at' or 'when'", event.name).into()),
+            };
+            let mut e = Event::new(&event.name, trigger).with_repeat(event.repeat);
+            for action in event.actions {
+                let value = parse_value(&event.name, &action.value)?;
+                e = e.with_action(match (action.set, action.add) {
+                    (Some(variable), None) => EventAction::Set { variable, value },
+                    (None, Some(stock)) => EventAction::Add { stock, amount: value },
+                    _ => return Err(format!("Event '{}' actions need exactly one of 'set' or 'add'", event.name).into()),
+                });
+            }
+            model.add_event(e)?;
+        }
+
+        for constraint in json.model.constraints {
+            let name = constraint.name.unwrap_or_else(|| constraint.condition.clone());
+            model.add_constraint(Constraint {
+                condition: Expression::parse_equation(&name, &constraint.condition)?,
+                name,
+                action: constraint.action,
+            })?;
+        }
+
+        model.hybrid = json.model.hybrid;
+        model.des = json.model.des;
+        model.modules = json.model.modules;
+
+        for group in json.model.conservation {
+            model.add_conserved_group(group)?;
+        }
+
+        for test in json.model.tests {
+            model.tests.push(test.into_behavior_test()?);
+        }
+
+        // Add lookup tables
+        for lookup in json.model.lookups {
+            let table = crate::simulation::LookupTable::new(lookup.name, lookup.points)?
+                .with_interpolation(lookup.interpolation)
+                .with_extrapolation(lookup.extrapolation);
+            model.add_lookup(table)?;
+        }
+        for lookup in json.model.lookups_2d {
+            model.add_lookup_2d(crate::simulation::LookupTable2D::new(lookup.name, lookup.x, lookup.y, lookup.values)?)?;
+        }
+
+        // Series given inline instead of in a file
+        for mut data in json.model.data {
+            if !data.points.is_empty() {
+                let points = std::mem::take(&mut data.points);
+                data.set_points(points)?;
+            }
+            model.add_data(data)?;
+        }
+
+        for instance in &json.model.instances {
+            let template = json.model.templates.iter().find(|template| template.name == instance.template)
+                .ok_or_else(|| format!("Instance '{}' uses undefined template '{}'", instance.name, instance.template))?;
+            let inputs = instance.inputs.iter()
+                .map(|(port, value)| match value {
+                    serde_json::Value::Number(n) => Ok((port.clone(), n.to_string())),
+                    serde_json::Value::String(s) => Ok((port.clone(), s.clone())),
+                    _ => Err(format!("Instance '{}': expected a number or equation for '{}', found {}", instance.name, port, value)),
+                })
+                .collect::<Result<BTreeMap<_, _>, _>>()?;
+            flatten(&mut model, &instance.name, &inputs, &instance.outputs, template.to_model()?)
+                .map_err(|e| format!("Instance '{}' of template '{}': {}", instance.name, template.name, e))?;
+        }
+
         Ok(model)
     }
 }
 
+impl JsonTemplate {
+    /// The template's elements as a model of their own
+    fn to_model(&self) -> Result<Model, ParseError> {
+        JsonModel::to_model(JsonModel {
+            model: JsonModelContent {
+                name: self.name.clone(),
+                stocks: self.stocks.clone(),
+                flows: self.flows.clone(),
+                auxiliaries: self.auxiliaries.clone(),
+                parameters: self.parameters.clone(),
+                lookups: self.lookups.clone(),
+                ..JsonModelContent::default()
+            },
+        })
+    }
+}
+
+impl JsonTest {
+    fn into_behavior_test(self) -> Result<BehaviorTest, ParseError> {
+        let bounds = match (self.min, self.max) {
+            (Some(min), Some(max)) => format!("in [{}, {}]", min, max),
+            (Some(min), None) => format!(">= {}", min),
+            (None, Some(max)) => format!("<= {}", max),
+            (None, None) => String::new(),
+        };
+        let windowed = self.at.is_some() || self.from.is_some() || self.to.is_some();
+        let (check, description) = match self.steady_by {
+            Some(_) if windowed || !bounds.is_empty() => {
+                return Err(format!("Test of '{}' cannot combine 'steady_by' with 'at', 'from', 'to', 'min' or 'max'", self.variable).into());
+            }
+            Some(time) => (
+                BehaviorCheck::SteadyBy { time, tolerance: self.tolerance.unwrap_or(1e-3) },
+                format!("{} steady by t={}", self.variable, time),
+            ),
+            None if bounds.is_empty() => {
+                return Err(format!("Test of '{}' needs 'min', 'max' or 'steady_by'", self.variable).into());
+            }
+            None if self.tolerance.is_some() => {
+                return Err(format!("Test of '{}' sets 'tolerance' without 'steady_by'", self.variable).into());
+            }
+            None => match self.at {
+                Some(_) if self.from.is_some() || self.to.is_some() => {
+                    return Err(format!("Test of '{}' cannot combine 'at' with 'from' or 'to'", self.variable).into());
+                }
+                Some(time) => (
+                    BehaviorCheck::At { time, min: self.min, max: self.max },
+                    format!("{} at t={} {}", self.variable, time, bounds),
+                ),
+                None => (
+                    BehaviorCheck::Always { from: self.from, to: self.to, min: self.min, max: self.max },
+                    format!("{} always {}", self.variable, bounds),
+                ),
+            },
+        };
+        Ok(BehaviorTest {
+            name: self.name.unwrap_or(description),
+            variable: self.variable,
+            check,
+        })
+    }
+}
+
+/// Parse a value of `variable` given as either a number or an equation
+fn parse_value(variable: &str, value: &serde_json::Value) -> Result<Expression, ParseError> {
+    match value {
+        serde_json::Value::Number(n) => Ok(Expression::parse_equation(variable, &n.to_string())?),
+        serde_json::Value::String(s) => Ok(Expression::parse_equation(variable, s)?),
+        _ => Err(format!("Expected a number or equation for '{}', found {}", variable, value).into()),
+    }
+}
+
 /// YAML model format (same structure as JSON)
 pub type YamlModel = JsonModel;
 
 impl ModelParser for JsonModel {
-    fn parse(contents: &str) -> Result<Model, String> {
+    fn parse(contents: &str) -> Result<Model, ParseError> {
         let json_model: JsonModel = serde_json::from_str(contents)
             .map_err(|e| format!("JSON parse error: {}", e))?;
         Self::to_model(json_model)
@@ -146,7 +533,7 @@ impl ModelParser for JsonModel {
 }
 
 /// Parse YAML format (uses same structure as JSON)
-pub fn parse_yaml(contents: &str) -> Result<Model, String> {
+pub fn parse_yaml(contents: &str) -> Result<Model, ParseError> {
     let yaml_model: YamlModel = serde_yaml::from_str(contents)
         .map_err(|e| format!("YAML parse error: {}", e))?;
     JsonModel::to_model(yaml_model)
@@ -188,4 +575,244 @@ mod tests {
         assert_eq!(model.stocks.len(), 1);
         assert_eq!(model.parameters.len(), 1);
     }
+
+    #[test]
+    fn test_parse_yaml_lookup_table() {
+        let yaml = r#"
+model:
+  name: Lookup
+  time: { start: 0, stop: 10, dt: 1 }
+  auxiliaries:
+    - name: effect
+      equation: LOOKUP("capacity_effect", TIME)
+  lookups:
+    - name: capacity_effect
+      points: [[0, 1.0], [10, 0.0]]
+    - name: tariff
+      points: [[0, 1.0], [5, 2.0], [10, 4.0]]
+      interpolation: step
+      extrapolation: clamp
+    - name: growth
+      points: [[0, 0.0], [10, 1.0]]
+      interpolation: spline
+      extrapolation: error
+"#;
+
+        let model = parse_yaml(yaml).unwrap();
+        assert_eq!(model.lookups["capacity_effect"].lookup(5.0).unwrap(), 0.5);
+        assert_eq!(model.lookups["tariff"].lookup(7.5).unwrap(), 2.0);
+        assert_eq!(model.lookups["tariff"].lookup(20.0).unwrap(), 4.0);
+        assert_eq!(model.lookups["growth"].lookup(2.5).unwrap(), 0.25);
+        assert!(model.lookups["growth"].lookup(11.0).is_err());
+        assert!(parse_yaml(&yaml.replace("interpolation: step", "interpolation: cubic")).is_err());
+    }
+
+    #[test]
+    fn test_parse_yaml_parameter_schedule() {
+        let yaml = r#"
+model:
+  name: Policy
+  time: { start: 0, stop: 4, dt: 1 }
+  stocks:
+    - { name: Revenue, initial: 0, inflows: [collection] }
+  flows:
+    - { name: collection, equation: "100 * tax_rate" }
+  parameters:
+    - name: tax_rate
+      schedule:
+        points: [[0, 0.1], [2, 0.2]]
+        interpolation: step
+"#;
+
+        let model = parse_yaml(yaml).unwrap();
+        assert_eq!(model.parameters["tax_rate"].value, 1.0);
+        let results = crate::simulation::SimulationEngine::new(model, crate::simulation::SimulationConfig::default())
+            .unwrap().run().unwrap();
+        let revenue = results.get_variable_series("Revenue").unwrap();
+        assert!(revenue.iter().zip([0.0, 10.0, 20.0, 40.0, 60.0]).all(|(a, b)| (a - b).abs() < 1e-9), "{:?}", revenue);
+
+        let doubled = parse_yaml(&yaml.replace("- name: tax_rate", "- name: tax_rate\n      value: 2")).unwrap();
+        assert_eq!(doubled.parameters["tax_rate"].value_at(3.0), 0.4);
+        assert!(parse_yaml(&yaml.replace("[2, 0.2]", "[0, 0.2]")).unwrap_err().to_string().contains("tax_rate"));
+    }
+
+    #[test]
+    fn test_parse_yaml_lookup_2d() {
+        let yaml = r#"
+model:
+  name: Production
+  time: { start: 0, stop: 2, dt: 1 }
+  auxiliaries:
+    - name: output
+      equation: LOOKUP2D("production", labor, capital)
+  parameters:
+    - { name: labor, value: 5 }
+    - { name: capital, value: 150 }
+  lookups_2d:
+    - name: production
+      x: [0, 10]
+      y: [0, 100, 200]
+      values:
+        - [0, 0, 0]
+        - [0, 50, 80]
+"#;
+
+        let model = parse_yaml(yaml).unwrap();
+        assert!(crate::analysis::validate_model(&model).is_valid());
+        let results = crate::simulation::SimulationEngine::new(model, crate::simulation::SimulationConfig::default())
+            .unwrap().run().unwrap();
+        assert_eq!(results.final_value("output"), Some(32.5));
+        assert!(parse_yaml(&yaml.replace("- [0, 50, 80]", "- [0, 50]")).is_err());
+    }
+
+    #[test]
+    fn test_parse_yaml_flow_direction() {
+        let yaml = r#"
+model:
+  name: Flows
+  time: { start: 0, stop: 10, dt: 1 }
+  flows:
+    - name: shipments
+      equation: "1"
+      non_negative: true
+    - name: trade
+      equation: "1"
+      biflow: true
+    - name: returns
+      equation: "1"
+      biflow: false
+"#;
+
+        let model = parse_yaml(yaml).unwrap();
+        assert!(model.flows["shipments"].non_negative);
+        assert!(model.flows["trade"].is_biflow());
+        assert!(model.flows["returns"].non_negative);
+
+        let conflicting = yaml.replace("biflow: true", "biflow: true\n      non_negative: true");
+        assert!(parse_yaml(&conflicting).is_err());
+    }
+
+    #[test]
+    fn test_parse_yaml_equation_error_location() {
+        let yaml = r#"
+model:
+  name: Broken
+  time: { start: 0, stop: 10, dt: 1 }
+  auxiliaries:
+    - name: growth
+      equation: "rate * (Population + 1"
+"#;
+
+        match parse_yaml(yaml) {
+            Err(ParseError::Equation { path: None, error }) => {
+                assert_eq!(error.variable, "growth");
+                assert_eq!(error.position, 22);
+                assert_eq!(error.underline(), format!("rate * (Population + 1\n{}^", " ".repeat(22)));
+            }
+            other => panic!("expected an equation error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_parse_yaml_data_variable() {
+        let yaml = r#"
+model:
+  name: Emissions
+  time: { start: 0, stop: 4, dt: 0.5 }
+  stocks:
+    - name: Carbon
+      initial: 0
+      inflows: [emissions]
+  flows:
+    - name: emissions
+      equation: "fuel_use * 2"
+  data:
+    - name: fuel_use
+      file: fuel.csv
+      interpolation: step
+      points: [[2, 3.0], [0, 1.0]]
+"#;
+
+        let model = parse_yaml(yaml).unwrap();
+        let fuel_use = &model.data["fuel_use"];
+        assert_eq!(fuel_use.points, vec![(0.0, 1.0), (2.0, 3.0)]);
+        assert_eq!(fuel_use.extrapolation, Extrapolation::Hold);
+
+        let config = crate::simulation::SimulationConfig::default();
+        let results = crate::simulation::SimulationEngine::new(model, config).unwrap().run().unwrap();
+        let carbon = results.get_variable_series("Carbon").unwrap();
+        // 2 per unit time until t = 2, then 6
+        assert!((carbon.last().unwrap() - 16.0).abs() < 1e-9, "{:?}", carbon);
+    }
+
+    #[test]
+    fn test_parse_yaml_behavior_tests() {
+        let yaml = r#"
+model:
+  name: Growth
+  time: { start: 0, stop: 10, dt: 1 }
+  stocks:
+    - name: Population
+      initial: 100
+  tests:
+    - name: Population near capacity
+      variable: Population
+      at: 10
+      min: 90
+      max: 110
+    - variable: Population
+      min: 0
+      from: 5
+    - variable: Population
+      steady_by: 5
+"#;
+
+        let model = parse_yaml(yaml).unwrap();
+        assert_eq!(model.tests.len(), 3);
+        assert_eq!(model.tests[0].name, "Population near capacity");
+        assert_eq!(model.tests[0].check, BehaviorCheck::At { time: 10.0, min: Some(90.0), max: Some(110.0) });
+        assert_eq!(model.tests[1].name, "Population always >= 0");
+        assert_eq!(model.tests[1].check, BehaviorCheck::Always { from: Some(5.0), to: None, min: Some(0.0), max: None });
+        assert_eq!(model.tests[2].check, BehaviorCheck::SteadyBy { time: 5.0, tolerance: 1e-3 });
+
+        let conflicting = yaml.replace("steady_by: 5", "steady_by: 5\n      max: 1");
+        assert!(parse_yaml(&conflicting).unwrap_err().to_string().contains("cannot combine 'steady_by'"));
+        let unbounded = yaml.replace("      min: 0\n", "");
+        assert!(parse_yaml(&unbounded).unwrap_err().to_string().contains("needs 'min', 'max' or 'steady_by'"));
+    }
+
+    #[test]
+    fn test_parse_yaml_template_instances() {
+        let yaml = r#"
+model:
+  name: Cohorts
+  time: { start: 0, stop: 2, dt: 1 }
+  parameters:
+    - { name: births, value: 10 }
+  templates:
+    - name: aging
+      stocks:
+        - { name: Young, initial: 0, inflows: [entering], outflows: [maturing] }
+      flows:
+        - { name: entering, equation: "arrivals" }
+        - { name: maturing, equation: "Young / delay" }
+      parameters:
+        - { name: arrivals, value: 0 }
+        - { name: delay, value: 2 }
+  instances:
+    - { name: north, template: aging, inputs: { arrivals: births } }
+    - { name: south, template: aging, inputs: { arrivals: "births * 2", delay: 4 }, outputs: { south_young: Young } }
+"#;
+
+        let model = parse_yaml(yaml).unwrap();
+        assert!(model.stocks.contains_key("north.Young") && model.stocks.contains_key("south.Young"));
+        assert_eq!(model.parameters["north.delay"].value, 2.0);
+        assert!(!model.parameters.contains_key("south.delay") && model.auxiliaries.contains_key("south.delay"));
+        assert_eq!(model.stocks["north.Young"].outflows, vec!["north.maturing".to_string()]);
+        assert_eq!(model.flows["south.maturing"].equation.to_string(), "(south.Young / south.delay)");
+        assert!(model.auxiliaries.contains_key("south_young"));
+
+        let unknown = yaml.replace("name: north, template: aging", "name: north, template: ageing");
+        assert!(parse_yaml(&unknown).unwrap_err().to_string().contains("undefined template 'ageing'"));
+    }
 }
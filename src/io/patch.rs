@@ -0,0 +1,265 @@
+/// Model patches: scenario variants kept as small diffs of a base model
+///
+/// A patch file lists the elements to remove, add and modify, by section, and
+/// amounts to add to parameter values:
+///
+/// ```yaml
+/// patch:
+///   remove:
+///     flows: [emigration]
+///   add:
+///     auxiliaries:
+///       - { name: subsidy, equation: "0.1 * births" }
+///   modify:
+///     time: { stop: 200 }
+///     flows:
+///       - { name: births, equation: "Population * birth_rate + subsidy" }
+///   deltas:
+///     birth_rate: 0.005
+/// ```
+///
+/// Patches apply to native YAML or JSON model documents, in the order above.
+/// Modified elements keep the fields the patch leaves out, and sections that
+/// are mappings, such as `time`, are merged key by key. Removing or modifying
+/// an element that does not exist, or adding one that does, is an error.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use serde::Deserialize;
+use serde_yaml::{Mapping, Value};
+use crate::error::{IoError, ParseError, Result};
+use crate::model::Model;
+use super::parser;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Patch {
+    /// Names of elements to remove, by section
+    #[serde(default)]
+    pub remove: BTreeMap<String, Vec<String>>,
+    /// Elements to add, by section
+    #[serde(default)]
+    pub add: BTreeMap<String, Vec<Value>>,
+    /// Changed fields of named elements, or of a mapping section, by section
+    #[serde(default)]
+    pub modify: BTreeMap<String, Value>,
+    /// Amounts added to parameter values, every element of arrayed ones included
+    #[serde(default)]
+    pub deltas: BTreeMap<String, f64>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PatchFile {
+    patch: Patch,
+}
+
+/// Parse a patch document
+pub fn parse_patch(contents: &str) -> Result<Patch, ParseError> {
+    let file: PatchFile = serde_yaml::from_str(contents)
+        .map_err(|e| format!("Failed to parse patch: {}", e))?;
+    Ok(file.patch)
+}
+
+/// Load a patch from a YAML or JSON file
+pub fn load_patch<P: AsRef<Path>>(path: P) -> Result<Patch> {
+    let path = path.as_ref();
+    let contents = fs::read_to_string(path)
+        .map_err(|source| IoError::Read { path: path.to_path_buf(), source })?;
+    Ok(parse_patch(&contents).map_err(|e| e.in_file(path))?)
+}
+
+fn element_name(element: &Value) -> Option<&str> {
+    element.get("name").and_then(Value::as_str)
+}
+
+fn position(elements: &[Value], name: &str) -> Option<usize> {
+    elements.iter().position(|element| element_name(element) == Some(name))
+}
+
+/// The elements of a list section, which must exist
+fn elements_mut<'a>(model: &'a mut Mapping, section: &str) -> Result<&'a mut Vec<Value>, ParseError> {
+    match model.get_mut(section) {
+        Some(Value::Sequence(elements)) => Ok(elements),
+        Some(_) => Err(format!("Section '{}' is not a list of elements", section).into()),
+        None => Err(format!("Model has no '{}' section", section).into()),
+    }
+}
+
+/// Overwrite `target` with `changes`, merging mappings key by key
+fn merge(target: &mut Value, changes: &Value) {
+    match (target, changes) {
+        (Value::Mapping(target), Value::Mapping(changes)) => {
+            for (key, value) in changes {
+                match target.get_mut(key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        target.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        (target, changes) => *target = changes.clone(),
+    }
+}
+
+impl Patch {
+    /// Apply the patch to a model document, as read from a native model file
+    pub fn apply(&self, document: &mut Value) -> Result<(), ParseError> {
+        let model = document.get_mut("model").and_then(Value::as_mapping_mut)
+            .ok_or_else(|| "Document has no 'model' section".to_string())?;
+
+        for (section, names) in &self.remove {
+            let elements = elements_mut(model, section)?;
+            for name in names {
+                let index = position(elements, name)
+                    .ok_or_else(|| format!("Cannot remove '{}' from {}: no such element", name, section))?;
+                elements.remove(index);
+            }
+        }
+
+        for (section, added) in &self.add {
+            if !model.contains_key(section.as_str()) {
+                model.insert(Value::from(section.as_str()), Value::Sequence(Vec::new()));
+            }
+            let elements = elements_mut(model, section)?;
+            for element in added {
+                let name = element_name(element)
+                    .ok_or_else(|| format!("Element added to {} has no name", section))?;
+                if position(elements, name).is_some() {
+                    return Err(format!("Cannot add '{}' to {}: it already exists", name, section).into());
+                }
+                elements.push(element.clone());
+            }
+        }
+
+        for (section, changes) in &self.modify {
+            match (model.get_mut(section.as_str()), changes) {
+                (Some(Value::Sequence(elements)), Value::Sequence(changes)) => {
+                    for change in changes {
+                        let name = element_name(change)
+                            .ok_or_else(|| format!("Element modified in {} has no name", section))?;
+                        let index = position(elements, name)
+                            .ok_or_else(|| format!("Cannot modify '{}' in {}: no such element", name, section))?;
+                        merge(&mut elements[index], change);
+                    }
+                }
+                (Some(Value::Sequence(_)), _) => {
+                    return Err(format!("Changes to {} must be a list of elements with names", section).into());
+                }
+                (Some(existing), changes) => merge(existing, changes),
+                (None, changes) => {
+                    model.insert(Value::from(section.as_str()), changes.clone());
+                }
+            }
+        }
+
+        if !self.deltas.is_empty() {
+            let parameters = elements_mut(model, "parameters")?;
+            for (name, delta) in &self.deltas {
+                let index = position(parameters, name)
+                    .ok_or_else(|| format!("Cannot change parameter '{}': no such parameter", name))?;
+                let parameter = parameters[index].as_mapping_mut()
+                    .ok_or_else(|| format!("Parameter '{}' is not a mapping", name))?;
+                // A scheduled parameter's value scales its schedule, so defaults to 1
+                let default = if parameter.contains_key("schedule") { 1.0 } else { 0.0 };
+                let value = parameter.get("value").and_then(Value::as_f64).unwrap_or(default);
+                parameter.insert(Value::from("value"), Value::from(value + delta));
+                if let Some(Value::Sequence(values)) = parameter.get_mut("values") {
+                    for element in values.iter_mut() {
+                        let value = element.as_f64()
+                            .ok_or_else(|| format!("Parameter '{}' has a value that is not a number", name))?;
+                        *element = Value::from(value + delta);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Apply a patch to the YAML or JSON model file at `base`, returning the
+/// merged document and the model it parses to
+pub fn patch_model_file<P: AsRef<Path>>(base: P, patch: &Patch) -> Result<(Value, Model)> {
+    let base = base.as_ref();
+    let in_file = |message: String| ParseError::from(message).in_file(base);
+    match base.extension().and_then(|s| s.to_str()) {
+        Some("yaml" | "yml" | "json") => {}
+        _ => return Err(in_file("Only YAML and JSON models can be patched".to_string()).into()),
+    }
+    let contents = fs::read_to_string(base)
+        .map_err(|source| IoError::Read { path: base.to_path_buf(), source })?;
+    let mut document: Value = serde_yaml::from_str(&contents)
+        .map_err(|e| in_file(format!("Failed to parse model: {}", e)))?;
+    patch.apply(&mut document)?;
+
+    let merged = serde_yaml::to_string(&document).map_err(|e| IoError::Output(e.to_string()))?;
+    let model = parser::parse_yaml(&merged)?;
+    Ok((document, model))
+}
+
+/// Write a model document as JSON for a `.json` path, otherwise as YAML
+pub fn write_document<P: AsRef<Path>>(document: &Value, path: P) -> Result<(), IoError> {
+    let path = path.as_ref();
+    let contents = match path.extension().and_then(|s| s.to_str()) {
+        Some("json") => serde_json::to_string_pretty(document).map_err(|e| IoError::Output(e.to_string()))?,
+        _ => serde_yaml::to_string(document).map_err(|e| IoError::Output(e.to_string()))?,
+    };
+    fs::write(path, contents).map_err(|source| IoError::Write { path: path.to_path_buf(), source })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_patch() {
+        let base = r#"
+model:
+  name: Population
+  time: { start: 0, stop: 100, dt: 1 }
+  stocks:
+    - { name: Population, initial: 100, inflows: [births], outflows: [deaths, emigration] }
+  flows:
+    - { name: births, equation: "Population * birth_rate", units: people/year }
+    - { name: deaths, equation: "Population * 0.01" }
+    - { name: emigration, equation: "Population * 0.002" }
+  parameters:
+    - { name: birth_rate, value: 0.02 }
+    - { name: regions, value: 0, values: [1, 2] }
+"#;
+        let patch = parse_patch(r#"
+patch:
+  remove:
+    flows: [emigration]
+  add:
+    auxiliaries:
+      - { name: subsidy, equation: "0.1 * births" }
+  modify:
+    time: { stop: 200 }
+    stocks:
+      - { name: Population, outflows: [deaths] }
+    flows:
+      - { name: births, equation: "Population * birth_rate + subsidy" }
+  deltas:
+    birth_rate: 0.005
+    regions: 1
+"#).unwrap();
+
+        let mut document: Value = serde_yaml::from_str(base).unwrap();
+        patch.apply(&mut document).unwrap();
+        let model = parser::parse_yaml(&serde_yaml::to_string(&document).unwrap()).unwrap();
+        assert_eq!(model.time.stop, 200.0);
+        assert_eq!(model.time.dt, 1.0);
+        assert!(!model.flows.contains_key("emigration") && model.auxiliaries.contains_key("subsidy"));
+        assert_eq!(model.flows["births"].units.as_deref(), Some("people/year"));
+        assert_eq!(model.flows["births"].equation.to_string(), "((Population * birth_rate) + subsidy)");
+        assert!((model.parameters["birth_rate"].value - 0.025).abs() < 1e-12);
+        assert_eq!(model.parameters["regions"].values, Some(vec![2.0, 3.0]));
+
+        let missing = parse_patch("patch:\n  remove:\n    flows: [emigration]\n").unwrap();
+        assert!(missing.apply(&mut document).unwrap_err().to_string().contains("no such element"));
+        assert!(matches!(parse_patch("patch:\n  delete:\n    flows: [deaths]\n"), Err(ParseError::Syntax { .. })));
+    }
+}
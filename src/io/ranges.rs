@@ -0,0 +1,217 @@
+/// Parameter range files for sensitivity, Monte Carlo and calibration runs
+///
+/// A ranges file is YAML (or JSON) mapping parameter names to bounds:
+///
+/// ```yaml
+/// growth_rate:
+///   min: 0.05
+///   max: 0.15
+///   baseline: 0.1   # optional, defaults to the midpoint
+/// ```
+///
+/// A priors file for Bayesian calibration has the same shape; each entry names
+/// a `distribution` (uniform, the default, normal or lognormal) and its
+/// parameters, with optional `min`/`max` truncation:
+///
+/// ```yaml
+/// beta:
+///   min: 0.1
+///   max: 0.5
+/// gamma:
+///   distribution: normal
+///   mean: 0.2
+///   sd: 0.05
+///   min: 0
+/// capacity:
+///   distribution: lognormal
+///   mu: 6.9
+///   sigma: 0.2
+/// ```
+///
+/// A decisions file for policy optimization gives each decision variable the
+/// times its value may change and its bounds:
+///
+/// ```yaml
+/// tax_rate:
+///   times: [2020, 2030, 2040]
+///   min: 0
+///   max: 0.5
+/// ```
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use serde::Deserialize;
+use crate::analysis::{ParameterRange, ParameterPrior, Prior, DecisionVariable};
+
+#[derive(Debug, Deserialize)]
+struct RangeSpec {
+    min: f64,
+    max: f64,
+    #[serde(default)]
+    baseline: Option<f64>,
+}
+
+/// Parse a ranges document; parameters are returned sorted by name
+pub fn parse_parameter_ranges(contents: &str) -> Result<Vec<ParameterRange>, String> {
+    let specs: BTreeMap<String, RangeSpec> = serde_yaml::from_str(contents)
+        .map_err(|e| format!("Failed to parse parameter ranges: {}", e))?;
+
+    if specs.is_empty() {
+        return Err("Parameter ranges file defines no parameters".to_string());
+    }
+
+    specs.into_iter()
+        .map(|(name, spec)| {
+            if spec.min > spec.max {
+                return Err(format!("Parameter '{}' has min {} greater than max {}", name, spec.min, spec.max));
+            }
+            let baseline = spec.baseline.unwrap_or((spec.min + spec.max) / 2.0);
+            Ok(ParameterRange::new(name, spec.min, spec.max, baseline))
+        })
+        .collect()
+}
+
+/// Load parameter ranges from a YAML or JSON file
+pub fn load_parameter_ranges<P: AsRef<Path>>(path: P) -> Result<Vec<ParameterRange>, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read file: {}", e))?;
+    parse_parameter_ranges(&contents)
+}
+
+#[derive(Debug, Deserialize)]
+struct PriorSpec {
+    #[serde(default)]
+    distribution: Option<String>,
+    min: Option<f64>,
+    max: Option<f64>,
+    mean: Option<f64>,
+    sd: Option<f64>,
+    mu: Option<f64>,
+    sigma: Option<f64>,
+}
+
+/// Parse a priors document; parameters are returned sorted by name
+pub fn parse_priors(contents: &str) -> Result<Vec<ParameterPrior>, String> {
+    let specs: BTreeMap<String, PriorSpec> = serde_yaml::from_str(contents)
+        .map_err(|e| format!("Failed to parse priors: {}", e))?;
+
+    if specs.is_empty() {
+        return Err("Priors file defines no parameters".to_string());
+    }
+
+    specs.into_iter()
+        .map(|(name, spec)| {
+            let require = |value: Option<f64>, field: &str| {
+                value.ok_or_else(|| format!("Prior of '{}' needs '{}'", name, field))
+            };
+            let positive = |value: f64, field: &str| {
+                if value > 0.0 { Ok(value) } else { Err(format!("Prior of '{}' needs a positive '{}'", name, field)) }
+            };
+
+            let distribution = spec.distribution.as_deref().unwrap_or("uniform").to_lowercase();
+            let prior = match distribution.as_str() {
+                "uniform" => {
+                    let (min, max) = (require(spec.min, "min")?, require(spec.max, "max")?);
+                    if min >= max {
+                        return Err(format!("Parameter '{}' has min {} not below max {}", name, min, max));
+                    }
+                    Prior::Uniform { min, max }
+                }
+                "normal" => Prior::Normal {
+                    mean: require(spec.mean, "mean")?,
+                    sd: positive(require(spec.sd, "sd")?, "sd")?,
+                },
+                "lognormal" => Prior::LogNormal {
+                    mu: require(spec.mu, "mu")?,
+                    sigma: positive(require(spec.sigma, "sigma")?, "sigma")?,
+                },
+                other => return Err(format!(
+                    "Unknown distribution '{}' for '{}' (expected uniform, normal or lognormal)", other, name
+                )),
+            };
+
+            let prior = ParameterPrior::new(&name, prior)
+                .truncated(spec.min.unwrap_or(f64::NEG_INFINITY), spec.max.unwrap_or(f64::INFINITY));
+            if prior.min >= prior.max {
+                return Err(format!("Prior of '{}' is truncated to an empty range", name));
+            }
+            Ok(prior)
+        })
+        .collect()
+}
+
+/// Load priors from a YAML or JSON file
+pub fn load_priors<P: AsRef<Path>>(path: P) -> Result<Vec<ParameterPrior>, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read file: {}", e))?;
+    parse_priors(&contents)
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DecisionSpec {
+    times: Vec<f64>,
+    min: f64,
+    max: f64,
+}
+
+/// Parse a decisions document; variables are returned sorted by name
+pub fn parse_decisions(contents: &str) -> Result<Vec<DecisionVariable>, String> {
+    let specs: BTreeMap<String, DecisionSpec> = serde_yaml::from_str(contents)
+        .map_err(|e| format!("Failed to parse decision variables: {}", e))?;
+
+    if specs.is_empty() {
+        return Err("Decisions file defines no decision variables".to_string());
+    }
+
+    specs.into_iter()
+        .map(|(name, spec)| {
+            if spec.min > spec.max {
+                return Err(format!("Decision variable '{}' has min {} greater than max {}", name, spec.min, spec.max));
+            }
+            Ok(DecisionVariable::new(&name, spec.times, spec.min, spec.max))
+        })
+        .collect()
+}
+
+/// Load decision variables from a YAML or JSON file
+pub fn load_decisions<P: AsRef<Path>>(path: P) -> Result<Vec<DecisionVariable>, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read file: {}", e))?;
+    parse_decisions(&contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ranges_yaml_and_json() {
+        let yaml = "beta:\n  min: 0.1\n  max: 0.5\n  baseline: 0.3\nalpha:\n  min: 1\n  max: 3\n";
+        let ranges = parse_parameter_ranges(yaml).unwrap();
+        assert_eq!(ranges.len(), 2);
+        assert_eq!(ranges[0].name, "alpha");
+        assert_eq!(ranges[0].baseline, 2.0);
+        assert_eq!(ranges[1].baseline, 0.3);
+
+        let json = r#"{"gamma": {"min": 0.0, "max": 1.0}}"#;
+        let ranges = parse_parameter_ranges(json).unwrap();
+        assert_eq!(ranges[0].name, "gamma");
+
+        assert!(parse_parameter_ranges("x:\n  min: 2\n  max: 1\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_priors() {
+        let yaml = "beta:\n  min: 0.1\n  max: 0.5\n  baseline: 0.3\n\
+                    gamma:\n  distribution: normal\n  mean: 0.2\n  sd: 0.05\n  min: 0\n";
+        let priors = parse_priors(yaml).unwrap();
+        assert_eq!(priors[0], ParameterPrior::new("beta", Prior::Uniform { min: 0.1, max: 0.5 }));
+        assert_eq!(priors[1].prior, Prior::Normal { mean: 0.2, sd: 0.05 });
+        assert_eq!((priors[1].min, priors[1].max), (0.0, f64::INFINITY));
+
+        assert!(parse_priors("k:\n  distribution: normal\n  mean: 1\n").is_err());
+        assert!(parse_priors("k:\n  distribution: gamma\n  min: 0\n  max: 1\n").is_err());
+    }
+}
@@ -0,0 +1,70 @@
+/// Scenario files for batch runs
+///
+/// A scenarios file is YAML (or JSON) mapping scenario names to parameter
+/// overrides and, optionally, time settings:
+///
+/// ```yaml
+/// baseline: {}
+/// high_growth:
+///   parameters:
+///     growth_rate: 0.15
+///   time:
+///     stop: 200
+/// ```
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use serde::Deserialize;
+use crate::analysis::{Scenario, ScenarioTime};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScenarioSpec {
+    #[serde(default)]
+    parameters: BTreeMap<String, f64>,
+    #[serde(default)]
+    time: ScenarioTime,
+}
+
+/// Parse a scenarios document; scenarios are returned sorted by name
+pub fn parse_scenarios(contents: &str) -> Result<Vec<Scenario>, String> {
+    let specs: BTreeMap<String, Option<ScenarioSpec>> = serde_yaml::from_str(contents)
+        .map_err(|e| format!("Failed to parse scenarios: {}", e))?;
+
+    if specs.is_empty() {
+        return Err("Scenarios file defines no scenarios".to_string());
+    }
+
+    Ok(specs.into_iter()
+        .map(|(name, spec)| {
+            let spec = spec.unwrap_or_default();
+            Scenario { name, parameters: spec.parameters, time: spec.time }
+        })
+        .collect())
+}
+
+/// Load scenarios from a YAML or JSON file
+pub fn load_scenarios<P: AsRef<Path>>(path: P) -> Result<Vec<Scenario>, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read file: {}", e))?;
+    parse_scenarios(&contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_scenarios() {
+        let yaml = "tax:\n  parameters:\n    tax_rate: 0.3\n  time:\n    stop: 2050\nbaseline:\n";
+        let scenarios = parse_scenarios(yaml).unwrap();
+        assert_eq!(scenarios.len(), 2);
+        assert_eq!(scenarios[0].name, "baseline");
+        assert!(scenarios[0].parameters.is_empty());
+        assert_eq!(scenarios[1].parameters["tax_rate"], 0.3);
+        assert_eq!(scenarios[1].time.stop, Some(2050.0));
+
+        assert!(parse_scenarios("tax:\n  parameter:\n    tax_rate: 0.3\n").is_err());
+    }
+}
@@ -0,0 +1,1123 @@
+/// Vensim `.mdl` text format importer and exporter
+///
+/// The importer supports the equation section of Vensim models:
+/// - `name = expression ~ units ~ comment |` entries: `INTEG` stocks, numeric
+///   constants and auxiliaries
+/// - Lookup tables, standalone (`name([(x0,y0)-(x1,y1)],(x,y),...)`) and
+///   inline (`WITH LOOKUP`)
+/// - Subscript ranges (`Region: North, South`); subscripted variables are
+///   expanded into one scalar per element named `name_element`
+/// - INITIAL TIME / FINAL TIME / TIME STEP control variables
+/// - GET XLS CONSTANTS / DATA / LOOKUPS, read by [`super::xlsx_reader`] when
+///   the model is loaded
+///
+/// The sketch section is ignored; macros and other data equations are rejected.
+///
+/// The exporter writes the same subset, so models can be loaded by Vensim or
+/// PySD (`pysd.read_vensim`) to cross-check results.
+
+use std::collections::HashMap;
+use crate::model::*;
+use crate::model::expression::{Operator, UnaryOperator};
+use crate::simulation::LookupTable;
+
+pub fn parse_vensim(contents: &str) -> Result<Model, String> {
+    // Everything after the sketch marker is diagram layout
+    let equations = contents.split("\\\\\\---///").next().unwrap_or("");
+    if equations.contains(":MACRO:") {
+        return Err("Vensim macros are not supported".to_string());
+    }
+    let equations = strip_comments(equations).replace("\\\r\n", " ").replace("\\\n", " ");
+
+    let mut dimensions: Vec<(String, Vec<String>)> = Vec::new();
+    let mut definitions = Vec::new();
+
+    for entry in equations.split('|') {
+        let mut fields = entry.split('~');
+        let definition = normalize(fields.next().unwrap_or(""));
+        let units = fields.next().map(parse_units).unwrap_or_default();
+        let comment = fields.next().map(normalize).filter(|c| !c.is_empty());
+
+        // Group headers look like "*****\n.Control\n*****"
+        if definition.is_empty() || definition.starts_with('*') {
+            continue;
+        }
+
+        // Data equations are supported when they read a spreadsheet
+        let definition = if definition.to_uppercase().contains("GET XLS DATA") {
+            definition.replacen(":=", "=", 1)
+        } else {
+            definition
+        };
+
+        match classify(&definition)? {
+            Definition::Range { name, elements } => {
+                let elements = elements.iter()
+                    .flat_map(|element| expand_numbered_range(element))
+                    .collect();
+                dimensions.push((name, elements));
+            }
+            Definition::Lookup { lhs, body } => definitions.push(VensimDefinition {
+                lhs,
+                rhs: body,
+                is_lookup: true,
+                units,
+                comment,
+            }),
+            Definition::Equation { lhs, rhs } => definitions.push(VensimDefinition {
+                lhs,
+                rhs,
+                is_lookup: false,
+                units,
+                comment,
+            }),
+        }
+    }
+
+    let ranges: HashMap<String, Vec<String>> = dimensions.iter().cloned().collect();
+
+    // Expand subscripts into scalar definitions
+    let mut scalars: Vec<(String, Scalar, Option<String>, Option<String>)> = Vec::new();
+    for definition in &definitions {
+        let (name, subscripts) = split_subscripts(&definition.lhs)?;
+        let expansions = expand(&subscripts, &ranges);
+
+        // `x[Region] = 1, 2` assigns one constant per element
+        let values: Vec<Option<f64>> = definition.rhs.split([',', ';'])
+            .map(|value| value.trim())
+            .filter(|value| !value.is_empty())
+            .map(|value| value.parse().ok())
+            .collect();
+        let element_values = expansions.len() > 1
+            && values.len() == expansions.len()
+            && values.iter().all(Option::is_some);
+
+        // Elements of an arrayed spreadsheet input read consecutive cells
+        let last_dimension = subscripts.last().and_then(|s| ranges.get(s)).map_or(1, Vec::len);
+
+        for (index, (suffix, bindings)) in expansions.iter().enumerate() {
+            let scalar_name = format!("{}{}", name, suffix);
+            let scalar = if element_values {
+                Scalar::Constant(values[index].unwrap_or_default())
+            } else if let Some((function, mut args)) = spreadsheet_call(&definition.rhs) {
+                if expansions.len() > 1 && args.len() > 2 {
+                    let (rows, columns) = spreadsheet_offset(function, &args, index, last_dimension);
+                    let cell = args.len() - 1;
+                    args[cell] = super::xlsx_reader::offset_cell(&args[cell], rows, columns)
+                        .map_err(|e| format!("Variable '{}': {}", scalar_name, e))?;
+                }
+                Scalar::Auxiliary(native_spreadsheet_call(function, &args))
+            } else if definition.is_lookup {
+                Scalar::Lookup(parse_points(&definition.rhs)
+                    .map_err(|e| format!("Lookup '{}': {}", scalar_name, e))?)
+            } else {
+                scalar_definition(&definition.rhs, bindings, &ranges)
+                    .map_err(|e| format!("Variable '{}': {}", scalar_name, e))?
+            };
+            scalars.push((scalar_name, scalar, definition.units.clone(), definition.comment.clone()));
+        }
+    }
+
+    build_model(dimensions, scalars)
+}
+
+/// Definition entry before subscript expansion
+struct VensimDefinition {
+    lhs: String,
+    rhs: String,
+    is_lookup: bool,
+    units: Option<String>,
+    comment: Option<String>,
+}
+
+enum Definition {
+    Range { name: String, elements: Vec<String> },
+    Lookup { lhs: String, body: String },
+    Equation { lhs: String, rhs: String },
+}
+
+/// A single scalar variable after subscript expansion
+enum Scalar {
+    Stock { rate: String, initial: String },
+    Constant(f64),
+    Lookup(Vec<(f64, f64)>),
+    /// Auxiliary reading an inline `WITH LOOKUP` table
+    WithLookup { input: String, points: Vec<(f64, f64)> },
+    Auxiliary(String),
+}
+
+const CONTROL_VARIABLES: [&str; 4] = ["INITIAL TIME", "FINAL TIME", "TIME STEP", "SAVEPER"];
+
+fn build_model(
+    dimensions: Vec<(String, Vec<String>)>,
+    scalars: Vec<(String, Scalar, Option<String>, Option<String>)>,
+) -> Result<Model, String> {
+    let mut model = Model::new("Untitled Model");
+
+    for (name, elements) in dimensions {
+        model.add_dimension(Dimension::new(&name, elements))?;
+    }
+
+    // Control variables set the time configuration; SAVEPER only counts when
+    // it is a number (it is usually written as `SAVEPER = TIME STEP`)
+    for (name, scalar, units, _) in &scalars {
+        let upper = name.to_uppercase();
+        if !CONTROL_VARIABLES.contains(&upper.as_str()) {
+            continue;
+        }
+        let value = match scalar {
+            Scalar::Constant(value) => Some(*value),
+            _ => None,
+        };
+        match (upper.as_str(), value) {
+            ("INITIAL TIME", Some(value)) => model.time.start = value,
+            ("FINAL TIME", Some(value)) => {
+                model.time.stop = value;
+                model.time.units = units.clone();
+            }
+            ("TIME STEP", Some(value)) => model.time.dt = value,
+            ("SAVEPER", value) => model.time.save_interval = value,
+            _ => return Err(format!("Control variable '{}' must be a number", name)),
+        }
+    }
+
+    let kinds: HashMap<&str, &Scalar> = scalars.iter()
+        .map(|(name, scalar, _, _)| (name.as_str(), scalar))
+        .collect();
+
+    // Decide stock inflows/outflows and which variables become flows
+    let mut stock_flows: HashMap<String, (Vec<String>, Vec<String>)> = HashMap::new();
+    let mut flow_names: Vec<String> = Vec::new();
+    let mut synthetic_flows: Vec<(String, String)> = Vec::new();
+
+    for (name, scalar, _, _) in &scalars {
+        let Scalar::Stock { rate, .. } = scalar else { continue };
+        let expression = Expression::parse(rate)
+            .map_err(|e| format!("Stock '{}': {}", name, e))?;
+
+        let mut terms = Vec::new();
+        let decomposed = rate_terms(&expression, true, &mut terms)
+            && terms.iter().all(|(term, _)| matches!(
+                kinds.get(term.as_str()),
+                Some(Scalar::Constant(_)) | Some(Scalar::Auxiliary(_)) | Some(Scalar::WithLookup { .. })
+            ));
+
+        let (mut inflows, mut outflows) = (Vec::new(), Vec::new());
+        if decomposed {
+            for (term, positive) in terms {
+                if !flow_names.contains(&term) {
+                    flow_names.push(term.clone());
+                }
+                if positive { inflows.push(term) } else { outflows.push(term) }
+            }
+        } else {
+            let flow = format!("{} net flow", name);
+            if kinds.contains_key(flow.as_str()) {
+                return Err(format!("Cannot create flow '{}': name already in use", flow));
+            }
+            inflows.push(flow.clone());
+            synthetic_flows.push((flow, rate.clone()));
+        }
+        stock_flows.insert(name.clone(), (inflows, outflows));
+    }
+
+    for (name, scalar, units, comment) in scalars {
+        if CONTROL_VARIABLES.contains(&name.to_uppercase().as_str()) {
+            if let Scalar::Constant(value) = scalar {
+                model.add_parameter(Parameter { name, value, units, description: comment, dimensions: None, values: None, min: None, max: None, schedule: None })?;
+            }
+            continue;
+        }
+
+        let is_flow = flow_names.contains(&name);
+        let context = |e: String| format!("Variable '{}': {}", name, e);
+        let equation = match scalar {
+            Scalar::Stock { initial, .. } => {
+                let (inflows, outflows) = stock_flows.remove(&name).unwrap_or_default();
+                let initial = Expression::parse(&initial).map_err(context)?;
+                model.add_stock(Stock {
+                    name,
+                    initial,
+                    inflows,
+                    outflows,
+                    units,
+                    non_negative: false,
+                    max_value: None,
+                    dimensions: None,
+                    kind: StockKind::Reservoir,
+                })?;
+                continue;
+            }
+            Scalar::Lookup(points) => {
+                model.add_lookup(LookupTable::new(name, points)?)?;
+                continue;
+            }
+            Scalar::Constant(value) if !is_flow => {
+                model.add_parameter(Parameter { name, value, units, description: comment, dimensions: None, values: None, min: None, max: None, schedule: None })?;
+                continue;
+            }
+            Scalar::Constant(value) => Expression::Constant(value),
+            Scalar::WithLookup { input, points } => {
+                let table = format!("{}_lookup", name);
+                model.add_lookup(LookupTable::new(table.clone(), points)?)?;
+                Expression::parse(&format!("LOOKUP(\"{}\", {})", table, input)).map_err(context)?
+            }
+            Scalar::Auxiliary(equation) => Expression::parse(&equation).map_err(context)?,
+        };
+
+        if is_flow {
+            model.add_flow(Flow { name, equation, units, dimensions: None, leak: false, non_negative: false })?;
+        } else {
+            model.add_auxiliary(Auxiliary { name, equation, units, dimensions: None })?;
+        }
+    }
+
+    for (name, rate) in synthetic_flows {
+        let equation = Expression::parse(&rate)?;
+        model.add_flow(Flow { name, equation, units: None, dimensions: None, leak: false, non_negative: false })?;
+    }
+
+    Ok(model)
+}
+
+const SPREADSHEET_FUNCTIONS: [&str; 3] = ["GET XLS CONSTANTS", "GET XLS DATA", "GET XLS LOOKUPS"];
+
+/// Function and unquoted arguments when `text` is one GET XLS call
+fn spreadsheet_call(text: &str) -> Option<(&'static str, Vec<String>)> {
+    SPREADSHEET_FUNCTIONS.into_iter().find_map(|function| {
+        let args = call_arguments(text, function)?;
+        Some((function, args.iter().map(|arg| arg.trim_matches(['\'', '"']).to_string()).collect()))
+    })
+}
+
+/// Cells between the first element of an arrayed GET XLS call and element `index`
+///
+/// Constants fill a block with the last subscript across each row; series
+/// sit side by side, each in the next row or column after the time axis.
+fn spreadsheet_offset(function: &str, args: &[String], index: usize, last_dimension: usize) -> (u32, u32) {
+    let index = index as u32;
+    if function == "GET XLS CONSTANTS" {
+        let width = last_dimension.max(1) as u32;
+        return (index / width, index % width);
+    }
+    let times_across_row = args.get(2).is_some_and(|axis| axis.trim().parse::<u32>().is_ok());
+    if times_across_row { (index, 0) } else { (0, index) }
+}
+
+fn native_spreadsheet_call(function: &str, args: &[String]) -> String {
+    let args: Vec<String> = args.iter().map(|arg| format!("\"{}\"", arg)).collect();
+    format!("{}({})", function.replace(' ', "_"), args.join(", "))
+}
+
+/// Collect `(variable, is_inflow)` terms of a rate made only of `+`/`-` over variables
+fn rate_terms(expr: &Expression, positive: bool, terms: &mut Vec<(String, bool)>) -> bool {
+    match expr {
+        Expression::Variable(name) => {
+            terms.push((name.clone(), positive));
+            true
+        }
+        Expression::BinaryOp { op: Operator::Add, left, right } => {
+            rate_terms(left, positive, terms) && rate_terms(right, positive, terms)
+        }
+        Expression::BinaryOp { op: Operator::Subtract, left, right } => {
+            rate_terms(left, positive, terms) && rate_terms(right, !positive, terms)
+        }
+        Expression::UnaryOp { op: UnaryOperator::Negate, expr } => rate_terms(expr, !positive, terms),
+        _ => false,
+    }
+}
+
+/// Classify a scalar right-hand side, substituting subscript bindings
+fn scalar_definition(
+    rhs: &str,
+    bindings: &HashMap<String, String>,
+    ranges: &HashMap<String, Vec<String>>,
+) -> Result<Scalar, String> {
+    if let Some(args) = call_arguments(rhs, "INTEG") {
+        let [rate, initial] = args.as_slice() else {
+            return Err(format!("INTEG expects 2 arguments, got {}", args.len()));
+        };
+        return Ok(Scalar::Stock {
+            rate: translate(&substitute_subscripts(rate, bindings, ranges))?,
+            initial: translate(&substitute_subscripts(initial, bindings, ranges))?,
+        });
+    }
+
+    if let Some(args) = call_arguments(rhs, "WITH LOOKUP") {
+        let [input, table] = args.as_slice() else {
+            return Err(format!("WITH LOOKUP expects 2 arguments, got {}", args.len()));
+        };
+        let table = table.trim();
+        let table = table.strip_prefix('(').and_then(|t| t.strip_suffix(')')).unwrap_or(table);
+        return Ok(Scalar::WithLookup {
+            input: translate(&substitute_subscripts(input, bindings, ranges))?,
+            points: parse_points(table)?,
+        });
+    }
+
+    if let Ok(value) = rhs.trim().parse::<f64>() {
+        return Ok(Scalar::Constant(value));
+    }
+
+    Ok(Scalar::Auxiliary(translate(&substitute_subscripts(rhs, bindings, ranges))?))
+}
+
+fn classify(definition: &str) -> Result<Definition, String> {
+    for unsupported in [":=", "<->", ":IGNORE:", ":EXCEPT:", ":INTERPOLATE:", ":RAW:", ":HOLD BACKWARD:", ":LOOK FORWARD:"] {
+        if definition.contains(unsupported) {
+            return Err(format!("Unsupported Vensim syntax '{}' in: {}", unsupported, definition));
+        }
+    }
+
+    let equals = top_level_position(definition, '=');
+    let colon = definition.find(':');
+    let paren = definition.find('(');
+
+    // Subscript range: "Region: North, South" (mapping "-> Other" ignored)
+    if let Some(colon) = colon
+        && equals.is_none_or(|e| colon < e)
+        && paren.is_none_or(|p| colon < p)
+    {
+        let name = normalize(&definition[..colon]);
+        let body = definition[colon + 1..].split("->").next().unwrap_or("");
+        let elements = body.split(',').map(normalize).filter(|e| !e.is_empty()).collect();
+        return Ok(Definition::Range { name, elements });
+    }
+
+    if let Some(equals) = equals {
+        return Ok(Definition::Equation {
+            lhs: normalize(&definition[..equals]),
+            rhs: definition[equals + 1..].trim().to_string(),
+        });
+    }
+
+    // Lookup: "name( [(x0,y0)-(x1,y1)], (x,y), ... )"
+    if let Some(paren) = paren
+        && definition.ends_with(')')
+    {
+        return Ok(Definition::Lookup {
+            lhs: normalize(&definition[..paren]),
+            body: definition[paren + 1..definition.len() - 1].to_string(),
+        });
+    }
+
+    Err(format!("Unrecognized Vensim definition: {}", definition))
+}
+
+/// Position of `target` outside parentheses and brackets
+fn top_level_position(text: &str, target: char) -> Option<usize> {
+    let mut depth = 0i32;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth -= 1,
+            c if c == target && depth == 0 => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Split `name[a, b]` into the name and its subscripts
+fn split_subscripts(lhs: &str) -> Result<(String, Vec<String>), String> {
+    match lhs.find('[') {
+        Some(open) => {
+            let close = lhs.rfind(']').ok_or_else(|| format!("Unclosed subscript in '{}'", lhs))?;
+            let subscripts = lhs[open + 1..close].split(',').map(normalize).collect();
+            Ok((normalize(&lhs[..open]), subscripts))
+        }
+        None => Ok((normalize(lhs), Vec::new())),
+    }
+}
+
+/// Name suffixes and dimension bindings for every element combination
+fn expand(subscripts: &[String], ranges: &HashMap<String, Vec<String>>) -> Vec<(String, HashMap<String, String>)> {
+    let mut expansions = vec![(String::new(), HashMap::new())];
+
+    for subscript in subscripts {
+        let elements = ranges.get(subscript).cloned().unwrap_or_else(|| vec![subscript.clone()]);
+        expansions = expansions.into_iter()
+            .flat_map(|(suffix, bindings)| {
+                elements.iter().map(move |element| {
+                    let mut bindings = bindings.clone();
+                    if ranges.contains_key(subscript) {
+                        bindings.insert(subscript.clone(), element.clone());
+                    }
+                    (format!("{}_{}", suffix, element), bindings)
+                })
+            })
+            .collect();
+    }
+
+    expansions
+}
+
+/// "(a1-a3)" expands to a1, a2, a3; other elements are returned unchanged
+fn expand_numbered_range(element: &str) -> Vec<String> {
+    let numbered = element.strip_prefix('(')
+        .and_then(|e| e.strip_suffix(')'))
+        .and_then(|e| e.split_once('-'));
+
+    if let Some((first, last)) = numbered {
+        let split = |s: &str| {
+            let digits = s.trim_end_matches(|c: char| c.is_ascii_digit());
+            s[digits.len()..].parse::<u32>().ok().map(|n| (digits.to_string(), n))
+        };
+        if let (Some((prefix, from)), Some((_, to))) = (split(first.trim()), split(last.trim())) {
+            return (from..=to).map(|n| format!("{}{}", prefix, n)).collect();
+        }
+    }
+    vec![element.to_string()]
+}
+
+/// Replace `x[Dim, Elem]` references with flattened scalar names `x_elem1_Elem`
+///
+/// A `Dim!` subscript (as in `SUM(x[Dim!])`) lists every element of the range,
+/// giving `x_a, x_b, ...` for the array function to reduce over.
+fn substitute_subscripts(
+    text: &str,
+    bindings: &HashMap<String, String>,
+    ranges: &HashMap<String, Vec<String>>,
+) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(open) = rest.find('[') {
+        let Some(close) = rest[open..].find(']').map(|c| open + c) else { break };
+        let before = rest[..open].trim_end();
+        let start = before.rfind(|c: char| "+-*/^(),=<>".contains(c)).map_or(0, |i| i + 1);
+        output.push_str(&before[..start]);
+
+        let mut names = vec![before[start..].to_string()];
+        for subscript in rest[open + 1..close].split(',') {
+            let bang = subscript.trim_end().ends_with('!');
+            let subscript = normalize(subscript.trim_end().trim_end_matches('!'));
+            let elements = match ranges.get(&subscript) {
+                Some(elements) if bang => elements.clone(),
+                _ => vec![bindings.get(&subscript).unwrap_or(&subscript).clone()],
+            };
+            names = names.iter()
+                .flat_map(|name| elements.iter().map(move |element| format!("{}_{}", name, element)))
+                .collect();
+        }
+        output.push_str(&names.join(", "));
+        rest = &rest[close + 1..];
+    }
+
+    output.push_str(rest);
+    output
+}
+
+/// Rewrite Vensim-only functions into the native expression syntax
+fn translate(text: &str) -> Result<String, String> {
+    for operator in [":AND:", ":OR:", ":NOT:"] {
+        if text.to_uppercase().contains(operator) {
+            return Err(format!("Logical operator {} is not supported", operator));
+        }
+    }
+
+    let mut output = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < text.len() {
+        let rest = &text[i..];
+        let at_word_start = text[..i].chars().next_back().is_none_or(|c| !c.is_alphanumeric() && c != '_');
+
+        if at_word_start
+            && let Some((name, args, consumed)) = rewrite_candidate(rest)
+        {
+            let args = args.iter().map(|arg| translate(arg)).collect::<Result<Vec<_>, _>>()?;
+            output.push_str(&match (name, args.as_slice()) {
+                ("IF THEN ELSE", [condition, then, otherwise]) => {
+                    format!("(IF {} THEN {} ELSE {})", condition, then, otherwise)
+                }
+                ("XIDZ", [a, b, x]) => format!("(IF ({b}) = 0 THEN ({x}) ELSE ({a}) / ({b}))"),
+                ("ZIDZ", [a, b]) => format!("(IF ({b}) = 0 THEN 0 ELSE ({a}) / ({b}))"),
+                // Vensim puts the initial value before the order
+                ("DELAY N", [input, time, initial, order]) => format!("DELAYN({input}, {time}, {order}, {initial})"),
+                ("SMOOTH N", [input, time, initial, order]) => format!("SMOOTHN({input}, {time}, {order}, {initial})"),
+                (name, args) => return Err(format!("{} got {} arguments", name, args.len())),
+            });
+            i += consumed;
+            continue;
+        }
+
+        let c = rest.chars().next().unwrap_or_default();
+        output.push(c);
+        i += c.len_utf8();
+    }
+
+    Ok(output)
+}
+
+/// Match a rewritten function call at the start of `text`: (name, args, bytes consumed)
+fn rewrite_candidate(text: &str) -> Option<(&'static str, Vec<String>, usize)> {
+    ["IF THEN ELSE", "XIDZ", "ZIDZ", "DELAY N", "SMOOTH N"].into_iter().find_map(|name| {
+        let head = text.get(..name.len())?;
+        if !head.eq_ignore_ascii_case(name) {
+            return None;
+        }
+        let after = &text[name.len()..];
+        let open = name.len() + after.len() - after.trim_start().len();
+        if !text[open..].starts_with('(') {
+            return None;
+        }
+        let close = matching_paren(text, open)?;
+        Some((name, split_arguments(&text[open + 1..close]), close + 1))
+    })
+}
+
+/// Arguments of `name(...)` when `text` is exactly one call to `name`
+fn call_arguments(text: &str, name: &str) -> Option<Vec<String>> {
+    let text = text.trim();
+    let head = text.get(..name.len())?;
+    if !head.eq_ignore_ascii_case(name) {
+        return None;
+    }
+    let open = text.find('(')?;
+    if !text[name.len()..open].trim().is_empty() || matching_paren(text, open)? != text.len() - 1 {
+        return None;
+    }
+    Some(split_arguments(&text[open + 1..text.len() - 1]))
+}
+
+fn matching_paren(text: &str, open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in text[open..].char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(open + i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Split on commas outside parentheses and brackets
+fn split_arguments(text: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut depth = 0i32;
+    let mut current = String::new();
+
+    for c in text.chars() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth -= 1,
+            ',' if depth == 0 => {
+                args.push(current.trim().to_string());
+                current.clear();
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    args.push(current.trim().to_string());
+    args
+}
+
+/// Parse lookup points, skipping the optional `[(xmin,ymin)-(xmax,ymax)]` range
+fn parse_points(body: &str) -> Result<Vec<(f64, f64)>, String> {
+    let body = body.trim();
+    let body = match body.strip_prefix('[') {
+        Some(range) => &range[range.find(']').ok_or("Unclosed lookup range")? + 1..],
+        None => body,
+    };
+
+    let mut points = Vec::new();
+    let mut rest = body;
+    while let Some(open) = rest.find('(') {
+        let close = rest[open..].find(')').map(|c| open + c).ok_or("Unclosed lookup point")?;
+        let pair = &rest[open + 1..close];
+        let (x, y) = pair.split_once(',').ok_or_else(|| format!("Invalid lookup point '({})'", pair))?;
+        let parse = |v: &str| v.trim().parse::<f64>()
+            .map_err(|_| format!("Invalid number '{}' in lookup point", v.trim()));
+        points.push((parse(x)?, parse(y)?));
+        rest = &rest[close + 1..];
+    }
+
+    if points.is_empty() {
+        return Err("Lookup has no points".to_string());
+    }
+    Ok(points)
+}
+
+/// Units field without the optional `[min,max,step]` range
+fn parse_units(field: &str) -> Option<String> {
+    let units = normalize(field.split('[').next().unwrap_or(""));
+    if units.is_empty() { None } else { Some(units) }
+}
+
+/// Remove `{...}` comments, including the `{UTF-8}` header
+fn strip_comments(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut depth = 0;
+    for c in text.chars() {
+        match c {
+            '{' => depth += 1,
+            '}' if depth > 0 => depth -= 1,
+            _ if depth == 0 => output.push(c),
+            _ => {}
+        }
+    }
+    output
+}
+
+/// Collapse runs of whitespace (including line breaks) to single spaces
+fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Write `model` as Vensim `.mdl` text
+///
+/// Stock `non_negative`/`max_value` constraints have no Vensim equivalent and
+/// are not written. Agent functions cannot be exported.
+pub fn write_vensim(model: &Model) -> Result<String, String> {
+    let mut out = String::from("{UTF-8}\n");
+
+    let mut dimensions: Vec<&Dimension> = model.dimensions.values().collect();
+    dimensions.sort_by(|a, b| a.name.cmp(&b.name));
+    for dimension in dimensions {
+        let elements: Vec<String> = dimension.elements.iter().map(|e| vensim_name(e)).collect();
+        write_entry(&mut out, &format!("{}:\n\t{}", vensim_name(&dimension.name), elements.join(", ")), None, None);
+    }
+
+    for name in sorted_keys(&model.stocks) {
+        let stock = &model.stocks[name];
+        let mut rate: Vec<String> = stock.inflows.iter().map(|f| vensim_name(f)).collect();
+        if rate.is_empty() && !stock.outflows.is_empty() {
+            rate.push("0".to_string());
+        }
+        let mut rate = rate.join(" + ");
+        for outflow in &stock.outflows {
+            rate.push_str(&format!(" - {}", vensim_name(outflow)));
+        }
+        if rate.is_empty() {
+            rate.push('0');
+        }
+        let initial = vensim_expression(&stock.initial).map_err(|e| format!("Stock '{}': {}", name, e))?;
+        let definition = format!("{}= INTEG (\n\t{},\n\t\t{})", vensim_name(name), rate, initial);
+        write_entry(&mut out, &definition, stock.units.as_deref(), None);
+    }
+
+    for name in sorted_keys(&model.flows) {
+        let flow = &model.flows[name];
+        let equation = vensim_expression(&flow.equation).map_err(|e| format!("Flow '{}': {}", name, e))?;
+        write_entry(&mut out, &format!("{}=\n\t{}", vensim_name(name), equation), flow.units.as_deref(), None);
+    }
+
+    for name in sorted_keys(&model.auxiliaries) {
+        let aux = &model.auxiliaries[name];
+        let equation = vensim_expression(&aux.equation).map_err(|e| format!("Auxiliary '{}': {}", name, e))?;
+        write_entry(&mut out, &format!("{}=\n\t{}", vensim_name(name), equation), aux.units.as_deref(), None);
+    }
+
+    for name in sorted_keys(&model.parameters) {
+        if CONTROL_VARIABLES.contains(&name.to_uppercase().as_str()) {
+            continue;
+        }
+        let parameter = &model.parameters[name];
+        let value = match &parameter.schedule {
+            None => parameter.value.to_string(),
+            Some(schedule) if schedule.interpolation == Interpolation::Linear => {
+                let pairs: Vec<String> = schedule.points.iter().map(|(t, v)| format!("({},{})", t, v)).collect();
+                format!("{} * WITH LOOKUP(Time, ({}))", parameter.value, pairs.join(","))
+            }
+            Some(_) => return Err(format!("Parameter '{}': only linear schedules have a Vensim equivalent", name)),
+        };
+        let definition = format!("{}=\n\t{}", vensim_name(name), value);
+        write_entry(&mut out, &definition, parameter.units.as_deref(), parameter.description.as_deref());
+    }
+
+    for name in sorted_keys(&model.lookups) {
+        let table = &model.lookups[name];
+        let (x_min, x_max) = table.points.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), p| (lo.min(p.0), hi.max(p.0)));
+        let (y_min, y_max) = table.points.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), p| (lo.min(p.1), hi.max(p.1)));
+        let points: Vec<String> = table.points.iter().map(|(x, y)| format!("({},{})", x, y)).collect();
+        let definition = format!(
+            "{}(\n\t[({},{})-({},{})],{})",
+            vensim_name(name), x_min, y_min, x_max, y_max, points.join(",")
+        );
+        write_entry(&mut out, &definition, None, None);
+    }
+
+    out.push_str("********************************************************\n\t.Control\n");
+    out.push_str("********************************************************~\n\t\tSimulation Control Parameters\n\t|\n\n");
+    let time_units = model.time.units.as_deref();
+    write_entry(&mut out, &format!("FINAL TIME  = {}", model.time.stop), time_units, Some("The final time for the simulation."));
+    write_entry(&mut out, &format!("INITIAL TIME  = {}", model.time.start), time_units, Some("The initial time for the simulation."));
+    let saveper = match model.time.save_interval {
+        Some(interval) => format!("SAVEPER  = {}", interval),
+        None => "SAVEPER  = \n\tTIME STEP".to_string(),
+    };
+    write_entry(&mut out, &saveper, time_units, Some("The frequency with which output is stored."));
+    write_entry(&mut out, &format!("TIME STEP  = {}", model.time.dt), time_units, Some("The time step for the simulation."));
+
+    out.push_str("\\\\\\---/// Sketch information - do not modify anything except names\n");
+    out.push_str("V300  Do not put anything below this section - it will be ignored\n*View 1\n");
+    Ok(out)
+}
+
+fn write_entry(out: &mut String, definition: &str, units: Option<&str>, comment: Option<&str>) {
+    out.push_str(&format!(
+        "{}\n\t~\t{}\n\t~\t{}\n\t|\n\n",
+        definition,
+        units.unwrap_or(""),
+        comment.unwrap_or("")
+    ));
+}
+
+fn sorted_keys<V>(map: &HashMap<String, V>) -> Vec<&String> {
+    let mut keys: Vec<&String> = map.keys().collect();
+    keys.sort();
+    keys
+}
+
+/// Quote names Vensim would not read as a bare identifier
+fn vensim_name(name: &str) -> String {
+    if name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == ' ') {
+        name.to_string()
+    } else {
+        format!("\"{}\"", name)
+    }
+}
+
+/// Render an expression in Vensim syntax
+fn vensim_expression(expr: &Expression) -> Result<String, String> {
+    Ok(match expr {
+        Expression::Constant(value) => value.to_string(),
+        Expression::Variable(name) => vensim_name(name),
+        Expression::StringLiteral { literal } => vensim_name(literal),
+        Expression::SubscriptedVariable { name, subscripts } => {
+            let subscripts: Vec<String> = subscripts.iter()
+                .map(|sub| match sub {
+                    SubscriptRef::Element(e) | SubscriptRef::Dimension(e) => Ok(vensim_name(e)),
+                    SubscriptRef::Wildcard => Ok("*".to_string()),
+                    SubscriptRef::Offset { .. } => Err(format!("Vensim has no subscript offsets ('{}')", expr)),
+                })
+                .collect::<Result<_, String>>()?;
+            format!("{}[{}]", vensim_name(name), subscripts.join(", "))
+        }
+        Expression::BinaryOp { op, left, right } => {
+            let op = match op {
+                Operator::Add => "+",
+                Operator::Subtract => "-",
+                Operator::Multiply => "*",
+                Operator::Divide => "/",
+                Operator::Power => "^",
+                Operator::GreaterThan => ">",
+                Operator::LessThan => "<",
+                Operator::GreaterEqual => ">=",
+                Operator::LessEqual => "<=",
+                Operator::Equal => "=",
+                Operator::NotEqual => "<>",
+            };
+            format!("({} {} {})", vensim_expression(left)?, op, vensim_expression(right)?)
+        }
+        Expression::UnaryOp { op: UnaryOperator::Negate, expr } => format!("(-{})", vensim_expression(expr)?),
+        Expression::Conditional { condition, true_expr, false_expr } => format!(
+            "IF THEN ELSE({}, {}, {})",
+            vensim_expression(condition)?,
+            vensim_expression(true_expr)?,
+            vensim_expression(false_expr)?
+        ),
+        Expression::FunctionCall { name, args, .. } => vensim_function(name, args)?,
+    })
+}
+
+fn vensim_function(name: &str, args: &[Expression]) -> Result<String, String> {
+    let rendered = args.iter().map(vensim_expression).collect::<Result<Vec<_>, _>>()?;
+    let upper = name.to_uppercase();
+
+    Ok(match (upper.as_str(), rendered.as_slice()) {
+        ("TIME", []) => "Time".to_string(),
+        ("POW", [base, exponent]) => format!("({} ^ {})", base, exponent),
+        ("MOD", _) => format!("MODULO({})", rendered.join(", ")),
+        ("DELAYP" | "DELAY_FIXED", _) => format!("DELAY FIXED({})", rendered.join(", ")),
+        ("DELAYN", [input, time, order]) => format!("DELAY N({input}, {time}, {input}, {order})"),
+        ("DELAYN", [input, time, order, initial]) => format!("DELAY N({input}, {time}, {initial}, {order})"),
+        ("SMOOTHN", [input, time, order]) => format!("SMOOTH N({input}, {time}, {input}, {order})"),
+        ("SMOOTHN", [input, time, order, initial]) => format!("SMOOTH N({input}, {time}, {initial}, {order})"),
+        ("RANDOM", []) => "RANDOM UNIFORM(0, 1, 0)".to_string(),
+        ("UNIFORM", [min, max]) => format!("RANDOM UNIFORM({}, {}, 0)", min, max),
+        ("LOOKUP", [table, input]) | ("WITH_LOOKUP", [input, table]) => format!("{}({})", table, input),
+        ("WITH_LOOKUP", [input, points @ ..]) if points.len() % 2 == 0 => {
+            let pairs: Vec<String> = points.chunks(2).map(|p| format!("({},{})", p[0], p[1])).collect();
+            format!("WITH LOOKUP({}, ({}))", input, pairs.join(","))
+        }
+        ("LOOKUP2D", _) => return Err("LOOKUP2D has no Vensim equivalent".to_string()),
+        ("WHITE_NOISE" | "PINK_NOISE" | "RANDOM_WALK" | "AR1" | "OU_PROCESS", _) => {
+            return Err(format!("{} has no Vensim equivalent", name));
+        }
+        ("SINWAVE", [amplitude, period]) => format!("{} * SIN(2 * 3.14159265358979 * Time / {})", amplitude, period),
+        ("COSWAVE", [amplitude, period]) => format!("{} * COS(2 * 3.14159265358979 * Time / {})", amplitude, period),
+        ("SAWTOOTH", [amplitude, period]) => format!("{} * MODULO(Time, {}) / {}", amplitude, period, period),
+        ("SEASONAL", [table, period]) => format!("{}(MODULO(Time, {}))", table, period),
+        (agent, _) if agent.starts_with("AGENT_") => {
+            return Err(format!("{} has no Vensim equivalent", name));
+        }
+        _ => format!("{}({})", name, rendered.join(", ")),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::{SimulationConfig, SimulationEngine};
+
+    const POPULATION: &str = r#"{UTF-8}
+Population= INTEG (
+	births-deaths,
+		100)
+	~	people
+	~	Total population.
+	|
+
+births=
+	Population*birth rate*effect of crowding(Population/capacity)
+	~	people/Year
+	~		|
+
+deaths=
+	Population/lifetime
+	~	people/Year
+	~		|
+
+birth rate=
+	0.1
+	~	1/Year [0,1,0.01]
+	~		|
+
+lifetime=
+	IF THEN ELSE(Time > 50, 20, 40)
+	~	Year
+	~		|
+
+capacity=
+	1000
+	~	people
+	~		|
+
+effect of crowding(
+	[(0,0)-(2,1)],(0,1),(1,0.5),(2,0))
+	~	dmnl
+	~		|
+
+********************************************************
+	.Control
+********************************************************~
+		Simulation Control Parameters
+	|
+
+FINAL TIME  = 100
+	~	Year
+	~	The final time for the simulation.
+	|
+
+INITIAL TIME  = 0
+	~	Year
+	~	The initial time for the simulation.
+	|
+
+SAVEPER  =
+        TIME STEP
+	~	Year [0,?]
+	~	The frequency with which output is stored.
+	|
+
+TIME STEP  = 0.5
+	~	Year [0,?]
+	~	The time step for the simulation.
+	|
+
+\\\---/// Sketch information - do not modify anything except names
+V300  Do not put anything below this section - it will be ignored
+*View 1
+"#;
+
+    #[test]
+    fn test_parse_population_model() {
+        let model = parse_vensim(POPULATION).unwrap();
+
+        assert_eq!((model.time.start, model.time.stop, model.time.dt), (0.0, 100.0, 0.5));
+        assert_eq!(model.time.save_interval, None);
+        assert_eq!(model.time.units.as_deref(), Some("Year"));
+
+        let population = &model.stocks["Population"];
+        assert_eq!(population.inflows, vec!["births".to_string()]);
+        assert_eq!(population.outflows, vec!["deaths".to_string()]);
+        assert_eq!(population.units.as_deref(), Some("people"));
+
+        assert!(model.flows.contains_key("births") && model.flows.contains_key("deaths"));
+        assert_eq!(model.parameters["birth rate"].value, 0.1);
+        assert_eq!(model.parameters["birth rate"].units.as_deref(), Some("1/Year"));
+        assert!(model.auxiliaries.contains_key("lifetime"));
+        assert_eq!(model.lookups["effect of crowding"].points.len(), 3);
+
+        let results = SimulationEngine::new(model, SimulationConfig::default()).unwrap().run().unwrap();
+        let population = results.get_variable_series("Population").unwrap();
+        // First Euler step: 100 + 0.5 * (100 * 0.1 * 0.95 - 100 / 40)
+        assert!((population[1] - 103.5).abs() < 1e-9, "{}", population[1]);
+    }
+
+    #[test]
+    fn test_parse_subscripts_and_inline_lookup() {
+        let mdl = r#"
+Region:
+	North, South
+	~	~	|
+Stock[Region]= INTEG (growth[Region], initial[Region])
+	~	widgets ~ |
+initial[Region]=
+	10, 20
+	~	widgets ~ |
+growth[Region]=
+	Stock[Region] * rate
+	~	widgets/Month ~ |
+rate=
+	WITH LOOKUP(Time, ([(0,0)-(10,1)],(0,0.1),(10,0.2)))
+	~	1/Month ~ |
+total=
+	SUM(Stock[Region!])
+	~	widgets ~ |
+"#;
+        let model = parse_vensim(mdl).unwrap();
+
+        assert_eq!(model.dimensions["Region"].elements, vec!["North".to_string(), "South".to_string()]);
+        assert_eq!(model.parameters["initial_South"].value, 20.0);
+        assert_eq!(model.stocks["Stock_North"].inflows, vec!["growth_North".to_string()]);
+        assert!(model.flows.contains_key("growth_South"));
+        assert!(model.lookups.contains_key("rate_lookup"));
+        assert_eq!(model.auxiliaries["total"].equation.to_string(), "SUM(Stock_North, Stock_South)");
+
+        let state = crate::simulation::SimulationState::initialize_from_model(&model).unwrap();
+        assert_eq!(state.stocks["Stock_South"], 20.0);
+    }
+
+    #[test]
+    fn test_parse_get_xls() {
+        let mdl = r#"
+Region:
+	North, South
+	~	~	|
+capacity[Region]=
+	GET XLS CONSTANTS('inputs.xlsx', 'Params', 'B2')
+	~	widgets ~ |
+demand:=
+	GET XLS DATA('inputs.xlsx', 'Series', 'A', 'B2')
+	~	widgets/Month ~ |
+effect(
+	GET XLS LOOKUPS('inputs.xlsx', 'Tables', '1', 'B2'))
+	~	~ |
+"#;
+        let mut model = parse_vensim(mdl).unwrap();
+
+        let equation = |name: &str| model.auxiliaries[name].equation.to_string();
+        assert_eq!(equation("capacity_South"), r#"GET_XLS_CONSTANTS("inputs.xlsx", "Params", "C2")"#);
+        assert_eq!(equation("demand"), r#"GET_XLS_DATA("inputs.xlsx", "Series", "A", "B2")"#);
+        assert_eq!(equation("effect"), r#"GET_XLS_LOOKUPS("inputs.xlsx", "Tables", "1", "B2")"#);
+
+        // The workbook does not exist (or spreadsheet support is not compiled in)
+        let err = super::super::xlsx_reader::resolve(&mut model, std::path::Path::new("missing")).unwrap_err();
+        assert!(err.starts_with("Variable 'capacity_North'"), "{}", err);
+    }
+
+    #[test]
+    fn test_write_vensim_round_trip() {
+        let model = parse_vensim(POPULATION).unwrap();
+        let written = write_vensim(&model).unwrap();
+        assert_eq!(written, r#"{UTF-8}
+Population= INTEG (
+	births - deaths,
+		100)
+	~	people
+	~	
+	|
+
+births=
+	((Population * birth rate) * effect of crowding((Population / capacity)))
+	~	people/Year
+	~	
+	|
+
+deaths=
+	(Population / lifetime)
+	~	people/Year
+	~	
+	|
+
+lifetime=
+	IF THEN ELSE((Time > 50), 20, 40)
+	~	Year
+	~	
+	|
+
+birth rate=
+	0.1
+	~	1/Year
+	~	
+	|
+
+capacity=
+	1000
+	~	people
+	~	
+	|
+
+effect of crowding(
+	[(0,0)-(2,1)],(0,1),(1,0.5),(2,0))
+	~	
+	~	
+	|
+
+********************************************************
+	.Control
+********************************************************~
+		Simulation Control Parameters
+	|
+
+FINAL TIME  = 100
+	~	Year
+	~	The final time for the simulation.
+	|
+
+INITIAL TIME  = 0
+	~	Year
+	~	The initial time for the simulation.
+	|
+
+SAVEPER  = 
+	TIME STEP
+	~	Year
+	~	The frequency with which output is stored.
+	|
+
+TIME STEP  = 0.5
+	~	Year
+	~	The time step for the simulation.
+	|
+
+\\\---/// Sketch information - do not modify anything except names
+V300  Do not put anything below this section - it will be ignored
+*View 1
+"#);
+
+        let reloaded = parse_vensim(&written).unwrap();
+        let run = |model: Model| SimulationEngine::new(model, SimulationConfig::default()).unwrap().run().unwrap();
+        let (expected, actual) = (run(model), run(reloaded));
+        assert_eq!(expected.get_variable_series("Population"), actual.get_variable_series("Population"));
+    }
+
+    #[test]
+    fn test_translate_functions() {
+        assert_eq!(translate("IF THEN ELSE(a > 1, XIDZ(b, c, 0), 2)").unwrap(),
+            "(IF a > 1 THEN (IF (c) = 0 THEN (0) ELSE (b) / (c)) ELSE 2)");
+        assert_eq!(translate("DELAY N(shipments, 6, 100, order)").unwrap(), "DELAYN(shipments, 6, order, 100)");
+        assert!(translate("a :AND: b").is_err());
+        assert!(parse_vensim("x := 1 ~ ~ |").is_err());
+    }
+}
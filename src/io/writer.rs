@@ -1,9 +1,10 @@
 /// Result writers for various formats
 
 use std::fs::File;
-use std::io::Write;
+use std::io::{BufWriter, Write};
 use std::path::Path;
-use crate::simulation::SimulationResults;
+use crate::model::Calendar;
+use crate::simulation::{AgentSnapshots, AttributeHistogram, ResultSink, SimulationResults, SimulationState};
 
 pub trait ResultWriter {
     fn write_file<P: AsRef<Path>>(results: &SimulationResults, path: P) -> Result<(), String>;
@@ -13,64 +14,185 @@ pub struct CsvWriter;
 
 impl CsvWriter {
     pub fn write_file<P: AsRef<Path>>(results: &SimulationResults, path: P) -> Result<(), String> {
-        let mut file = File::create(path)
-            .map_err(|e| format!("Failed to create file: {}", e))?;
-
-        if results.states.is_empty() {
+        if results.is_empty() {
             return Err("No results to write".to_string());
         }
 
-        // Get all variable names from first state
-        let first_state = &results.states[0];
-        let mut var_names: Vec<String> = Vec::new();
-
-        // Collect stocks
-        let mut stock_names: Vec<_> = first_state.stocks.keys().cloned().collect();
-        stock_names.sort();
-        var_names.extend(stock_names);
-
-        // Collect flows
-        let mut flow_names: Vec<_> = first_state.flows.keys().cloned().collect();
-        flow_names.sort();
-        var_names.extend(flow_names);
+        let file = File::create(path)
+            .map_err(|e| format!("Failed to create file: {}", e))?;
+        Self::write(results, BufWriter::new(file))
+    }
 
-        // Collect auxiliaries
-        let mut aux_names: Vec<_> = first_state.auxiliaries.keys().cloned().collect();
-        aux_names.sort();
-        var_names.extend(aux_names);
+    /// Write buffered results, with the same columns a [`CsvSink`] writes
+    pub fn write<W: Write>(results: &SimulationResults, mut out: W) -> Result<(), String> {
+        let columns: Vec<(&String, &[f64])> = results.columns().collect();
 
-        // Write header
-        write!(file, "Time")
+        write!(out, "Time")
             .map_err(|e| format!("Write error: {}", e))?;
-        for var_name in &var_names {
-            write!(file, ",{}", var_name)
+        for (name, _) in &columns {
+            write!(out, ",{}", name)
                 .map_err(|e| format!("Write error: {}", e))?;
         }
-        writeln!(file)
+        writeln!(out)
             .map_err(|e| format!("Write error: {}", e))?;
 
-        // Write data rows
-        for (i, state) in results.states.iter().enumerate() {
-            write!(file, "{}", results.times[i])
+        for (row, time) in results.times.iter().enumerate() {
+            write!(out, "{}", format_time(*time, results.calendar.as_ref())?)
+                .map_err(|e| format!("Write error: {}", e))?;
+            for (_, series) in &columns {
+                write!(out, ",{}", series[row])
+                    .map_err(|e| format!("Write error: {}", e))?;
+            }
+            writeln!(out)
                 .map_err(|e| format!("Write error: {}", e))?;
+        }
 
-            for var_name in &var_names {
-                let value = state.stocks.get(var_name)
-                    .or_else(|| state.flows.get(var_name))
-                    .or_else(|| state.auxiliaries.get(var_name))
-                    .unwrap_or(&0.0);
+        out.flush()
+            .map_err(|e| format!("Write error: {}", e))
+    }
 
-                write!(file, ",{}", value)
-                    .map_err(|e| format!("Write error: {}", e))?;
+    /// Write agent snapshots, one row per agent and time:
+    /// `time,agent_type,agent_id,state`, then a column per attribute
+    ///
+    /// Agent types and states holding commas or quotes are quoted.
+    pub fn write_agents<P: AsRef<Path>>(snapshots: &AgentSnapshots, path: P) -> Result<(), String> {
+        let mut out = csv::Writer::from_path(path)
+            .map_err(|e| format!("Failed to create file: {}", e))?;
+        let error = |e: csv::Error| format!("Write error: {}", e);
+
+        let mut header = vec!["time", "agent_type", "agent_id", "state"];
+        header.extend(snapshots.attributes.iter().map(String::as_str));
+        out.write_record(&header).map_err(error)?;
+
+        for row in 0..snapshots.len() {
+            let mut record = vec![
+                snapshots.time[row].to_string(),
+                snapshots.agent_type[row].clone(),
+                snapshots.agent_id[row].to_string(),
+                snapshots.state[row].clone(),
+            ];
+            record.extend(snapshots.values.iter().map(|values| values[row].to_string()));
+            out.write_record(&record).map_err(error)?;
+        }
+        out.flush().map_err(|e| format!("Write error: {}", e))
+    }
+
+    /// Write histograms in long format: `attribute,agent_type,time,bin_min,bin_max,count`
+    pub fn write_histograms<P: AsRef<Path>>(histograms: &[AttributeHistogram], path: P) -> Result<(), String> {
+        let mut out = csv::Writer::from_path(path)
+            .map_err(|e| format!("Failed to create file: {}", e))?;
+        let error = |e: csv::Error| format!("Write error: {}", e);
+
+        out.write_record(["attribute", "agent_type", "time", "bin_min", "bin_max", "count"]).map_err(error)?;
+        for histogram in histograms {
+            let agent_type = histogram.agent_type.as_deref().unwrap_or("");
+            for (time, counts) in histogram.times.iter().zip(&histogram.counts) {
+                for (bin, count) in counts.iter().enumerate() {
+                    out.write_record([
+                        histogram.attribute.clone(),
+                        agent_type.to_string(),
+                        time.to_string(),
+                        histogram.edges[bin].to_string(),
+                        histogram.edges[bin + 1].to_string(),
+                        count.to_string(),
+                    ]).map_err(error)?;
+                }
             }
-            writeln!(file)
+        }
+        out.flush().map_err(|e| format!("Write error: {}", e))
+    }
+}
+
+/// A time as written to CSV: an ISO date when the model has a calendar
+fn format_time(time: f64, calendar: Option<&Calendar>) -> Result<String, String> {
+    match calendar {
+        Some(calendar) => calendar.format(time),
+        None => Ok(time.to_string()),
+    }
+}
+
+/// Streaming CSV output
+///
+/// Rows are written as the engine records them; the header is taken from the
+/// first state (stocks, then flows, then auxiliaries, each sorted by name).
+pub struct CsvSink<W: Write> {
+    out: W,
+    columns: Option<Vec<String>>,
+    calendar: Option<Calendar>,
+}
+
+impl CsvSink<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let file = File::create(path)
+            .map_err(|e| format!("Failed to create file: {}", e))?;
+        Ok(Self::new(BufWriter::new(file)))
+    }
+}
+
+impl<W: Write> CsvSink<W> {
+    pub fn new(out: W) -> Self {
+        Self { out, columns: None, calendar: None }
+    }
+
+    /// Write times as dates of `calendar`
+    pub fn with_calendar(mut self, calendar: Option<Calendar>) -> Self {
+        self.calendar = calendar;
+        self
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    fn write_header(&mut self, state: &SimulationState) -> Result<(), String> {
+        let mut columns = Vec::new();
+        for names in [&state.stocks, &state.flows, &state.auxiliaries] {
+            let mut names: Vec<String> = names.keys().cloned().collect();
+            names.sort();
+            columns.extend(names);
+        }
+
+        write!(self.out, "Time")
+            .map_err(|e| format!("Write error: {}", e))?;
+        for column in &columns {
+            write!(self.out, ",{}", column)
                 .map_err(|e| format!("Write error: {}", e))?;
         }
+        writeln!(self.out)
+            .map_err(|e| format!("Write error: {}", e))?;
 
+        self.columns = Some(columns);
         Ok(())
     }
 }
 
+impl<W: Write> ResultSink for CsvSink<W> {
+    fn record(&mut self, time: f64, state: &SimulationState) -> Result<(), String> {
+        if self.columns.is_none() {
+            self.write_header(state)?;
+        }
+
+        write!(self.out, "{}", format_time(time, self.calendar.as_ref())?)
+            .map_err(|e| format!("Write error: {}", e))?;
+        for column in self.columns.iter().flatten() {
+            let value = state.stocks.get(column)
+                .or_else(|| state.flows.get(column))
+                .or_else(|| state.auxiliaries.get(column))
+                .unwrap_or(&0.0);
+
+            write!(self.out, ",{}", value)
+                .map_err(|e| format!("Write error: {}", e))?;
+        }
+        writeln!(self.out)
+            .map_err(|e| format!("Write error: {}", e))
+    }
+
+    fn finish(&mut self) -> Result<(), String> {
+        self.out.flush()
+            .map_err(|e| format!("Write error: {}", e))
+    }
+}
+
 impl ResultWriter for CsvWriter {
     fn write_file<P: AsRef<Path>>(results: &SimulationResults, path: P) -> Result<(), String> {
         Self::write_file(results, path)
@@ -103,3 +225,92 @@ impl ResultWriter for JsonWriter {
         Self::write_file(results, path)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Model, Stock, Flow, Parameter};
+    use crate::simulation::{SimulationEngine, SimulationConfig};
+
+    fn growth_model() -> Model {
+        let mut model = Model::new("Test");
+        model.time.start = 0.0;
+        model.time.stop = 5.0;
+        model.time.dt = 1.0;
+
+        model.add_stock(Stock::new("Population", "100")).unwrap();
+        model.add_parameter(Parameter::new("growth_rate", 0.1)).unwrap();
+        model.add_flow(Flow::new("growth", "Population * growth_rate")).unwrap();
+        model.stocks.get_mut("Population").unwrap().inflows.push("growth".to_string());
+        model
+    }
+
+    #[test]
+    fn test_streamed_csv_matches_buffered() {
+        let results = SimulationEngine::new(growth_model(), SimulationConfig::default())
+            .unwrap().run().unwrap();
+        let mut buffered = Vec::new();
+        CsvWriter::write(&results, &mut buffered).unwrap();
+
+        let mut streamed = CsvSink::new(Vec::new());
+        let recorded = SimulationEngine::new(growth_model(), SimulationConfig::default())
+            .unwrap().run_into(&mut streamed).unwrap();
+
+        let csv = String::from_utf8(streamed.into_inner()).unwrap();
+        assert_eq!(recorded, results.times.len());
+        assert_eq!(csv, String::from_utf8(buffered).unwrap());
+        assert!(csv.starts_with("Time,Population,growth\n0,100,"));
+        assert_eq!(csv.lines().count(), 7);
+    }
+
+    #[test]
+    fn test_csv_dates() {
+        let mut model = growth_model();
+        model.time.calendar = Some(Calendar::new(chrono::NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(), crate::model::CalendarUnit::Months));
+        let calendar = model.time.calendar.clone();
+        let results = SimulationEngine::new(model.clone(), SimulationConfig::default()).unwrap().run().unwrap();
+        let mut buffered = Vec::new();
+        CsvWriter::write(&results, &mut buffered).unwrap();
+
+        let mut streamed = CsvSink::new(Vec::new()).with_calendar(calendar);
+        SimulationEngine::new(model, SimulationConfig::default()).unwrap().run_into(&mut streamed).unwrap();
+        let csv = String::from_utf8(streamed.into_inner()).unwrap();
+        assert_eq!(csv, String::from_utf8(buffered).unwrap());
+        let times: Vec<&str> = csv.lines().skip(1).map(|line| line.split(',').next().unwrap()).collect();
+        assert_eq!(times, ["2024-01-01", "2024-02-01", "2024-03-01", "2024-04-01", "2024-05-01", "2024-06-01"]);
+    }
+
+    #[test]
+    fn test_agent_csv() {
+        let snapshots = AgentSnapshots {
+            time: vec![0.0, 1.5],
+            agent_type: vec!["Firm, Inc".to_string(), "Household".to_string()],
+            agent_id: vec![1, 2],
+            state: vec!["said \"hi\"".to_string(), String::new()],
+            attributes: vec!["wealth".to_string()],
+            values: vec![vec![10.0, 2.5]],
+        };
+        let histograms = [AttributeHistogram {
+            attribute: "wealth".to_string(),
+            agent_type: Some("Firm, Inc".to_string()),
+            edges: vec![0.0, 5.0, 10.0],
+            times: vec![0.0],
+            counts: vec![vec![1, 3]],
+        }];
+        let dir = std::env::temp_dir().join(format!("rssdsim_agent_csv_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        CsvWriter::write_agents(&snapshots, dir.join("agents.csv")).unwrap();
+        CsvWriter::write_histograms(&histograms, dir.join("histograms.csv")).unwrap();
+
+        let agents = std::fs::read_to_string(dir.join("agents.csv")).unwrap();
+        assert_eq!(agents, "time,agent_type,agent_id,state,wealth\n0,\"Firm, Inc\",1,\"said \"\"hi\"\"\",10\n1.5,Household,2,,2.5\n");
+        let rows: Vec<csv::StringRecord> = csv::Reader::from_path(dir.join("agents.csv")).unwrap()
+            .records().collect::<Result<_, _>>().unwrap();
+        assert_eq!(&rows[0][1], "Firm, Inc");
+        assert_eq!(&rows[0][3], "said \"hi\"");
+
+        let histograms = std::fs::read_to_string(dir.join("histograms.csv")).unwrap();
+        assert_eq!(histograms, "attribute,agent_type,time,bin_min,bin_max,count\nwealth,\"Firm, Inc\",0,0,5,1\nwealth,\"Firm, Inc\",0,5,10,3\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
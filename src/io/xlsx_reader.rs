@@ -0,0 +1,262 @@
+/// Spreadsheet input for Vensim's GET XLS functions
+///
+/// Equations that are a single call to one of these functions are replaced by
+/// the values they read when the model is loaded:
+/// - `GET_XLS_CONSTANTS("file", "sheet", "B2")` becomes a parameter
+/// - `GET_XLS_DATA("file", "sheet", "A", "B2")` becomes a data variable
+/// - `GET_XLS_LOOKUPS("file", "sheet", "1", "B2")` becomes a lookup table
+///
+/// For series, the third argument is the column letter (times run down the
+/// column) or row number (times run across the row) holding the times or x
+/// values, and the fourth is the first value cell. Reading spreadsheets
+/// (.xlsx, .xlsm, .xlsb, .xls, .ods) needs the `with-xlsx` feature.
+
+use std::path::Path;
+use crate::model::{Auxiliary, DataVariable, Expression, Model, Parameter};
+use crate::simulation::LookupTable;
+
+/// Read every GET_XLS_* call in `model`, resolving files against `base_dir`
+pub fn resolve(model: &mut Model, base_dir: &Path) -> Result<(), String> {
+    // A flow keeps its place in the stock-flow structure and reads the call
+    // through an auxiliary
+    let mut flows: Vec<String> = model.flows.iter()
+        .filter(|(_, flow)| spreadsheet_call(&flow.equation).is_some())
+        .map(|(name, _)| name.clone())
+        .collect();
+    flows.sort();
+    for name in flows {
+        let source = format!("{} data", name);
+        let Some(flow) = model.flows.get_mut(&name) else { continue };
+        let equation = std::mem::replace(&mut flow.equation, Expression::Variable(source.clone()));
+        model.add_auxiliary(Auxiliary::new(&source, "0").with_equation(equation))?;
+    }
+
+    let mut auxiliaries: Vec<String> = model.auxiliaries.iter()
+        .filter(|(_, aux)| spreadsheet_call(&aux.equation).is_some())
+        .map(|(name, _)| name.clone())
+        .collect();
+    auxiliaries.sort();
+
+    for name in auxiliaries {
+        let Some(aux) = model.auxiliaries.remove(&name) else { continue };
+        let Some((function, args)) = spreadsheet_call(&aux.equation) else { continue };
+        let context = |e: String| format!("Variable '{}': {}", name, e);
+
+        match function.as_str() {
+            "GET_XLS_CONSTANTS" => {
+                let [file, sheet, cell] = arguments(&function, args).map_err(context)?;
+                let value = read_constant(&base_dir.join(file), sheet, cell).map_err(context)?;
+                let mut parameter = Parameter::new(&name, value);
+                parameter.units = aux.units;
+                model.add_parameter(parameter)?;
+            }
+            "GET_XLS_DATA" => {
+                // Read along with the model's other data variables
+                let [file, sheet, time_at, cell] = arguments(&function, args).map_err(context)?;
+                let mut data = DataVariable::new(&name, file).with_time_column(time_at);
+                data.sheet = Some(sheet.to_string());
+                data.cell = Some(cell.to_string());
+                data.units = aux.units;
+                model.add_data(data)?;
+            }
+            _ => {
+                let [file, sheet, x_at, cell] = arguments(&function, args).map_err(context)?;
+                let points = read_series(&base_dir.join(file), Some(sheet), x_at, cell).map_err(context)?;
+                model.add_lookup(LookupTable::new(name.clone(), points).map_err(context)?)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Name and arguments of an equation that is one GET_XLS_* call
+fn spreadsheet_call(equation: &Expression) -> Option<(String, &[Expression])> {
+    match equation {
+        Expression::FunctionCall { name, args, .. } => {
+            let name = name.to_uppercase();
+            matches!(name.as_str(), "GET_XLS_CONSTANTS" | "GET_XLS_DATA" | "GET_XLS_LOOKUPS")
+                .then_some((name, args.as_slice()))
+        }
+        _ => None,
+    }
+}
+
+fn arguments<'a, const N: usize>(function: &str, args: &'a [Expression]) -> Result<[&'a str; N], String> {
+    let strings: Vec<&str> = args.iter()
+        .map(|arg| match arg {
+            Expression::StringLiteral { literal } => Ok(literal.as_str()),
+            _ => Err(format!("{} arguments must be quoted strings", function)),
+        })
+        .collect::<Result<_, _>>()?;
+    strings.try_into()
+        .map_err(|strings: Vec<&str>| format!("{} expects {} arguments, got {}", function, N, strings.len()))
+}
+
+/// Zero-based (row, column) of a cell reference such as `B2`
+pub fn cell_position(cell: &str) -> Result<(u32, u32), String> {
+    let cell = cell.trim().trim_end_matches('*').replace('$', "");
+    let split = cell.find(|c: char| c.is_ascii_digit()).unwrap_or(cell.len());
+    let (letters, digits) = cell.split_at(split);
+    let column = column_index(letters);
+    let row = digits.parse::<u32>().ok().filter(|&row| row > 0);
+    match (row, column) {
+        (Some(row), Some(column)) => Ok((row - 1, column)),
+        _ => Err(format!("Invalid cell reference '{}'", cell)),
+    }
+}
+
+/// Zero-based index of a column letter such as `A` or `AB`
+fn column_index(letters: &str) -> Option<u32> {
+    if letters.is_empty() || !letters.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+    Some(letters.to_ascii_uppercase().bytes().fold(0, |index, b| index * 26 + u32::from(b - b'A' + 1)) - 1)
+}
+
+/// Cell reference `rows` below and `columns` right of `cell`
+pub fn offset_cell(cell: &str, rows: u32, columns: u32) -> Result<String, String> {
+    let (row, column) = cell_position(cell)?;
+    let mut column = column + columns + 1;
+    let mut letters = Vec::new();
+    while column > 0 {
+        let remainder = (column - 1) % 26;
+        letters.push(char::from(b'A' + remainder as u8));
+        column = (column - 1) / 26;
+    }
+    Ok(format!("{}{}", letters.iter().rev().collect::<String>(), row + rows + 1))
+}
+
+/// Row or column holding the times of a series
+enum Axis {
+    Row(u32),
+    Column(u32),
+}
+
+fn parse_axis(text: &str) -> Result<Axis, String> {
+    let text = text.trim();
+    if let Ok(row) = text.parse::<u32>()
+        && row > 0
+    {
+        return Ok(Axis::Row(row - 1));
+    }
+    column_index(text).map(Axis::Column)
+        .ok_or_else(|| format!("Invalid time row or column '{}'", text))
+}
+
+/// (time, value) points starting at `start`, until the times run out
+///
+/// Cells without a value are skipped.
+fn series_from_grid<F>(cell: F, axis: &Axis, start: (u32, u32)) -> Vec<(f64, f64)>
+where
+    F: Fn(u32, u32) -> Option<f64>,
+{
+    let mut points = Vec::new();
+    for step in 0.. {
+        let (time, value) = match *axis {
+            Axis::Row(row) => (cell(row, start.1 + step), cell(start.0, start.1 + step)),
+            Axis::Column(column) => (cell(start.0 + step, column), cell(start.0 + step, start.1)),
+        };
+        match (time, value) {
+            (None, _) => break,
+            (Some(time), Some(value)) => points.push((time, value)),
+            (Some(_), None) => {}
+        }
+    }
+    points
+}
+
+/// Value of one cell
+pub fn read_constant(path: &Path, sheet: &str, cell: &str) -> Result<f64, String> {
+    let (row, column) = cell_position(cell)?;
+    let grid = read_sheet(path, Some(sheet))?;
+    grid(row, column).ok_or_else(|| format!("{} {}!{} is not a number", path.display(), sheet, cell))
+}
+
+/// (time, value) points with times in `time_at` and values from `cell` on
+///
+/// `sheet` defaults to the first sheet.
+pub fn read_series(path: &Path, sheet: Option<&str>, time_at: &str, cell: &str) -> Result<Vec<(f64, f64)>, String> {
+    let axis = parse_axis(time_at)?;
+    let start = cell_position(cell)?;
+    let grid = read_sheet(path, sheet)?;
+    Ok(series_from_grid(grid, &axis, start))
+}
+
+/// Numeric cells of a sheet, by zero-based (row, column)
+#[cfg(feature = "with-xlsx")]
+fn read_sheet(path: &Path, sheet: Option<&str>) -> Result<impl Fn(u32, u32) -> Option<f64>, String> {
+    use calamine::{open_workbook_auto, DataType, Reader};
+
+    let mut workbook = open_workbook_auto(path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let sheet = match sheet {
+        Some(sheet) => sheet.to_string(),
+        None => workbook.sheet_names().first().cloned()
+            .ok_or_else(|| format!("{} has no sheets", path.display()))?,
+    };
+    let range = workbook.worksheet_range(&sheet)
+        .map_err(|e| format!("Failed to read sheet '{}' of {}: {}", sheet, path.display(), e))?;
+
+    Ok(move |row, column| range.get_value((row, column)).and_then(|cell| cell.as_f64()))
+}
+
+/// Numeric cell lookup by zero-based (row, column)
+#[cfg(not(feature = "with-xlsx"))]
+type Sheet = fn(u32, u32) -> Option<f64>;
+
+#[cfg(not(feature = "with-xlsx"))]
+fn read_sheet(_path: &Path, _sheet: Option<&str>) -> Result<Sheet, String> {
+    Err("Spreadsheet support not enabled. Compile with --features with-xlsx".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cell_references() {
+        assert_eq!(cell_position("B2").unwrap(), (1, 1));
+        assert_eq!(cell_position("$AA$10").unwrap(), (9, 26));
+        assert!(cell_position("B0").is_err());
+        assert!(cell_position("12").is_err());
+        assert_eq!(offset_cell("Y3", 1, 2).unwrap(), "AA4");
+    }
+
+    #[test]
+    fn test_series_from_grid() {
+        // Years across row 1, values in row 3 from column B; D3 is blank
+        let sheet = |row: u32, column: u32| match (row, column) {
+            (0, 1..=4) => Some(1999.0 + column as f64),
+            (2, 1..=4) if column != 3 => Some(column as f64 * 10.0),
+            _ => None,
+        };
+        let points = series_from_grid(sheet, &parse_axis("1").unwrap(), cell_position("B3").unwrap());
+        assert_eq!(points, vec![(2000.0, 10.0), (2001.0, 20.0), (2003.0, 40.0)]);
+
+        let transposed = |row: u32, column: u32| sheet(column, row);
+        let points = series_from_grid(transposed, &parse_axis("A").unwrap(), cell_position("C2").unwrap());
+        assert_eq!(points, vec![(2000.0, 10.0), (2001.0, 20.0), (2003.0, 40.0)]);
+    }
+
+    #[test]
+    #[cfg(feature = "with-xlsx")]
+    fn test_resolve_workbook() {
+        // inputs.xlsx, sheet Inputs: years in A2:A4, demand in B2:B4 with B3
+        // blank, and 0.5 in D1
+        let mut model = Model::new("Spreadsheet");
+        model.add_auxiliary(Auxiliary::new("share", r#"GET_XLS_CONSTANTS("inputs.xlsx", "Inputs", "D1")"#)).unwrap();
+        model.add_auxiliary(Auxiliary::new("demand", r#"GET_XLS_LOOKUPS("inputs.xlsx", "Inputs", "A", "B2")"#)).unwrap();
+        model.add_auxiliary(Auxiliary::new("missing", r#"GET_XLS_CONSTANTS("inputs.xlsx", "Nope", "D1")"#)).unwrap();
+        let dir = Path::new("tests/fixtures/xlsx");
+
+        let err = resolve(&mut model.clone(), dir).unwrap_err();
+        assert!(err.starts_with("Variable 'missing': Failed to read sheet 'Nope'"), "{}", err);
+
+        model.auxiliaries.remove("missing");
+        resolve(&mut model, dir).unwrap();
+        assert_eq!(model.parameters["share"].value, 0.5);
+        assert_eq!(model.lookups["demand"].points, vec![(2000.0, 10.0), (2002.0, 30.0)]);
+        assert!(model.auxiliaries.is_empty());
+    }
+}
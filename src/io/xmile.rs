@@ -5,9 +5,10 @@
 use quick_xml::events::Event;
 use quick_xml::Reader;
 use std::collections::HashMap;
+use crate::error::ParseError;
 use crate::model::*;
 
-pub fn parse_xmile(xml: &str) -> Result<Model, String> {
+pub fn parse_xmile(xml: &str) -> Result<Model, ParseError> {
     let mut reader = Reader::from_str(xml);
     reader.trim_text(true);
 
@@ -15,18 +16,24 @@ pub fn parse_xmile(xml: &str) -> Result<Model, String> {
     let mut stocks = Vec::new();
     let mut flows = Vec::new();
     let mut auxs = Vec::new();
+    let mut gfs = Vec::new();
 
     // Track current context
     let mut in_model = false;
     let mut in_variables = false;
+    let mut in_view = false;
     let mut current_stock: Option<XmileStock> = None;
     let mut current_flow: Option<XmileFlow> = None;
     let mut current_aux: Option<XmileAux> = None;
+    let mut current_module: Option<Module> = None;
+    let mut current_gf: Option<XmileGf> = None;
 
     let mut buf = Vec::new();
 
     loop {
-        match reader.read_event_into(&mut buf) {
+        let event = reader.read_event_into(&mut buf);
+        let empty = matches!(event, Ok(Event::Empty(_)));
+        match event {
             Ok(Event::Start(e)) | Ok(Event::Empty(e)) => {
                 match e.name().as_ref() {
                     b"xmile" => {
@@ -55,6 +62,9 @@ pub fn parse_xmile(xml: &str) -> Result<Model, String> {
                     }
                     b"sim_specs" => {
                         // Parse simulation specs
+                        if let Some(save_interval) = get_attribute(&e, b"isee:save_interval") {
+                            model.time.save_interval = save_interval.parse().ok();
+                        }
                         parse_sim_specs(&mut reader, &mut model, &mut buf)?;
                     }
                     b"model" => {
@@ -63,6 +73,18 @@ pub fn parse_xmile(xml: &str) -> Result<Model, String> {
                     b"variables" => {
                         in_variables = true;
                     }
+                    b"view" => {
+                        in_view = !empty;
+                    }
+                    b"stock" | b"flow" | b"aux" if in_view => {
+                        // Diagram placement; the first view wins for elements drawn twice
+                        let name = get_attribute(&e, b"name").unwrap_or_default();
+                        let x = get_attribute(&e, b"x").and_then(|x| x.parse().ok());
+                        let y = get_attribute(&e, b"y").and_then(|y| y.parse().ok());
+                        if let (Some(x), Some(y)) = (x, y) {
+                            model.positions.entry(name.trim_matches('"').to_string()).or_insert(Position { x, y });
+                        }
+                    }
                     b"stock" => {
                         if in_variables {
                             let name = get_attribute(&e, b"name").unwrap_or_default();
@@ -74,13 +96,16 @@ pub fn parse_xmile(xml: &str) -> Result<Model, String> {
                                 units: None,
                                 non_negative: false,
                                 max_value: None,
+                                conveyor: None,
                             });
                         }
                     }
                     b"non_negative" => {
-                        // Set non_negative flag for current stock
+                        // Set non_negative flag for current stock, or make the current flow a uniflow
                         if let Some(ref mut stock) = current_stock {
                             stock.non_negative = true;
+                        } else if let Some(ref mut flow) = current_flow {
+                            flow.non_negative = true;
                         }
                     }
                     b"max" => {
@@ -93,14 +118,48 @@ pub fn parse_xmile(xml: &str) -> Result<Model, String> {
                             }
                         }
                     }
+                    b"conveyor" => {
+                        if let Some(ref mut stock) = current_stock {
+                            stock.conveyor = Some(XmileConveyor::default());
+                        }
+                    }
+                    b"len" => {
+                        // Conveyor transit time
+                        if let Some(conveyor) = current_stock.as_mut().and_then(|s| s.conveyor.as_mut())
+                            && let Ok(Event::Text(e)) = reader.read_event_into(&mut buf)
+                        {
+                            conveyor.len = e.unescape().unwrap_or_default().to_string();
+                        }
+                    }
+                    b"capacity" => {
+                        if let Some(conveyor) = current_stock.as_mut().and_then(|s| s.conveyor.as_mut())
+                            && let Ok(Event::Text(e)) = reader.read_event_into(&mut buf)
+                        {
+                            conveyor.capacity = Some(e.unescape().unwrap_or_default().to_string());
+                        }
+                    }
                     b"flow" => {
                         if in_variables {
                             let name = get_attribute(&e, b"name").unwrap_or_default();
-                            current_flow = Some(XmileFlow {
+                            let flow = XmileFlow {
                                 name,
                                 eqn: String::new(),
                                 units: None,
-                            });
+                                leak: false,
+                                non_negative: false,
+                                gf: None,
+                            };
+                            // `<flow name="..."/>` has no closing tag
+                            if empty {
+                                flows.push(flow);
+                            } else {
+                                current_flow = Some(flow);
+                            }
+                        }
+                    }
+                    b"leak" => {
+                        if let Some(ref mut flow) = current_flow {
+                            flow.leak = true;
                         }
                     }
                     b"aux" => {
@@ -110,9 +169,79 @@ pub fn parse_xmile(xml: &str) -> Result<Model, String> {
                                 name,
                                 eqn: String::new(),
                                 units: None,
+                                gf: None,
                             });
                         }
                     }
+                    b"gf" if in_variables && !empty => {
+                        current_gf = Some(XmileGf {
+                            name: get_attribute(&e, b"name"),
+                            kind: get_attribute(&e, b"type"),
+                            ..XmileGf::default()
+                        });
+                    }
+                    b"xscale" | b"yscale" => {
+                        let is_x = e.name().as_ref() == b"xscale";
+                        let bound = |attr: &[u8]| get_attribute(&e, attr).and_then(|v| v.trim().parse::<f64>().ok());
+                        if let Some(gf) = current_gf.as_mut()
+                            && let (Some(min), Some(max)) = (bound(b"min"), bound(b"max"))
+                        {
+                            if is_x {
+                                gf.x_range = Some((min, max));
+                            } else {
+                                gf.y_range = Some((min, max));
+                            }
+                        }
+                    }
+                    b"element" if current_gf.is_some() => {
+                        // A row of an arrayed graphical function
+                        if let Some(gf) = current_gf.as_mut() {
+                            gf.element = Some(get_attribute(&e, b"subscript").unwrap_or_default());
+                        }
+                    }
+                    b"xpts" | b"ypts" if current_gf.is_some() => {
+                        let is_x = e.name().as_ref() == b"xpts";
+                        let separator = get_attribute(&e, b"sep").unwrap_or_else(|| ",".to_string());
+                        if let Ok(Event::Text(text)) = reader.read_event_into(&mut buf)
+                            && let Some(gf) = current_gf.as_mut()
+                        {
+                            let text = text.unescape().unwrap_or_default();
+                            let values = text.split(separator.as_str())
+                                .map(|value| value.trim().parse::<f64>())
+                                .collect::<Result<Vec<_>, _>>()
+                                .map_err(|_| format!("Graphical function points must be numbers, found '{}'", text))?;
+                            match (is_x, gf.element.clone()) {
+                                (true, _) => gf.xpts = Some(values),
+                                (false, Some(subscript)) => gf.rows.push((subscript, values)),
+                                (false, None) => gf.ypts = values,
+                            }
+                        }
+                    }
+                    b"module" if in_variables => {
+                        let name = get_attribute(&e, b"name").unwrap_or_default();
+                        let Some(resource) = get_attribute(&e, b"resource") else {
+                            return Err(format!("Module '{}' has no resource file; modules defined in the same file are not supported", name).into());
+                        };
+                        let module = Module::new(&name, &resource);
+                        if empty {
+                            model.modules.push(module);
+                        } else {
+                            current_module = Some(module);
+                        }
+                    }
+                    b"connect" => {
+                        // Into the module when `to` is one of its variables, out of it otherwise
+                        if let Some(module) = current_module.as_mut() {
+                            let to = get_attribute(&e, b"to").unwrap_or_default();
+                            let from = get_attribute(&e, b"from").unwrap_or_default();
+                            let prefix = format!("{}.", module.name);
+                            match (to.strip_prefix(&prefix), from.strip_prefix(&prefix)) {
+                                (Some(port), _) => module.inputs.insert(port.to_string(), from),
+                                (None, Some(variable)) => module.outputs.insert(to, variable.to_string()),
+                                (None, None) => return Err(format!("Module '{}' connects '{}' to '{}', neither of which is in it", module.name, from, to).into()),
+                            };
+                        }
+                    }
                     b"eqn" => {
                         // Read equation text
                         if let Ok(Event::Text(e)) = reader.read_event_into(&mut buf) {
@@ -161,9 +290,37 @@ pub fn parse_xmile(xml: &str) -> Result<Model, String> {
                             auxs.push(aux);
                         }
                     }
+                    b"element" => {
+                        if let Some(gf) = current_gf.as_mut() {
+                            gf.element = None;
+                        }
+                    }
+                    b"gf" => {
+                        // A graphical function of the variable it is in, or one named on its own
+                        if let Some(gf) = current_gf.take() {
+                            if !gf.rows.is_empty() && (current_aux.is_some() || current_flow.is_some()) {
+                                return Err("Arrayed graphical functions are only read as named 2D tables outside a variable".to_string().into());
+                            }
+                            if let Some(aux) = current_aux.as_mut() {
+                                aux.gf = Some(gf);
+                            } else if let Some(flow) = current_flow.as_mut() {
+                                flow.gf = Some(gf);
+                            } else {
+                                gfs.push(gf);
+                            }
+                        }
+                    }
+                    b"module" => {
+                        if let Some(module) = current_module.take() {
+                            model.modules.push(module);
+                        }
+                    }
                     b"variables" => {
                         in_variables = false;
                     }
+                    b"view" => {
+                        in_view = false;
+                    }
                     b"model" => {
                         in_model = false;
                     }
@@ -171,7 +328,7 @@ pub fn parse_xmile(xml: &str) -> Result<Model, String> {
                 }
             }
             Ok(Event::Eof) => break,
-            Err(e) => return Err(format!("XML parse error at position {}: {}", reader.buffer_position(), e)),
+            Err(e) => return Err(format!("XML parse error at position {}: {}", reader.buffer_position(), e).into()),
             _ => {}
         }
         buf.clear();
@@ -179,33 +336,68 @@ pub fn parse_xmile(xml: &str) -> Result<Model, String> {
 
     // Convert XMILE structures to Model
     for xstock in stocks {
+        let kind = match xstock.conveyor {
+            Some(conveyor) => StockKind::Conveyor {
+                transit_time: Expression::parse_equation(&xstock.name, &conveyor.len)?,
+                capacity: conveyor.capacity.as_deref()
+                    .map(|capacity| Expression::parse_equation(&xstock.name, capacity))
+                    .transpose()?,
+            },
+            None => StockKind::Reservoir,
+        };
         let stock = Stock {
             name: xstock.name.clone(),
-            initial: Expression::parse(&xstock.eqn)?,
+            initial: Expression::parse_equation(&xstock.name, &xstock.eqn)?,
             inflows: xstock.inflows,
             outflows: xstock.outflows,
             units: xstock.units,
             non_negative: xstock.non_negative,
             max_value: xstock.max_value,
             dimensions: None,
+            kind,
         };
         model.add_stock(stock)?;
     }
 
+    for gf in gfs {
+        let name = gf.name.clone().ok_or("A graphical function outside a variable has no name")?;
+        if gf.rows.is_empty() {
+            model.add_lookup(gf.to_table(name)?)?;
+        } else {
+            model.add_lookup_2d(gf.to_table_2d(name)?)?;
+        }
+    }
+
     for xflow in flows {
+        // Conveyor outflows may omit their equation; the conveyor sets the rate
+        let equation = if let Some(gf) = &xflow.gf {
+            graphical_function(&mut model, &xflow.name, &xflow.eqn, gf)?
+        } else if xflow.eqn.trim().is_empty() {
+            Expression::Constant(0.0)
+        } else {
+            Expression::parse_equation(&xflow.name, &xflow.eqn)?
+        };
         let flow = Flow {
             name: xflow.name.clone(),
-            equation: Expression::parse(&xflow.eqn)?,
+            equation,
             units: xflow.units,
+            dimensions: None,
+            leak: xflow.leak,
+            non_negative: xflow.non_negative,
         };
         model.add_flow(flow)?;
     }
 
     for xaux in auxs {
+        let equation = match &xaux.gf {
+            Some(gf) => graphical_function(&mut model, &xaux.name, &xaux.eqn, gf)?,
+            None => Expression::parse_equation(&xaux.name, &xaux.eqn)?,
+        };
         let aux = Auxiliary {
             name: xaux.name.clone(),
-            equation: Expression::parse(&xaux.eqn)?,
+            equation,
             units: xaux.units,
+            dimensions: None,
         };
         model.add_auxiliary(aux)?;
     }
@@ -217,6 +409,7 @@ fn parse_sim_specs(reader: &mut Reader<&[u8]>, model: &mut Model, buf: &mut Vec<
     let mut start = None;
     let mut stop = None;
     let mut dt = None;
+    let mut save_step = None;
 
     loop {
         match reader.read_event_into(buf) {
@@ -237,6 +430,11 @@ fn parse_sim_specs(reader: &mut Reader<&[u8]>, model: &mut Model, buf: &mut Vec<
                             dt = e.unescape().unwrap_or_default().parse().ok();
                         }
                     }
+                    b"save_step" => {
+                        if let Ok(Event::Text(e)) = reader.read_event_into(buf) {
+                            save_step = e.unescape().unwrap_or_default().parse().ok();
+                        }
+                    }
                     _ => {}
                 }
             }
@@ -253,10 +451,28 @@ fn parse_sim_specs(reader: &mut Reader<&[u8]>, model: &mut Model, buf: &mut Vec<
         model.time.stop = stop_val;
         model.time.dt = dt_val;
     }
+    if save_step.is_some() {
+        model.time.save_interval = save_step;
+    }
 
     Ok(())
 }
 
+/// Add the graphical function of variable `name` as a lookup table, returning
+/// the equation that reads it at the variable's own equation
+fn graphical_function(model: &mut Model, name: &str, input: &str, gf: &XmileGf) -> Result<Expression, ParseError> {
+    if input.trim().is_empty() {
+        return Err(format!("'{}' has a graphical function but no equation for its input", name).into());
+    }
+    let table = format!("{}_lookup", name);
+    model.add_lookup(gf.to_table(table.clone())?)?;
+    Ok(Expression::FunctionCall {
+        name: "LOOKUP".to_string(),
+        args: vec![Expression::StringLiteral { literal: table }, Expression::parse_equation(name, input)?],
+        site: None,
+    })
+}
+
 fn get_attribute(element: &quick_xml::events::BytesStart, attr_name: &[u8]) -> Option<String> {
     element
         .attributes()
@@ -274,18 +490,104 @@ struct XmileStock {
     units: Option<String>,
     non_negative: bool,
     max_value: Option<f64>,
+    conveyor: Option<XmileConveyor>,
+}
+
+#[derive(Default)]
+struct XmileConveyor {
+    len: String,
+    capacity: Option<String>,
 }
 
 struct XmileFlow {
     name: String,
     eqn: String,
     units: Option<String>,
+    leak: bool,
+    non_negative: bool,
+    gf: Option<XmileGf>,
 }
 
 struct XmileAux {
     name: String,
     eqn: String,
     units: Option<String>,
+    gf: Option<XmileGf>,
+}
+
+/// `<gf>`: y points at given x points, or spread evenly over the x scale
+///
+/// A named `<gf>` arrayed over a dimension whose subscripts are numbers, with
+/// `<ypts>` in an `<element>` per subscript, is a 2D table whose y values are
+/// the subscripts.
+#[derive(Default)]
+struct XmileGf {
+    name: Option<String>,
+    /// `continuous` (the default), `extrapolate` or `discrete`
+    kind: Option<String>,
+    x_range: Option<(f64, f64)>,
+    y_range: Option<(f64, f64)>,
+    xpts: Option<Vec<f64>>,
+    ypts: Vec<f64>,
+    /// Subscript of the `<element>` being read
+    element: Option<String>,
+    /// y points of each element, by subscript
+    rows: Vec<(String, Vec<f64>)>,
+}
+
+impl XmileGf {
+    /// x points for `count` y points
+    fn x_points(&self, name: &str, count: usize) -> Result<Vec<f64>, String> {
+        let xs = match (&self.xpts, self.x_range) {
+            (Some(xs), _) => xs.clone(),
+            (None, Some((min, max))) if count > 1 => {
+                let step = (max - min) / (count - 1) as f64;
+                (0..count).map(|i| min + step * i as f64).collect()
+            }
+            (None, Some((min, _))) => vec![min],
+            (None, None) => return Err(format!("Graphical function '{}' has neither x points nor an x scale", name)),
+        };
+        if xs.len() != count {
+            return Err(format!("Graphical function '{}' has {} x points and {} y points", name, xs.len(), count));
+        }
+        Ok(xs)
+    }
+
+    fn to_table(&self, name: String) -> Result<crate::simulation::LookupTable, String> {
+        let xs = self.x_points(&name, self.ypts.len())?;
+        let (interpolation, extrapolation) = match self.kind.as_deref().unwrap_or("continuous") {
+            "continuous" => (Interpolation::Linear, Extrapolation::Hold),
+            "extrapolate" => (Interpolation::Linear, Extrapolation::Linear),
+            "discrete" => (Interpolation::Step, Extrapolation::Hold),
+            other => return Err(format!("Graphical function '{}' has unknown type '{}'", name, other)),
+        };
+        let points = xs.into_iter().zip(self.ypts.iter().copied()).collect();
+        let table = crate::simulation::LookupTable::new(name, points)?
+            .with_interpolation(interpolation)
+            .with_extrapolation(extrapolation);
+        Ok(match self.y_range {
+            Some((min, max)) => table.with_y_range(min, max),
+            None => table,
+        })
+    }
+
+    /// The 2D table of an arrayed graphical function, interpolated bilinearly
+    fn to_table_2d(&self, name: String) -> Result<crate::simulation::LookupTable2D, String> {
+        let mut rows = self.rows.iter()
+            .map(|(subscript, ypts)| match subscript.trim().parse::<f64>() {
+                Ok(y) => Ok((y, ypts)),
+                Err(_) => Err(format!("Graphical function '{}' has element '{}'; only numeric subscripts make a 2D table", name, subscript)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let xs = self.x_points(&name, rows[0].1.len())?;
+        if let Some((y, ypts)) = rows.iter().find(|(_, ypts)| ypts.len() != xs.len()) {
+            return Err(format!("Graphical function '{}' has {} points at {}, not {}", name, ypts.len(), y, xs.len()));
+        }
+        let ys = rows.iter().map(|(y, _)| *y).collect();
+        let values = (0..xs.len()).map(|i| rows.iter().map(|(_, ypts)| ypts[i]).collect()).collect();
+        crate::simulation::LookupTable2D::new(name, xs, ys, values)
+    }
 }
 
 #[cfg(test)]
@@ -304,6 +606,7 @@ mod tests {
                 <start>0</start>
                 <stop>10</stop>
                 <dt>1</dt>
+                <save_step>2</save_step>
             </sim_specs>
             <model>
                 <variables>
@@ -324,7 +627,141 @@ mod tests {
         assert_eq!(model.time.start, 0.0);
         assert_eq!(model.time.stop, 10.0);
         assert_eq!(model.time.dt, 1.0);
+        assert_eq!(model.time.save_interval, Some(2.0));
         assert_eq!(model.stocks.len(), 1);
         assert_eq!(model.flows.len(), 1);
     }
+
+    #[test]
+    fn test_parse_conveyor() {
+        let xml = r#"
+        <xmile version="1.0">
+            <sim_specs><start>0</start><stop>10</stop><dt>0.5</dt></sim_specs>
+            <model>
+                <variables>
+                    <stock name="In Transit">
+                        <eqn>0</eqn>
+                        <inflow>shipping</inflow>
+                        <outflow>arriving</outflow>
+                        <outflow>spoilage</outflow>
+                        <conveyor>
+                            <len>delivery_time</len>
+                            <capacity>50</capacity>
+                        </conveyor>
+                    </stock>
+                    <flow name="shipping"><eqn>5</eqn></flow>
+                    <flow name="arriving"/>
+                    <flow name="spoilage"><eqn>0.1</eqn><leak/></flow>
+                </variables>
+            </model>
+        </xmile>
+        "#;
+
+        let model = parse_xmile(xml).unwrap();
+        match &model.stocks["In Transit"].kind {
+            StockKind::Conveyor { transit_time, capacity } => {
+                assert!(matches!(transit_time, Expression::Variable(name) if name == "delivery_time"));
+                assert!(matches!(capacity, Some(Expression::Constant(c)) if *c == 50.0));
+            }
+            other => panic!("expected a conveyor, got {:?}", other),
+        }
+        assert!(model.flows["spoilage"].leak);
+        assert!(!model.flows["arriving"].leak);
+    }
+
+    #[test]
+    fn test_parse_module() {
+        let xml = r#"
+        <xmile version="1.0">
+            <sim_specs><start>0</start><stop>10</stop><dt>1</dt></sim_specs>
+            <model>
+                <variables>
+                    <aux name="demand"><eqn>20</eqn></aux>
+                    <module name="Factory" resource="factory.xmile">
+                        <connect to="Factory.orders" from="demand"/>
+                        <connect to="shipped" from="Factory.shipments"/>
+                    </module>
+                </variables>
+            </model>
+        </xmile>
+        "#;
+
+        let model = parse_xmile(xml).unwrap();
+        let module = &model.modules[0];
+        assert_eq!(module.file, "factory.xmile");
+        assert_eq!(module.inputs["orders"], "demand");
+        assert_eq!(module.outputs["shipped"], "shipments");
+    }
+
+    #[test]
+    fn test_parse_graphical_functions() {
+        let xml = r#"
+        <xmile version="1.0">
+            <sim_specs><start>0</start><stop>10</stop><dt>1</dt></sim_specs>
+            <model>
+                <variables>
+                    <aux name="price">
+                        <eqn>TIME</eqn>
+                        <gf type="discrete">
+                            <xscale min="0" max="10"/>
+                            <ypts>1,2,3</ypts>
+                        </gf>
+                    </aux>
+                    <aux name="demand">
+                        <eqn>price</eqn>
+                        <gf type="extrapolate">
+                            <yscale min="0" max="100"/>
+                            <xpts sep=";">0;1</xpts>
+                            <ypts sep=";">50;40</ypts>
+                        </gf>
+                    </aux>
+                    <gf name="effect">
+                        <xpts>0,1</xpts>
+                        <ypts>1,0</ypts>
+                    </gf>
+                </variables>
+            </model>
+        </xmile>
+        "#;
+
+        let model = parse_xmile(xml).unwrap();
+        let price = &model.lookups["price_lookup"];
+        assert_eq!(price.points, vec![(0.0, 1.0), (5.0, 2.0), (10.0, 3.0)]);
+        assert_eq!(price.lookup(4.9).unwrap(), 1.0);
+        assert_eq!(price.lookup(12.0).unwrap(), 3.0);
+        assert_eq!(model.auxiliaries["price"].equation.to_string(), "LOOKUP(\"price_lookup\", TIME)");
+
+        let demand = &model.lookups["demand_lookup"];
+        assert_eq!(demand.lookup(2.0).unwrap(), 30.0);
+        // Extended past the points, then held to the y scale
+        assert_eq!(demand.lookup(6.0).unwrap(), 0.0);
+        assert_eq!(demand.lookup(-6.0).unwrap(), 100.0);
+        assert_eq!(model.lookups["effect"].lookup(0.25).unwrap(), 0.75);
+    }
+
+    #[test]
+    fn test_parse_arrayed_graphical_function() {
+        let xml = r#"
+        <xmile version="1.0">
+            <sim_specs><start>0</start><stop>10</stop><dt>1</dt></sim_specs>
+            <model>
+                <variables>
+                    <gf name="production">
+                        <xscale min="0" max="10"/>
+                        <element subscript="200"><ypts>0,80</ypts></element>
+                        <element subscript="100"><ypts>0,50</ypts></element>
+                    </gf>
+                </variables>
+            </model>
+        </xmile>
+        "#;
+
+        let model = parse_xmile(xml).unwrap();
+        let production = &model.lookups_2d["production"];
+        assert_eq!(production.y, vec![100.0, 200.0]);
+        assert_eq!(production.lookup(5.0, 150.0), 32.5);
+
+        let named = xml.replace("subscript=\"100\"", "subscript=\"low\"");
+        assert!(parse_xmile(&named).unwrap_err().to_string().contains("only numeric subscripts"));
+    }
 }
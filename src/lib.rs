@@ -0,0 +1,50 @@
+//! rsedsim - Rust System Dynamics Simulator
+//!
+//! A system dynamics simulator that can be embedded in other programs as
+//! well as run from the `rsedsim` command line. Models are loaded from
+//! JSON, YAML, XMILE, InsightMaker or Vensim files ([`load_model`]) or built
+//! in code ([`Model`]), run with a [`SimulationEngine`], and analysed with
+//! the tools in [`analysis`].
+//!
+//! ```
+//! use rssdsim::{Flow, Model, Parameter, SimulationConfig, SimulationEngine, Stock};
+//!
+//! let mut model = Model::new("Growth");
+//! model.time.stop = 10.0;
+//! model.add_stock(Stock::new("Population", "100").with_inflows(vec!["births".to_string()]))?;
+//! model.add_parameter(Parameter::new("birth_rate", 0.05))?;
+//! model.add_flow(Flow::new("births", "Population * birth_rate"))?;
+//!
+//! let results = SimulationEngine::new(model, SimulationConfig::default())?.run()?;
+//! assert!(results.final_value("Population").unwrap() > 150.0);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! Public entry points return [`Error`], which tells model problems apart
+//! from failures while running or writing output.
+//!
+//! The HTTP server, MCP/A2A protocols and distributed runs sit behind the
+//! default `server` feature. Building with `--no-default-features --features
+//! wasm` leaves the model, simulation and analysis core with JavaScript
+//! bindings in [`wasm`](crate::wasm) for running models in a browser.
+//! Other languages can link the shared library through the C functions in
+//! [`ffi`], declared in `include/rsedsim.h`.
+
+pub mod error;
+#[cfg(feature = "server")]
+pub mod protocol;
+pub mod model;
+pub mod simulation;
+pub mod io;
+pub mod analysis;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod visualization;
+pub mod ffi;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use error::{EquationError, Error, IoError, ModelError, NonFiniteValue, ParseError, Result, SimulationError};
+pub use model::{Auxiliary, Flow, Model, ModelBuilder, Parameter, Stock};
+pub use simulation::{IntegrationMethod, ResultSink, SimulationConfig, SimulationEngine, SimulationResults, SimulationState};
+pub use io::{load_model, export_model, write_csv};
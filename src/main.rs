@@ -7,16 +7,11 @@
 /// - Multi-dimensional variables
 /// - MCP and A2A protocol integration
 
-mod protocol;
-mod model;
-mod simulation;
-mod io;
-mod analysis;
-mod server;
-mod visualization;
+use rssdsim::{analysis, io, protocol, server, simulation, visualization};
 
 use clap::{Parser, Subcommand};
-use std::path::PathBuf;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
 use colored::*;
 
 #[derive(Parser)]
@@ -35,153 +30,2027 @@ enum Commands {
         /// Model file (JSON or YAML)
         model: PathBuf,
 
-        /// Output file path
+        /// Output file path (.csv, or .parquet with --features with-parquet)
         #[arg(short, long)]
         output: Option<PathBuf>,
 
-        /// Override parameters (format: "param1=value1,param2=value2")
+        /// Override parameters, stock initial values and time settings
+        /// (format: "param=1,capacity[North]=100,Stock.initial=5,time.stop=200")
         #[arg(short, long)]
         params: Option<String>,
 
-        /// Integration method (euler or rk4)
+        /// Integration method (euler, rk4, rk45, heun, backward-euler or bdf)
         #[arg(long, default_value = "euler")]
         integrator: String,
 
         /// Override timestep (dt)
         #[arg(long)]
         dt: Option<f64>,
+
+        /// Record output every N time units (overrides the model's save interval)
+        #[arg(long)]
+        saveper: Option<f64>,
+
+        /// Random seed for stochastic functions (overrides the model's seed)
+        #[arg(long)]
+        seed: Option<u64>,
+
+        /// Write each point as it is computed instead of buffering the run (.csv or .nc)
+        #[arg(long)]
+        stream: bool,
+
+        /// Only record these variables (comma-separated, e.g. "Population,births")
+        #[arg(long, value_delimiter = ',')]
+        vars: Option<Vec<String>>,
+
+        /// Periodically save the full simulation state to this file
+        #[arg(long)]
+        checkpoint: Option<PathBuf>,
+
+        /// Simulated time between checkpoints (default: a tenth of the run)
+        #[arg(long, requires = "checkpoint")]
+        checkpoint_every: Option<f64>,
+
+        /// Continue a run from a checkpoint file
+        #[arg(long, conflicts_with = "init")]
+        resume: Option<PathBuf>,
+
+        /// Starting stock values: initial (the model's initial values) or equilibrium (a steady state)
+        #[arg(long, default_value = "initial")]
+        init: String,
+
+        /// Evaluate independent equations on this many threads within each step (for very large models)
+        #[arg(long)]
+        threads: Option<usize>,
+
+        /// Run every scenario in this YAML file; the output becomes a directory (default: scenarios)
+        #[arg(long, conflicts_with_all = ["stream", "checkpoint", "resume"])]
+        scenarios: Option<PathBuf>,
+
+        /// Run the scenarios in parallel
+        #[arg(long, requires = "scenarios")]
+        parallel: bool,
+
+        /// What every failing reality check does (warn, abort or log), overriding the model
+        #[arg(long)]
+        constraints: Option<String>,
+
+        /// Stop, tracing where it came from, once any value exceeds this magnitude (NaN and infinity always stop the run)
+        #[arg(long, value_name = "LIMIT")]
+        max_value: Option<f64>,
+    },
+
+    /// Run a model in decision intervals, pausing to change parameters (management flight simulator)
+    Play {
+        /// Model file (JSON or YAML)
+        model: PathBuf,
+
+        /// Time steps to simulate between decisions
+        #[arg(short = 'k', long, default_value = "10")]
+        interval: usize,
+
+        /// Integration method (euler, rk4, heun, backward-euler or bdf)
+        #[arg(long, default_value = "euler")]
+        integrator: String,
+
+        /// Variables to show at each decision point (default: the stocks)
+        #[arg(long, value_delimiter = ',')]
+        vars: Option<Vec<String>>,
+
+        /// Write the played run to this CSV file
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Validate a model file
+    Validate {
+        /// Model file to validate
+        model: PathBuf,
+
+        /// Treat unit mismatches as errors instead of warnings
+        #[arg(long)]
+        strict_units: bool,
+    },
+
+    /// Run models and check the behavior tests declared in them (exits non-zero on failure)
+    Test {
+        /// Model files with a tests section
+        #[arg(required = true)]
+        models: Vec<PathBuf>,
+
+        /// Integration method (euler, rk4, rk45, heun, backward-euler or bdf)
+        #[arg(long, default_value = "euler")]
+        integrator: String,
+    },
+
+    /// Re-run the model with each parameter at extreme values and report implausible behavior
+    Extreme {
+        /// Model file (JSON or YAML)
+        model: PathBuf,
+
+        /// Ranges file giving parameters' min and max (overrides those declared in the model)
+        #[arg(short, long)]
+        ranges: Option<PathBuf>,
+
+        /// Factor applied to each parameter's value
+        #[arg(long, default_value = "10")]
+        multiplier: f64,
+
+        /// Values beyond this many times the baseline's largest are implausible
+        #[arg(long, default_value = "100")]
+        plausibility: f64,
+
+        /// Integration method (euler, rk4, rk45, heun, backward-euler or bdf)
+        #[arg(long, default_value = "rk4")]
+        integrator: String,
+
+        /// Output directory
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Run the model at dt, dt/2 and dt/4 and report how much the results depend on the time step
+    DtCheck {
+        /// Model file (JSON or YAML)
+        model: PathBuf,
+
+        /// Relative divergence beyond which results count as time-step dependent
+        #[arg(long, default_value = "0.01")]
+        threshold: f64,
+
+        /// Integration method (euler, rk4, heun, backward-euler or bdf)
+        #[arg(long, default_value = "euler")]
+        integrator: String,
+
+        /// Recommend a dt, or an adaptive integrator, that brings the results within the threshold
+        #[arg(long)]
+        recommend: bool,
+
+        /// Output directory
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Run a sensitivity analysis over parameter ranges
+    Sensitivity {
+        /// Model file (JSON or YAML)
+        model: PathBuf,
+
+        /// Parameter ranges file (YAML or JSON of min/max/baseline per parameter)
+        #[arg(short, long)]
+        ranges: PathBuf,
+
+        /// Sampling method (sweep, lhs, sobol or morris)
+        #[arg(short, long, default_value = "lhs")]
+        method: String,
+
+        /// Samples (steps per parameter for sweep, trajectories for morris)
+        #[arg(short = 'n', long, default_value = "20")]
+        samples: usize,
+
+        /// Output variable, optionally with a _final/_mean/_max/_min suffix
+        #[arg(long)]
+        variable: String,
+
+        /// Random seed for lhs and morris sampling
+        #[arg(long)]
+        seed: Option<u64>,
+
+        /// Integration method (euler, rk4, rk45, heun, backward-euler or bdf)
+        #[arg(long, default_value = "euler")]
+        integrator: String,
+
+        /// Output CSV file path
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Run a parallel Monte Carlo analysis over parameter ranges
+    #[command(name = "montecarlo")]
+    MonteCarlo {
+        /// Model file (JSON or YAML)
+        model: PathBuf,
+
+        /// Parameter ranges file (YAML or JSON of min/max/baseline per parameter)
+        #[arg(short, long)]
+        ranges_file: PathBuf,
+
+        /// Number of simulation runs
+        #[arg(short = 'n', long, default_value = "100")]
+        runs: usize,
+
+        /// Random seed for reproducible sampling
+        #[arg(long)]
+        seed: Option<u64>,
+
+        /// Sampling strategy (random, lhs or sobol)
+        #[arg(long, default_value = "random")]
+        sampling: String,
+
+        /// Confidence level for the interval columns
+        #[arg(long, default_value = "0.95")]
+        confidence: f64,
+
+        /// Also write every individual run
+        #[arg(long)]
+        save_runs: bool,
+
+        /// Integration method (euler, rk4, rk45, heun, backward-euler or bdf)
+        #[arg(long, default_value = "euler")]
+        integrator: String,
+
+        /// Output format (csv, netcdf, hdf5 or parquet)
+        #[arg(short, long, default_value = "csv")]
+        format: String,
+
+        /// Output directory for csv, or file path for netcdf/hdf5/parquet
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Analyze model structure and feedback loops
+    Analyze {
+        /// Model file (JSON or YAML)
+        model: PathBuf,
+
+        /// Write the dependency graph in Graphviz DOT format
+        #[arg(long)]
+        dot: Option<PathBuf>,
+
+        /// Print the loop listing as JSON instead of the text report
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Sweep a parameter, following the equilibrium and locating fold and Hopf bifurcations
+    Bifurcation {
+        /// Model file (JSON or YAML)
+        model: PathBuf,
+
+        /// Parameter to sweep
+        #[arg(short, long)]
+        parameter: String,
+
+        /// First parameter value
+        #[arg(long)]
+        from: f64,
+
+        /// Last parameter value
+        #[arg(long)]
+        to: f64,
+
+        /// Number of steps between the first and last values
+        #[arg(short = 'n', long, default_value = "100")]
+        steps: usize,
+
+        /// Output directory
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Phase portrait of two stocks: vector field, nullclines and trajectories
+    Phase {
+        /// Model file (JSON or YAML)
+        model: PathBuf,
+
+        /// Stock on the horizontal axis
+        #[arg(short, long)]
+        x: String,
+
+        /// Stock on the vertical axis
+        #[arg(short, long)]
+        y: String,
+
+        /// Range of the horizontal axis as min,max (default: from a run of the model)
+        #[arg(long, value_delimiter = ',')]
+        x_range: Option<Vec<f64>>,
+
+        /// Range of the vertical axis as min,max (default: from a run of the model)
+        #[arg(long, value_delimiter = ',')]
+        y_range: Option<Vec<f64>>,
+
+        /// Vector field samples along each axis
+        #[arg(long, default_value = "20")]
+        grid: usize,
+
+        /// Trajectory starting points along each axis
+        #[arg(long, default_value = "5")]
+        starts: usize,
+
+        /// Integration method (euler, rk4, rk45, heun, backward-euler or bdf)
+        #[arg(long, default_value = "rk4")]
+        integrator: String,
+
+        /// Output directory
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Export the dependency graph as Graphviz DOT, a Mermaid flowchart or JSON
+    Graph {
+        /// Model file
+        model: PathBuf,
+
+        /// Output format (dot, mermaid or json)
+        #[arg(short, long, default_value = "dot")]
+        format: String,
+
+        /// Output file (default: stdout)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Export a model to another format (Vensim .mdl, readable by PySD)
+    Export {
+        /// Model file to convert
+        model: PathBuf,
+
+        /// Output file; the format is chosen by extension
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+
+    /// Apply a patch of added, changed and removed elements to a model
+    ApplyPatch {
+        /// Base model file (YAML or JSON)
+        base: PathBuf,
+
+        /// Patch file
+        patch: PathBuf,
+
+        /// Merged model file, written as JSON for .json (default: YAML on stdout)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Calibrate parameters against observed time series
+    Optimize {
+        /// Model file (JSON or YAML)
+        model: PathBuf,
+
+        /// Observed data CSV (a time column plus one column per variable)
+        #[arg(short, long)]
+        data: PathBuf,
+
+        /// Parameter bounds file (YAML or JSON of min/max per parameter)
+        #[arg(short, long)]
+        bounds: PathBuf,
+
+        /// Objective (sse, mae or likelihood)
+        #[arg(long, default_value = "sse")]
+        objective: String,
+
+        /// Optimization algorithm (gradient or genetic)
+        #[arg(short, long, default_value = "gradient")]
+        algorithm: String,
+
+        /// Maximum iterations (generations for genetic)
+        #[arg(long, default_value = "100")]
+        max_iterations: usize,
+
+        /// Integration method (euler, rk4, rk45, heun, backward-euler or bdf)
+        #[arg(long, default_value = "rk4")]
+        integrator: String,
+
+        /// Output directory
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Optimize piecewise-constant schedules of decision parameters
+    Policy {
+        /// Model file (JSON or YAML)
+        model: PathBuf,
+
+        /// Decision variables file (YAML or JSON of times, min and max per parameter)
+        #[arg(short, long)]
+        decisions: PathBuf,
+
+        /// Variable to optimize, optionally with a _final/_mean/_max/_min suffix
+        #[arg(long)]
+        objective: String,
+
+        /// Maximize the objective instead of minimizing it
+        #[arg(long)]
+        maximize: bool,
+
+        /// Optimization algorithm (gradient or genetic)
+        #[arg(short, long, default_value = "genetic")]
+        algorithm: String,
+
+        /// Maximum iterations (generations for genetic)
+        #[arg(long, default_value = "100")]
+        max_iterations: usize,
+
+        /// Integration method (euler, rk4, rk45, heun, backward-euler or bdf)
+        #[arg(long, default_value = "rk4")]
+        integrator: String,
+
+        /// Output directory
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Bayesian calibration: sample the posterior of parameters given observed data (MCMC)
+    Calibrate {
+        /// Model file (JSON or YAML)
+        model: PathBuf,
+
+        /// Observed data CSV (a time column plus one column per variable)
+        #[arg(short, long)]
+        data: PathBuf,
+
+        /// Priors file (YAML or JSON; a ranges file gives uniform priors)
+        #[arg(short, long)]
+        priors: PathBuf,
+
+        /// Posterior draws kept per chain
+        #[arg(short = 'n', long, default_value = "1000")]
+        samples: usize,
+
+        /// Iterations discarded at the start of each chain
+        #[arg(long, default_value = "500")]
+        burn_in: usize,
+
+        /// Number of chains
+        #[arg(long, default_value = "4")]
+        chains: usize,
+
+        /// Keep every n-th iteration
+        #[arg(long, default_value = "1")]
+        thin: usize,
+
+        /// Observation error standard deviation (default: integrated out)
+        #[arg(long)]
+        noise_sd: Option<f64>,
+
+        /// Credible interval level
+        #[arg(long, default_value = "0.95")]
+        level: f64,
+
+        /// Random seed for reproducible chains
+        #[arg(long)]
+        seed: Option<u64>,
+
+        /// Integration method (euler, rk4, rk45, heun, backward-euler or bdf)
+        #[arg(long, default_value = "rk4")]
+        integrator: String,
+
+        /// Output directory
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Assimilate observed data into the stocks during a run (Ensemble Kalman Filter)
+    Assimilate {
+        /// Model file (JSON or YAML)
+        model: PathBuf,
+
+        /// Observed data CSV (a time column plus one column per variable)
+        #[arg(short, long)]
+        data: PathBuf,
+
+        /// Number of ensemble members
+        #[arg(short = 'n', long, default_value = "50")]
+        ensemble: usize,
+
+        /// Measurement error standard deviation, as a fraction of each observation
+        #[arg(long, default_value = "0.05")]
+        obs_error: f64,
+
+        /// Model error added to the stocks, as a fraction per square root of time unit
+        #[arg(long, default_value = "0.02")]
+        process_noise: f64,
+
+        /// Spread of the initial stocks, as a fraction of their values
+        #[arg(long, default_value = "0.1")]
+        initial_spread: f64,
+
+        /// Random seed for a reproducible ensemble
+        #[arg(long)]
+        seed: Option<u64>,
+
+        /// Integration method (euler, rk4, rk45, heun, backward-euler or bdf)
+        #[arg(long, default_value = "rk4")]
+        integrator: String,
+
+        /// Output directory
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Compare a scenario run with a baseline, variable by variable
+    Compare {
+        /// Baseline results CSV, or a model file to run
+        baseline: PathBuf,
+
+        /// Scenario results CSV, or a model file to run (default: the baseline model)
+        scenario: Option<PathBuf>,
+
+        /// Only compare these variables (comma-separated, e.g. "Population,births")
+        #[arg(long, value_delimiter = ',')]
+        vars: Option<Vec<String>>,
+
+        /// Parameter overrides for running the baseline model ("param1=value1,...")
+        #[arg(long)]
+        baseline_params: Option<String>,
+
+        /// Parameter overrides for running the scenario model ("param1=value1,...")
+        #[arg(long)]
+        scenario_params: Option<String>,
+
+        /// Integration method for model runs (euler, rk4, rk45, heun, backward-euler or bdf)
+        #[arg(long, default_value = "euler")]
+        integrator: String,
+
+        /// Output directory
+        #[arg(short, long)]
+        output: Option<PathBuf>,
     },
 
-    /// Validate a model file
-    Validate {
-        /// Model file to validate
-        model: PathBuf,
-    },
+    /// Chart a results CSV, or a Monte Carlo statistics CSV as a fan chart, to SVG or PNG
+    #[cfg(feature = "plot")]
+    Plot {
+        /// Results CSV with a time column
+        input: PathBuf,
+
+        /// Variables to plot (comma-separated; default: every column, or a fan
+        /// chart for a Monte Carlo statistics CSV)
+        #[arg(long, value_delimiter = ',')]
+        vars: Option<Vec<String>>,
+
+        /// Output file; the extension picks SVG or PNG
+        #[arg(short, long, default_value = "plot.svg")]
+        output: PathBuf,
+
+        /// Chart title
+        #[arg(long)]
+        title: Option<String>,
+
+        /// Value axis label
+        #[arg(long)]
+        y_label: Option<String>,
+
+        /// Logarithmic time axis
+        #[arg(long)]
+        log_x: bool,
+
+        /// Logarithmic value axis
+        #[arg(long)]
+        log_y: bool,
+
+        /// Width in pixels
+        #[arg(long, default_value = "1024")]
+        width: u32,
+
+        /// Height in pixels
+        #[arg(long, default_value = "640")]
+        height: u32,
+    },
+
+    /// Draw a model's stock-and-flow diagram as SVG
+    Diagram {
+        /// Model file; XMILE views keep their own placement
+        model: PathBuf,
+
+        /// Output SVG file
+        #[arg(short, long, default_value = "diagram.svg")]
+        output: PathBuf,
+    },
+
+    /// Serve MCP (Model Context Protocol) tools over stdin/stdout
+    Mcp,
+
+    /// Show version and info
+    Info,
+
+    /// Start the HTTP/WebSocket API server
+    Serve {
+        /// Port to listen on
+        #[arg(short, long, default_value = "8080")]
+        port: u16,
+
+        /// Directory of model files to load at startup
+        #[arg(long)]
+        model_dir: Option<PathBuf>,
+
+        /// Allowed CORS origins, comma-separated ("*" allows any)
+        #[arg(long, default_value = "*")]
+        cors: String,
+
+        /// Directory to save finished runs in, reloaded at startup
+        #[arg(long)]
+        data_dir: Option<PathBuf>,
+
+        /// Simulations to run at once (default: one per CPU)
+        #[arg(long)]
+        workers: Option<usize>,
+
+        /// Simulations allowed to wait for a worker before new ones are refused
+        #[arg(long, default_value = "1000")]
+        queue_capacity: usize,
+
+        /// Accept this API key (repeatable); requests without a key are refused
+        #[arg(long = "api-key")]
+        api_keys: Vec<String>,
+
+        /// File of API keys, one per line ('#' starts a comment)
+        #[arg(long)]
+        api_keys_file: Option<PathBuf>,
+
+        /// Accept JWTs signed (HS256) with this secret
+        #[arg(long)]
+        jwt_secret: Option<String>,
+
+        /// Requests allowed per API key or JWT subject per minute, or per
+        /// client address without keys or a secret
+        #[arg(long)]
+        rate_limit: Option<u32>,
+    },
+}
+
+#[tokio::main]
+async fn main() {
+    if let Err(error) = run_command(Cli::parse()).await {
+        report_error(error.as_ref());
+        std::process::exit(1);
+    }
+}
+
+/// Print a failure, spelling out the variable and equation of parse and simulation errors
+fn report_error(error: &(dyn std::error::Error + 'static)) {
+    let label = "error:".red().bold();
+    match error.downcast_ref::<rssdsim::Error>() {
+        Some(rssdsim::Error::Simulation(rssdsim::SimulationError::Evaluation { kind, variable, equation, time, message })) => {
+            eprintln!("{} failed to evaluate {} '{}' at t={}", label, kind, variable.bold(), time);
+            eprintln!("  {} {}", "equation:".cyan(), equation);
+            eprintln!("  {} {}", "cause:".cyan(), message);
+        }
+        Some(rssdsim::Error::Parse(rssdsim::ParseError::Equation { path, error })) => {
+            let file = path.as_ref().map(|path| format!(" in {}", path.display())).unwrap_or_default();
+            eprintln!("{} failed to parse the equation for '{}'{}", label, error.variable.bold(), file);
+            for line in error.underline().lines() {
+                eprintln!("  {}", line);
+            }
+            eprintln!("  {} {} at position {}", "cause:".cyan(), error.message, error.position);
+        }
+        Some(rssdsim::Error::Model(rssdsim::ModelError::Initialization { variable, message })) => {
+            eprintln!("{} failed to initialize '{}'", label, variable.bold());
+            eprintln!("  {} {}", "cause:".cyan(), message);
+        }
+        _ => eprintln!("{} {}", label, error),
+    }
+}
+
+async fn run_command(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
+    match cli.command {
+        Some(Commands::Run { model, output, params, integrator, dt, saveper, seed, stream, vars, checkpoint, checkpoint_every, resume, init, threads, scenarios, parallel, constraints, max_value }) => {
+            let checkpoint = checkpoint.map(|path| (path, checkpoint_every));
+            let scenarios = scenarios.map(|path| (path, parallel));
+            run_simulation(model, output, params, integrator, dt, saveper, seed, stream, vars, checkpoint, resume, init, threads, scenarios, constraints, max_value)?;
+        }
+        Some(Commands::Play { model, interval, integrator, vars, output }) => {
+            play_model(model, interval, integrator, vars, output)?;
+        }
+        Some(Commands::Sensitivity { model, ranges, method, samples, variable, seed, integrator, output }) => {
+            run_sensitivity(model, ranges, method, samples, variable, seed, integrator, output)?;
+        }
+        Some(Commands::MonteCarlo { model, ranges_file, runs, seed, sampling, confidence, save_runs, integrator, format, output }) => {
+            let mc_config = analysis::MonteCarloConfig {
+                n_runs: runs,
+                seed,
+                confidence_level: confidence,
+                save_individual_runs: save_runs || format.eq_ignore_ascii_case("parquet"),
+                sampling: sampling.parse()?,
+            };
+            run_monte_carlo(model, ranges_file, mc_config, integrator, format, output)?;
+        }
+        Some(Commands::Analyze { model, dot, json }) => {
+            analyze_model(model, dot, json)?;
+        }
+        Some(Commands::Bifurcation { model, parameter, from, to, steps, output }) => {
+            let analyzer = analysis::BifurcationAnalyzer::new(&parameter, from, to, steps);
+            run_bifurcation(model, analyzer, output)?;
+        }
+        Some(Commands::Phase { model, x, y, x_range, y_range, grid, starts, integrator, output }) => {
+            draw_phase_portrait(model, (x, y), (x_range, y_range), (grid, starts), parse_integrator(&integrator), output)?;
+        }
+        Some(Commands::Graph { model, format, output }) => {
+            export_graph(model, &format, output)?;
+        }
+        Some(Commands::Optimize { model, data, bounds, objective, algorithm, max_iterations, integrator, output }) => {
+            let config = analysis::OptimizationConfig {
+                max_iterations,
+                integration_method: parse_integrator(&integrator),
+                ..Default::default()
+            };
+            run_optimization(model, data, bounds, objective, algorithm, config, output)?;
+        }
+        Some(Commands::Policy { model, decisions, objective, maximize, algorithm, max_iterations, integrator, output }) => {
+            let config = analysis::OptimizationConfig {
+                max_iterations,
+                integration_method: parse_integrator(&integrator),
+                ..Default::default()
+            };
+            let objective = analysis::PolicyObjective::parse(&objective, maximize);
+            optimize_policy(model, decisions, objective, algorithm, config, output)?;
+        }
+        Some(Commands::Calibrate { model, data, priors, samples, burn_in, chains, thin, noise_sd, level, seed, integrator, output }) => {
+            let config = analysis::McmcConfig {
+                samples,
+                burn_in,
+                chains,
+                thin,
+                seed,
+                noise_sd,
+                integration_method: parse_integrator(&integrator),
+            };
+            run_bayesian_calibration(model, data, priors, config, level, output)?;
+        }
+        Some(Commands::Assimilate { model, data, ensemble, obs_error, process_noise, initial_spread, seed, integrator, output }) => {
+            let config = analysis::AssimilationConfig {
+                ensemble_size: ensemble,
+                observation_error: obs_error,
+                process_noise,
+                initial_spread,
+                seed,
+                integration_method: parse_integrator(&integrator),
+            };
+            run_assimilation(model, data, config, output)?;
+        }
+        Some(Commands::Compare { baseline, scenario, vars, baseline_params, scenario_params, integrator, output }) => {
+            let scenario = scenario.unwrap_or_else(|| baseline.clone());
+            let integration_method = parse_integrator(&integrator);
+            compare_runs((baseline, baseline_params), (scenario, scenario_params), vars, integration_method, output)?;
+        }
+        #[cfg(feature = "plot")]
+        Some(Commands::Plot { input, vars, output, title, y_label, log_x, log_y, width, height }) => {
+            let options = visualization::ChartOptions {
+                title,
+                y_label,
+                width,
+                height,
+                log_x,
+                log_y,
+                ..Default::default()
+            };
+            plot_results(input, vars, output, options)?;
+        }
+        Some(Commands::Diagram { model, output }) => {
+            draw_diagram(model, output)?;
+        }
+        Some(Commands::Mcp) => {
+            // stdout carries the JSON-RPC stream, so nothing else may print to it
+            protocol::McpServer::new().serve_stdio().await?;
+        }
+        Some(Commands::Validate { model, strict_units }) => {
+            validate_model(model, strict_units)?;
+        }
+        Some(Commands::Test { models, integrator }) => {
+            test_models(&models, parse_integrator(&integrator))?;
+        }
+        Some(Commands::Extreme { model, ranges, multiplier, plausibility, integrator, output }) => {
+            let config = analysis::ExtremeConditionsConfig {
+                multiplier,
+                plausibility_factor: plausibility,
+                integration_method: parse_integrator(&integrator),
+            };
+            test_extreme_conditions(model, ranges, config, output)?;
+        }
+        Some(Commands::DtCheck { model, threshold, integrator, recommend, output }) => {
+            let config = analysis::DtSensitivityConfig {
+                threshold,
+                integration_method: parse_integrator(&integrator),
+            };
+            check_dt_sensitivity(model, config, recommend, output)?;
+        }
+        Some(Commands::Export { model, output }) => {
+            export_model(model, output)?;
+        }
+        Some(Commands::ApplyPatch { base, patch, output }) => {
+            apply_patch(base, patch, output)?;
+        }
+        Some(Commands::Info) => {
+            show_info();
+        }
+        Some(Commands::Serve {
+            port, model_dir, cors, data_dir, workers, queue_capacity,
+            mut api_keys, api_keys_file, jwt_secret, rate_limit,
+        }) => {
+            if let Some(path) = api_keys_file {
+                let contents = std::fs::read_to_string(&path)
+                    .map_err(|e| format!("Failed to read '{}': {}", path.display(), e))?;
+                api_keys.extend(contents.lines()
+                    .map(|line| line.split('#').next().unwrap_or("").trim())
+                    .filter(|key| !key.is_empty())
+                    .map(str::to_string));
+            }
+            let config = server::ServerConfig {
+                port,
+                model_dir,
+                cors_origins: cors.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect(),
+                data_dir,
+                workers,
+                queue_capacity,
+                auth: server::AuthConfig { api_keys, jwt_secret, rate_limit },
+            };
+            server::serve(config).await?;
+        }
+        None => {
+            show_info();
+        }
+    }
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_simulation(
+    model_path: PathBuf,
+    output_path: Option<PathBuf>,
+    params: Option<String>,
+    integrator: String,
+    dt_override: Option<f64>,
+    saveper: Option<f64>,
+    seed: Option<u64>,
+    stream: bool,
+    output_variables: Option<Vec<String>>,
+    checkpoint: Option<(PathBuf, Option<f64>)>,
+    resume: Option<PathBuf>,
+    init: String,
+    threads: Option<usize>,
+    scenarios: Option<(PathBuf, bool)>,
+    constraint_action: Option<String>,
+    max_value: Option<f64>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    println!("{}", "Loading model...".cyan());
+    let mut model = io::load_model(&model_path)?;
+
+    println!("  Model: {}", model.metadata.name.green());
+    println!("  Stocks: {}", model.stocks.len());
+    println!("  Flows: {}", model.flows.len());
+    println!("  Parameters: {}", model.parameters.len());
+
+    // Override parameters if specified
+    if let Some(param_str) = params {
+        println!("\n{}", "Applying parameter overrides...".cyan());
+        apply_parameter_overrides(&mut model, &param_str)?;
+    }
+
+    // Override timestep if specified
+    if let Some(dt) = dt_override {
+        println!("\n{}", "Overriding timestep...".cyan());
+        println!("  dt = {} (was {})", dt, model.time.dt);
+        model.time.dt = dt;
+    }
+
+    if seed.is_some() {
+        model.time.seed = seed;
+    }
+
+    if let Some(action) = constraint_action {
+        let action: rssdsim::model::ConstraintAction = action.parse()?;
+        for constraint in &mut model.constraints {
+            constraint.action = action;
+        }
+    }
+
+    // Create simulation config
+    let integration_method = parse_integrator(&integrator);
+    let initialization = match init.to_lowercase().as_str() {
+        "initial" => simulation::Initialization::InitialValues,
+        "equilibrium" => simulation::Initialization::Equilibrium,
+        _ => return Err(format!("Unknown initialization '{}' (expected initial or equilibrium)", init).into()),
+    };
+
+    let output_variables = output_variables.map(|vars| {
+        vars.into_iter().map(|v| v.trim().to_string()).filter(|v| !v.is_empty()).collect()
+    });
+    let run_length = model.time.stop - model.time.start;
+    let checkpoint = checkpoint.map(|(path, every)| {
+        simulation::CheckpointConfig::new(path, every.unwrap_or(run_length / 10.0))
+    });
+    let config = simulation::SimulationConfig {
+        integration_method,
+        output_interval: saveper,
+        output_variables,
+        checkpoint,
+        initialization,
+        threads,
+        max_magnitude: Some(max_value.unwrap_or(f64::INFINITY)),
+        audit_conservation: !model.conservation.is_empty(),
+        ..Default::default()
+    };
+
+    if let Some((scenarios_path, parallel)) = scenarios {
+        let output_dir = output_path.unwrap_or_else(|| PathBuf::from("scenarios"));
+        return run_scenario_batch(&model, &config, &scenarios_path, parallel, &output_dir);
+    }
+
+    println!("\n{}", "Running simulation...".cyan());
+    println!("  Time: {} to {} (dt={})", model.time.start, model.time.stop, model.time.dt);
+    println!("  Integrator: {:?}", integration_method);
+    if let Some(threads) = threads.filter(|&threads| threads > 1) {
+        println!("  Evaluation threads: {}", threads);
+    }
+    if let Some(seed) = model.time.seed {
+        println!("  Seed: {}", seed);
+    }
+
+    // Ctrl-C stops the run after the current step; streamed output keeps what was written
+    let cancellation = simulation::CancellationToken::new();
+    let canceller = cancellation.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            canceller.cancel();
+        }
+    });
+
+    let calendar = model.time.calendar.clone();
+    let mut engine = simulation::SimulationEngine::new(model, config)?
+        .with_cancellation(cancellation);
+    if std::io::stderr().is_terminal() {
+        engine = engine.with_progress(draw_progress_bar);
+    }
+
+    if initialization == simulation::Initialization::Equilibrium {
+        println!("  Initialized at equilibrium");
+    }
+
+    if let Some(resume_path) = resume {
+        engine.resume_from_checkpoint(&resume_path)?;
+        println!("  Resumed from {} at t={}", resume_path.display().to_string().green(), engine.current_time());
+    }
+
+    let output_file = output_path.unwrap_or_else(|| PathBuf::from("results.csv"));
+    let extension = output_file.extension().and_then(|e| e.to_str()).unwrap_or("csv");
+
+    if stream {
+        let mut sink: Box<dyn simulation::ResultSink> = match extension {
+            "nc" => Box::new(io::NetCDFSink::create(&output_file)?),
+            "parquet" => return Err("Parquet output cannot be streamed; drop --stream".into()),
+            _ => Box::new(io::CsvSink::create(&output_file)?.with_calendar(calendar)),
+        };
+        println!("  Streaming to: {}", output_file.display().to_string().green());
+
+        let recorded = engine.run_into(sink.as_mut())?;
+
+        println!("  {} steps completed", recorded.to_string().green());
+        if let Some(stats) = engine.step_statistics() {
+            println!("  Adaptive steps: {} accepted, {} rejected", stats.accepted, stats.rejected);
+        }
+    } else {
+        let results = engine.run()?;
+
+        println!("  {} steps completed", results.times.len().to_string().green());
+        if let Some(stats) = engine.step_statistics() {
+            println!("  Adaptive steps: {} accepted, {} rejected", stats.accepted, stats.rejected);
+        }
+
+        // Write output
+        println!("\n{}", "Writing results...".cyan());
+        match extension {
+            "parquet" => io::ParquetWriter::write(&results, &output_file),
+            _ => io::write_csv(&results, &output_file).map_err(String::from),
+        }
+        .map_err(|e| format!("Failed to write results: {}", e))?;
+
+        println!("  Output: {}", output_file.display().to_string().green());
+    }
+
+    if let Some(recorder) = engine.agent_output() {
+        write_agent_output(recorder, &output_file)?;
+    }
+
+    print_reality_checks(&engine.current_state().constraints);
+    if let Some(audit) = engine.conservation_audit() {
+        print_conservation_audit(audit);
+    }
+
+    println!("\n{}", "✓ Simulation complete!".green().bold());
+
+    Ok(())
+}
+
+/// Write agent snapshots and histograms next to the results in `output_file`
+fn write_agent_output(recorder: &simulation::AgentRecorder, output_file: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let stem = output_file.file_stem().and_then(|s| s.to_str()).unwrap_or("results");
+    let directory = output_file.parent().unwrap_or(Path::new(""));
+
+    if !recorder.snapshots.is_empty() {
+        let default_extension = if cfg!(feature = "with-parquet") { "parquet" } else { "csv" };
+        let path = match &recorder.config.file {
+            Some(file) => directory.join(file),
+            None => directory.join(format!("{}_agents.{}", stem, default_extension)),
+        };
+        match path.extension().and_then(|e| e.to_str()).unwrap_or("csv") {
+            "parquet" => io::ParquetWriter::write_agents(&recorder.snapshots, &path),
+            "h5" | "hdf5" => io::HDF5Writer::write_agents(&recorder.snapshots, &path),
+            _ => io::writer::CsvWriter::write_agents(&recorder.snapshots, &path),
+        }
+        .map_err(|e| format!("Failed to write agent snapshots: {}", e))?;
+        println!("  Agents: {} ({} rows)", path.display().to_string().green(), recorder.snapshots.len());
+    }
+
+    if !recorder.histograms.is_empty() {
+        let path = directory.join(format!("{}_histograms.csv", stem));
+        io::writer::CsvWriter::write_histograms(&recorder.histograms, &path)
+            .map_err(|e| format!("Failed to write histograms: {}", e))?;
+        println!("  Histograms: {}", path.display().to_string().green());
+    }
+    Ok(())
+}
+
+/// How often each of the model's constraints failed during the run
+fn print_reality_checks(monitor: &simulation::ConstraintMonitor) {
+    if monitor.summaries.is_empty() {
+        return;
+    }
+    println!("\n{}", "Reality checks:".cyan());
+    for summary in &monitor.summaries {
+        match (summary.first_failure, summary.last_failure) {
+            (Some(first), Some(last)) => println!("  {} {}: failed {} of {} checks, from t={} to t={}",
+                "✗".red().bold(), summary.name, summary.failures.to_string().red(), summary.checks, first, last),
+            _ => println!("  {} {}: held at all {} checks", "✓".green().bold(), summary.name, summary.checks),
+        }
+    }
+    if !monitor.log.is_empty() {
+        println!("  Logged failures:");
+        for violation in monitor.log.iter().take(20) {
+            println!("    t={} {}", violation.time, violation.constraint);
+        }
+        if monitor.log.len() > 20 {
+            println!("    ... and {} more", monitor.log.len() - 20);
+        }
+    }
+}
+
+/// Totals of the model's conserved groups, and the flows that broke their balance
+fn print_conservation_audit(audit: &simulation::ConservationAudit) {
+    if audit.groups.is_empty() {
+        return;
+    }
+    println!("\n{}", "Conservation audit:".cyan());
+    for group in &audit.groups {
+        let totals = format!("total {} -> {}", group.initial_total, group.final_total);
+        match group.first_failure {
+            Some(first) => println!("  {} {}: {}, {} of {} steps out of balance from t={}, imbalance {}",
+                "✗".red().bold(), group.name, totals, group.failures.to_string().red(), group.steps, first, group.imbalance),
+            None => println!("  {} {}: {}, balanced at all {} steps", "✓".green().bold(), group.name, totals, group.steps),
+        }
+        for (leak, amount) in group.leaks.iter().filter(|(_, amount)| *amount != 0.0) {
+            println!("    {} ({} over the run)", leak, amount);
+        }
+    }
+}
+
+/// Run every scenario in `scenarios_path`, writing `<name>.csv` for each and a combined long-format file
+fn run_scenario_batch(
+    model: &rssdsim::model::Model,
+    config: &simulation::SimulationConfig,
+    scenarios_path: &Path,
+    parallel: bool,
+    output_dir: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let scenarios = io::load_scenarios(scenarios_path)
+        .map_err(|e| format!("Failed to load scenarios: {}", e))?;
+
+    println!("\n{}", "Running scenarios...".cyan());
+    println!("  Scenarios: {}", scenarios.len());
+    println!("  Integrator: {:?}", config.integration_method);
+    if parallel {
+        println!("  Parallel execution enabled");
+    }
+
+    let runs = analysis::run_scenarios(model, &scenarios, config, parallel)?;
+
+    println!("\n{}", "Writing results...".cyan());
+    std::fs::create_dir_all(output_dir)
+        .map_err(|e| format!("Failed to create {}: {}", output_dir.display(), e))?;
+    for (name, results) in &runs {
+        let path = output_dir.join(format!("{}.csv", name));
+        io::write_csv(results, &path)
+            .map_err(|e| format!("Failed to write results: {}", e))?;
+        println!("  {}: {} steps -> {}", name, results.times.len(), path.display().to_string().green());
+    }
+
+    let combined = output_dir.join("combined.csv");
+    std::fs::write(&combined, analysis::scenarios::combined_csv(&runs))
+        .map_err(|e| format!("Failed to write {}: {}", combined.display(), e))?;
+    println!("  Combined: {}", combined.display().to_string().green());
+
+    println!("\n{}", "✓ Scenarios complete!".green().bold());
+
+    Ok(())
+}
+
+/// Simulate `interval` steps at a time, reading parameter changes from stdin between them
+fn play_model(
+    model_path: PathBuf,
+    interval: usize,
+    integrator: String,
+    vars: Option<Vec<String>>,
+    output_path: Option<PathBuf>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use std::io::{BufRead, Write as _};
+
+    if interval == 0 {
+        return Err("The decision interval must be at least one step".into());
+    }
+    let model = io::load_model(&model_path)?;
+    println!("  Model: {}", model.metadata.name.green());
+    let shown = vars.unwrap_or_else(|| {
+        let mut stocks: Vec<String> = model.stocks.keys().cloned().collect();
+        stocks.sort();
+        stocks
+    });
+    let dt = model.time.dt;
+
+    let config = simulation::SimulationConfig { integration_method: parse_integrator(&integrator), ..Default::default() };
+    let mut engine = simulation::SimulationEngine::new(model, config)?;
+    let mut results = simulation::SimulationResults::new();
+    println!("  Deciding every {} steps ({} time units)", interval, interval as f64 * dt);
+    println!("  Enter changes as name=value[,name=value], a blank line to continue, or q to stop\n");
+
+    let mut lines = std::io::stdin().lock().lines();
+    'play: loop {
+        engine.run_steps(interval, &mut results)?;
+        let state = engine.current_state();
+        println!("{}", format!("t={}", state.time).cyan().bold());
+        for name in &shown {
+            let value = state.stocks.get(name).or(state.flows.get(name)).or(state.auxiliaries.get(name));
+            match value {
+                Some(value) => println!("  {:<24} {:.6}", name, value),
+                None => println!("  {:<24} {}", name, "(not a stock, flow or auxiliary)".dimmed()),
+            }
+        }
+        if engine.is_finished() {
+            break;
+        }
+
+        loop {
+            print!("{} ", "decision>".green());
+            std::io::stdout().flush()?;
+            let Some(line) = lines.next().transpose()? else {
+                // Out of input: play the rest without further decisions
+                println!();
+                break;
+            };
+            let line = line.trim();
+            match line {
+                "" => break,
+                "q" | "quit" => break 'play,
+                _ => {
+                    for change in line.split(',') {
+                        if let Err(e) = apply_decision(&mut engine, change) {
+                            eprintln!("  {} {}", "Error:".red(), e);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    if let Some(path) = output_path {
+        io::write_csv(&results, &path)?;
+        println!("\n  Output: {}", path.display().to_string().green());
+    }
+    Ok(())
+}
+
+/// Apply one `name=value` decision to a parameter, or else to a stock's current value
+fn apply_decision(engine: &mut simulation::SimulationEngine, change: &str) -> Result<(), String> {
+    let (name, value) = change.split_once('=')
+        .ok_or_else(|| format!("Invalid change '{}' (expected name=value)", change.trim()))?;
+    let (name, value) = (name.trim(), value.trim());
+    let value: f64 = value.parse().map_err(|_| format!("Invalid value '{}' for '{}'", value, name))?;
+    if engine.set_parameter(name, value).is_ok() {
+        println!("  {} = {}", name, value);
+        return Ok(());
+    }
+    engine.set_stock(name, value).map_err(|_| format!("No parameter or stock named '{}'", name))?;
+    println!("  {} = {} (stock)", name, value);
+    Ok(())
+}
+
+/// Redraw a one-line progress bar on stderr
+fn draw_progress_bar(progress: &simulation::Progress) {
+    const WIDTH: usize = 30;
+    let filled = (progress.fraction * WIDTH as f64).round() as usize;
+    let eta = match progress.eta {
+        Some(eta) if progress.fraction < 1.0 => format!(", ETA {:.0}s", eta.as_secs_f64()),
+        _ => String::new(),
+    };
+    eprint!(
+        "\r  [{}{}] {:>3.0}% t={:.2} ({} steps{})\x1b[K",
+        "#".repeat(filled), "-".repeat(WIDTH - filled),
+        progress.fraction * 100.0, progress.time, progress.steps, eta
+    );
+    if progress.fraction >= 1.0 {
+        eprintln!();
+    }
+}
+
+/// Apply overrides such as "a=1,capacity[North]=100,Stock.initial=5,time.stop=200"
+fn apply_parameter_overrides(model: &mut rssdsim::model::Model, overrides: &str) -> Result<(), String> {
+    for item in rssdsim::model::Override::parse_list(overrides)? {
+        match item.apply(model)? {
+            Some(previous) => println!("  {} = {} (was {})", item.target, item.value, previous),
+            None => println!("  {} = {}", item.target, item.value),
+        }
+    }
+    Ok(())
+}
+
+fn parse_integrator(integrator: &str) -> simulation::IntegrationMethod {
+    integrator.parse().unwrap_or_else(|e| {
+        eprintln!("{} {}, using Euler", "Warning:".yellow(), e);
+        simulation::IntegrationMethod::Euler
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_sensitivity(
+    model_path: PathBuf,
+    ranges_path: PathBuf,
+    method: String,
+    samples: usize,
+    variable: String,
+    seed: Option<u64>,
+    integrator: String,
+    output_path: Option<PathBuf>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    println!("{}", "Loading model...".cyan());
+    let model = io::load_model(&model_path)?;
+    println!("  Model: {}", model.metadata.name.green());
+
+    let ranges = io::load_parameter_ranges(&ranges_path)
+        .map_err(|e| format!("Failed to load ranges: {}", e))?;
+    for range in &ranges {
+        if !model.parameters.contains_key(&range.name) {
+            return Err(format!("Parameter '{}' not found in model", range.name).into());
+        }
+        println!("  {} in [{}, {}] (baseline {})", range.name, range.min, range.max, range.baseline);
+    }
+
+    // A bare variable name means its final value
+    let metric = if ["_final", "_mean", "_max", "_min"].iter().any(|s| variable.ends_with(s)) {
+        variable
+    } else {
+        format!("{}_final", variable)
+    };
+
+    let config = simulation::SimulationConfig {
+        integration_method: parse_integrator(&integrator),
+        ..Default::default()
+    };
+
+    let mut analyzer = analysis::SensitivityAnalyzer::new(ranges);
+    println!("\n{}", "Running sensitivity analysis...".cyan());
+    match method.to_lowercase().as_str() {
+        "sweep" => analyzer.parameter_sweep(&model, &config, samples)?,
+        "lhs" => analyzer.latin_hypercube_sampling(&model, &config, samples, seed)?,
+        "sobol" => analyzer.sample_space(&model, &config, analysis::SamplingMethod::Sobol, samples, seed)?,
+        "morris" => analyzer.morris_screening(&model, &config, samples, 4, seed)?,
+        _ => return Err(format!("Unknown sampling method '{}' (expected sweep, lhs, sobol or morris)", method).into()),
+    }
+    println!("  {} simulations completed", analyzer.results.len().to_string().green());
+
+    if let Some(first) = analyzer.results.first()
+        && !first.metrics.contains_key(&metric)
+    {
+        return Err(format!("Output variable '{}' not found in results", metric).into());
+    }
+
+    if method.eq_ignore_ascii_case("morris") {
+        println!("\n{}", "Morris elementary effects (mu*, sigma):".bold());
+        let mut effects: Vec<_> = analyzer.calculate_morris_effects(&metric).into_iter().collect();
+        effects.sort_by(|a, b| b.1.0.total_cmp(&a.1.0));
+        for (name, (mu_star, sigma)) in effects {
+            println!("  {:<24} {:>12.6} {:>12.6}", name, mu_star, sigma);
+        }
+    }
+
+    let output_file = output_path.unwrap_or_else(|| PathBuf::from("sensitivity.csv"));
+    let csv = analyzer.export_results(&metric)?;
+    std::fs::write(&output_file, csv)
+        .map_err(|e| format!("Failed to write results: {}", e))?;
+    println!("\n  Output: {}", output_file.display().to_string().green());
+
+    println!("\n{}", "✓ Sensitivity analysis complete!".green().bold());
+
+    Ok(())
+}
+
+fn run_monte_carlo(
+    model_path: PathBuf,
+    ranges_path: PathBuf,
+    mc_config: analysis::MonteCarloConfig,
+    integrator: String,
+    format: String,
+    output_path: Option<PathBuf>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if mc_config.confidence_level <= 0.0 || mc_config.confidence_level >= 1.0 {
+        return Err(format!("Confidence level must be between 0 and 1, got {}", mc_config.confidence_level).into());
+    }
+    if mc_config.n_runs == 0 {
+        return Err("Number of runs must be positive".into());
+    }
+
+    println!("{}", "Loading model...".cyan());
+    let model = io::load_model(&model_path)?;
+    println!("  Model: {}", model.metadata.name.green());
+
+    let ranges = io::load_parameter_ranges(&ranges_path)
+        .map_err(|e| format!("Failed to load ranges: {}", e))?;
+    for range in &ranges {
+        if !model.parameters.contains_key(&range.name) {
+            return Err(format!("Parameter '{}' not found in model", range.name).into());
+        }
+        println!("  {} in [{}, {}]", range.name, range.min, range.max);
+    }
+
+    let config = simulation::SimulationConfig {
+        integration_method: parse_integrator(&integrator),
+        ..Default::default()
+    };
+
+    println!("\n{}", "Running Monte Carlo analysis...".cyan());
+    println!("  Runs: {} (threads: {})", mc_config.n_runs, rayon::current_num_threads());
+    let simulator = analysis::ParallelMonteCarloSimulator::new(ranges, mc_config);
+    let results = simulator.run(&model, &config)?;
+    println!("  {} runs completed", results.n_runs.to_string().green());
+
+    println!("\n{}", "Writing results...".cyan());
+    match format.to_lowercase().as_str() {
+        "csv" => {
+            let output_dir = output_path.unwrap_or_else(|| PathBuf::from("montecarlo"));
+            std::fs::create_dir_all(&output_dir)
+                .map_err(|e| format!("Failed to create output directory: {}", e))?;
+
+            let mut names: Vec<&String> = results.statistics.keys().collect();
+            names.sort();
+            for name in names {
+                let path = output_dir.join(format!("{}_stats.csv", name));
+                std::fs::write(&path, results.export_csv(name)?)
+                    .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+                println!("  {}", path.display().to_string().green());
+
+                if results.individual_runs.is_some() {
+                    let path = output_dir.join(format!("{}_runs.csv", name));
+                    std::fs::write(&path, results.export_runs_csv(name)?)
+                        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+                    println!("  {}", path.display().to_string().green());
+                }
+            }
+        }
+        "netcdf" | "nc" => {
+            let output_file = output_path.unwrap_or_else(|| PathBuf::from("montecarlo.nc"));
+            io::NetCDFWriter::write_monte_carlo(&results, &output_file)?;
+            println!("  Output: {}", output_file.display().to_string().green());
+        }
+        "hdf5" | "h5" => {
+            let output_file = output_path.unwrap_or_else(|| PathBuf::from("montecarlo.h5"));
+            io::HDF5Writer::write_monte_carlo(&results, &output_file)?;
+            println!("  Output: {}", output_file.display().to_string().green());
+        }
+        "parquet" => {
+            let output_file = output_path.unwrap_or_else(|| PathBuf::from("montecarlo.parquet"));
+            io::ParquetWriter::write_monte_carlo(&results, &output_file)?;
+            println!("  Output: {}", output_file.display().to_string().green());
+        }
+        _ => return Err(format!("Unknown output format '{}' (expected csv, netcdf, hdf5 or parquet)", format).into()),
+    }
+
+    println!("\n{}", "✓ Monte Carlo analysis complete!".green().bold());
+
+    Ok(())
+}
+
+fn analyze_model(
+    model_path: PathBuf,
+    dot_path: Option<PathBuf>,
+    json: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let model = io::load_model(&model_path)?;
+    let analyzer = analysis::StructureAnalyzer::new(&model);
+
+    if let Some(path) = &dot_path {
+        std::fs::write(path, analyzer.export_dot())
+            .map_err(|e| format!("Failed to write DOT file: {}", e))?;
+    }
+
+    // Keep stdout machine-readable in JSON mode
+    if json {
+        println!("{}", serde_json::to_string_pretty(&analyzer.export_json())?);
+        return Ok(());
+    }
+
+    println!("  Model: {}\n", model.metadata.name.green());
+    print!("{}", analyzer.generate_report());
+
+    if let Some(path) = dot_path {
+        println!("\n  Graph: {}", path.display().to_string().green());
+    }
+
+    Ok(())
+}
+
+fn run_bifurcation(
+    model_path: PathBuf,
+    analyzer: analysis::BifurcationAnalyzer,
+    output_path: Option<PathBuf>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    println!("{}", "Loading model...".cyan());
+    let model = io::load_model(&model_path)?;
+    println!("  Model: {}", model.metadata.name.green());
+
+    println!("\n{}", format!("Sweeping {} from {} to {}...", analyzer.parameter, analyzer.from, analyzer.to).cyan());
+    let diagram = analyzer.run(&model)?;
+    println!("  Equilibria found at {} of {} values", diagram.points.len(), analyzer.steps + 1);
+    if !diagram.unsolved.is_empty() {
+        println!("  {}", format!("No equilibrium at {} values", diagram.unsolved.len()).yellow());
+    }
+
+    println!("\n{}", "Bifurcations:".cyan());
+    if diagram.bifurcations.is_empty() {
+        println!("  None found");
+    }
+    for bifurcation in &diagram.bifurcations {
+        let period = bifurcation.period.map_or(String::new(), |p| format!(" (period {:.4})", p));
+        println!("  {:?} at {} = {}{}", bifurcation.kind, diagram.parameter,
+            format!("{:.6}", bifurcation.value).green(), period);
+    }
+
+    let output_dir = output_path.unwrap_or_else(|| PathBuf::from("bifurcation"));
+    std::fs::create_dir_all(&output_dir)
+        .map_err(|e| format!("Failed to create output directory: {}", e))?;
+
+    println!("\n{}", "Writing results...".cyan());
+    for (file, contents) in [("diagram.csv", diagram.to_csv()), ("bifurcations.csv", diagram.bifurcations_csv())] {
+        let path = output_dir.join(file);
+        std::fs::write(&path, contents)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        println!("  {}", path.display().to_string().green());
+    }
+
+    println!("\n{}", "✓ Bifurcation analysis complete!".green().bold());
+
+    Ok(())
+}
+
+fn draw_phase_portrait(
+    model_path: PathBuf,
+    (x, y): (String, String),
+    (x_range, y_range): (Option<Vec<f64>>, Option<Vec<f64>>),
+    (grid, starts): (usize, usize),
+    integration_method: simulation::IntegrationMethod,
+    output_path: Option<PathBuf>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    println!("{}", "Loading model...".cyan());
+    let model = io::load_model(&model_path)?;
+    println!("  Model: {}", model.metadata.name.green());
+
+    let parse_range = |values: Option<Vec<f64>>, axis: &str| -> Result<Option<(f64, f64)>, String> {
+        match values.as_deref() {
+            None => Ok(None),
+            Some(&[min, max]) => Ok(Some((min, max))),
+            Some(_) => Err(format!("--{}-range takes two values, min,max", axis)),
+        }
+    };
+    let (x_range, y_range) = (parse_range(x_range, "x")?, parse_range(y_range, "y")?);
+
+    // Frame a missing range around the path of a run from the initial values
+    let results = match (x_range, y_range) {
+        (Some(_), Some(_)) => None,
+        _ => {
+            let config = simulation::SimulationConfig { integration_method, ..Default::default() };
+            Some(simulation::SimulationEngine::new(model.clone(), config)?.run()?)
+        }
+    };
+    let frame = |range: Option<(f64, f64)>, name: &str| -> Result<(f64, f64), String> {
+        if let Some(range) = range {
+            return Ok(range);
+        }
+        let series = results.as_ref().and_then(|r| r.series(name))
+            .ok_or_else(|| format!("Stock '{}' not found in model", name))?;
+        let min = series.iter().copied().fold(0.0, f64::min);
+        let max = series.iter().copied().fold(0.0, f64::max);
+        Ok((1.2 * min, if max > 0.0 { 1.2 * max } else { 1.0 }))
+    };
+    let (x_range, y_range) = (frame(x_range, &x)?, frame(y_range, &y)?);
+
+    println!("\n{}", format!("Mapping {} ∈ [{}, {}] against {} ∈ [{}, {}]...", x, x_range.0, x_range.1, y, y_range.0, y_range.1).cyan());
+    let plane = analysis::PhasePlane { grid, starts, integration_method, ..analysis::PhasePlane::new(&x, &y, x_range, y_range) };
+    let portrait = plane.run(&model)?;
+    println!("  {} vector field samples", portrait.vectors.len());
+    println!("  {} nullcline points: {}, {} nullcline points: {}", x, portrait.x_nullcline.len(), y, portrait.y_nullcline.len());
+    println!("  {} trajectories", portrait.trajectories.len());
 
-    /// Show version and info
-    Info,
+    let output_dir = output_path.unwrap_or_else(|| PathBuf::from("phase"));
+    std::fs::create_dir_all(&output_dir)
+        .map_err(|e| format!("Failed to create output directory: {}", e))?;
 
-    /// Start web server
-    Serve {
-        /// Port to listen on
-        #[arg(short, long, default_value = "8080")]
-        port: u16,
-    },
+    println!("\n{}", "Writing results...".cyan());
+    let files = [
+        ("vector_field.csv", portrait.vectors_csv()),
+        ("nullclines.csv", portrait.nullclines_csv()),
+        ("trajectories.csv", portrait.trajectories_csv()),
+    ];
+    for (file, contents) in files {
+        let path = output_dir.join(file);
+        std::fs::write(&path, contents)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        println!("  {}", path.display().to_string().green());
+    }
+
+    #[cfg(feature = "plot")]
+    {
+        let path = output_dir.join("portrait.svg");
+        let options = visualization::ChartOptions { title: Some(model.metadata.name.clone()), ..Default::default() };
+        portrait.render(&path, &options)?;
+        println!("  {}", path.display().to_string().green());
+    }
+
+    println!("\n{}", "✓ Phase portrait complete!".green().bold());
+
+    Ok(())
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let cli = Cli::parse();
+fn run_optimization(
+    model_path: PathBuf,
+    data_path: PathBuf,
+    bounds_path: PathBuf,
+    objective: String,
+    algorithm: String,
+    config: analysis::OptimizationConfig,
+    output_path: Option<PathBuf>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use std::fmt::Write as _;
 
-    match cli.command {
-        Some(Commands::Run { model, output, params, integrator, dt }) => {
-            run_simulation(model, output, params, integrator, dt)?;
-        }
-        Some(Commands::Validate { model }) => {
-            validate_model(model)?;
-        }
-        Some(Commands::Info) => {
-            show_info();
+    println!("{}", "Loading model...".cyan());
+    let mut model = io::load_model(&model_path)?;
+    println!("  Model: {}", model.metadata.name.green());
+
+    let contents = std::fs::read_to_string(&data_path)
+        .map_err(|e| format!("Failed to read observed data: {}", e))?;
+    let data = analysis::ObservedData::from_csv(&contents)?.in_model_time(model.time.calendar.as_ref())?;
+    println!("  Observations: {} times of {}", data.times.len(),
+        data.series.keys().cloned().collect::<Vec<_>>().join(", "));
+
+    let bounds: Vec<analysis::ParameterBounds> = io::load_parameter_ranges(&bounds_path)
+        .map_err(|e| format!("Failed to load bounds: {}", e))?
+        .into_iter()
+        .map(|range| analysis::ParameterBounds::new(&range.name, range.min, range.max))
+        .collect();
+    for bound in &bounds {
+        if !model.parameters.contains_key(&bound.name) {
+            return Err(format!("Parameter '{}' not found in model", bound.name).into());
         }
-        Some(Commands::Serve { port }) => {
-            server::serve(port).await;
+    }
+
+    let fit_objective = analysis::FitObjective::parse(&objective)?;
+    let integration_method = config.integration_method;
+
+    println!("\n{}", "Calibrating...".cyan());
+    println!("  Algorithm: {}, objective: {:?}", algorithm, fit_objective);
+    let result = match algorithm.to_lowercase().as_str() {
+        "gradient" | "bfgs" => analysis::GradientOptimizer::new(config, bounds)
+            .optimize(&model, data.clone().objective_function(fit_objective))?,
+        "genetic" | "ga" => analysis::GeneticOptimizer::new(config, bounds)
+            .optimize(&model, data.clone().objective_function(fit_objective))?,
+        _ => return Err(format!("Unknown algorithm '{}' (expected gradient or genetic)", algorithm).into()),
+    };
+
+    println!("  Iterations: {} (converged: {})", result.iterations, result.converged);
+    println!("  Objective: {}", result.objective_value.to_string().green());
+
+    let mut names: Vec<&String> = result.parameters.keys().collect();
+    names.sort();
+
+    let mut parameters_csv = String::from("parameter,initial,calibrated\n");
+    for name in &names {
+        let calibrated = result.parameters[*name];
+        let initial = model.parameters[*name].value;
+        println!("  {} = {} (was {})", name, calibrated, initial);
+        writeln!(parameters_csv, "{},{},{}", name, initial, calibrated)?;
+        model.parameters.get_mut(*name).expect("bounds checked above").value = calibrated;
+    }
+
+    let mut history_csv = String::from("iteration,objective\n");
+    for (i, value) in result.history.iter().enumerate() {
+        writeln!(history_csv, "{},{}", i, value)?;
+    }
+
+    // Rerun with the calibrated values for the fit trajectory
+    let sim_config = simulation::SimulationConfig {
+        integration_method,
+        ..Default::default()
+    };
+    let results = simulation::SimulationEngine::new(model, sim_config)?.run()?;
+    let simulated = data.simulated(&results)?;
+
+    let statistics = analysis::FitStatistics::for_results(&data, &results, names.len())?;
+    println!("\n{}", "Goodness of fit:".cyan());
+    for s in &statistics {
+        println!("  {}: RMSE {:.4}, MAE {:.4}, MAPE {:.2}%, R² {:.4}", s.variable, s.rmse, s.mae, s.mape, s.r_squared);
+        println!("    Theil U {:.4} (bias {:.2}, variance {:.2}, covariance {:.2}), AIC {:.2}, BIC {:.2}",
+            s.theil_u, s.bias_fraction, s.variance_fraction, s.covariance_fraction, s.aic, s.bic);
+    }
+
+    let mut fit_csv = String::from("time");
+    for name in data.series.keys() {
+        write!(fit_csv, ",{}_observed,{}_simulated", name, name)?;
+    }
+    fit_csv.push('\n');
+    for (i, time) in data.times.iter().enumerate() {
+        write!(fit_csv, "{}", time)?;
+        for (name, observed) in &data.series {
+            let observed = if observed[i].is_nan() { String::new() } else { observed[i].to_string() };
+            write!(fit_csv, ",{},{}", observed, simulated[name][i])?;
         }
-        None => {
-            show_info();
+        fit_csv.push('\n');
+    }
+
+    let output_dir = output_path.unwrap_or_else(|| PathBuf::from("calibration"));
+    std::fs::create_dir_all(&output_dir)
+        .map_err(|e| format!("Failed to create output directory: {}", e))?;
+
+    println!("\n{}", "Writing results...".cyan());
+    let files = [
+        ("parameters.csv", parameters_csv),
+        ("history.csv", history_csv),
+        ("fit.csv", fit_csv),
+        ("fit_statistics.csv", analysis::fit_statistics_csv(&statistics)),
+    ];
+    for (file, contents) in files {
+        let path = output_dir.join(file);
+        std::fs::write(&path, contents)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        println!("  {}", path.display().to_string().green());
+    }
+    let path = output_dir.join("trajectory.csv");
+    io::write_csv(&results, &path)?;
+    println!("  {}", path.display().to_string().green());
+
+    println!("\n{}", "✓ Calibration complete!".green().bold());
+
+    Ok(())
+}
+
+fn optimize_policy(
+    model_path: PathBuf,
+    decisions_path: PathBuf,
+    objective: analysis::PolicyObjective,
+    algorithm: String,
+    config: analysis::OptimizationConfig,
+    output_path: Option<PathBuf>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    println!("{}", "Loading model...".cyan());
+    let model = io::load_model(&model_path)?;
+    println!("  Model: {}", model.metadata.name.green());
+
+    let decisions = io::load_decisions(&decisions_path)
+        .map_err(|e| format!("Failed to load decision variables: {}", e))?;
+    for decision in &decisions {
+        println!("  {} in [{}, {}] from {}", decision.name, decision.min, decision.max,
+            decision.times.iter().map(|t| t.to_string()).collect::<Vec<_>>().join(", "));
+    }
+    let problem = analysis::PolicyProblem::new(decisions);
+    let prepared = problem.prepare(&model)?;
+
+    let integration_method = config.integration_method;
+    let direction = if objective.maximize { "Maximizing" } else { "Minimizing" };
+    println!("\n{}", format!("{} {}...", direction, objective.variable).cyan());
+    let objective_fn = objective.clone().objective_function();
+    let result = match algorithm.to_lowercase().as_str() {
+        "gradient" | "bfgs" => analysis::GradientOptimizer::new(config, problem.bounds()).optimize(&prepared, objective_fn)?,
+        "genetic" | "ga" => analysis::GeneticOptimizer::new(config, problem.bounds()).optimize(&prepared, objective_fn)?,
+        _ => return Err(format!("Unknown algorithm '{}' (expected gradient or genetic)", algorithm).into()),
+    };
+    println!("  Iterations: {} (converged: {})", result.iterations, result.converged);
+
+    let policy = problem.policy(&result.parameters);
+    for schedule in &policy.decisions {
+        println!("  {}:", schedule.variable);
+        for (time, value) in schedule.times.iter().zip(&schedule.values) {
+            println!("    from {}: {}", time, value);
         }
     }
 
+    // Rerun with the optimal schedule for its trajectory
+    let sim_config = simulation::SimulationConfig {
+        integration_method,
+        ..Default::default()
+    };
+    let results = simulation::SimulationEngine::new(policy.apply(&model)?, sim_config)?.run()?;
+    println!("  Objective: {}", objective.value(&results)?.to_string().green());
+
+    let output_dir = output_path.unwrap_or_else(|| PathBuf::from("policy"));
+    std::fs::create_dir_all(&output_dir)
+        .map_err(|e| format!("Failed to create output directory: {}", e))?;
+
+    println!("\n{}", "Writing results...".cyan());
+    for (file, contents) in [("events.yaml", policy.to_events_yaml()), ("schedule.csv", policy.to_csv())] {
+        let path = output_dir.join(file);
+        std::fs::write(&path, contents)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        println!("  {}", path.display().to_string().green());
+    }
+    let path = output_dir.join("trajectory.csv");
+    io::write_csv(&results, &path)?;
+    println!("  {}", path.display().to_string().green());
+
+    println!("\n{}", "✓ Policy optimization complete!".green().bold());
+
     Ok(())
 }
 
-fn run_simulation(
+fn run_bayesian_calibration(
     model_path: PathBuf,
+    data_path: PathBuf,
+    priors_path: PathBuf,
+    config: analysis::McmcConfig,
+    level: f64,
     output_path: Option<PathBuf>,
-    params: Option<String>,
-    integrator: String,
-    dt_override: Option<f64>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     println!("{}", "Loading model...".cyan());
-    let mut model = io::load_model(&model_path)
-        .map_err(|e| format!("Failed to load model: {}", e))?;
+    let model = io::load_model(&model_path)?;
+    println!("  Model: {}", model.metadata.name.green());
+
+    let contents = std::fs::read_to_string(&data_path)
+        .map_err(|e| format!("Failed to read observed data: {}", e))?;
+    let data = analysis::ObservedData::from_csv(&contents)?.in_model_time(model.time.calendar.as_ref())?;
+    println!("  Observations: {} times of {}", data.times.len(),
+        data.series.keys().cloned().collect::<Vec<_>>().join(", "));
+
+    let priors = io::load_priors(&priors_path)
+        .map_err(|e| format!("Failed to load priors: {}", e))?;
+
+    println!("\n{}", "Sampling posterior...".cyan());
+    println!("  {} chains of {} draws after {} burn-in iterations", config.chains, config.samples, config.burn_in);
+    let posterior = analysis::BayesianCalibrator::new(priors, data, config).run(&model)?;
+
+    for (i, chain) in posterior.chains.iter().enumerate() {
+        println!("  Chain {}: acceptance rate {:.2}", i, chain.acceptance_rate);
+    }
+    println!("\n{}", format!("Posterior ({:.0}% credible intervals):", level * 100.0).cyan());
+    let summary = posterior.summary(level);
+    for s in &summary {
+        println!("  {} = {:.6} ± {:.6}  [{:.6}, {:.6}]  R-hat {:.3}, ESS {:.0}",
+            s.name, s.mean, s.sd, s.lower, s.upper, s.r_hat, s.ess);
+    }
+    if summary.iter().any(|s| s.r_hat > 1.1) {
+        println!("  {}", "R-hat above 1.1: the chains have not converged; run longer or with more burn-in".yellow());
+    }
 
+    let output_dir = output_path.unwrap_or_else(|| PathBuf::from("posterior"));
+    std::fs::create_dir_all(&output_dir)
+        .map_err(|e| format!("Failed to create output directory: {}", e))?;
+
+    println!("\n{}", "Writing results...".cyan());
+    for (file, contents) in [("samples.csv", posterior.to_csv()), ("summary.csv", posterior.summary_csv(level))] {
+        let path = output_dir.join(file);
+        std::fs::write(&path, contents)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        println!("  {}", path.display().to_string().green());
+    }
+
+    println!("\n{}", "✓ Bayesian calibration complete!".green().bold());
+
+    Ok(())
+}
+
+fn run_assimilation(
+    model_path: PathBuf,
+    data_path: PathBuf,
+    config: analysis::AssimilationConfig,
+    output_path: Option<PathBuf>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    println!("{}", "Loading model...".cyan());
+    let model = io::load_model(&model_path)?;
     println!("  Model: {}", model.metadata.name.green());
-    println!("  Stocks: {}", model.stocks.len());
-    println!("  Flows: {}", model.flows.len());
-    println!("  Parameters: {}", model.parameters.len());
 
-    // Override parameters if specified
-    if let Some(param_str) = params {
-        println!("\n{}", "Applying parameter overrides...".cyan());
-        for pair in param_str.split(',') {
-            let parts: Vec<&str> = pair.split('=').collect();
-            if parts.len() == 2 {
-                let name = parts[0].trim();
-                let value: f64 = parts[1].trim().parse()
-                    .map_err(|_| format!("Invalid parameter value: {}", parts[1]))?;
-
-                if let Some(param) = model.parameters.get_mut(name) {
-                    println!("  {} = {} (was {})", name, value, param.value);
-                    param.value = value;
-                } else {
-                    eprintln!("  {} {}", "Warning:".yellow(), format!("Parameter '{}' not found", name));
-                }
+    let contents = std::fs::read_to_string(&data_path)
+        .map_err(|e| format!("Failed to read observed data: {}", e))?;
+    let data = analysis::ObservedData::from_csv(&contents)?.in_model_time(model.time.calendar.as_ref())?;
+    println!("  Observations: {} times of {}", data.times.len(),
+        data.series.keys().cloned().collect::<Vec<_>>().join(", "));
+
+    println!("\n{}", "Assimilating...".cyan());
+    println!("  Ensemble of {} members", config.ensemble_size);
+    let results = analysis::EnsembleKalmanFilter::new(config).run(&model, &data)?;
+
+    println!("  {} observations assimilated", results.innovations.len().to_string().green());
+    let last = results.times.len() - 1;
+    for (name, mean) in &results.mean {
+        println!("  {} at t={}: {:.4} ± {:.4}", name, results.times[last], mean[last], results.sd[name][last]);
+    }
+
+    let output_dir = output_path.unwrap_or_else(|| PathBuf::from("assimilation"));
+    std::fs::create_dir_all(&output_dir)
+        .map_err(|e| format!("Failed to create output directory: {}", e))?;
+
+    println!("\n{}", "Writing results...".cyan());
+    for (file, contents) in [("estimates.csv", results.to_csv()), ("innovations.csv", results.innovations_csv())] {
+        let path = output_dir.join(file);
+        std::fs::write(&path, contents)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        println!("  {}", path.display().to_string().green());
+    }
+
+    println!("\n{}", "✓ Assimilation complete!".green().bold());
+
+    Ok(())
+}
+
+fn compare_runs(
+    baseline: (PathBuf, Option<String>),
+    scenario: (PathBuf, Option<String>),
+    variables: Option<Vec<String>>,
+    integration_method: simulation::IntegrationMethod,
+    output_path: Option<PathBuf>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let baseline = load_run("baseline", baseline, integration_method)?;
+    let scenario = load_run("scenario", scenario, integration_method)?;
+
+    let variables = variables.map(|vars| {
+        vars.into_iter().map(|v| v.trim().to_string()).filter(|v| !v.is_empty()).collect::<Vec<_>>()
+    });
+    let comparison = analysis::RunComparison::new(&baseline, &scenario, variables.as_deref())?;
+
+    println!("\n{}", "Differences (scenario - baseline):".bold());
+    for (name, variable) in &comparison.variables {
+        let s = &variable.summary;
+        let relative = if s.final_relative.is_nan() {
+            String::new()
+        } else {
+            format!(" ({:+.2}%)", s.final_relative * 100.0)
+        };
+        println!("  {}: final {} -> {}{}, largest {:+} at t={}, RMS {}",
+            name.green(), s.final_baseline, s.final_scenario, relative, s.max_difference, s.time_of_max, s.rms_difference);
+    }
+
+    let output_dir = output_path.unwrap_or_else(|| PathBuf::from("comparison"));
+    std::fs::create_dir_all(&output_dir)
+        .map_err(|e| format!("Failed to create output directory: {}", e))?;
+
+    println!("\n{}", "Writing results...".cyan());
+    for (file, contents) in [("differences.csv", comparison.differences_csv()), ("summary.csv", comparison.summary_csv())] {
+        let path = output_dir.join(file);
+        std::fs::write(&path, contents)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        println!("  {}", path.display().to_string().green());
+    }
+
+    println!("\n{}", "✓ Comparison complete!".green().bold());
+
+    Ok(())
+}
+
+#[cfg(feature = "plot")]
+fn plot_results(
+    input: PathBuf,
+    variables: Option<Vec<String>>,
+    output: PathBuf,
+    mut options: visualization::ChartOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    println!("{} {}", "Reading:".cyan(), input.display());
+    let contents = std::fs::read_to_string(&input)
+        .map_err(|e| format!("Failed to read {}: {}", input.display(), e))?;
+    let data = analysis::ObservedData::from_csv(&contents)?;
+    if data.dates {
+        options.dates = true;
+        options.x_label = "Date".to_string();
+    }
+
+    let variables = variables.map(|vars| {
+        vars.into_iter().map(|v| v.trim().to_string()).filter(|v| !v.is_empty()).collect::<Vec<_>>()
+    });
+    let figure = match variables {
+        Some(variables) => visualization::Figure::lines(&data, &variables)?,
+        None => match visualization::Figure::fan(&data) {
+            Some(fan) => {
+                println!("  Monte Carlo statistics: drawing a fan chart");
+                fan
             }
+            None => visualization::Figure::lines(&data, &data.series.keys().cloned().collect::<Vec<_>>())?,
+        },
+    };
+
+    figure.render(&output, &options)?;
+    println!("{} {}", "✓ Chart written to".green().bold(), output.display());
+
+    Ok(())
+}
+
+fn export_graph(model_path: PathBuf, format: &str, output: Option<PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
+    let model = io::load_model(&model_path)?;
+    let analyzer = analysis::StructureAnalyzer::new(&model);
+
+    let graph = match format {
+        "dot" => analyzer.export_dot(),
+        "mermaid" => analyzer.export_mermaid(),
+        "json" => serde_json::to_string_pretty(&analyzer.export_graph_json())? + "\n",
+        _ => return Err(format!("Unknown graph format '{}' (expected dot, mermaid or json)", format).into()),
+    };
+
+    // Without -o, stdout carries only the graph so it can be piped
+    match output {
+        Some(path) => {
+            std::fs::write(&path, graph).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+            println!("{} {}", "✓ Graph written to".green().bold(), path.display());
         }
+        None => print!("{}", graph),
     }
 
-    // Override timestep if specified
-    if let Some(dt) = dt_override {
-        println!("\n{}", "Overriding timestep...".cyan());
-        println!("  dt = {} (was {})", dt, model.time.dt);
-        model.time.dt = dt;
+    Ok(())
+}
+
+fn draw_diagram(model_path: PathBuf, output: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
+    println!("{} {}", "Loading model:".cyan(), model_path.display());
+    let model = io::load_model(&model_path)?;
+
+    let layout = visualization::LayoutEngine::auto_layout(&model);
+    if model.positions.is_empty() {
+        println!("  No diagram in the model file: laid out automatically");
     }
+    std::fs::write(&output, layout.to_svg())
+        .map_err(|e| format!("Failed to write {}: {}", output.display(), e))?;
+    println!("{} {}", "✓ Diagram written to".green().bold(), output.display());
 
-    // Create simulation config
-    let integration_method = match integrator.to_lowercase().as_str() {
-        "euler" => simulation::IntegrationMethod::Euler,
-        "rk4" => simulation::IntegrationMethod::RK4,
-        _ => {
-            eprintln!("{} Unknown integrator '{}', using Euler", "Warning:".yellow(), integrator);
-            simulation::IntegrationMethod::Euler
+    Ok(())
+}
+
+/// Read a results CSV, or run a model file with parameter overrides
+fn load_run(
+    label: &str,
+    (path, params): (PathBuf, Option<String>),
+    integration_method: simulation::IntegrationMethod,
+) -> Result<analysis::ObservedData, Box<dyn std::error::Error>> {
+    let is_csv = path.extension().and_then(|e| e.to_str()).is_some_and(|e| e.eq_ignore_ascii_case("csv"));
+    if is_csv {
+        if params.is_some() {
+            return Err(format!("Parameter overrides need a model file, but the {} is a CSV", label).into());
         }
-    };
+        println!("{} {}", format!("Reading {}:", label).cyan(), path.display());
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        return Ok(analysis::ObservedData::from_csv(&contents)?);
+    }
 
+    println!("{} {}", format!("Running {}:", label).cyan(), path.display());
+    let mut model = io::load_model(&path)?;
+    if let Some(params) = params {
+        apply_parameter_overrides(&mut model, &params)?;
+    }
     let config = simulation::SimulationConfig {
         integration_method,
-        output_interval: None,
+        ..Default::default()
     };
+    let results = simulation::SimulationEngine::new(model, config)?.run()?;
+    Ok(analysis::ObservedData::from_results(&results))
+}
 
-    println!("\n{}", "Running simulation...".cyan());
-    println!("  Time: {} to {} (dt={})", model.time.start, model.time.stop, model.time.dt);
-    println!("  Integrator: {:?}", integration_method);
-
-    let mut engine = simulation::SimulationEngine::new(model, config)
-        .map_err(|e| format!("Failed to create engine: {}", e))?;
-
-    let results = engine.run()
-        .map_err(|e| format!("Simulation failed: {}", e))?;
+fn export_model(model_path: PathBuf, output_path: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
+    println!("{}", "Exporting model...".cyan());
 
-    println!("  {} steps completed", results.times.len().to_string().green());
+    let model = io::load_model(&model_path)?;
+    println!("  Model: {}", model.metadata.name.green());
 
-    // Write output
-    let output_file = output_path.unwrap_or_else(|| PathBuf::from("results.csv"));
-    println!("\n{}", "Writing results...".cyan());
-    io::write_csv(&results, &output_file)
-        .map_err(|e| format!("Failed to write results: {}", e))?;
+    io::export_model(&model, &output_path)
+        .map_err(|e| format!("Failed to export model: {}", e))?;
+    println!("  Output: {}", output_path.display().to_string().green());
 
-    println!("  Output: {}", output_file.display().to_string().green());
+    println!("\n{}", "✓ Export complete!".green().bold());
+    Ok(())
+}
 
-    println!("\n{}", "✓ Simulation complete!".green().bold());
+fn apply_patch(base_path: PathBuf, patch_path: PathBuf, output: Option<PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
+    let patch = io::load_patch(&patch_path)?;
+    let (merged, model) = io::patch::patch_model_file(&base_path, &patch)?;
+    // Included modules are only resolved on loading, so their names would look undefined
+    if model.modules.is_empty() {
+        let report = analysis::validate_model(&model);
+        if !report.is_valid() {
+            return Err(format!("Patched model is invalid:\n  {}", report.errors.join("\n  ")).into());
+        }
+    }
 
+    match output {
+        Some(path) => {
+            io::patch::write_document(&merged, &path)?;
+            println!("{} {}", "✓ Patched model written to".green(), path.display());
+        }
+        None => print!("{}", serde_yaml::to_string(&merged)?),
+    }
     Ok(())
 }
 
-fn validate_model(model_path: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
+fn validate_model(model_path: PathBuf, strict_units: bool) -> Result<(), Box<dyn std::error::Error>> {
     println!("{}", "Validating model...".cyan());
 
-    let model = io::load_model(&model_path)
-        .map_err(|e| format!("Failed to load model: {}", e))?;
+    let model = io::load_model(&model_path)?;
 
     println!("  Model: {}", model.metadata.name.green());
     println!("\n{}", "Structure:".bold());
@@ -189,21 +2058,51 @@ fn validate_model(model_path: PathBuf) -> Result<(), Box<dyn std::error::Error>>
     println!("  Flows: {}", model.flows.len());
     println!("  Auxiliaries: {}", model.auxiliaries.len());
     println!("  Parameters: {}", model.parameters.len());
+    if !model.events.is_empty() {
+        println!("  Events: {}", model.events.len());
+    }
+    if !model.data.is_empty() {
+        println!("  Data variables: {}", model.data.len());
+    }
+    if !model.conservation.is_empty() {
+        println!("  Conserved groups: {}", model.conservation.len());
+    }
 
-    // Basic validation
-    let mut errors = Vec::new();
+    // Undefined references, unused elements and algebraic loops
+    let report = analysis::validate_model(&model);
+    let mut errors = report.errors;
+    let mut warnings = report.warnings;
 
-    // Check that all flows referenced by stocks exist
-    for (stock_name, stock) in &model.stocks {
-        for inflow in &stock.inflows {
-            if !model.flows.contains_key(inflow) {
-                errors.push(format!("Stock '{}' references non-existent inflow '{}'", stock_name, inflow));
-            }
+    // Units of undeclared variables, propagated from the declared ones
+    let inference = rssdsim::model::UnitChecker::infer_model(&model);
+    if !inference.inferred.is_empty() {
+        println!("\n{}", "Inferred units:".bold());
+        for inferred in &inference.inferred {
+            let source = if inferred.source == inferred.variable {
+                "its equation".to_string()
+            } else {
+                format!("'{}'", inferred.source)
+            };
+            println!("  {}: {} {}", inferred.variable,
+                inferred.units.render(&inference.time_unit).green(), format!("(from {})", source).dimmed());
         }
-        for outflow in &stock.outflows {
-            if !model.flows.contains_key(outflow) {
-                errors.push(format!("Stock '{}' references non-existent outflow '{}'", stock_name, outflow));
-            }
+    }
+
+    // Dimensional consistency of every equation
+    let unit_issues: Vec<String> = rssdsim::model::UnitChecker::check_model(&model)
+        .iter()
+        .map(|issue| format!("Units: {}", issue))
+        .collect();
+    if strict_units {
+        errors.extend(unit_issues);
+    } else {
+        warnings.extend(unit_issues);
+    }
+
+    if !warnings.is_empty() {
+        println!("\n{}", "Warnings:".yellow().bold());
+        for warning in &warnings {
+            println!("  {}", warning.yellow());
         }
     }
 
@@ -211,9 +2110,177 @@ fn validate_model(model_path: PathBuf) -> Result<(), Box<dyn std::error::Error>>
         println!("\n{}", "✓ Model is valid!".green().bold());
     } else {
         println!("\n{}", "✗ Validation errors:".red().bold());
-        for error in errors {
+        for error in &errors {
             println!("  {}", error.red());
         }
+        return Err(format!("{} validation error(s)", errors.len()).into());
+    }
+
+    Ok(())
+}
+
+fn test_models(model_paths: &[PathBuf], integration_method: simulation::IntegrationMethod) -> Result<(), Box<dyn std::error::Error>> {
+    let (mut passed, mut failed) = (0, 0);
+
+    for path in model_paths {
+        println!("{} {}", "Testing".cyan(), path.display());
+        let model = match io::load_model(path) {
+            Ok(model) => model,
+            Err(e) => {
+                println!("  {} failed to load: {}", "✗".red().bold(), e);
+                failed += 1;
+                continue;
+            }
+        };
+        if model.tests.is_empty() {
+            println!("  {}", "No tests declared".yellow());
+            continue;
+        }
+
+        let tests = model.tests.clone();
+        let config = simulation::SimulationConfig { integration_method, ..Default::default() };
+        let results = match simulation::SimulationEngine::new(model, config).and_then(|mut engine| engine.run()) {
+            Ok(results) => results,
+            Err(e) => {
+                println!("  {} simulation failed: {}", "✗".red().bold(), e);
+                failed += tests.len();
+                continue;
+            }
+        };
+
+        for test in &tests {
+            let outcome = test.check(&results);
+            if outcome.passed {
+                passed += 1;
+                println!("  {} {}: {}", "✓".green().bold(), outcome.name, outcome.message.dimmed());
+            } else {
+                failed += 1;
+                println!("  {} {}: {}", "✗".red().bold(), outcome.name, outcome.message.red());
+            }
+        }
+    }
+
+    let summary = format!("{} passed, {} failed", passed, failed);
+    if failed > 0 {
+        println!("\n{}", summary.red().bold());
+        return Err(format!("{} behavior test(s) failed", failed).into());
+    }
+    println!("\n{}", format!("✓ {}", summary).green().bold());
+
+    Ok(())
+}
+
+fn test_extreme_conditions(
+    model_path: PathBuf,
+    ranges_path: Option<PathBuf>,
+    config: analysis::ExtremeConditionsConfig,
+    output_path: Option<PathBuf>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    println!("{}", "Loading model...".cyan());
+    let mut model = io::load_model(&model_path)?;
+    println!("  Model: {}", model.metadata.name.green());
+
+    if let Some(ranges_path) = ranges_path {
+        let ranges = io::load_parameter_ranges(&ranges_path)
+            .map_err(|e| format!("Failed to load ranges: {}", e))?;
+        for range in ranges {
+            let parameter = model.parameters.get_mut(&range.name)
+                .ok_or_else(|| format!("Parameter '{}' not found in model", range.name))?;
+            parameter.min = Some(range.min);
+            parameter.max = Some(range.max);
+        }
+    }
+
+    println!("\n{}", format!("Testing {} parameters at extreme values...", model.parameters.len()).cyan());
+    let report = analysis::ExtremeConditionsTester::new(config).run(&model)?;
+    for case in &report.cases {
+        let label = format!("{} = {}", case.parameter, case.value);
+        if case.passed() {
+            println!("  {} {}", "✓".green().bold(), label);
+        } else if let Some(error) = &case.error {
+            println!("  {} {}: {}", "✗".red().bold(), label, error.red());
+        } else {
+            let first = &case.violations[0];
+            let more = match case.violations.len() {
+                1 => String::new(),
+                n => format!(" and {} more", n - 1),
+            };
+            println!("  {} {}: {}{}", "✗".red().bold(), label,
+                format!("{} {} at t={}", first.variable, first.kind, first.time).red(), more);
+        }
+    }
+
+    let output_dir = output_path.unwrap_or_else(|| PathBuf::from("extreme"));
+    std::fs::create_dir_all(&output_dir)
+        .map_err(|e| format!("Failed to create output directory: {}", e))?;
+
+    println!("\n{}", "Writing results...".cyan());
+    for (file, contents) in [("report.txt", report.to_text()), ("violations.csv", report.to_csv())] {
+        let path = output_dir.join(file);
+        std::fs::write(&path, contents)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        println!("  {}", path.display().to_string().green());
+    }
+
+    let failed = report.cases.len() - report.passed();
+    let summary = format!("{} passed, {} failed", report.passed(), failed);
+    if failed > 0 {
+        println!("\n{}", summary.yellow().bold());
+    } else {
+        println!("\n{}", format!("✓ {}", summary).green().bold());
+    }
+
+    Ok(())
+}
+
+fn check_dt_sensitivity(
+    model_path: PathBuf,
+    config: analysis::DtSensitivityConfig,
+    recommend: bool,
+    output_path: Option<PathBuf>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    println!("{}", "Loading model...".cyan());
+    let model = io::load_model(&model_path)?;
+    println!("  Model: {}", model.metadata.name.green());
+
+    let dt = model.time.dt;
+    println!("\n{}", format!("Running at dt={}, {} and {}...", dt, dt / 2.0, dt / 4.0).cyan());
+    let report = analysis::DtSensitivityAnalyzer::new(config).run(&model)?;
+    for v in report.variables.iter().take(10) {
+        let line = format!("  {}: {:.3}% (dt/2: {:.3}%) at t={}", v.variable, v.coarse * 100.0, v.half * 100.0, v.time);
+        if v.coarse > report.threshold {
+            println!("{}", line.yellow());
+        } else {
+            println!("{}", line);
+        }
+    }
+    if report.variables.len() > 10 {
+        println!("  ... and {} more", report.variables.len() - 10);
+    }
+
+    let output_dir = output_path.unwrap_or_else(|| PathBuf::from("dt_check"));
+    std::fs::create_dir_all(&output_dir)
+        .map_err(|e| format!("Failed to create output directory: {}", e))?;
+
+    println!("\n{}", "Writing results...".cyan());
+    for (file, contents) in [("report.txt", report.to_text()), ("divergence.csv", report.to_csv())] {
+        let path = output_dir.join(file);
+        std::fs::write(&path, contents)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        println!("  {}", path.display().to_string().green());
+    }
+
+    if report.is_dt_dependent() {
+        println!("\n{}", format!("Warning: results depend on the time step (divergence up to {:.3}%)",
+            report.max_divergence() * 100.0).yellow().bold());
+        if recommend {
+            if let Some(order) = report.order {
+                println!("  Estimated order of convergence: {:.2}", order);
+            }
+            println!("  Recommendation: {}", report.recommendation.to_string().green());
+        }
+    } else {
+        println!("\n{}", format!("✓ Results are within {}% of those at dt/4", report.threshold * 100.0).green().bold());
     }
 
     Ok(())
@@ -228,20 +2295,26 @@ fn show_info() {
     println!("{}", "Features:".bold());
     println!("  ✓ Stock-flow models");
     println!("  ✓ Expression evaluation");
-    println!("  ✓ Multiple integrators (Euler, RK4)");
+    println!("  ✓ Multiple integrators (Euler, RK4, RK45, Heun, Backward Euler, BDF)");
     println!("  ✓ JSON/YAML model format");
     println!("  ✓ CSV output");
     println!("  ○ Agent-based modeling (planned)");
     println!("  ○ Hybrid models (planned)");
-    println!("  ○ Multi-dimensional variables (planned)");
+    println!("  ✓ Arrayed (subscripted) variables");
 
     println!("\n{}", "Protocol support:".bold());
-    println!("  ○ MCP (Model Context Protocol) - stubs ready");
+    println!("  ✓ MCP (Model Context Protocol) - rsedsim mcp (stdio)");
     println!("  ○ A2A (Agent-to-Agent) - stubs ready");
 
     println!("\n{}", "Usage:".bold());
     println!("  rsedsim run <model.yaml> -o results.csv");
     println!("  rsedsim validate <model.yaml>");
+    println!("  rsedsim export <model.yaml> -o model.mdl");
+    println!("  rsedsim sensitivity <model.yaml> -r ranges.yaml --variable Population");
+    println!("  rsedsim montecarlo <model.yaml> -r ranges.yaml --runs 1000 --seed 42");
+    println!("  rsedsim analyze <model.yaml> --dot model.dot");
+    println!("  rsedsim optimize <model.yaml> -d observed.csv -b bounds.yaml");
+    println!("  rsedsim serve --port 8080 --model-dir examples");
     println!("  rsedsim run <model.json> -p \"param1=10,param2=0.5\"");
 
     println!("\n{}", "Examples:".bold());
@@ -0,0 +1,536 @@
+/// Expansion of arrayed (subscripted) variables
+///
+/// Stocks, flows, auxiliaries and parameters declared with `dimensions` are
+/// expanded by [`Model::compile`] into one scalar variable per element, named
+/// `Name_Element` like flattened Vensim subscripts. Subscripts in equations are
+/// resolved against the element being defined, so an apply-to-all equation
+/// such as `Population[Region] * birth_rate[Region]` reads the matching
+/// element, and `*` stands for the referenced variable's own dimension at that
+/// position. Array functions (`SUM`, `PROD`, `MEAN`, `VMAX`, `VMIN`) receive one
+/// argument per element they range over, so `SUM(Population[*])` becomes
+/// `SUM(Population_North, Population_South)`.
+
+use std::collections::HashMap;
+use super::{Dimension, Expression, Model, SubscriptRef};
+
+/// Scalar name of one element of an arrayed variable
+pub fn element_name(name: &str, elements: &[String]) -> String {
+    let mut full_name = name.to_string();
+    for element in elements {
+        full_name.push('_');
+        full_name.push_str(element);
+    }
+    full_name
+}
+
+/// Every element combination of `dimensions`, in row-major order
+pub fn element_combinations(
+    dimensions: &[String],
+    defined: &HashMap<String, Dimension>,
+) -> Result<Vec<Vec<String>>, String> {
+    let mut combinations = vec![Vec::new()];
+    for name in dimensions {
+        let dimension = defined.get(name)
+            .ok_or_else(|| format!("Dimension '{}' not found", name))?;
+        combinations = combinations.into_iter()
+            .flat_map(|prefix| {
+                dimension.elements.iter().map(move |element| {
+                    let mut combination = prefix.clone();
+                    combination.push(element.clone());
+                    combination
+                })
+            })
+            .collect();
+    }
+    Ok(combinations)
+}
+
+/// Replace every arrayed definition in `model` with its scalar elements
+pub(crate) fn expand(model: &mut Model) -> Result<(), String> {
+    let arrays: HashMap<String, Vec<String>> = model.stocks.values()
+        .filter_map(|s| Some((s.name.clone(), s.dimensions.clone()?)))
+        .chain(model.flows.values().filter_map(|f| Some((f.name.clone(), f.dimensions.clone()?))))
+        .chain(model.auxiliaries.values().filter_map(|a| Some((a.name.clone(), a.dimensions.clone()?))))
+        .chain(model.parameters.values().filter_map(|p| Some((p.name.clone(), p.dimensions.clone()?))))
+        .collect();
+    let resolver = Resolver { dimensions: model.dimensions.clone(), arrays };
+
+    for (name, stock) in std::mem::take(&mut model.stocks) {
+        for (elements, bindings) in resolver.instances(&name, stock.dimensions.as_deref())? {
+            let mut element = stock.clone();
+            element.name = element_name(&name, &elements);
+            element.dimensions = None;
+            element.initial = resolver.resolve(&stock.initial, &bindings, &name)?;
+            element.inflows = resolver.flow_names(&stock.inflows, &bindings, &name)?;
+            element.outflows = resolver.flow_names(&stock.outflows, &bindings, &name)?;
+            for expr in element.kind.expressions_mut() {
+                *expr = resolver.resolve(expr, &bindings, &name)?;
+            }
+            model.add_stock(element)?;
+        }
+    }
+
+    for (name, flow) in std::mem::take(&mut model.flows) {
+        for (elements, bindings) in resolver.instances(&name, flow.dimensions.as_deref())? {
+            let mut element = flow.clone();
+            element.name = element_name(&name, &elements);
+            element.dimensions = None;
+            element.equation = resolver.resolve(&flow.equation, &bindings, &name)?;
+            model.add_flow(element)?;
+        }
+    }
+
+    for (name, aux) in std::mem::take(&mut model.auxiliaries) {
+        for (elements, bindings) in resolver.instances(&name, aux.dimensions.as_deref())? {
+            let mut element = aux.clone();
+            element.name = element_name(&name, &elements);
+            element.dimensions = None;
+            element.equation = resolver.resolve(&aux.equation, &bindings, &name)?;
+            model.add_auxiliary(element)?;
+        }
+    }
+
+    for (name, param) in std::mem::take(&mut model.parameters) {
+        let instances = resolver.instances(&name, param.dimensions.as_deref())?;
+        if let Some(values) = &param.values
+            && values.len() != instances.len()
+        {
+            return Err(format!(
+                "Parameter '{}' has {} values for {} elements",
+                name, values.len(), instances.len()
+            ));
+        }
+
+        for (index, (elements, _)) in instances.iter().enumerate() {
+            let mut element = param.clone();
+            element.name = element_name(&name, elements);
+            element.dimensions = None;
+            element.values = None;
+            if let Some(values) = &param.values {
+                element.value = values[index];
+            }
+            model.add_parameter(element)?;
+        }
+    }
+
+    model.expanded_arrays.extend(resolver.arrays);
+    Ok(())
+}
+
+/// Array functions whose arguments are expanded into every element they range over
+const REDUCTIONS: [&str; 5] = ["SUM", "PROD", "MEAN", "VMAX", "VMIN"];
+
+fn is_reduction(name: &str) -> bool {
+    REDUCTIONS.iter().any(|r| r.eq_ignore_ascii_case(name))
+}
+
+/// Element names of one element and the dimension each is bound to
+type Instance = (Vec<String>, HashMap<String, String>);
+
+/// Resolves subscripts for one element of an arrayed equation
+struct Resolver {
+    dimensions: HashMap<String, Dimension>,
+    /// Dimensions of each arrayed variable
+    arrays: HashMap<String, Vec<String>>,
+}
+
+impl Resolver {
+    /// Element names and dimension bindings for each element of a definition
+    fn instances(
+        &self,
+        name: &str,
+        dimensions: Option<&[String]>,
+    ) -> Result<Vec<Instance>, String> {
+        let dimensions = dimensions.unwrap_or_default();
+        let combinations = element_combinations(dimensions, &self.dimensions)
+            .map_err(|e| format!("Variable '{}': {}", name, e))?;
+
+        Ok(combinations.into_iter()
+            .map(|elements| {
+                let bindings = dimensions.iter().cloned().zip(elements.iter().cloned()).collect();
+                (elements, bindings)
+            })
+            .collect())
+    }
+
+    fn flow_names(
+        &self,
+        flows: &[String],
+        bindings: &HashMap<String, String>,
+        context: &str,
+    ) -> Result<Vec<String>, String> {
+        flows.iter()
+            .map(|flow| match self.resolve(&Expression::Variable(flow.clone()), bindings, context)? {
+                Expression::Variable(name) => Ok(name),
+                _ => unreachable!("variables resolve to variables"),
+            })
+            .collect()
+    }
+
+    /// Rewrite `expr` for the element described by `bindings`
+    ///
+    /// Subscripted references become references to the element's scalar name.
+    fn resolve(
+        &self,
+        expr: &Expression,
+        bindings: &HashMap<String, String>,
+        context: &str,
+    ) -> Result<Expression, String> {
+        Ok(match expr {
+            Expression::Variable(name) => match self.arrays.get(name) {
+                // Inside an arrayed equation a bare name means the same element
+                Some(dimensions) => {
+                    let elements = dimensions.iter()
+                        .map(|dimension| {
+                            bindings.get(dimension).cloned().ok_or_else(|| format!(
+                                "'{}' uses arrayed variable '{}' without subscripts", context, name
+                            ))
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    Expression::Variable(element_name(name, &elements))
+                }
+                None => expr.clone(),
+            },
+
+            Expression::SubscriptedVariable { name, subscripts } => {
+                let declared = self.arrays.get(name);
+                if let Some(declared) = declared
+                    && declared.len() != subscripts.len()
+                {
+                    return Err(format!(
+                        "'{}' references '{}' with {} subscripts but it has {} dimensions",
+                        context, name, subscripts.len(), declared.len()
+                    ));
+                }
+
+                let mut elements = Vec::with_capacity(subscripts.len());
+                for (position, subscript) in subscripts.iter().enumerate() {
+                    let dimension = declared.map(|d| d[position].as_str());
+                    // Element and the dimension it was taken from, if any
+                    let (mut element, source) = match subscript {
+                        SubscriptRef::Element(s) | SubscriptRef::Dimension(s) if self.dimensions.contains_key(s) => {
+                            (self.bound(s, name, bindings, context)?, Some(s.as_str()))
+                        }
+                        SubscriptRef::Element(element) => (element.clone(), None),
+                        SubscriptRef::Dimension(dimension) => {
+                            return Err(format!("Dimension '{}' not found", dimension));
+                        }
+                        SubscriptRef::Wildcard => match dimension {
+                            Some(dimension) => (self.bound(dimension, name, bindings, context)?, Some(dimension)),
+                            None => return Err(format!(
+                                "'{}' uses '*' on '{}', which is not an arrayed variable", context, name
+                            )),
+                        },
+                        SubscriptRef::Offset { dimension: source, offset } => {
+                            let Some(elements) = self.dimensions.get(source).map(|d| &d.elements) else {
+                                return Err(format!("Dimension '{}' not found", source));
+                            };
+                            let current = self.bound(source, name, bindings, context)?;
+                            let index = elements.iter().position(|e| *e == current).unwrap_or_default() as i64
+                                + *offset as i64;
+                            match usize::try_from(index).ok().and_then(|i| elements.get(i)) {
+                                Some(element) => (element.clone(), Some(source.as_str())),
+                                // Past either end of the dimension the reference reads as zero
+                                None => return Ok(Expression::Constant(0.0)),
+                            }
+                        }
+                    };
+
+                    if let (Some(dimension), Some(source)) = (dimension, source)
+                        && dimension != source
+                        && self.dimensions.get(dimension).is_some_and(|d| !d.contains(&element))
+                        && let Some(mapped) = self.map_element(&element, source, dimension)
+                    {
+                        element = mapped;
+                    }
+
+                    if let Some(dimension) = dimension
+                        && !self.dimensions.get(dimension).is_some_and(|d| d.contains(&element))
+                    {
+                        return Err(format!(
+                            "'{}' references '{}[{}]' but '{}' is not an element of '{}'",
+                            context, name, element, element, dimension
+                        ));
+                    }
+                    elements.push(element);
+                }
+
+                Expression::Variable(element_name(name, &elements))
+            }
+
+            Expression::BinaryOp { op, left, right } => Expression::BinaryOp {
+                op: *op,
+                left: Box::new(self.resolve(left, bindings, context)?),
+                right: Box::new(self.resolve(right, bindings, context)?),
+            },
+            Expression::UnaryOp { op, expr } => Expression::UnaryOp {
+                op: *op,
+                expr: Box::new(self.resolve(expr, bindings, context)?),
+            },
+            Expression::FunctionCall { name, args, .. } if is_reduction(name) => {
+                let mut elements = Vec::new();
+                for arg in args {
+                    elements.extend(self.expand_reduction(arg, bindings, context)?);
+                }
+                Expression::FunctionCall { name: name.clone(), args: elements, site: None }
+            }
+            Expression::FunctionCall { name, args, .. } => Expression::FunctionCall {
+                name: name.clone(),
+                args: args.iter()
+                    .map(|arg| self.resolve(arg, bindings, context))
+                    .collect::<Result<_, _>>()?,
+                site: None,
+            },
+            Expression::Conditional { condition, true_expr, false_expr } => Expression::Conditional {
+                condition: Box::new(self.resolve(condition, bindings, context)?),
+                true_expr: Box::new(self.resolve(true_expr, bindings, context)?),
+                false_expr: Box::new(self.resolve(false_expr, bindings, context)?),
+            },
+            Expression::Constant(_) | Expression::StringLiteral { .. } => expr.clone(),
+        })
+    }
+
+    /// One resolved expression per element of the dimensions `arg` reduces over
+    fn expand_reduction(
+        &self,
+        arg: &Expression,
+        bindings: &HashMap<String, String>,
+        context: &str,
+    ) -> Result<Vec<Expression>, String> {
+        let mut reduced = Vec::new();
+        self.reduced_dimensions(arg, bindings, &mut reduced);
+
+        element_combinations(&reduced, &self.dimensions)?
+            .into_iter()
+            .map(|elements| {
+                let mut bindings = bindings.clone();
+                bindings.extend(reduced.iter().cloned().zip(elements));
+                self.resolve(arg, &bindings, context)
+            })
+            .collect()
+    }
+
+    /// Dimensions a reduction argument ranges over
+    ///
+    /// `*` always ranges over the referenced variable's dimension; a dimension
+    /// name or a bare arrayed variable only over dimensions the enclosing
+    /// equation does not already fix, so `SUM(Sales[Region, *])` in an
+    /// equation arrayed by `Region` sums each region's products.
+    fn reduced_dimensions(&self, expr: &Expression, bindings: &HashMap<String, String>, reduced: &mut Vec<String>) {
+        let mut add = |dimension: &str| {
+            if !reduced.iter().any(|d| d == dimension) {
+                reduced.push(dimension.to_string());
+            }
+        };
+
+        match expr {
+            Expression::Variable(name) => {
+                for dimension in self.arrays.get(name).into_iter().flatten() {
+                    if !bindings.contains_key(dimension) {
+                        add(dimension);
+                    }
+                }
+            }
+            Expression::SubscriptedVariable { name, subscripts } => {
+                let declared = self.arrays.get(name);
+                for (position, subscript) in subscripts.iter().enumerate() {
+                    match subscript {
+                        SubscriptRef::Wildcard => {
+                            if let Some(dimension) = declared.and_then(|d| d.get(position)) {
+                                add(dimension);
+                            }
+                        }
+                        SubscriptRef::Element(s)
+                        | SubscriptRef::Dimension(s)
+                        | SubscriptRef::Offset { dimension: s, .. } => {
+                            if self.dimensions.contains_key(s) && !bindings.contains_key(s) {
+                                add(s);
+                            }
+                        }
+                    }
+                }
+            }
+            Expression::BinaryOp { left, right, .. } => {
+                self.reduced_dimensions(left, bindings, reduced);
+                self.reduced_dimensions(right, bindings, reduced);
+            }
+            Expression::UnaryOp { expr, .. } => self.reduced_dimensions(expr, bindings, reduced),
+            // Nested reductions range over their own arguments
+            Expression::FunctionCall { name, .. } if is_reduction(name) => {}
+            Expression::FunctionCall { args, .. } => {
+                for arg in args {
+                    self.reduced_dimensions(arg, bindings, reduced);
+                }
+            }
+            Expression::Conditional { condition, true_expr, false_expr } => {
+                self.reduced_dimensions(condition, bindings, reduced);
+                self.reduced_dimensions(true_expr, bindings, reduced);
+                self.reduced_dimensions(false_expr, bindings, reduced);
+            }
+            Expression::Constant(_) | Expression::StringLiteral { .. } => {}
+        }
+    }
+
+    /// Translate an element of `source` into `target` through a dimension mapping
+    ///
+    /// A mapping declared on `target` back onto `source` is used in reverse.
+    fn map_element(&self, element: &str, source: &str, target: &str) -> Option<String> {
+        let (source, target) = (self.dimensions.get(source)?, self.dimensions.get(target)?);
+        source.map_element(element, target).or_else(|| {
+            let mapping = target.maps_to.as_ref().filter(|m| m.dimension == source.name)?;
+            let index = if mapping.elements.is_empty() {
+                source.get_index(element)?
+            } else {
+                mapping.elements.iter().position(|e| e == element)?
+            };
+            target.elements.get(index).cloned()
+        })
+    }
+
+    /// Element of `dimension` for the element being defined
+    fn bound(
+        &self,
+        dimension: &str,
+        name: &str,
+        bindings: &HashMap<String, String>,
+        context: &str,
+    ) -> Result<String, String> {
+        bindings.get(dimension).cloned().ok_or_else(|| format!(
+            "'{}' references '{}' over '{}', which is not a dimension of '{}'",
+            context, name, dimension, context
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Auxiliary, Flow, Parameter, Stock};
+
+    fn regional_model() -> Model {
+        let mut model = Model::new("Regions");
+        model.add_dimension(Dimension::new("Region", vec!["North".to_string(), "South".to_string()])).unwrap();
+        model.add_parameter(Parameter::new("birth_rate", 0.0)
+            .with_dimensions(vec!["Region".to_string()])
+            .with_values(vec![0.1, 0.2])).unwrap();
+        model.add_stock(Stock::new("Population", "100")
+            .with_dimensions(vec!["Region".to_string()])
+            .with_inflows(vec!["births".to_string()])).unwrap();
+        model.add_flow(Flow::new("births", "Population[Region] * birth_rate[*]")
+            .with_dimensions(vec!["Region".to_string()])).unwrap();
+        model.add_auxiliary(Auxiliary::new("north_share", "Population[North] / (Population[North] + Population[South])")).unwrap();
+        model
+    }
+
+    #[test]
+    fn test_expand_arrayed_definitions() {
+        let mut model = regional_model();
+        model.compile().unwrap();
+
+        assert!(model.stocks.contains_key("Population_North") && !model.stocks.contains_key("Population"));
+        assert_eq!(model.stocks["Population_South"].inflows, vec!["births_South"]);
+        assert_eq!(model.parameters["birth_rate_South"].value, 0.2);
+        assert_eq!(model.flows["births_North"].equation.to_string(), "(Population_North * birth_rate_North)");
+        assert_eq!(model.expanded_arrays["Population"], vec!["Region"]);
+    }
+
+    #[test]
+    fn test_unbound_subscript_is_an_error() {
+        let mut model = regional_model();
+        model.add_auxiliary(Auxiliary::new("total", "Population[Region]")).unwrap();
+        let err = model.compile().unwrap_err().to_string();
+        assert!(err.contains("not a dimension of 'total'"), "{}", err);
+
+        let mut model = regional_model();
+        model.add_auxiliary(Auxiliary::new("east", "Population[East]")).unwrap();
+        assert!(model.compile().is_err());
+    }
+
+    #[test]
+    fn test_reductions_over_dimensions() {
+        use crate::simulation::{SimulationConfig, SimulationEngine};
+
+        let mut model = Model::new("Sales");
+        model.time.stop = 1.0;
+        model.time.dt = 1.0;
+        model.add_dimension(Dimension::new("Region", vec!["North".to_string(), "South".to_string()])).unwrap();
+        model.add_dimension(Dimension::new("Product", vec!["A".to_string(), "B".to_string()])).unwrap();
+        model.add_parameter(Parameter::new("sales", 0.0)
+            .with_dimensions(vec!["Region".to_string(), "Product".to_string()])
+            .with_values(vec![1.0, 2.0, 3.0, 4.0])).unwrap();
+        for (name, equation) in [
+            ("total", "SUM(sales[*, *])"),
+            ("product_a", "SUM(sales[*, A])"),
+            ("average", "MEAN(sales[Region, Product])"),
+            ("peak", "VMAX(sales)"),
+            ("lowest_b", "VMIN(sales[*, B])"),
+            ("north_product", "PROD(sales[North, *])"),
+        ] {
+            model.add_auxiliary(Auxiliary::new(name, equation)).unwrap();
+        }
+        model.add_auxiliary(Auxiliary::new("regional", "SUM(sales[Region, *])")
+            .with_dimensions(vec!["Region".to_string()])).unwrap();
+
+        let results = SimulationEngine::new(model, SimulationConfig::default()).unwrap().run().unwrap();
+        let values = &results.state_at(1).unwrap().auxiliaries;
+        assert_eq!(values["total"], 10.0);
+        assert_eq!(values["product_a"], 4.0);
+        assert_eq!(values["average"], 2.5);
+        assert_eq!(values["peak"], 4.0);
+        assert_eq!(values["lowest_b"], 2.0);
+        assert_eq!(values["north_product"], 2.0);
+        assert_eq!((values["regional_North"], values["regional_South"]), (3.0, 7.0));
+    }
+
+    #[test]
+    fn test_aging_chain_with_offsets() {
+        use crate::simulation::{SimulationConfig, SimulationEngine};
+
+        let age = || vec!["Age".to_string()];
+        let mut model = Model::new("Aging");
+        model.time.stop = 1.0;
+        model.time.dt = 1.0;
+        model.add_dimension(Dimension::new("Age", vec!["young".to_string(), "adult".to_string(), "old".to_string()])).unwrap();
+        model.add_parameter(Parameter::new("aging_rate", 0.0).with_dimensions(age()).with_values(vec![0.1, 0.2, 0.5])).unwrap();
+        model.add_parameter(Parameter::new("entry", 0.0).with_dimensions(age()).with_values(vec![1.0, 0.0, 0.0])).unwrap();
+        model.add_stock(Stock::new("Cohort", "100").with_dimensions(age())
+            .with_inflows(vec!["aging_in".to_string()])
+            .with_outflows(vec!["aging_out".to_string()])).unwrap();
+        model.add_flow(Flow::new("aging_out", "Cohort[Age] * aging_rate[Age]").with_dimensions(age())).unwrap();
+        model.add_flow(Flow::new("aging_in", "aging_out[Age-1] + entry[Age] * 10").with_dimensions(age())).unwrap();
+        model.add_auxiliary(Auxiliary::new("older", "Cohort[Age+1]").with_dimensions(age())).unwrap();
+
+        assert_eq!(model.flows["aging_in"].equation.to_string(), "(aging_out[Age-1] + (entry[Age] * 10))");
+
+        let results = SimulationEngine::new(model, SimulationConfig::default()).unwrap().run().unwrap();
+        let last = results.final_state().unwrap();
+        assert_eq!(last.stocks["Cohort_young"], 100.0);
+        assert_eq!(last.stocks["Cohort_adult"], 90.0);
+        assert_eq!(last.stocks["Cohort_old"], 70.0);
+        // Past the last element the reference reads as zero
+        assert_eq!(last.auxiliaries["older_adult"], 100.0);
+        assert_eq!(last.auxiliaries["older_old"], 0.0);
+    }
+
+    #[test]
+    fn test_dimension_mapping() {
+        let mut model = Model::new("Mapping");
+        let elements = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        model.add_dimension(Dimension::new("Region", elements(&["North", "South"]))).unwrap();
+        model.add_dimension(Dimension::new("Zone", elements(&["Z1", "Z2"])).with_mapping("Region", Vec::new())).unwrap();
+        model.add_dimension(Dimension::new("Site", elements(&["a", "b", "c"]))
+            .with_mapping("Region", elements(&["North", "North", "South"]))).unwrap();
+        model.add_parameter(Parameter::new("price", 0.0)
+            .with_dimensions(vec!["Region".to_string()])
+            .with_values(vec![5.0, 7.0])).unwrap();
+        model.add_auxiliary(Auxiliary::new("zone_price", "price[Zone]")
+            .with_dimensions(vec!["Zone".to_string()])).unwrap();
+        model.add_auxiliary(Auxiliary::new("site_price", "price[Site]")
+            .with_dimensions(vec!["Site".to_string()])).unwrap();
+        model.compile().unwrap();
+
+        assert_eq!(model.auxiliaries["zone_price_Z2"].equation.to_string(), "price_South");
+        assert_eq!(model.auxiliaries["site_price_b"].equation.to_string(), "price_North");
+        assert_eq!(model.auxiliaries["site_price_c"].equation.to_string(), "price_South");
+    }
+}
@@ -9,6 +9,9 @@ pub struct Auxiliary {
     pub equation: Expression,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub units: Option<String>,
+    /// Optional dimensions/subscripts for array variables
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dimensions: Option<Vec<String>>,
 }
 
 impl Auxiliary {
@@ -17,6 +20,7 @@ impl Auxiliary {
             name: name.to_string(),
             equation: Expression::parse(equation).unwrap_or(Expression::Constant(0.0)),
             units: None,
+            dimensions: None,
         }
     }
 
@@ -29,4 +33,9 @@ impl Auxiliary {
         self.units = Some(units.to_string());
         self
     }
+
+    pub fn with_dimensions(mut self, dimensions: Vec<String>) -> Self {
+        self.dimensions = Some(dimensions);
+        self
+    }
 }
@@ -0,0 +1,152 @@
+/// Behavior tests declared in a model file
+///
+/// Each test states how one variable is expected to behave over a run: its
+/// value at a time, bounds it stays within, or settling to a steady state.
+/// `rsedsim test` runs the model and checks every test, so a repository of
+/// models can catch changes that break their expected behavior.
+
+use serde::{Deserialize, Serialize};
+use crate::simulation::SimulationResults;
+
+/// Expected behavior of one variable
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BehaviorTest {
+    pub name: String,
+    pub variable: String,
+    pub check: BehaviorCheck,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BehaviorCheck {
+    /// The value at `time`, interpolated between recorded points, is within the bounds
+    At { time: f64, min: Option<f64>, max: Option<f64> },
+    /// Every recorded value from `from` to `to` (the whole run when unset) is within the bounds
+    Always { from: Option<f64>, to: Option<f64>, min: Option<f64>, max: Option<f64> },
+    /// From `time` on, the rate of change stays below `tolerance` per unit of
+    /// time, relative to the value (or absolute for values below 1)
+    SteadyBy { time: f64, tolerance: f64 },
+}
+
+/// Result of checking one test against a run
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestOutcome {
+    pub name: String,
+    pub passed: bool,
+    /// What was found: the value checked, or the first violation
+    pub message: String,
+}
+
+impl BehaviorTest {
+    /// Check the test against a run of its model
+    pub fn check(&self, results: &SimulationResults) -> TestOutcome {
+        let (passed, message) = match results.series(&self.variable) {
+            Some(series) => self.check_series(&results.times, series),
+            None => (false, format!("'{}' is not in the results", self.variable)),
+        };
+        TestOutcome { name: self.name.clone(), passed, message }
+    }
+
+    fn check_series(&self, times: &[f64], series: &[f64]) -> (bool, String) {
+        let variable = &self.variable;
+        match self.check {
+            BehaviorCheck::At { time, min, max } => match value_at(times, series, time) {
+                Some(value) => match bounds_violation(value, min, max) {
+                    None => (true, format!("{} = {} at t={}", variable, value, time)),
+                    Some(violation) => (false, format!("{} = {} at t={}, {}", variable, value, time, violation)),
+                },
+                None => (false, format!("t={} is outside the run", time)),
+            },
+            BehaviorCheck::Always { from, to, min, max } => {
+                let window: Vec<(f64, f64)> = times.iter().copied().zip(series.iter().copied())
+                    .filter(|(t, _)| from.is_none_or(|from| *t >= from) && to.is_none_or(|to| *t <= to))
+                    .collect();
+                for &(time, value) in &window {
+                    if let Some(violation) = bounds_violation(value, min, max) {
+                        return (false, format!("{} = {} at t={}, {}", variable, value, time, violation));
+                    }
+                }
+                if window.is_empty() {
+                    return (false, "no recorded points in the window".to_string());
+                }
+                let (low, high) = window.iter()
+                    .fold((f64::INFINITY, f64::NEG_INFINITY), |(low, high), &(_, v)| (low.min(v), high.max(v)));
+                (true, format!("{} stays within [{}, {}]", variable, low, high))
+            }
+            BehaviorCheck::SteadyBy { time, tolerance } => {
+                let mut largest: Option<f64> = None;
+                for i in 1..times.len() {
+                    if times[i - 1] < time {
+                        continue;
+                    }
+                    let rate = (series[i] - series[i - 1]) / (times[i] - times[i - 1]);
+                    let relative = rate.abs() / series[i].abs().max(1.0);
+                    if !relative.is_finite() || relative > tolerance {
+                        return (false, format!("{} still changing at {} per time unit at t={}", variable, rate, times[i]));
+                    }
+                    largest = Some(largest.unwrap_or(0.0).max(rate.abs()));
+                }
+                match largest {
+                    Some(rate) => (true, format!("{} changes by at most {} per time unit from t={}", variable, rate, time)),
+                    None => (false, format!("no recorded steps after t={}", time)),
+                }
+            }
+        }
+    }
+}
+
+/// How `value` breaks the bounds, if it does
+fn bounds_violation(value: f64, min: Option<f64>, max: Option<f64>) -> Option<String> {
+    match (min, max) {
+        _ if value.is_nan() => Some("not a number".to_string()),
+        (Some(min), _) if value < min => Some(format!("below {}", min)),
+        (_, Some(max)) if value > max => Some(format!("above {}", max)),
+        _ => None,
+    }
+}
+
+/// Linear interpolation of the series at `time`, None outside the recorded times
+fn value_at(times: &[f64], series: &[f64], time: f64) -> Option<f64> {
+    let i = times.iter().position(|&t| t >= time)?;
+    if times[i] == time {
+        return Some(series[i]);
+    }
+    if i == 0 {
+        return None;
+    }
+    let fraction = (time - times[i - 1]) / (times[i] - times[i - 1]);
+    Some(series[i - 1] + fraction * (series[i] - series[i - 1]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Model, Stock, Flow, Parameter};
+    use crate::simulation::{SimulationEngine, SimulationConfig};
+
+    #[test]
+    fn test_behavior_checks() {
+        // Goal seeking: Level approaches 100 from 0 with a time constant of 5
+        let mut model = Model::new("Goal seeking");
+        model.time.stop = 60.0;
+        model.time.dt = 0.5;
+        model.add_stock(Stock::new("Level", "0")).unwrap();
+        model.add_parameter(Parameter::new("goal", 100.0)).unwrap();
+        model.add_flow(Flow::new("adjustment", "(goal - Level) / 5")).unwrap();
+        model.stocks.get_mut("Level").unwrap().inflows.push("adjustment".to_string());
+        let results = SimulationEngine::new(model, SimulationConfig::default()).unwrap().run().unwrap();
+
+        let test = |variable: &str, check| BehaviorTest { name: "t".to_string(), variable: variable.to_string(), check };
+        let outcome = test("Level", BehaviorCheck::At { time: 10.25, min: Some(80.0), max: Some(95.0) }).check(&results);
+        assert!(outcome.passed, "{}", outcome.message);
+        assert!(test("Level", BehaviorCheck::Always { from: None, to: None, min: Some(0.0), max: Some(100.0) }).check(&results).passed);
+        assert!(test("Level", BehaviorCheck::SteadyBy { time: 50.0, tolerance: 1e-3 }).check(&results).passed);
+
+        let outcome = test("Level", BehaviorCheck::Always { from: Some(1.0), to: None, min: Some(50.0), max: None }).check(&results);
+        assert!(!outcome.passed);
+        assert!(outcome.message.starts_with("Level = ") && outcome.message.ends_with("at t=1, below 50"), "{}", outcome.message);
+        assert!(!test("Level", BehaviorCheck::SteadyBy { time: 10.0, tolerance: 1e-3 }).check(&results).passed);
+        assert!(!test("Level", BehaviorCheck::At { time: 70.0, min: None, max: None }).check(&results).passed);
+        assert_eq!(test("Missing", BehaviorCheck::At { time: 0.0, min: None, max: None }).check(&results).message, "'Missing' is not in the results");
+    }
+}
@@ -0,0 +1,237 @@
+/// Fluent construction of models in code
+///
+/// `stock`, `flow`, `auxiliary` and `parameter` each start an element, and the
+/// calls after one (`initial`, `inflow`, `equation`, `units`, ...) describe it
+/// until the next starts. Equations are parsed and every reference checked
+/// when the model is built, so a typo fails `build` instead of the run.
+
+use crate::error::{ModelError, ParseError, Result};
+use super::{Auxiliary, Expression, Flow, Model, Parameter, Stock};
+
+/// An element being described, with the equation text still to parse
+#[derive(Debug)]
+enum Element {
+    Stock(Box<Stock>, String),
+    Flow(Flow, Option<String>),
+    Auxiliary(Auxiliary, Option<String>),
+    Parameter(Parameter),
+}
+
+impl Element {
+    fn describe(&self) -> String {
+        match self {
+            Element::Stock(stock, _) => format!("stock '{}'", stock.name),
+            Element::Flow(flow, _) => format!("flow '{}'", flow.name),
+            Element::Auxiliary(aux, _) => format!("auxiliary '{}'", aux.name),
+            Element::Parameter(parameter) => format!("parameter '{}'", parameter.name),
+        }
+    }
+}
+
+/// Builder of a [`Model`], validated by [`build`](ModelBuilder::build)
+///
+/// ```
+/// use rssdsim::ModelBuilder;
+///
+/// let model = ModelBuilder::new("Growth")
+///     .time(0.0, 10.0, 0.25)
+///     .stock("Population").initial("100").inflow("births")
+///     .flow("births").equation("Population * birth_rate")
+///     .parameter("birth_rate", 0.05).units("1/year")
+///     .build()?;
+/// assert_eq!(model.stocks["Population"].inflows, vec!["births".to_string()]);
+/// # Ok::<(), rssdsim::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct ModelBuilder {
+    model: Model,
+    elements: Vec<Element>,
+    /// Calls that did not fit the element they followed, reported by `build`
+    misuses: Vec<String>,
+}
+
+impl ModelBuilder {
+    pub fn new(name: &str) -> Self {
+        Self {
+            model: Model::new(name),
+            elements: Vec::new(),
+            misuses: Vec::new(),
+        }
+    }
+
+    pub fn time(mut self, start: f64, stop: f64, dt: f64) -> Self {
+        self.model.time.start = start;
+        self.model.time.stop = stop;
+        self.model.time.dt = dt;
+        self
+    }
+
+    pub fn description(mut self, description: &str) -> Self {
+        self.model.metadata.description = Some(description.to_string());
+        self
+    }
+
+    /// Start a stock, initially 0 unless `initial` follows
+    pub fn stock(mut self, name: &str) -> Self {
+        self.elements.push(Element::Stock(Box::new(Stock::new(name, "0")), "0".to_string()));
+        self
+    }
+
+    /// Start a flow; `equation` must follow
+    pub fn flow(mut self, name: &str) -> Self {
+        self.elements.push(Element::Flow(Flow::new(name, "0"), None));
+        self
+    }
+
+    /// Start an auxiliary; `equation` must follow
+    pub fn auxiliary(mut self, name: &str) -> Self {
+        self.elements.push(Element::Auxiliary(Auxiliary::new(name, "0"), None));
+        self
+    }
+
+    pub fn parameter(mut self, name: &str, value: f64) -> Self {
+        self.elements.push(Element::Parameter(Parameter::new(name, value)));
+        self
+    }
+
+    /// Record that `call` does not apply to the current element
+    fn misuse(&mut self, call: &str, applies_to: &str) {
+        let problem = match self.elements.last() {
+            Some(element) => format!("'{}' applies to {}, not {}", call, applies_to, element.describe()),
+            None => format!("'{}' must follow the element it describes", call),
+        };
+        self.misuses.push(problem);
+    }
+
+    /// Initial value equation of the current stock
+    pub fn initial(mut self, equation: &str) -> Self {
+        match self.elements.last_mut() {
+            Some(Element::Stock(_, initial)) => *initial = equation.to_string(),
+            _ => self.misuse("initial", "a stock"),
+        }
+        self
+    }
+
+    pub fn inflow(mut self, flow: &str) -> Self {
+        match self.elements.last_mut() {
+            Some(Element::Stock(stock, _)) => stock.inflows.push(flow.to_string()),
+            _ => self.misuse("inflow", "a stock"),
+        }
+        self
+    }
+
+    pub fn outflow(mut self, flow: &str) -> Self {
+        match self.elements.last_mut() {
+            Some(Element::Stock(stock, _)) => stock.outflows.push(flow.to_string()),
+            _ => self.misuse("outflow", "a stock"),
+        }
+        self
+    }
+
+    /// Equation of the current flow or auxiliary
+    pub fn equation(mut self, equation: &str) -> Self {
+        match self.elements.last_mut() {
+            Some(Element::Flow(_, text) | Element::Auxiliary(_, text)) => *text = Some(equation.to_string()),
+            _ => self.misuse("equation", "a flow or auxiliary"),
+        }
+        self
+    }
+
+    pub fn units(mut self, units: &str) -> Self {
+        let units = Some(units.to_string());
+        match self.elements.last_mut() {
+            Some(Element::Stock(stock, _)) => stock.units = units,
+            Some(Element::Flow(flow, _)) => flow.units = units,
+            Some(Element::Auxiliary(aux, _)) => aux.units = units,
+            Some(Element::Parameter(parameter)) => parameter.units = units,
+            None => self.misuse("units", "an element"),
+        }
+        self
+    }
+
+    /// Keep the current stock from going below zero, or the current flow from
+    /// reversing
+    pub fn non_negative(mut self) -> Self {
+        match self.elements.last_mut() {
+            Some(Element::Stock(stock, _)) => stock.non_negative = true,
+            Some(Element::Flow(flow, _)) => flow.non_negative = true,
+            _ => self.misuse("non_negative", "a stock or flow"),
+        }
+        self
+    }
+
+    /// Parse every equation and check the model's references
+    pub fn build(self) -> Result<Model> {
+        let mut model = self.model;
+        if let Some(problem) = self.misuses.into_iter().next() {
+            return Err(ModelError::Invalid(problem).into());
+        }
+
+        let parse = |name: &str, equation: &str| -> Result<Expression> {
+            Expression::parse_equation(name, equation).map_err(|e| ParseError::from(e).into())
+        };
+        let missing = |kind: &str, name: &str| ModelError::Invalid(format!("{} '{}' has no equation", kind, name));
+        for element in self.elements {
+            match element {
+                Element::Stock(mut stock, initial) => {
+                    stock.initial = parse(&stock.name, &initial)?;
+                    model.add_stock(*stock)?;
+                }
+                Element::Flow(mut flow, equation) => {
+                    let equation = equation.ok_or_else(|| missing("Flow", &flow.name))?;
+                    flow.equation = parse(&flow.name, &equation)?;
+                    model.add_flow(flow)?;
+                }
+                Element::Auxiliary(mut aux, equation) => {
+                    let equation = equation.ok_or_else(|| missing("Auxiliary", &aux.name))?;
+                    aux.equation = parse(&aux.name, &equation)?;
+                    model.add_auxiliary(aux)?;
+                }
+                Element::Parameter(parameter) => model.add_parameter(parameter)?,
+            }
+        }
+
+        let report = crate::analysis::validate_model(&model);
+        if !report.is_valid() {
+            return Err(ModelError::Invalid(report.errors.join("; ")).into());
+        }
+        Ok(model)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::simulation::{SimulationConfig, SimulationEngine};
+
+    fn epidemic() -> ModelBuilder {
+        ModelBuilder::new("SIR")
+            .time(0.0, 10.0, 1.0)
+            .stock("Susceptible").initial("990").outflow("infection").non_negative()
+            .stock("Infected").initial("10").inflow("infection").outflow("recovery")
+            .flow("infection").equation("contact_rate * Susceptible * Infected / total")
+            .flow("recovery").equation("Infected / duration")
+            .auxiliary("total").equation("Susceptible + Infected")
+            .parameter("contact_rate", 0.5)
+            .parameter("duration", 5.0).units("day")
+    }
+
+    #[test]
+    fn test_builder_validates_model() {
+        let model = epidemic().build().unwrap();
+        assert!(model.stocks["Susceptible"].non_negative);
+        assert_eq!(model.parameters["duration"].units.as_deref(), Some("day"));
+        let results = SimulationEngine::new(model, SimulationConfig::default()).unwrap().run().unwrap();
+        assert!(results.final_value("Infected").unwrap() > 10.0);
+
+        let typo = epidemic().auxiliary("prevalence").equation("Infectd / total").build().unwrap_err();
+        assert!(typo.to_string().contains("Infectd"), "{}", typo);
+        let syntax = epidemic().auxiliary("prevalence").equation("(Infected / total").build().unwrap_err();
+        assert!(matches!(syntax, Error::Parse(ParseError::Equation { .. })), "{}", syntax);
+        let misplaced = epidemic().flow("births").equation("1").initial("5").build().unwrap_err();
+        assert!(misplaced.to_string().contains("'initial' applies to a stock, not flow 'births'"), "{}", misplaced);
+        let duplicate = epidemic().parameter("duration", 3.0).build().unwrap_err();
+        assert!(matches!(duplicate, Error::Model(ModelError::Duplicate { .. })), "{}", duplicate);
+    }
+}
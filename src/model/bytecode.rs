@@ -0,0 +1,343 @@
+/// Compiled bytecode form of expressions
+///
+/// Expressions are compiled (see [`super::expression::compile`]) into a flat
+/// stack program whose variable references are dense indices into a `&[f64]`
+/// value slab, avoiding string hashing on every evaluation.
+
+use std::collections::HashMap;
+
+/// Dense name -> index mapping shared by compiled programs and their value slab
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    indices: HashMap<String, usize>,
+    names: Vec<String>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build a table covering every stock, flow, auxiliary and parameter of a model
+    ///
+    /// Names are sorted within each category so indices are deterministic.
+    pub fn from_model(model: &crate::model::Model) -> Self {
+        let mut table = Self::new();
+
+        for names in [
+            model.stocks.keys().collect::<Vec<_>>(),
+            model.flows.keys().collect(),
+            model.auxiliaries.keys().collect(),
+            model.parameters.keys().collect(),
+        ] {
+            let mut names = names;
+            names.sort();
+            for name in names {
+                table.insert(name);
+            }
+        }
+
+        table
+    }
+
+    /// Add a name, returning its index (existing names keep their index)
+    pub fn insert(&mut self, name: &str) -> usize {
+        if let Some(&index) = self.indices.get(name) {
+            return index;
+        }
+        let index = self.names.len();
+        self.indices.insert(name.to_string(), index);
+        self.names.push(name.to_string());
+        index
+    }
+
+    pub fn get(&self, name: &str) -> Option<usize> {
+        self.indices.get(name).copied()
+    }
+
+    pub fn name(&self, index: usize) -> Option<&str> {
+        self.names.get(index).map(|s| s.as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+}
+
+/// Built-in pure functions available to compiled programs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Builtin {
+    Abs,
+    Sqrt,
+    Exp,
+    Ln,
+    Log10,
+    Sin,
+    Cos,
+    Tan,
+    Asin,
+    Acos,
+    Atan,
+    Floor,
+    Ceil,
+    Round,
+    Pow,
+    Modulo,
+    Step,
+    Pulse,
+    PulseRepeat,
+    Ramp,
+    RampEnd,
+    Assert,
+    Implies,
+}
+
+impl Builtin {
+    /// Resolve a function name and argument count to a builtin
+    pub fn resolve(name: &str, arg_count: usize) -> Option<Self> {
+        Some(match (name.to_uppercase().as_str(), arg_count) {
+            ("ABS", 1) => Builtin::Abs,
+            ("SQRT", 1) => Builtin::Sqrt,
+            ("EXP", 1) => Builtin::Exp,
+            ("LN", 1) | ("LOG", 1) => Builtin::Ln,
+            ("LOG10", 1) => Builtin::Log10,
+            ("SIN", 1) => Builtin::Sin,
+            ("COS", 1) => Builtin::Cos,
+            ("TAN", 1) => Builtin::Tan,
+            ("ASIN", 1) => Builtin::Asin,
+            ("ACOS", 1) => Builtin::Acos,
+            ("ATAN", 1) => Builtin::Atan,
+            ("FLOOR", 1) => Builtin::Floor,
+            ("CEIL", 1) => Builtin::Ceil,
+            ("ROUND", 1) => Builtin::Round,
+            ("POW", 2) => Builtin::Pow,
+            ("MODULO", 2) | ("MOD", 2) => Builtin::Modulo,
+            ("STEP", 2) => Builtin::Step,
+            ("PULSE", 2) => Builtin::Pulse,
+            ("PULSE", 3) => Builtin::PulseRepeat,
+            ("RAMP", 2) => Builtin::Ramp,
+            ("RAMP", 3) => Builtin::RampEnd,
+            ("ASSERT", 1) => Builtin::Assert,
+            ("IMPLIES", 2) => Builtin::Implies,
+            _ => return None,
+        })
+    }
+
+    pub fn arity(&self) -> usize {
+        match self {
+            Builtin::Pow | Builtin::Modulo | Builtin::Step | Builtin::Pulse | Builtin::Ramp | Builtin::Implies => 2,
+            Builtin::PulseRepeat | Builtin::RampEnd => 3,
+            _ => 1,
+        }
+    }
+
+    /// Apply the function; `args` has exactly `arity()` values
+    fn apply(&self, args: &[f64], time: f64) -> Result<f64, String> {
+        let x = args[0];
+        Ok(match self {
+            Builtin::Abs => x.abs(),
+            Builtin::Sqrt => x.sqrt(),
+            Builtin::Exp => x.exp(),
+            Builtin::Ln => {
+                if x <= 0.0 {
+                    return Err("LN requires positive argument".to_string());
+                }
+                x.ln()
+            }
+            Builtin::Log10 => {
+                if x <= 0.0 {
+                    return Err("LOG10 requires positive argument".to_string());
+                }
+                x.log10()
+            }
+            Builtin::Sin => x.sin(),
+            Builtin::Cos => x.cos(),
+            Builtin::Tan => x.tan(),
+            Builtin::Asin => {
+                if !(-1.0..=1.0).contains(&x) {
+                    return Err("ASIN requires argument in [-1, 1]".to_string());
+                }
+                x.asin()
+            }
+            Builtin::Acos => {
+                if !(-1.0..=1.0).contains(&x) {
+                    return Err("ACOS requires argument in [-1, 1]".to_string());
+                }
+                x.acos()
+            }
+            Builtin::Atan => x.atan(),
+            Builtin::Floor => x.floor(),
+            Builtin::Ceil => x.ceil(),
+            Builtin::Round => x.round(),
+            Builtin::Pow => x.powf(args[1]),
+            Builtin::Modulo => {
+                if args[1] == 0.0 {
+                    return Err("MODULO by zero".to_string());
+                }
+                x % args[1]
+            }
+            Builtin::Step => if time >= args[1] { x } else { 0.0 },
+            Builtin::Pulse => if time >= x && time < x + args[1] { 1.0 } else { 0.0 },
+            Builtin::PulseRepeat => {
+                let interval = args[2];
+                if interval <= 0.0 {
+                    return Err("PULSE interval must be positive".to_string());
+                }
+                if time >= x && (time - x) % interval < args[1] { 1.0 } else { 0.0 }
+            }
+            Builtin::Ramp => if time < args[1] { 0.0 } else { x * (time - args[1]) },
+            Builtin::RampEnd => {
+                if time < args[1] {
+                    0.0
+                } else {
+                    x * (time.min(args[2]) - args[1])
+                }
+            }
+            Builtin::Assert => if x != 0.0 { 1.0 } else { 0.0 },
+            Builtin::Implies => if x == 0.0 || args[1] != 0.0 { 1.0 } else { 0.0 },
+        })
+    }
+}
+
+/// Single stack-machine instruction
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instruction {
+    /// Push a constant
+    Const(f64),
+    /// Push `values[index]`
+    Load(usize),
+    /// Push the current simulation time
+    Time,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    Gt,
+    Lt,
+    Ge,
+    Le,
+    Eq,
+    Ne,
+    Neg,
+    /// Pop `n` values and push their minimum
+    Min(usize),
+    /// Pop `n` values and push their maximum
+    Max(usize),
+    /// Pop `n` values and push their sum
+    Sum(usize),
+    /// Pop `n` values and push their product
+    Prod(usize),
+    /// Pop `arity` arguments and push the function result
+    Call(Builtin),
+    /// Pop a condition and jump to the target if it is false (<= 0.5)
+    JumpIfFalse(usize),
+    /// Unconditional jump
+    Jump(usize),
+}
+
+/// A compiled expression
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Program {
+    pub code: Vec<Instruction>,
+    /// Maximum stack depth reached during execution
+    pub max_stack: usize,
+}
+
+impl Program {
+    /// Execute against a value slab indexed by the program's symbol table
+    pub fn execute(&self, values: &[f64], time: f64) -> Result<f64, String> {
+        let mut stack = Vec::with_capacity(self.max_stack);
+        self.execute_with_stack(values, time, &mut stack)
+    }
+
+    /// Execute reusing a caller-provided stack to avoid allocating per call
+    pub fn execute_with_stack(&self, values: &[f64], time: f64, stack: &mut Vec<f64>) -> Result<f64, String> {
+        stack.clear();
+        let mut pc = 0;
+
+        while pc < self.code.len() {
+            match self.code[pc] {
+                Instruction::Const(value) => stack.push(value),
+                Instruction::Load(index) => stack.push(values[index]),
+                Instruction::Time => stack.push(time),
+                Instruction::Neg => {
+                    let top = stack.last_mut().ok_or("Stack underflow")?;
+                    *top = -*top;
+                }
+                Instruction::Min(n) | Instruction::Max(n) => {
+                    let start = stack.len() - n;
+                    let value = if matches!(self.code[pc], Instruction::Min(_)) {
+                        stack[start..].iter().copied().fold(f64::INFINITY, f64::min)
+                    } else {
+                        stack[start..].iter().copied().fold(f64::NEG_INFINITY, f64::max)
+                    };
+                    stack.truncate(start);
+                    stack.push(value);
+                }
+                Instruction::Sum(n) | Instruction::Prod(n) => {
+                    let start = stack.len() - n;
+                    let value = if matches!(self.code[pc], Instruction::Sum(_)) {
+                        stack[start..].iter().sum()
+                    } else {
+                        stack[start..].iter().product()
+                    };
+                    stack.truncate(start);
+                    stack.push(value);
+                }
+                Instruction::Call(builtin) => {
+                    let start = stack.len() - builtin.arity();
+                    let value = builtin.apply(&stack[start..], time)?;
+                    stack.truncate(start);
+                    stack.push(value);
+                }
+                Instruction::JumpIfFalse(target) => {
+                    let condition = stack.pop().ok_or("Stack underflow")?;
+                    if condition <= 0.5 {
+                        pc = target;
+                        continue;
+                    }
+                }
+                Instruction::Jump(target) => {
+                    pc = target;
+                    continue;
+                }
+                op => {
+                    let right = stack.pop().ok_or("Stack underflow")?;
+                    let left = stack.pop().ok_or("Stack underflow")?;
+                    let bool_value = |b: bool| if b { 1.0 } else { 0.0 };
+                    stack.push(match op {
+                        Instruction::Add => left + right,
+                        Instruction::Sub => left - right,
+                        Instruction::Mul => left * right,
+                        Instruction::Div => {
+                            if right == 0.0 {
+                                return Err("Division by zero".to_string());
+                            }
+                            left / right
+                        }
+                        Instruction::Pow => left.powf(right),
+                        Instruction::Gt => bool_value(left > right),
+                        Instruction::Lt => bool_value(left < right),
+                        Instruction::Ge => bool_value(left >= right),
+                        Instruction::Le => bool_value(left <= right),
+                        Instruction::Eq => bool_value((left - right).abs() < 1e-10),
+                        Instruction::Ne => bool_value((left - right).abs() >= 1e-10),
+                        _ => unreachable!("non-binary instruction handled above"),
+                    });
+                }
+            }
+            pc += 1;
+        }
+
+        stack.pop().ok_or_else(|| "Program produced no value".to_string())
+    }
+}
@@ -0,0 +1,168 @@
+/// Calendar semantics for model time
+///
+/// A calendar ties model time to dates: time 0 is the `epoch` date and one
+/// unit of time is a day, a month or a year. Results are then written with
+/// ISO dates, and MONTH(), DAYOFYEAR() and YEAR() read the date being
+/// simulated. A fraction of a month or year is that fraction of the month or
+/// year it falls in, so month 1.5 is halfway through the second month.
+
+use chrono::{Datelike, Duration, Months, NaiveDate, NaiveDateTime, NaiveTime};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CalendarUnit {
+    Days,
+    Months,
+    Years,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Calendar {
+    /// Date at time 0, as `YYYY-MM-DD`
+    pub epoch: NaiveDate,
+    /// Length of one unit of model time
+    pub unit: CalendarUnit,
+}
+
+/// Length of the month starting at `start`, in seconds
+fn month_seconds(start: NaiveDateTime) -> Option<f64> {
+    let next = start.checked_add_months(Months::new(1))?;
+    Some((next - start).num_seconds() as f64)
+}
+
+/// `days` as a duration, to the millisecond
+fn duration_of(days: f64) -> Option<Duration> {
+    let milliseconds = (days * 86_400_000.0).round();
+    if !milliseconds.is_finite() || milliseconds.abs() >= i64::MAX as f64 {
+        return None;
+    }
+    Duration::try_milliseconds(milliseconds as i64)
+}
+
+impl Calendar {
+    pub fn new(epoch: NaiveDate, unit: CalendarUnit) -> Self {
+        Self { epoch, unit }
+    }
+
+    /// Start of the month `months` whole months from the epoch, if there is such a date
+    fn month_start(&self, months: i64) -> Option<NaiveDateTime> {
+        let epoch = self.epoch.and_time(NaiveTime::MIN);
+        let count = Months::new(u32::try_from(months.unsigned_abs()).ok()?);
+        if months >= 0 {
+            epoch.checked_add_months(count)
+        } else {
+            epoch.checked_sub_months(count)
+        }
+    }
+
+    fn months_per_unit(&self) -> f64 {
+        match self.unit {
+            CalendarUnit::Days => 0.0,
+            CalendarUnit::Months => 1.0,
+            CalendarUnit::Years => 12.0,
+        }
+    }
+
+    /// Date and time at model time `time`; an error for a time beyond the
+    /// dates that can be represented
+    pub fn date_at(&self, time: f64) -> Result<NaiveDateTime, String> {
+        self.checked_date_at(time)
+            .ok_or_else(|| format!("Time {} is outside the dates the calendar can represent", time))
+    }
+
+    fn checked_date_at(&self, time: f64) -> Option<NaiveDateTime> {
+        if self.unit == CalendarUnit::Days {
+            return self.epoch.and_time(NaiveTime::MIN).checked_add_signed(duration_of(time)?);
+        }
+        let months = time * self.months_per_unit();
+        let whole = months.floor();
+        if !whole.is_finite() || whole.abs() > u32::MAX as f64 {
+            return None;
+        }
+        let start = self.month_start(whole as i64)?;
+        let fraction = (months - whole) * month_seconds(start)? / 86_400.0;
+        start.checked_add_signed(duration_of(fraction)?)
+    }
+
+    /// Model time at `date`, the inverse of [`date_at`](Self::date_at)
+    pub fn time_at(&self, date: NaiveDateTime) -> Result<f64, String> {
+        if self.unit == CalendarUnit::Days {
+            return Ok((date - self.epoch.and_time(NaiveTime::MIN)).num_milliseconds() as f64 / 86_400_000.0);
+        }
+        let out_of_range = || format!("Date {} is outside the months the calendar can represent", format_date(date));
+        // The month `date` falls in, counted from the epoch's day of the month
+        let mut whole = (date.year() as i64 - self.epoch.year() as i64) * 12 + date.month() as i64 - self.epoch.month() as i64;
+        if self.month_start(whole).is_none_or(|start| start > date) {
+            whole -= 1;
+        }
+        let start = self.month_start(whole).ok_or_else(out_of_range)?;
+        let seconds = month_seconds(start).ok_or_else(out_of_range)?;
+        let fraction = (date - start).num_milliseconds() as f64 / 1000.0 / seconds;
+        Ok((whole as f64 + fraction) / self.months_per_unit())
+    }
+
+    /// ISO 8601 date at `time`, with the time of day when it is not midnight
+    pub fn format(&self, time: f64) -> Result<String, String> {
+        Ok(format_date(self.date_at(time)?))
+    }
+}
+
+/// ISO 8601 date, with the time of day when it is not midnight
+pub fn format_date(date: NaiveDateTime) -> String {
+    if date.time() == NaiveTime::MIN {
+        date.format("%Y-%m-%d").to_string()
+    } else {
+        date.format("%Y-%m-%dT%H:%M:%S").to_string()
+    }
+}
+
+/// Parse an ISO 8601 date, with or without a time of day
+pub fn parse_date(text: &str) -> Option<NaiveDateTime> {
+    let text = text.trim();
+    NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S").ok()
+        .or_else(|| NaiveDate::parse_from_str(text, "%Y-%m-%d").ok().map(|date| date.and_time(NaiveTime::MIN)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(text: &str) -> NaiveDateTime {
+        parse_date(text).unwrap()
+    }
+
+    #[test]
+    fn test_calendar_dates() {
+        let epoch = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let days = Calendar::new(epoch, CalendarUnit::Days);
+        assert_eq!(days.format(59.0).unwrap(), "2024-02-29");
+        assert_eq!(days.format(0.5).unwrap(), "2024-01-01T12:00:00");
+        assert_eq!(days.time_at(date("2024-03-01")).unwrap(), 60.0);
+
+        let months = Calendar::new(epoch, CalendarUnit::Months);
+        assert_eq!(months.format(1.5).unwrap(), "2024-02-15T12:00:00");
+        assert_eq!(months.format(-1.0).unwrap(), "2023-12-01");
+        assert_eq!(months.time_at(date("2024-02-15T12:00:00")).unwrap(), 1.5);
+
+        let years = Calendar::new(epoch, CalendarUnit::Years);
+        assert_eq!(years.format(2.25).unwrap(), "2026-04-01");
+        assert_eq!(years.time_at(date("2026-04-01")).unwrap(), 2.25);
+        assert!(parse_date("April 2026").is_none());
+    }
+
+    #[test]
+    fn test_dates_out_of_range() {
+        let epoch = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        for unit in [CalendarUnit::Days, CalendarUnit::Months, CalendarUnit::Years] {
+            let calendar = Calendar::new(epoch, unit);
+            for time in [1.0e9, -1.0e9, 1.0e300, f64::NAN] {
+                let error = calendar.date_at(time).unwrap_err();
+                assert!(error.contains("outside the dates"), "{}", error);
+            }
+        }
+        let months = Calendar::new(epoch, CalendarUnit::Months);
+        assert!(months.time_at(NaiveDate::MAX.and_time(NaiveTime::MIN)).is_err());
+    }
+}
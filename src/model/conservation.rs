@@ -0,0 +1,133 @@
+/// Conserved stock groups
+///
+/// The material in a group of stocks, such as the people in Susceptible,
+/// Infected and Recovered, only changes through the flows that cross the
+/// group's boundary. A flow that is an outflow of one stock of the group and an
+/// inflow of another only moves material around; one that drains two stocks
+/// of the group, or fills one without draining another, creates or destroys
+/// material, which is almost always a sign error in the wiring.
+
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use super::Model;
+use super::arrays::{element_combinations, element_name};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConservedGroup {
+    pub name: String,
+    pub stocks: Vec<String>,
+    /// Flows that bring material into the group or take it out, such as
+    /// births and deaths
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub boundary: Vec<String>,
+    /// Largest imbalance allowed in a step, relative to the group's total
+    #[serde(default = "default_tolerance")]
+    pub tolerance: f64,
+}
+
+fn default_tolerance() -> f64 {
+    1e-6
+}
+
+/// A flow that changes a group's total without being one of its boundary flows
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Leak {
+    pub flow: String,
+    /// Stocks of the group the flow fills
+    pub inflow_of: usize,
+    /// Stocks of the group the flow drains
+    pub outflow_of: usize,
+}
+
+impl ConservedGroup {
+    pub fn new(name: &str, stocks: Vec<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            stocks,
+            boundary: Vec::new(),
+            tolerance: default_tolerance(),
+        }
+    }
+
+    pub fn with_boundary(mut self, flows: Vec<String>) -> Self {
+        self.boundary = flows;
+        self
+    }
+
+    pub fn with_tolerance(mut self, tolerance: f64) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Stocks of the group in `model`, with arrayed stocks expanded into
+    /// their elements once the model is compiled
+    pub fn members(&self, model: &Model) -> Vec<String> {
+        expand(model, &self.stocks)
+    }
+
+    /// Flows of the group that create or destroy material, by name
+    pub fn leaks(&self, model: &Model) -> Vec<Leak> {
+        let boundary = expand(model, &self.boundary);
+        let mut counts: BTreeMap<&String, (usize, usize)> = BTreeMap::new();
+        for name in self.members(model) {
+            let Some(stock) = model.stocks.get(&name) else { continue };
+            for flow in &stock.inflows {
+                counts.entry(flow).or_default().0 += 1;
+            }
+            for flow in &stock.outflows {
+                counts.entry(flow).or_default().1 += 1;
+            }
+        }
+        counts.into_iter()
+            .filter(|(flow, (inflow_of, outflow_of))| inflow_of != outflow_of && !boundary.contains(flow))
+            .map(|(flow, (inflow_of, outflow_of))| Leak { flow: flow.clone(), inflow_of, outflow_of })
+            .collect()
+    }
+}
+
+impl ConservedGroup {
+    /// Boundary flows of the group in `model`, each with the net number of
+    /// times it adds to the group's stocks
+    pub fn boundary_flows(&self, model: &Model) -> Vec<(String, f64)> {
+        let members = self.members(model);
+        expand(model, &self.boundary).into_iter()
+            .map(|flow| {
+                let net = members.iter()
+                    .filter_map(|name| model.stocks.get(name))
+                    .map(|stock| {
+                        stock.inflows.iter().filter(|f| **f == flow).count() as f64
+                            - stock.outflows.iter().filter(|f| **f == flow).count() as f64
+                    })
+                    .sum();
+                (flow, net)
+            })
+            .collect()
+    }
+}
+
+impl Leak {
+    /// Net number of times the flow adds to the group: positive when it creates material
+    pub fn coefficient(&self) -> f64 {
+        self.inflow_of as f64 - self.outflow_of as f64
+    }
+}
+
+impl std::fmt::Display for Leak {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let effect = if self.inflow_of > self.outflow_of { "creates" } else { "destroys" };
+        write!(f, "flow '{}' {} material: inflow of {} and outflow of {} of its stocks", self.flow, effect, self.inflow_of, self.outflow_of)
+    }
+}
+
+/// `names`, with each arrayed variable replaced by its elements
+fn expand(model: &Model, names: &[String]) -> Vec<String> {
+    let mut expanded = Vec::new();
+    for name in names {
+        match model.expanded_arrays.get(name).map(|dimensions| element_combinations(dimensions, &model.dimensions)) {
+            Some(Ok(combinations)) => expanded.extend(combinations.iter().map(|elements| element_name(name, elements))),
+            _ => expanded.push(name.clone()),
+        }
+    }
+    expanded
+}
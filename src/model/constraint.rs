@@ -0,0 +1,59 @@
+/// Reality-check constraints
+///
+/// A constraint is a condition that should hold at every step of a run, such
+/// as `ASSERT(Inventory >= 0)` or `IMPLIES(Price > 100, Demand < initial_demand)`
+/// (Sterman, 2000, ch. 21). The engine checks each one after every step and,
+/// when it fails, warns, stops the run or only records the violation.
+
+use serde::{Deserialize, Serialize};
+use super::Expression;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Constraint {
+    pub name: String,
+    /// Holds while non-zero
+    pub condition: Expression,
+    #[serde(default)]
+    pub action: ConstraintAction,
+}
+
+/// What a run does when a constraint fails
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConstraintAction {
+    /// Print a warning the first time it fails, and keep running
+    #[default]
+    Warn,
+    /// Stop the run with an error
+    Abort,
+    /// Record every failure without printing anything
+    Log,
+}
+
+impl Constraint {
+    pub fn new(name: &str, condition: &str) -> Result<Self, String> {
+        Ok(Self {
+            name: name.to_string(),
+            condition: Expression::parse(condition)?,
+            action: ConstraintAction::default(),
+        })
+    }
+
+    pub fn with_action(mut self, action: ConstraintAction) -> Self {
+        self.action = action;
+        self
+    }
+}
+
+impl std::str::FromStr for ConstraintAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s.to_lowercase().as_str() {
+            "warn" => Ok(ConstraintAction::Warn),
+            "abort" => Ok(ConstraintAction::Abort),
+            "log" => Ok(ConstraintAction::Log),
+            _ => Err(format!("Unknown constraint action '{}' (expected warn, abort or log)", s)),
+        }
+    }
+}
@@ -0,0 +1,235 @@
+/// Exogenous data-driven variables
+///
+/// A data variable takes its value from an external time series (a CSV or
+/// Parquet file) instead of an equation, so historical inputs such as GDP or
+/// temperature can drive a model without writing them out as lookup points.
+/// Spreadsheets are read cell by cell, as Vensim's GET XLS DATA does.
+/// Files are read by [`crate::io::load_data`]; the model only keeps the points.
+
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DataVariable {
+    pub name: String,
+    /// CSV, Parquet or spreadsheet file holding the series
+    pub file: PathBuf,
+    /// Column with the values; defaults to the variable's name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub column: Option<String>,
+    /// Column with the times; defaults to the first column. In a spreadsheet,
+    /// the column letter or row number holding the times
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_column: Option<String>,
+    /// Spreadsheet sheet; defaults to the first
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sheet: Option<String>,
+    /// Spreadsheet cell holding the first value, such as `B2`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cell: Option<String>,
+    #[serde(default)]
+    pub interpolation: Interpolation,
+    #[serde(default)]
+    pub extrapolation: Extrapolation,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub units: Option<String>,
+    /// (time, value) points sorted by time, filled in when the file is loaded
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub points: Vec<(f64, f64)>,
+}
+
+/// Value between two data points
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Interpolation {
+    /// Straight line between neighbouring points
+    #[default]
+    Linear,
+    /// Hold each point's value until the next point
+    Step,
+    /// Natural cubic spline, smooth through every point; x values must differ
+    Spline,
+}
+
+/// Value before the first or after the last data point
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Extrapolation {
+    /// Hold the nearest point's value
+    #[default]
+    #[serde(alias = "clamp")]
+    Hold,
+    /// Extend the line through the two nearest points
+    Linear,
+    /// Fail the simulation
+    Error,
+}
+
+impl DataVariable {
+    pub fn new(name: &str, file: impl Into<PathBuf>) -> Self {
+        Self {
+            name: name.to_string(),
+            file: file.into(),
+            column: None,
+            time_column: None,
+            sheet: None,
+            cell: None,
+            interpolation: Interpolation::default(),
+            extrapolation: Extrapolation::default(),
+            units: None,
+            points: Vec::new(),
+        }
+    }
+
+    pub fn with_column(mut self, column: &str) -> Self {
+        self.column = Some(column.to_string());
+        self
+    }
+
+    pub fn with_time_column(mut self, column: &str) -> Self {
+        self.time_column = Some(column.to_string());
+        self
+    }
+
+    pub fn with_interpolation(mut self, interpolation: Interpolation) -> Self {
+        self.interpolation = interpolation;
+        self
+    }
+
+    pub fn with_extrapolation(mut self, extrapolation: Extrapolation) -> Self {
+        self.extrapolation = extrapolation;
+        self
+    }
+
+    /// Use these points instead of reading the file
+    pub fn with_points(mut self, points: Vec<(f64, f64)>) -> Result<Self, String> {
+        self.set_points(points)?;
+        Ok(self)
+    }
+
+    /// Replace the series, sorting it by time
+    pub fn set_points(&mut self, mut points: Vec<(f64, f64)>) -> Result<(), String> {
+        if points.is_empty() {
+            return Err(format!("Data variable '{}' has no data points", self.name));
+        }
+        if points.iter().any(|(t, _)| !t.is_finite()) {
+            return Err(format!("Data variable '{}' has a non-numeric time", self.name));
+        }
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        if let Some(w) = points.windows(2).find(|w| w[0].0 == w[1].0) {
+            return Err(format!("Data variable '{}' has two values at time {}", self.name, w[0].0));
+        }
+        self.points = points;
+        Ok(())
+    }
+
+    /// Column holding the values
+    pub fn value_column(&self) -> &str {
+        self.column.as_deref().unwrap_or(&self.name)
+    }
+
+    /// Value of the series at `time`
+    pub fn value_at(&self, time: f64) -> Result<f64, String> {
+        let (first, last) = match (self.points.first(), self.points.last()) {
+            (Some(first), Some(last)) => (first.0, last.0),
+            _ => return Err(format!("Data variable '{}' has not been loaded", self.name)),
+        };
+        if time.is_nan() {
+            return Err(format!("Data variable '{}' read at a time that is not a number", self.name));
+        }
+        interpolate(&self.points, time, self.interpolation, self.extrapolation).ok_or_else(|| format!(
+            "Time {} is outside the data for '{}' ({} to {})",
+            time, self.name, first, last
+        ))
+    }
+}
+
+/// Value at `x` of (x, y) points sorted by x; `None` when there are no points,
+/// `x` is NaN, or `x` is outside them and `extrapolation` is [`Extrapolation::Error`]
+pub(crate) fn interpolate(points: &[(f64, f64)], x: f64, interpolation: Interpolation, extrapolation: Extrapolation) -> Option<f64> {
+    let (first, last) = (*points.first()?, *points.last()?);
+    if x.is_nan() {
+        return None;
+    }
+
+    if x < first.0 || x > last.0 {
+        return match extrapolation {
+            Extrapolation::Hold => Some(if x < first.0 { first.1 } else { last.1 }),
+            Extrapolation::Linear if points.len() > 1 => {
+                let (a, b) = if x < first.0 { (first, points[1]) } else { (points[points.len() - 2], last) };
+                Some(a.1 + (x - a.0) * (b.1 - a.1) / (b.0 - a.0))
+            }
+            Extrapolation::Linear => Some(first.1),
+            Extrapolation::Error => None,
+        };
+    }
+
+    // Index of the first point after `x`
+    let next = points.partition_point(|(px, _)| *px <= x);
+    if next == points.len() {
+        return Some(last.1);
+    }
+    let (a, b) = (points[next - 1], points[next]);
+    Some(match interpolation {
+        Interpolation::Linear => a.1 + (x - a.0) * (b.1 - a.1) / (b.0 - a.0),
+        Interpolation::Step => a.1,
+        Interpolation::Spline => spline(points, next, x),
+    })
+}
+
+/// Natural cubic spline through `points` at `x`, which lies between
+/// `points[next - 1]` and `points[next]`
+fn spline(points: &[(f64, f64)], next: usize, x: f64) -> f64 {
+    // Second derivatives at the points, zero at both ends, solving the
+    // tridiagonal system by forward elimination and back substitution
+    let n = points.len();
+    let width = |i: usize| points[i + 1].0 - points[i].0;
+    let slope = |i: usize| (points[i + 1].1 - points[i].1) / width(i);
+    let mut diagonal = vec![0.0; n];
+    let mut rhs = vec![0.0; n];
+    for i in 1..n - 1 {
+        diagonal[i] = 2.0 * (width(i - 1) + width(i));
+        rhs[i] = 6.0 * (slope(i) - slope(i - 1));
+        if i > 1 {
+            let factor = width(i - 1) / diagonal[i - 1];
+            diagonal[i] -= factor * width(i - 1);
+            rhs[i] -= factor * rhs[i - 1];
+        }
+    }
+    let mut curvature = vec![0.0; n];
+    for i in (1..n - 1).rev() {
+        curvature[i] = (rhs[i] - width(i) * curvature[i + 1]) / diagonal[i];
+    }
+
+    let (a, b, h) = (points[next - 1], points[next], width(next - 1));
+    let (t, u) = ((b.0 - x) / h, (x - a.0) / h);
+    t * a.1 + u * b.1 + h * h / 6.0 * ((t.powi(3) - t) * curvature[next - 1] + (u.powi(3) - u) * curvature[next])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_data_interpolation_and_extrapolation() {
+        let data = DataVariable::new("gdp", "gdp.csv")
+            .with_points(vec![(2001.0, 12.0), (2000.0, 10.0), (2002.0, 16.0)])
+            .unwrap();
+        assert_eq!(data.value_at(2000.5).unwrap(), 11.0);
+        assert_eq!(data.value_at(2002.0).unwrap(), 16.0);
+        assert_eq!(data.value_at(1990.0).unwrap(), 10.0);
+        assert_eq!(data.value_at(2010.0).unwrap(), 16.0);
+
+        let step = data.clone().with_interpolation(Interpolation::Step);
+        assert_eq!(step.value_at(2001.5).unwrap(), 12.0);
+
+        let linear = data.clone().with_extrapolation(Extrapolation::Linear);
+        assert_eq!(linear.value_at(1999.0).unwrap(), 8.0);
+        assert_eq!(linear.value_at(2003.0).unwrap(), 20.0);
+
+        let strict = data.with_extrapolation(Extrapolation::Error);
+        assert!(strict.value_at(2003.0).is_err());
+        assert!(strict.value_at(f64::NAN).unwrap_err().contains("not a number"));
+        assert!(DataVariable::new("x", "x.csv").with_points(vec![(1.0, 1.0), (1.0, 2.0)]).is_err());
+    }
+}
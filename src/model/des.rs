@@ -0,0 +1,222 @@
+/// Discrete-event settings
+///
+/// The `des` section of a model moves individual entities through a network
+/// of generators, queues, servers and sinks alongside the stocks and flows.
+/// Generators create entities at random interarrival times, or by breaking
+/// the material an SD flow moves into units of `size`. Queues hold entities
+/// first in, first out or by priority. Servers take entities from a queue for
+/// a random service time and pass them to a queue or sink. Sinks remove them,
+/// adding their size to an SD stock when one is named.
+
+use serde::{Deserialize, Serialize};
+use crate::simulation::StochasticManager;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DesConfig {
+    #[serde(default)]
+    pub generators: Vec<GeneratorConfig>,
+    #[serde(default)]
+    pub queues: Vec<QueueConfig>,
+    #[serde(default)]
+    pub servers: Vec<ServerConfig>,
+    #[serde(default)]
+    pub sinks: Vec<SinkConfig>,
+}
+
+/// Source of entities, with exactly one of `interarrival` or `flow`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GeneratorConfig {
+    pub name: String,
+    /// Queue or sink new entities enter
+    pub target: String,
+    /// Time between arrivals, the first one after the start time
+    #[serde(default)]
+    pub interarrival: Option<TimeDistribution>,
+    /// SD flow whose material is turned into one entity per `size`
+    #[serde(default)]
+    pub flow: Option<String>,
+    /// Amount of material each entity carries
+    #[serde(default = "default_size")]
+    pub size: f64,
+    /// Priority of the entities in priority queues; higher goes first
+    #[serde(default)]
+    pub priority: f64,
+    /// Most entities to create over the run
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+/// Order a queue releases entities in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueDiscipline {
+    #[default]
+    Fifo,
+    /// Highest priority first, then first in, first out
+    Priority,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QueueConfig {
+    pub name: String,
+    #[serde(default)]
+    pub discipline: QueueDiscipline,
+    /// Most entities waiting at once; arrivals at a full queue are lost
+    #[serde(default)]
+    pub capacity: Option<usize>,
+}
+
+/// Serves entities from `queue` and passes them on to `target`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServerConfig {
+    pub name: String,
+    pub queue: String,
+    pub service: TimeDistribution,
+    /// Entities served at the same time
+    #[serde(default = "default_capacity")]
+    pub capacity: usize,
+    /// Queue or sink served entities go to
+    pub target: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SinkConfig {
+    pub name: String,
+    /// SD stock the size of every entity arriving is added to
+    #[serde(default)]
+    pub stock: Option<String>,
+}
+
+/// Distribution of interarrival and service times; negative draws are taken as 0
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "distribution", rename_all = "snake_case")]
+pub enum TimeDistribution {
+    Constant { value: f64 },
+    Exponential { mean: f64 },
+    Uniform { min: f64, max: f64 },
+    Triangular { min: f64, mode: f64, max: f64 },
+    Normal { mean: f64, std_dev: f64 },
+}
+
+fn default_size() -> f64 {
+    1.0
+}
+
+fn default_capacity() -> usize {
+    1
+}
+
+impl TimeDistribution {
+    pub fn sample(&self, stochastic: &mut StochasticManager) -> Result<f64, String> {
+        let value = match *self {
+            TimeDistribution::Constant { value } => value,
+            TimeDistribution::Exponential { mean } => -mean * (1.0 - stochastic.random()).ln(),
+            TimeDistribution::Uniform { min, max } => stochastic.uniform(min, max),
+            TimeDistribution::Triangular { min, mode, max } => {
+                let u = stochastic.random();
+                let split = if max > min { (mode - min) / (max - min) } else { 0.0 };
+                if u < split {
+                    min + (u * (max - min) * (mode - min)).sqrt()
+                } else {
+                    max - ((1.0 - u) * (max - min) * (max - mode)).sqrt()
+                }
+            }
+            TimeDistribution::Normal { mean, std_dev } => stochastic.normal(mean, std_dev)?,
+        };
+        Ok(value.max(0.0))
+    }
+
+    /// Check the parameters describe times
+    pub fn validate(&self) -> Result<(), String> {
+        let valid = match *self {
+            TimeDistribution::Constant { value } => value >= 0.0,
+            TimeDistribution::Exponential { mean } => mean > 0.0,
+            TimeDistribution::Uniform { min, max } => min >= 0.0 && min <= max,
+            TimeDistribution::Triangular { min, mode, max } => min >= 0.0 && min <= mode && mode <= max,
+            TimeDistribution::Normal { std_dev, .. } => std_dev >= 0.0,
+        };
+        if valid { Ok(()) } else { Err(format!("Invalid time distribution {:?}", self)) }
+    }
+
+    /// Check the parameters describe interarrival times, which must move time
+    /// forward so arrivals cannot pile up at one instant
+    pub fn validate_interarrival(&self) -> Result<(), String> {
+        self.validate()?;
+        let advances = match *self {
+            TimeDistribution::Constant { value } => value > 0.0,
+            TimeDistribution::Exponential { .. } => true,
+            TimeDistribution::Uniform { max, .. } | TimeDistribution::Triangular { max, .. } => max > 0.0,
+            TimeDistribution::Normal { mean, .. } => mean > 0.0,
+        };
+        if advances { Ok(()) } else { Err(format!("Interarrival time {:?} is never positive", self)) }
+    }
+}
+
+impl DesConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_generator(mut self, generator: GeneratorConfig) -> Self {
+        self.generators.push(generator);
+        self
+    }
+
+    pub fn with_queue(mut self, name: &str, discipline: QueueDiscipline, capacity: Option<usize>) -> Self {
+        self.queues.push(QueueConfig { name: name.to_string(), discipline, capacity });
+        self
+    }
+
+    pub fn with_server(mut self, name: &str, queue: &str, service: TimeDistribution, capacity: usize, target: &str) -> Self {
+        self.servers.push(ServerConfig {
+            name: name.to_string(),
+            queue: queue.to_string(),
+            service,
+            capacity,
+            target: target.to_string(),
+        });
+        self
+    }
+
+    pub fn with_sink(mut self, name: &str, stock: Option<&str>) -> Self {
+        self.sinks.push(SinkConfig { name: name.to_string(), stock: stock.map(str::to_string) });
+        self
+    }
+
+    /// Names of the queues, servers and sinks, which the DES_ functions take
+    pub fn node_names(&self) -> impl Iterator<Item = &String> {
+        self.queues.iter().map(|queue| &queue.name)
+            .chain(self.servers.iter().map(|server| &server.name))
+            .chain(self.sinks.iter().map(|sink| &sink.name))
+    }
+}
+
+impl GeneratorConfig {
+    /// Entities entering `target` at `interarrival` times
+    pub fn new(name: &str, target: &str, interarrival: TimeDistribution) -> Self {
+        Self {
+            name: name.to_string(),
+            target: target.to_string(),
+            interarrival: Some(interarrival),
+            flow: None,
+            size: default_size(),
+            priority: 0.0,
+            limit: None,
+        }
+    }
+
+    /// Entities of `size` made from the material moved by SD flow `flow`
+    pub fn from_flow(name: &str, target: &str, flow: &str, size: f64) -> Self {
+        Self {
+            interarrival: None,
+            flow: Some(flow.to_string()),
+            size,
+            ..Self::new(name, target, TimeDistribution::Constant { value: 0.0 })
+        }
+    }
+}
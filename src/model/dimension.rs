@@ -2,6 +2,7 @@
 
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fmt;
 
 /// A dimension (also called subscript or index) for array variables
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -10,6 +11,20 @@ pub struct Dimension {
     pub elements: Vec<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
+    /// Correspondence with another dimension, so a variable arrayed over the
+    /// target can be read with this dimension's subscripts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub maps_to: Option<DimensionMapping>,
+}
+
+/// Mapping of a dimension's elements onto another dimension
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DimensionMapping {
+    /// Dimension mapped onto
+    pub dimension: String,
+    /// Target element for each element, in order; elements map by position when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub elements: Vec<String>,
 }
 
 impl Dimension {
@@ -18,6 +33,7 @@ impl Dimension {
             name: name.to_string(),
             elements,
             description: None,
+            maps_to: None,
         }
     }
 
@@ -26,6 +42,23 @@ impl Dimension {
         self
     }
 
+    /// Map onto `dimension`, element by element (`elements`) or by position (empty)
+    pub fn with_mapping(mut self, dimension: &str, elements: Vec<String>) -> Self {
+        self.maps_to = Some(DimensionMapping { dimension: dimension.to_string(), elements });
+        self
+    }
+
+    /// Element of `target` that `element` of this dimension maps to
+    pub fn map_element(&self, element: &str, target: &Dimension) -> Option<String> {
+        let mapping = self.maps_to.as_ref().filter(|m| m.dimension == target.name)?;
+        let index = self.get_index(element)?;
+        if mapping.elements.is_empty() {
+            target.elements.get(index).cloned()
+        } else {
+            mapping.elements.get(index).filter(|e| target.contains(e)).cloned()
+        }
+    }
+
     /// Get the size of this dimension
     pub fn size(&self) -> usize {
         self.elements.len()
@@ -51,6 +84,18 @@ pub enum SubscriptRef {
     Dimension(String),
     /// Wildcard/asterisk to iterate over all elements
     Wildcard,
+    /// Element a fixed distance from the current one (e.g., "age-1")
+    Offset { dimension: String, offset: i32 },
+}
+
+impl fmt::Display for SubscriptRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubscriptRef::Element(name) | SubscriptRef::Dimension(name) => write!(f, "{}", name),
+            SubscriptRef::Wildcard => write!(f, "*"),
+            SubscriptRef::Offset { dimension, offset } => write!(f, "{}{:+}", dimension, offset),
+        }
+    }
 }
 
 /// Manager for dimension definitions and subscript resolution
@@ -112,6 +157,10 @@ impl DimensionManager {
                 }
             }
             SubscriptRef::Wildcard => Ok(dimension.elements.clone()),
+            SubscriptRef::Offset { dimension: dim_name, offset } => Err(format!(
+                "Offset subscript '{}{:+}' needs a current element of '{}'",
+                dim_name, offset, dim_name
+            )),
         }
     }
 
@@ -0,0 +1,86 @@
+/// Discrete events
+///
+/// An event fires at a fixed time or when a condition becomes true, and
+/// changes the model at that instant: setting a parameter (for example a
+/// policy switch) or a stock, or adding to a stock.
+
+use serde::{Deserialize, Serialize};
+use super::Expression;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Event {
+    pub name: String,
+    pub trigger: EventTrigger,
+    #[serde(default)]
+    pub actions: Vec<EventAction>,
+    /// Fire a condition event every time its condition becomes true, not just the first
+    #[serde(default)]
+    pub repeat: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventTrigger {
+    /// Simulation time at which the event fires
+    At(f64),
+    /// Condition whose change from false (zero) to true (non-zero) fires the event
+    When(Expression),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventAction {
+    /// Set a parameter or stock to a value
+    Set { variable: String, value: Expression },
+    /// Add an amount (negative to remove) to a stock
+    Add { stock: String, amount: Expression },
+}
+
+impl Event {
+    pub fn new(name: &str, trigger: EventTrigger) -> Self {
+        Self {
+            name: name.to_string(),
+            trigger,
+            actions: Vec::new(),
+            repeat: false,
+        }
+    }
+
+    /// Event firing once at `time`
+    pub fn at(name: &str, time: f64) -> Self {
+        Self::new(name, EventTrigger::At(time))
+    }
+
+    /// Event firing when `condition` becomes true
+    pub fn when(name: &str, condition: &str) -> Result<Self, String> {
+        Ok(Self::new(name, EventTrigger::When(Expression::parse(condition)?)))
+    }
+
+    pub fn with_action(mut self, action: EventAction) -> Self {
+        self.actions.push(action);
+        self
+    }
+
+    pub fn with_repeat(mut self, repeat: bool) -> Self {
+        self.repeat = repeat;
+        self
+    }
+}
+
+impl EventAction {
+    pub fn set(variable: &str, value: &str) -> Result<Self, String> {
+        Ok(EventAction::Set { variable: variable.to_string(), value: Expression::parse(value)? })
+    }
+
+    pub fn add(stock: &str, amount: &str) -> Result<Self, String> {
+        Ok(EventAction::Add { stock: stock.to_string(), amount: Expression::parse(amount)? })
+    }
+
+    /// Name of the parameter or stock the action changes
+    pub fn target(&self) -> &str {
+        match self {
+            EventAction::Set { variable, .. } => variable,
+            EventAction::Add { stock, .. } => stock,
+        }
+    }
+}
@@ -2,12 +2,18 @@
 
 use serde::{Deserialize, Serialize};
 use std::fmt;
+use super::bytecode::{Builtin, Instruction, Program, SymbolTable};
+use crate::error::EquationError;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum Expression {
     Constant(f64),
     Variable(String),
+    /// Quoted string, used for name arguments such as `LOOKUP("table", x)`
+    StringLiteral {
+        literal: String,
+    },
     /// Variable with subscripts for array indexing
     /// Example: Population[Region] or Sales[Region, Product]
     SubscriptedVariable {
@@ -26,6 +32,10 @@ pub enum Expression {
     FunctionCall {
         name: String,
         args: Vec<Expression>,
+        /// Position among the function calls of its variable's equation,
+        /// numbered by [`Model::compile`](crate::model::Model::compile)
+        #[serde(skip)]
+        site: Option<usize>,
     },
     Conditional {
         condition: Box<Expression>,
@@ -55,417 +65,56 @@ pub enum UnaryOperator {
     Negate,
 }
 
-impl Expression {
-    /// Parse a simple expression from string
-    /// For now, supports: constants, variables, and basic operations
-    pub fn parse(s: &str) -> Result<Self, String> {
-        let s = s.trim();
-
-        // Try to parse as number
-        if let Ok(num) = s.parse::<f64>() {
-            return Ok(Expression::Constant(num));
-        }
-
-        // Check for IF THEN ELSE conditional (highest priority)
-        if let Some(conditional) = Self::try_parse_conditional(s) {
-            return conditional;
-        }
-
-        // Check for comparison operators
-        // Priority: comparisons (lowest), + - , * / , ^ (highest)
-        if let Some(expr) = Self::try_parse_comparison(s) {
-            return Ok(expr);
-        }
-
-        // Look for + or - (lowest precedence)
-        if let Some(expr) = Self::try_parse_binary(s, &['+', '-']) {
-            return Ok(expr);
-        }
-
-        // Look for * or /
-        if let Some(expr) = Self::try_parse_binary(s, &['*', '/']) {
-            return Ok(expr);
-        }
-
-        // Look for ^
-        if let Some(expr) = Self::try_parse_binary(s, &['^']) {
-            return Ok(expr);
-        }
-
-        // Check for parentheses first (before function calls)
-        if s.starts_with('(') && s.ends_with(')') {
-            // Could be either parentheses or a function call
-            // If there's no function name before the '(', it's just parentheses
-            return Self::parse(&s[1..s.len() - 1]);
-        }
-
-        // Check for function call
-        if let Some(paren_idx) = s.find('(') {
-            if s.ends_with(')') && paren_idx > 0 {
-                let func_name = s[..paren_idx].trim();
-
-                // Skip if function name is empty (means it's just parentheses)
-                if !func_name.is_empty() {
-                    let args_str = &s[paren_idx + 1..s.len() - 1];
-
-                    // Split arguments by commas, respecting nested parentheses
-                    let arg_strings = Self::split_function_args(args_str);
-
-                    // Parse each argument
-                    let args: Result<Vec<_>, _> = arg_strings
-                        .iter()
-                        .map(|arg| Self::parse(arg.trim()))
-                        .collect();
-
-                    return Ok(Expression::FunctionCall {
-                        name: func_name.to_string(),
-                        args: args?,
-                    });
-                }
-            }
-        }
-
-        // Check for unary minus
-        if s.starts_with('-') {
-            let inner = Self::parse(&s[1..])?;
-            return Ok(Expression::UnaryOp {
-                op: UnaryOperator::Negate,
-                expr: Box::new(inner),
-            });
-        }
-
-        // Check for subscripted variable: VarName[Sub1, Sub2, ...]
-        if let Some(bracket_idx) = s.find('[') {
-            if s.ends_with(']') {
-                let var_name = s[..bracket_idx].trim();
-                let subscripts_str = &s[bracket_idx + 1..s.len() - 1];
-
-                // Parse subscripts (comma-separated)
-                let subscript_strings = Self::split_function_args(subscripts_str);
-                let subscripts: Vec<crate::model::SubscriptRef> = subscript_strings
-                    .iter()
-                    .map(|sub| {
-                        let sub_trimmed = sub.trim();
-                        // For now, treat all subscripts as elements
-                        // Could be enhanced to detect wildcards (*) or dimensions
-                        if sub_trimmed == "*" {
-                            crate::model::SubscriptRef::Wildcard
-                        } else {
-                            crate::model::SubscriptRef::Element(sub_trimmed.to_string())
-                        }
-                    })
-                    .collect();
-
-                return Ok(Expression::SubscriptedVariable {
-                    name: var_name.to_string(),
-                    subscripts,
-                });
-            }
-        }
-
-        // Otherwise treat as variable name
-        Ok(Expression::Variable(s.to_string()))
-    }
-
-    fn try_parse_binary(s: &str, ops: &[char]) -> Option<Expression> {
-        // Find rightmost operator (for left-to-right evaluation)
-        let mut depth = 0;
-        let mut op_pos = None;
-        let mut op_char = None;
-
-        for (i, ch) in s.chars().enumerate() {
-            match ch {
-                '(' => depth += 1,
-                ')' => depth -= 1,
-                c if depth == 0 && ops.contains(&c) => {
-                    op_pos = Some(i);
-                    op_char = Some(c);
-                }
-                _ => {}
-            }
-        }
-
-        if let (Some(pos), Some(op)) = (op_pos, op_char) {
-            let left = &s[..pos].trim();
-            let right = &s[pos + 1..].trim();
-
-            if left.is_empty() || right.is_empty() {
-                return None;
-            }
-
-            let left_expr = Self::parse(left).ok()?;
-            let right_expr = Self::parse(right).ok()?;
-
-            let operator = match op {
-                '+' => Operator::Add,
-                '-' => Operator::Subtract,
-                '*' => Operator::Multiply,
-                '/' => Operator::Divide,
-                '^' => Operator::Power,
-                _ => return None,
-            };
-
-            return Some(Expression::BinaryOp {
-                op: operator,
-                left: Box::new(left_expr),
-                right: Box::new(right_expr),
-            });
-        }
-
-        None
-    }
-
-    fn split_function_args(s: &str) -> Vec<String> {
-        // Split on commas, but only when not inside parentheses
-        let mut args = Vec::new();
-        let mut current_arg = String::new();
-        let mut depth = 0;
-
-        for ch in s.chars() {
-            match ch {
-                '(' => {
-                    depth += 1;
-                    current_arg.push(ch);
-                }
-                ')' => {
-                    depth -= 1;
-                    current_arg.push(ch);
-                }
-                ',' if depth == 0 => {
-                    args.push(current_arg.trim().to_string());
-                    current_arg.clear();
-                }
-                _ => {
-                    current_arg.push(ch);
-                }
-            }
-        }
-
-        // Don't forget the last argument
-        if !current_arg.trim().is_empty() {
-            args.push(current_arg.trim().to_string());
-        }
+/// Delay and smoothing functions: their first argument feeds a delay's
+/// state, and their output follows it after a lag
+pub const DELAY_FUNCTIONS: &[&str] = &[
+    "DELAY1", "DELAY3", "DELAYN", "DELAYP", "DELAY FIXED", "DELAY_FIXED", "SMOOTH", "SMOOTHN",
+];
 
-        args
-    }
-
-    fn try_parse_conditional(s: &str) -> Option<Result<Expression, String>> {
-        // Look for IF ... THEN ... ELSE ... pattern
-        let s_upper = s.to_uppercase();
-
-        if !s_upper.starts_with("IF ") {
-            return None;
-        }
-
-        // Find THEN and ELSE at the correct nesting level
-        // Use a simpler approach: scan for keywords and track depth
-        let (then_pos, else_pos) = Self::find_if_then_else_positions(&s_upper)?;
-
-        // Extract parts (use original case from s, not s_upper)
-        // IF is at 0-1, space at 2, condition starts at 3
-        // THEN is at then_pos..(then_pos+4), true_expr starts at then_pos+4
-        // ELSE is at else_pos..(else_pos+4), false_expr starts at else_pos+4
-        let condition_str = s[3..then_pos].trim();
-        let true_str = s[then_pos + 4..else_pos].trim();  // Skip "THEN"
-        let false_str = s[else_pos + 4..].trim();  // Skip "ELSE"
-
-        // Parse each part recursively
-        let condition = match Self::parse(condition_str) {
-            Ok(e) => e,
-            Err(e) => return Some(Err(e)),
-        };
-        let true_expr = match Self::parse(true_str) {
-            Ok(e) => e,
-            Err(e) => return Some(Err(e)),
-        };
-        let false_expr = match Self::parse(false_str) {
-            Ok(e) => e,
-            Err(e) => return Some(Err(e)),
-        };
+/// Functions that draw from the random stream when evaluated
+const RANDOM_FUNCTIONS: &[&str] = &[
+    "RANDOM", "UNIFORM", "NORMAL", "LOGNORMAL", "POISSON",
+    "WHITE_NOISE", "PINK_NOISE", "RANDOM_WALK", "AR1", "OU_PROCESS",
+];
 
-        Some(Ok(Expression::Conditional {
-            condition: Box::new(condition),
-            true_expr: Box::new(true_expr),
-            false_expr: Box::new(false_expr),
-        }))
-    }
-
-    fn find_if_then_else_positions(s_upper: &str) -> Option<(usize, usize)> {
-        // Find matching THEN and ELSE for the IF at the beginning
-        // Track depth: IF increases, THEN/ELSE decreases when inside nested IFs
-        let mut depth = 0;
-        let mut then_pos = None;
-        let mut i = 0;
-
-        while i < s_upper.len() {
-            if Self::match_keyword_at(s_upper, i, "IF") {
-                depth += 1;
-                i += 2;
-            } else if Self::match_keyword_at(s_upper, i, "THEN") {
-                if depth == 1 && then_pos.is_none() {
-                    // This is our THEN
-                    then_pos = Some(i);
-                } else if depth > 1 {
-                    depth -= 1;
-                }
-                i += 4;
-            } else if Self::match_keyword_at(s_upper, i, "ELSE") {
-                if depth == 1 && then_pos.is_some() {
-                    // This is our ELSE
-                    return Some((then_pos.unwrap(), i));
-                } else if depth > 1 {
-                    depth -= 1;
-                }
-                i += 4;
-            } else {
-                i += 1;
-            }
-        }
-
-        None
-    }
-
-    fn find_keyword_at_depth(s_upper: &str, keyword: &str, start_pos: usize) -> Option<usize> {
-        // Find a keyword at nesting depth 0 (not inside a nested IF)
-        // Track IF nesting depth: each "IF " increases depth, each "THEN" or "ELSE" at same depth closes it
-        let mut depth = 0;
-        let mut i = start_pos;
-        let keyword_trimmed = keyword.trim();
-
-        while i < s_upper.len() {
-            // Check for nested IF (increases depth)
-            if Self::match_keyword_at(s_upper, i, "IF") {
-                depth += 1;
-                i += 2;  // Skip "IF"
-                continue;
-            }
-
-            // Check for the keyword we're looking for
-            if Self::match_keyword_at(s_upper, i, keyword_trimmed) {
-                if depth == 1 {
-                    // Found it at the right depth (depth 1 because we're inside the outermost IF)
-                    // Return position of the space before the keyword (for backward compatibility)
-                    return if i > 0 && &s_upper[i-1..i] == " " {
-                        Some(i - 1)
-                    } else {
-                        Some(i)
-                    };
-                } else if depth > 1 {
-                    // This keyword closes a nested IF
-                    depth -= 1;
-                }
-                i += keyword_trimmed.len();
-                continue;
-            }
-
-            // Check for other keywords that affect depth
-            if keyword_trimmed == "ELSE" {
-                // When looking for ELSE, THEN keywords close nested IFs
-                if Self::match_keyword_at(s_upper, i, "THEN") && depth > 1 {
-                    depth -= 1;
-                    i += 4;
-                    continue;
-                }
-            }
-
-            i += 1;
-        }
-
-        None
+impl Expression {
+    /// Parse an expression from string
+    ///
+    /// Operator precedence, lowest to highest:
+    /// `IF ... THEN ... ELSE ...`, comparisons, `+ -`, `* /`, unary `-`, `^`.
+    /// Binary operators are left-associative except `^`, which is right-associative.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Self::parse_located(s.trim())
+            .map_err(|e| format!("{} at position {}", e.message, e.position))
     }
 
-    fn match_keyword_at(s: &str, pos: usize, keyword: &str) -> bool {
-        // Check if keyword appears at position, with word boundaries
-        if pos + keyword.len() > s.len() {
-            return false;
-        }
-
-        // Check if the keyword matches
-        if &s[pos..pos + keyword.len()] != keyword {
-            return false;
-        }
-
-        // Check word boundary before (must be start, space, or non-letter)
-        if pos > 0 {
-            let prev_char = s.chars().nth(pos - 1).unwrap();
-            if prev_char.is_alphabetic() {
-                return false;
-            }
-        }
-
-        // Check word boundary after (must be end, space, or non-letter)
-        if pos + keyword.len() < s.len() {
-            let next_char = s.chars().nth(pos + keyword.len()).unwrap();
-            if next_char.is_alphabetic() {
-                return false;
-            }
-        }
-
-        true
+    /// Parse the equation defining `variable`, keeping where in it parsing failed
+    ///
+    /// Positions count characters from the start of the trimmed equation.
+    pub fn parse_equation(variable: &str, equation: &str) -> Result<Self, EquationError> {
+        let equation = equation.trim();
+        Self::parse_located(equation).map_err(|e| EquationError {
+            variable: variable.to_string(),
+            equation: equation.to_string(),
+            position: e.position,
+            message: e.message,
+        })
     }
 
-    fn try_parse_comparison(s: &str) -> Option<Expression> {
-        // Look for comparison operators: >=, <=, >, <, ==, !=
-        // Check two-character operators first
-        let two_char_ops = [(">=", Operator::GreaterEqual), ("<=", Operator::LessEqual),
-                           ("==", Operator::Equal), ("!=", Operator::NotEqual)];
-
-        for (op_str, op) in &two_char_ops {
-            if let Some(pos) = s.find(op_str) {
-                let left = s[..pos].trim();
-                let right = s[pos + 2..].trim();
-
-                if !left.is_empty() && !right.is_empty() {
-                    if let (Ok(left_expr), Ok(right_expr)) = (Self::parse(left), Self::parse(right)) {
-                        return Some(Expression::BinaryOp {
-                            op: *op,
-                            left: Box::new(left_expr),
-                            right: Box::new(right_expr),
-                        });
-                    }
-                }
-            }
-        }
-
-        // Check single-character operators (>, <)
-        let mut depth = 0;
-        let mut op_pos = None;
-        let mut op_char = None;
-
-        for (i, ch) in s.chars().enumerate() {
-            match ch {
-                '(' => depth += 1,
-                ')' => depth -= 1,
-                '>' | '<' if depth == 0 => {
-                    op_pos = Some(i);
-                    op_char = Some(ch);
-                }
-                _ => {}
-            }
+    fn parse_located(s: &str) -> Result<Self, SyntaxError> {
+        // Fast path for plain numbers
+        if let Ok(num) = s.parse::<f64>() {
+            return Ok(Expression::Constant(num));
         }
 
-        if let (Some(pos), Some(op)) = (op_pos, op_char) {
-            let left = s[..pos].trim();
-            let right = s[pos + 1..].trim();
-
-            if !left.is_empty() && !right.is_empty() {
-                if let (Ok(left_expr), Ok(right_expr)) = (Self::parse(left), Self::parse(right)) {
-                    let operator = match op {
-                        '>' => Operator::GreaterThan,
-                        '<' => Operator::LessThan,
-                        _ => return None,
-                    };
+        let tokens = tokenize(s)?;
+        let mut parser = Parser { tokens, pos: 0, len: s.chars().count() };
+        let expr = parser.parse_expr(0)?;
 
-                    return Some(Expression::BinaryOp {
-                        op: operator,
-                        left: Box::new(left_expr),
-                        right: Box::new(right_expr),
-                    });
-                }
-            }
+        match parser.peek() {
+            Token::End => Ok(expr),
+            token => Err(SyntaxError::new(format!("Unexpected {}", token.describe()), parser.position())),
         }
-
-        None
     }
 
     /// Evaluate expression given a context
@@ -477,6 +126,12 @@ impl Expression {
                 context.get_variable(name)
             }
 
+            // In numeric position a quoted string names a variable, as XMILE and
+            // Vensim quote names containing spaces or punctuation
+            Expression::StringLiteral { literal } => {
+                context.get_variable(literal)
+            }
+
             Expression::SubscriptedVariable { name, subscripts } => {
                 context.get_subscripted_variable(name, subscripts)
             }
@@ -513,8 +168,8 @@ impl Expression {
                 })
             }
 
-            Expression::FunctionCall { name, args } => {
-                Self::evaluate_function(name, args, context)
+            Expression::FunctionCall { name, args, .. } => {
+                self.evaluate_function(name, args, context)
             }
 
             Expression::Conditional { condition, true_expr, false_expr } => {
@@ -529,7 +184,137 @@ impl Expression {
         }
     }
 
-    fn evaluate_function(name: &str, args: &[Expression], context: &mut EvaluationContext) -> Result<f64, String> {
+    /// Whether evaluating the expression changes a delay or draws random numbers
+    pub fn advances_state(&self) -> bool {
+        match self {
+            Expression::FunctionCall { name, args, .. } => {
+                let upper = name.to_uppercase();
+                DELAY_FUNCTIONS.contains(&upper.as_str()) || RANDOM_FUNCTIONS.contains(&upper.as_str())
+                    || args.iter().any(Self::advances_state)
+            }
+            Expression::BinaryOp { left, right, .. } => left.advances_state() || right.advances_state(),
+            Expression::UnaryOp { expr, .. } => expr.advances_state(),
+            Expression::Conditional { condition, true_expr, false_expr } => {
+                condition.advances_state() || true_expr.advances_state() || false_expr.advances_state()
+            }
+            _ => false,
+        }
+    }
+
+    /// Name carried by a string literal or bare identifier argument
+    pub fn name_argument(&self) -> Option<&str> {
+        match self {
+            Expression::StringLiteral { literal } => Some(literal),
+            Expression::Variable(name) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// Copy with the variables and name arguments `rename` gives a new name
+    /// replaced, and the calls of lookup tables `rename_table` gives one
+    pub fn rename_variables(
+        &self,
+        rename: &impl Fn(&str) -> Option<String>,
+        rename_table: &impl Fn(&str) -> Option<String>,
+    ) -> Expression {
+        let renamed = |name: &String| rename(name).unwrap_or_else(|| name.clone());
+        let boxed = |expr: &Expression| Box::new(expr.rename_variables(rename, rename_table));
+        match self {
+            Expression::Constant(_) => self.clone(),
+            Expression::Variable(name) => Expression::Variable(renamed(name)),
+            Expression::StringLiteral { literal } => Expression::StringLiteral { literal: renamed(literal) },
+            Expression::SubscriptedVariable { name, subscripts } => Expression::SubscriptedVariable {
+                name: renamed(name),
+                subscripts: subscripts.clone(),
+            },
+            Expression::BinaryOp { op, left, right } => Expression::BinaryOp { op: *op, left: boxed(left), right: boxed(right) },
+            Expression::UnaryOp { op, expr } => Expression::UnaryOp { op: *op, expr: boxed(expr) },
+            Expression::FunctionCall { name, args, site } => Expression::FunctionCall {
+                name: rename_table(name).unwrap_or_else(|| name.clone()),
+                args: args.iter().map(|arg| arg.rename_variables(rename, rename_table)).collect(),
+                site: *site,
+            },
+            Expression::Conditional { condition, true_expr, false_expr } => Expression::Conditional {
+                condition: boxed(condition),
+                true_expr: boxed(true_expr),
+                false_expr: boxed(false_expr),
+            },
+        }
+    }
+
+    /// Key for the delay or noise state of this call: the variable being
+    /// evaluated and the call's site number, so identical calls keep separate
+    /// state; outside a compiled variable's equation, the call's text
+    fn state_key(&self, context: &EvaluationContext) -> String {
+        if let Some(variable) = context.call_site
+            && let Expression::FunctionCall { site: Some(site), .. } = self
+        {
+            return format!("{}#{}", variable, site);
+        }
+        self.to_string()
+    }
+
+    /// Number the function calls in this expression depth first, from 0
+    pub fn number_call_sites(&mut self) {
+        fn visit(expr: &mut Expression, next: &mut usize) {
+            match expr {
+                Expression::FunctionCall { args, site, .. } => {
+                    *site = Some(*next);
+                    *next += 1;
+                    args.iter_mut().for_each(|arg| visit(arg, next));
+                }
+                Expression::BinaryOp { left, right, .. } => {
+                    visit(left, next);
+                    visit(right, next);
+                }
+                Expression::UnaryOp { expr, .. } => visit(expr, next),
+                Expression::Conditional { condition, true_expr, false_expr } => {
+                    visit(condition, next);
+                    visit(true_expr, next);
+                    visit(false_expr, next);
+                }
+                _ => {}
+            }
+        }
+        visit(self, &mut 0);
+    }
+
+    fn evaluate_function(&self, name: &str, args: &[Expression], context: &mut EvaluationContext) -> Result<f64, String> {
+        // Functions whose first argument is a name must not evaluate it as a number
+        if name.eq_ignore_ascii_case("LOOKUP") {
+            return Self::evaluate_lookup(args, context);
+        }
+        if name.eq_ignore_ascii_case("SEASONAL") {
+            return Self::evaluate_seasonal(args, context);
+        }
+        if name.eq_ignore_ascii_case("LOOKUP2D") {
+            return Self::evaluate_lookup_2d(args, context);
+        }
+        if name.eq_ignore_ascii_case("WITH_LOOKUP") && args.len() == 2 {
+            return Self::evaluate_lookup(&[args[1].clone(), args[0].clone()], context);
+        }
+        if name.eq_ignore_ascii_case("AGENT_COUNT") {
+            return Self::evaluate_agent_count(args, context);
+        }
+        if let Some(statistic) = ["AGENT_SUM", "AGENT_MEAN", "AGENT_MAX", "AGENT_MIN"].into_iter()
+            .find(|function| name.eq_ignore_ascii_case(function))
+        {
+            return Self::evaluate_agent_statistic(statistic, args, context);
+        }
+        if let Some(statistic) = ["DES_QUEUE", "DES_WAIT", "DES_BUSY", "DES_DELIVERED"].into_iter()
+            .find(|function| name.eq_ignore_ascii_case(function))
+        {
+            return Self::evaluate_des_statistic(statistic, args, context);
+        }
+
+        // Spreadsheets are read once when the model is loaded, not every step
+        if name.to_uppercase().starts_with("GET_XLS_") {
+            return Err(format!(
+                "{} must be read when the model is loaded (io::load_model or io::xlsx_reader::resolve)",
+                name.to_uppercase()
+            ));
+        }
+
         let arg_values: Result<Vec<f64>, String> = args
             .iter()
             .map(|arg| arg.evaluate(context))
@@ -538,19 +323,29 @@ impl Expression {
 
         match name.to_uppercase().as_str() {
             // Variadic functions
-            "MIN" => {
+            "MIN" | "VMIN" => {
                 if arg_values.is_empty() {
-                    return Err("MIN requires at least 1 argument".to_string());
+                    return Err(format!("{} requires at least 1 argument", name.to_uppercase()));
                 }
                 Ok(arg_values.iter().copied().fold(f64::INFINITY, f64::min))
             }
-            "MAX" => {
+            "MAX" | "VMAX" => {
                 if arg_values.is_empty() {
-                    return Err("MAX requires at least 1 argument".to_string());
+                    return Err(format!("{} requires at least 1 argument", name.to_uppercase()));
                 }
                 Ok(arg_values.iter().copied().fold(f64::NEG_INFINITY, f64::max))
             }
 
+            // Array reductions; Model::compile expands arrayed arguments into elements
+            "SUM" => Ok(arg_values.iter().sum()),
+            "PROD" => Ok(arg_values.iter().product()),
+            "MEAN" => {
+                if arg_values.is_empty() {
+                    return Err("MEAN requires at least 1 argument".to_string());
+                }
+                Ok(arg_values.iter().sum::<f64>() / arg_values.len() as f64)
+            }
+
             // Single-argument math functions
             "ABS" => {
                 if arg_values.len() != 1 {
@@ -680,6 +475,21 @@ impl Expression {
                 Ok(arg_values[0] % arg_values[1])
             }
 
+            // Reality checks: 1 while the constraint holds, 0 when it is violated
+            "ASSERT" => {
+                if arg_values.len() != 1 {
+                    return Err(format!("ASSERT expects 1 argument, got {}", arg_values.len()));
+                }
+                Ok(if arg_values[0] != 0.0 { 1.0 } else { 0.0 })
+            }
+            "IMPLIES" => {
+                // IMPLIES(condition, consequence): the consequence holds whenever the condition does
+                if arg_values.len() != 2 {
+                    return Err(format!("IMPLIES expects 2 arguments, got {}", arg_values.len()));
+                }
+                Ok(if arg_values[0] == 0.0 || arg_values[1] != 0.0 { 1.0 } else { 0.0 })
+            }
+
             // System Dynamics specific functions
             "PULSE" => {
                 // PULSE(start, width) or PULSE(start, width, repeat_interval)
@@ -765,6 +575,39 @@ impl Expression {
                 Ok(context.time)
             }
 
+            // Periodic forcing: (amplitude, period), repeating from time 0
+            "SINWAVE" | "COSWAVE" | "SAWTOOTH" => {
+                if arg_values.len() != 2 {
+                    return Err(format!("{} expects 2 arguments (amplitude, period), got {}", name, arg_values.len()));
+                }
+                let (amplitude, period) = (arg_values[0], arg_values[1]);
+                if period <= 0.0 {
+                    return Err(format!("{} period must be positive, got {}", name, period));
+                }
+                let phase = context.time / period;
+                Ok(match name.to_uppercase().as_str() {
+                    "SINWAVE" => amplitude * (2.0 * std::f64::consts::PI * phase).sin(),
+                    "COSWAVE" => amplitude * (2.0 * std::f64::consts::PI * phase).cos(),
+                    _ => amplitude * phase.rem_euclid(1.0),
+                })
+            }
+
+            // Calendar functions, reading the date of the time being evaluated
+            "MONTH" | "DAYOFYEAR" | "YEAR" => {
+                use chrono::Datelike;
+                if !arg_values.is_empty() {
+                    return Err(format!("{} expects 0 arguments, got {}", name, arg_values.len()));
+                }
+                let calendar = context.model.time.calendar.as_ref()
+                    .ok_or_else(|| format!("{}() needs a calendar in the model's time settings", name))?;
+                let date = calendar.date_at(context.time)?;
+                Ok(match name.to_uppercase().as_str() {
+                    "MONTH" => date.month() as f64,
+                    "DAYOFYEAR" => date.ordinal() as f64,
+                    _ => date.year() as f64,
+                })
+            }
+
             // Delay functions
             "DELAY1" | "SMOOTH" => {
                 // DELAY1(input, delay_time) or DELAY1(input, delay_time, initial)
@@ -779,11 +622,9 @@ impl Expression {
                     input
                 };
 
-                // Create unique key for this delay
-                let key = format!("{}_{}", name, args.iter().map(|a| format!("{}", a)).collect::<Vec<_>>().join("_"));
-
-                let delay = context.state.delays.get_or_create_exponential(&key, initial, delay_time, 1);
-                Ok(delay.get_value())
+                let key = self.state_key(context);
+                let delay = context.delays.get_or_create_exponential(&key, initial, delay_time, 1);
+                Ok(delay.record_and_get(context.time, input, delay_time))
             }
 
             "DELAY3" => {
@@ -799,48 +640,55 @@ impl Expression {
                     input
                 };
 
-                let key = format!("DELAY3_{}", args.iter().map(|a| format!("{}", a)).collect::<Vec<_>>().join("_"));
-
-                let delay = context.state.delays.get_or_create_exponential(&key, initial, delay_time, 3);
-                Ok(delay.get_value())
+                let key = self.state_key(context);
+                let delay = context.delays.get_or_create_exponential(&key, initial, delay_time, 3);
+                Ok(delay.record_and_get(context.time, input, delay_time))
             }
 
-            "DELAYP" => {
-                // DELAYP(input, delay_time, initial)
-                if arg_values.len() != 3 {
-                    return Err(format!("DELAYP expects 3 arguments, got {}", arg_values.len()));
+            "DELAYN" | "SMOOTHN" => {
+                // DELAYN(input, delay_time, n) or DELAYN(input, delay_time, n, initial):
+                // a cascade of n first-order stages, the order read when the delay starts
+                if arg_values.len() < 3 || arg_values.len() > 4 {
+                    return Err(format!("{} expects 3 or 4 arguments, got {}", name, arg_values.len()));
                 }
                 let input = arg_values[0];
                 let delay_time = arg_values[1];
-                let initial = arg_values[2];
-
-                let key = format!("DELAYP_{}", args.iter().map(|a| format!("{}", a)).collect::<Vec<_>>().join("_"));
+                let order = arg_values[2].round();
+                let max_order = crate::simulation::delay::MAX_DELAY_ORDER;
+                if !(1.0..=max_order as f64).contains(&order) {
+                    return Err(format!("{} order must be from 1 to {}, got {}", name, max_order, arg_values[2]));
+                }
+                let initial = arg_values.get(3).copied().unwrap_or(input);
 
-                let delay = context.state.delays.get_or_create_pipeline(&key, initial, delay_time);
-                Ok(delay.get_delayed_value(context.time))
+                let key = self.state_key(context);
+                let delay = context.delays.get_or_create_exponential(&key, initial, delay_time, order as usize);
+                Ok(delay.record_and_get(context.time, input, delay_time))
             }
 
-            // Lookup functions
-            "LOOKUP" => {
-                // LOOKUP(lookup_table_name, x)
-                if arg_values.len() != 2 {
-                    return Err(format!("LOOKUP expects 2 arguments, got {}", arg_values.len()));
+            "DELAYP" | "DELAY FIXED" | "DELAY_FIXED" => {
+                // DELAYP(input, delay_time, initial) / DELAY FIXED(input, delay_time, initial)
+                if arg_values.len() != 3 {
+                    return Err(format!("{} expects 3 arguments, got {}", name, arg_values.len()));
+                }
+                let input = arg_values[0];
+                let delay_time = arg_values[1];
+                let initial = arg_values[2];
+                if delay_time < 0.0 {
+                    return Err(format!("{} delay time must be non-negative", name));
                 }
 
-                // First argument should be a constant that represents the lookup table name
-                // For now, we'll use a workaround - the table name is embedded in the function call
-                // Better: LOOKUP("TableName", x) where first arg is parsed as string
-                // For implementation, we expect: LOOKUP(table_index, x) where table_index refers to a parameter
-
-                // This is a simplified implementation - in practice, you'd want to support string arguments
-                Err("LOOKUP function requires direct lookup table reference - use WITH_LOOKUP instead".to_string())
+                let key = self.state_key(context);
+                let delay = context.delays.get_or_create_pipeline(&key, initial, delay_time);
+                Ok(delay.record_and_get(context.time, input))
             }
 
+            // Lookup functions
             "WITH_LOOKUP" => {
                 // WITH_LOOKUP(x, (x1,y1), (x2,y2), ...)
                 // This is a simplified inline lookup - user provides data points directly
                 // For example: WITH_LOOKUP(TIME, 0,0, 10,100, 20,50)
-                // Pairs of values represent (x,y) points
+                // Pairs of values represent (x,y) points, interpolated linearly;
+                // WITH_LOOKUP(x, "table") reads a model table with its own settings
 
                 if arg_values.len() < 3 || arg_values.len() % 2 != 1 {
                     return Err("WITH_LOOKUP expects odd number of arguments: x, x1, y1, x2, y2, ...".to_string());
@@ -855,7 +703,7 @@ impl Expression {
 
                 // Create temporary lookup table
                 let table = crate::simulation::LookupTable::new("inline".to_string(), points)?;
-                Ok(table.lookup(x))
+                table.lookup(x)
             }
 
             // Stochastic functions
@@ -864,7 +712,7 @@ impl Expression {
                 if !arg_values.is_empty() {
                     return Err(format!("RANDOM expects 0 arguments, got {}", arg_values.len()));
                 }
-                Ok(context.state.stochastic.random())
+                Ok(context.stochastic.random())
             }
 
             "UNIFORM" => {
@@ -872,7 +720,7 @@ impl Expression {
                 if arg_values.len() != 2 {
                     return Err(format!("UNIFORM expects 2 arguments, got {}", arg_values.len()));
                 }
-                Ok(context.state.stochastic.uniform(arg_values[0], arg_values[1]))
+                Ok(context.stochastic.uniform(arg_values[0], arg_values[1]))
             }
 
             "NORMAL" => {
@@ -880,7 +728,7 @@ impl Expression {
                 if arg_values.len() != 2 {
                     return Err(format!("NORMAL expects 2 arguments, got {}", arg_values.len()));
                 }
-                context.state.stochastic.normal(arg_values[0], arg_values[1])
+                context.stochastic.normal(arg_values[0], arg_values[1])
             }
 
             "LOGNORMAL" => {
@@ -888,7 +736,7 @@ impl Expression {
                 if arg_values.len() != 2 {
                     return Err(format!("LOGNORMAL expects 2 arguments, got {}", arg_values.len()));
                 }
-                context.state.stochastic.lognormal(arg_values[0], arg_values[1])
+                context.stochastic.lognormal(arg_values[0], arg_values[1])
             }
 
             "POISSON" => {
@@ -896,58 +744,622 @@ impl Expression {
                 if arg_values.len() != 1 {
                     return Err(format!("POISSON expects 1 argument, got {}", arg_values.len()));
                 }
-                context.state.stochastic.poisson(arg_values[0])
+                context.stochastic.poisson(arg_values[0])
             }
 
-            // Agent-Based Modeling functions
-            // Note: These are simplified implementations. In practice, you'd want to
-            // support string arguments for agent type names. For now, we use parameter references.
-            "AGENT_COUNT" => {
-                // AGENT_COUNT() - total count of all agents
-                // or AGENT_COUNT(type_name) - count of specific type (not yet implemented)
-                if arg_values.is_empty() {
-                    Ok(context.state.agents.total_agent_count() as f64)
-                } else {
-                    Err("AGENT_COUNT with type parameter not yet implemented - use AGENT_COUNT()".to_string())
+            // Noise processes, which step once per time step
+            "WHITE_NOISE" => {
+                // WHITE_NOISE(mean, std_dev) or WHITE_NOISE(mean, std_dev, correlation_time)
+                if arg_values.len() < 2 || arg_values.len() > 3 {
+                    return Err(format!("WHITE_NOISE expects 2 or 3 arguments, got {}", arg_values.len()));
                 }
+                let correlation_time = arg_values.get(2).copied().unwrap_or(0.0);
+                let key = self.state_key(context);
+                context.stochastic.reverting_process(&key, context.time, None, arg_values[0], arg_values[1], correlation_time)
             }
 
-            "AGENT_SUM" => {
-                // AGENT_SUM(type_name, attribute_name)
-                // Simplified: expects numeric parameters
-                Err("AGENT_SUM requires string arguments for type and attribute names".to_string())
+            "AR1" | "OU_PROCESS" => {
+                // AR1(mean, std_dev, correlation_time, initial)
+                if arg_values.len() != 4 {
+                    return Err(format!("{} expects 4 arguments, got {}", name, arg_values.len()));
+                }
+                let key = self.state_key(context);
+                context.stochastic.reverting_process(
+                    &key, context.time, Some(arg_values[3]), arg_values[0], arg_values[1], arg_values[2],
+                )
             }
 
-            "AGENT_MEAN" => {
-                // AGENT_MEAN(type_name, attribute_name)
-                Err("AGENT_MEAN requires string arguments for type and attribute names".to_string())
+            "RANDOM_WALK" => {
+                // RANDOM_WALK(initial, std_dev)
+                if arg_values.len() != 2 {
+                    return Err(format!("RANDOM_WALK expects 2 arguments, got {}", arg_values.len()));
+                }
+                let key = self.state_key(context);
+                context.stochastic.random_walk(&key, context.time, arg_values[0], arg_values[1])
             }
 
-            "AGENT_MAX" => {
-                // AGENT_MAX(type_name, attribute_name)
-                Err("AGENT_MAX requires string arguments for type and attribute names".to_string())
+            "PINK_NOISE" => {
+                // PINK_NOISE(mean, amplitude)
+                if arg_values.len() != 2 {
+                    return Err(format!("PINK_NOISE expects 2 arguments, got {}", arg_values.len()));
+                }
+                let key = self.state_key(context);
+                Ok(context.stochastic.pink_process(&key, context.time, arg_values[0], arg_values[1]))
             }
 
-            "AGENT_MIN" => {
-                // AGENT_MIN(type_name, attribute_name)
-                Err("AGENT_MIN requires string arguments for type and attribute names".to_string())
+            _ => {
+                // Vensim-style call of a lookup table by name: table(x)
+                if let Some(table) = context.model.lookups.get(name) {
+                    if arg_values.len() != 1 {
+                        return Err(format!("Lookup table '{}' expects 1 argument, got {}", name, arg_values.len()));
+                    }
+                    return table.lookup(arg_values[0]);
+                }
+                Err(format!("Unknown function: '{}' (length: {})", name, name.len()))
             }
+        }
+    }
+
+    /// AGENT_COUNT() - all agents; AGENT_COUNT("name") - agents of a type, or
+    /// else of every type in a state; AGENT_COUNT("type", "state") - agents of
+    /// a type in a state
+    fn evaluate_agent_count(args: &[Expression], context: &mut EvaluationContext) -> Result<f64, String> {
+        let names: Vec<&str> = args.iter()
+            .map(|arg| arg.name_argument()
+                .ok_or_else(|| format!("AGENT_COUNT expects agent type or state names, got '{}'", arg)))
+            .collect::<Result<_, _>>()?;
+        let agents = context.agents;
+        let count = match names[..] {
+            [] => agents.total_agent_count(),
+            [name] if agents.agent_types.contains_key(name) => agents.count_agents(name),
+            [state] if agents.has_state(state) => agents.count_in_state(state),
+            [name] => return Err(format!("Agent type or state '{}' not found", name)),
+            [type_name, state] => agents.get_population(type_name)
+                .ok_or_else(|| format!("Agent type '{}' not found", type_name))?
+                .count_in_state(state),
+            _ => return Err(format!("AGENT_COUNT expects at most 2 arguments, got {}", args.len())),
+        };
+        Ok(count as f64)
+    }
+
+    /// AGENT_SUM/MEAN/MAX/MIN("type", "attribute") - statistic of an attribute
+    /// over the agents of a type that have it, or only those in the state given
+    /// as a third argument; 0 when there are none
+    fn evaluate_agent_statistic(function: &str, args: &[Expression], context: &mut EvaluationContext) -> Result<f64, String> {
+        let names: Vec<&str> = args.iter()
+            .map(|arg| arg.name_argument()
+                .ok_or_else(|| format!("{} expects agent type, attribute and state names, got '{}'", function, arg)))
+            .collect::<Result<_, _>>()?;
+        let (type_name, attribute, state) = match names[..] {
+            [type_name, attribute] => (type_name, attribute, None),
+            [type_name, attribute, state] => (type_name, attribute, Some(state)),
+            _ => return Err(format!("{} expects 2 or 3 arguments, got {}", function, args.len())),
+        };
+
+        let population = context.agents.get_population(type_name)
+            .ok_or_else(|| format!("Agent type '{}' not found", type_name))?;
+        let values: Vec<f64> = population.attribute_values(attribute, state).collect();
+        if values.is_empty() {
+            return Ok(0.0);
+        }
+        Ok(match function {
+            "AGENT_SUM" => values.iter().sum(),
+            "AGENT_MEAN" => values.iter().sum::<f64>() / values.len() as f64,
+            "AGENT_MAX" => values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            _ => values.iter().copied().fold(f64::INFINITY, f64::min),
+        })
+    }
 
-            _ => Err(format!("Unknown function: '{}' (length: {})", name, name.len()))
+    /// DES_QUEUE("queue") - entities waiting; DES_WAIT("queue") - mean time
+    /// waited by those that have left it; DES_BUSY("server") - entities in
+    /// service; DES_DELIVERED("sink") - entities delivered so far
+    fn evaluate_des_statistic(function: &str, args: &[Expression], context: &mut EvaluationContext) -> Result<f64, String> {
+        let [arg] = args else {
+            return Err(format!("{} expects 1 argument, got {}", function, args.len()));
+        };
+        let name = arg.name_argument()
+            .ok_or_else(|| format!("{} expects a queue, server or sink name, got '{}'", function, arg))?;
+        let des = context.des;
+        let (value, kind) = match function {
+            "DES_QUEUE" => (des.queue_length(name).map(|n| n as f64), "Queue"),
+            "DES_WAIT" => (des.mean_wait(name), "Queue"),
+            "DES_BUSY" => (des.in_service(name).map(|n| n as f64), "Server"),
+            _ => (des.delivered(name).map(|n| n as f64), "Sink"),
+        };
+        value.ok_or_else(|| format!("{} '{}' not found", kind, name))
+    }
+
+    /// LOOKUP("table_name", x) - interpolate in one of the model's lookup tables
+    fn evaluate_lookup(args: &[Expression], context: &mut EvaluationContext) -> Result<f64, String> {
+        if args.len() != 2 {
+            return Err(format!("LOOKUP expects 2 arguments, got {}", args.len()));
+        }
+
+        let table_name = args[0].name_argument()
+            .ok_or_else(|| format!("LOOKUP expects a table name as first argument, got '{}'", args[0]))?;
+        let x = args[1].evaluate(context)?;
+
+        let table = context.model.lookups.get(table_name)
+            .ok_or_else(|| format!("Lookup table '{}' not found", table_name))?;
+        table.lookup(x)
+    }
+
+    /// SEASONAL("table_name", period) - a lookup table over one period, repeated
+    /// from time 0, so the table's x values run from 0 to `period`
+    fn evaluate_seasonal(args: &[Expression], context: &mut EvaluationContext) -> Result<f64, String> {
+        if args.len() != 2 {
+            return Err(format!("SEASONAL expects 2 arguments, got {}", args.len()));
+        }
+
+        let table_name = args[0].name_argument()
+            .ok_or_else(|| format!("SEASONAL expects a table name as first argument, got '{}'", args[0]))?;
+        let period = args[1].evaluate(context)?;
+        if period <= 0.0 {
+            return Err(format!("SEASONAL period must be positive, got {}", period));
+        }
+
+        let table = context.model.lookups.get(table_name)
+            .ok_or_else(|| format!("Lookup table '{}' not found", table_name))?;
+        table.lookup(context.time.rem_euclid(period))
+    }
+
+    /// LOOKUP2D("table_name", x, y) - interpolate in one of the model's 2D lookup tables
+    fn evaluate_lookup_2d(args: &[Expression], context: &mut EvaluationContext) -> Result<f64, String> {
+        if args.len() != 3 {
+            return Err(format!("LOOKUP2D expects 3 arguments, got {}", args.len()));
+        }
+
+        let table_name = args[0].name_argument()
+            .ok_or_else(|| format!("LOOKUP2D expects a table name as first argument, got '{}'", args[0]))?;
+        let x = args[1].evaluate(context)?;
+        let y = args[2].evaluate(context)?;
+
+        let table = context.model.lookups_2d.get(table_name)
+            .ok_or_else(|| format!("2D lookup table '{}' not found", table_name))?;
+        Ok(table.lookup(x, y))
+    }
+}
+
+/// Lexical token produced by [`tokenize`]
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Str(String),
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Caret,
+    Greater,
+    Less,
+    GreaterEqual,
+    LessEqual,
+    Equal,
+    NotEqual,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Comma,
+    If,
+    Then,
+    Else,
+    End,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Number(n) => format!("number '{}'", n),
+            Token::Ident(name) => format!("identifier '{}'", name),
+            Token::Str(literal) => format!("string \"{}\"", literal),
+            Token::Plus => "'+'".to_string(),
+            Token::Minus => "'-'".to_string(),
+            Token::Star => "'*'".to_string(),
+            Token::Slash => "'/'".to_string(),
+            Token::Caret => "'^'".to_string(),
+            Token::Greater => "'>'".to_string(),
+            Token::Less => "'<'".to_string(),
+            Token::GreaterEqual => "'>='".to_string(),
+            Token::LessEqual => "'<='".to_string(),
+            Token::Equal => "'=='".to_string(),
+            Token::NotEqual => "'!='".to_string(),
+            Token::LParen => "'('".to_string(),
+            Token::RParen => "')'".to_string(),
+            Token::LBracket => "'['".to_string(),
+            Token::RBracket => "']'".to_string(),
+            Token::Comma => "','".to_string(),
+            Token::If => "keyword IF".to_string(),
+            Token::Then => "keyword THEN".to_string(),
+            Token::Else => "keyword ELSE".to_string(),
+            Token::End => "end of expression".to_string(),
+        }
+    }
+
+    /// Binary operator and its (left, right) binding power
+    fn infix(&self) -> Option<(Operator, u8, u8)> {
+        // Higher binding power binds tighter; right > left gives left-associativity
+        Some(match self {
+            Token::Greater => (Operator::GreaterThan, 1, 2),
+            Token::Less => (Operator::LessThan, 1, 2),
+            Token::GreaterEqual => (Operator::GreaterEqual, 1, 2),
+            Token::LessEqual => (Operator::LessEqual, 1, 2),
+            Token::Equal => (Operator::Equal, 1, 2),
+            Token::NotEqual => (Operator::NotEqual, 1, 2),
+            Token::Plus => (Operator::Add, 3, 4),
+            Token::Minus => (Operator::Subtract, 3, 4),
+            Token::Star => (Operator::Multiply, 5, 6),
+            Token::Slash => (Operator::Divide, 5, 6),
+            Token::Caret => (Operator::Power, 10, 9),
+            _ => return None,
+        })
+    }
+}
+
+/// Binding power of unary minus: tighter than `*` but looser than `^`, so `-2^2 = -4`
+const UNARY_BP: u8 = 7;
+
+/// Split an expression string into tokens paired with their character positions
+///
+/// Adjacent identifier words are joined with a single space so that names like
+/// `Birth Rate` remain a single variable, as in Vensim and InsightMaker models.
+fn tokenize(s: &str) -> Result<Vec<(Token, usize)>, SyntaxError> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut tokens: Vec<(Token, usize)> = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        if c.is_ascii_digit() || (c == '.' && chars.get(i + 1).is_some_and(|d| d.is_ascii_digit())) {
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            // Scientific notation: 1e-3, 2.5E+6
+            if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+                let mut j = i + 1;
+                if j < chars.len() && (chars[j] == '+' || chars[j] == '-') {
+                    j += 1;
+                }
+                if j < chars.len() && chars[j].is_ascii_digit() {
+                    i = j;
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+            }
+            let text: String = chars[start..i].iter().collect();
+            let value = text.parse::<f64>()
+                .map_err(|_| SyntaxError::new(format!("Invalid number '{}'", text), start))?;
+            tokens.push((Token::Number(value), start));
+            continue;
+        }
+
+        if c == '"' {
+            let end = chars[i + 1..].iter().position(|&ch| ch == '"')
+                .ok_or_else(|| SyntaxError::new("Unterminated string starting".to_string(), start))?;
+            let literal: String = chars[i + 1..i + 1 + end].iter().collect();
+            tokens.push((Token::Str(literal), start));
+            i += end + 2;
+            continue;
+        }
+
+        if c.is_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '.') {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect();
+            let token = match word.to_uppercase().as_str() {
+                "IF" => Token::If,
+                "THEN" => Token::Then,
+                "ELSE" => Token::Else,
+                _ => Token::Ident(word),
+            };
+
+            // Merge multi-word identifiers ("Birth Rate")
+            if let (Token::Ident(word), Some((Token::Ident(prev), _))) = (&token, tokens.last_mut())
+                && chars[start - 1].is_whitespace()
+            {
+                prev.push(' ');
+                prev.push_str(word);
+                continue;
+            }
+            tokens.push((token, start));
+            continue;
+        }
+
+        let next = chars.get(i + 1).copied();
+        let (token, width) = match (c, next) {
+            ('>', Some('=')) => (Token::GreaterEqual, 2),
+            ('<', Some('=')) => (Token::LessEqual, 2),
+            ('<', Some('>')) => (Token::NotEqual, 2),
+            ('!', Some('=')) => (Token::NotEqual, 2),
+            ('=', Some('=')) => (Token::Equal, 2),
+            ('=', _) => (Token::Equal, 1),
+            ('>', _) => (Token::Greater, 1),
+            ('<', _) => (Token::Less, 1),
+            ('+', _) => (Token::Plus, 1),
+            ('-', _) => (Token::Minus, 1),
+            ('*', _) => (Token::Star, 1),
+            ('/', _) => (Token::Slash, 1),
+            ('^', _) => (Token::Caret, 1),
+            ('(', _) => (Token::LParen, 1),
+            (')', _) => (Token::RParen, 1),
+            ('[', _) => (Token::LBracket, 1),
+            (']', _) => (Token::RBracket, 1),
+            (',', _) => (Token::Comma, 1),
+            _ => return Err(SyntaxError::new(format!("Unexpected character '{}'", c), start)),
+        };
+        tokens.push((token, start));
+        i += width;
+    }
+
+    Ok(tokens)
+}
+
+/// What went wrong parsing an expression, at which character
+struct SyntaxError {
+    message: String,
+    position: usize,
+}
+
+impl SyntaxError {
+    fn new(message: String, position: usize) -> Self {
+        Self { message, position }
+    }
+}
+
+/// Pratt parser over a token stream
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    /// Length of the source in characters, reported as the position of `End`
+    len: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        self.tokens.get(self.pos).map(|(t, _)| t).unwrap_or(&Token::End)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens.get(self.pos).map(|(_, p)| *p).unwrap_or(self.len)
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.peek().clone();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), SyntaxError> {
+        if *self.peek() == expected {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(SyntaxError::new(
+                format!("Expected {} but found {}", expected.describe(), self.peek().describe()),
+                self.position(),
+            ))
+        }
+    }
+
+    fn parse_expr(&mut self, min_bp: u8) -> Result<Expression, SyntaxError> {
+        let mut left = self.parse_prefix()?;
+
+        while let Some((op, left_bp, right_bp)) = self.peek().infix() {
+            if left_bp < min_bp {
+                break;
+            }
+            self.pos += 1;
+            let right = self.parse_expr(right_bp)?;
+            left = Expression::BinaryOp {
+                op,
+                left: Box::new(left),
+                right: Box::new(right),
+            };
+        }
+
+        Ok(left)
+    }
+
+    fn parse_prefix(&mut self) -> Result<Expression, SyntaxError> {
+        let position = self.position();
+
+        match self.next() {
+            Token::Number(value) => Ok(Expression::Constant(value)),
+            Token::Str(literal) => Ok(Expression::StringLiteral { literal }),
+            Token::Minus => {
+                let inner = self.parse_expr(UNARY_BP)?;
+                Ok(match inner {
+                    Expression::Constant(value) => Expression::Constant(-value),
+                    inner => Expression::UnaryOp {
+                        op: UnaryOperator::Negate,
+                        expr: Box::new(inner),
+                    },
+                })
+            }
+            Token::Plus => self.parse_expr(UNARY_BP),
+            Token::LParen => {
+                let inner = self.parse_expr(0)?;
+                self.expect(Token::RParen)?;
+                Ok(inner)
+            }
+            Token::If => {
+                let condition = self.parse_expr(0)?;
+                self.expect(Token::Then)?;
+                let true_expr = self.parse_expr(0)?;
+                self.expect(Token::Else)?;
+                let false_expr = self.parse_expr(0)?;
+                Ok(Expression::Conditional {
+                    condition: Box::new(condition),
+                    true_expr: Box::new(true_expr),
+                    false_expr: Box::new(false_expr),
+                })
+            }
+            Token::Ident(name) => match self.peek() {
+                Token::LParen => {
+                    self.pos += 1;
+                    let args = self.parse_args()?;
+                    Ok(Expression::FunctionCall { name, args, site: None })
+                }
+                Token::LBracket => {
+                    self.pos += 1;
+                    let subscripts = self.parse_subscripts()?;
+                    Ok(Expression::SubscriptedVariable { name, subscripts })
+                }
+                _ => Ok(Expression::Variable(name)),
+            },
+            token => Err(SyntaxError::new(format!("Unexpected {}", token.describe()), position)),
+        }
+    }
+
+    /// Parse a comma-separated argument list after the opening parenthesis
+    fn parse_args(&mut self) -> Result<Vec<Expression>, SyntaxError> {
+        let mut args = Vec::new();
+        if *self.peek() == Token::RParen {
+            self.pos += 1;
+            return Ok(args);
+        }
+
+        loop {
+            args.push(self.parse_expr(0)?);
+            match self.next() {
+                Token::Comma => continue,
+                Token::RParen => return Ok(args),
+                token => {
+                    return Err(SyntaxError::new(
+                        format!("Expected ',' or ')' but found {}", token.describe()),
+                        self.tokens.get(self.pos - 1).map(|(_, p)| *p).unwrap_or(self.len),
+                    ))
+                }
+            }
+        }
+    }
+
+    /// Parse a comma-separated subscript list after the opening bracket
+    fn parse_subscripts(&mut self) -> Result<Vec<crate::model::SubscriptRef>, SyntaxError> {
+        let mut subscripts = Vec::new();
+
+        loop {
+            let position = self.position();
+            match self.next() {
+                Token::Star => subscripts.push(crate::model::SubscriptRef::Wildcard),
+                Token::Ident(elem) => match self.peek() {
+                    // `age-1` / `age+1`: element offset within a dimension
+                    Token::Plus | Token::Minus => {
+                        let sign = if matches!(self.next(), Token::Minus) { -1 } else { 1 };
+                        let position = self.position();
+                        match self.next() {
+                            Token::Number(n) if n.fract() == 0.0 => subscripts.push(
+                                crate::model::SubscriptRef::Offset { dimension: elem, offset: sign * n as i32 },
+                            ),
+                            token => {
+                                return Err(SyntaxError::new(
+                                    format!("Expected whole-number subscript offset but found {}", token.describe()),
+                                    position,
+                                ))
+                            }
+                        }
+                    }
+                    _ => subscripts.push(crate::model::SubscriptRef::Element(elem)),
+                },
+                Token::Number(n) => subscripts.push(crate::model::SubscriptRef::Element(n.to_string())),
+                token => {
+                    return Err(SyntaxError::new(format!("Unexpected {} in subscript", token.describe()), position))
+                }
+            }
+
+            let position = self.position();
+            match self.next() {
+                Token::Comma => continue,
+                Token::RBracket => return Ok(subscripts),
+                token => {
+                    return Err(SyntaxError::new(format!("Expected ',' or ']' but found {}", token.describe()), position))
+                }
+            }
         }
     }
 }
 
 /// Context for evaluating expressions
+///
+/// Variable values are only read; the stateful functions (delays and random
+/// streams) are the only parts borrowed mutably. An integrator stage can so
+/// evaluate against a state it does not own, writing into an
+/// [`EvaluationScratch`] rather than a clone of the whole state.
+///
+/// [`EvaluationScratch`]: crate::simulation::EvaluationScratch
 pub struct EvaluationContext<'a> {
     pub model: &'a crate::model::Model,
-    pub state: &'a mut crate::simulation::SimulationState,
+    pub stocks: &'a crate::simulation::VariableValues,
+    pub flows: &'a crate::simulation::VariableValues,
+    pub auxiliaries: &'a crate::simulation::VariableValues,
+    pub agents: &'a crate::simulation::AgentManager,
+    /// Queues, servers and sinks of the discrete-event layer
+    pub des: &'a crate::simulation::DesManager,
+    /// Parameter values set by events, read in place of the model's
+    pub events: &'a crate::simulation::EventManager,
+    pub delays: &'a mut crate::simulation::DelayManager,
+    pub stochastic: &'a mut crate::simulation::StochasticManager,
     pub time: f64,
+    /// Attributes of the agent whose rules are being evaluated, which
+    /// equations read by name ahead of the model's variables
+    pub attributes: Option<&'a std::collections::HashMap<String, f64>>,
+    /// Variable whose equation is being evaluated, which with each call's
+    /// site number keys the state of its delays and noise processes
+    pub call_site: Option<&'a str>,
 }
 
 impl<'a> EvaluationContext<'a> {
+    /// Evaluate against `state`, with delays and random streams advancing in it
     pub fn new(model: &'a crate::model::Model, state: &'a mut crate::simulation::SimulationState, time: f64) -> Self {
-        Self { model, state, time }
+        Self {
+            model,
+            stocks: &state.stocks,
+            flows: &state.flows,
+            auxiliaries: &state.auxiliaries,
+            agents: &state.agents,
+            des: &state.des,
+            events: &state.events,
+            delays: &mut state.delays,
+            stochastic: &mut state.stochastic,
+            time,
+            attributes: None,
+            call_site: None,
+        }
+    }
+
+    /// Evaluate with `stocks` in place of `state`'s, taking auxiliaries, flows
+    /// and stateful functions from `scratch`; `state` itself is not changed
+    pub fn with_scratch(
+        model: &'a crate::model::Model,
+        state: &'a crate::simulation::SimulationState,
+        stocks: &'a crate::simulation::VariableValues,
+        scratch: &'a mut crate::simulation::EvaluationScratch,
+        time: f64,
+    ) -> Self {
+        Self {
+            model,
+            stocks,
+            flows: &scratch.flows,
+            auxiliaries: &scratch.auxiliaries,
+            agents: &state.agents,
+            des: &state.des,
+            events: &state.events,
+            delays: &mut scratch.delays,
+            stochastic: &mut scratch.stochastic,
+            time,
+            attributes: None,
+            call_site: None,
+        }
     }
 
     pub fn get_variable(&self, name: &str) -> Result<f64, String> {
@@ -956,7 +1368,26 @@ impl<'a> EvaluationContext<'a> {
             return Ok(self.time);
         }
 
-        self.model.get_variable(name, self.state)
+        if let Some(&value) = self.attributes.and_then(|attributes| attributes.get(name)) {
+            return Ok(value);
+        }
+
+        // Data variables are read at the time being evaluated, which inside
+        // an integrator stage is not the state's time
+        if let Some(data) = self.model.data.get(name) {
+            return data.value_at(self.time);
+        }
+
+        // Events can change a parameter partway through a run
+        if let Some(param) = self.model.parameters.get(name) {
+            return Ok(self.events.parameters.get(name).copied().unwrap_or_else(|| param.value_at(self.time)));
+        }
+
+        self.stocks.get(name)
+            .or_else(|| self.flows.get(name))
+            .or_else(|| self.auxiliaries.get(name))
+            .copied()
+            .ok_or_else(|| format!("Variable '{}' not found", name))
     }
 
     pub fn get_subscripted_variable(
@@ -964,10 +1395,9 @@ impl<'a> EvaluationContext<'a> {
         name: &str,
         subscripts: &[crate::model::SubscriptRef],
     ) -> Result<f64, String> {
-        // For now, subscripted variables are not fully supported in the basic SimulationState
-        // This is a placeholder that will need to be implemented when ArraySimulationState is integrated
-        // For basic implementation, we can try to construct the full variable name
-        // e.g., "Population[North]" becomes "Population_North"
+        // Model::compile resolves subscripts into per-element names; this
+        // handles equations evaluated against an uncompiled model, using the
+        // same flattening: "Population[North]" becomes "Population_North"
 
         if subscripts.is_empty() {
             return self.get_variable(name);
@@ -981,16 +1411,12 @@ impl<'a> EvaluationContext<'a> {
                     full_name.push('_');
                     full_name.push_str(elem);
                 }
-                crate::model::SubscriptRef::Dimension(dim) => {
-                    // Sum over all elements in dimension (not implemented yet)
+                _ => {
                     return Err(format!(
-                        "Dimension subscript '{}' not yet supported - use specific elements",
-                        dim
+                        "Subscript '{}' of '{}' needs a compiled model (Model::compile)",
+                        sub, name
                     ));
                 }
-                crate::model::SubscriptRef::Wildcard => {
-                    return Err("Wildcard subscript not yet supported".to_string());
-                }
             }
         }
 
@@ -999,22 +1425,157 @@ impl<'a> EvaluationContext<'a> {
     }
 }
 
+/// Compile an expression into a stack program over the dense indices of `symbols`
+///
+/// Only pure expressions can be compiled; stateful functions (delays, random
+/// numbers, lookups, agent queries) return an error so callers can fall back
+/// to [`Expression::evaluate`].
+pub fn compile(expr: &Expression, symbols: &SymbolTable) -> Result<Program, String> {
+    let mut compiler = Compiler { code: Vec::new(), depth: 0, max_depth: 0 };
+    compiler.emit_expr(expr, symbols)?;
+    Ok(Program { code: compiler.code, max_stack: compiler.max_depth })
+}
+
+struct Compiler {
+    code: Vec<Instruction>,
+    depth: usize,
+    max_depth: usize,
+}
+
+impl Compiler {
+    /// Push an instruction, tracking its effect on the stack depth
+    fn emit(&mut self, instruction: Instruction, pops: usize, pushes: usize) {
+        self.code.push(instruction);
+        self.depth = self.depth - pops + pushes;
+        self.max_depth = self.max_depth.max(self.depth);
+    }
+
+    fn emit_expr(&mut self, expr: &Expression, symbols: &SymbolTable) -> Result<(), String> {
+        match expr {
+            Expression::Constant(value) => self.emit(Instruction::Const(*value), 0, 1),
+
+            Expression::StringLiteral { .. } => {
+                return Err(format!("Cannot compile string literal '{}'", expr));
+            }
+
+            Expression::Variable(name) => {
+                if name.eq_ignore_ascii_case("TIME") {
+                    self.emit(Instruction::Time, 0, 1);
+                } else {
+                    let index = symbols.get(name)
+                        .ok_or_else(|| format!("Variable '{}' not found", name))?;
+                    self.emit(Instruction::Load(index), 0, 1);
+                }
+            }
+
+            Expression::SubscriptedVariable { name, subscripts } => {
+                // Same flattening as EvaluationContext::get_subscripted_variable
+                let mut full_name = name.clone();
+                for sub in subscripts {
+                    match sub {
+                        crate::model::SubscriptRef::Element(elem) => {
+                            full_name.push('_');
+                            full_name.push_str(elem);
+                        }
+                        _ => return Err(format!("Cannot compile non-element subscript in '{}'", expr)),
+                    }
+                }
+                let index = symbols.get(&full_name)
+                    .ok_or_else(|| format!("Variable '{}' not found", full_name))?;
+                self.emit(Instruction::Load(index), 0, 1);
+            }
+
+            Expression::BinaryOp { op, left, right } => {
+                self.emit_expr(left, symbols)?;
+                self.emit_expr(right, symbols)?;
+                let instruction = match op {
+                    Operator::Add => Instruction::Add,
+                    Operator::Subtract => Instruction::Sub,
+                    Operator::Multiply => Instruction::Mul,
+                    Operator::Divide => Instruction::Div,
+                    Operator::Power => Instruction::Pow,
+                    Operator::GreaterThan => Instruction::Gt,
+                    Operator::LessThan => Instruction::Lt,
+                    Operator::GreaterEqual => Instruction::Ge,
+                    Operator::LessEqual => Instruction::Le,
+                    Operator::Equal => Instruction::Eq,
+                    Operator::NotEqual => Instruction::Ne,
+                };
+                self.emit(instruction, 2, 1);
+            }
+
+            Expression::UnaryOp { op: UnaryOperator::Negate, expr } => {
+                self.emit_expr(expr, symbols)?;
+                self.emit(Instruction::Neg, 1, 1);
+            }
+
+            Expression::FunctionCall { name, args, .. } => {
+                let upper = name.to_uppercase();
+                if upper == "TIME" && args.is_empty() {
+                    self.emit(Instruction::Time, 0, 1);
+                    return Ok(());
+                }
+
+                for arg in args {
+                    self.emit_expr(arg, symbols)?;
+                }
+
+                match upper.as_str() {
+                    "MIN" | "VMIN" if !args.is_empty() => self.emit(Instruction::Min(args.len()), args.len(), 1),
+                    "MAX" | "VMAX" if !args.is_empty() => self.emit(Instruction::Max(args.len()), args.len(), 1),
+                    "SUM" => self.emit(Instruction::Sum(args.len()), args.len(), 1),
+                    "PROD" => self.emit(Instruction::Prod(args.len()), args.len(), 1),
+                    "MEAN" if !args.is_empty() => {
+                        self.emit(Instruction::Sum(args.len()), args.len(), 1);
+                        self.emit(Instruction::Const(args.len() as f64), 0, 1);
+                        self.emit(Instruction::Div, 2, 1);
+                    }
+                    _ => {
+                        let builtin = Builtin::resolve(name, args.len()).ok_or_else(|| {
+                            format!("Function '{}' with {} argument(s) cannot be compiled", name, args.len())
+                        })?;
+                        self.emit(Instruction::Call(builtin), args.len(), 1);
+                    }
+                }
+            }
+
+            Expression::Conditional { condition, true_expr, false_expr } => {
+                self.emit_expr(condition, symbols)?;
+                let jump_if_false = self.code.len();
+                self.emit(Instruction::JumpIfFalse(0), 1, 0);
+
+                self.emit_expr(true_expr, symbols)?;
+                let jump_to_end = self.code.len();
+                self.emit(Instruction::Jump(0), 0, 0);
+
+                // Only one branch runs, so the false branch starts from the pre-branch depth
+                self.depth -= 1;
+                let false_start = self.code.len();
+                self.emit_expr(false_expr, symbols)?;
+                let end = self.code.len();
+
+                self.code[jump_if_false] = Instruction::JumpIfFalse(false_start);
+                self.code[jump_to_end] = Instruction::Jump(end);
+            }
+        }
+
+        Ok(())
+    }
+}
+
 impl fmt::Display for Expression {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Expression::Constant(val) => write!(f, "{}", val),
             Expression::Variable(name) => write!(f, "{}", name),
+            Expression::StringLiteral { literal } => write!(f, "\"{}\"", literal),
             Expression::SubscriptedVariable { name, subscripts } => {
                 write!(f, "{}[", name)?;
                 for (i, sub) in subscripts.iter().enumerate() {
                     if i > 0 {
                         write!(f, ", ")?;
                     }
-                    match sub {
-                        crate::model::SubscriptRef::Element(e) => write!(f, "{}", e)?,
-                        crate::model::SubscriptRef::Dimension(d) => write!(f, "{}", d)?,
-                        crate::model::SubscriptRef::Wildcard => write!(f, "*")?,
-                    }
+                    write!(f, "{}", sub)?;
                 }
                 write!(f, "]")
             }
@@ -1039,7 +1600,7 @@ impl fmt::Display for Expression {
                     UnaryOperator::Negate => write!(f, "(-{})", expr),
                 }
             }
-            Expression::FunctionCall { name, args } => {
+            Expression::FunctionCall { name, args, .. } => {
                 write!(f, "{}(", name)?;
                 for (i, arg) in args.iter().enumerate() {
                     if i > 0 {
@@ -1095,4 +1656,256 @@ mod tests {
         let expr = Expression::parse("3 * 4").unwrap();
         assert!(matches!(expr, Expression::BinaryOp { op: Operator::Multiply, .. }));
     }
+
+    fn eval(s: &str) -> f64 {
+        let model = crate::model::Model::new("Test");
+        let mut state = crate::simulation::SimulationState::new();
+        let mut context = EvaluationContext::new(&model, &mut state, 0.0);
+        Expression::parse(s).unwrap().evaluate(&mut context).unwrap()
+    }
+
+    #[test]
+    fn test_left_associativity() {
+        assert_eq!(eval("10 - 4 + 3"), 9.0);
+        assert_eq!(eval("8 / 4 / 2"), 1.0);
+        assert_eq!(eval("2 * 3 - 4 / 2 + 1"), 5.0);
+    }
+
+    #[test]
+    fn test_power_right_associative() {
+        assert_eq!(eval("2^3^2"), 512.0);
+        assert_eq!(eval("-2^2"), -4.0);
+        assert_eq!(eval("2 * 3^2"), 18.0);
+    }
+
+    #[test]
+    fn test_unary_minus_and_parentheses() {
+        assert_eq!(eval("-(3 + 4) * 2"), -14.0);
+        assert_eq!(eval("5 * -2"), -10.0);
+        assert_eq!(eval("(1 + 2) * (3 + 4)"), 21.0);
+        assert_eq!(eval("1.5e2 + 2E-1"), 150.2);
+    }
+
+    #[test]
+    fn test_comparison_precedence() {
+        assert_eq!(eval("1 + 2 > 2"), 1.0);
+        assert_eq!(eval("IF 3 >= 2 + 1 THEN 10 ELSE 20"), 10.0);
+        assert_eq!(eval("IF 1 > 2 THEN 1 ELSE IF 2 > 1 THEN 2 ELSE 3"), 2.0);
+        assert_eq!(eval("MAX(1, MIN(5, 3)) + 1"), 4.0);
+    }
+
+    #[test]
+    fn test_parse_function_and_subscripts() {
+        let expr = Expression::parse("SMOOTH(Population[North, *], 5)").unwrap();
+        match expr {
+            Expression::FunctionCall { name, args, .. } => {
+                assert_eq!(name, "SMOOTH");
+                assert!(matches!(
+                    &args[0],
+                    Expression::SubscriptedVariable { name, subscripts }
+                        if name == "Population" && subscripts.len() == 2
+                ));
+            }
+            other => panic!("unexpected expression: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_multi_word_identifier() {
+        let expr = Expression::parse("Birth Rate * 2").unwrap();
+        match expr {
+            Expression::BinaryOp { left, .. } => {
+                assert!(matches!(*left, Expression::Variable(ref name) if name == "Birth Rate"));
+            }
+            other => panic!("unexpected expression: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_errors_report_position() {
+        let err = Expression::parse("(1 + 2").unwrap_err();
+        assert!(err.contains("position 6"), "{}", err);
+
+        let err = Expression::parse("1 + * 2").unwrap_err();
+        assert!(err.contains("position 4"), "{}", err);
+
+        let err = Expression::parse("a $ b").unwrap_err();
+        assert!(err.contains("position 2"), "{}", err);
+    }
+
+    fn compiled_matches_ast(s: &str, vars: &[(&str, f64)], time: f64) {
+        let mut model = crate::model::Model::new("Test");
+        for (name, value) in vars {
+            model.add_parameter(crate::model::Parameter::new(name, *value)).unwrap();
+        }
+        let symbols = SymbolTable::from_model(&model);
+        let mut values = vec![0.0; symbols.len()];
+        for (name, value) in vars {
+            values[symbols.get(name).unwrap()] = *value;
+        }
+
+        let expr = Expression::parse(s).unwrap();
+        let program = compile(&expr, &symbols).unwrap();
+
+        let mut state = crate::simulation::SimulationState::new();
+        let mut context = EvaluationContext::new(&model, &mut state, time);
+        let expected = expr.evaluate(&mut context).unwrap();
+
+        assert_eq!(program.execute(&values, time).unwrap(), expected, "{}", s);
+    }
+
+    #[test]
+    fn test_compiled_program_matches_ast() {
+        let vars = [("a", 3.0), ("b", -2.5), ("c", 0.5)];
+        for s in [
+            "a + b * c - 4 / a",
+            "-a^2 + 2^3^2",
+            "IF a > b THEN MAX(a, b, c) ELSE MIN(a, b)",
+            "IF c >= 1 THEN 1 ELSE IF c == 0.5 THEN 2 ELSE 3",
+            "ABS(b) + SQRT(a) * EXP(c) - LN(a) + MODULO(7, a) + POW(a, c)",
+            "STEP(a, 5) + PULSE(2, 4) + RAMP(c, 1, 8) + TIME",
+            "SUM(a, b, c) + PROD(a, c) - MEAN(a, b) * VMAX(a, b) / VMIN(a, c)",
+        ] {
+            compiled_matches_ast(s, &vars, 6.0);
+        }
+    }
+
+    #[test]
+    fn test_compile_rejects_stateful_and_unknown() {
+        let mut model = crate::model::Model::new("Test");
+        model.add_parameter(crate::model::Parameter::new("x", 1.0)).unwrap();
+        let symbols = SymbolTable::from_model(&model);
+
+        assert!(compile(&Expression::parse("SMOOTH(x, 5)").unwrap(), &symbols).is_err());
+        assert!(compile(&Expression::parse("x + missing").unwrap(), &symbols).is_err());
+
+        let err = compile(&Expression::parse("1 / (x - 1)").unwrap(), &symbols)
+            .unwrap()
+            .execute(&[1.0], 0.0)
+            .unwrap_err();
+        assert_eq!(err, "Division by zero");
+    }
+
+    #[test]
+    fn test_parse_string_literal() {
+        let expr = Expression::parse("LOOKUP(\"capacity effect\", x)").unwrap();
+        match expr {
+            Expression::FunctionCall { args, .. } => {
+                assert!(matches!(&args[0], Expression::StringLiteral { literal } if literal == "capacity effect"));
+            }
+            other => panic!("unexpected expression: {:?}", other),
+        }
+
+        let err = Expression::parse("LOOKUP(\"open, x)").unwrap_err();
+        assert!(err.contains("Unterminated string starting at position 7"), "{}", err);
+    }
+
+    #[test]
+    fn test_periodic_functions() {
+        let mut model = crate::model::Model::new("Test");
+        model.add_lookup(
+            crate::simulation::LookupTable::new("demand".to_string(), vec![(0.0, 1.0), (6.0, 3.0), (12.0, 1.0)]).unwrap()
+        ).unwrap();
+        let mut state = crate::simulation::SimulationState::new();
+        let mut context = EvaluationContext::new(&model, &mut state, 27.0);
+
+        let value = |s: &str, context: &mut EvaluationContext| Expression::parse(s).unwrap().evaluate(context);
+        assert!((value("SINWAVE(2, 12)", &mut context).unwrap() - 2.0).abs() < 1e-12);
+        assert!(value("COSWAVE(2, 12)", &mut context).unwrap().abs() < 1e-12);
+        assert_eq!(value("SAWTOOTH(10, 12)", &mut context).unwrap(), 2.5);
+        assert_eq!(value("SEASONAL(demand, 12)", &mut context).unwrap(), 2.0);
+        assert!(value("SINWAVE(1, 0)", &mut context).unwrap_err().contains("period must be positive"));
+    }
+
+    #[test]
+    fn test_delayn() {
+        let model = crate::model::Model::new("Test");
+        let mut state = crate::simulation::SimulationState::new();
+        let delay = Expression::parse("DELAYN(10, 4, 2, 0)").unwrap();
+
+        // Two stages of 2 time units each, advanced a step at a time:
+        // 10 * (1 - e^(-t/2) * (1 + t/2)) at time t
+        for t in 0..4 {
+            let t = t as f64;
+            let output = delay.evaluate(&mut EvaluationContext::new(&model, &mut state, t)).unwrap();
+            assert!((output - 10.0 * (1.0 - (-t / 2.0).exp() * (1.0 + t / 2.0))).abs() < 1e-12);
+            state.delays.advance(t + 1.0);
+        }
+
+        let err = Expression::parse("SMOOTHN(1, 4, 0)").unwrap()
+            .evaluate(&mut EvaluationContext::new(&model, &mut state, 0.0)).unwrap_err();
+        assert!(err.contains("order must be from 1"), "{}", err);
+        assert!(Expression::parse("DELAYN(1, 4, 1e9)").unwrap()
+            .evaluate(&mut EvaluationContext::new(&model, &mut state, 0.0)).is_err());
+    }
+
+    #[test]
+    fn test_calendar_functions() {
+        let mut model = crate::model::Model::new("Test");
+        let mut state = crate::simulation::SimulationState::new();
+        let month = Expression::parse("MONTH()").unwrap();
+        let err = month.evaluate(&mut EvaluationContext::new(&model, &mut state, 0.0)).unwrap_err();
+        assert!(err.contains("needs a calendar"), "{}", err);
+
+        let epoch = chrono::NaiveDate::from_ymd_opt(2023, 12, 1).unwrap();
+        model.time.calendar = Some(crate::model::Calendar::new(epoch, crate::model::CalendarUnit::Days));
+        let mut context = EvaluationContext::new(&model, &mut state, 45.5);
+        for (s, expected) in [("MONTH()", 1.0), ("DAYOFYEAR()", 15.0), ("YEAR()", 2024.0)] {
+            assert_eq!(Expression::parse(s).unwrap().evaluate(&mut context).unwrap(), expected, "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_lookup_resolves_model_table() {
+        let mut model = crate::model::Model::new("Test");
+        model.add_parameter(crate::model::Parameter::new("load", 5.0)).unwrap();
+        model.add_lookup(
+            crate::simulation::LookupTable::new("capacity_effect".to_string(), vec![(0.0, 1.0), (10.0, 0.0)]).unwrap()
+        ).unwrap();
+        let mut state = crate::simulation::SimulationState::new();
+        let mut context = EvaluationContext::new(&model, &mut state, 0.0);
+
+        for s in ["LOOKUP(\"capacity_effect\", load)", "LOOKUP(capacity_effect, load)", "capacity_effect(load)", "WITH_LOOKUP(load, capacity_effect)"] {
+            let value = Expression::parse(s).unwrap().evaluate(&mut context).unwrap();
+            assert_eq!(value, 0.5, "{}", s);
+        }
+
+        let err = Expression::parse("LOOKUP(\"missing\", load)").unwrap()
+            .evaluate(&mut context)
+            .unwrap_err();
+        assert_eq!(err, "Lookup table 'missing' not found");
+    }
+
+    #[test]
+    fn test_agent_statistics() {
+        let model = crate::model::Model::new("Test");
+        let mut state = crate::simulation::SimulationState::new();
+        let mut household = crate::simulation::AgentType::new("Household".to_string());
+        household.add_attribute("wealth".to_string(), 0.0);
+        household.state_machine = Some(crate::simulation::StateMachine::new(vec!["Poor".to_string(), "Rich".to_string()]));
+        state.agents.register_type(household);
+        state.agents.create_agents("Household", 4).unwrap();
+        let population = state.agents.get_population_mut("Household").unwrap();
+        for (id, wealth) in [(0, 10.0), (1, 30.0), (2, 50.0), (3, 110.0)] {
+            let agent = population.get_agent_mut(id).unwrap();
+            agent.set("wealth".to_string(), wealth);
+            agent.state = Some(if wealth > 40.0 { "Rich" } else { "Poor" }.to_string());
+        }
+        let mut context = EvaluationContext::new(&model, &mut state, 0.0);
+
+        let cases = [
+            ("AGENT_SUM(\"Household\", \"wealth\")", 200.0),
+            ("AGENT_MEAN(\"Household\", \"wealth\")", 50.0),
+            ("agent_max(Household, wealth)", 110.0),
+            ("AGENT_MIN(\"Household\", \"wealth\")", 10.0),
+            ("AGENT_MEAN(\"Household\", \"wealth\", \"Rich\")", 80.0),
+            ("AGENT_SUM(\"Household\", \"income\")", 0.0),
+        ];
+        for (s, expected) in cases {
+            assert_eq!(Expression::parse(s).unwrap().evaluate(&mut context).unwrap(), expected, "{}", s);
+        }
+
+        let err = Expression::parse("AGENT_SUM(\"Firm\", \"wealth\")").unwrap().evaluate(&mut context).unwrap_err();
+        assert_eq!(err, "Agent type 'Firm' not found");
+    }
 }
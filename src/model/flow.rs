@@ -9,6 +9,17 @@ pub struct Flow {
     pub equation: Expression,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub units: Option<String>,
+    /// Optional dimensions/subscripts for array variables
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dimensions: Option<Vec<String>>,
+    /// Leakage outflow of a conveyor; the equation gives the fraction of
+    /// each batch that leaks out during its transit
+    #[serde(default)]
+    pub leak: bool,
+    /// Uniflow: negative rates are clamped to zero instead of reversing the
+    /// flow. Flows are bi-flows unless this is set.
+    #[serde(default)]
+    pub non_negative: bool,
 }
 
 impl Flow {
@@ -17,6 +28,9 @@ impl Flow {
             name: name.to_string(),
             equation: Expression::parse(equation).unwrap_or(Expression::Constant(0.0)),
             units: None,
+            dimensions: None,
+            leak: false,
+            non_negative: false,
         }
     }
 
@@ -29,4 +43,24 @@ impl Flow {
         self.units = Some(units.to_string());
         self
     }
+
+    pub fn with_dimensions(mut self, dimensions: Vec<String>) -> Self {
+        self.dimensions = Some(dimensions);
+        self
+    }
+
+    pub fn with_non_negative(mut self, non_negative: bool) -> Self {
+        self.non_negative = non_negative;
+        self
+    }
+
+    /// Whether the flow can run in both directions
+    pub fn is_biflow(&self) -> bool {
+        !self.non_negative
+    }
+
+    pub fn with_leak(mut self, leak: bool) -> Self {
+        self.leak = leak;
+        self
+    }
 }
@@ -0,0 +1,191 @@
+/// Hybrid system dynamics / agent-based settings
+///
+/// A hybrid model declares agent types, how many agents of each exist at the
+/// start and how agents are coupled to the SD variables. Each step runs the
+/// phases of `pipeline` in order: `sd` integrates the stocks, `agents` passes
+/// SD values to agent attributes, creates and removes agents from flows and
+/// applies every agent's rules, `aggregate` collects agent attributes into SD
+/// values and `inject` writes those into their parameters or stocks.
+/// `agent_output` records individual agents and attribute histograms.
+
+use serde::{Deserialize, Serialize};
+use crate::simulation::{AgentSDConfig, AgentType};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HybridConfig {
+    #[serde(default)]
+    pub agents: Vec<AgentPopulationConfig>,
+    /// Coupling of each agent type with the SD variables, by type name
+    #[serde(default = "AgentSDConfig::new")]
+    pub couplings: AgentSDConfig,
+    /// Phases of each step, in the order they run
+    #[serde(default = "default_pipeline")]
+    pub pipeline: Vec<HybridPhase>,
+    /// Agent-level output of a run
+    #[serde(default)]
+    pub agent_output: Option<AgentOutputConfig>,
+}
+
+/// An agent type and the number of agents it starts with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentPopulationConfig {
+    #[serde(flatten)]
+    pub agent_type: AgentType,
+    #[serde(default)]
+    pub count: usize,
+}
+
+/// What is recorded about individual agents during a run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AgentOutputConfig {
+    /// Record every agent's state and attributes at the start and every this
+    /// many steps after; 0 records no snapshots
+    #[serde(default = "default_every")]
+    pub every: usize,
+    /// Attributes to record; every attribute when empty
+    #[serde(default)]
+    pub attributes: Vec<String>,
+    /// File snapshots are written to (.parquet, .h5 or .csv), relative to the
+    /// results; by default next to them
+    #[serde(default)]
+    pub file: Option<String>,
+    /// Distributions of attributes recorded at every step
+    #[serde(default)]
+    pub histograms: Vec<HistogramConfig>,
+}
+
+/// Distribution of one attribute over `bins` equal bins from `min` to `max`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HistogramConfig {
+    pub attribute: String,
+    /// Agents of this type only; agents of every type with the attribute otherwise
+    #[serde(default)]
+    pub agent_type: Option<String>,
+    pub min: f64,
+    pub max: f64,
+    #[serde(default = "default_bins")]
+    pub bins: usize,
+}
+
+fn default_every() -> usize {
+    1
+}
+
+fn default_bins() -> usize {
+    10
+}
+
+impl AgentOutputConfig {
+    pub fn new(every: usize) -> Self {
+        Self {
+            every,
+            attributes: Vec::new(),
+            file: None,
+            histograms: Vec::new(),
+        }
+    }
+
+    pub fn with_histogram(mut self, attribute: &str, min: f64, max: f64, bins: usize) -> Self {
+        self.histograms.push(HistogramConfig { attribute: attribute.to_string(), agent_type: None, min, max, bins });
+        self
+    }
+}
+
+/// One phase of a hybrid step
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HybridPhase {
+    /// Integrate the SD stocks over the step
+    Sd,
+    /// Update agents from the SD variables, then apply their rules
+    Agents,
+    /// Aggregate agent attributes into SD values
+    Aggregate,
+    /// Write the aggregated values into their SD parameters or stocks
+    Inject,
+}
+
+fn default_pipeline() -> Vec<HybridPhase> {
+    vec![HybridPhase::Sd, HybridPhase::Agents, HybridPhase::Aggregate, HybridPhase::Inject]
+}
+
+impl HybridConfig {
+    pub fn new() -> Self {
+        Self {
+            agents: Vec::new(),
+            couplings: AgentSDConfig::new(),
+            pipeline: default_pipeline(),
+            agent_output: None,
+        }
+    }
+
+    /// Add an agent type starting with `count` agents
+    pub fn with_agents(mut self, agent_type: AgentType, count: usize) -> Self {
+        self.agents.push(AgentPopulationConfig { agent_type, count });
+        self
+    }
+
+    pub fn with_pipeline(mut self, pipeline: Vec<HybridPhase>) -> Self {
+        self.pipeline = pipeline;
+        self
+    }
+}
+
+impl Default for HybridConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Model, Parameter};
+    use crate::simulation::{AgentRule, AttributeDistribution, SimulationConfig, SimulationEngine, UpdateOrder};
+
+    #[test]
+    fn test_declarative_agents() {
+        let yaml = r#"
+agents:
+  - name: Household
+    count: 50
+    initial_attributes: { savings: 0 }
+    distributions:
+      income: { distribution: uniform, min: 10, max: 20 }
+    update_order: random
+    rules:
+      - set_attribute: { attribute: savings, expression: "savings + income * rate" }
+      - conditional:
+          condition: "savings > 1000"
+          then_rules: [die]
+"#;
+        let hybrid: HybridConfig = serde_yaml::from_str(yaml).unwrap();
+        let household = &hybrid.agents[0].agent_type;
+        assert_eq!(household.update_order, UpdateOrder::Random);
+        assert_eq!(household.distributions["income"], AttributeDistribution::Uniform { min: 10.0, max: 20.0 });
+        assert!(matches!(&household.rules[1], AgentRule::Conditional { then_rules, else_rules, .. }
+            if matches!(then_rules[..], [AgentRule::Die]) && else_rules.is_empty()));
+
+        let mut model = Model::new("Households");
+        model.time.stop = 3.0;
+        model.time.dt = 1.0;
+        model.time.seed = Some(7);
+        model.add_parameter(Parameter::new("rate", 0.5)).unwrap();
+        model.hybrid = Some(hybrid);
+        let mut engine = SimulationEngine::new(model, SimulationConfig::default()).unwrap();
+        engine.run().unwrap();
+
+        // Every household drew its own income and saved half of it each step
+        let population = engine.current_state().agents.get_population("Household").unwrap();
+        assert_eq!(population.count_active(), 50);
+        let incomes: Vec<f64> = population.all_agents().map(|agent| agent.get("income").unwrap()).collect();
+        assert!(incomes.iter().all(|income| (10.0..=20.0).contains(income)));
+        assert!(incomes.iter().any(|income| *income != incomes[0]));
+        for agent in population.all_agents() {
+            assert!((agent.get("savings").unwrap() - 1.5 * agent.get("income").unwrap()).abs() < 1e-9);
+        }
+    }
+}
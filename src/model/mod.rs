@@ -2,22 +2,48 @@
 
 use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
+use crate::error::ModelError;
 
 pub mod stock;
 pub mod flow;
 pub mod auxiliary;
 pub mod parameter;
 pub mod expression;
+pub mod bytecode;
 pub mod dimension;
 pub mod units;
+pub mod arrays;
+pub mod event;
+pub mod constraint;
+pub mod conservation;
+pub mod hybrid;
+pub mod des;
+pub mod module;
+pub mod behavior;
+pub mod data;
+pub mod overrides;
+pub mod builder;
+pub mod calendar;
 
-pub use stock::Stock;
+pub use stock::{Stock, StockKind};
 pub use flow::Flow;
 pub use auxiliary::Auxiliary;
-pub use parameter::Parameter;
+pub use parameter::{Parameter, Schedule};
 pub use expression::Expression;
-pub use dimension::{Dimension, DimensionManager, SubscriptRef};
-pub use units::{DimensionalFormula, UnitChecker, BaseDimension};
+pub use bytecode::{Program, SymbolTable};
+pub use dimension::{Dimension, DimensionManager, DimensionMapping, SubscriptRef};
+pub use event::{Event, EventAction, EventTrigger};
+pub use constraint::{Constraint, ConstraintAction};
+pub use conservation::{ConservedGroup, Leak};
+pub use hybrid::{HybridConfig, HybridPhase, AgentPopulationConfig, AgentOutputConfig, HistogramConfig};
+pub use des::{DesConfig, GeneratorConfig, QueueConfig, QueueDiscipline, ServerConfig, SinkConfig, TimeDistribution};
+pub use behavior::{BehaviorTest, BehaviorCheck, TestOutcome};
+pub use data::{DataVariable, Interpolation, Extrapolation};
+pub use overrides::{Override, OverrideTarget};
+pub use module::Module;
+pub use builder::ModelBuilder;
+pub use calendar::{Calendar, CalendarUnit};
+pub use units::{DimensionalFormula, UnitChecker, UnitIssue, UnitInference, InferredUnits, BaseDimension};
 
 /// Time configuration for simulation
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -25,8 +51,18 @@ pub struct TimeConfig {
     pub start: f64,
     pub stop: f64,
     pub dt: f64,
+    /// Spacing of recorded output (SAVEPER); every `dt` when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub save_interval: Option<f64>,
+    /// Seed for stochastic functions; runs are reproducible when set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub units: Option<String>,
+    /// Dates that model times stand for, used for ISO dates in results and
+    /// by MONTH(), DAYOFYEAR() and YEAR()
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub calendar: Option<Calendar>,
 }
 
 impl Default for TimeConfig {
@@ -35,7 +71,10 @@ impl Default for TimeConfig {
             start: 0.0,
             stop: 100.0,
             dt: 0.25,
+            save_interval: None,
+            seed: None,
             units: None,
+            calendar: None,
         }
     }
 }
@@ -50,6 +89,13 @@ pub struct ModelMetadata {
     pub author: Option<String>,
 }
 
+/// Centre of an element on a stock-and-flow diagram
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Position {
+    pub x: f64,
+    pub y: f64,
+}
+
 /// Complete system dynamics model
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Model {
@@ -64,6 +110,47 @@ pub struct Model {
     pub dimensions: HashMap<String, Dimension>,
     #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub lookups: HashMap<String, crate::simulation::LookupTable>,
+    /// Tables of two inputs, read by LOOKUP2D
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub lookups_2d: HashMap<String, crate::simulation::LookupTable2D>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<Event>,
+    /// Reality checks made at every step of a run
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub constraints: Vec<Constraint>,
+    /// Groups of stocks whose total only changes through their boundary flows
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub conservation: Vec<ConservedGroup>,
+    /// Agents run alongside the SD model, and how the two are coupled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hybrid: Option<HybridConfig>,
+    /// Entities moved through queues and servers alongside the stocks and flows
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub des: Option<DesConfig>,
+    /// Submodels still to be flattened into the model, which loading does
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub modules: Vec<Module>,
+    /// Expected behavior, checked by `rsedsim test`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tests: Vec<BehaviorTest>,
+    /// Variables driven by external time series
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub data: HashMap<String, DataVariable>,
+    /// Diagram positions of elements, such as those read from an XMILE view
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub positions: HashMap<String, Position>,
+    /// Auxiliaries and flows in dependency order, filled in by [`Model::compile`]
+    #[serde(skip)]
+    pub evaluation_order: Option<Vec<String>>,
+    /// The evaluation order split into strata to evaluate in parallel; set by
+    /// the engine when [`SimulationConfig::threads`] asks for more than one thread
+    ///
+    /// [`SimulationConfig::threads`]: crate::simulation::SimulationConfig::threads
+    #[serde(skip)]
+    pub evaluation_strata: Option<Vec<crate::analysis::Stratum>>,
+    /// Dimensions of arrayed variables that [`Model::compile`] expanded into elements
+    #[serde(skip)]
+    pub expanded_arrays: HashMap<String, Vec<String>>,
 }
 
 impl Model {
@@ -81,62 +168,153 @@ impl Model {
             parameters: HashMap::new(),
             dimensions: HashMap::new(),
             lookups: HashMap::new(),
+            lookups_2d: HashMap::new(),
+            events: Vec::new(),
+            constraints: Vec::new(),
+            conservation: Vec::new(),
+            hybrid: None,
+            des: None,
+            modules: Vec::new(),
+            tests: Vec::new(),
+            data: HashMap::new(),
+            positions: HashMap::new(),
+            evaluation_order: None,
+            evaluation_strata: None,
+            expanded_arrays: HashMap::new(),
+        }
+    }
+
+    /// Prepare the model for simulation by expanding arrayed variables into
+    /// per-element scalars and resolving the evaluation order
+    ///
+    /// Must be called again after adding or changing auxiliaries or flows.
+    pub fn compile(&mut self) -> Result<(), ModelError> {
+        if let Some(module) = self.modules.first() {
+            return Err(ModelError::Invalid(format!(
+                "Module '{}' must be flattened when the model is loaded (io::load_model)", module.name
+            )));
+        }
+        arrays::expand(self)?;
+        for aux in self.auxiliaries.values_mut() {
+            aux.equation.number_call_sites();
         }
+        for flow in self.flows.values_mut() {
+            flow.equation.number_call_sites();
+        }
+        self.evaluation_order = Some(Self::compute_evaluation_order(self)?);
+        self.evaluation_strata = None;
+        Ok(())
     }
 
-    pub fn add_stock(&mut self, stock: Stock) -> Result<(), String> {
+    /// Topologically sort auxiliaries and flows so each is evaluated after its inputs
+    pub fn compute_evaluation_order(model: &Model) -> Result<Vec<String>, String> {
+        crate::analysis::DependencyGraph::evaluation_order(model)
+            .map(|nodes| nodes.into_iter().map(|n| n.name).collect())
+    }
+
+    /// Auxiliaries and flows in strata that can each be evaluated in parallel
+    pub fn compute_evaluation_strata(model: &Model) -> Result<Vec<crate::analysis::Stratum>, String> {
+        let order = match &model.evaluation_order {
+            Some(order) => order.clone(),
+            None => Self::compute_evaluation_order(model)?,
+        };
+        Ok(crate::analysis::DependencyGraph::evaluation_strata(model, &order))
+    }
+
+    pub fn add_stock(&mut self, stock: Stock) -> Result<(), ModelError> {
         if self.stocks.contains_key(&stock.name) {
-            return Err(format!("Stock '{}' already exists", stock.name));
+            return Err(ModelError::Duplicate { kind: "Stock", name: stock.name.clone() });
         }
         self.stocks.insert(stock.name.clone(), stock);
         Ok(())
     }
 
-    pub fn add_flow(&mut self, flow: Flow) -> Result<(), String> {
+    pub fn add_flow(&mut self, flow: Flow) -> Result<(), ModelError> {
         if self.flows.contains_key(&flow.name) {
-            return Err(format!("Flow '{}' already exists", flow.name));
+            return Err(ModelError::Duplicate { kind: "Flow", name: flow.name.clone() });
         }
         self.flows.insert(flow.name.clone(), flow);
         Ok(())
     }
 
-    pub fn add_auxiliary(&mut self, aux: Auxiliary) -> Result<(), String> {
+    pub fn add_auxiliary(&mut self, aux: Auxiliary) -> Result<(), ModelError> {
         if self.auxiliaries.contains_key(&aux.name) {
-            return Err(format!("Auxiliary '{}' already exists", aux.name));
+            return Err(ModelError::Duplicate { kind: "Auxiliary", name: aux.name.clone() });
         }
         self.auxiliaries.insert(aux.name.clone(), aux);
         Ok(())
     }
 
-    pub fn add_parameter(&mut self, param: Parameter) -> Result<(), String> {
+    pub fn add_parameter(&mut self, param: Parameter) -> Result<(), ModelError> {
         if self.parameters.contains_key(&param.name) {
-            return Err(format!("Parameter '{}' already exists", param.name));
+            return Err(ModelError::Duplicate { kind: "Parameter", name: param.name.clone() });
         }
         self.parameters.insert(param.name.clone(), param);
         Ok(())
     }
 
-    pub fn add_dimension(&mut self, dimension: Dimension) -> Result<(), String> {
+    pub fn add_dimension(&mut self, dimension: Dimension) -> Result<(), ModelError> {
         if self.dimensions.contains_key(&dimension.name) {
-            return Err(format!("Dimension '{}' already exists", dimension.name));
+            return Err(ModelError::Duplicate { kind: "Dimension", name: dimension.name.clone() });
         }
         self.dimensions.insert(dimension.name.clone(), dimension);
         Ok(())
     }
 
-    pub fn add_lookup(&mut self, lookup: crate::simulation::LookupTable) -> Result<(), String> {
-        if self.lookups.contains_key(&lookup.name) {
-            return Err(format!("Lookup table '{}' already exists", lookup.name));
+    pub fn add_lookup(&mut self, lookup: crate::simulation::LookupTable) -> Result<(), ModelError> {
+        if self.lookups.contains_key(&lookup.name) || self.lookups_2d.contains_key(&lookup.name) {
+            return Err(ModelError::Duplicate { kind: "Lookup table", name: lookup.name.clone() });
         }
         self.lookups.insert(lookup.name.clone(), lookup);
         Ok(())
     }
 
+    pub fn add_lookup_2d(&mut self, lookup: crate::simulation::LookupTable2D) -> Result<(), ModelError> {
+        if self.lookups.contains_key(&lookup.name) || self.lookups_2d.contains_key(&lookup.name) {
+            return Err(ModelError::Duplicate { kind: "Lookup table", name: lookup.name.clone() });
+        }
+        self.lookups_2d.insert(lookup.name.clone(), lookup);
+        Ok(())
+    }
+
+    pub fn add_event(&mut self, event: Event) -> Result<(), ModelError> {
+        if self.events.iter().any(|e| e.name == event.name) {
+            return Err(ModelError::Duplicate { kind: "Event", name: event.name.clone() });
+        }
+        self.events.push(event);
+        Ok(())
+    }
+
+    pub fn add_constraint(&mut self, constraint: Constraint) -> Result<(), ModelError> {
+        if self.constraints.iter().any(|c| c.name == constraint.name) {
+            return Err(ModelError::Duplicate { kind: "Constraint", name: constraint.name.clone() });
+        }
+        self.constraints.push(constraint);
+        Ok(())
+    }
+
+    pub fn add_conserved_group(&mut self, group: ConservedGroup) -> Result<(), ModelError> {
+        if self.conservation.iter().any(|g| g.name == group.name) {
+            return Err(ModelError::Duplicate { kind: "Conserved group", name: group.name.clone() });
+        }
+        self.conservation.push(group);
+        Ok(())
+    }
+
+    pub fn add_data(&mut self, data: DataVariable) -> Result<(), ModelError> {
+        if self.data.contains_key(&data.name) {
+            return Err(ModelError::Duplicate { kind: "Data variable", name: data.name.clone() });
+        }
+        self.data.insert(data.name.clone(), data);
+        Ok(())
+    }
+
     /// Get variable value (parameter or from state)
     pub fn get_variable(&self, name: &str, state: &crate::simulation::SimulationState) -> Result<f64, String> {
         // Try parameter first
         if let Some(param) = self.parameters.get(name) {
-            return Ok(param.value);
+            // Events can change a parameter partway through a run
+            return Ok(state.events.parameters.get(name).copied().unwrap_or_else(|| param.value_at(state.time)));
         }
 
         // Try stock
@@ -0,0 +1,47 @@
+/// Submodels included from other model files
+///
+/// A module brings every element of another model file into this one under
+/// its name as a namespace, so `Inventory` in a module named `factory` becomes
+/// `factory.Inventory`. Inputs replace parameters or auxiliaries of the module
+/// with equations in the including model; outputs define auxiliaries of the
+/// including model that read module variables. Modules are flattened when the
+/// model is loaded, and the including model's time settings apply.
+
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Module {
+    /// Namespace the module's elements are prefixed with
+    pub name: String,
+    /// Model file, relative to the including model
+    pub file: String,
+    /// Equations, in the including model, for parameters or auxiliaries of the module
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub inputs: BTreeMap<String, String>,
+    /// Module variables read by auxiliaries of the including model, by auxiliary name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub outputs: BTreeMap<String, String>,
+}
+
+impl Module {
+    pub fn new(name: &str, file: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            file: file.to_string(),
+            inputs: BTreeMap::new(),
+            outputs: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_input(mut self, port: &str, equation: &str) -> Self {
+        self.inputs.insert(port.to_string(), equation.to_string());
+        self
+    }
+
+    pub fn with_output(mut self, name: &str, variable: &str) -> Self {
+        self.outputs.insert(name.to_string(), variable.to_string());
+        self
+    }
+}
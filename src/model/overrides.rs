@@ -0,0 +1,251 @@
+/// Command-line style overrides of model settings
+///
+/// An override sets one value by name:
+///
+/// - `birth_rate=0.2` sets a parameter (every element, if it is arrayed)
+/// - `capacity[North]=100` sets one element of an arrayed parameter
+/// - `Population.initial=500` or `Population[North].initial=500` sets a stock's initial value
+/// - `time.stop=200` sets a time setting (`start`, `stop`, `dt`, `save_interval` or `seed`)
+
+use super::{arrays, Expression, Model};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Override {
+    pub target: OverrideTarget,
+    pub value: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OverrideTarget {
+    Parameter { name: String, subscripts: Vec<String> },
+    StockInitial { name: String, subscripts: Vec<String> },
+    Time(String),
+}
+
+const TIME_SETTINGS: [&str; 5] = ["start", "stop", "dt", "save_interval", "seed"];
+
+impl Override {
+    /// Parse one `target=value` override
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (target, value) = spec.split_once('=')
+            .ok_or_else(|| format!("Invalid override '{}' (expected target=value)", spec.trim()))?;
+        let value: f64 = value.trim().parse()
+            .map_err(|_| format!("Invalid value '{}' for '{}'", value.trim(), target.trim()))?;
+        Ok(Self { target: OverrideTarget::parse(target)?, value })
+    }
+
+    /// Parse comma-separated overrides, e.g. `"a=1,capacity[North]=100,time.stop=200"`
+    ///
+    /// Commas inside brackets separate subscripts, not overrides.
+    pub fn parse_list(specs: &str) -> Result<Vec<Self>, String> {
+        let mut overrides = Vec::new();
+        let mut depth = 0;
+        let mut start = 0;
+        for (i, c) in specs.char_indices() {
+            match c {
+                '[' => depth += 1,
+                ']' => depth -= 1,
+                ',' if depth == 0 => {
+                    overrides.push(&specs[start..i]);
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        overrides.push(&specs[start..]);
+
+        overrides.into_iter()
+            .filter(|spec| !spec.trim().is_empty())
+            .map(Self::parse)
+            .collect()
+    }
+
+    /// Apply the override to `model`, returning the value it replaced when that was a number
+    ///
+    /// Setting one element of an arrayed variable expands the model's arrays
+    /// first, as [`Model::compile`] would.
+    pub fn apply(&self, model: &mut Model) -> Result<Option<f64>, String> {
+        match &self.target {
+            OverrideTarget::Parameter { name, subscripts } => {
+                let names = element_names(model, name, subscripts, |m, n| m.parameters.contains_key(n))
+                    .ok_or_else(|| format!("Unknown parameter '{}'", self.target))?;
+                let mut previous = None;
+                for element in names {
+                    let parameter = model.parameters.get_mut(&element).expect("element exists");
+                    previous = Some(parameter.value);
+                    parameter.value = self.value;
+                    parameter.values = None;
+                    parameter.schedule = None;
+                }
+                Ok(previous)
+            }
+            OverrideTarget::StockInitial { name, subscripts } => {
+                let names = element_names(model, name, subscripts, |m, n| m.stocks.contains_key(n))
+                    .ok_or_else(|| format!("Unknown stock '{}'", self.target.variable()))?;
+                let mut previous = None;
+                for element in names {
+                    let stock = model.stocks.get_mut(&element).expect("element exists");
+                    previous = match stock.initial {
+                        Expression::Constant(value) => Some(value),
+                        _ => None,
+                    };
+                    stock.initial = Expression::Constant(self.value);
+                }
+                Ok(previous)
+            }
+            OverrideTarget::Time(setting) => {
+                let time = &mut model.time;
+                let slot = match setting.as_str() {
+                    "start" => &mut time.start,
+                    "stop" => &mut time.stop,
+                    "dt" => &mut time.dt,
+                    "save_interval" => {
+                        let previous = time.save_interval.replace(self.value);
+                        return Ok(previous);
+                    }
+                    "seed" => {
+                        if self.value < 0.0 || self.value.fract() != 0.0 {
+                            return Err(format!("time.seed must be a non-negative integer, not {}", self.value));
+                        }
+                        let previous = time.seed.replace(self.value as u64);
+                        return Ok(previous.map(|seed| seed as f64));
+                    }
+                    _ => unreachable!("checked when parsed"),
+                };
+                Ok(Some(std::mem::replace(slot, self.value)))
+            }
+        }
+    }
+}
+
+impl OverrideTarget {
+    /// Parse a target such as `capacity[North]`, `Stock.initial` or `time.stop`
+    pub fn parse(target: &str) -> Result<Self, String> {
+        let target = target.trim();
+        if let Some(setting) = target.strip_prefix("time.") {
+            return if TIME_SETTINGS.contains(&setting) {
+                Ok(OverrideTarget::Time(setting.to_string()))
+            } else {
+                Err(format!(
+                    "Unknown time setting '{}' (expected one of {})",
+                    setting, TIME_SETTINGS.join(", ")
+                ))
+            };
+        }
+
+        let (variable, initial) = match target.strip_suffix(".initial") {
+            Some(variable) => (variable, true),
+            None => (target, false),
+        };
+        let (name, subscripts) = match variable.split_once('[') {
+            Some((name, rest)) => {
+                let subscripts = rest.strip_suffix(']')
+                    .ok_or_else(|| format!("Invalid override target '{}'", target))?;
+                (name.trim(), subscripts.split(',').map(|s| s.trim().to_string()).collect())
+            }
+            None => (variable, Vec::new()),
+        };
+        if name.is_empty() || name.contains(['.', ']']) {
+            return Err(format!("Invalid override target '{}'", target));
+        }
+
+        let name = name.to_string();
+        Ok(if initial {
+            OverrideTarget::StockInitial { name, subscripts }
+        } else {
+            OverrideTarget::Parameter { name, subscripts }
+        })
+    }
+
+    /// The variable, with any subscripts, that the override sets
+    fn variable(&self) -> String {
+        match self {
+            OverrideTarget::Parameter { name, subscripts } | OverrideTarget::StockInitial { name, subscripts } => {
+                if subscripts.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{}[{}]", name, subscripts.join(","))
+                }
+            }
+            OverrideTarget::Time(setting) => format!("time.{}", setting),
+        }
+    }
+}
+
+impl std::fmt::Display for OverrideTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            OverrideTarget::StockInitial { .. } => write!(f, "{}.initial", self.variable()),
+            _ => write!(f, "{}", self.variable()),
+        }
+    }
+}
+
+/// Scalar names an override of `name[subscripts]` sets, or None if there is no such variable
+fn element_names(
+    model: &mut Model,
+    name: &str,
+    subscripts: &[String],
+    exists: fn(&Model, &str) -> bool,
+) -> Option<Vec<String>> {
+    let unexpanded = model.parameters.get(name).map(|p| p.dimensions.is_some())
+        .or_else(|| model.stocks.get(name).map(|s| s.dimensions.is_some()))
+        .unwrap_or(false);
+    if unexpanded && !subscripts.is_empty() {
+        arrays::expand(model).ok()?;
+    }
+
+    if subscripts.is_empty() {
+        if exists(model, name) {
+            return Some(vec![name.to_string()]);
+        }
+        // Every element of an already expanded array
+        let dimensions = model.expanded_arrays.get(name)?;
+        let names: Vec<String> = arrays::element_combinations(dimensions, &model.dimensions).ok()?
+            .iter()
+            .map(|elements| arrays::element_name(name, elements))
+            .collect();
+        return names.iter().all(|n| exists(model, n)).then_some(names);
+    }
+
+    let element = arrays::element_name(name, subscripts);
+    exists(model, &element).then(|| vec![element])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Dimension, Parameter, Stock};
+
+    #[test]
+    fn test_overrides() {
+        let mut model = Model::new("Regions");
+        model.add_dimension(Dimension::new("Region", vec!["North".to_string(), "South".to_string()])).unwrap();
+        let region = vec!["Region".to_string()];
+        model.add_parameter(Parameter::new("growth", 0.1)).unwrap();
+        model.add_parameter(Parameter::new("capacity", 0.0).with_dimensions(region.clone()).with_values(vec![50.0, 60.0])).unwrap();
+        model.add_stock(Stock::new("Population", "capacity[Region] / 2").with_dimensions(region)).unwrap();
+
+        let overrides = Override::parse_list("growth=0.2, capacity[North]=100, Population[South].initial=7, time.stop=200").unwrap();
+        assert_eq!(overrides[1].target.to_string(), "capacity[North]");
+        assert_eq!(overrides[2].target.to_string(), "Population[South].initial");
+
+        let previous: Vec<Option<f64>> = overrides.iter().map(|o| o.apply(&mut model).unwrap()).collect();
+        assert_eq!(previous, vec![Some(0.1), Some(50.0), None, Some(100.0)]);
+        assert_eq!(model.parameters["growth"].value, 0.2);
+        assert_eq!(model.parameters["capacity_North"].value, 100.0);
+        assert_eq!(model.parameters["capacity_South"].value, 60.0);
+        assert!(matches!(model.stocks["Population_South"].initial, Expression::Constant(v) if v == 7.0));
+        assert_eq!(model.time.stop, 200.0);
+
+        Override::parse("capacity=80").unwrap().apply(&mut model).unwrap();
+        assert_eq!(model.parameters["capacity_South"].value, 80.0);
+
+        let err = Override::parse("capacity[West]=1").unwrap().apply(&mut model).unwrap_err();
+        assert_eq!(err, "Unknown parameter 'capacity[West]'");
+        assert!(Override::parse("Inventory.initial=1").unwrap().apply(&mut model).is_err());
+        assert!(Override::parse("time.end=1").is_err());
+        assert!(Override::parse("growth").is_err());
+        assert!(Override::parse("growth=fast").is_err());
+    }
+}
@@ -1,6 +1,11 @@
 /// Parameter (constant) variable
+///
+/// A parameter with a schedule changes at planned times instead, following
+/// (time, value) breakpoints scaled by its `value`, so sensitivity and Monte
+/// Carlo runs that sample `value` scale the whole schedule.
 
 use serde::{Deserialize, Serialize};
+use super::data::{interpolate, Extrapolation, Interpolation};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Parameter {
@@ -10,6 +15,51 @@ pub struct Parameter {
     pub units: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
+    /// Optional dimensions/subscripts for array variables
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dimensions: Option<Vec<String>>,
+    /// One value per element (row-major) for arrayed parameters; `value` is
+    /// used for every element when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub values: Option<Vec<f64>>,
+    /// Smallest plausible value, tried by extreme-conditions tests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    /// Largest plausible value, tried by extreme-conditions tests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+    /// Values over time, multiplied by `value`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<Schedule>,
+}
+
+/// Planned values of a parameter as (time, value) breakpoints, holding the
+/// first value before them and the last after
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Schedule {
+    /// Breakpoints sorted by time
+    pub points: Vec<(f64, f64)>,
+    /// `linear` ramps between breakpoints; `step` holds each value until the next
+    #[serde(default)]
+    pub interpolation: Interpolation,
+}
+
+impl Schedule {
+    pub fn new(mut points: Vec<(f64, f64)>, interpolation: Interpolation) -> Result<Self, String> {
+        if points.is_empty() {
+            return Err("Schedule has no breakpoints".to_string());
+        }
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        if let Some(w) = points.windows(2).find(|w| w[0].0 == w[1].0) {
+            return Err(format!("Schedule has two values at time {}", w[0].0));
+        }
+        Ok(Self { points, interpolation })
+    }
+
+    pub fn value_at(&self, time: f64) -> f64 {
+        interpolate(&self.points, time, self.interpolation, Extrapolation::Hold).unwrap_or(0.0)
+    }
 }
 
 impl Parameter {
@@ -19,6 +69,11 @@ impl Parameter {
             value,
             units: None,
             description: None,
+            dimensions: None,
+            values: None,
+            min: None,
+            max: None,
+            schedule: None,
         }
     }
 
@@ -31,4 +86,55 @@ impl Parameter {
         self.description = Some(description.to_string());
         self
     }
+
+    pub fn with_dimensions(mut self, dimensions: Vec<String>) -> Self {
+        self.dimensions = Some(dimensions);
+        self
+    }
+
+    pub fn with_values(mut self, values: Vec<f64>) -> Self {
+        self.values = Some(values);
+        self
+    }
+
+    pub fn with_range(mut self, min: f64, max: f64) -> Self {
+        self.min = Some(min);
+        self.max = Some(max);
+        self
+    }
+
+    pub fn with_schedule(mut self, schedule: Schedule) -> Self {
+        self.schedule = Some(schedule);
+        self
+    }
+
+    /// Value at `time`: the scaled schedule when there is one, otherwise `value`
+    pub fn value_at(&self, time: f64) -> f64 {
+        match &self.schedule {
+            Some(schedule) => self.value * schedule.value_at(time),
+            None => self.value,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scheduled_parameter() {
+        let schedule = Schedule::new(vec![(10.0, 0.2), (0.0, 0.1), (20.0, 0.2)], Interpolation::Step).unwrap();
+        let tax = Parameter::new("tax_rate", 1.0).with_schedule(schedule.clone());
+        assert_eq!(tax.value_at(-5.0), 0.1);
+        assert_eq!(tax.value_at(9.9), 0.1);
+        assert_eq!(tax.value_at(10.0), 0.2);
+        assert_eq!(tax.value_at(50.0), 0.2);
+
+        // Sampling `value` scales the schedule
+        let ramp = Parameter::new("tax_rate", 2.0)
+            .with_schedule(Schedule { interpolation: Interpolation::Linear, ..schedule });
+        assert!((ramp.value_at(5.0) - 0.3).abs() < 1e-12);
+        assert_eq!(Parameter::new("constant", 3.0).value_at(5.0), 3.0);
+        assert!(Schedule::new(vec![(1.0, 1.0), (1.0, 2.0)], Interpolation::Linear).is_err());
+    }
 }
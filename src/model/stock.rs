@@ -20,6 +20,57 @@ pub struct Stock {
     /// Optional dimensions/subscripts for array variables
     #[serde(skip_serializing_if = "Option::is_none")]
     pub dimensions: Option<Vec<String>>,
+    /// How the stock moves material from its inflows to its outflows
+    #[serde(default, skip_serializing_if = "StockKind::is_reservoir")]
+    pub kind: StockKind,
+}
+
+/// Accumulation behaviour of a stock
+///
+/// Conveyor and oven parameters are evaluated once, when the simulation starts.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StockKind {
+    /// Integrates inflows minus outflows
+    #[default]
+    Reservoir,
+    /// Material leaves through the first non-leak outflow `transit_time`
+    /// after entering. Outflows marked `leak` remove a fraction of each
+    /// batch along the way, and `capacity` limits what the inflows deliver.
+    Conveyor {
+        transit_time: Expression,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        capacity: Option<Expression>,
+    },
+    /// Batch process: fills until `capacity` is reached or `fill_time` has
+    /// passed since the first arrival, holds the batch for `cook_time`, then
+    /// releases all of it through its outflow in a single step
+    Oven {
+        cook_time: Expression,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        capacity: Option<Expression>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        fill_time: Option<Expression>,
+    },
+}
+
+impl StockKind {
+    pub fn is_reservoir(&self) -> bool {
+        matches!(self, StockKind::Reservoir)
+    }
+
+    /// Every parameter expression of a conveyor or oven
+    pub fn expressions_mut(&mut self) -> Vec<&mut Expression> {
+        match self {
+            StockKind::Reservoir => Vec::new(),
+            StockKind::Conveyor { transit_time, capacity } => {
+                std::iter::once(transit_time).chain(capacity.as_mut()).collect()
+            }
+            StockKind::Oven { cook_time, capacity, fill_time } => {
+                std::iter::once(cook_time).chain(capacity.as_mut()).chain(fill_time.as_mut()).collect()
+            }
+        }
+    }
 }
 
 impl Stock {
@@ -33,6 +84,7 @@ impl Stock {
             non_negative: false,
             max_value: None,
             dimensions: None,
+            kind: StockKind::Reservoir,
         }
     }
 
@@ -70,4 +122,9 @@ impl Stock {
         self.dimensions = Some(dimensions);
         self
     }
+
+    pub fn with_kind(mut self, kind: StockKind) -> Self {
+        self.kind = kind;
+        self
+    }
 }
@@ -1,10 +1,15 @@
 /// Unit checking and dimensional analysis
 ///
-/// Provides compile-time-like checks for unit consistency in models
+/// Provides compile-time-like checks for unit consistency in models.
+/// Besides SI dimensions, units such as `people` or `widgets` are tracked as
+/// named dimensions, and calendar units (`day`, `month`, `year`, ...) all count
+/// as time.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
 use serde::{Deserialize, Serialize};
+use super::expression::{Expression, Operator};
+use super::Model;
 
 /// Base SI dimensions
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -23,6 +28,9 @@ pub enum BaseDimension {
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct DimensionalFormula {
     pub dimensions: HashMap<BaseDimension, i32>,
+    /// Non-SI units (e.g. `person`, `dollar`) as powers
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub named: BTreeMap<String, i32>,
 }
 
 impl DimensionalFormula {
@@ -30,16 +38,24 @@ impl DimensionalFormula {
     pub fn dimensionless() -> Self {
         Self {
             dimensions: HashMap::new(),
+            named: BTreeMap::new(),
         }
     }
 
+    /// Create from a named (non-SI) unit
+    pub fn named(unit: &str) -> Self {
+        let mut named = BTreeMap::new();
+        named.insert(unit.to_string(), 1);
+        Self { dimensions: HashMap::new(), named }
+    }
+
     /// Create from a single base dimension
     pub fn from_base(dim: BaseDimension, power: i32) -> Self {
         let mut dimensions = HashMap::new();
         if power != 0 {
             dimensions.insert(dim, power);
         }
-        Self { dimensions }
+        Self { dimensions, named: BTreeMap::new() }
     }
 
     /// Common unit formulas
@@ -60,7 +76,7 @@ impl DimensionalFormula {
         let mut dimensions = HashMap::new();
         dimensions.insert(BaseDimension::Length, 1);
         dimensions.insert(BaseDimension::Time, -1);
-        Self { dimensions }
+        Self { dimensions, named: BTreeMap::new() }
     }
 
     pub fn acceleration() -> Self {
@@ -68,7 +84,7 @@ impl DimensionalFormula {
         let mut dimensions = HashMap::new();
         dimensions.insert(BaseDimension::Length, 1);
         dimensions.insert(BaseDimension::Time, -2);
-        Self { dimensions }
+        Self { dimensions, named: BTreeMap::new() }
     }
 
     pub fn force() -> Self {
@@ -77,7 +93,7 @@ impl DimensionalFormula {
         dimensions.insert(BaseDimension::Mass, 1);
         dimensions.insert(BaseDimension::Length, 1);
         dimensions.insert(BaseDimension::Time, -2);
-        Self { dimensions }
+        Self { dimensions, named: BTreeMap::new() }
     }
 
     pub fn energy() -> Self {
@@ -86,7 +102,7 @@ impl DimensionalFormula {
         dimensions.insert(BaseDimension::Mass, 1);
         dimensions.insert(BaseDimension::Length, 2);
         dimensions.insert(BaseDimension::Time, -2);
-        Self { dimensions }
+        Self { dimensions, named: BTreeMap::new() }
     }
 
     pub fn power() -> Self {
@@ -95,12 +111,12 @@ impl DimensionalFormula {
         dimensions.insert(BaseDimension::Mass, 1);
         dimensions.insert(BaseDimension::Length, 2);
         dimensions.insert(BaseDimension::Time, -3);
-        Self { dimensions }
+        Self { dimensions, named: BTreeMap::new() }
     }
 
     /// Check if this is dimensionless
     pub fn is_dimensionless(&self) -> bool {
-        self.dimensions.is_empty() || self.dimensions.values().all(|&p| p == 0)
+        self.dimensions.values().all(|&p| p == 0) && self.named.values().all(|&p| p == 0)
     }
 
     /// Multiply two dimensional formulas
@@ -109,10 +125,16 @@ impl DimensionalFormula {
         for (dim, power) in &other.dimensions {
             *result.entry(*dim).or_insert(0) += power;
         }
+        let mut named = self.named.clone();
+        for (unit, power) in &other.named {
+            *named.entry(unit.clone()).or_insert(0) += power;
+        }
         // Remove zero powers
         result.retain(|_, &mut power| power != 0);
+        named.retain(|_, &mut power| power != 0);
         Self {
             dimensions: result,
+            named,
         }
     }
 
@@ -122,10 +144,16 @@ impl DimensionalFormula {
         for (dim, power) in &other.dimensions {
             *result.entry(*dim).or_insert(0) -= power;
         }
+        let mut named = self.named.clone();
+        for (unit, power) in &other.named {
+            *named.entry(unit.clone()).or_insert(0) -= power;
+        }
         // Remove zero powers
         result.retain(|_, &mut power| power != 0);
+        named.retain(|_, &mut power| power != 0);
         Self {
             dimensions: result,
+            named,
         }
     }
 
@@ -135,8 +163,14 @@ impl DimensionalFormula {
         for (dim, power) in &self.dimensions {
             result.insert(*dim, power * exponent);
         }
+        let mut named: BTreeMap<String, i32> = self.named.iter()
+            .map(|(unit, power)| (unit.clone(), power * exponent))
+            .collect();
+        result.retain(|_, &mut power| power != 0);
+        named.retain(|_, &mut power| power != 0);
         Self {
             dimensions: result,
+            named,
         }
     }
 
@@ -168,86 +202,620 @@ impl DimensionalFormula {
             _ => {}
         }
 
-        // For now, treat unrecognized units as dimensionless with a warning
-        // A full implementation would parse complex unit expressions
-        Ok(Self::dimensionless())
+        let mut parser = UnitParser { chars: s.chars().collect(), pos: 0 };
+        let formula = parser.expression()?;
+        parser.skip_whitespace();
+        if parser.pos < parser.chars.len() {
+            return Err(format!("Unexpected '{}' in units '{}'", parser.chars[parser.pos], s));
+        }
+        Ok(formula)
+    }
+
+    /// Units of a single unit name such as `people` or `year`
+    fn from_unit_name(name: &str) -> Self {
+        let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+        match name.as_str() {
+            "1" | "dmnl" | "dimensionless" | "unitless" | "fraction" => Self::dimensionless(),
+            "m" | "meter" | "meters" | "metre" | "metres" => Self::length(),
+            "kg" | "kilogram" | "kilograms" => Self::mass(),
+            "s" | "sec" | "second" | "seconds" | "minute" | "minutes" | "hour" | "hours" | "hr"
+            | "day" | "days" | "week" | "weeks" | "month" | "months" | "quarter" | "quarters"
+            | "year" | "years" | "yr" => Self::time(),
+            "n" | "newton" | "newtons" => Self::force(),
+            "j" | "joule" | "joules" => Self::energy(),
+            "w" | "watt" | "watts" => Self::power(),
+            "people" | "persons" => Self::named("person"),
+            _ => Self::named(&singular(&name)),
+        }
     }
 }
 
-impl fmt::Display for DimensionalFormula {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if self.is_dimensionless() {
-            return write!(f, "1");
-        }
-
-        let mut parts = Vec::new();
-
-        // Positive powers in numerator
-        for (dim, &power) in &self.dimensions {
-            if power > 0 {
-                let dim_str = match dim {
-                    BaseDimension::Length => "m",
-                    BaseDimension::Mass => "kg",
-                    BaseDimension::Time => "s",
-                    BaseDimension::Current => "A",
-                    BaseDimension::Temperature => "K",
-                    BaseDimension::Amount => "mol",
-                    BaseDimension::Luminosity => "cd",
-                };
-                if power == 1 {
-                    parts.push(dim_str.to_string());
-                } else {
-                    parts.push(format!("{}^{}", dim_str, power));
+/// Strip a plural ending so `widget` and `widgets` are the same unit
+fn singular(name: &str) -> String {
+    if name.len() > 4 && name.ends_with("ies") {
+        format!("{}y", &name[..name.len() - 3])
+    } else if name.len() > 3 && name.ends_with('s') && !name.ends_with("ss") {
+        name[..name.len() - 1].to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// Recursive descent parser for unit expressions like `people/(year*km^2)`
+struct UnitParser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl UnitParser {
+    fn skip_whitespace(&mut self) {
+        while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn expression(&mut self) -> Result<DimensionalFormula, String> {
+        let mut formula = self.term()?;
+        loop {
+            self.skip_whitespace();
+            match self.chars.get(self.pos) {
+                Some('*') => {
+                    self.pos += 1;
+                    formula = formula.multiply(&self.term()?);
+                }
+                Some('/') => {
+                    self.pos += 1;
+                    formula = formula.divide(&self.term()?);
                 }
+                _ => return Ok(formula),
             }
         }
+    }
 
-        let numerator = if parts.is_empty() {
-            "1".to_string()
-        } else {
-            parts.join("*")
-        };
+    fn term(&mut self) -> Result<DimensionalFormula, String> {
+        let base = self.factor()?;
+        self.skip_whitespace();
+        if self.chars.get(self.pos) != Some(&'^') {
+            return Ok(base);
+        }
+        self.pos += 1;
+        self.skip_whitespace();
 
-        // Negative powers in denominator
-        parts.clear();
-        for (dim, &power) in &self.dimensions {
-            if power < 0 {
-                let dim_str = match dim {
-                    BaseDimension::Length => "m",
-                    BaseDimension::Mass => "kg",
-                    BaseDimension::Time => "s",
-                    BaseDimension::Current => "A",
-                    BaseDimension::Temperature => "K",
-                    BaseDimension::Amount => "mol",
-                    BaseDimension::Luminosity => "cd",
-                };
-                if power == -1 {
-                    parts.push(dim_str.to_string());
-                } else {
-                    parts.push(format!("{}^{}", dim_str, -power));
-                }
+        let start = self.pos;
+        if self.chars.get(self.pos) == Some(&'-') {
+            self.pos += 1;
+        }
+        while self.chars.get(self.pos).is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        let exponent: String = self.chars[start..self.pos].iter().collect();
+        let exponent: i32 = exponent.parse()
+            .map_err(|_| format!("Expected an integer exponent, found '{}'", exponent))?;
+        Ok(base.raise_to_power(exponent))
+    }
+
+    fn factor(&mut self) -> Result<DimensionalFormula, String> {
+        self.skip_whitespace();
+        if self.chars.get(self.pos) == Some(&'(') {
+            self.pos += 1;
+            let inner = self.expression()?;
+            self.skip_whitespace();
+            if self.chars.get(self.pos) != Some(&')') {
+                return Err("Missing ')' in units".to_string());
             }
+            self.pos += 1;
+            return Ok(inner);
         }
 
-        if parts.is_empty() {
-            write!(f, "{}", numerator)
+        let start = self.pos;
+        while self.chars.get(self.pos).is_some_and(|c| !matches!(c, '*' | '/' | '^' | '(' | ')')) {
+            self.pos += 1;
+        }
+        let name: String = self.chars[start..self.pos].iter().collect();
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("Expected a unit name".to_string());
+        }
+        if name.parse::<f64>().is_ok_and(|n| n != 1.0) {
+            return Err(format!("Unexpected number '{}' in units", name));
+        }
+        Ok(DimensionalFormula::from_unit_name(name))
+    }
+}
+
+impl DimensionalFormula {
+    /// Render with `time_unit` in place of `s`, e.g. `person/year`
+    pub fn render(&self, time_unit: &str) -> String {
+        if self.is_dimensionless() {
+            return "1".to_string();
+        }
+
+        let symbol = |dim: &BaseDimension| match dim {
+            BaseDimension::Length => "m",
+            BaseDimension::Mass => "kg",
+            BaseDimension::Time => time_unit,
+            BaseDimension::Current => "A",
+            BaseDimension::Temperature => "K",
+            BaseDimension::Amount => "mol",
+            BaseDimension::Luminosity => "cd",
+        };
+        let powers: Vec<(&str, i32)> = self.dimensions.iter()
+            .map(|(dim, &power)| (symbol(dim), power))
+            .chain(self.named.iter().map(|(unit, &power)| (unit.as_str(), power)))
+            .collect();
+
+        // Positive powers in numerator, negative powers in denominator
+        let join = |sign: i32| {
+            let parts: Vec<String> = powers.iter()
+                .filter(|(_, power)| power * sign > 0)
+                .map(|(unit, power)| match power * sign {
+                    1 => unit.to_string(),
+                    p => format!("{}^{}", unit, p),
+                })
+                .collect();
+            parts.join("*")
+        };
+
+        let (numerator, denominator) = (join(1), join(-1));
+        let numerator = if numerator.is_empty() { "1".to_string() } else { numerator };
+        if denominator.is_empty() {
+            numerator
         } else {
-            write!(f, "{}/{}", numerator, parts.join("*"))
+            format!("{}/{}", numerator, denominator)
         }
     }
 }
 
+impl fmt::Display for DimensionalFormula {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.render("s"))
+    }
+}
+
+/// A unit inconsistency found in one variable's definition
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnitIssue {
+    pub variable: String,
+    pub message: String,
+}
+
+impl fmt::Display for UnitIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.variable, self.message)
+    }
+}
+
+/// Units inferred for a variable that declares none
+#[derive(Debug, Clone, PartialEq)]
+pub struct InferredUnits {
+    pub variable: String,
+    pub units: DimensionalFormula,
+    /// Variable whose definition implies the units: the variable itself when
+    /// inferred from its own equation
+    pub source: String,
+}
+
+/// Result of [`UnitChecker::infer_model`]
+#[derive(Debug, Clone)]
+pub struct UnitInference {
+    /// Sorted by variable name
+    pub inferred: Vec<InferredUnits>,
+    pub conflicts: Vec<UnitIssue>,
+    /// Name of the model's time unit, for rendering units
+    pub time_unit: String,
+}
+
+/// Equation of every stock (its initial value), flow and auxiliary
+fn equations(model: &Model) -> Vec<(&String, &Expression)> {
+    model.stocks.iter()
+        .map(|(name, s)| (name, &s.initial))
+        .chain(model.flows.iter().map(|(name, f)| (name, &f.equation)))
+        .chain(model.auxiliaries.iter().map(|(name, a)| (name, &a.equation)))
+        .collect()
+}
+
 /// Unit checker for validating model consistency
 pub struct UnitChecker {
     /// Map of variable names to their dimensional formulas
     variable_units: HashMap<String, DimensionalFormula>,
+    /// Units of `TIME` and the denominator of stock rates
+    time_units: DimensionalFormula,
+    /// Name used for the time dimension in messages
+    time_label: String,
 }
 
 impl UnitChecker {
     pub fn new() -> Self {
         Self {
             variable_units: HashMap::new(),
+            time_units: DimensionalFormula::time(),
+            time_label: "time".to_string(),
+        }
+    }
+
+    /// Check every equation of `model` against the declared units
+    ///
+    /// Variables without units take the units inferred for them (see
+    /// [`UnitChecker::infer_model`]); bare numbers and variables whose units
+    /// cannot be inferred are treated as unknown and never reported. Besides
+    /// mismatches inside equations (e.g. adding `people` to `people/year`),
+    /// each equation's result is compared with the variable's declared units
+    /// and each stock's flows must have the stock's units per time unit.
+    pub fn check_model(model: &Model) -> Vec<UnitIssue> {
+        let (mut checker, mut issues) = Self::declared(model);
+        let (inferred, conflicts) = checker.propagate(model);
+        // Inferred units disagreeing with a definition are reported as conflicts
+        let declared = |name: &str| match inferred.iter().any(|i| i.variable == name) {
+            true => None,
+            false => checker.get_units(name),
+        };
+
+        let mut equations = equations(model);
+        equations.sort_by_key(|(name, _)| *name);
+
+        for (name, equation) in equations {
+            let mut messages = Vec::new();
+            let inferred = checker.infer(equation, &mut messages);
+            if let (Some(inferred), Some(declared)) = (inferred, declared(name))
+                && !inferred.is_compatible(declared)
+            {
+                messages.push(format!(
+                    "equation has units {} but is declared as {}",
+                    checker.describe(&inferred), checker.describe(declared)
+                ));
+            }
+            issues.extend(messages.into_iter().map(|message| UnitIssue { variable: name.clone(), message }));
+        }
+
+        let mut stocks: Vec<&String> = model.stocks.keys().collect();
+        stocks.sort();
+        for name in stocks {
+            let stock = &model.stocks[name];
+            let Some(stock_units) = declared(name) else { continue };
+            let expected = stock_units.divide(&checker.time_units);
+
+            for flow in stock.inflows.iter().chain(&stock.outflows) {
+                if let Some(flow_units) = declared(flow)
+                    && !flow_units.is_compatible(&expected)
+                {
+                    issues.push(UnitIssue {
+                        variable: name.clone(),
+                        message: format!(
+                            "flow '{}' has units {} but the stock needs {}",
+                            flow, checker.describe(flow_units), checker.describe(&expected)
+                        ),
+                    });
+                }
+            }
+        }
+
+        issues.extend(conflicts);
+        issues
+    }
+
+    /// Checker with the model's time units and every declared unit registered,
+    /// and the declarations that could not be parsed
+    fn declared(model: &Model) -> (Self, Vec<UnitIssue>) {
+        let mut checker = Self::new();
+        let mut issues = Vec::new();
+
+        if let Some(units) = &model.time.units {
+            match DimensionalFormula::parse(units) {
+                Ok(formula) => checker.time_units = formula,
+                Err(e) => issues.push(UnitIssue { variable: "time".to_string(), message: e }),
+            }
+            checker.time_label = singular(&units.trim().to_lowercase());
+        }
+
+        let declared = model.stocks.iter().map(|(name, s)| (name, &s.units))
+            .chain(model.flows.iter().map(|(name, f)| (name, &f.units)))
+            .chain(model.auxiliaries.iter().map(|(name, a)| (name, &a.units)))
+            .chain(model.parameters.iter().map(|(name, p)| (name, &p.units)));
+        for (name, units) in declared {
+            let Some(units) = units else { continue };
+            match DimensionalFormula::parse(units) {
+                Ok(formula) => checker.register_variable(name.clone(), formula),
+                Err(e) => issues.push(UnitIssue { variable: name.clone(), message: e }),
+            }
+        }
+
+        (checker, issues)
+    }
+
+    /// Infer units for the variables that declare none
+    ///
+    /// Known units are propagated through the equations in both directions
+    /// until nothing more can be inferred: a variable takes the units of its
+    /// equation, operands take the units their equation needs (both sides of
+    /// `+`, `orders / delivery_delay` for a rate in `widgets/week` gives
+    /// `delivery_delay` the unit `week` if `orders` is in `widgets`), and a
+    /// stock and its flows differ by a time unit. A variable's own equation
+    /// takes precedence over its stock or flows, which take precedence over
+    /// the equations it appears in. Where the same variable would get
+    /// different units from different places, the first is kept and the
+    /// others are reported as conflicts.
+    pub fn infer_model(model: &Model) -> UnitInference {
+        let (mut checker, _) = Self::declared(model);
+        let (inferred, conflicts) = checker.propagate(model);
+        UnitInference { inferred, conflicts, time_unit: checker.time_label }
+    }
+
+    /// Register units inferred for undeclared variables until none are left
+    /// to infer, returning them with the conflicts found on the way
+    fn propagate(&mut self, model: &Model) -> (Vec<InferredUnits>, Vec<UnitIssue>) {
+        let mut equations = equations(model);
+        equations.sort_by_key(|(name, _)| *name);
+        let mut stocks: Vec<&String> = model.stocks.keys().collect();
+        stocks.sort();
+        let is_variable = |name: &str| model.stocks.contains_key(name) || model.flows.contains_key(name)
+            || model.auxiliaries.contains_key(name) || model.parameters.contains_key(name);
+
+        let mut inferred: Vec<InferredUnits> = Vec::new();
+        let mut conflicts: Vec<UnitIssue> = Vec::new();
+        loop {
+            // (variable, units, the variable whose definition implies them) from,
+            // in order of precedence, equations, stock-flow links and operands
+            let mut candidates: [Vec<(String, DimensionalFormula, &String)>; 3] = Default::default();
+            for (name, equation) in &equations {
+                let forward = self.infer(equation, &mut Vec::new());
+                let expected = self.get_units(name).cloned().or(forward.clone());
+                if let Some(units) = forward {
+                    candidates[0].push(((*name).clone(), units, name));
+                }
+                let mut found = Vec::new();
+                self.constrain(equation, expected.as_ref(), &mut found);
+                candidates[2].extend(found.into_iter().map(|(variable, units)| (variable, units, *name)));
+            }
+            for &name in &stocks {
+                let stock = &model.stocks[name];
+                let flows = stock.inflows.iter().chain(&stock.outflows);
+                match self.get_units(name) {
+                    Some(units) => {
+                        let rate = units.divide(&self.time_units);
+                        candidates[1].extend(flows.map(|flow| (flow.clone(), rate.clone(), name)));
+                    }
+                    None => candidates[1].extend(flows.filter_map(|flow| {
+                        Some((name.clone(), self.get_units(flow)?.multiply(&self.time_units), flow))
+                    })),
+                }
+            }
+
+            // One variable per pass, so each is inferred from the strongest evidence available
+            let mut changed = false;
+            for (variable, units, source) in candidates.into_iter().flatten() {
+                if !is_variable(&variable) {
+                    continue;
+                }
+                match self.get_units(&variable) {
+                    None if changed => {}
+                    None => {
+                        self.register_variable(variable.clone(), units.clone());
+                        inferred.push(InferredUnits { variable, units, source: source.clone() });
+                        changed = true;
+                    }
+                    Some(known) if !known.is_compatible(&units) => {
+                        // Declared units are checked against every equation by `check_model`
+                        let Some(first) = inferred.iter().find(|i| i.variable == variable) else { continue };
+                        let message = format!(
+                            "inferred as {} from '{}' but as {} from '{}'",
+                            self.describe(&first.units), first.source, self.describe(&units), source
+                        );
+                        if !conflicts.iter().any(|c| c.variable == variable && c.message == message) {
+                            conflicts.push(UnitIssue { variable, message });
+                        }
+                    }
+                    Some(_) => {}
+                }
+            }
+            if !changed {
+                break;
+            }
         }
+
+        inferred.sort_by(|a, b| a.variable.cmp(&b.variable));
+        (inferred, conflicts)
+    }
+
+    /// Find the units that undeclared variables in `expr` need for it to have
+    /// the units `expected` (or, when unknown, to be consistent in itself)
+    fn constrain(
+        &self,
+        expr: &Expression,
+        expected: Option<&DimensionalFormula>,
+        found: &mut Vec<(String, DimensionalFormula)>,
+    ) {
+        let infer = |expr: &Expression| self.infer(expr, &mut Vec::new());
+        match expr {
+            Expression::Constant(_) | Expression::StringLiteral { .. } => {}
+            Expression::Variable(name) | Expression::SubscriptedVariable { name, .. } => {
+                if let Some(expected) = expected
+                    && self.get_units(name).is_none()
+                    && !name.eq_ignore_ascii_case("time")
+                {
+                    found.push((name.clone(), expected.clone()));
+                }
+            }
+            Expression::UnaryOp { expr, .. } => self.constrain(expr, expected, found),
+            Expression::BinaryOp { op, left, right } => {
+                let (left_units, right_units) = (infer(left), infer(right));
+                let (left_expected, right_expected) = match op {
+                    Operator::Add | Operator::Subtract => {
+                        let units = expected.cloned().or(left_units).or(right_units);
+                        (units.clone(), units)
+                    }
+                    Operator::Multiply => (
+                        expected.zip(right_units.as_ref()).map(|(e, r)| e.divide(r)),
+                        expected.zip(left_units.as_ref()).map(|(e, l)| e.divide(l)),
+                    ),
+                    Operator::Divide => (
+                        expected.zip(right_units.as_ref()).map(|(e, r)| e.multiply(r)),
+                        match (left_units, expected) {
+                            (Some(l), Some(e)) => Some(l.divide(e)),
+                            (None, Some(e)) if matches!(**left, Expression::Constant(_)) => {
+                                Some(DimensionalFormula::dimensionless().divide(e))
+                            }
+                            _ => None,
+                        },
+                    ),
+                    Operator::Power => (None, None),
+                    Operator::GreaterThan | Operator::LessThan | Operator::GreaterEqual
+                    | Operator::LessEqual | Operator::Equal | Operator::NotEqual => (right_units, left_units),
+                };
+                self.constrain(left, left_expected.as_ref(), found);
+                self.constrain(right, right_expected.as_ref(), found);
+            }
+            Expression::Conditional { condition, true_expr, false_expr } => {
+                self.constrain(condition, None, found);
+                let units = expected.cloned().or_else(|| infer(true_expr)).or_else(|| infer(false_expr));
+                self.constrain(true_expr, units.as_ref(), found);
+                self.constrain(false_expr, units.as_ref(), found);
+            }
+            Expression::FunctionCall { name, args, .. } => {
+                let time = Some(self.time_units.clone());
+                let expected_args: Vec<Option<DimensionalFormula>> = match name.to_uppercase().as_str() {
+                    "MIN" | "MAX" | "VMIN" | "VMAX" | "SUM" | "MEAN" => {
+                        let units = expected.cloned().or_else(|| args.iter().find_map(infer));
+                        vec![units; args.len()]
+                    }
+                    "ABS" | "FLOOR" | "CEIL" | "ROUND" | "MODULO" | "MOD" => vec![expected.cloned()],
+                    // (input, delay time, initial value)
+                    "DELAY1" | "SMOOTH" | "DELAY3" | "DELAYP" | "DELAY_FIXED" => {
+                        vec![expected.cloned(), time, expected.cloned()]
+                    }
+                    // (input, delay time, order, initial value)
+                    "DELAYN" | "SMOOTHN" => {
+                        vec![expected.cloned(), time, Some(DimensionalFormula::dimensionless()), expected.cloned()]
+                    }
+                    "STEP" | "SINWAVE" | "COSWAVE" | "SAWTOOTH" => vec![expected.cloned(), time],
+                    "SEASONAL" => vec![None, time],
+                    // (mean, std dev, correlation time, initial value)
+                    "WHITE_NOISE" | "AR1" | "OU_PROCESS" => {
+                        vec![expected.cloned(), expected.cloned(), time, expected.cloned()]
+                    }
+                    "PINK_NOISE" => vec![expected.cloned(), expected.cloned()],
+                    "RANDOM_WALK" => vec![expected.cloned()],
+                    "EXP" | "LN" | "LOG" | "LOG10" | "SIN" | "COS" | "TAN" | "ASIN" | "ACOS"
+                    | "ATAN" => vec![Some(DimensionalFormula::dimensionless())],
+                    _ => Vec::new(),
+                };
+                for (i, arg) in args.iter().enumerate() {
+                    self.constrain(arg, expected_args.get(i).cloned().flatten().as_ref(), found);
+                }
+            }
+        }
+    }
+
+    /// Infer the units of `expr`, collecting mismatches into `issues`
+    ///
+    /// Returns `None` when the units are unknown (bare numbers, undeclared
+    /// variables, lookups); unknown operands are compatible with anything.
+    pub fn infer(&self, expr: &Expression, issues: &mut Vec<String>) -> Option<DimensionalFormula> {
+        match expr {
+            Expression::Constant(_) | Expression::StringLiteral { .. } => None,
+            Expression::Variable(name) => match self.get_units(name) {
+                Some(units) => Some(units.clone()),
+                None if name.eq_ignore_ascii_case("time") => Some(self.time_units.clone()),
+                None => None,
+            },
+            Expression::SubscriptedVariable { name, .. } => self.get_units(name).cloned(),
+            Expression::UnaryOp { expr, .. } => self.infer(expr, issues),
+            Expression::BinaryOp { op, left, right } => {
+                let left_units = self.infer(left, issues);
+                let right_units = self.infer(right, issues);
+                match op {
+                    Operator::Add => self.same_units(left_units, right_units, "+", issues),
+                    Operator::Subtract => self.same_units(left_units, right_units, "-", issues),
+                    Operator::Multiply => Some(left_units?.multiply(&right_units?)),
+                    Operator::Divide => match (left_units, right_units) {
+                        (Some(l), Some(r)) => Some(l.divide(&r)),
+                        // `1 / delay_time` style rates
+                        (None, Some(r)) if matches!(**left, Expression::Constant(_)) => {
+                            Some(DimensionalFormula::dimensionless().divide(&r))
+                        }
+                        _ => None,
+                    },
+                    Operator::Power => match (left_units, right.as_ref()) {
+                        (Some(base), Expression::Constant(exponent)) if exponent.fract() == 0.0 => {
+                            Some(base.raise_to_power(*exponent as i32))
+                        }
+                        (Some(base), _) if base.is_dimensionless() => Some(base),
+                        _ => None,
+                    },
+                    Operator::GreaterThan | Operator::LessThan | Operator::GreaterEqual
+                    | Operator::LessEqual | Operator::Equal | Operator::NotEqual => {
+                        self.same_units(left_units, right_units, "comparison", issues);
+                        Some(DimensionalFormula::dimensionless())
+                    }
+                }
+            }
+            Expression::Conditional { condition, true_expr, false_expr } => {
+                self.infer(condition, issues);
+                let true_units = self.infer(true_expr, issues);
+                let false_units = self.infer(false_expr, issues);
+                self.same_units(true_units, false_units, "IF THEN ELSE branches", issues)
+            }
+            Expression::FunctionCall { name, args, .. } => {
+                let units: Vec<Option<DimensionalFormula>> = args.iter()
+                    .map(|arg| self.infer(arg, issues))
+                    .collect();
+                let first = units.first().cloned().flatten();
+
+                match name.to_uppercase().as_str() {
+                    "TIME" => Some(self.time_units.clone()),
+                    "MIN" | "MAX" | "VMIN" | "VMAX" | "SUM" | "MEAN" => units.into_iter()
+                        .reduce(|a, b| self.same_units(a, b, name, issues))
+                        .flatten(),
+                    "PROD" => units.into_iter()
+                        .reduce(|a, b| Some(a?.multiply(&b?)))
+                        .flatten(),
+                    "ABS" | "FLOOR" | "CEIL" | "ROUND" | "DELAY1" | "SMOOTH" | "DELAY3" | "DELAYN"
+                    | "SMOOTHN" | "DELAYP" | "DELAY_FIXED" | "STEP" | "PULSE" | "MODULO" | "MOD" | "SINWAVE"
+                    | "COSWAVE" | "SAWTOOTH" | "WHITE_NOISE" | "PINK_NOISE" | "RANDOM_WALK" | "AR1"
+                    | "OU_PROCESS" => first,
+                    "RAMP" => Some(first?.multiply(&self.time_units)),
+                    "SQRT" => first.and_then(|units| {
+                        let halve = |power: &i32| (power % 2 == 0).then_some(power / 2);
+                        Some(DimensionalFormula {
+                            dimensions: units.dimensions.iter()
+                                .map(|(dim, power)| halve(power).map(|p| (*dim, p)))
+                                .collect::<Option<_>>()?,
+                            named: units.named.iter()
+                                .map(|(unit, power)| halve(power).map(|p| (unit.clone(), p)))
+                                .collect::<Option<_>>()?,
+                        })
+                    }),
+                    "EXP" | "LN" | "LOG" | "LOG10" | "SIN" | "COS" | "TAN" | "ASIN" | "ACOS"
+                    | "ATAN" | "ASSERT" | "IMPLIES" => Some(DimensionalFormula::dimensionless()),
+                    _ => None,
+                }
+            }
+        }
+    }
+
+    /// Units of an addition-like combination, recording a mismatch if any
+    fn same_units(
+        &self,
+        left: Option<DimensionalFormula>,
+        right: Option<DimensionalFormula>,
+        context: &str,
+        issues: &mut Vec<String>,
+    ) -> Option<DimensionalFormula> {
+        match (left, right) {
+            (Some(l), Some(r)) => {
+                if !l.is_compatible(&r) {
+                    issues.push(format!(
+                        "unit mismatch in {}: {} vs {}",
+                        context, self.describe(&l), self.describe(&r)
+                    ));
+                }
+                Some(l)
+            }
+            (l, r) => l.or(r),
+        }
+    }
+
+    fn describe(&self, formula: &DimensionalFormula) -> String {
+        formula.render(&self.time_label)
     }
 
     /// Register a variable's units
@@ -307,6 +875,7 @@ impl Default for UnitChecker {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::model::{Auxiliary, Flow, Parameter, Stock};
 
     #[test]
     fn test_dimensional_formula() {
@@ -363,4 +932,90 @@ mod tests {
         let force_str = DimensionalFormula::force().to_string();
         assert!(force_str == "kg*m/s^2" || force_str == "m*kg/s^2", "Got: {}", force_str);
     }
+
+    #[test]
+    fn test_parse_compound_units() {
+        let rate = DimensionalFormula::parse("People/Year").unwrap();
+        assert_eq!(rate, DimensionalFormula::named("person").divide(&DimensionalFormula::time()));
+        assert_eq!(DimensionalFormula::parse("widgets / (week * km^2)").unwrap().render("week"), "widget/week*km^2");
+        assert!(DimensionalFormula::parse("Dmnl").unwrap().is_dimensionless());
+        assert!(DimensionalFormula::parse("people/(year").is_err());
+    }
+
+    #[test]
+    fn test_check_model_reports_mismatches() {
+        let mut model = Model::new("Population");
+        model.time.units = Some("years".to_string());
+
+        let mut population = Stock::new("Population", "100");
+        population.units = Some("people".to_string());
+        population.inflows.push("births".to_string());
+        population.outflows.push("deaths".to_string());
+        model.add_stock(population).unwrap();
+
+        let mut rate = Parameter::new("birth_rate", 0.03);
+        rate.units = Some("1/year".to_string());
+        model.add_parameter(rate).unwrap();
+        let mut lifetime = Parameter::new("lifetime", 70.0);
+        lifetime.units = Some("years".to_string());
+        model.add_parameter(lifetime).unwrap();
+
+        let mut births = Flow::new("births", "Population * birth_rate");
+        births.units = Some("people/year".to_string());
+        model.add_flow(births).unwrap();
+        // Missing the division by lifetime: people, not people/year
+        let mut deaths = Flow::new("deaths", "Population + lifetime");
+        deaths.units = Some("people/year".to_string());
+        model.add_flow(deaths).unwrap();
+        let mut doubling = Auxiliary::new("doubling", "LN(2) / birth_rate");
+        doubling.units = Some("years".to_string());
+        model.add_auxiliary(doubling).unwrap();
+
+        let issues = UnitChecker::check_model(&model);
+        let messages: Vec<String> = issues.iter().map(|i| i.to_string()).collect();
+        assert_eq!(messages, vec![
+            "deaths: unit mismatch in +: person vs year",
+            "deaths: equation has units person but is declared as person/year",
+        ]);
+    }
+
+    #[test]
+    fn test_infer_model() {
+        let mut model = Model::new("Population");
+        model.time.units = Some("years".to_string());
+        let mut population = Stock::new("Population", "100");
+        population.units = Some("people".to_string());
+        population.inflows.push("births".to_string());
+        population.outflows.push("deaths".to_string());
+        model.add_stock(population).unwrap();
+        model.add_parameter(Parameter::new("birth_delay", 30.0)).unwrap();
+        model.add_parameter(Parameter::new("death_fraction", 0.01)).unwrap();
+        model.add_flow(Flow::new("births", "Population / birth_delay")).unwrap();
+        model.add_flow(Flow::new("deaths", "Population * death_fraction")).unwrap();
+        model.add_auxiliary(Auxiliary::new("lifetime", "1 / death_fraction")).unwrap();
+        // Adds years to people
+        model.add_auxiliary(Auxiliary::new("crowding", "Population + lifetime")).unwrap();
+
+        let inference = UnitChecker::infer_model(&model);
+        let inferred: Vec<String> = inference.inferred.iter()
+            .map(|i| format!("{} {} {}", i.variable, i.units.render(&inference.time_unit), i.source))
+            .collect();
+        assert_eq!(inferred, vec![
+            "birth_delay year births",
+            "births person/year Population",
+            "crowding person crowding",
+            "death_fraction 1/year deaths",
+            "deaths person/year Population",
+            "lifetime person crowding",
+        ]);
+        let conflicts: Vec<String> = inference.conflicts.iter().map(|c| c.to_string()).collect();
+        assert_eq!(conflicts, vec![
+            "death_fraction: inferred as 1/year from 'deaths' but as 1/person from 'lifetime'",
+            "lifetime: inferred as person from 'crowding' but as year from 'lifetime'",
+        ]);
+
+        // Reported as conflicts rather than again as mismatches in equations
+        let issues: Vec<String> = UnitChecker::check_model(&model).iter().map(|i| i.to_string()).collect();
+        assert_eq!(issues, conflicts);
+    }
 }
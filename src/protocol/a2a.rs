@@ -14,6 +14,12 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, Mutex};
 
 /// A2A Protocol Version
 pub const A2A_VERSION: &str = "0.1.0";
@@ -140,7 +146,7 @@ pub enum A2aPayload {
 }
 
 /// Agent information for discovery
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct AgentInfo {
     pub id: AgentId,
     pub agent_type: String,
@@ -198,6 +204,47 @@ pub enum SimControlCommand {
     BarrierReady {
         barrier_id: String,
     },
+
+    /// All required agents are ready; proceed past the barrier
+    BarrierRelease {
+        barrier_id: String,
+    },
+
+    /// The barrier deadline passed before the listed agents were ready
+    BarrierTimeout {
+        barrier_id: String,
+        missing: Vec<AgentId>,
+    },
+}
+
+impl DiscoveryQuery {
+    /// Whether `agent` satisfies every criterion set on this query
+    ///
+    /// Capabilities must all be present; attributes must all be present with
+    /// equal values. Unset criteria match anything.
+    pub fn matches(&self, agent: &AgentInfo) -> bool {
+        if let Some(agent_type) = &self.agent_type
+            && *agent_type != agent.agent_type
+        {
+            return false;
+        }
+        if let Some(namespace) = &self.namespace
+            && *namespace != agent.id.namespace
+        {
+            return false;
+        }
+        if let Some(capabilities) = &self.capabilities
+            && !capabilities.iter().all(|c| agent.capabilities.contains(c))
+        {
+            return false;
+        }
+        if let Some(attributes) = &self.attributes
+            && !attributes.iter().all(|(key, value)| agent.attributes.get(key) == Some(value))
+        {
+            return false;
+        }
+        true
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -225,9 +272,27 @@ pub struct A2aNode {
     /// Local agent ID
     agent_id: AgentId,
 
-    /// Agent registry (for directory nodes)
+    /// Agent registry (authoritative on directory nodes, a cache elsewhere)
     registry: HashMap<AgentId, AgentInfo>,
 
+    /// Whether this node answers discovery queries and propagates registrations
+    is_directory: bool,
+
+    /// Directory node used for registration and discovery
+    directory: Option<AgentId>,
+
+    /// How long `discover` waits for a directory reply
+    discovery_timeout: Duration,
+
+    /// Agents that reported ready, per barrier (coordinator side)
+    barrier_ready: HashMap<String, std::collections::HashSet<AgentId>>,
+
+    /// Released (Ok) or timed-out (Err with stragglers) barriers not yet waited on
+    barrier_outcomes: HashMap<String, Result<(), Vec<AgentId>>>,
+
+    /// Deadline for barrier coordination and waits
+    barrier_timeout: Duration,
+
     /// Topic subscriptions
     subscriptions: HashMap<String, Vec<AgentId>>,
 
@@ -244,6 +309,12 @@ impl A2aNode {
         Self {
             agent_id,
             registry: HashMap::new(),
+            is_directory: false,
+            directory: None,
+            discovery_timeout: Duration::from_secs(5),
+            barrier_ready: HashMap::new(),
+            barrier_outcomes: HashMap::new(),
+            barrier_timeout: Duration::from_secs(30),
             subscriptions: HashMap::new(),
             transport: None,
             handlers: HashMap::new(),
@@ -256,6 +327,35 @@ impl A2aNode {
         self
     }
 
+    /// Run this node as a directory
+    pub fn as_directory(mut self) -> Self {
+        self.is_directory = true;
+        self
+    }
+
+    /// Use `directory` for registration and discovery
+    pub fn with_directory(mut self, directory: AgentId) -> Self {
+        self.directory = Some(directory);
+        self
+    }
+
+    /// Set how long `discover` waits for a directory reply
+    pub fn with_discovery_timeout(mut self, timeout: Duration) -> Self {
+        self.discovery_timeout = timeout;
+        self
+    }
+
+    /// Set the deadline for barrier coordination and waits
+    pub fn with_barrier_timeout(mut self, timeout: Duration) -> Self {
+        self.barrier_timeout = timeout;
+        self
+    }
+
+    /// Agents currently known to this node
+    pub fn registry(&self) -> &HashMap<AgentId, AgentInfo> {
+        &self.registry
+    }
+
     /// Register message handler
     pub fn register_handler<F>(&mut self, message_type: &str, handler: F)
     where
@@ -300,10 +400,125 @@ impl A2aNode {
         }
     }
 
+    /// Announce `info` to the directory, or to all peers when no directory is set
+    pub async fn register(&mut self, info: AgentInfo) -> Result<(), A2aError> {
+        self.registry.insert(info.id.clone(), info.clone());
+        let payload = A2aPayload::Register { agent_info: info };
+        match self.directory.clone() {
+            Some(directory) => self.send(directory, payload).await,
+            None => self.broadcast(payload).await,
+        }
+    }
+
+    /// Withdraw this node's registration
+    pub async fn unregister(&mut self) -> Result<(), A2aError> {
+        self.registry.remove(&self.agent_id);
+        match self.directory.clone() {
+            Some(directory) => self.send(directory, A2aPayload::Unregister).await,
+            None => self.broadcast(A2aPayload::Unregister).await,
+        }
+    }
+
     /// Discover agents
-    pub async fn discover(&self, query: DiscoveryQuery) -> Result<Vec<AgentInfo>, A2aError> {
-        // TODO: Implement discovery logic
-        Ok(vec![])
+    ///
+    /// Directory nodes, and nodes without a directory, answer from their own
+    /// registry. Other nodes ask their directory and wait for its reply,
+    /// processing any other messages that arrive meanwhile.
+    pub async fn discover(&mut self, query: DiscoveryQuery) -> Result<Vec<AgentInfo>, A2aError> {
+        let directory = match &self.directory {
+            Some(directory) if !self.is_directory => directory.clone(),
+            _ => return Ok(self.find(&query)),
+        };
+
+        self.send(directory.clone(), A2aPayload::Discover { query }).await?;
+
+        let timeout = self.discovery_timeout;
+        let reply = async {
+            loop {
+                let message = self.poll().await?;
+                if message.from == directory
+                    && let A2aPayload::DiscoveryResult { agents } = message.payload
+                {
+                    return Ok(agents);
+                }
+            }
+        };
+        tokio::time::timeout(timeout, reply).await
+            .map_err(|_| A2aError::TimeoutError)?
+    }
+
+    /// Registered agents matching `query`, ordered by id
+    fn find(&self, query: &DiscoveryQuery) -> Vec<AgentInfo> {
+        let mut agents: Vec<AgentInfo> = self.registry.values()
+            .filter(|agent| query.matches(agent))
+            .cloned()
+            .collect();
+        agents.sort_by_key(|agent| agent.id.to_string());
+        agents
+    }
+
+    /// Coordinate a barrier: announce it, wait for every required agent to
+    /// report ready, then release it
+    ///
+    /// If the deadline passes first, participants are told which agents were
+    /// missing and `BarrierTimeout` is returned.
+    pub async fn coordinate_barrier(&mut self, barrier_id: &str, required_agents: Vec<AgentId>) -> Result<(), A2aError> {
+        self.broadcast(A2aPayload::SimControl {
+            command: SimControlCommand::Barrier {
+                barrier_id: barrier_id.to_string(),
+                required_agents: required_agents.clone(),
+            },
+        }).await?;
+
+        let deadline = tokio::time::Instant::now() + self.barrier_timeout;
+        loop {
+            let ready = self.barrier_ready.get(barrier_id);
+            let missing: Vec<AgentId> = required_agents.iter()
+                .filter(|agent| !ready.is_some_and(|ready| ready.contains(agent)))
+                .cloned()
+                .collect();
+
+            if missing.is_empty() {
+                break;
+            }
+
+            if let Ok(received) = tokio::time::timeout_at(deadline, self.poll()).await {
+                received?;
+            } else {
+                self.barrier_ready.remove(barrier_id);
+                self.broadcast(A2aPayload::SimControl {
+                    command: SimControlCommand::BarrierTimeout {
+                        barrier_id: barrier_id.to_string(),
+                        missing: missing.clone(),
+                    },
+                }).await?;
+                return Err(A2aError::BarrierTimeout(barrier_id.to_string(), missing));
+            }
+        }
+
+        self.barrier_ready.remove(barrier_id);
+        self.broadcast(A2aPayload::SimControl {
+            command: SimControlCommand::BarrierRelease { barrier_id: barrier_id.to_string() },
+        }).await
+    }
+
+    /// Report ready at a barrier and wait for the coordinator to release it
+    pub async fn barrier_wait(&mut self, coordinator: AgentId, barrier_id: &str) -> Result<(), A2aError> {
+        self.send(coordinator, A2aPayload::SimControl {
+            command: SimControlCommand::BarrierReady { barrier_id: barrier_id.to_string() },
+        }).await?;
+
+        let deadline = tokio::time::Instant::now() + self.barrier_timeout;
+        loop {
+            match self.barrier_outcomes.remove(barrier_id) {
+                Some(Ok(())) => return Ok(()),
+                Some(Err(missing)) => return Err(A2aError::BarrierTimeout(barrier_id.to_string(), missing)),
+                None => {}
+            }
+
+            tokio::time::timeout_at(deadline, self.poll()).await
+                .map_err(|_| A2aError::TimeoutError)??;
+        }
     }
 
     /// Subscribe to topic
@@ -319,13 +534,27 @@ impl A2aNode {
         self.broadcast(payload).await
     }
 
+    /// Receive and process the next message from the transport, returning it
+    pub async fn poll(&mut self) -> Result<A2aMessage, A2aError> {
+        let message = match &self.transport {
+            Some(transport) => transport.receive().await?,
+            None => return Err(A2aError::NoTransport),
+        };
+        self.process_message(message.clone()).await?;
+        Ok(message)
+    }
+
     /// Process incoming message
     pub async fn process_message(&mut self, message: A2aMessage) -> Result<(), A2aError> {
         // Call registered handlers
         let msg_type = match &message.payload {
             A2aPayload::Register { .. } => "register",
+            A2aPayload::Unregister => "unregister",
+            A2aPayload::Discover { .. } => "discover",
+            A2aPayload::DiscoveryResult { .. } => "discovery_result",
             A2aPayload::DirectMessage { .. } => "direct_message",
             A2aPayload::Publish { .. } => "publish",
+            A2aPayload::SimControl { .. } => "sim_control",
             _ => "unknown",
         };
 
@@ -334,13 +563,42 @@ impl A2aNode {
         }
 
         // Built-in message handling
-        match message.payload {
+        match &message.payload {
             A2aPayload::Register { agent_info } => {
-                self.registry.insert(agent_info.id.clone(), agent_info);
+                let previous = self.registry.insert(agent_info.id.clone(), agent_info.clone());
+                // Only forward changes, so directories forwarding to each other settle
+                if self.is_directory && previous.as_ref() != Some(agent_info) {
+                    self.propagate(message).await?;
+                }
             }
+            A2aPayload::Unregister => {
+                let removed = self.registry.remove(&message.from).is_some();
+                if self.is_directory && removed {
+                    self.propagate(message).await?;
+                }
+            }
+            A2aPayload::Discover { query } if self.is_directory => {
+                let agents = self.find(query);
+                self.send(message.from.clone(), A2aPayload::DiscoveryResult { agents }).await?;
+            }
+            A2aPayload::SimControl { command } => match command {
+                SimControlCommand::BarrierReady { barrier_id } => {
+                    self.barrier_ready
+                        .entry(barrier_id.clone())
+                        .or_default()
+                        .insert(message.from.clone());
+                }
+                SimControlCommand::BarrierRelease { barrier_id } => {
+                    self.barrier_outcomes.insert(barrier_id.clone(), Ok(()));
+                }
+                SimControlCommand::BarrierTimeout { barrier_id, missing } => {
+                    self.barrier_outcomes.insert(barrier_id.clone(), Err(missing.clone()));
+                }
+                _ => {}
+            },
             A2aPayload::Subscribe { topic } => {
                 self.subscriptions
-                    .entry(topic)
+                    .entry(topic.clone())
                     .or_insert_with(Vec::new)
                     .push(message.from);
             }
@@ -350,21 +608,31 @@ impl A2aNode {
         Ok(())
     }
 
+    /// Forward a registry change to all peers, keeping the original sender
+    async fn propagate(&self, mut message: A2aMessage) -> Result<(), A2aError> {
+        match message.ttl {
+            Some(0) => return Ok(()),
+            Some(ttl) => message.ttl = Some(ttl - 1),
+            None => {}
+        }
+        message.to = None;
+
+        match &self.transport {
+            Some(transport) => transport.broadcast(message).await,
+            None => Ok(()),
+        }
+    }
+
     /// Start message processing loop
+    ///
+    /// Returns once the transport is closed.
     pub async fn run(&mut self) -> Result<(), A2aError> {
-        // TODO: Implement main event loop
         loop {
-            if let Some(transport) = &self.transport {
-                match transport.receive().await {
-                    Ok(message) => {
-                        self.process_message(message).await?;
-                    }
-                    Err(e) => {
-                        eprintln!("Error receiving message: {}", e);
-                    }
-                }
-            } else {
-                return Err(A2aError::NoTransport);
+            match self.poll().await {
+                Ok(_) => {}
+                Err(A2aError::Closed) => return Ok(()),
+                Err(A2aError::NoTransport) => return Err(A2aError::NoTransport),
+                Err(e) => eprintln!("Error receiving message: {}", e),
             }
         }
     }
@@ -430,26 +698,321 @@ impl A2aTransport for UdpTransport {
     }
 }
 
+/// Largest accepted frame (16 MiB); larger length prefixes are treated as corrupt
+pub const MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+/// Write one length-prefixed JSON frame (u32 big-endian byte length, then the JSON body)
+pub async fn write_frame<W>(writer: &mut W, message: &A2aMessage) -> Result<(), A2aError>
+where
+    W: AsyncWrite + Unpin,
+{
+    let body = serde_json::to_vec(message)
+        .map_err(|e| A2aError::SerializationError(e.to_string()))?;
+    if body.len() > MAX_FRAME_SIZE {
+        return Err(A2aError::InvalidMessage(format!("Frame of {} bytes exceeds limit", body.len())));
+    }
+
+    let mut frame = Vec::with_capacity(4 + body.len());
+    frame.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&body);
+    writer.write_all(&frame).await
+        .map_err(|e| A2aError::TransportError(e.to_string()))?;
+    writer.flush().await
+        .map_err(|e| A2aError::TransportError(e.to_string()))
+}
+
+/// Read one length-prefixed JSON frame; `Ok(None)` on a clean end of stream
+pub async fn read_frame<R>(reader: &mut R) -> Result<Option<A2aMessage>, A2aError>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut prefix = [0u8; 4];
+    match reader.read_exact(&mut prefix).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(A2aError::TransportError(e.to_string())),
+    }
+
+    let length = u32::from_be_bytes(prefix) as usize;
+    if length > MAX_FRAME_SIZE {
+        return Err(A2aError::InvalidMessage(format!("Frame of {} bytes exceeds limit", length)));
+    }
+
+    let mut body = vec![0u8; length];
+    reader.read_exact(&mut body).await
+        .map_err(|e| A2aError::TransportError(e.to_string()))?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| A2aError::SerializationError(e.to_string()))
+}
+
+/// TCP transport with length-prefixed JSON framing
+///
+/// Incoming connections are accepted in the background and their frames queued
+/// for `receive`. Outgoing connections are opened lazily per peer and
+/// re-established, with exponential backoff, when a connect or write fails.
+/// Each peer's connection has its own lock, so a slow peer holds up only
+/// messages to itself.
+pub struct TcpTransport {
+    local_addr: SocketAddr,
+    /// Accepts incoming connections until the transport is dropped
+    listener: tokio::task::JoinHandle<()>,
+    /// Known peers and their open outgoing connection, if any
+    peers: Mutex<HashMap<SocketAddr, Arc<Mutex<Option<OwnedWriteHalf>>>>>,
+    /// Peer address for directed messages to each agent
+    routes: Mutex<HashMap<AgentId, SocketAddr>>,
+    inbox: Mutex<mpsc::UnboundedReceiver<A2aMessage>>,
+    max_retries: u32,
+    retry_delay: Duration,
+}
+
+impl TcpTransport {
+    /// Bind a listener on `addr` (port 0 picks an ephemeral port)
+    pub async fn bind(addr: &str) -> Result<Self, A2aError> {
+        let listener = TcpListener::bind(addr).await
+            .map_err(|e| A2aError::TransportError(format!("Failed to bind {}: {}", addr, e)))?;
+        let local_addr = listener.local_addr()
+            .map_err(|e| A2aError::TransportError(e.to_string()))?;
+        let (sender, inbox) = mpsc::unbounded_channel();
+
+        let listener = tokio::spawn(async move {
+            loop {
+                // Failures such as running out of file descriptors pass, so keep accepting
+                let stream = match listener.accept().await {
+                    Ok((stream, _)) => stream,
+                    Err(e) => {
+                        log::warn!("A2A accept failed: {}", e);
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        continue;
+                    }
+                };
+                let sender = sender.clone();
+                tokio::spawn(async move {
+                    let (mut reader, _) = stream.into_split();
+                    loop {
+                        match read_frame(&mut reader).await {
+                            Ok(Some(message)) => {
+                                if sender.send(message).is_err() {
+                                    break;
+                                }
+                            }
+                            Ok(None) => break,
+                            Err(e) => {
+                                log::warn!("A2A connection dropped: {}", e);
+                                break;
+                            }
+                        }
+                    }
+                });
+            }
+        });
+
+        Ok(Self {
+            local_addr,
+            listener,
+            peers: Mutex::new(HashMap::new()),
+            routes: Mutex::new(HashMap::new()),
+            inbox: Mutex::new(inbox),
+            max_retries: 3,
+            retry_delay: Duration::from_millis(100),
+        })
+    }
+
+    /// Set reconnect attempts per send and the initial backoff delay
+    pub fn with_retry(mut self, max_retries: u32, retry_delay: Duration) -> Self {
+        self.max_retries = max_retries;
+        self.retry_delay = retry_delay;
+        self
+    }
+
+    /// Address the listener is bound to
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Add a peer that receives broadcasts
+    pub async fn add_peer(&self, addr: SocketAddr) {
+        self.peers.lock().await.entry(addr).or_default();
+    }
+
+    /// Route directed messages for `agent` to `addr`, adding it as a peer
+    pub async fn add_route(&self, agent: AgentId, addr: SocketAddr) {
+        self.add_peer(addr).await;
+        self.routes.lock().await.insert(agent, addr);
+    }
+
+    /// Send to one peer, retrying with backoff
+    async fn send_to(&self, addr: SocketAddr, message: &A2aMessage) -> Result<(), A2aError> {
+        let mut delay = self.retry_delay;
+        let mut attempt = 0;
+
+        loop {
+            match self.try_send(addr, message).await {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt >= self.max_retries => return Err(e),
+                Err(_) => {
+                    attempt += 1;
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                }
+            }
+        }
+    }
+
+    /// Write on the cached connection, connecting first if needed
+    ///
+    /// A failed write drops the connection so the next attempt reconnects.
+    async fn try_send(&self, addr: SocketAddr, message: &A2aMessage) -> Result<(), A2aError> {
+        let connection = self.peers.lock().await.entry(addr).or_default().clone();
+        let mut connection = connection.lock().await;
+
+        let mut writer = match connection.take() {
+            Some(writer) => writer,
+            None => {
+                let stream = TcpStream::connect(addr).await
+                    .map_err(|e| A2aError::TransportError(format!("Failed to connect to {}: {}", addr, e)))?;
+                let _ = stream.set_nodelay(true);
+                stream.into_split().1
+            }
+        };
+
+        write_frame(&mut writer, message).await?;
+        *connection = Some(writer);
+        Ok(())
+    }
+}
+
+impl Drop for TcpTransport {
+    fn drop(&mut self) {
+        self.listener.abort();
+    }
+}
+
+#[async_trait::async_trait]
+impl A2aTransport for TcpTransport {
+    async fn send(&self, message: A2aMessage) -> Result<(), A2aError> {
+        let Some(to) = &message.to else {
+            return self.broadcast(message).await;
+        };
+        let addr = self.routes.lock().await.get(to).copied()
+            .ok_or_else(|| A2aError::NotFound(format!("No route to agent {}", to.to_string())))?;
+        self.send_to(addr, &message).await
+    }
+
+    async fn receive(&self) -> Result<A2aMessage, A2aError> {
+        self.inbox.lock().await.recv().await.ok_or(A2aError::Closed)
+    }
+
+    async fn broadcast(&self, message: A2aMessage) -> Result<(), A2aError> {
+        let peers: Vec<SocketAddr> = self.peers.lock().await.keys().copied().collect();
+
+        // Attempt every peer, reporting the first failure
+        let mut result = Ok(());
+        for addr in peers {
+            if let Err(e) = self.send_to(addr, &message).await
+                && result.is_ok()
+            {
+                result = Err(e);
+            }
+        }
+        result
+    }
+}
+
+/// In-process message hub connecting loopback transports by agent id
+#[derive(Clone, Default)]
+pub struct LoopbackHub {
+    inboxes: Arc<std::sync::Mutex<HashMap<AgentId, mpsc::UnboundedSender<A2aMessage>>>>,
+}
+
+impl LoopbackHub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Attach a transport for `agent`, replacing any previous one
+    pub fn connect(&self, agent: AgentId) -> LoopbackTransport {
+        let (sender, inbox) = mpsc::unbounded_channel();
+        self.inboxes.lock().unwrap().insert(agent.clone(), sender);
+        LoopbackTransport {
+            agent,
+            hub: self.clone(),
+            inbox: Mutex::new(inbox),
+        }
+    }
+}
+
+/// Transport delivering messages through a `LoopbackHub`, for tests and single-process setups
+pub struct LoopbackTransport {
+    agent: AgentId,
+    hub: LoopbackHub,
+    inbox: Mutex<mpsc::UnboundedReceiver<A2aMessage>>,
+}
+
+#[async_trait::async_trait]
+impl A2aTransport for LoopbackTransport {
+    async fn send(&self, message: A2aMessage) -> Result<(), A2aError> {
+        let Some(to) = message.to.clone() else {
+            return self.broadcast(message).await;
+        };
+        let inboxes = self.hub.inboxes.lock().unwrap();
+        let inbox = inboxes.get(&to)
+            .ok_or_else(|| A2aError::NotFound(format!("Agent {} is not connected", to.to_string())))?;
+        inbox.send(message).map_err(|_| A2aError::Closed)
+    }
+
+    async fn receive(&self) -> Result<A2aMessage, A2aError> {
+        self.inbox.lock().await.recv().await.ok_or(A2aError::Closed)
+    }
+
+    async fn broadcast(&self, message: A2aMessage) -> Result<(), A2aError> {
+        let inboxes = self.hub.inboxes.lock().unwrap();
+        for (agent, inbox) in inboxes.iter() {
+            if *agent != self.agent {
+                // A transport dropped mid-broadcast simply misses the message
+                let _ = inbox.send(message.clone());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for LoopbackTransport {
+    fn drop(&mut self) {
+        if let Ok(mut inboxes) = self.hub.inboxes.lock() {
+            inboxes.remove(&self.agent);
+        }
+    }
+}
+
 /// A2A Error types
 #[derive(Debug, Clone)]
 pub enum A2aError {
     NoTransport,
+    Closed,
     TransportError(String),
     SerializationError(String),
     TimeoutError,
     NotFound(String),
     InvalidMessage(String),
+    /// Barrier id and the agents that never reported ready
+    BarrierTimeout(String, Vec<AgentId>),
 }
 
 impl std::fmt::Display for A2aError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             A2aError::NoTransport => write!(f, "No transport configured"),
+            A2aError::Closed => write!(f, "Transport closed"),
             A2aError::TransportError(msg) => write!(f, "Transport error: {}", msg),
             A2aError::SerializationError(msg) => write!(f, "Serialization error: {}", msg),
             A2aError::TimeoutError => write!(f, "Timeout"),
             A2aError::NotFound(msg) => write!(f, "Not found: {}", msg),
             A2aError::InvalidMessage(msg) => write!(f, "Invalid message: {}", msg),
+            A2aError::BarrierTimeout(barrier_id, missing) => {
+                let missing: Vec<String> = missing.iter().map(|agent| agent.to_string()).collect();
+                write!(f, "Barrier '{}' timed out waiting for {}", barrier_id, missing.join(", "))
+            }
         }
     }
 }
@@ -482,4 +1045,222 @@ mod tests {
 
         assert_eq!(msg.message_id, "test_123");
     }
+
+    fn agent_info(namespace: &str, id: &str, agent_type: &str, capabilities: &[&str]) -> AgentInfo {
+        AgentInfo {
+            id: AgentId::new(namespace, id),
+            agent_type: agent_type.to_string(),
+            capabilities: capabilities.iter().map(|c| c.to_string()).collect(),
+            attributes: HashMap::from([("region".to_string(), serde_json::json!("north"))]),
+            endpoint: None,
+        }
+    }
+
+    #[test]
+    fn test_discovery_query_matching() {
+        let agent = agent_info("sim1", "a", "consumer", &["buy", "sell"]);
+        let any = DiscoveryQuery { agent_type: None, capabilities: None, attributes: None, namespace: None };
+        assert!(any.matches(&agent));
+
+        let by_type = DiscoveryQuery { agent_type: Some("consumer".to_string()), ..any.clone() };
+        assert!(by_type.matches(&agent));
+        let wrong_type = DiscoveryQuery { agent_type: Some("producer".to_string()), ..any.clone() };
+        assert!(!wrong_type.matches(&agent));
+
+        let subset = DiscoveryQuery { capabilities: Some(vec!["sell".to_string()]), ..any.clone() };
+        assert!(subset.matches(&agent));
+        let missing = DiscoveryQuery { capabilities: Some(vec!["sell".to_string(), "lend".to_string()]), ..any.clone() };
+        assert!(!missing.matches(&agent));
+
+        let region = |value: &str| DiscoveryQuery {
+            attributes: Some(HashMap::from([("region".to_string(), serde_json::json!(value))])),
+            ..any.clone()
+        };
+        assert!(region("north").matches(&agent));
+        assert!(!region("south").matches(&agent));
+
+        let namespace = DiscoveryQuery { namespace: Some("sim2".to_string()), ..any.clone() };
+        assert!(!namespace.matches(&agent));
+    }
+
+    #[tokio::test]
+    async fn test_directory_discovery_and_propagation() {
+        let hub = LoopbackHub::new();
+        let (dir, a, b) = (AgentId::new("sim1", "dir"), AgentId::new("sim1", "a"), AgentId::new("sim1", "b"));
+        let mut directory = A2aNode::new(dir.clone()).as_directory()
+            .with_transport(Box::new(hub.connect(dir.clone())));
+        let mut node_a = A2aNode::new(a.clone()).with_directory(dir.clone())
+            .with_transport(Box::new(hub.connect(a.clone())));
+        let mut node_b = A2aNode::new(b.clone()).with_directory(dir.clone())
+            .with_transport(Box::new(hub.connect(b.clone())));
+
+        node_a.register(agent_info("sim1", "a", "consumer", &["buy"])).await.unwrap();
+        directory.poll().await.unwrap();
+        assert!(directory.registry().contains_key(&a));
+
+        // The registration is forwarded to the other nodes
+        node_b.poll().await.unwrap();
+        assert!(node_b.registry().contains_key(&a));
+        node_a.poll().await.unwrap();
+
+        let query = DiscoveryQuery {
+            agent_type: Some("consumer".to_string()),
+            capabilities: None,
+            attributes: None,
+            namespace: None,
+        };
+        let (found, served) = tokio::join!(node_b.discover(query.clone()), directory.poll());
+        served.unwrap();
+        let found = found.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].id, a);
+
+        node_a.unregister().await.unwrap();
+        directory.poll().await.unwrap();
+        assert!(directory.discover(query).await.unwrap().is_empty());
+        node_b.poll().await.unwrap();
+        assert!(!node_b.registry().contains_key(&a));
+    }
+
+    #[tokio::test]
+    async fn test_discover_times_out_without_directory_reply() {
+        let hub = LoopbackHub::new();
+        let (dir, a) = (AgentId::new("sim1", "dir"), AgentId::new("sim1", "a"));
+        let _directory_transport = hub.connect(dir.clone());
+        let mut node = A2aNode::new(a.clone()).with_directory(dir)
+            .with_discovery_timeout(Duration::from_millis(10))
+            .with_transport(Box::new(hub.connect(a)));
+
+        let query = DiscoveryQuery { agent_type: None, capabilities: None, attributes: None, namespace: None };
+        assert!(matches!(node.discover(query).await, Err(A2aError::TimeoutError)));
+    }
+
+    #[tokio::test]
+    async fn test_barrier_lock_step() {
+        let hub = LoopbackHub::new();
+        let (coord, a, b) = (AgentId::new("sim1", "coord"), AgentId::new("sim1", "a"), AgentId::new("sim1", "b"));
+        let mut coordinator = A2aNode::new(coord.clone()).with_transport(Box::new(hub.connect(coord.clone())));
+        let mut node_a = A2aNode::new(a.clone()).with_transport(Box::new(hub.connect(a.clone())));
+        let mut node_b = A2aNode::new(b.clone()).with_transport(Box::new(hub.connect(b.clone())));
+
+        for step in 0..3 {
+            let barrier_id = format!("step-{}", step);
+            let (released, ready_a, ready_b) = tokio::join!(
+                coordinator.coordinate_barrier(&barrier_id, vec![a.clone(), b.clone()]),
+                node_a.barrier_wait(coord.clone(), &barrier_id),
+                node_b.barrier_wait(coord.clone(), &barrier_id),
+            );
+            released.unwrap();
+            ready_a.unwrap();
+            ready_b.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_barrier_times_out_stragglers() {
+        let hub = LoopbackHub::new();
+        let (coord, a, b) = (AgentId::new("sim1", "coord"), AgentId::new("sim1", "a"), AgentId::new("sim1", "b"));
+        let mut coordinator = A2aNode::new(coord.clone())
+            .with_barrier_timeout(Duration::from_millis(20))
+            .with_transport(Box::new(hub.connect(coord.clone())));
+        let mut node_a = A2aNode::new(a.clone()).with_transport(Box::new(hub.connect(a.clone())));
+        let _straggler = hub.connect(b.clone());
+
+        let (released, ready_a) = tokio::join!(
+            coordinator.coordinate_barrier("step-0", vec![a.clone(), b.clone()]),
+            node_a.barrier_wait(coord.clone(), "step-0"),
+        );
+        assert!(matches!(released, Err(A2aError::BarrierTimeout(_, ref missing)) if *missing == vec![b.clone()]));
+        assert!(matches!(ready_a, Err(A2aError::BarrierTimeout(_, _))));
+    }
+
+    #[tokio::test]
+    async fn test_frame_round_trip() {
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        let message = A2aMessage {
+            message_id: "frame_1".to_string(),
+            from: AgentId::new("sim1", "agent_1"),
+            to: None,
+            timestamp: 42,
+            ttl: None,
+            payload: A2aPayload::Publish { topic: "prices".to_string(), content: serde_json::json!([1, 2]) },
+        };
+
+        write_frame(&mut client, &message).await.unwrap();
+        drop(client);
+
+        let received = read_frame(&mut server).await.unwrap().unwrap();
+        assert_eq!(received.message_id, "frame_1");
+        assert!(read_frame(&mut server).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_loopback_send_and_broadcast() {
+        let hub = LoopbackHub::new();
+        let (a, b, c) = (AgentId::new("sim1", "a"), AgentId::new("sim1", "b"), AgentId::new("sim1", "c"));
+        let node_a = A2aNode::new(a.clone()).with_transport(Box::new(hub.connect(a.clone())));
+        let mut node_b = A2aNode::new(b.clone()).with_transport(Box::new(hub.connect(b.clone())));
+        let mut node_c = A2aNode::new(c.clone()).with_transport(Box::new(hub.connect(c.clone())));
+
+        node_a.send(b.clone(), A2aPayload::DirectMessage { content: serde_json::json!("hi") }).await.unwrap();
+        let message = node_b.poll().await.unwrap();
+        assert_eq!(message.from, a);
+        assert!(matches!(message.payload, A2aPayload::DirectMessage { .. }));
+
+        node_a.broadcast(A2aPayload::Heartbeat).await.unwrap();
+        assert!(matches!(node_b.poll().await.unwrap().payload, A2aPayload::Heartbeat));
+        assert!(matches!(node_c.poll().await.unwrap().payload, A2aPayload::Heartbeat));
+
+        let missing = node_a.send(AgentId::new("sim1", "nobody"), A2aPayload::Heartbeat).await;
+        assert!(matches!(missing, Err(A2aError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_tcp_send_and_broadcast() {
+        let (a, b) = (AgentId::new("sim1", "a"), AgentId::new("sim1", "b"));
+        let transport_a = TcpTransport::bind("127.0.0.1:0").await.unwrap();
+        let transport_b = TcpTransport::bind("127.0.0.1:0").await.unwrap();
+        transport_a.add_route(b.clone(), transport_b.local_addr()).await;
+        transport_b.add_peer(transport_a.local_addr()).await;
+
+        let mut node_a = A2aNode::new(a.clone()).with_transport(Box::new(transport_a));
+        let mut node_b = A2aNode::new(b.clone()).with_transport(Box::new(transport_b));
+
+        for i in 0..3 {
+            node_a.send(b.clone(), A2aPayload::DirectMessage { content: serde_json::json!(i) }).await.unwrap();
+        }
+        for i in 0..3 {
+            match node_b.poll().await.unwrap().payload {
+                A2aPayload::DirectMessage { content } => assert_eq!(content, serde_json::json!(i)),
+                other => panic!("unexpected payload {:?}", other),
+            }
+        }
+
+        node_b.broadcast(A2aPayload::Heartbeat).await.unwrap();
+        let message = node_a.poll().await.unwrap();
+        assert_eq!(message.from, b);
+    }
+
+    #[tokio::test]
+    async fn test_tcp_unreachable_peer_fails_after_retries() {
+        // Bind then drop a listener to get an address nothing listens on
+        let addr = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+        let transport = TcpTransport::bind("127.0.0.1:0").await.unwrap()
+            .with_retry(2, Duration::from_millis(1));
+        transport.add_peer(addr).await;
+
+        let node = A2aNode::new(AgentId::new("sim1", "a")).with_transport(Box::new(transport));
+        assert!(matches!(node.broadcast(A2aPayload::Heartbeat).await, Err(A2aError::TransportError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_tcp_drop_closes_listener() {
+        let transport = TcpTransport::bind("127.0.0.1:0").await.unwrap();
+        let addr = transport.local_addr();
+        assert!(TcpStream::connect(addr).await.is_ok());
+
+        drop(transport);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(TcpStream::connect(addr).await.is_err());
+    }
 }
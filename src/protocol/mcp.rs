@@ -6,7 +6,12 @@
 /// Reference: https://modelcontextprotocol.io/
 
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::collections::HashMap;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use crate::analysis::{ParameterRange, SensitivityAnalyzer, StabilityAnalyzer, StructureAnalyzer};
+use crate::model::Model;
+use crate::simulation::{SimulationConfig, SimulationEngine, SimulationResults, SimulationState};
 
 /// MCP Protocol Version
 pub const MCP_VERSION: &str = "2024-11-05";
@@ -66,6 +71,7 @@ pub enum McpMessage {
 
 /// MCP Capabilities
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct McpCapabilities {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub resources: Option<ResourceCapabilities>,
@@ -78,17 +84,20 @@ pub struct McpCapabilities {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ResourceCapabilities {
     pub subscribe: bool,
     pub list_changed: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ToolCapabilities {
     pub list_changed: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct PromptCapabilities {
     pub list_changed: bool,
 }
@@ -108,7 +117,7 @@ pub struct ClientInfo {
 pub enum McpResult {
     Resources {
         resources: Vec<Resource>,
-        #[serde(skip_serializing_if = "Option::is_none")]
+        #[serde(rename = "nextCursor", skip_serializing_if = "Option::is_none")]
         next_cursor: Option<String>,
     },
     ResourceContent {
@@ -119,13 +128,14 @@ pub enum McpResult {
     },
     ToolResult {
         content: Vec<ToolContent>,
-        #[serde(skip_serializing_if = "Option::is_none")]
+        #[serde(rename = "isError", skip_serializing_if = "Option::is_none")]
         is_error: Option<bool>,
     },
 }
 
 /// Resource definition
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Resource {
     pub uri: String,
     pub name: String,
@@ -137,6 +147,7 @@ pub struct Resource {
 
 /// Resource content
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ResourceContent {
     pub uri: String,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -154,6 +165,7 @@ pub enum ResourceContentType {
 
 /// Tool definition
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Tool {
     pub name: String,
     pub description: String,
@@ -167,16 +179,46 @@ pub enum ToolContent {
     #[serde(rename = "text")]
     Text { text: String },
     #[serde(rename = "image")]
-    Image { data: String, mime_type: String },
+    Image {
+        data: String,
+        #[serde(rename = "mimeType")]
+        mime_type: String,
+    },
     #[serde(rename = "resource")]
     Resource { resource: ResourceContent },
 }
 
+/// JSON-RPC 2.0 request (or notification when `id` is absent)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonRpcRequest {
+    pub jsonrpc: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<Value>,
+    pub method: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params: Option<Value>,
+}
+
 /// MCP Server implementation for rsedsim
 pub struct McpServer {
     capabilities: McpCapabilities,
     resources: Vec<Resource>,
     tools: Vec<Tool>,
+    /// Model paths used by tool calls, in first-use order
+    models: Vec<String>,
+    /// Completed simulation results by id
+    simulations: HashMap<String, SimulationResults>,
+    latest_simulation: Option<String>,
+    /// Runs played in decision intervals that have not reached their stop time, by id
+    decision_runs: HashMap<String, DecisionRun>,
+}
+
+/// A run played in decision intervals
+struct DecisionRun {
+    engine: SimulationEngine,
+    /// Time steps run between decisions
+    interval: usize,
+    results: SimulationResults,
 }
 
 impl McpServer {
@@ -196,6 +238,10 @@ impl McpServer {
             },
             resources: Self::default_resources(),
             tools: Self::default_tools(),
+            models: Vec::new(),
+            simulations: HashMap::new(),
+            latest_simulation: None,
+            decision_runs: HashMap::new(),
         }
     }
 
@@ -247,6 +293,11 @@ impl McpServer {
                                 "stop": {"type": "number"},
                                 "dt": {"type": "number"}
                             }
+                        },
+                        "variables": {
+                            "type": "array",
+                            "items": {"type": "string"},
+                            "description": "Variables to return as time series (default: all stocks)"
                         }
                     },
                     "required": ["model"]
@@ -295,6 +346,43 @@ impl McpServer {
                     "required": ["model", "parameters"]
                 }),
             },
+            Tool {
+                name: "start_decision_run".to_string(),
+                description: "Start playing a model in decision intervals, as in a management flight simulator, and run the first interval".to_string(),
+                input_schema: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "model": {
+                            "type": "string",
+                            "description": "Model file path or URI"
+                        },
+                        "interval": {
+                            "type": "integer",
+                            "description": "Time steps to run between decisions"
+                        },
+                        "parameters": {
+                            "type": "object",
+                            "description": "Parameter overrides as key-value pairs"
+                        }
+                    },
+                    "required": ["model", "interval"]
+                }),
+            },
+            Tool {
+                name: "advance_decision_run".to_string(),
+                description: "Set parameters or stocks of a decision run, then run its next interval".to_string(),
+                input_schema: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "simulation_id": {"type": "string"},
+                        "changes": {
+                            "type": "object",
+                            "description": "New values of parameters, or else stocks, as key-value pairs"
+                        }
+                    },
+                    "required": ["simulation_id"]
+                }),
+            },
             Tool {
                 name: "get_variable_timeseries".to_string(),
                 description: "Extract time series data for specific variables".to_string(),
@@ -313,44 +401,432 @@ impl McpServer {
         ]
     }
 
-    /// Handle incoming MCP message
-    pub async fn handle_message(&mut self, message: McpMessage) -> Result<McpMessage, McpError> {
-        // TODO: Implement message handling logic
-        match message {
-            McpMessage::ListResources { .. } => {
-                Ok(McpMessage::Response {
-                    request_id: "TODO".to_string(),
-                    result: McpResult::Resources {
-                        resources: self.resources.clone(),
-                        next_cursor: None,
+    /// Handle incoming MCP message, answering with a `Response` or `Error` for `request_id`
+    pub async fn handle_message(&mut self, request_id: String, message: McpMessage) -> McpMessage {
+        let result = match message {
+            McpMessage::ListResources { .. } => Ok(McpResult::Resources {
+                resources: self.resources.clone(),
+                next_cursor: None,
+            }),
+            McpMessage::ListTools { .. } => Ok(McpResult::Tools {
+                tools: self.tools.clone(),
+            }),
+            McpMessage::ReadResource { uri } => self.read_resource(&uri),
+            McpMessage::CallTool { name, arguments } => self.call_tool(&name, &arguments),
+            _ => Err(McpError::NotImplemented),
+        };
+
+        match result {
+            Ok(result) => McpMessage::Response { request_id, result },
+            Err(error) => McpMessage::Error {
+                request_id,
+                code: error.code(),
+                message: error.to_string(),
+                data: None,
+            },
+        }
+    }
+
+    /// Handle one JSON-RPC request, returning the response (None for notifications)
+    pub async fn handle_request(&mut self, request: JsonRpcRequest) -> Option<Value> {
+        let id = request.id.clone()?;
+        let response = match self.dispatch(&request.method, request.params.unwrap_or(Value::Null)) {
+            Ok(result) => serde_json::json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(error) => Self::error_response(id, &error),
+        };
+        Some(response)
+    }
+
+    /// Handle one line of JSON-RPC input
+    pub async fn handle_line(&mut self, line: &str) -> Option<Value> {
+        let value: Value = match serde_json::from_str(line) {
+            Ok(value) => value,
+            Err(e) => return Some(Self::error_response(Value::Null, &McpError::ParseError(e.to_string()))),
+        };
+        let id = value.get("id").cloned().unwrap_or(Value::Null);
+
+        match serde_json::from_value::<JsonRpcRequest>(value) {
+            Ok(request) if request.jsonrpc == "2.0" => self.handle_request(request).await,
+            Ok(_) => Some(Self::error_response(id, &McpError::InvalidRequest("jsonrpc must be \"2.0\"".to_string()))),
+            Err(e) => Some(Self::error_response(id, &McpError::InvalidRequest(e.to_string()))),
+        }
+    }
+
+    fn dispatch(&mut self, method: &str, params: Value) -> Result<Value, McpError> {
+        let result = match method {
+            "initialize" => {
+                return Ok(serde_json::json!({
+                    "protocolVersion": MCP_VERSION,
+                    "capabilities": self.capabilities,
+                    "serverInfo": {
+                        "name": "rsedsim",
+                        "version": env!("CARGO_PKG_VERSION"),
                     },
-                })
+                }));
             }
-            McpMessage::ListTools { .. } => {
-                Ok(McpMessage::Response {
-                    request_id: "TODO".to_string(),
-                    result: McpResult::Tools {
-                        tools: self.tools.clone(),
-                    },
-                })
+            "ping" => return Ok(serde_json::json!({})),
+            "tools/list" => McpResult::Tools { tools: self.tools.clone() },
+            "resources/list" => McpResult::Resources {
+                resources: self.resources.clone(),
+                next_cursor: None,
+            },
+            "resources/read" => {
+                let uri = params.get("uri").and_then(Value::as_str)
+                    .ok_or_else(|| McpError::InvalidParams("Missing 'uri'".to_string()))?;
+                self.read_resource(uri)?
             }
-            _ => Err(McpError::NotImplemented),
+            "tools/call" => {
+                let name = params.get("name").and_then(Value::as_str)
+                    .ok_or_else(|| McpError::InvalidParams("Missing tool 'name'".to_string()))?;
+                let arguments: HashMap<String, Value> = match params.get("arguments") {
+                    Some(arguments) => serde_json::from_value(arguments.clone())
+                        .map_err(|e| McpError::InvalidParams(format!("Invalid arguments: {}", e)))?,
+                    None => HashMap::new(),
+                };
+                self.call_tool(name, &arguments)?
+            }
+            _ => return Err(McpError::MethodNotFound(method.to_string())),
+        };
+
+        serde_json::to_value(result).map_err(|e| McpError::InternalError(e.to_string()))
+    }
+
+    fn error_response(id: Value, error: &McpError) -> Value {
+        serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": error.code(), "message": error.to_string() },
+        })
+    }
+
+    /// Read one of the advertised resources
+    fn read_resource(&self, uri: &str) -> Result<McpResult, McpError> {
+        let latest = self.latest_simulation.as_ref().and_then(|id| self.simulations.get(id));
+
+        let value = match uri {
+            "rsedsim://models/list" => serde_json::json!(self.models),
+            "rsedsim://simulation/state" => match latest.and_then(SimulationResults::final_state) {
+                Some(state) => state_json(&state),
+                None => Value::Null,
+            },
+            "rsedsim://results/latest" => match (&self.latest_simulation, latest) {
+                (Some(id), Some(results)) => serde_json::json!({
+                    "simulation_id": id,
+                    "time": results.times,
+                    "states": (0..results.len())
+                        .filter_map(|row| results.state_at(row))
+                        .map(|state| state_json(&state))
+                        .collect::<Vec<_>>(),
+                }),
+                _ => Value::Null,
+            },
+            _ => return Err(McpError::InvalidParams(format!("Unknown resource '{}'", uri))),
+        };
+
+        Ok(McpResult::ResourceContent {
+            contents: vec![ResourceContent {
+                uri: uri.to_string(),
+                mime_type: Some("application/json".to_string()),
+                content: ResourceContentType::Text { text: value.to_string() },
+            }],
+        })
+    }
+
+    /// Run a tool
+    ///
+    /// Unknown tools and missing required arguments are protocol errors; failures
+    /// while executing the tool are reported in the result with `isError` set.
+    fn call_tool(&mut self, name: &str, arguments: &HashMap<String, Value>) -> Result<McpResult, McpError> {
+        let tool = self.tools.iter()
+            .find(|tool| tool.name == name)
+            .ok_or_else(|| McpError::InvalidParams(format!("Unknown tool '{}'", name)))?;
+
+        let required = tool.input_schema.get("required").and_then(Value::as_array);
+        for argument in required.into_iter().flatten().filter_map(Value::as_str) {
+            if !arguments.contains_key(argument) {
+                return Err(McpError::InvalidParams(
+                    format!("Missing required argument '{}' for tool '{}'", argument, name)
+                ));
+            }
+        }
+
+        let outcome = match name {
+            "run_simulation" => self.tool_run_simulation(arguments),
+            "analyze_model" => self.tool_analyze_model(arguments),
+            "sensitivity_analysis" => self.tool_sensitivity_analysis(arguments),
+            "start_decision_run" => self.tool_start_decision_run(arguments),
+            "advance_decision_run" => self.tool_advance_decision_run(arguments),
+            "get_variable_timeseries" => self.tool_get_variable_timeseries(arguments),
+            _ => return Err(McpError::InvalidParams(format!("Unknown tool '{}'", name))),
+        };
+
+        let (text, is_error) = match outcome {
+            Ok(text) => (text, None),
+            Err(message) => (message, Some(true)),
+        };
+        Ok(McpResult::ToolResult {
+            content: vec![ToolContent::Text { text }],
+            is_error,
+        })
+    }
+
+    fn load_model(&mut self, arguments: &HashMap<String, Value>) -> Result<Model, String> {
+        let path = arguments.get("model").and_then(Value::as_str)
+            .ok_or("Missing 'model' argument")?;
+        let model = crate::io::load_model(path)?;
+        if !self.models.iter().any(|m| m == path) {
+            self.models.push(path.to_string());
         }
+        Ok(model)
+    }
+
+    fn tool_run_simulation(&mut self, arguments: &HashMap<String, Value>) -> Result<String, String> {
+        let mut model = self.load_model(arguments)?;
+
+        if let Some(parameters) = arguments.get("parameters").and_then(Value::as_object) {
+            for (name, value) in parameters {
+                let value = value.as_f64()
+                    .ok_or_else(|| format!("Parameter '{}' must be a number", name))?;
+                model.set_parameter(name, value)?;
+            }
+        }
+        if let Some(time) = arguments.get("time_config").and_then(Value::as_object) {
+            let field = |key: &str| time.get(key).and_then(Value::as_f64);
+            model.time.start = field("start").unwrap_or(model.time.start);
+            model.time.stop = field("stop").unwrap_or(model.time.stop);
+            model.time.dt = field("dt").unwrap_or(model.time.dt);
+        }
+
+        // Report the requested variables, or every stock by default
+        let variables: Vec<String> = match arguments.get("variables").and_then(Value::as_array) {
+            Some(variables) => variables.iter()
+                .map(|v| v.as_str().map(str::to_string).ok_or("Variable names must be strings"))
+                .collect::<Result<_, _>>()?,
+            None => {
+                let mut stocks: Vec<String> = model.stocks.keys().cloned().collect();
+                stocks.sort();
+                stocks
+            }
+        };
+
+        let results = SimulationEngine::new(model, SimulationConfig::default())?.run()?;
+        let id = uuid::Uuid::new_v4().to_string();
+
+        let summary = serde_json::json!({
+            "simulation_id": id,
+            "steps": results.times.len(),
+            "final": results.final_state().as_ref().map(state_json),
+            "series": series_json(&results, &variables)?,
+        });
+
+        self.simulations.insert(id.clone(), results);
+        self.latest_simulation = Some(id);
+
+        serde_json::to_string_pretty(&summary).map_err(|e| e.to_string())
+    }
+
+    fn tool_analyze_model(&mut self, arguments: &HashMap<String, Value>) -> Result<String, String> {
+        let model = self.load_model(arguments)?;
+        let analysis_type = arguments.get("analysis_type").and_then(Value::as_str)
+            .ok_or("Missing 'analysis_type' argument")?;
+
+        match analysis_type {
+            "structure" => Ok(StructureAnalyzer::new(&model).generate_report()),
+            "loops" => serde_json::to_string_pretty(&StructureAnalyzer::new(&model).export_json())
+                .map_err(|e| e.to_string()),
+            "equilibrium" => {
+                let state = SimulationState::initialize_from_model(&model)?;
+                Ok(StabilityAnalyzer::default().analyze(&model, &state)?.summary())
+            }
+            "sensitivity" => Err("Use the sensitivity_analysis tool for sensitivity analysis".to_string()),
+            other => Err(format!("Unknown analysis_type '{}'", other)),
+        }
+    }
+
+    fn tool_sensitivity_analysis(&mut self, arguments: &HashMap<String, Value>) -> Result<String, String> {
+        let model = self.load_model(arguments)?;
+        let parameters = arguments.get("parameters").and_then(Value::as_array)
+            .ok_or("Missing 'parameters' argument")?;
+        let ranges = arguments.get("ranges").and_then(Value::as_object);
+        let samples = arguments.get("samples").and_then(Value::as_u64).unwrap_or(20) as usize;
+
+        let mut parameter_ranges = Vec::new();
+        for name in parameters {
+            let name = name.as_str().ok_or("Parameter names must be strings")?;
+            let baseline = model.parameters.get(name)
+                .ok_or_else(|| format!("Parameter '{}' not found", name))?
+                .value;
+
+            // Ranges may be {"min": a, "max": b} or [a, b]; default to +/-50%
+            let (min, max) = match ranges.and_then(|r| r.get(name)) {
+                Some(Value::Array(bounds)) if bounds.len() == 2 => (
+                    bounds[0].as_f64().ok_or("Range bounds must be numbers")?,
+                    bounds[1].as_f64().ok_or("Range bounds must be numbers")?,
+                ),
+                Some(Value::Object(bounds)) => (
+                    bounds.get("min").and_then(Value::as_f64).ok_or("Range needs numeric 'min'")?,
+                    bounds.get("max").and_then(Value::as_f64).ok_or("Range needs numeric 'max'")?,
+                ),
+                Some(_) => return Err(format!("Invalid range for '{}'", name)),
+                None => {
+                    let (a, b) = (baseline * 0.5, baseline * 1.5);
+                    (a.min(b), a.max(b))
+                }
+            };
+            parameter_ranges.push(ParameterRange::new(name.to_string(), min, max, baseline));
+        }
+
+        let mut analyzer = SensitivityAnalyzer::new(parameter_ranges);
+        analyzer.latin_hypercube_sampling(&model, &SimulationConfig::default(), samples, None)?;
+
+        let mut stock_names: Vec<&String> = model.stocks.keys().collect();
+        stock_names.sort();
+        let metrics: Vec<String> = stock_names.iter().map(|name| format!("{}_final", name)).collect();
+
+        let rows: Vec<Value> = analyzer.results.iter()
+            .map(|result| {
+                let mut row = serde_json::Map::new();
+                for range in &analyzer.parameter_ranges {
+                    row.insert(range.name.clone(), serde_json::json!(result.sample.get(&range.name)));
+                }
+                for metric in &metrics {
+                    row.insert(metric.clone(), serde_json::json!(result.metrics.get(metric)));
+                }
+                Value::Object(row)
+            })
+            .collect();
+
+        serde_json::to_string_pretty(&serde_json::json!({ "samples": rows })).map_err(|e| e.to_string())
+    }
+
+    fn tool_start_decision_run(&mut self, arguments: &HashMap<String, Value>) -> Result<String, String> {
+        let mut model = self.load_model(arguments)?;
+        let interval = arguments.get("interval").and_then(Value::as_u64)
+            .filter(|steps| *steps > 0)
+            .ok_or("'interval' must be a positive number of steps")? as usize;
+        if let Some(parameters) = arguments.get("parameters").and_then(Value::as_object) {
+            for (name, value) in parameters {
+                let value = value.as_f64()
+                    .ok_or_else(|| format!("Parameter '{}' must be a number", name))?;
+                model.set_parameter(name, value)?;
+            }
+        }
+
+        let engine = SimulationEngine::new(model, SimulationConfig::default())?;
+        let id = uuid::Uuid::new_v4().to_string();
+        self.decision_runs.insert(id.clone(), DecisionRun { engine, interval, results: SimulationResults::new() });
+        self.advance_decision_run(id, &serde_json::Map::new())
+    }
+
+    fn tool_advance_decision_run(&mut self, arguments: &HashMap<String, Value>) -> Result<String, String> {
+        let id = arguments.get("simulation_id").and_then(Value::as_str)
+            .ok_or("Missing 'simulation_id' argument")?;
+        let changes = arguments.get("changes").and_then(Value::as_object).cloned().unwrap_or_default();
+        self.advance_decision_run(id.to_string(), &changes)
+    }
+
+    /// Apply `changes` to a decision run and run its next interval; a run that
+    /// reaches its stop time moves to the finished simulations
+    fn advance_decision_run(&mut self, id: String, changes: &serde_json::Map<String, Value>) -> Result<String, String> {
+        let run = self.decision_runs.get_mut(&id).ok_or_else(|| {
+            if self.simulations.contains_key(&id) {
+                format!("Simulation '{}' has reached its stop time", id)
+            } else {
+                format!("Decision run '{}' not found", id)
+            }
+        })?;
+        let changes = changes.iter()
+            .map(|(name, value)| {
+                value.as_f64().map(|value| (name.as_str(), value))
+                    .ok_or_else(|| format!("Change to '{}' must be a number", name))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        run.engine.set_decisions(&changes)?;
+        run.engine.run_steps(run.interval, &mut run.results)?;
+
+        let finished = run.engine.is_finished();
+        let summary = serde_json::json!({
+            "simulation_id": id,
+            "finished": finished,
+            "state": state_json(run.engine.current_state()),
+        });
+        if finished && let Some(run) = self.decision_runs.remove(&id) {
+            self.simulations.insert(id.clone(), run.results);
+            self.latest_simulation = Some(id);
+        }
+        serde_json::to_string_pretty(&summary).map_err(|e| e.to_string())
+    }
+
+    fn tool_get_variable_timeseries(&mut self, arguments: &HashMap<String, Value>) -> Result<String, String> {
+        let id = arguments.get("simulation_id").and_then(Value::as_str)
+            .ok_or("Missing 'simulation_id' argument")?;
+        let results = self.simulations.get(id)
+            .ok_or_else(|| format!("Simulation '{}' not found", id))?;
+        let variables = arguments.get("variables").and_then(Value::as_array)
+            .ok_or("Missing 'variables' argument")?;
+
+        let names = variables.iter()
+            .map(|v| v.as_str().map(str::to_string).ok_or("Variable names must be strings"))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        serde_json::to_string(&series_json(results, &names)?).map_err(|e| e.to_string())
     }
 
     /// Start MCP server on stdio
+    ///
+    /// Reads newline-delimited JSON-RPC 2.0 messages from stdin and writes one
+    /// response line per request to stdout until stdin closes.
     pub async fn serve_stdio(&mut self) -> Result<(), McpError> {
-        // TODO: Implement stdio-based JSON-RPC server
-        todo!("Implement stdio transport")
+        let mut lines = BufReader::new(tokio::io::stdin()).lines();
+        let mut stdout = tokio::io::stdout();
+
+        while let Some(line) = lines.next_line().await
+            .map_err(|e| McpError::TransportError(e.to_string()))?
+        {
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Some(response) = self.handle_line(&line).await {
+                let mut bytes = response.to_string().into_bytes();
+                bytes.push(b'\n');
+                stdout.write_all(&bytes).await
+                    .map_err(|e| McpError::TransportError(e.to_string()))?;
+                stdout.flush().await
+                    .map_err(|e| McpError::TransportError(e.to_string()))?;
+            }
+        }
+
+        Ok(())
     }
 
     /// Start MCP server on HTTP SSE
-    pub async fn serve_http(&mut self, addr: &str) -> Result<(), McpError> {
-        // TODO: Implement HTTP SSE transport
-        todo!("Implement HTTP SSE transport")
+    pub async fn serve_http(&mut self, _addr: &str) -> Result<(), McpError> {
+        Err(McpError::NotImplemented)
     }
 }
 
+/// JSON object with a `time` array plus one array per named variable
+fn series_json(results: &SimulationResults, variables: &[String]) -> Result<Value, String> {
+    let mut series = serde_json::Map::new();
+    series.insert("time".to_string(), serde_json::json!(results.times));
+    for name in variables {
+        let values = results.get_variable_series(name)
+            .ok_or_else(|| format!("Variable '{}' not found", name))?;
+        series.insert(name.clone(), serde_json::json!(values));
+    }
+    Ok(Value::Object(series))
+}
+
+/// JSON view of a simulation state's variables
+fn state_json(state: &SimulationState) -> Value {
+    serde_json::json!({
+        "time": state.time,
+        "stocks": state.stocks,
+        "flows": state.flows,
+        "auxiliaries": state.auxiliaries,
+    })
+}
+
 impl Default for McpServer {
     fn default() -> Self {
         Self::new()
@@ -409,6 +885,19 @@ pub enum McpError {
     TransportError(String),
 }
 
+impl McpError {
+    /// JSON-RPC 2.0 error code
+    pub fn code(&self) -> i32 {
+        match self {
+            McpError::ParseError(_) => -32700,
+            McpError::InvalidRequest(_) => -32600,
+            McpError::MethodNotFound(_) => -32601,
+            McpError::InvalidParams(_) => -32602,
+            McpError::InternalError(_) | McpError::NotImplemented | McpError::TransportError(_) => -32603,
+        }
+    }
+}
+
 impl std::fmt::Display for McpError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -442,4 +931,117 @@ mod tests {
         let json = serde_json::to_string(&msg).unwrap();
         assert!(json.contains("ListTools"));
     }
+
+    #[tokio::test]
+    async fn test_handle_message_propagates_request_id() {
+        let mut server = McpServer::new();
+
+        let mut arguments = HashMap::new();
+        arguments.insert("model".to_string(), serde_json::json!("examples/sir_epidemic.yaml"));
+        arguments.insert("analysis_type".to_string(), serde_json::json!("structure"));
+        match server.handle_message("req-1".to_string(), McpMessage::CallTool { name: "analyze_model".to_string(), arguments }).await {
+            McpMessage::Response { request_id, result: McpResult::ToolResult { is_error, .. } } => {
+                assert_eq!(request_id, "req-1");
+                assert_eq!(is_error, None);
+            }
+            other => panic!("unexpected reply: {:?}", other),
+        }
+
+        let reply = server.handle_message(
+            "req-2".to_string(),
+            McpMessage::CallTool { name: "nope".to_string(), arguments: HashMap::new() },
+        ).await;
+        assert!(matches!(reply, McpMessage::Error { ref request_id, code: -32602, .. } if request_id == "req-2"));
+    }
+
+    #[tokio::test]
+    async fn test_json_rpc_session() {
+        let mut server = McpServer::new();
+
+        let init = server.handle_line(r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#).await.unwrap();
+        assert_eq!(init["result"]["protocolVersion"], MCP_VERSION);
+
+        // Notifications get no response
+        assert!(server.handle_line(r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#).await.is_none());
+
+        let tools = server.handle_line(r#"{"jsonrpc":"2.0","id":2,"method":"tools/list"}"#).await.unwrap();
+        assert!(tools["result"]["tools"][0]["inputSchema"].is_object());
+
+        let run = server.handle_line(
+            r#"{"jsonrpc":"2.0","id":3,"method":"tools/call","params":{"name":"run_simulation","arguments":{"model":"examples/sir_epidemic.yaml","parameters":{"contact_rate":2.0}}}}"#,
+        ).await.unwrap();
+        let text = run["result"]["content"][0]["text"].as_str().unwrap();
+        let summary: Value = serde_json::from_str(text).unwrap();
+        let id = summary["simulation_id"].as_str().unwrap();
+
+        let series = server.handle_line(&format!(
+            r#"{{"jsonrpc":"2.0","id":4,"method":"tools/call","params":{{"name":"get_variable_timeseries","arguments":{{"simulation_id":"{}","variables":["Infected"]}}}}}}"#,
+            id
+        )).await.unwrap();
+        let text = series["result"]["content"][0]["text"].as_str().unwrap();
+        assert!(serde_json::from_str::<Value>(text).unwrap()["Infected"].is_array());
+
+        assert!(summary["series"]["Infected"].is_array());
+
+        let missing_argument = server.handle_line(
+            r#"{"jsonrpc":"2.0","id":7,"method":"tools/call","params":{"name":"analyze_model","arguments":{"model":"examples/sir_epidemic.yaml"}}}"#,
+        ).await.unwrap();
+        assert_eq!(missing_argument["error"]["code"], -32602);
+
+        let missing = server.handle_line(
+            r#"{"jsonrpc":"2.0","id":5,"method":"tools/call","params":{"name":"run_simulation","arguments":{"model":"nope.yaml"}}}"#,
+        ).await.unwrap();
+        assert_eq!(missing["result"]["isError"], true);
+
+        let unknown = server.handle_line(r#"{"jsonrpc":"2.0","id":6,"method":"bogus"}"#).await.unwrap();
+        assert_eq!(unknown["error"]["code"], -32601);
+
+        let garbage = server.handle_line("{not json").await.unwrap();
+        assert_eq!(garbage["error"]["code"], -32700);
+    }
+
+    #[test]
+    fn test_unknown_tool_is_invalid_params() {
+        let mut server = McpServer::new();
+        let err = server.call_tool("nope", &HashMap::new()).unwrap_err();
+        assert!(matches!(err, McpError::InvalidParams(ref msg) if msg == "Unknown tool 'nope'"));
+
+        // Listed but not implemented is the same error
+        server.tools.push(Tool {
+            name: "unimplemented".to_string(),
+            description: String::new(),
+            input_schema: serde_json::json!({"type": "object"}),
+        });
+        let err = server.call_tool("unimplemented", &HashMap::new()).unwrap_err();
+        assert!(matches!(err, McpError::InvalidParams(ref msg) if msg == "Unknown tool 'unimplemented'"));
+    }
+
+    #[test]
+    fn test_decision_run() {
+        let mut server = McpServer::new();
+        let mut call = |name: &str, arguments: Value| {
+            let arguments = serde_json::from_value(arguments).unwrap();
+            match server.call_tool(name, &arguments).unwrap() {
+                McpResult::ToolResult { content, is_error } => match &content[0] {
+                    ToolContent::Text { text } => (serde_json::from_str::<Value>(text).unwrap_or(Value::Null), is_error),
+                    other => panic!("unexpected content: {:?}", other),
+                },
+                other => panic!("unexpected result: {:?}", other),
+            }
+        };
+
+        // 400 steps of 0.25 to the stop time, in two intervals
+        let (started, _) = call("start_decision_run", serde_json::json!({"model": "examples/sir_epidemic.yaml", "interval": 200}));
+        assert_eq!((started["state"]["time"].as_f64(), started["finished"].as_bool()), (Some(50.0), Some(false)));
+        let id = started["simulation_id"].as_str().unwrap().to_string();
+
+        let (ended, _) = call("advance_decision_run", serde_json::json!({"simulation_id": id, "changes": {"Infected": 0}}));
+        assert_eq!((ended["state"]["time"].as_f64(), ended["finished"].as_bool()), (Some(100.0), Some(true)));
+        assert_eq!(ended["state"]["stocks"]["Infected"], 0.0);
+
+        let (_, is_error) = call("advance_decision_run", serde_json::json!({"simulation_id": id}));
+        assert_eq!(is_error, Some(true));
+        let (series, _) = call("get_variable_timeseries", serde_json::json!({"simulation_id": id, "variables": ["Infected"]}));
+        assert_eq!(series["time"].as_array().unwrap().last().and_then(Value::as_f64), Some(100.0));
+    }
 }
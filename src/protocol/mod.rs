@@ -7,5 +7,5 @@
 pub mod mcp;
 pub mod a2a;
 
-pub use mcp::{McpServer, McpClient, McpMessage};
-pub use a2a::{A2aNode, A2aMessage, A2aTransport};
+pub use mcp::{McpServer, McpClient, McpMessage, JsonRpcRequest};
+pub use a2a::{A2aNode, A2aMessage, A2aTransport, TcpTransport, LoopbackHub, LoopbackTransport};
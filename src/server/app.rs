@@ -1,16 +1,73 @@
 use axum::{
+    middleware,
     routing::{delete, get, post},
     Router,
 };
-use tower_http::cors::{Any, CorsLayer};
+use std::path::PathBuf;
+use axum::http::HeaderValue;
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
 use tower_http::trace::TraceLayer;
-use crate::server::{routes, state::AppState, websocket};
+use std::sync::Arc;
+use crate::server::{
+    auth::{self, AuthConfig, Authenticator},
+    dashboard, openapi, queue::JobQueue, routes, state::AppState, websocket,
+};
+
+/// Server startup options
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub port: u16,
+    /// Directory of model files to load at startup
+    pub model_dir: Option<PathBuf>,
+    /// Allowed CORS origins; `*` allows any origin
+    pub cors_origins: Vec<String>,
+    /// Directory where finished runs are saved and reloaded from at startup
+    pub data_dir: Option<PathBuf>,
+    /// Runs allowed at once; one per CPU when unset
+    pub workers: Option<usize>,
+    /// Runs allowed to wait for a worker before new ones are refused
+    pub queue_capacity: usize,
+    /// API keys, JWT secret and rate limit; open to anyone when unset
+    pub auth: AuthConfig,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            port: 8080,
+            model_dir: None,
+            cors_origins: vec!["*".to_string()],
+            data_dir: None,
+            workers: None,
+            queue_capacity: 1000,
+            auth: AuthConfig::default(),
+        }
+    }
+}
+
+impl ServerConfig {
+    fn cors_layer(&self) -> Result<CorsLayer, String> {
+        let layer = CorsLayer::new().allow_methods(Any).allow_headers(Any);
+
+        if self.cors_origins.iter().any(|origin| origin == "*") {
+            return Ok(layer.allow_origin(Any));
+        }
+
+        let origins = self.cors_origins.iter()
+            .map(|origin| origin.parse::<HeaderValue>()
+                .map_err(|_| format!("Invalid CORS origin '{}'", origin)))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(layer.allow_origin(AllowOrigin::list(origins)))
+    }
+}
 
 /// Create the Axum application with all routes
 pub fn create_app() -> Router {
-    let state = AppState::new();
+    router(AppState::new(), CorsLayer::permissive(), &AuthConfig::default())
+}
 
-    Router::new()
+pub(crate) fn router(state: AppState, cors: CorsLayer, auth: &AuthConfig) -> Router {
+    let routes = Router::new()
         // Model management routes
         .route("/api/models", get(routes::models::list_models))
         .route("/api/models", post(routes::models::upload_model))
@@ -20,7 +77,15 @@ pub fn create_app() -> Router {
             "/api/models/{id}/structure",
             get(routes::models::get_model_structure),
         )
+        .route(
+            "/api/models/{id}/parameters",
+            get(routes::models::get_model_parameters),
+        )
         // Simulation control routes
+        .route(
+            "/api/simulations",
+            get(routes::simulations::list_simulations),
+        )
         .route(
             "/api/simulations",
             post(routes::simulations::start_simulation),
@@ -33,30 +98,102 @@ pub fn create_app() -> Router {
             "/api/simulations/{id}/",
             delete(routes::simulations::stop_simulation),
         )
+        .route(
+            "/api/simulations/{id}/results",
+            get(routes::simulations::get_results),
+        )
+        .route(
+            "/api/simulations/{id}/cancel",
+            post(routes::simulations::cancel_simulation),
+        )
+        .route("/api/queue", get(routes::simulations::queue_metrics))
+        // Decision-interval sessions
+        .route("/api/sessions", post(routes::sessions::start_session))
+        .route(
+            "/api/sessions/{id}/advance",
+            post(routes::sessions::advance_session),
+        )
+        .route(
+            "/api/sessions/{id}/results",
+            get(routes::sessions::get_session_results),
+        )
+        .route(
+            "/api/sessions/{id}/",
+            delete(routes::sessions::delete_session),
+        )
         // WebSocket route
         .route("/ws/simulation/{id}/", get(websocket::handler))
         // Health check
         .route("/health", get(health_check))
-        // CORS - allow all origins for development
-        .layer(
-            CorsLayer::new()
-                .allow_origin(Any)
-                .allow_methods(Any)
-                .allow_headers(Any),
-        )
+        // Dashboard
+        .route("/", get(dashboard::index))
+        .route("/dashboard.js", get(dashboard::script))
+        .route("/dashboard.css", get(dashboard::style))
+        // API description
+        .route("/openapi.json", get(openapi::openapi_json))
+        .route("/docs", get(openapi::swagger_ui));
+    // Inside the CORS layer, so rejections still carry CORS headers
+    let routes = if auth.is_active() {
+        routes.layer(middleware::from_fn_with_state(Arc::new(Authenticator::new(auth)), auth::require_auth))
+    } else {
+        routes
+    };
+    routes
+        .layer(cors)
         // Logging
-        .layer(TraceLayer::new_for_http())
+        .layer(TraceLayer::new_for_http().make_span_with(|request: &axum::http::Request<axum::body::Body>| {
+            // As the default span, but without WebSocket tokens
+            tracing::debug_span!("request",
+                method = %request.method(),
+                uri = %auth::redacted_uri(request.uri()),
+                version = ?request.version())
+        }))
         // Add state
         .with_state(state)
 }
 
 /// Health check endpoint
-async fn health_check() -> &'static str {
+#[utoipa::path(get, path = "/health", responses((status = 200, description = "The server is up", body = String)))]
+pub(crate) async fn health_check() -> &'static str {
     "OK"
 }
 
-/// Start the server on the specified port
-pub async fn serve(port: u16) {
+/// Load every model file in `dir` into the state, keyed by file stem
+///
+/// Files that fail to parse are logged and skipped.
+pub async fn load_model_dir(state: &AppState, dir: &std::path::Path) -> Result<usize, String> {
+    let entries = std::fs::read_dir(dir)
+        .map_err(|e| format!("Failed to read model directory '{}': {}", dir.display(), e))?;
+
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_file())
+        .collect();
+    paths.sort();
+
+    let mut loaded = 0;
+    for path in paths {
+        let Some(id) = path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        match crate::io::load_model(&path) {
+            Ok(model) => {
+                if state.insert_model(id.to_string(), model).await {
+                    tracing::info!("Loaded model '{}' from {}", id, path.display());
+                    loaded += 1;
+                } else {
+                    tracing::warn!("Skipping {}: model id '{}' already loaded", path.display(), id);
+                }
+            }
+            Err(e) => tracing::warn!("Skipping {}: {}", path.display(), e),
+        }
+    }
+
+    Ok(loaded)
+}
+
+/// Start the server with the given configuration
+pub async fn serve(config: ServerConfig) -> Result<(), String> {
     // Initialize tracing
     tracing_subscriber::fmt()
         .with_env_filter(
@@ -65,25 +202,96 @@ pub async fn serve(port: u16) {
         )
         .init();
 
-    let app = create_app();
-    let addr = format!("0.0.0.0:{}", port);
+    let state = match &config.data_dir {
+        Some(dir) => {
+            let state = AppState::with_data_dir(dir.clone())?;
+            tracing::info!("Loaded {} runs from {}", state.simulations.read().await.len(), dir.display());
+            state
+        }
+        None => AppState::new(),
+    };
+    let workers = config.workers.unwrap_or_else(|| JobQueue::default().metrics().workers);
+    let state = state.with_queue(JobQueue::new(workers, config.queue_capacity));
+    tracing::info!("Running up to {} simulations at once", workers);
+    if let Some(dir) = &config.model_dir {
+        let loaded = load_model_dir(&state, dir).await?;
+        tracing::info!("Loaded {} models from {}", loaded, dir.display());
+    }
+
+    if config.auth.is_enabled() {
+        tracing::info!("Requiring an API key or bearer token on /api and /ws routes");
+    } else if let Some(limit) = config.auth.rate_limit {
+        tracing::info!("Allowing {} requests per minute from each client address", limit);
+    }
+    let app = router(state, config.cors_layer()?, &config.auth);
+    let addr = format!("0.0.0.0:{}", config.port);
 
     tracing::info!("Starting server on {}", addr);
 
     let listener = tokio::net::TcpListener::bind(&addr)
         .await
-        .expect("Failed to bind to address");
+        .map_err(|e| format!("Failed to bind to {}: {}", addr, e))?;
 
     tracing::info!("Server listening on http://{}", addr);
+    tracing::info!("Dashboard at http://localhost:{}/", config.port);
     tracing::info!("API documentation:");
     tracing::info!("  GET  /health");
     tracing::info!("  GET  /api/models");
     tracing::info!("  POST /api/models");
     tracing::info!("  GET  /api/models/{{id}}/");
     tracing::info!("  GET  /api/models/{{id}}/structure");
+    tracing::info!("  GET  /api/models/{{id}}/parameters");
+    tracing::info!("  DEL  /api/models/{{id}}/");
+    tracing::info!("  GET  /api/simulations");
+    tracing::info!("  POST /api/simulations");
+    tracing::info!("  GET  /api/simulations/{{id}}/");
+    tracing::info!("  GET  /api/simulations/{{id}}/results?format=json|csv");
+    tracing::info!("  POST /api/simulations/{{id}}/cancel");
+    tracing::info!("  DEL  /api/simulations/{{id}}/");
+    tracing::info!("  GET  /api/queue");
+    tracing::info!("  POST /api/sessions");
+    tracing::info!("  POST /api/sessions/{{id}}/advance");
+    tracing::info!("  GET  /api/sessions/{{id}}/results");
+    tracing::info!("  DEL  /api/sessions/{{id}}/");
+    tracing::info!("  GET  /openapi.json (Swagger UI at /docs)");
     tracing::info!("  WS   /ws/simulation/{{id}}/");
 
-    axum::serve(listener, app)
+    axum::serve(listener, app.into_make_service_with_connect_info::<std::net::SocketAddr>())
         .await
-        .expect("Server error");
+        .map_err(|e| format!("Server error: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_load_model_dir() {
+        let dir = std::env::temp_dir().join(format!("rsedsim-models-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::copy("examples/sir_epidemic.yaml", dir.join("sir.yaml")).unwrap();
+        std::fs::write(dir.join("broken.yaml"), "not: [a model").unwrap();
+
+        let state = AppState::new();
+        let loaded = load_model_dir(&state, &dir).await.unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(loaded, 1);
+        assert!(state.get_model("sir").await.is_some());
+    }
+
+    #[test]
+    fn test_cors_origins() {
+        let config = ServerConfig {
+            cors_origins: vec!["http://localhost:3000".to_string()],
+            ..ServerConfig::default()
+        };
+        assert!(config.cors_layer().is_ok());
+
+        let config = ServerConfig {
+            cors_origins: vec!["bad\norigin".to_string()],
+            ..ServerConfig::default()
+        };
+        assert!(config.cors_layer().is_err());
+    }
 }
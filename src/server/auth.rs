@@ -0,0 +1,271 @@
+/// Optional authentication and per-client rate limiting for the HTTP server
+///
+/// Clients present a static API key or an HS256-signed JWT, either as
+/// `Authorization: Bearer <token>`, in an `X-API-Key` header, or, for
+/// WebSocket clients that cannot set headers, as a `token` query parameter.
+/// Requests are counted per key (or per JWT subject, which tokens must then
+/// carry) in one-minute windows; with a rate limit but no keys or secret,
+/// every client may connect and is counted by IP address.
+/// `/health`, the dashboard's files, the API description and CORS preflight
+/// requests are always let through.
+
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use axum::{
+    extract::{ConnectInfo, Query, Request, State},
+    http::{header, Method, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use crate::server::{dashboard, error::AppError};
+
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Who may use the server, and how often
+#[derive(Debug, Clone, Default)]
+pub struct AuthConfig {
+    /// Accepted static API keys
+    pub api_keys: Vec<String>,
+    /// Shared secret for HS256-signed JWTs; the `exp` claim is required
+    pub jwt_secret: Option<String>,
+    /// Requests allowed per client per minute
+    pub rate_limit: Option<u32>,
+}
+
+impl AuthConfig {
+    /// Whether requests need a key or token
+    pub fn is_enabled(&self) -> bool {
+        !self.api_keys.is_empty() || self.jwt_secret.is_some()
+    }
+
+    /// Whether requests pass through [`require_auth`] at all
+    pub fn is_active(&self) -> bool {
+        self.is_enabled() || self.rate_limit.is_some()
+    }
+}
+
+/// Claims read from a JWT
+#[derive(Debug, Deserialize)]
+struct Claims {
+    /// Client the token was issued to; counted for rate limiting
+    sub: Option<String>,
+}
+
+/// Checks credentials and counts requests; shared by every request
+pub struct Authenticator {
+    api_keys: HashSet<String>,
+    jwt: Option<(DecodingKey, Validation)>,
+    rate_limit: Option<u32>,
+    windows: Mutex<RateWindows>,
+}
+
+/// Start of each client's current window and the requests made in it
+struct RateWindows {
+    clients: HashMap<String, (Instant, u32)>,
+    /// When expired windows were last dropped
+    swept: Instant,
+}
+
+impl Authenticator {
+    pub fn new(config: &AuthConfig) -> Self {
+        let jwt = config.jwt_secret.as_ref().map(|secret| {
+            let mut validation = Validation::new(Algorithm::HS256);
+            if config.rate_limit.is_some() {
+                validation.set_required_spec_claims(&["exp", "sub"]);
+            }
+            (DecodingKey::from_secret(secret.as_bytes()), validation)
+        });
+        Self {
+            api_keys: config.api_keys.iter().cloned().collect(),
+            jwt,
+            rate_limit: config.rate_limit,
+            windows: Mutex::new(RateWindows { clients: HashMap::new(), swept: Instant::now() }),
+        }
+    }
+
+    /// The client a token belongs to, if it is a known API key or a valid JWT
+    fn identify(&self, token: &str) -> Result<String, AppError> {
+        if self.api_keys.contains(token) {
+            return Ok(format!("key:{}", token));
+        }
+        if let Some((key, validation)) = &self.jwt {
+            return match jsonwebtoken::decode::<Claims>(token, key, validation) {
+                Ok(data) => Ok(format!("jwt:{}", data.claims.sub.unwrap_or_default())),
+                Err(e) => Err(AppError::Unauthorized(format!("Invalid token: {}", e))),
+            };
+        }
+        Err(AppError::Unauthorized("Invalid API key".into()))
+    }
+
+    /// Count a request from `client`, failing once it is over the limit for this window
+    fn check_rate(&self, client: &str) -> Result<(), AppError> {
+        self.check_rate_at(client, Instant::now())
+    }
+
+    fn check_rate_at(&self, client: &str, now: Instant) -> Result<(), AppError> {
+        let Some(limit) = self.rate_limit else {
+            return Ok(());
+        };
+        let mut windows = self.windows.lock().unwrap();
+        // Forget clients whose window has ended, at most once per window
+        if now.duration_since(windows.swept) >= RATE_WINDOW {
+            windows.clients.retain(|_, (started, _)| now.duration_since(*started) < RATE_WINDOW);
+            windows.swept = now;
+        }
+        let (started, count) = windows.clients.entry(client.to_string()).or_insert((now, 0));
+        if now.duration_since(*started) >= RATE_WINDOW {
+            *started = now;
+            *count = 0;
+        }
+        if *count >= limit {
+            let retry = RATE_WINDOW.saturating_sub(now.duration_since(*started));
+            return Err(AppError::TooManyRequests(format!(
+                "Rate limit of {} requests per minute exceeded; retry in {} s", limit, retry.as_secs() + 1
+            )));
+        }
+        *count += 1;
+        Ok(())
+    }
+}
+
+/// Middleware rejecting requests without valid credentials, or over the rate limit
+pub async fn require_auth(
+    State(auth): State<Arc<Authenticator>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path();
+    let open = matches!(path, "/health" | "/openapi.json" | "/docs") || dashboard::PATHS.contains(&path);
+    if request.method() == Method::OPTIONS || open {
+        return next.run(request).await;
+    }
+    let checked = if auth.api_keys.is_empty() && auth.jwt.is_none() {
+        let address = request.extensions().get::<ConnectInfo<SocketAddr>>().map(|info| info.0.ip());
+        auth.check_rate(&format!("ip:{}", address.map_or_else(|| "unknown".to_string(), |ip| ip.to_string())))
+    } else {
+        match request_token(&request) {
+            Some(token) => auth.identify(&token).and_then(|client| auth.check_rate(&client)),
+            None => Err(AppError::Unauthorized("Missing API key or bearer token".into())),
+        }
+    };
+    match checked {
+        Ok(()) => next.run(request).await,
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Token from the `Authorization` or `X-API-Key` header, or the `token` query parameter
+fn request_token(request: &Request) -> Option<String> {
+    let headers = request.headers();
+    if let Some(bearer) = headers.get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+    {
+        return Some(bearer.trim().to_string());
+    }
+    if let Some(key) = headers.get("x-api-key").and_then(|value| value.to_str().ok()) {
+        return Some(key.trim().to_string());
+    }
+    let Query(mut query) = Query::<HashMap<String, String>>::try_from_uri(request.uri()).ok()?;
+    query.remove("token")
+}
+
+/// `uri` with the value of any `token` query parameter hidden, for logs
+pub fn redacted_uri(uri: &Uri) -> String {
+    let Some(query) = uri.query() else {
+        return uri.to_string();
+    };
+    let query: Vec<&str> = query.split('&')
+        .map(|pair| if pair.starts_with("token=") { "token=REDACTED" } else { pair })
+        .collect();
+    format!("{}?{}", uri.path(), query.join("&"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::Request as HttpRequest};
+    use tower::ServiceExt;
+    use crate::server::state::AppState;
+
+    async fn status(app: &axum::Router, uri: &str, headers: &[(&str, &str)]) -> u16 {
+        let mut request = HttpRequest::builder().uri(uri);
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        app.clone().oneshot(request.body(Body::empty()).unwrap()).await.unwrap().status().as_u16()
+    }
+
+    #[tokio::test]
+    async fn test_api_keys_and_rate_limit() {
+        let config = AuthConfig { api_keys: vec!["class key+1".into()], jwt_secret: None, rate_limit: Some(2) };
+        let app = crate::server::app::router(AppState::new(), tower_http::cors::CorsLayer::permissive(), &config);
+
+        assert_eq!(status(&app, "/health", &[]).await, 200);
+        assert_eq!(status(&app, "/api/models", &[]).await, 401);
+        assert_eq!(status(&app, "/api/models", &[("x-api-key", "wrong")]).await, 401);
+        assert_eq!(status(&app, "/api/models", &[("x-api-key", "class key+1")]).await, 200);
+        // As encodeURIComponent writes it
+        assert_eq!(status(&app, "/api/models?token=class%20key%2B1", &[]).await, 200);
+        assert_eq!(status(&app, "/api/models", &[("authorization", "Bearer class key+1")]).await, 429);
+    }
+
+    #[test]
+    fn test_expired_windows_are_dropped() {
+        let auth = Authenticator::new(&AuthConfig { rate_limit: Some(1), ..AuthConfig::default() });
+        let start = Instant::now();
+        auth.check_rate_at("a", start).unwrap();
+        assert!(auth.check_rate_at("a", start + Duration::from_secs(1)).is_err());
+
+        auth.check_rate_at("b", start + RATE_WINDOW + Duration::from_secs(1)).unwrap();
+        let windows = auth.windows.lock().unwrap();
+        assert_eq!(windows.clients.keys().collect::<Vec<_>>(), ["b"]);
+    }
+
+    #[test]
+    fn test_redacted_uri() {
+        let uri: Uri = "/ws/simulations?token=s3cret&decimation=5".parse().unwrap();
+        assert_eq!(redacted_uri(&uri), "/ws/simulations?token=REDACTED&decimation=5");
+        assert_eq!(redacted_uri(&"/api/models".parse().unwrap()), "/api/models");
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_by_address() {
+        let config = AuthConfig { api_keys: vec![], jwt_secret: None, rate_limit: Some(1) };
+        let app = crate::server::app::router(AppState::new(), tower_http::cors::CorsLayer::permissive(), &config);
+        let from = |address: &str| {
+            let mut request = HttpRequest::builder().uri("/api/models").body(Body::empty()).unwrap();
+            request.extensions_mut().insert(ConnectInfo(address.parse::<SocketAddr>().unwrap()));
+            let app = app.clone();
+            async move { app.oneshot(request).await.unwrap().status().as_u16() }
+        };
+
+        assert_eq!(from("10.0.0.1:4000").await, 200);
+        // Another port on the same host is the same client
+        assert_eq!(from("10.0.0.1:4001").await, 429);
+        assert_eq!(from("10.0.0.2:4000").await, 200);
+    }
+
+    #[tokio::test]
+    async fn test_jwt() {
+        use jsonwebtoken::{encode, EncodingKey, Header};
+
+        let config = AuthConfig { api_keys: vec![], jwt_secret: Some("s3cret".into()), rate_limit: Some(10) };
+        let app = crate::server::app::router(AppState::new(), tower_http::cors::CorsLayer::permissive(), &config);
+        let exp = chrono::Utc::now().timestamp() + 600;
+        let sign = |secret: &[u8], claims: serde_json::Value| {
+            format!("Bearer {}", encode(&Header::default(), &claims, &EncodingKey::from_secret(secret)).unwrap())
+        };
+        let claims = serde_json::json!({"sub": "team-a", "exp": exp});
+
+        assert_eq!(status(&app, "/api/models", &[("authorization", &sign(b"s3cret", claims.clone()))]).await, 200);
+        assert_eq!(status(&app, "/api/models", &[("authorization", &sign(b"other", claims))]).await, 401);
+        // Rate limits count subjects, so a token must name one
+        let anonymous = serde_json::json!({"exp": exp});
+        assert_eq!(status(&app, "/api/models", &[("authorization", &sign(b"s3cret", anonymous))]).await, 401);
+    }
+}
@@ -0,0 +1,61 @@
+/// Web dashboard served at `/`
+///
+/// A single page, compiled into the binary, for picking or uploading a
+/// model, setting its parameters with sliders and plotting runs live from
+/// the simulation WebSocket. It needs no build step or internet access, so
+/// `rsedsim serve` works on its own in a classroom.
+
+use axum::{http::header, response::{Html, IntoResponse}};
+
+const INDEX: &str = include_str!("dashboard/index.html");
+const SCRIPT: &str = include_str!("dashboard/dashboard.js");
+const STYLE: &str = include_str!("dashboard/dashboard.css");
+
+/// Paths of the dashboard's files, which are served without credentials
+pub const PATHS: [&str; 3] = ["/", "/dashboard.js", "/dashboard.css"];
+
+pub async fn index() -> Html<&'static str> {
+    Html(INDEX)
+}
+
+pub async fn script() -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "text/javascript; charset=utf-8")], SCRIPT)
+}
+
+pub async fn style() -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "text/css; charset=utf-8")], STYLE)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request};
+    use tower::ServiceExt;
+    use crate::server::{state::AppState, AuthConfig};
+
+    async fn get(app: &axum::Router, uri: &str) -> (u16, String) {
+        let response = app.clone().oneshot(Request::get(uri).body(Body::empty()).unwrap()).await.unwrap();
+        let status = response.status().as_u16();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_dashboard_and_parameters() {
+        let state = AppState::new();
+        state.insert_model("sir".to_string(), crate::io::load_model("examples/sir_epidemic.yaml").unwrap()).await;
+        // The page loads without credentials even when the API needs them
+        let auth = AuthConfig { api_keys: vec!["key".into()], ..AuthConfig::default() };
+        let app = crate::server::app::router(state, tower_http::cors::CorsLayer::permissive(), &auth);
+
+        let (status, page) = get(&app, "/").await;
+        assert_eq!(status, 200);
+        assert!(page.contains("/dashboard.js"));
+        assert_eq!(get(&app, "/dashboard.js").await.0, 200);
+
+        let (status, body) = get(&app, "/api/models/sir/parameters?token=key").await;
+        assert_eq!(status, 200);
+        let parameters: Vec<crate::server::types::ParameterInfo> = serde_json::from_str(&body).unwrap();
+        assert!(parameters.iter().any(|p| p.name == "contact_rate"));
+        assert!(parameters.windows(2).all(|pair| pair[0].name < pair[1].name));
+    }
+}
@@ -0,0 +1,291 @@
+// Dashboard served by `rsedsim serve`: pick or upload a model, set parameters
+// with sliders and watch runs streamed over the simulation WebSocket.
+"use strict";
+
+const COLORS = ["#2680c2", "#e12d39", "#3ebd93", "#f7c948", "#8662c7", "#f0b429", "#0c6b58", "#9446ed"];
+
+const $ = (id) => document.getElementById(id);
+
+const state = {
+  socket: null,
+  modelId: null,
+  running: false,
+  timeConfig: null,
+  variables: [],
+  times: [],
+  series: {},
+};
+
+// Servers started with --api-key or --jwt-secret need a token on every request
+function apiKey() {
+  return localStorage.getItem("rssdsim-api-key") || "";
+}
+
+async function api(path, options = {}) {
+  const headers = options.headers || {};
+  if (apiKey()) {
+    headers["X-API-Key"] = apiKey();
+  }
+  const response = await fetch(path, { ...options, headers });
+  if (!response.ok) {
+    const body = await response.json().catch(() => ({}));
+    throw new Error(body.error || `${response.status} ${response.statusText}`);
+  }
+  return response.json();
+}
+
+function setStatus(text) {
+  $("status").textContent = text;
+}
+
+async function loadModels(selected) {
+  const models = await api("/api/models");
+  models.sort((a, b) => a.name.localeCompare(b.name));
+  const select = $("models");
+  select.innerHTML = "";
+  for (const model of models) {
+    const option = document.createElement("option");
+    option.value = model.id;
+    option.textContent = `${model.name} (${model.id.slice(0, 8)})`;
+    select.appendChild(option);
+  }
+  if (models.length === 0) {
+    setStatus("No models loaded; upload one or start the server with --model-dir");
+    return;
+  }
+  select.value = selected && models.some((m) => m.id === selected) ? selected : models[0].id;
+  await selectModel(select.value);
+}
+
+async function selectModel(id) {
+  state.modelId = id;
+  await loadParameters(id);
+  connect(id);
+}
+
+async function loadParameters(id) {
+  const parameters = await api(`/api/models/${encodeURIComponent(id)}/parameters`);
+  const container = $("parameters");
+  container.innerHTML = "";
+  for (const parameter of parameters) {
+    const span = parameter.value === 0 ? 1 : Math.abs(parameter.value) * 2;
+    const min = parameter.value < 0 ? -span : 0;
+    const row = document.createElement("div");
+    row.className = "parameter";
+    row.innerHTML = `<div class="name"><span></span><output></output></div>
+      <input type="range" min="${min}" max="${span}" step="${span / 200}" value="${parameter.value}">`;
+    row.querySelector("span").textContent = parameter.units ? `${parameter.name} (${parameter.units})` : parameter.name;
+    if (parameter.description) {
+      row.title = parameter.description;
+    }
+    const input = row.querySelector("input");
+    const output = row.querySelector("output");
+    input.dataset.name = parameter.name;
+    output.textContent = format(parameter.value);
+    input.addEventListener("input", () => {
+      output.textContent = format(Number(input.value));
+      if (state.running) {
+        send({ type: "set_parameter", parameter: parameter.name, value: Number(input.value) });
+      }
+    });
+    container.appendChild(row);
+  }
+}
+
+function connect(id) {
+  if (state.socket) {
+    state.socket.onclose = null;
+    state.socket.close();
+  }
+  const protocol = location.protocol === "https:" ? "wss:" : "ws:";
+  const token = apiKey() ? `?token=${encodeURIComponent(apiKey())}` : "";
+  const socket = new WebSocket(`${protocol}//${location.host}/ws/simulation/${encodeURIComponent(id)}/${token}`);
+  socket.onopen = () => setStatus("Ready");
+  socket.onmessage = (event) => handle(JSON.parse(event.data));
+  socket.onclose = () => {
+    setRunning(false);
+    setStatus("Disconnected");
+  };
+  state.socket = socket;
+}
+
+function send(message) {
+  if (state.socket && state.socket.readyState === WebSocket.OPEN) {
+    state.socket.send(JSON.stringify(message));
+  }
+}
+
+function run() {
+  const params = {};
+  for (const input of $("parameters").querySelectorAll("input")) {
+    params[input.dataset.name] = Number(input.value);
+  }
+  send({ type: "run", integrator: $("integrator").value, params, decimation: 1 });
+  setRunning(true);
+}
+
+function setRunning(running) {
+  state.running = running;
+  $("run").disabled = running;
+  $("cancel").disabled = !running;
+}
+
+function handle(message) {
+  switch (message.type) {
+    case "start":
+      state.timeConfig = message.time_config;
+      state.variables = message.variables;
+      state.times = [];
+      state.series = Object.fromEntries(message.variables.map((name) => [name, []]));
+      $("progress").value = 0;
+      setStatus(`Running ${message.model_name}`);
+      drawLegend();
+      break;
+    case "data":
+      state.times.push(message.time);
+      for (const name of state.variables) {
+        state.series[name].push(message.values[name] ?? NaN);
+      }
+      scheduleDraw();
+      break;
+    case "progress":
+      $("progress").value = message.fraction;
+      break;
+    case "complete":
+      $("progress").value = 1;
+      setStatus(`Finished ${message.total_steps} steps in ${message.elapsed_ms} ms`);
+      setRunning(false);
+      break;
+    case "cancelled":
+      setStatus(`Stopped at t=${format(message.time)}`);
+      setRunning(false);
+      break;
+    case "error":
+      setStatus(`Error: ${message.message}`);
+      setRunning(false);
+      break;
+  }
+}
+
+let drawPending = false;
+
+function scheduleDraw() {
+  if (!drawPending) {
+    drawPending = true;
+    requestAnimationFrame(() => {
+      drawPending = false;
+      draw();
+    });
+  }
+}
+
+function drawLegend() {
+  const legend = $("legend");
+  legend.innerHTML = "";
+  state.variables.forEach((name, i) => {
+    const span = document.createElement("span");
+    span.style.setProperty("--color", COLORS[i % COLORS.length]);
+    span.textContent = name;
+    legend.appendChild(span);
+  });
+}
+
+function draw() {
+  const canvas = $("chart");
+  const ratio = window.devicePixelRatio || 1;
+  canvas.width = canvas.clientWidth * ratio;
+  canvas.height = canvas.clientHeight * ratio;
+  const ctx = canvas.getContext("2d");
+  ctx.scale(ratio, ratio);
+  const width = canvas.clientWidth;
+  const height = canvas.clientHeight;
+  const margin = { left: 60, right: 16, top: 12, bottom: 32 };
+  ctx.clearRect(0, 0, width, height);
+  if (!state.timeConfig) {
+    return;
+  }
+
+  let low = 0;
+  let high = 1;
+  for (const series of Object.values(state.series)) {
+    for (const value of series) {
+      if (Number.isFinite(value)) {
+        low = Math.min(low, value);
+        high = Math.max(high, value);
+      }
+    }
+  }
+  const pad = (high - low) * 0.05;
+  high += pad;
+  low = low < 0 ? low - pad : low;
+  const { start, stop } = state.timeConfig;
+  const x = (t) => margin.left + ((t - start) / (stop - start || 1)) * (width - margin.left - margin.right);
+  const y = (v) => height - margin.bottom - ((v - low) / (high - low)) * (height - margin.top - margin.bottom);
+
+  // Axes, with five ticks on each
+  ctx.strokeStyle = "#9fb3c8";
+  ctx.fillStyle = "#486581";
+  ctx.font = "12px system-ui, sans-serif";
+  ctx.beginPath();
+  ctx.moveTo(margin.left, margin.top);
+  ctx.lineTo(margin.left, height - margin.bottom);
+  ctx.lineTo(width - margin.right, height - margin.bottom);
+  ctx.stroke();
+  for (let i = 0; i <= 4; i++) {
+    const t = start + ((stop - start) * i) / 4;
+    const v = low + ((high - low) * i) / 4;
+    ctx.textAlign = "center";
+    ctx.fillText(format(t), x(t), height - margin.bottom + 16);
+    ctx.textAlign = "right";
+    ctx.fillText(format(v), margin.left - 6, y(v) + 4);
+  }
+  ctx.textAlign = "center";
+  ctx.fillText(state.timeConfig.units, width / 2, height - 4);
+
+  state.variables.forEach((name, i) => {
+    ctx.strokeStyle = COLORS[i % COLORS.length];
+    ctx.lineWidth = 2;
+    ctx.beginPath();
+    state.series[name].forEach((value, row) => {
+      const point = [x(state.times[row]), y(value)];
+      row === 0 ? ctx.moveTo(...point) : ctx.lineTo(...point);
+    });
+    ctx.stroke();
+  });
+}
+
+function format(value) {
+  return Math.abs(value) >= 1e4 || (value !== 0 && Math.abs(value) < 1e-2)
+    ? value.toExponential(2)
+    : Number(value.toFixed(3)).toString();
+}
+
+async function upload(file) {
+  const form = new FormData();
+  form.append("file", file);
+  const model = await api("/api/models", { method: "POST", body: form });
+  await loadModels(model.id);
+}
+
+function report(error) {
+  setStatus(`Error: ${error.message}`);
+}
+
+$("models").addEventListener("change", (event) => selectModel(event.target.value).catch(report));
+$("upload").addEventListener("change", (event) => {
+  if (event.target.files.length > 0) {
+    upload(event.target.files[0]).catch(report);
+  }
+});
+$("run").addEventListener("click", run);
+$("cancel").addEventListener("click", () => send({ type: "cancel" }));
+$("api-key").addEventListener("click", () => {
+  const key = prompt("API key or token", apiKey());
+  if (key !== null) {
+    localStorage.setItem("rssdsim-api-key", key.trim());
+    loadModels(state.modelId).catch(report);
+  }
+});
+window.addEventListener("resize", scheduleDraw);
+
+loadModels().catch(report);
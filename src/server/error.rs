@@ -3,12 +3,27 @@ use axum::{
     response::{IntoResponse, Response},
     Json,
 };
-use serde_json::json;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Body of every error response
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorResponse {
+    pub error: String,
+}
 
 #[derive(Debug)]
 pub enum AppError {
     NotFound(String),
     BadRequest(String),
+    /// Missing or invalid credentials
+    Unauthorized(String),
+    /// The request does not fit the resource's current state
+    Conflict(String),
+    /// The server is too busy to take the request
+    Unavailable(String),
+    /// The client is over its rate limit
+    TooManyRequests(String),
     InternalError(String),
 }
 
@@ -17,10 +32,14 @@ impl IntoResponse for AppError {
         let (status, message) = match self {
             AppError::NotFound(msg) => (StatusCode::NOT_FOUND, msg),
             AppError::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg),
+            AppError::Unauthorized(msg) => (StatusCode::UNAUTHORIZED, msg),
+            AppError::Conflict(msg) => (StatusCode::CONFLICT, msg),
+            AppError::Unavailable(msg) => (StatusCode::SERVICE_UNAVAILABLE, msg),
+            AppError::TooManyRequests(msg) => (StatusCode::TOO_MANY_REQUESTS, msg),
             AppError::InternalError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
         };
 
-        (status, Json(json!({ "error": message }))).into_response()
+        (status, Json(ErrorResponse { error: message })).into_response()
     }
 }
 
@@ -1,11 +1,17 @@
 pub mod app;
+pub mod auth;
+pub mod dashboard;
 pub mod error;
+pub mod openapi;
+pub mod queue;
 pub mod routes;
 pub mod state;
 pub mod types;
 pub mod websocket;
 
-pub use app::{create_app, serve};
+pub use app::{create_app, load_model_dir, serve, ServerConfig};
+pub use auth::AuthConfig;
 pub use error::AppError;
+pub use queue::{JobQueue, QueueMetrics};
 pub use state::AppState;
 pub use types::*;
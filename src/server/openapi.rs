@@ -0,0 +1,118 @@
+/// OpenAPI description of the REST API, served at `/openapi.json` with a
+/// Swagger UI page at `/docs`
+///
+/// The spec is generated from the `#[utoipa::path]` annotations on the
+/// route handlers and the schemas of the request and response types, so it
+/// stays in step with the code. Clients can be generated from it with any
+/// OpenAPI tool.
+
+use axum::{response::Html, Json};
+use utoipa::{
+    openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme},
+    Modify, OpenApi,
+};
+use crate::server::{error::ErrorResponse, queue::QueueMetrics, routes, types};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "rssdsim", description = "Load system dynamics models and run them in the background."),
+    paths(
+        super::app::health_check,
+        routes::models::list_models,
+        routes::models::upload_model,
+        routes::models::get_model,
+        routes::models::delete_model,
+        routes::models::get_model_structure,
+        routes::models::get_model_parameters,
+        routes::simulations::list_simulations,
+        routes::simulations::start_simulation,
+        routes::simulations::get_status,
+        routes::simulations::stop_simulation,
+        routes::simulations::get_results,
+        routes::simulations::cancel_simulation,
+        routes::simulations::queue_metrics,
+        routes::sessions::start_session,
+        routes::sessions::advance_session,
+        routes::sessions::get_session_results,
+        routes::sessions::delete_session,
+    ),
+    components(schemas(
+        types::ModelInfo,
+        types::ModelUpload,
+        types::ParameterInfo,
+        types::StartSimulationRequest,
+        types::SimulationStatus,
+        types::RunStatus,
+        types::RunResults,
+        types::StartSessionRequest,
+        types::AdvanceSessionRequest,
+        types::SessionState,
+        QueueMetrics,
+        ErrorResponse,
+    )),
+    modifiers(&Credentials),
+    security((), ("bearer" = []), ("api_key" = [])),
+    tags(
+        (name = "models", description = "Upload and inspect models"),
+        (name = "simulations", description = "Queue runs and fetch their results"),
+        (name = "sessions", description = "Play runs in decision intervals"),
+    )
+)]
+pub struct ApiDoc;
+
+/// The ways to authenticate when the server requires it; see [`AuthConfig`](super::AuthConfig)
+struct Credentials;
+
+impl Modify for Credentials {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).bearer_format("API key or JWT").build()),
+        );
+        components.add_security_scheme("api_key", SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("X-API-Key"))));
+    }
+}
+
+/// The OpenAPI document
+pub async fn openapi_json() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// Swagger UI for the OpenAPI document; the UI itself loads from a CDN
+pub async fn swagger_ui() -> Html<&'static str> {
+    Html(r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>rssdsim API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+  <script>SwaggerUIBundle({ url: "/openapi.json", dom_id: "#swagger-ui" });</script>
+</body>
+</html>
+"##)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spec_covers_routes() {
+        let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let paths = spec["paths"].as_object().unwrap();
+        for path in ["/api/models", "/api/models/{id}/", "/api/simulations", "/api/simulations/{id}/results", "/api/queue"] {
+            assert!(paths.contains_key(path), "missing {}", path);
+        }
+        assert!(paths["/api/simulations"]["post"]["requestBody"].is_object());
+        assert_eq!(
+            spec["components"]["schemas"]["RunStatus"]["enum"],
+            serde_json::json!(["queued", "running", "completed", "cancelled", "failed"])
+        );
+        assert!(spec["components"]["securitySchemes"]["bearer"].is_object());
+    }
+}
@@ -0,0 +1,169 @@
+/// Bounded priority queue of blocking jobs, such as simulation runs
+///
+/// At most `workers` jobs run at once, each on tokio's blocking thread pool;
+/// the rest wait, highest priority first and in submission order within a
+/// priority. Submissions beyond `capacity` waiting jobs are refused, so a
+/// burst of Monte Carlo or optimization requests cannot pile up without bound.
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::sync::{Arc, Mutex};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Work to run on a worker
+pub type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// Counts reported by `GET /api/queue`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct QueueMetrics {
+    /// Jobs that may run at once
+    pub workers: usize,
+    /// Jobs that may wait before submissions are refused
+    pub capacity: usize,
+    pub running: usize,
+    pub queued: usize,
+    /// Jobs accepted since the server started
+    pub submitted: u64,
+    /// Jobs that have run to the end, however they ended
+    pub finished: u64,
+    /// Submissions refused because the queue was full
+    pub rejected: u64,
+}
+
+/// Shared handle to the queue; clones refer to the same queue
+#[derive(Clone)]
+pub struct JobQueue {
+    inner: Arc<Mutex<QueueInner>>,
+}
+
+struct QueueInner {
+    pending: BinaryHeap<QueuedJob>,
+    next_sequence: u64,
+    metrics: QueueMetrics,
+}
+
+struct QueuedJob {
+    priority: i32,
+    sequence: u64,
+    job: Job,
+}
+
+impl PartialEq for QueuedJob {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for QueuedJob {}
+
+impl PartialOrd for QueuedJob {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for QueuedJob {
+    /// Higher priority first, then earlier submissions first
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority.cmp(&other.priority)
+            .then_with(|| other.sequence.cmp(&self.sequence))
+    }
+}
+
+impl JobQueue {
+    /// A queue running up to `workers` jobs at once (at least one) with up to `capacity` waiting
+    pub fn new(workers: usize, capacity: usize) -> Self {
+        let metrics = QueueMetrics { workers: workers.max(1), capacity, ..QueueMetrics::default() };
+        Self {
+            inner: Arc::new(Mutex::new(QueueInner {
+                pending: BinaryHeap::new(),
+                next_sequence: 0,
+                metrics,
+            })),
+        }
+    }
+
+    /// Queue `job`, starting it straight away if a worker is free
+    ///
+    /// Must be called from within a tokio runtime. Fails when `capacity`
+    /// jobs are already waiting.
+    pub fn submit(&self, priority: i32, job: impl FnOnce() + Send + 'static) -> Result<(), String> {
+        {
+            let mut inner = self.inner.lock().unwrap();
+            if inner.metrics.running >= inner.metrics.workers && inner.pending.len() >= inner.metrics.capacity {
+                inner.metrics.rejected += 1;
+                return Err(format!("Job queue is full ({} waiting)", inner.pending.len()));
+            }
+            let sequence = inner.next_sequence;
+            inner.next_sequence += 1;
+            inner.metrics.submitted += 1;
+            inner.pending.push(QueuedJob { priority, sequence, job: Box::new(job) });
+        }
+        self.dispatch();
+        Ok(())
+    }
+
+    pub fn metrics(&self) -> QueueMetrics {
+        let inner = self.inner.lock().unwrap();
+        QueueMetrics { queued: inner.pending.len(), ..inner.metrics.clone() }
+    }
+
+    /// Start waiting jobs while there are free workers
+    fn dispatch(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        while inner.metrics.running < inner.metrics.workers {
+            let Some(queued) = inner.pending.pop() else {
+                break;
+            };
+            inner.metrics.running += 1;
+            let queue = self.clone();
+            tokio::task::spawn_blocking(move || {
+                // The job has its own way of reporting failure; a panic still frees the worker
+                let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(queued.job));
+                {
+                    let mut inner = queue.inner.lock().unwrap();
+                    inner.metrics.running -= 1;
+                    inner.metrics.finished += 1;
+                }
+                queue.dispatch();
+            });
+        }
+    }
+}
+
+impl Default for JobQueue {
+    /// One worker per available CPU, with up to 1000 jobs waiting
+    fn default() -> Self {
+        let workers = std::thread::available_parallelism().map_or(1, |n| n.get());
+        Self::new(workers, 1000)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[tokio::test]
+    async fn test_priority_order_and_capacity() {
+        let queue = JobQueue::new(1, 3);
+        let (release, gate) = mpsc::channel::<()>();
+        let (done, order) = mpsc::channel();
+
+        // Holds the only worker until released
+        queue.submit(0, move || gate.recv().unwrap()).unwrap();
+        for (priority, name) in [(0, "low"), (5, "high"), (0, "low-later")] {
+            let done = done.clone();
+            queue.submit(priority, move || done.send(name).unwrap()).unwrap();
+        }
+        assert!(queue.submit(9, || {}).is_err());
+
+        let metrics = queue.metrics();
+        assert_eq!((metrics.running, metrics.queued, metrics.rejected), (1, 3, 1));
+
+        release.send(()).unwrap();
+        let order: Vec<&str> = (0..3).map(|_| order.recv_timeout(std::time::Duration::from_secs(5)).unwrap()).collect();
+        assert_eq!(order, ["high", "low", "low-later"]);
+    }
+}
@@ -2,10 +2,16 @@ use axum::{
     extract::{Multipart, Path, State},
     Json,
 };
-use crate::server::{error::AppError, state::AppState, types::ModelInfo};
+use crate::server::{
+    error::{AppError, ErrorResponse},
+    state::AppState,
+    types::{ModelInfo, ModelUpload, ParameterInfo},
+};
 use crate::{io, model::Model};
 
 /// List all uploaded models
+#[utoipa::path(get, path = "/api/models", tag = "models",
+    responses((status = 200, description = "Every uploaded model", body = [ModelInfo])))]
 pub async fn list_models(State(state): State<AppState>) -> Result<Json<Vec<ModelInfo>>, AppError> {
     let models = state.list_models().await;
 
@@ -24,6 +30,12 @@ pub async fn list_models(State(state): State<AppState>) -> Result<Json<Vec<Model
 }
 
 /// Upload a new model file
+#[utoipa::path(post, path = "/api/models", tag = "models",
+    request_body(content = ModelUpload, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "The stored model", body = ModelInfo),
+        (status = 400, description = "No file, or the file does not parse", body = ErrorResponse),
+    ))]
 pub async fn upload_model(
     State(state): State<AppState>,
     mut multipart: Multipart,
@@ -70,6 +82,11 @@ pub async fn upload_model(
 }
 
 /// Get a specific model by ID
+#[utoipa::path(get, path = "/api/models/{id}/", tag = "models", params(("id" = String, Path, description = "Model id")),
+    responses(
+        (status = 200, body = ModelInfo),
+        (status = 404, body = ErrorResponse),
+    ))]
 pub async fn get_model(
     State(state): State<AppState>,
     Path(id): Path<String>,
@@ -88,6 +105,11 @@ pub async fn get_model(
 }
 
 /// Delete a model
+#[utoipa::path(delete, path = "/api/models/{id}/", tag = "models", params(("id" = String, Path, description = "Model id")),
+    responses(
+        (status = 200, description = "Deleted", body = serde_json::Value),
+        (status = 404, body = ErrorResponse),
+    ))]
 pub async fn delete_model(
     State(state): State<AppState>,
     Path(id): Path<String>,
@@ -100,7 +122,42 @@ pub async fn delete_model(
     Ok(Json(serde_json::json!({ "message": "Model deleted" })))
 }
 
+/// Scalar parameters of a model, sorted by name; these can be set when starting a run
+#[utoipa::path(get, path = "/api/models/{id}/parameters", tag = "models",
+    params(("id" = String, Path, description = "Model id")),
+    responses(
+        (status = 200, body = [ParameterInfo]),
+        (status = 404, body = ErrorResponse),
+    ))]
+pub async fn get_model_parameters(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<ParameterInfo>>, AppError> {
+    let model = state
+        .get_model(&id)
+        .await
+        .ok_or_else(|| AppError::NotFound("Model not found".into()))?;
+
+    let mut parameters: Vec<ParameterInfo> = model.parameters.into_values()
+        .filter(|parameter| parameter.dimensions.is_none())
+        .map(|parameter| ParameterInfo {
+            name: parameter.name,
+            value: parameter.value,
+            units: parameter.units,
+            description: parameter.description,
+        })
+        .collect();
+    parameters.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(Json(parameters))
+}
+
 /// Get model structure with layout
+#[utoipa::path(get, path = "/api/models/{id}/structure", tag = "models", params(("id" = String, Path, description = "Model id")),
+    responses(
+        (status = 200, description = "Stock and flow diagram layout", body = serde_json::Value),
+        (status = 404, body = ErrorResponse),
+    ))]
 pub async fn get_model_structure(
     State(state): State<AppState>,
     Path(id): Path<String>,
@@ -125,16 +182,8 @@ fn parse_model_from_bytes(data: &[u8], filename: &str) -> Result<Model, AppError
             AppError::BadRequest(format!("Failed to parse XMILE: {}", e))
         })
     } else if filename.ends_with(".json") {
-        // Try InsightMaker format first
-        if let Ok(model) = io::insightmaker::parse_insightmaker(&contents) {
-            return Ok(model);
-        }
-
-        // Fall back to standard JSON
-        let json_model: io::parser::JsonModel = serde_json::from_str(&contents)
-            .map_err(|e| AppError::BadRequest(format!("Failed to parse JSON: {}", e)))?;
-
-        io::parser::JsonModel::to_model(json_model)
+        // Native or InsightMaker JSON
+        io::parse_json(&contents)
             .map_err(|e| AppError::BadRequest(format!("Invalid model: {}", e)))
     } else if filename.ends_with(".yaml") || filename.ends_with(".yml") {
         io::parser::parse_yaml(&contents)
@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use uuid::Uuid;
+use crate::error::{Error, SimulationError};
+use crate::server::{
+    error::{AppError, ErrorResponse},
+    state::{AppState, DecisionSession, SessionRun},
+    types::{AdvanceSessionRequest, RunResults, SessionState, StartSessionRequest},
+};
+use crate::simulation::{CancellationToken, IntegrationMethod, SimulationConfig, SimulationEngine, SimulationResults};
+
+/// Most time steps in one decision interval
+const MAX_INTERVAL: usize = 100_000;
+/// Most sessions open at once
+const MAX_SESSIONS: usize = 1000;
+
+/// Start playing a model in decision intervals, as in a management flight
+/// simulator; the first interval is run before this returns
+///
+/// Intervals run on the job queue. Refused with 503 when the queue is full
+/// or too many sessions are open.
+#[utoipa::path(post, path = "/api/sessions", tag = "sessions",
+    request_body = StartSessionRequest,
+    responses(
+        (status = 200, description = "The session after its first interval", body = SessionState),
+        (status = 400, description = "Unknown integrator or parameter, or an interval of no or too many steps", body = ErrorResponse),
+        (status = 404, description = "Model not found", body = ErrorResponse),
+        (status = 503, description = "Job queue is full, or too many sessions are open", body = ErrorResponse),
+    ))]
+pub async fn start_session(
+    State(state): State<AppState>,
+    Json(request): Json<StartSessionRequest>,
+) -> Result<Json<SessionState>, AppError> {
+    let model = state
+        .get_model(&request.model_id)
+        .await
+        .ok_or_else(|| AppError::NotFound("Model not found".into()))?;
+    if request.interval == 0 {
+        return Err(AppError::BadRequest("A decision interval needs at least one step".into()));
+    }
+    if request.interval > MAX_INTERVAL {
+        return Err(AppError::BadRequest(format!("A decision interval has at most {} steps", MAX_INTERVAL)));
+    }
+
+    let integration_method = request.integrator.parse::<IntegrationMethod>().map_err(AppError::BadRequest)?;
+    let config = SimulationConfig { integration_method, ..SimulationConfig::default() };
+    let cancellation = CancellationToken::new();
+    let mut engine = SimulationEngine::new(model, config)
+        .map_err(|e| AppError::BadRequest(format!("Failed to create simulation: {}", e)))?
+        .with_cancellation(cancellation.clone());
+    for (name, value) in request.parameters.iter().flatten() {
+        engine.set_parameter(name, *value).map_err(|e| AppError::BadRequest(e.to_string()))?;
+    }
+
+    let id = Uuid::new_v4().to_string();
+    let session = Arc::new(DecisionSession {
+        model_id: request.model_id,
+        interval: request.interval,
+        cancellation,
+        run: Mutex::new(SessionRun { engine, results: SimulationResults::new() }),
+    });
+    {
+        // Hold a place while the first interval runs, so the cap can't be overshot
+        let mut sessions = state.sessions.write().await;
+        if sessions.len() >= MAX_SESSIONS {
+            return Err(AppError::Unavailable(format!("Too many sessions open ({})", sessions.len())));
+        }
+        sessions.insert(id.clone(), session.clone());
+    }
+    match advance(&state, id.clone(), session, HashMap::new()).await {
+        Ok(current) => Ok(Json(current)),
+        Err(e) => {
+            state.sessions.write().await.remove(&id);
+            Err(e)
+        }
+    }
+}
+
+/// Apply decisions, then run the session's next interval
+///
+/// Changes set parameters, or else the current values of stocks; none is
+/// applied unless every change names one.
+#[utoipa::path(post, path = "/api/sessions/{id}/advance", tag = "sessions", params(("id" = String, Path, description = "Session id")),
+    request_body = AdvanceSessionRequest,
+    responses(
+        (status = 200, description = "The session after the interval", body = SessionState),
+        (status = 400, description = "A change names no parameter or stock, or the interval failed", body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+        (status = 409, description = "Session has reached its stop time, or was deleted during the interval", body = ErrorResponse),
+        (status = 503, description = "Job queue is full", body = ErrorResponse),
+    ))]
+pub async fn advance_session(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(request): Json<AdvanceSessionRequest>,
+) -> Result<Json<SessionState>, AppError> {
+    let session = state.sessions.read().await
+        .get(&id)
+        .cloned()
+        .ok_or_else(|| AppError::NotFound("Session not found".into()))?;
+    Ok(Json(advance(&state, id, session, request.changes).await?))
+}
+
+/// Output of a session so far
+#[utoipa::path(get, path = "/api/sessions/{id}/results", tag = "sessions", params(("id" = String, Path, description = "Session id")),
+    responses(
+        (status = 200, body = RunResults),
+        (status = 404, body = ErrorResponse),
+    ))]
+pub async fn get_session_results(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<RunResults>, AppError> {
+    let sessions = state.sessions.read().await;
+    let session = sessions.get(&id).ok_or_else(|| AppError::NotFound("Session not found".into()))?;
+    let results = RunResults::from(&session.run.lock().unwrap().results);
+    Ok(Json(results))
+}
+
+/// End a session, cancelling any interval it is running
+#[utoipa::path(delete, path = "/api/sessions/{id}/", tag = "sessions", params(("id" = String, Path, description = "Session id")),
+    responses(
+        (status = 200, description = "Deleted", body = serde_json::Value),
+        (status = 404, body = ErrorResponse),
+    ))]
+pub async fn delete_session(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    if let Some(session) = state.sessions.write().await.remove(&id) {
+        session.cancellation.cancel();
+        Ok(Json(serde_json::json!({ "message": "Session deleted" })))
+    } else {
+        Err(AppError::NotFound("Session not found".into()))
+    }
+}
+
+/// Apply `changes` and run one interval on the job queue
+async fn advance(state: &AppState, id: String, session: Arc<DecisionSession>, changes: HashMap<String, f64>) -> Result<SessionState, AppError> {
+    let (done, outcome) = tokio::sync::oneshot::channel();
+    state.queue.submit(0, move || {
+        let _ = done.send(run_interval(id, &session, &changes));
+    }).map_err(AppError::Unavailable)?;
+    outcome.await.map_err(|_| AppError::InternalError("Run aborted".into()))?
+}
+
+fn run_interval(id: String, session: &DecisionSession, changes: &HashMap<String, f64>) -> Result<SessionState, AppError> {
+    let ended = || AppError::Conflict("Session has been deleted".into());
+    if session.cancellation.is_cancelled() {
+        return Err(ended());
+    }
+    let mut run = session.run.lock().unwrap();
+    let SessionRun { engine, results } = &mut *run;
+    if engine.is_finished() {
+        return Err(AppError::Conflict("Session has reached its stop time".into()));
+    }
+    let changes: Vec<(&str, f64)> = changes.iter().map(|(name, value)| (name.as_str(), *value)).collect();
+    engine.set_decisions(&changes).map_err(|e| AppError::BadRequest(e.to_string()))?;
+    engine.run_steps(session.interval, results).map_err(|e| match e {
+        Error::Simulation(SimulationError::Cancelled { .. }) => ended(),
+        e => AppError::BadRequest(e.to_string()),
+    })?;
+
+    let current = engine.current_state();
+    let values = |values: &crate::simulation::VariableValues| values.into_iter().map(|(name, value)| (name.clone(), *value)).collect();
+    Ok(SessionState {
+        id,
+        model_id: session.model_id.clone(),
+        time: current.time,
+        finished: engine.is_finished(),
+        stocks: values(&current.stocks),
+        flows: values(&current.flows),
+        auxiliaries: values(&current.auxiliaries),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::{header, Request}};
+    use tower::ServiceExt;
+    use crate::server::AuthConfig;
+
+    async fn call(app: &axum::Router, method: &str, uri: &str, body: Option<serde_json::Value>) -> (u16, Vec<u8>) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body.map_or_else(Body::empty, |json| Body::from(json.to_string())))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status().as_u16();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, bytes.to_vec())
+    }
+
+    #[tokio::test]
+    async fn test_session_lifecycle() {
+        let state = AppState::new();
+        state.insert_model("sir".to_string(), crate::io::load_model("examples/sir_epidemic.yaml").unwrap()).await;
+        let app = crate::server::app::router(state, tower_http::cors::CorsLayer::permissive(), &AuthConfig::default());
+
+        for interval in [0, MAX_INTERVAL + 1] {
+            let (code, _) = call(&app, "POST", "/api/sessions", Some(serde_json::json!({"model_id": "sir", "interval": interval}))).await;
+            assert_eq!(code, 400);
+        }
+
+        // 400 steps of 0.25 to the stop time, in two intervals
+        let (code, body) = call(&app, "POST", "/api/sessions", Some(serde_json::json!({"model_id": "sir", "interval": 200}))).await;
+        assert_eq!(code, 200);
+        let started: SessionState = serde_json::from_slice(&body).unwrap();
+        assert_eq!((started.time, started.finished), (50.0, false));
+        let advance_uri = format!("/api/sessions/{}/advance", started.id);
+
+        // Nothing is applied when any name is unknown
+        let (code, _) = call(&app, "POST", &advance_uri, Some(serde_json::json!({"changes": {"Susceptible": 0, "nope": 1}}))).await;
+        assert_eq!(code, 400);
+
+        // Curing everyone at once ends the epidemic
+        let changes = serde_json::json!({"changes": {"Infected": 0, "contact_rate": 0}});
+        let (code, body) = call(&app, "POST", &advance_uri, Some(changes)).await;
+        assert_eq!(code, 200);
+        let ended: SessionState = serde_json::from_slice(&body).unwrap();
+        assert_eq!((ended.time, ended.finished), (100.0, true));
+        assert_eq!(ended.stocks["Infected"], 0.0);
+        assert_eq!(ended.stocks["Susceptible"], started.stocks["Susceptible"]);
+
+        let (code, _) = call(&app, "POST", &advance_uri, Some(serde_json::json!({}))).await;
+        assert_eq!(code, 409);
+
+        let (code, body) = call(&app, "GET", &format!("/api/sessions/{}/results", started.id), None).await;
+        assert_eq!(code, 200);
+        let results: RunResults = serde_json::from_slice(&body).unwrap();
+        assert_eq!((results.times.first(), results.times.last()), (Some(&0.0), Some(&100.0)));
+
+        let (code, _) = call(&app, "DELETE", &format!("/api/sessions/{}/", started.id), None).await;
+        assert_eq!(code, 200);
+        let (code, _) = call(&app, "POST", &advance_uri, Some(serde_json::json!({}))).await;
+        assert_eq!(code, 404);
+    }
+}
@@ -1,40 +1,117 @@
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
+    http::header,
+    response::{IntoResponse, Response},
     Json,
 };
 use uuid::Uuid;
+use crate::error::{Error, SimulationError};
 use crate::server::{
-    error::AppError,
-    state::AppState,
-    types::{SimulationStatus, StartSimulationRequest},
+    error::{AppError, ErrorResponse},
+    queue::QueueMetrics,
+    state::{AppState, RunHandle},
+    types::{ResultsQuery, RunResults, RunStatus, SimulationStatus, StartSimulationRequest},
 };
+use crate::simulation::{IntegrationMethod, SimulationConfig, SimulationEngine, SimulationResults};
 
-/// Start a new simulation
+/// Queue a run to go in the background; poll its status with the returned id
+///
+/// Refused with 503 when the job queue is full. For streamed runs, connect
+/// to the WebSocket endpoint instead.
+#[utoipa::path(post, path = "/api/simulations", tag = "simulations",
+    request_body = StartSimulationRequest,
+    responses(
+        (status = 200, description = "The queued run", body = SimulationStatus),
+        (status = 400, description = "Unknown integrator or parameter", body = ErrorResponse),
+        (status = 404, description = "Model not found", body = ErrorResponse),
+        (status = 503, description = "Job queue is full", body = ErrorResponse),
+    ))]
 pub async fn start_simulation(
     State(state): State<AppState>,
     Json(request): Json<StartSimulationRequest>,
 ) -> Result<Json<SimulationStatus>, AppError> {
-    // Verify model exists
-    let _model = state
+    let model = state
         .get_model(&request.model_id)
         .await
         .ok_or_else(|| AppError::NotFound("Model not found".into()))?;
 
-    let sim_id = Uuid::new_v4().to_string();
-
-    // For streaming simulations, client should connect to WebSocket endpoint
-    // For non-streaming, we would run the simulation here and return results
+    let integration_method = request.integrator.parse::<IntegrationMethod>().map_err(AppError::BadRequest)?;
+    let config = SimulationConfig { integration_method, ..SimulationConfig::default() };
+    let start_time = model.time.start;
+    let mut engine = SimulationEngine::new(model, config)
+        .map_err(|e| AppError::BadRequest(format!("Failed to create simulation: {}", e)))?;
+    for (name, value) in request.parameters.iter().flatten() {
+        engine.set_parameter(name, *value).map_err(|e| AppError::BadRequest(e.to_string()))?;
+    }
 
-    Ok(Json(SimulationStatus {
-        id: sim_id,
+    let status = SimulationStatus {
+        id: Uuid::new_v4().to_string(),
         model_id: request.model_id,
-        status: "created".into(),
+        status: RunStatus::Queued,
         progress: 0.0,
-        current_time: 0.0,
-    }))
+        current_time: start_time,
+        created_at: chrono::Utc::now().timestamp(),
+        error: None,
+    };
+    let handle = RunHandle::new(status.clone());
+    let progress = handle.progress.clone();
+    let started = handle.started.clone();
+    let cancellation = handle.cancellation.clone();
+    let mut engine = engine
+        .with_progress(move |report| *progress.lock().unwrap() = Some(*report))
+        .with_cancellation(cancellation.clone());
+
+    let (done, outcome) = tokio::sync::oneshot::channel();
+    state.queue.submit(request.priority, move || {
+        // Cancelled while it was waiting for a worker
+        if cancellation.is_cancelled() {
+            return;
+        }
+        started.store(true, std::sync::atomic::Ordering::Relaxed);
+        let mut results = SimulationResults::new();
+        let outcome = engine.run_into(&mut results);
+        let _ = done.send((outcome, results));
+    }).map_err(AppError::Unavailable)?;
+    state.simulations.write().await.insert(status.id.clone(), handle);
+
+    let id = status.id.clone();
+    let registry = state.clone();
+    tokio::spawn(async move {
+        let (status, error, results) = match outcome.await {
+            Ok((Ok(_), results)) => (RunStatus::Completed, None, Some(RunResults::from(&results))),
+            Ok((Err(Error::Simulation(SimulationError::Cancelled { .. })), results)) => {
+                (RunStatus::Cancelled, None, Some(RunResults::from(&results)))
+            }
+            Ok((Err(e), _)) => (RunStatus::Failed, Some(e.to_string()), None),
+            // Cancelled before it started, or the run panicked
+            Err(_) if registry.is_cancelled(&id).await => (RunStatus::Cancelled, None, None),
+            Err(_) => (RunStatus::Failed, Some("Run aborted".to_string()), None),
+        };
+        tracing::info!("Run {} finished: {:?}", id, status);
+        registry.finish_run(&id, status, error, results).await;
+    });
+
+    Ok(Json(status))
+}
+
+/// List every run, newest first
+#[utoipa::path(get, path = "/api/simulations", tag = "simulations",
+    responses((status = 200, description = "Every run, newest first", body = [SimulationStatus])))]
+pub async fn list_simulations(State(state): State<AppState>) -> Json<Vec<SimulationStatus>> {
+    let mut runs: Vec<SimulationStatus> = state.simulations.read().await
+        .values()
+        .map(RunHandle::current_status)
+        .collect();
+    runs.sort_by(|a, b| b.created_at.cmp(&a.created_at).then_with(|| a.id.cmp(&b.id)));
+    Json(runs)
 }
 
 /// Get simulation status
+#[utoipa::path(get, path = "/api/simulations/{id}/", tag = "simulations", params(("id" = String, Path, description = "Run id")),
+    responses(
+        (status = 200, body = SimulationStatus),
+        (status = 404, body = ErrorResponse),
+    ))]
 pub async fn get_status(
     State(state): State<AppState>,
     Path(id): Path<String>,
@@ -44,26 +121,222 @@ pub async fn get_status(
         .get(&id)
         .ok_or_else(|| AppError::NotFound("Simulation not found".into()))?;
 
-    Ok(Json(SimulationStatus {
-        id: sim.id.clone(),
-        model_id: sim.model_id.clone(),
-        status: format!("{:?}", sim.status),
-        progress: 0.0, // TODO: Calculate from current_time and model.time.stop
-        current_time: sim.current_time,
-    }))
+    Ok(Json(sim.current_status()))
+}
+
+/// Results of a completed or cancelled run, as JSON or, with `?format=csv`, CSV
+#[utoipa::path(get, path = "/api/simulations/{id}/results", tag = "simulations",
+    params(("id" = String, Path, description = "Run id"), ResultsQuery),
+    responses(
+        (status = 200, content((RunResults = "application/json"), (String = "text/csv"))),
+        (status = 404, description = "Run not found, or it failed", body = ErrorResponse),
+        (status = 409, description = "Run has not finished", body = ErrorResponse),
+    ))]
+pub async fn get_results(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<ResultsQuery>,
+) -> Result<Response, AppError> {
+    let results = {
+        let simulations = state.simulations.read().await;
+        let sim = simulations
+            .get(&id)
+            .ok_or_else(|| AppError::NotFound("Simulation not found".into()))?;
+        match (&sim.results, sim.status.status) {
+            (Some(results), _) => results.clone(),
+            (None, RunStatus::Queued | RunStatus::Running) => {
+                return Err(AppError::Conflict("Simulation has not finished".into()))
+            }
+            (None, _) => return Err(AppError::NotFound("Simulation has no results".into())),
+        }
+    };
+
+    match query.format.as_deref() {
+        None | Some("json") => Ok(Json(results.as_ref()).into_response()),
+        Some("csv") => Ok(([(header::CONTENT_TYPE, "text/csv")], results.to_csv()).into_response()),
+        Some(other) => Err(AppError::BadRequest(format!("Unknown results format '{}'", other))),
+    }
+}
+
+/// Cancel a queued or running simulation; the results of a running one up to now are kept
+#[utoipa::path(post, path = "/api/simulations/{id}/cancel", tag = "simulations", params(("id" = String, Path, description = "Run id")),
+    responses(
+        (status = 200, body = SimulationStatus),
+        (status = 404, body = ErrorResponse),
+        (status = 409, description = "Run has already finished", body = ErrorResponse),
+    ))]
+pub async fn cancel_simulation(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<SimulationStatus>, AppError> {
+    let simulations = state.simulations.read().await;
+    let sim = simulations
+        .get(&id)
+        .ok_or_else(|| AppError::NotFound("Simulation not found".into()))?;
+    if !matches!(sim.status.status, RunStatus::Queued | RunStatus::Running) {
+        return Err(AppError::Conflict("Simulation is not running".into()));
+    }
+    sim.cancellation.cancel();
+
+    Ok(Json(sim.current_status()))
+}
+
+/// Counts of queued, running and finished jobs
+#[utoipa::path(get, path = "/api/queue", tag = "simulations",
+    responses((status = 200, body = QueueMetrics)))]
+pub async fn queue_metrics(State(state): State<AppState>) -> Json<QueueMetrics> {
+    Json(state.queue.metrics())
 }
 
-/// Stop a running simulation
+/// Delete a simulation and its results, stopping it if it is running
+#[utoipa::path(delete, path = "/api/simulations/{id}/", tag = "simulations", params(("id" = String, Path, description = "Run id")),
+    responses(
+        (status = 200, description = "Deleted", body = serde_json::Value),
+        (status = 404, body = ErrorResponse),
+    ))]
 pub async fn stop_simulation(
     State(state): State<AppState>,
     Path(id): Path<String>,
 ) -> Result<Json<serde_json::Value>, AppError> {
-    let mut simulations = state.simulations.write().await;
-
-    if let Some(sim) = simulations.remove(&id) {
-        sim.abort_handle.abort();
-        Ok(Json(serde_json::json!({ "message": "Simulation stopped" })))
+    if state.remove_run(&id).await.is_some() {
+        Ok(Json(serde_json::json!({ "message": "Simulation deleted" })))
     } else {
         Err(AppError::NotFound("Simulation not found".into()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::Request};
+    use tower::ServiceExt;
+    use crate::server::AuthConfig;
+
+    async fn call(app: &axum::Router, method: &str, uri: &str, body: Option<serde_json::Value>) -> (u16, Vec<u8>) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body.map_or_else(Body::empty, |json| Body::from(json.to_string())))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status().as_u16();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, bytes.to_vec())
+    }
+
+    async fn wait_until_finished(app: &axum::Router, id: &str) -> serde_json::Value {
+        for _ in 0..500 {
+            let (_, body) = call(app, "GET", &format!("/api/simulations/{}/", id), None).await;
+            let status: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            if status["status"] != "queued" && status["status"] != "running" {
+                return status;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        panic!("run {} did not finish", id);
+    }
+
+    #[tokio::test]
+    async fn test_run_lifecycle() {
+        let dir = std::env::temp_dir().join(format!("rsedsim-runs-{}", std::process::id()));
+        let state = AppState::with_data_dir(dir.clone()).unwrap();
+        state.insert_model("sir".to_string(), crate::io::load_model("examples/sir_epidemic.yaml").unwrap()).await;
+        let app = crate::server::app::router(state, tower_http::cors::CorsLayer::permissive(), &AuthConfig::default());
+
+        let (code, _) = call(&app, "POST", "/api/simulations", Some(serde_json::json!({"model_id": "sir", "integrator": "nope"}))).await;
+        assert_eq!(code, 400);
+
+        let request = serde_json::json!({"model_id": "sir", "integrator": "rk4", "parameters": {"contact_rate": 4}});
+        let (code, body) = call(&app, "POST", "/api/simulations", Some(request)).await;
+        assert_eq!(code, 200);
+        let started: SimulationStatus = serde_json::from_slice(&body).unwrap();
+        let status = wait_until_finished(&app, &started.id).await;
+        assert_eq!(status["status"], "completed");
+        assert_eq!(status["progress"], 1.0);
+
+        let (code, body) = call(&app, "GET", &format!("/api/simulations/{}/results", started.id), None).await;
+        assert_eq!(code, 200);
+        let results: RunResults = serde_json::from_slice(&body).unwrap();
+        assert_eq!(results.times.len(), results.series["Infected"].len());
+        assert_eq!(results.times.last(), Some(&100.0));
+
+        let (code, body) = call(&app, "GET", &format!("/api/simulations/{}/results?format=csv", started.id), None).await;
+        assert_eq!(code, 200);
+        let csv = String::from_utf8(body).unwrap();
+        assert!(csv.starts_with("Time,"));
+        assert_eq!(csv.lines().count(), results.times.len() + 1);
+
+        let (_, body) = call(&app, "GET", "/api/simulations", None).await;
+        let runs: Vec<SimulationStatus> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(runs.len(), 1);
+
+        // The finished run is saved and comes back with a new state
+        let reloaded = AppState::with_data_dir(dir.clone()).unwrap();
+        assert!(reloaded.simulations.read().await[&started.id].results.is_some());
+
+        let (code, _) = call(&app, "DELETE", &format!("/api/simulations/{}/", started.id), None).await;
+        assert_eq!(code, 200);
+        let (code, _) = call(&app, "GET", &format!("/api/simulations/{}/", started.id), None).await;
+        assert_eq!(code, 404);
+        assert!(AppState::with_data_dir(dir.clone()).unwrap().simulations.read().await.is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_cancel_run() {
+        let state = AppState::new();
+        let mut model = crate::io::load_model("examples/sir_epidemic.yaml").unwrap();
+        model.time.stop = 1.0e7;
+        state.insert_model("long".to_string(), model).await;
+        let app = crate::server::app::router(state, tower_http::cors::CorsLayer::permissive(), &AuthConfig::default());
+
+        let (_, body) = call(&app, "POST", "/api/simulations", Some(serde_json::json!({"model_id": "long"}))).await;
+        let started: SimulationStatus = serde_json::from_slice(&body).unwrap();
+        // Wait for a worker to pick it up, so there are results to keep
+        loop {
+            let (_, body) = call(&app, "GET", &format!("/api/simulations/{}/", started.id), None).await;
+            if serde_json::from_slice::<SimulationStatus>(&body).unwrap().status == RunStatus::Running {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        }
+        let (code, _) = call(&app, "POST", &format!("/api/simulations/{}/cancel", started.id), None).await;
+        assert_eq!(code, 200);
+
+        let status = wait_until_finished(&app, &started.id).await;
+        assert_eq!(status["status"], "cancelled");
+        let (code, _) = call(&app, "GET", &format!("/api/simulations/{}/results", started.id), None).await;
+        assert_eq!(code, 200);
+    }
+
+    #[tokio::test]
+    async fn test_queued_runs() {
+        let state = AppState::new().with_queue(crate::server::JobQueue::new(1, 1));
+        let mut model = crate::io::load_model("examples/sir_epidemic.yaml").unwrap();
+        model.time.stop = 1.0e7;
+        state.insert_model("long".to_string(), model).await;
+        let app = crate::server::app::router(state, tower_http::cors::CorsLayer::permissive(), &AuthConfig::default());
+
+        let start = Some(serde_json::json!({"model_id": "long"}));
+        let (_, body) = call(&app, "POST", "/api/simulations", start.clone()).await;
+        let running: SimulationStatus = serde_json::from_slice(&body).unwrap();
+        let (_, body) = call(&app, "POST", "/api/simulations", start.clone()).await;
+        let queued: SimulationStatus = serde_json::from_slice(&body).unwrap();
+        assert_eq!(queued.status, RunStatus::Queued);
+        let (code, _) = call(&app, "POST", "/api/simulations", start).await;
+        assert_eq!(code, 503);
+
+        let (_, body) = call(&app, "GET", "/api/queue", None).await;
+        let metrics: QueueMetrics = serde_json::from_slice(&body).unwrap();
+        assert_eq!((metrics.running, metrics.queued, metrics.rejected), (1, 1, 1));
+
+        // Cancelling the waiting run never starts it
+        call(&app, "POST", &format!("/api/simulations/{}/cancel", queued.id), None).await;
+        call(&app, "POST", &format!("/api/simulations/{}/cancel", running.id), None).await;
+        assert_eq!(wait_until_finished(&app, &running.id).await["status"], "cancelled");
+        assert_eq!(wait_until_finished(&app, &queued.id).await["status"], "cancelled");
+        let (code, _) = call(&app, "GET", &format!("/api/simulations/{}/results", queued.id), None).await;
+        assert_eq!(code, 404);
+    }
+}
@@ -1,13 +1,25 @@
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use serde::{Deserialize, Serialize};
 use tokio::sync::RwLock;
 use uuid::Uuid;
 use crate::model::Model;
+use crate::server::queue::JobQueue;
+use crate::server::types::{RunResults, RunStatus, SimulationStatus};
+use crate::simulation::{CancellationToken, Progress, SimulationEngine, SimulationResults};
 
 #[derive(Clone)]
 pub struct AppState {
     pub models: Arc<RwLock<HashMap<String, StoredModel>>>,
-    pub simulations: Arc<RwLock<HashMap<String, SimulationHandle>>>,
+    pub simulations: Arc<RwLock<HashMap<String, RunHandle>>>,
+    /// Where finished runs are saved, one `runs/{id}.json` file each
+    pub data_dir: Option<PathBuf>,
+    /// Runs waiting for, or using, a worker
+    pub queue: JobQueue,
+    /// Runs played in decision intervals, by session id
+    pub sessions: Arc<RwLock<HashMap<String, Arc<DecisionSession>>>>,
 }
 
 #[derive(Clone)]
@@ -17,20 +29,67 @@ pub struct StoredModel {
     pub created_at: i64,
 }
 
-pub struct SimulationHandle {
-    pub id: String,
-    pub model_id: String,
+/// A run in the registry, in progress or finished
+pub struct RunHandle {
     pub status: SimulationStatus,
-    pub current_time: f64,
-    pub abort_handle: tokio::task::AbortHandle,
+    /// Latest progress report from the engine while the run is going
+    pub progress: Arc<Mutex<Option<Progress>>>,
+    pub cancellation: CancellationToken,
+    /// Set by the worker when a queued run starts
+    pub started: Arc<AtomicBool>,
+    /// Set once the run has completed or been cancelled
+    pub results: Option<Arc<RunResults>>,
+}
+
+impl RunHandle {
+    pub fn new(status: SimulationStatus) -> Self {
+        Self {
+            status,
+            progress: Arc::default(),
+            cancellation: CancellationToken::new(),
+            started: Arc::default(),
+            results: None,
+        }
+    }
+
+    /// The status with the latest progress report filled in
+    pub fn current_status(&self) -> SimulationStatus {
+        let mut status = self.status.clone();
+        if status.status == RunStatus::Queued && self.started.load(Ordering::Relaxed) {
+            status.status = RunStatus::Running;
+        }
+        if status.status == RunStatus::Running
+            && let Some(progress) = *self.progress.lock().unwrap()
+        {
+            status.progress = progress.fraction;
+            status.current_time = progress.time;
+        }
+        status
+    }
 }
 
-#[derive(Debug, Clone)]
-pub enum SimulationStatus {
-    Running,
-    Paused,
-    Completed,
-    Error(String),
+/// A run played in decision intervals through `/api/sessions`
+pub struct DecisionSession {
+    pub model_id: String,
+    /// Time steps run between decisions
+    pub interval: usize,
+    /// Stops a running interval when the session is deleted
+    pub cancellation: CancellationToken,
+    /// Locked while an interval runs
+    pub run: Mutex<SessionRun>,
+}
+
+/// The engine of a decision session and its output so far
+pub struct SessionRun {
+    pub engine: SimulationEngine,
+    pub results: SimulationResults,
+}
+
+/// A finished run as saved under the data directory
+#[derive(Serialize, Deserialize)]
+struct SavedRun {
+    status: SimulationStatus,
+    results: Option<RunResults>,
 }
 
 impl AppState {
@@ -38,9 +97,55 @@ impl AppState {
         Self {
             models: Arc::new(RwLock::new(HashMap::new())),
             simulations: Arc::new(RwLock::new(HashMap::new())),
+            data_dir: None,
+            queue: JobQueue::default(),
+            sessions: Arc::default(),
         }
     }
 
+    /// State that saves finished runs under `dir`, starting with the runs saved there before
+    ///
+    /// Files that fail to load are logged and skipped.
+    pub fn with_data_dir(dir: PathBuf) -> Result<Self, String> {
+        let runs_dir = dir.join("runs");
+        std::fs::create_dir_all(&runs_dir)
+            .map_err(|e| format!("Failed to create data directory '{}': {}", runs_dir.display(), e))?;
+        let entries = std::fs::read_dir(&runs_dir)
+            .map_err(|e| format!("Failed to read data directory '{}': {}", runs_dir.display(), e))?;
+
+        let mut runs = HashMap::new();
+        for path in entries.filter_map(|entry| entry.ok().map(|e| e.path())) {
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let saved = std::fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|json| serde_json::from_str::<SavedRun>(&json).map_err(|e| e.to_string()));
+            match saved {
+                Ok(saved) => {
+                    let mut handle = RunHandle::new(saved.status);
+                    handle.results = saved.results.map(Arc::new);
+                    runs.insert(handle.status.id.clone(), handle);
+                }
+                Err(e) => tracing::warn!("Skipping {}: {}", path.display(), e),
+            }
+        }
+
+        Ok(Self {
+            models: Arc::new(RwLock::new(HashMap::new())),
+            simulations: Arc::new(RwLock::new(runs)),
+            data_dir: Some(dir),
+            queue: JobQueue::default(),
+            sessions: Arc::default(),
+        })
+    }
+
+    /// Run jobs on `queue` instead of the default one
+    pub fn with_queue(mut self, queue: JobQueue) -> Self {
+        self.queue = queue;
+        self
+    }
+
     pub async fn add_model(&self, model: Model) -> String {
         let id = Uuid::new_v4().to_string();
         let stored = StoredModel {
@@ -53,6 +158,21 @@ impl AppState {
         id
     }
 
+    /// Store a model under a caller-chosen id; returns false if the id is taken
+    pub async fn insert_model(&self, id: String, model: Model) -> bool {
+        let mut models = self.models.write().await;
+        if models.contains_key(&id) {
+            return false;
+        }
+        let stored = StoredModel {
+            id: id.clone(),
+            model,
+            created_at: chrono::Utc::now().timestamp(),
+        };
+        models.insert(id, stored);
+        true
+    }
+
     pub async fn get_model(&self, id: &str) -> Option<Model> {
         self.models.read().await.get(id).map(|s| s.model.clone())
     }
@@ -68,6 +188,60 @@ impl AppState {
     pub async fn remove_model(&self, id: &str) -> Option<StoredModel> {
         self.models.write().await.remove(id)
     }
+
+    /// Record how a run ended and save it to the data directory, if there is one
+    pub async fn finish_run(&self, id: &str, status: RunStatus, error: Option<String>, results: Option<RunResults>) {
+        let mut simulations = self.simulations.write().await;
+        let Some(handle) = simulations.get_mut(id) else {
+            // Deleted while it was running
+            return;
+        };
+        handle.status = handle.current_status();
+        handle.status.status = status;
+        handle.status.error = error;
+        if status == RunStatus::Completed {
+            handle.status.progress = 1.0;
+        }
+        if let Some(time) = results.as_ref().and_then(|r| r.times.last()) {
+            handle.status.current_time = *time;
+        }
+        handle.results = results.map(Arc::new);
+
+        if let Some(path) = self.run_path(id) {
+            let saved = SavedRun {
+                status: handle.status.clone(),
+                results: handle.results.as_deref().cloned(),
+            };
+            let written = serde_json::to_string(&saved)
+                .map_err(|e| e.to_string())
+                .and_then(|json| std::fs::write(&path, json).map_err(|e| e.to_string()));
+            if let Err(e) = written {
+                tracing::warn!("Failed to save run {} to {}: {}", id, path.display(), e);
+            }
+        }
+    }
+
+    /// Whether a run has been asked to stop
+    pub async fn is_cancelled(&self, id: &str) -> bool {
+        self.simulations.read().await.get(id).is_some_and(|run| run.cancellation.is_cancelled())
+    }
+
+    /// Remove a run from the registry and the data directory, cancelling it if it is still going
+    pub async fn remove_run(&self, id: &str) -> Option<RunHandle> {
+        let handle = self.simulations.write().await.remove(id)?;
+        handle.cancellation.cancel();
+        if let Some(path) = self.run_path(id)
+            && path.exists()
+            && let Err(e) = std::fs::remove_file(&path)
+        {
+            tracing::warn!("Failed to remove {}: {}", path.display(), e);
+        }
+        Some(handle)
+    }
+
+    fn run_path(&self, id: &str) -> Option<PathBuf> {
+        Some(self.data_dir.as_ref()?.join("runs").join(format!("{}.json", id)))
+    }
 }
 
 impl Default for AppState {
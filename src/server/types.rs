@@ -1,7 +1,9 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use utoipa::{IntoParams, ToSchema};
+use crate::simulation::SimulationResults;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct ModelInfo {
     pub id: String,
     pub name: String,
@@ -10,7 +12,26 @@ pub struct ModelInfo {
     pub flows_count: usize,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// A scalar parameter of a model, as listed by `GET /api/models/{id}/parameters`
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ParameterInfo {
+    pub name: String,
+    pub value: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub units: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/// Form of `POST /api/models`; the file extension picks the format
+#[derive(ToSchema)]
+pub struct ModelUpload {
+    /// Model file: JSON, YAML, XMILE or InsightMaker
+    #[schema(value_type = String, format = Binary)]
+    pub file: Vec<u8>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct StartSimulationRequest {
     pub model_id: String,
     #[serde(default = "default_integrator")]
@@ -19,6 +40,9 @@ pub struct StartSimulationRequest {
     pub stream: bool,
     pub decimation: Option<usize>,
     pub parameters: Option<HashMap<String, f64>>,
+    /// Queue priority; higher runs sooner (default 0)
+    #[serde(default)]
+    pub priority: i32,
 }
 
 fn default_integrator() -> String {
@@ -29,13 +53,113 @@ fn default_stream() -> bool {
     true
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Body of `POST /api/sessions`: play a model in decision intervals
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct StartSessionRequest {
+    pub model_id: String,
+    /// A fixed-step integration method (default: euler)
+    #[serde(default = "default_integrator")]
+    pub integrator: String,
+    /// Time steps run between decisions
+    pub interval: usize,
+    pub parameters: Option<HashMap<String, f64>>,
+}
+
+/// Body of `POST /api/sessions/{id}/advance`
+#[derive(Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct AdvanceSessionRequest {
+    /// Parameters, or else stocks, to set before the next interval
+    #[serde(default)]
+    pub changes: HashMap<String, f64>,
+}
+
+/// A decision session at the end of its latest interval
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SessionState {
+    pub id: String,
+    pub model_id: String,
+    pub time: f64,
+    /// Whether the stop time has been reached
+    pub finished: bool,
+    pub stocks: HashMap<String, f64>,
+    pub flows: HashMap<String, f64>,
+    pub auxiliaries: HashMap<String, f64>,
+}
+
+/// Where a run started through `POST /api/simulations` has got to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum RunStatus {
+    /// Waiting for a free worker
+    Queued,
+    Running,
+    Completed,
+    Cancelled,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct SimulationStatus {
     pub id: String,
     pub model_id: String,
-    pub status: String,
+    pub status: RunStatus,
+    /// Fraction of the run completed, from 0 to 1
     pub progress: f64,
     pub current_time: f64,
+    pub created_at: i64,
+    /// Why the run failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Recorded output of a run, as returned by `GET /api/simulations/{id}/results`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct RunResults {
+    pub times: Vec<f64>,
+    /// Variable names in column order: stocks, then flows, then auxiliaries
+    pub variables: Vec<String>,
+    pub series: HashMap<String, Vec<f64>>,
+}
+
+impl RunResults {
+    /// The results as CSV, with the columns [`CsvWriter`](crate::io::writer::CsvWriter) writes
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("Time");
+        for name in &self.variables {
+            csv.push(',');
+            csv.push_str(name);
+        }
+        csv.push('\n');
+        for (row, time) in self.times.iter().enumerate() {
+            csv.push_str(&time.to_string());
+            for name in &self.variables {
+                csv.push(',');
+                if let Some(value) = self.series.get(name).and_then(|series| series.get(row)) {
+                    csv.push_str(&value.to_string());
+                }
+            }
+            csv.push('\n');
+        }
+        csv
+    }
+}
+
+impl From<&SimulationResults> for RunResults {
+    fn from(results: &SimulationResults) -> Self {
+        Self {
+            times: results.times.clone(),
+            variables: results.variables().cloned().collect(),
+            series: results.to_map(),
+        }
+    }
+}
+
+/// Query string of `GET /api/simulations/{id}/results`
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ResultsQuery {
+    /// `json` (default) or `csv`
+    pub format: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -46,12 +170,20 @@ pub struct TimeConfig {
     pub units: String,
 }
 
+/// Message from the server on a simulation WebSocket (`/ws/simulation/{model_id}/`)
+///
+/// Sent as JSON objects tagged by `type`. A run answers a client `run`
+/// request with `start`, then `data` frames (the initial state, every
+/// `decimation` steps and the final state) interleaved with `progress`, and
+/// ends with `complete`, `cancelled` or `error`. `error` is also sent for a
+/// message the server cannot act on, without ending the connection.
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum WebSocketMessage {
     #[serde(rename = "start")]
     Start {
         model_name: String,
+        /// Variables included in each `data` frame
         variables: Vec<String>,
         time_config: TimeConfig,
     },
@@ -60,15 +192,58 @@ pub enum WebSocketMessage {
         time: f64,
         values: HashMap<String, f64>,
     },
+    #[serde(rename = "progress")]
+    Progress {
+        steps: usize,
+        time: f64,
+        /// Fraction of the run completed, from 0 to 1
+        fraction: f64,
+        /// Estimated milliseconds to completion
+        eta_ms: Option<u128>,
+    },
     #[serde(rename = "complete")]
     Complete {
         total_steps: usize,
         elapsed_ms: u128,
     },
+    #[serde(rename = "cancelled")]
+    Cancelled {
+        time: f64,
+        total_steps: usize,
+    },
     #[serde(rename = "error")]
     Error { message: String },
 }
 
+/// Message from a client on a simulation WebSocket, tagged by `type`
+///
+/// - `{"type": "run", ...}` starts a run with the [`RunRequest`] settings;
+///   one run at a time per connection, and another may follow once it ends
+/// - `{"type": "set_parameter", "parameter": "contact_rate", "value": 2}`
+///   changes a parameter of the run in progress from the next step
+/// - `{"type": "cancel"}` stops the run in progress
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    Run(RunRequest),
+    SetParameter(ParameterUpdate),
+    Cancel,
+}
+
+/// Settings of a WebSocket run; every field is optional
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct RunRequest {
+    /// Integration method by name (default: euler)
+    pub integrator: Option<String>,
+    /// Parameter values to set before the run starts
+    pub params: HashMap<String, f64>,
+    /// Variables to send in `data` frames (default: the stocks)
+    pub variables: Option<Vec<String>>,
+    /// Steps between `data` frames (default: 10)
+    pub decimation: Option<usize>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct ParameterUpdate {
     pub parameter: String,
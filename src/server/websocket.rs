@@ -5,10 +5,21 @@ use axum::{
     },
     response::Response,
 };
-use futures::{sink::SinkExt, stream::StreamExt};
+use futures::{stream::{SplitSink, SplitStream}, FutureExt, SinkExt, StreamExt};
 use std::collections::HashMap;
-use crate::server::{error::AppError, state::AppState, types::WebSocketMessage};
-use crate::simulation::{IntegrationMethod, SimulationConfig, SimulationEngine};
+use tokio::sync::mpsc;
+use crate::model::Model;
+use crate::server::{state::AppState, types::{ClientMessage, RunRequest, WebSocketMessage}};
+use crate::simulation::{IntegrationMethod, ProgressTracker, SimulationConfig, SimulationEngine, SimulationState};
+
+/// Frames waiting for a slow client before the run pauses
+///
+/// A run only steps while there is room in this queue, so a client that
+/// reads slowly slows the run down instead of the server buffering frames
+/// without bound or dropping them.
+const FRAME_QUEUE: usize = 64;
+
+const DEFAULT_DECIMATION: usize = 10;
 
 /// WebSocket upgrade handler
 pub async fn handler(
@@ -19,140 +30,239 @@ pub async fn handler(
     ws.on_upgrade(move |socket| handle_socket(socket, model_id, state))
 }
 
-/// Handle WebSocket connection for simulation streaming
+/// Handle a WebSocket connection: run requests in, simulation frames out
+///
+/// See [`ClientMessage`] and [`WebSocketMessage`] for the protocol.
 async fn handle_socket(socket: WebSocket, model_id: String, state: AppState) {
-    let (mut sender, mut receiver) = socket.split();
+    let (sender, mut receiver) = socket.split();
+    let (frames, queued) = mpsc::channel(FRAME_QUEUE);
+    let writer = tokio::spawn(write_frames(sender, queued));
 
-    // Get model
-    let model = match state.get_model(&model_id).await {
-        Some(m) => m,
+    match state.get_model(&model_id).await {
+        Some(model) => {
+            while let Some(Ok(message)) = receiver.next().await {
+                let reply = match message {
+                    Message::Text(text) => match serde_json::from_str::<ClientMessage>(&text) {
+                        Ok(ClientMessage::Run(request)) => {
+                            match run(&model, request, &frames, &mut receiver).await {
+                                Ok(()) => None,
+                                Err(RunEnd::Failed(message)) => Some(message),
+                                Err(RunEnd::Disconnected) => break,
+                            }
+                        }
+                        Ok(_) => Some("No run in progress".to_string()),
+                        Err(e) => Some(format!("Invalid message: {}", e)),
+                    },
+                    Message::Close(_) => break,
+                    _ => None,
+                };
+                if let Some(message) = reply
+                    && frames.send(WebSocketMessage::Error { message }).await.is_err()
+                {
+                    break;
+                }
+            }
+        }
         None => {
-            let _ = send_error(&mut sender, "Model not found").await;
+            let _ = frames.send(WebSocketMessage::Error { message: "Model not found".to_string() }).await;
+        }
+    }
+
+    drop(frames);
+    let _ = writer.await;
+}
+
+/// Write queued frames to the socket until the queue closes or the client goes away
+async fn write_frames(mut sender: SplitSink<WebSocket, Message>, mut queued: mpsc::Receiver<WebSocketMessage>) {
+    while let Some(frame) = queued.recv().await {
+        let Ok(json) = serde_json::to_string(&frame) else {
+            continue;
+        };
+        if sender.send(Message::Text(json.into())).await.is_err() {
             return;
         }
+    }
+    let _ = sender.close().await;
+}
+
+/// Why a run stopped short of its stop time
+enum RunEnd {
+    /// The run could not start or failed; reported to the client as an `error` frame
+    Failed(String),
+    /// The client went away
+    Disconnected,
+}
+
+/// Run `model` as `request` asks, streaming frames and handling client messages between steps
+async fn run(
+    model: &Model,
+    request: RunRequest,
+    frames: &mpsc::Sender<WebSocketMessage>,
+    receiver: &mut SplitStream<WebSocket>,
+) -> Result<(), RunEnd> {
+    let integration_method = match &request.integrator {
+        Some(name) => name.parse::<IntegrationMethod>().map_err(RunEnd::Failed)?,
+        None => IntegrationMethod::Euler,
     };
+    let config = SimulationConfig { integration_method, ..SimulationConfig::default() };
+    let mut engine = SimulationEngine::new(model.clone(), config)
+        .map_err(|e| RunEnd::Failed(format!("Failed to create simulation: {}", e)))?;
+    for (name, value) in &request.params {
+        engine.set_parameter(name, *value).map_err(|e| RunEnd::Failed(e.to_string()))?;
+    }
+    let variables = request.variables.unwrap_or_else(|| {
+        let mut stocks: Vec<String> = model.stocks.keys().cloned().collect();
+        stocks.sort();
+        stocks
+    });
+    let decimation = request.decimation.unwrap_or(DEFAULT_DECIMATION).max(1);
 
-    // Send start message
-    let start_msg = WebSocketMessage::Start {
+    let send = |frame| async move { frames.send(frame).await.map_err(|_| RunEnd::Disconnected) };
+    send(WebSocketMessage::Start {
         model_name: model.metadata.name.clone(),
-        variables: model.stocks.keys().cloned().collect(),
+        variables: variables.clone(),
         time_config: crate::server::types::TimeConfig {
             start: model.time.start,
             stop: model.time.stop,
             dt: model.time.dt,
             units: model.time.units.clone().unwrap_or_else(|| "time".into()),
         },
-    };
-
-    if send_message(&mut sender, &start_msg).await.is_err() {
-        return;
-    }
-
-    // Create simulation config
-    let config = SimulationConfig {
-        integration_method: IntegrationMethod::Euler,
-        output_interval: None,
-    };
-
-    // Create simulation engine
-    let mut engine = match SimulationEngine::new(model.clone(), config) {
-        Ok(e) => e,
-        Err(e) => {
-            let _ = send_error(&mut sender, &format!("Failed to create simulation: {}", e)).await;
-            return;
-        }
-    };
+    }).await?;
+    send(data_frame(engine.current_state(), &variables)).await?;
 
-    // Run simulation and stream results
     let start_time = std::time::Instant::now();
+    let mut tracker = ProgressTracker::new(model.time.start, model.time.stop);
     let mut step = 0;
-    let decimation = 10; // Send every 10th step
-
-    while engine.current_time() < model.time.stop {
-        // Check for incoming messages (pause, parameter updates)
-        while let Ok(Some(Ok(msg))) = tokio::time::timeout(
-            std::time::Duration::from_millis(1),
-            receiver.next()
-        ).await {
-            if let Message::Text(text) = msg {
-                if let Err(e) = handle_client_message(&text.to_string(), &mut engine).await {
-                    tracing::warn!("Error handling client message: {}", e);
-                }
-            }
-        }
-
-        // Step simulation
-        if let Err(e) = engine.step() {
-            let _ = send_error(&mut sender, &format!("Simulation error: {}", e)).await;
-            return;
-        }
-
-        // Send data every Nth step
-        if step % decimation == 0 {
-            let state = engine.current_state();
-            let mut values = HashMap::new();
-
-            // Collect stock values
-            for (name, value) in &state.stocks {
-                values.insert(name.clone(), *value);
-            }
 
-            let data_msg = WebSocketMessage::Data {
-                time: state.time,
-                values,
+    while !engine.is_finished() {
+        // Handle whatever the client has sent since the last step, without waiting
+        while let Some(message) = receiver.next().now_or_never() {
+            let Some(Ok(Message::Text(text))) = message else {
+                match message {
+                    None | Some(Err(_)) | Some(Ok(Message::Close(_))) => return Err(RunEnd::Disconnected),
+                    _ => continue,
+                }
             };
-
-            if send_message(&mut sender, &data_msg).await.is_err() {
-                return;
+            match serde_json::from_str::<ClientMessage>(&text) {
+                Ok(ClientMessage::Cancel) => {
+                    tracing::info!("Simulation cancelled by client");
+                    return send(WebSocketMessage::Cancelled { time: engine.current_time(), total_steps: step }).await;
+                }
+                Ok(ClientMessage::SetParameter(update)) => match engine.set_parameter(&update.parameter, update.value) {
+                    Ok(()) => tracing::info!("Updated parameter {} = {}", update.parameter, update.value),
+                    Err(e) => send(WebSocketMessage::Error { message: e.to_string() }).await?,
+                },
+                Ok(ClientMessage::Run(_)) => {
+                    send(WebSocketMessage::Error { message: "A run is already in progress".to_string() }).await?
+                }
+                Err(e) => send(WebSocketMessage::Error { message: format!("Invalid message: {}", e) }).await?,
             }
         }
 
+        engine.step().map_err(|e| RunEnd::Failed(format!("Simulation error: {}", e)))?;
         step += 1;
 
+        if step % decimation == 0 || engine.is_finished() {
+            send(data_frame(engine.current_state(), &variables)).await?;
+        }
+        if let Some(progress) = tracker.update(step, engine.current_time()) {
+            send(WebSocketMessage::Progress {
+                steps: progress.steps,
+                time: progress.time,
+                fraction: progress.fraction,
+                eta_ms: progress.eta.map(|eta| eta.as_millis()),
+            }).await?;
+        }
+
         // Yield to allow other tasks to run
         tokio::task::yield_now().await;
     }
 
-    // Send completion message
-    let complete_msg = WebSocketMessage::Complete {
+    send(WebSocketMessage::Complete {
         total_steps: step,
         elapsed_ms: start_time.elapsed().as_millis(),
-    };
-
-    let _ = send_message(&mut sender, &complete_msg).await;
+    }).await
 }
 
-/// Send a message to the client
-async fn send_message(
-    sender: &mut futures::stream::SplitSink<WebSocket, Message>,
-    msg: &WebSocketMessage,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let json = serde_json::to_string(msg)?;
-    sender.send(Message::Text(json.into())).await?;
-    Ok(())
+/// A `data` frame with the current values of `variables`
+fn data_frame(state: &SimulationState, variables: &[String]) -> WebSocketMessage {
+    let values: HashMap<String, f64> = variables.iter()
+        .filter_map(|name| {
+            let value = state.stocks.get(name).or(state.flows.get(name)).or(state.auxiliaries.get(name))?;
+            Some((name.clone(), *value))
+        })
+        .collect();
+    WebSocketMessage::Data { time: state.time, values }
 }
 
-/// Send an error message to the client
-async fn send_error(
-    sender: &mut futures::stream::SplitSink<WebSocket, Message>,
-    error: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let msg = WebSocketMessage::Error {
-        message: error.to_string(),
-    };
-    send_message(sender, &msg).await
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_messages() {
+        let run = r#"{"type": "run", "integrator": "rk4", "params": {"contact_rate": 4}, "decimation": 5}"#;
+        match serde_json::from_str::<ClientMessage>(run).unwrap() {
+            ClientMessage::Run(request) => {
+                assert_eq!(request.integrator.as_deref(), Some("rk4"));
+                assert_eq!(request.params["contact_rate"], 4.0);
+                assert_eq!(request.decimation, Some(5));
+                assert!(request.variables.is_none());
+            }
+            other => panic!("expected a run request, got {:?}", other),
+        }
+        assert!(matches!(serde_json::from_str(r#"{"type": "run"}"#), Ok(ClientMessage::Run(_))));
+        assert!(matches!(serde_json::from_str(r#"{"type": "cancel"}"#), Ok(ClientMessage::Cancel)));
+        let update = r#"{"type": "set_parameter", "parameter": "contact_rate", "value": 2}"#;
+        assert!(matches!(serde_json::from_str(update), Ok(ClientMessage::SetParameter(u)) if u.value == 2.0));
+    }
+
+    #[tokio::test]
+    async fn test_run_over_socket() {
+        use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+        let state = AppState::new();
+        state.insert_model("sir".to_string(), crate::io::load_model("examples/sir_epidemic.yaml").unwrap()).await;
+        let app = crate::server::app::router(state, tower_http::cors::CorsLayer::permissive(), &crate::server::AuthConfig::default());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
 
-/// Handle incoming messages from client (parameter updates, etc.)
-async fn handle_client_message(
-    text: &str,
-    engine: &mut SimulationEngine,
-) -> Result<(), String> {
-    // Try to parse as parameter update
-    if let Ok(update) = serde_json::from_str::<crate::server::types::ParameterUpdate>(text) {
-        engine.set_parameter(&update.parameter, update.value)?;
-        tracing::info!("Updated parameter {} = {}", update.parameter, update.value);
+        let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws/simulation/sir/", addr)).await.unwrap();
+        let run = r#"{"type": "run", "variables": ["Infected"], "decimation": 100}"#;
+        socket.send(WsMessage::Text(run.into())).await.unwrap();
+
+        let mut types = Vec::new();
+        let mut last_time = 0.0;
+        while let Some(Ok(WsMessage::Text(text))) = socket.next().await {
+            let frame: serde_json::Value = serde_json::from_str(&text).unwrap();
+            let kind = frame["type"].as_str().unwrap().to_string();
+            if kind == "data" {
+                last_time = frame["time"].as_f64().unwrap();
+                assert!(frame["values"]["Infected"].is_number(), "{}", frame);
+            }
+            types.push(kind.clone());
+            if kind == "complete" || kind == "error" {
+                break;
+            }
+        }
+
+        assert_eq!(types.first().map(String::as_str), Some("start"));
+        assert_eq!(types.last().map(String::as_str), Some("complete"));
+        // 400 steps: the initial state and every 100th step
+        assert_eq!(types.iter().filter(|t| *t == "data").count(), 5);
+        assert_eq!(last_time, 100.0);
     }
 
-    Ok(())
+    #[test]
+    fn test_data_frame_includes_requested_variables() {
+        let mut state = SimulationState::new();
+        state.time = 2.0;
+        state.stocks.insert("Population", 120.0);
+        state.flows.insert("births", 6.0);
+
+        let variables = vec!["Population".to_string(), "births".to_string(), "Land".to_string()];
+        let frame = serde_json::to_value(data_frame(&state, &variables)).unwrap();
+        assert_eq!(frame, serde_json::json!({"type": "data", "time": 2.0, "values": {"Population": 120.0, "births": 6.0}}));
+    }
 }
@@ -5,6 +5,7 @@
 
 use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
+use super::StochasticManager;
 
 /// Unique identifier for an agent
 pub type AgentId = usize;
@@ -16,6 +17,9 @@ pub struct AgentState {
     pub agent_type: String,
     pub attributes: HashMap<String, f64>,
     pub active: bool,
+    /// Current state in its type's state machine
+    #[serde(default)]
+    pub state: Option<String>,
 }
 
 impl AgentState {
@@ -25,6 +29,7 @@ impl AgentState {
             agent_type,
             attributes: HashMap::new(),
             active: true,
+            state: None,
         }
     }
 
@@ -42,7 +47,8 @@ impl AgentState {
 }
 
 /// Agent behavior rule
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum AgentRule {
     /// Set attribute to expression result
     SetAttribute {
@@ -52,19 +58,199 @@ pub enum AgentRule {
     /// Conditional rule: if condition then action
     Conditional {
         condition: String,
+        #[serde(default)]
         then_rules: Vec<AgentRule>,
+        #[serde(default)]
         else_rules: Vec<AgentRule>,
     },
     /// Die/remove agent
     Die,
 }
 
+/// Distribution an agent attribute's initial value is drawn from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "distribution", rename_all = "snake_case")]
+pub enum AttributeDistribution {
+    Uniform { min: f64, max: f64 },
+    Normal { mean: f64, std_dev: f64 },
+    Lognormal { mean: f64, std_dev: f64 },
+    Poisson { lambda: f64 },
+}
+
+impl AttributeDistribution {
+    pub fn sample(&self, stochastic: &mut StochasticManager) -> Result<f64, String> {
+        match *self {
+            AttributeDistribution::Uniform { min, max } => Ok(stochastic.uniform(min, max)),
+            AttributeDistribution::Normal { mean, std_dev } => stochastic.normal(mean, std_dev),
+            AttributeDistribution::Lognormal { mean, std_dev } => stochastic.lognormal(mean, std_dev),
+            AttributeDistribution::Poisson { lambda } => stochastic.poisson(lambda),
+        }
+    }
+}
+
+/// Order agents of a type apply their rules in within a step
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpdateOrder {
+    /// Every agent sees the others as they were at the start of the step
+    #[default]
+    Synchronous,
+    /// Agents update one after another in order of id, each seeing the
+    /// updates of those before it
+    Sequential,
+    /// Like `sequential`, in a random order drawn every step
+    Random,
+    /// Asynchronous: each agent activates at the ticks of its own Poisson
+    /// clock, at `activation_rate` per unit time, so it may act several times
+    /// in a step or not at all. Each activation sees the updates of those
+    /// before it, and state transitions cover 1 / `activation_rate` of time.
+    Poisson,
+}
+
+/// Discrete states agents of a type move between
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StateMachine {
+    pub states: Vec<String>,
+    /// Relative weight of each state for new agents; when empty every agent
+    /// starts in the first state
+    #[serde(default)]
+    pub initial: HashMap<String, f64>,
+    /// Checked in order every step; an agent takes at most one
+    #[serde(default)]
+    pub transitions: Vec<StateTransition>,
+}
+
+/// Move from one state to another with a chance evaluated per agent per step
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StateTransition {
+    pub from: String,
+    pub to: String,
+    #[serde(flatten)]
+    pub chance: TransitionChance,
+}
+
+/// Expression of the SD variables and the agent's attributes giving how
+/// likely a transition is
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransitionChance {
+    /// Hazard per unit time: fires within a step of length dt with
+    /// probability 1 - exp(-rate * dt)
+    Rate(String),
+    /// Probability per step
+    Probability(String),
+}
+
+impl TransitionChance {
+    pub fn expression(&self) -> &str {
+        match self {
+            TransitionChance::Rate(expression) | TransitionChance::Probability(expression) => expression,
+        }
+    }
+
+    /// Probability of firing within a step of length `dt` given the expression's value
+    pub fn probability(&self, value: f64, dt: f64) -> f64 {
+        match self {
+            TransitionChance::Rate(_) => 1.0 - (-value.max(0.0) * dt).exp(),
+            TransitionChance::Probability(_) => value.clamp(0.0, 1.0),
+        }
+    }
+}
+
+impl StateMachine {
+    pub fn new(states: Vec<String>) -> Self {
+        Self {
+            states,
+            initial: HashMap::new(),
+            transitions: Vec::new(),
+        }
+    }
+
+    pub fn with_initial(mut self, state: &str, weight: f64) -> Self {
+        self.initial.insert(state.to_string(), weight);
+        self
+    }
+
+    pub fn add_transition(&mut self, from: &str, to: &str, chance: TransitionChance) {
+        self.transitions.push(StateTransition { from: from.to_string(), to: to.to_string(), chance });
+    }
+
+    /// Check that the machine only refers to its own states
+    pub fn validate(&self) -> Result<(), String> {
+        if self.states.is_empty() {
+            return Err("State machine declares no states".to_string());
+        }
+        for (i, state) in self.states.iter().enumerate() {
+            if self.states[..i].contains(state) {
+                return Err(format!("State '{}' is declared twice", state));
+            }
+        }
+        let mut initial: Vec<(&String, &f64)> = self.initial.iter().collect();
+        initial.sort_by(|a, b| a.0.cmp(b.0));
+        for (state, weight) in initial {
+            if !self.states.contains(state) {
+                return Err(format!("Initial state '{}' is not a declared state", state));
+            }
+            if !(*weight >= 0.0 && weight.is_finite()) {
+                return Err(format!("Initial weight of state '{}' must be non-negative, got {}", state, weight));
+            }
+        }
+        for transition in &self.transitions {
+            for state in [&transition.from, &transition.to] {
+                if !self.states.contains(state) {
+                    return Err(format!(
+                        "Transition from '{}' to '{}' uses undeclared state '{}'", transition.from, transition.to, state
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Draw the state of a new agent from the initial weights
+    pub fn initial_state(&self, stochastic: &mut StochasticManager) -> Option<String> {
+        let weighted: Vec<(&String, f64)> = self.states.iter()
+            .filter_map(|state| self.initial.get(state).map(|weight| (state, *weight)))
+            .filter(|(_, weight)| *weight > 0.0)
+            .collect();
+        let total: f64 = weighted.iter().map(|(_, weight)| weight).sum();
+        if total <= 0.0 {
+            return self.states.first().cloned();
+        }
+        let mut draw = stochastic.random() * total;
+        for (state, weight) in &weighted {
+            if draw < *weight {
+                return Some((*state).clone());
+            }
+            draw -= weight;
+        }
+        weighted.last().map(|(state, _)| (*state).clone())
+    }
+}
+
 /// Agent type definition
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AgentType {
     pub name: String,
+    #[serde(default)]
     pub initial_attributes: HashMap<String, f64>,
+    /// Attributes whose initial value is drawn for every new agent,
+    /// overriding `initial_attributes`
+    #[serde(default)]
+    pub distributions: HashMap<String, AttributeDistribution>,
+    #[serde(default)]
     pub rules: Vec<AgentRule>,
+    #[serde(default)]
+    pub update_order: UpdateOrder,
+    /// Mean activations per unit time of each agent under `poisson` updates
+    #[serde(default = "default_activation_rate")]
+    pub activation_rate: f64,
+    #[serde(default)]
+    pub state_machine: Option<StateMachine>,
+}
+
+fn default_activation_rate() -> f64 {
+    1.0
 }
 
 impl AgentType {
@@ -72,7 +258,11 @@ impl AgentType {
         Self {
             name,
             initial_attributes: HashMap::new(),
+            distributions: HashMap::new(),
             rules: Vec::new(),
+            update_order: UpdateOrder::default(),
+            activation_rate: default_activation_rate(),
+            state_machine: None,
         }
     }
 
@@ -80,6 +270,11 @@ impl AgentType {
         self.initial_attributes.insert(name, initial_value);
     }
 
+    /// Draw the attribute's initial value from `distribution` for every new agent
+    pub fn add_distribution(&mut self, name: String, distribution: AttributeDistribution) {
+        self.distributions.insert(name, distribution);
+    }
+
     pub fn add_rule(&mut self, rule: AgentRule) {
         self.rules.push(rule);
     }
@@ -88,12 +283,13 @@ impl AgentType {
     pub fn create_agent(&self, id: AgentId) -> AgentState {
         let mut agent = AgentState::new(id, self.name.clone());
         agent.attributes = self.initial_attributes.clone();
+        agent.state = self.state_machine.as_ref().and_then(|machine| machine.states.first().cloned());
         agent
     }
 }
 
 /// Population of agents of a specific type
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AgentPopulation {
     pub agent_type: String,
     pub agents: HashMap<AgentId, AgentState>,
@@ -149,6 +345,20 @@ impl AgentPopulation {
         self.agents.get_mut(&id)
     }
 
+    /// Count active agents in a state
+    pub fn count_in_state(&self, state: &str) -> usize {
+        self.agents.values().filter(|a| a.active && a.state.as_deref() == Some(state)).count()
+    }
+
+    /// Values of an attribute over the active agents that have it, optionally
+    /// only those in `state`
+    pub fn attribute_values<'a>(&'a self, attribute: &'a str, state: Option<&'a str>) -> impl Iterator<Item = f64> + 'a {
+        self.agents
+            .values()
+            .filter(move |a| a.active && state.is_none_or(|state| a.state.as_deref() == Some(state)))
+            .filter_map(move |a| a.get(attribute))
+    }
+
     /// Calculate aggregate statistics
     pub fn sum_attribute(&self, attribute: &str) -> f64 {
         self.agents
@@ -184,7 +394,7 @@ impl AgentPopulation {
 }
 
 /// Manager for all agent populations in a simulation
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AgentManager {
     pub agent_types: HashMap<String, AgentType>,
     pub populations: HashMap<String, AgentPopulation>,
@@ -225,6 +435,46 @@ impl AgentManager {
         Ok(())
     }
 
+    /// Create agents of a given type, drawing the attributes that have a
+    /// distribution from `stochastic` in order of attribute name
+    pub fn create_agents_sampled(
+        &mut self,
+        type_name: &str,
+        count: usize,
+        stochastic: &mut StochasticManager,
+    ) -> Result<(), String> {
+        let agent_type = self
+            .agent_types
+            .get(type_name)
+            .ok_or_else(|| format!("Agent type '{}' not found", type_name))?
+            .clone();
+
+        let population = self
+            .populations
+            .get_mut(type_name)
+            .ok_or_else(|| format!("Population for type '{}' not found", type_name))?;
+
+        let mut sampled: Vec<(&String, &AttributeDistribution)> = agent_type.distributions.iter().collect();
+        sampled.sort_by(|a, b| a.0.cmp(b.0));
+        for _ in 0..count {
+            let id = population.create_agent(&agent_type);
+            for (name, distribution) in &sampled {
+                let value = distribution.sample(stochastic)
+                    .map_err(|e| format!("Attribute '{}' of agent type '{}': {}", name, type_name, e))?;
+                if let Some(agent) = population.get_agent_mut(id) {
+                    agent.set((*name).clone(), value);
+                }
+            }
+            if let Some(machine) = &agent_type.state_machine
+                && let Some(agent) = population.get_agent_mut(id)
+            {
+                agent.state = machine.initial_state(stochastic);
+            }
+        }
+
+        Ok(())
+    }
+
     /// Get population
     pub fn get_population(&self, type_name: &str) -> Option<&AgentPopulation> {
         self.populations.get(type_name)
@@ -291,6 +541,18 @@ impl AgentManager {
             .map(|p| p.count_active())
             .unwrap_or(0)
     }
+
+    /// Get count of agents of any type in a state
+    pub fn count_in_state(&self, state: &str) -> usize {
+        self.populations.values().map(|p| p.count_in_state(state)).sum()
+    }
+
+    /// Whether any agent type's state machine declares `state`
+    pub fn has_state(&self, state: &str) -> bool {
+        self.agent_types.values()
+            .filter_map(|t| t.state_machine.as_ref())
+            .any(|machine| machine.states.iter().any(|s| s == state))
+    }
 }
 
 impl Default for AgentManager {
@@ -0,0 +1,203 @@
+/// Agent-level output of a run
+///
+/// Snapshots hold one row per agent at each recorded time: its type, id,
+/// state and attributes, stored as columns ready for Parquet or HDF5. An
+/// attribute an agent lacks is NaN. Histograms count the agents in each bin
+/// of an attribute at every step; values outside the range count in the first
+/// or last bin.
+
+use crate::model::{AgentOutputConfig, HistogramConfig};
+use super::AgentManager;
+
+#[derive(Debug, Clone)]
+pub struct AgentRecorder {
+    pub config: AgentOutputConfig,
+    pub snapshots: AgentSnapshots,
+    /// One per configured histogram, in the configured order
+    pub histograms: Vec<AttributeHistogram>,
+    steps: usize,
+}
+
+/// Agent snapshots in columns
+#[derive(Debug, Clone, Default)]
+pub struct AgentSnapshots {
+    pub time: Vec<f64>,
+    pub agent_type: Vec<String>,
+    pub agent_id: Vec<u64>,
+    /// Empty for agents without a state machine
+    pub state: Vec<String>,
+    /// Attribute names, in the order they were first recorded
+    pub attributes: Vec<String>,
+    /// A column of values per attribute
+    pub values: Vec<Vec<f64>>,
+}
+
+/// Distribution of one attribute over a run
+#[derive(Debug, Clone)]
+pub struct AttributeHistogram {
+    pub attribute: String,
+    pub agent_type: Option<String>,
+    /// Bin edges, one more than the bins
+    pub edges: Vec<f64>,
+    pub times: Vec<f64>,
+    /// Count of agents in each bin at each time
+    pub counts: Vec<Vec<u64>>,
+}
+
+impl AgentRecorder {
+    pub fn new(config: AgentOutputConfig) -> Result<Self, String> {
+        let histograms = config.histograms.iter().map(AttributeHistogram::new).collect::<Result<_, _>>()?;
+        Ok(Self {
+            config,
+            snapshots: AgentSnapshots::default(),
+            histograms,
+            steps: 0,
+        })
+    }
+
+    /// Record the agents at the start of a run
+    pub(crate) fn start(&mut self, time: f64, agents: &AgentManager) {
+        self.steps = 0;
+        self.record(time, agents);
+    }
+
+    /// Record the agents after a step, when due
+    pub(crate) fn step_end(&mut self, time: f64, agents: &AgentManager) {
+        self.steps += 1;
+        self.record(time, agents);
+    }
+
+    fn record(&mut self, time: f64, agents: &AgentManager) {
+        for histogram in &mut self.histograms {
+            histogram.record(time, agents);
+        }
+        if self.config.every > 0 && self.steps.is_multiple_of(self.config.every) {
+            self.snapshots.record(time, agents, &self.config.attributes);
+        }
+    }
+}
+
+impl AgentSnapshots {
+    pub fn len(&self) -> usize {
+        self.time.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.time.is_empty()
+    }
+
+    /// Add a row per active agent, by type name then id
+    fn record(&mut self, time: f64, agents: &AgentManager, selected: &[String]) {
+        let mut type_names: Vec<&String> = agents.populations.keys().collect();
+        type_names.sort();
+        for type_name in type_names {
+            let population = &agents.populations[type_name];
+            let mut ids: Vec<usize> = population.all_agents().filter(|agent| agent.active).map(|agent| agent.id).collect();
+            ids.sort_unstable();
+            for id in ids {
+                let agent = &population.agents[&id];
+                let mut names: Vec<&String> = agent.attributes.keys()
+                    .filter(|name| selected.is_empty() || selected.contains(name))
+                    .collect();
+                names.sort();
+                for name in names {
+                    if !self.attributes.contains(name) {
+                        self.attributes.push(name.clone());
+                        self.values.push(vec![f64::NAN; self.time.len()]);
+                    }
+                }
+
+                self.time.push(time);
+                self.agent_type.push(type_name.clone());
+                self.agent_id.push(id as u64);
+                self.state.push(agent.state.clone().unwrap_or_default());
+                for (name, column) in self.attributes.iter().zip(&mut self.values) {
+                    column.push(agent.get(name).unwrap_or(f64::NAN));
+                }
+            }
+        }
+    }
+}
+
+impl AttributeHistogram {
+    fn new(config: &HistogramConfig) -> Result<Self, String> {
+        if config.bins == 0 || config.min.partial_cmp(&config.max) != Some(std::cmp::Ordering::Less) {
+            return Err(format!(
+                "Histogram of '{}' needs at least one bin and min < max, got {} bins from {} to {}",
+                config.attribute, config.bins, config.min, config.max
+            ));
+        }
+        let width = (config.max - config.min) / config.bins as f64;
+        Ok(Self {
+            attribute: config.attribute.clone(),
+            agent_type: config.agent_type.clone(),
+            edges: (0..=config.bins).map(|i| config.min + width * i as f64).collect(),
+            times: Vec::new(),
+            counts: Vec::new(),
+        })
+    }
+
+    fn record(&mut self, time: f64, agents: &AgentManager) {
+        let bins = self.edges.len() - 1;
+        let (min, max) = (self.edges[0], self.edges[bins]);
+        let mut counts = vec![0; bins];
+        let populations = agents.populations.iter()
+            .filter(|(name, _)| self.agent_type.as_ref().is_none_or(|t| t == *name))
+            .map(|(_, population)| population);
+        for population in populations {
+            for value in population.all_agents().filter(|agent| agent.active).filter_map(|agent| agent.get(&self.attribute)) {
+                if value.is_nan() {
+                    continue;
+                }
+                let bin = ((value - min) / (max - min) * bins as f64).floor().clamp(0.0, (bins - 1) as f64);
+                counts[bin as usize] += 1;
+            }
+        }
+        self.times.push(time);
+        self.counts.push(counts);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::{AgentOutputConfig, HybridConfig, Model};
+    use crate::simulation::{AgentRule, AgentType, SimulationConfig, SimulationEngine};
+
+    #[test]
+    fn test_agent_output() {
+        // Every agent's age grows by one more each step
+        let mut model = Model::new("Ages");
+        model.time.stop = 4.0;
+        model.time.dt = 1.0;
+        let mut person = AgentType::new("Person".to_string());
+        person.add_attribute("age".to_string(), 0.0);
+        person.add_attribute("step".to_string(), 0.0);
+        person.add_rule(AgentRule::SetAttribute { attribute: "step".to_string(), expression: "step + 1".to_string() });
+        person.add_rule(AgentRule::SetAttribute { attribute: "age".to_string(), expression: "age + step".to_string() });
+        let mut hybrid = HybridConfig::new().with_agents(person, 3);
+        let mut output = AgentOutputConfig::new(2).with_histogram("age", 0.0, 10.0, 5);
+        output.attributes = vec!["age".to_string()];
+        hybrid.agent_output = Some(output);
+        model.hybrid = Some(hybrid);
+
+        let mut engine = SimulationEngine::new(model, SimulationConfig::default()).unwrap();
+        engine.run().unwrap();
+        let recorder = engine.agent_output().unwrap();
+
+        // Snapshots at t=0, 2 and 4, three agents each, of the selected attribute only
+        let snapshots = &recorder.snapshots;
+        assert_eq!(snapshots.len(), 9);
+        assert_eq!(snapshots.attributes, vec!["age".to_string()]);
+        assert_eq!(snapshots.time[3..6], [2.0, 2.0, 2.0]);
+        assert_eq!(snapshots.agent_id[3..6], [0, 1, 2]);
+        // Ages are 0, 1, 3, 6, 10 over the steps
+        assert_eq!(snapshots.values[0][6..], [10.0, 10.0, 10.0]);
+
+        // Histograms at every step; 10 falls in the last bin
+        let histogram = &recorder.histograms[0];
+        assert_eq!(histogram.edges, vec![0.0, 2.0, 4.0, 6.0, 8.0, 10.0]);
+        assert_eq!(histogram.times, vec![0.0, 1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(histogram.counts[2], vec![0, 3, 0, 0, 0]);
+        assert_eq!(histogram.counts[4], vec![0, 0, 0, 0, 3]);
+    }
+}
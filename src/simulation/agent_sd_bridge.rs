@@ -7,11 +7,13 @@
 /// - Spatial agent distribution
 
 use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
 use crate::simulation::{AgentManager, AgentType, AgentState, AgentRule};
 use crate::model::Expression;
 
 /// Bridge configuration for agent-SD coupling
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct AgentSDConfig {
     /// Map agent population names to their coupling rules
     pub agent_couplings: HashMap<String, AgentCoupling>,
@@ -30,7 +32,8 @@ impl AgentSDConfig {
 }
 
 /// Coupling rules for a specific agent type
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct AgentCoupling {
     /// Agent attributes that feed into SD variables
     pub attributes_to_sd: Vec<AttributeMapping>,
@@ -61,7 +64,7 @@ impl AgentCoupling {
 }
 
 /// Mapping from agent attribute to SD variable
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AttributeMapping {
     pub attribute_name: String,
     pub sd_variable: String,
@@ -69,7 +72,8 @@ pub struct AttributeMapping {
 }
 
 /// Types of aggregation for agent attributes
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum AggregationType {
     Sum,
     Mean,
@@ -80,7 +84,7 @@ pub enum AggregationType {
 }
 
 /// Mapping from SD variable to agent attribute
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SDMapping {
     pub sd_variable: String,
     pub attribute_name: String,
@@ -88,7 +92,8 @@ pub struct SDMapping {
 }
 
 /// How SD variable affects agent attribute
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum MappingType {
     Direct,                    // attribute = sd_value
     Scaled(f64),               // attribute = sd_value * scale
@@ -188,20 +193,31 @@ impl AgentSDBridge {
         flow_values: &HashMap<String, f64>,
         dt: f64,
     ) -> Result<(), String> {
-        for (agent_type, coupling) in &self.config.agent_couplings {
-            if let Some(creation_flow) = &coupling.creation_flow {
-                if let Some(&flow_rate) = flow_values.get(creation_flow) {
-                    // Number of agents to create this timestep
-                    let n_agents = (flow_rate * dt * coupling.agents_per_flow_unit).round() as usize;
+        for (agent_type, n_agents) in self.agents_to_create(flow_values, dt) {
+            agents.create_agents(&agent_type, n_agents)?;
+        }
 
-                    if n_agents > 0 {
-                        agents.create_agents(agent_type, n_agents)?;
-                    }
+        Ok(())
+    }
+
+    /// Number of agents of each type the creation flows create this
+    /// timestep, by type name
+    pub fn agents_to_create(&self, flow_values: &HashMap<String, f64>, dt: f64) -> Vec<(String, usize)> {
+        let mut counts = Vec::new();
+        for (agent_type, coupling) in &self.config.agent_couplings {
+            if let Some(creation_flow) = &coupling.creation_flow
+                && let Some(&flow_rate) = flow_values.get(creation_flow)
+            {
+                // Number of agents to create this timestep
+                let n_agents = (flow_rate * dt * coupling.agents_per_flow_unit).round() as usize;
+
+                if n_agents > 0 {
+                    counts.push((agent_type.clone(), n_agents));
                 }
             }
         }
-
-        Ok(())
+        counts.sort();
+        counts
     }
 
     /// Handle agent destruction from flows
@@ -218,12 +234,13 @@ impl AgentSDBridge {
                     let n_agents = (flow_rate * dt * coupling.agents_per_flow_unit).round() as usize;
 
                     if n_agents > 0 {
-                        // First collect agent IDs to remove
-                        let agent_ids: Vec<_> = if let Some(population) = agents.get_population(agent_type) {
+                        // First collect agent IDs to remove, oldest first
+                        let mut agent_ids: Vec<_> = if let Some(population) = agents.get_population(agent_type) {
                             population.agents.keys().copied().collect()
                         } else {
                             Vec::new()
                         };
+                        agent_ids.sort_unstable();
 
                         // Then remove them
                         if let Some(population) = agents.get_population_mut(agent_type) {
@@ -1,16 +1,17 @@
 /// Multi-dimensional array value support for simulation state
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use ndarray::{ArrayD, IxDyn};
+use crate::model::Model;
+use crate::model::arrays::{element_combinations, element_name};
 
 /// A value that can be either scalar or multi-dimensional array
 #[derive(Debug, Clone)]
 pub enum ArrayValue {
     /// Scalar value
     Scalar(f64),
-    /// Multi-dimensional array stored as flat vector with shape information
-    /// shape: dimensions of the array (e.g., [3, 4] for 3x4 matrix)
-    /// data: flat array in row-major order
-    Array { shape: Vec<usize>, data: Vec<f64> },
+    /// Multi-dimensional array in row-major order, one axis per dimension
+    Array(ArrayD<f64>),
 }
 
 impl ArrayValue {
@@ -21,14 +22,10 @@ impl ArrayValue {
 
     /// Create a multi-dimensional array with given shape, initialized to zero
     pub fn zeros(shape: Vec<usize>) -> Self {
-        let size: usize = shape.iter().product();
-        ArrayValue::Array {
-            shape,
-            data: vec![0.0; size],
-        }
+        ArrayValue::Array(ArrayD::zeros(IxDyn(&shape)))
     }
 
-    /// Create a multi-dimensional array with given shape and data
+    /// Create a multi-dimensional array with given shape and row-major data
     pub fn from_vec(shape: Vec<usize>, data: Vec<f64>) -> Result<Self, String> {
         let expected_size: usize = shape.iter().product();
         if data.len() != expected_size {
@@ -39,14 +36,24 @@ impl ArrayValue {
                 expected_size
             ));
         }
-        Ok(ArrayValue::Array { shape, data })
+        ArrayD::from_shape_vec(IxDyn(&shape), data)
+            .map(ArrayValue::Array)
+            .map_err(|e| e.to_string())
     }
 
     /// Get scalar value (error if array)
     pub fn as_scalar(&self) -> Result<f64, String> {
         match self {
             ArrayValue::Scalar(v) => Ok(*v),
-            ArrayValue::Array { .. } => Err("Cannot convert array to scalar".to_string()),
+            ArrayValue::Array(_) => Err("Cannot convert array to scalar".to_string()),
+        }
+    }
+
+    /// Underlying array (None for scalars)
+    pub fn as_array(&self) -> Option<&ArrayD<f64>> {
+        match self {
+            ArrayValue::Scalar(_) => None,
+            ArrayValue::Array(array) => Some(array),
         }
     }
 
@@ -60,28 +67,9 @@ impl ArrayValue {
                     Err("Cannot index scalar value".to_string())
                 }
             }
-            ArrayValue::Array { shape, data } => {
-                if indices.len() != shape.len() {
-                    return Err(format!(
-                        "Expected {} indices, got {}",
-                        shape.len(),
-                        indices.len()
-                    ));
-                }
-
-                // Check bounds
-                for (i, &idx) in indices.iter().enumerate() {
-                    if idx >= shape[i] {
-                        return Err(format!(
-                            "Index {} out of bounds for dimension {} (size {})",
-                            idx, i, shape[i]
-                        ));
-                    }
-                }
-
-                // Convert multi-dimensional index to flat index
-                let flat_idx = Self::indices_to_flat(indices, shape);
-                Ok(data[flat_idx])
+            ArrayValue::Array(array) => {
+                Self::check_indices(array, indices)?;
+                Ok(array[IxDyn(indices)])
             }
         }
     }
@@ -97,52 +85,39 @@ impl ArrayValue {
                     Err("Cannot index scalar value".to_string())
                 }
             }
-            ArrayValue::Array { shape, data } => {
-                if indices.len() != shape.len() {
-                    return Err(format!(
-                        "Expected {} indices, got {}",
-                        shape.len(),
-                        indices.len()
-                    ));
-                }
-
-                // Check bounds
-                for (i, &idx) in indices.iter().enumerate() {
-                    if idx >= shape[i] {
-                        return Err(format!(
-                            "Index {} out of bounds for dimension {} (size {})",
-                            idx, i, shape[i]
-                        ));
-                    }
-                }
-
-                let flat_idx = Self::indices_to_flat(indices, shape);
-                data[flat_idx] = value;
+            ArrayValue::Array(array) => {
+                Self::check_indices(array, indices)?;
+                array[IxDyn(indices)] = value;
                 Ok(())
             }
         }
     }
 
-    /// Convert multi-dimensional indices to flat index (row-major order)
-    fn indices_to_flat(indices: &[usize], shape: &[usize]) -> usize {
-        let mut flat_index = 0;
-        let mut multiplier = 1;
+    fn check_indices(array: &ArrayD<f64>, indices: &[usize]) -> Result<(), String> {
+        if indices.len() != array.ndim() {
+            return Err(format!(
+                "Expected {} indices, got {}",
+                array.ndim(),
+                indices.len()
+            ));
+        }
 
-        for i in (0..indices.len()).rev() {
-            flat_index += indices[i] * multiplier;
-            if i > 0 {
-                multiplier *= shape[i];
+        for (i, (&idx, &size)) in indices.iter().zip(array.shape()).enumerate() {
+            if idx >= size {
+                return Err(format!(
+                    "Index {} out of bounds for dimension {} (size {})",
+                    idx, i, size
+                ));
             }
         }
-
-        flat_index
+        Ok(())
     }
 
     /// Get the shape of this value (empty for scalar)
     pub fn shape(&self) -> Vec<usize> {
         match self {
             ArrayValue::Scalar(_) => vec![],
-            ArrayValue::Array { shape, .. } => shape.clone(),
+            ArrayValue::Array(array) => array.shape().to_vec(),
         }
     }
 
@@ -153,11 +128,15 @@ impl ArrayValue {
 
     /// Check if this is an array
     pub fn is_array(&self) -> bool {
-        matches!(self, ArrayValue::Array { .. })
+        matches!(self, ArrayValue::Array(_))
     }
 }
 
-/// Extended simulation state that supports multi-dimensional variables
+/// Simulation state with arrayed variables gathered into ndarray values
+///
+/// The engine integrates arrayed variables element by element (see
+/// [`crate::model::arrays`]); this view collects the elements of each variable
+/// declared with dimensions back into one array, indexed in dimension order.
 #[derive(Debug, Clone)]
 pub struct ArraySimulationState {
     pub time: f64,
@@ -197,124 +176,89 @@ impl ArraySimulationState {
             .get(indices)
     }
 
-    /// Initialize from a model, detecting array dimensions from model definition
-    pub fn initialize_from_model(model: &crate::model::Model) -> Result<Self, String> {
-        use crate::model::expression::EvaluationContext;
-
-        let mut state = Self::new();
-        state.time = model.time.start;
-
-        // Initialize stocks with their initial values
-        // For now, treat all as scalars (array support requires dimension info in model)
-        for (name, stock) in &model.stocks {
-            let mut temp_state_scalar = super::SimulationState::new();
-            temp_state_scalar.time = model.time.start;
-
-            let mut context = EvaluationContext::new(model, &mut temp_state_scalar, model.time.start);
-            let initial_value = stock.initial.evaluate(&mut context)?;
-
-            // Check if stock has dimensions defined
-            if let Some(ref dim_names) = stock.dimensions {
-                // Get shape from dimensions
-                let mut shape = Vec::new();
-                let mut valid = true;
-                for dim_name in dim_names {
-                    if let Some(dimension) = model.dimensions.get(dim_name) {
-                        shape.push(dimension.elements.len());
-                    } else {
-                        valid = false;
-                        break;
-                    }
-                }
+    /// Initial state of a model, with arrayed stocks holding one value per element
+    pub fn initialize_from_model(model: &Model) -> Result<Self, String> {
+        let mut model = model.clone();
+        model.compile()?;
+        let state = super::SimulationState::initialize_from_model(&model)?;
+        Self::from_state(&model, &state)
+    }
 
-                if valid && !shape.is_empty() {
-                    // Create array initialized with the scalar value
-                    let size: usize = shape.iter().product();
-                    state.stocks.insert(name.clone(), ArrayValue::Array {
-                        shape,
-                        data: vec![initial_value; size],
-                    });
-                } else {
-                    // Fallback to scalar if dimensions not found
-                    state.stocks.insert(name.clone(), ArrayValue::Scalar(initial_value));
+    /// Gather the per-element values of a compiled model's state into arrays
+    pub fn from_state(model: &Model, state: &super::SimulationState) -> Result<Self, String> {
+        let mut array_state = Self::new();
+        array_state.time = state.time;
+        array_state.delays = state.delays.clone();
+        array_state.stochastic = state.stochastic.clone();
+        array_state.agents = state.agents.clone();
+
+        let mut element_names = HashSet::new();
+        for (name, dimensions) in &model.expanded_arrays {
+            let names: Vec<String> = element_combinations(dimensions, &model.dimensions)?
+                .iter()
+                .map(|elements| element_name(name, elements))
+                .collect();
+            let shape: Vec<usize> = dimensions.iter().map(|d| model.dimensions[d].size()).collect();
+
+            for (source, target) in [
+                (&state.stocks, &mut array_state.stocks),
+                (&state.flows, &mut array_state.flows),
+                (&state.auxiliaries, &mut array_state.auxiliaries),
+            ] {
+                let values: Option<Vec<f64>> = names.iter().map(|n| source.get(n).copied()).collect();
+                if let Some(values) = values {
+                    target.insert(name.clone(), ArrayValue::from_vec(shape.clone(), values)?);
                 }
-            } else {
-                // No dimensions defined, use scalar
-                state.stocks.insert(name.clone(), ArrayValue::Scalar(initial_value));
             }
-
-            // Merge back state changes
-            state.delays = temp_state_scalar.delays;
-            state.stochastic = temp_state_scalar.stochastic;
-            state.agents = temp_state_scalar.agents;
+            element_names.extend(names);
         }
 
-        // Initialize flows
-        for name in model.flows.keys() {
-            state.flows.insert(name.clone(), ArrayValue::Scalar(0.0));
-        }
-
-        // Initialize auxiliaries
-        for name in model.auxiliaries.keys() {
-            state.auxiliaries.insert(name.clone(), ArrayValue::Scalar(0.0));
+        for (source, target) in [
+            (&state.stocks, &mut array_state.stocks),
+            (&state.flows, &mut array_state.flows),
+            (&state.auxiliaries, &mut array_state.auxiliaries),
+        ] {
+            for (name, value) in source {
+                if !element_names.contains(name) {
+                    target.insert(name.clone(), ArrayValue::Scalar(*value));
+                }
+            }
         }
 
-        Ok(state)
+        Ok(array_state)
     }
 
-    /// Convert to scalar SimulationState (for backward compatibility)
-    /// Arrays are flattened or summed
-    pub fn to_scalar_state(&self) -> super::SimulationState {
+    /// Convert to a scalar SimulationState with one entry per array element
+    pub fn to_scalar_state(&self, model: &Model) -> Result<super::SimulationState, String> {
         let mut scalar_state = super::SimulationState::new();
         scalar_state.time = self.time;
         scalar_state.delays = self.delays.clone();
         scalar_state.stochastic = self.stochastic.clone();
         scalar_state.agents = self.agents.clone();
 
-        // Convert stocks
-        for (name, value) in &self.stocks {
-            scalar_state.stocks.insert(
-                name.clone(),
-                value.as_scalar().unwrap_or_else(|_| {
-                    // Sum all elements for arrays
-                    if let ArrayValue::Array { data, .. } = value {
-                        data.iter().sum()
-                    } else {
-                        0.0
+        for (source, target) in [
+            (&self.stocks, &mut scalar_state.stocks),
+            (&self.flows, &mut scalar_state.flows),
+            (&self.auxiliaries, &mut scalar_state.auxiliaries),
+        ] {
+            for (name, value) in source {
+                match value {
+                    ArrayValue::Scalar(v) => {
+                        target.insert(name.clone(), *v);
                     }
-                }),
-            );
-        }
-
-        // Convert flows
-        for (name, value) in &self.flows {
-            scalar_state.flows.insert(
-                name.clone(),
-                value.as_scalar().unwrap_or_else(|_| {
-                    if let ArrayValue::Array { data, .. } = value {
-                        data.iter().sum()
-                    } else {
-                        0.0
+                    ArrayValue::Array(array) => {
+                        let dimensions = model.expanded_arrays.get(name)
+                            .ok_or_else(|| format!("Variable '{}' is not arrayed in this model", name))?;
+                        let elements = element_combinations(dimensions, &model.dimensions)?;
+                        for (elements, v) in elements.iter().zip(array.iter()) {
+                            target.insert(element_name(name, elements), *v);
+                        }
                     }
-                }),
-            );
-        }
-
-        // Convert auxiliaries
-        for (name, value) in &self.auxiliaries {
-            scalar_state.auxiliaries.insert(
-                name.clone(),
-                value.as_scalar().unwrap_or_else(|_| {
-                    if let ArrayValue::Array { data, .. } = value {
-                        data.iter().sum()
-                    } else {
-                        0.0
-                    }
-                }),
-            );
+                }
+            }
         }
 
-        scalar_state
+        Ok(scalar_state)
     }
 }
 
@@ -358,4 +302,32 @@ mod tests {
         assert_eq!(val.get(&[1, 0]).unwrap(), 4.0);
         assert_eq!(val.get(&[1, 2]).unwrap(), 6.0);
     }
+
+    #[test]
+    fn test_array_state_from_run() {
+        use crate::model::{Dimension, Flow, Parameter, Stock};
+        use crate::simulation::{SimulationConfig, SimulationEngine};
+
+        let mut model = Model::new("Regions");
+        model.time.stop = 1.0;
+        model.time.dt = 1.0;
+        model.add_dimension(Dimension::new("Region", vec!["North".to_string(), "South".to_string()])).unwrap();
+        model.add_dimension(Dimension::new("Age", vec!["Young".to_string(), "Old".to_string()])).unwrap();
+        let dims = vec!["Region".to_string(), "Age".to_string()];
+        model.add_parameter(Parameter::new("rate", 0.0).with_dimensions(dims.clone())
+            .with_values(vec![0.1, 0.2, 0.3, 0.4])).unwrap();
+        model.add_stock(Stock::new("Population", "100").with_dimensions(dims.clone())
+            .with_inflows(vec!["growth".to_string()])).unwrap();
+        model.add_flow(Flow::new("growth", "Population[*, *] * rate").with_dimensions(dims)).unwrap();
+
+        let mut engine = SimulationEngine::new(model, SimulationConfig::default()).unwrap();
+        let results = engine.run().unwrap();
+        assert_eq!(results.get_variable_series("Population_South_Young").unwrap(), vec![100.0, 130.0]);
+
+        let state = engine.array_state().unwrap();
+        let population = state.get_value("Population").unwrap();
+        assert_eq!(population.shape(), vec![2, 2]);
+        assert_eq!(population.get(&[0, 1]).unwrap(), 120.0);
+        assert_eq!(state.get_element("growth", &[1, 1]).unwrap(), 40.0);
+    }
 }
@@ -0,0 +1,147 @@
+/// Runtime support for reality-check constraints
+///
+/// Counts how often each of the model's constraints has been checked and has
+/// failed, and keeps every failure of constraints that log, as part of the
+/// simulation state so a run resumed from a checkpoint reports the whole run.
+
+use serde::{Deserialize, Serialize};
+use crate::model::{ConstraintAction, Model};
+use crate::model::expression::EvaluationContext;
+use super::SimulationState;
+use super::integrator::evaluate_system;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConstraintMonitor {
+    /// One per constraint, in the model's order
+    pub summaries: Vec<ConstraintSummary>,
+    /// Every failure of a constraint whose action is `log`
+    pub log: Vec<ConstraintViolation>,
+    /// Time of the last check, so a state is not checked twice
+    last_checked: Option<f64>,
+}
+
+/// Checks and failures of one constraint over a run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConstraintSummary {
+    pub name: String,
+    pub action: ConstraintAction,
+    pub checks: usize,
+    pub failures: usize,
+    pub first_failure: Option<f64>,
+    pub last_failure: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConstraintViolation {
+    pub constraint: String,
+    pub time: f64,
+}
+
+impl ConstraintMonitor {
+    pub fn from_model(model: &Model) -> Self {
+        Self {
+            summaries: model.constraints.iter()
+                .map(|constraint| ConstraintSummary {
+                    name: constraint.name.clone(),
+                    action: constraint.action,
+                    checks: 0,
+                    failures: 0,
+                    first_failure: None,
+                    last_failure: None,
+                })
+                .collect(),
+            log: Vec::new(),
+            last_checked: None,
+        }
+    }
+
+    /// Whether every constraint has held at every check
+    pub fn all_held(&self) -> bool {
+        self.summaries.iter().all(|summary| summary.failures == 0)
+    }
+}
+
+/// Check every constraint against `state`, with auxiliaries and flows for its stocks
+///
+/// A failing `warn` constraint prints a warning the first time it fails; a
+/// failing `abort` constraint returns an error, which stops the run.
+pub(crate) fn check(model: &Model, state: &mut SimulationState) -> Result<(), String> {
+    if model.constraints.is_empty() || state.constraints.last_checked == Some(state.time) {
+        return Ok(());
+    }
+
+    let mut current = state.clone();
+    evaluate_system(model, &mut current, state.time)?;
+    let time = state.time;
+
+    for (constraint, summary) in model.constraints.iter().zip(&mut state.constraints.summaries) {
+        let held = constraint.condition.evaluate(&mut EvaluationContext::new(model, &mut current, time))
+            .map_err(|e| format!("Constraint '{}': {}", constraint.name, e))?
+            != 0.0;
+        summary.checks += 1;
+        if held {
+            continue;
+        }
+
+        summary.failures += 1;
+        summary.first_failure.get_or_insert(time);
+        summary.last_failure = Some(time);
+        match constraint.action {
+            ConstraintAction::Warn if summary.failures == 1 => {
+                eprintln!("Warning: reality check '{}' failed at t={}", constraint.name, time);
+            }
+            ConstraintAction::Warn => {}
+            ConstraintAction::Abort => {
+                return Err(format!("Reality check '{}' failed", constraint.name));
+            }
+            ConstraintAction::Log => {
+                state.constraints.log.push(ConstraintViolation { constraint: constraint.name.clone(), time });
+            }
+        }
+    }
+
+    state.constraints.last_checked = Some(time);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::{Constraint, ConstraintAction, Flow, Model, Parameter, Stock};
+    use crate::simulation::{SimulationConfig, SimulationEngine};
+
+    fn shipping_model() -> Model {
+        // Shipments go on whatever is left, so Inventory is empty at t=5 and negative after
+        let mut model = Model::new("Warehouse");
+        model.time.stop = 10.0;
+        model.time.dt = 1.0;
+        model.add_stock(Stock::new("Inventory", "50").with_outflows(vec!["shipments".to_string()])).unwrap();
+        model.add_parameter(Parameter::new("demand", 10.0)).unwrap();
+        model.add_flow(Flow::new("shipments", "demand")).unwrap();
+        model
+    }
+
+    #[test]
+    fn test_constraints_checked_every_step() {
+        let mut model = shipping_model();
+        model.add_constraint(Constraint::new("stocked", "ASSERT(Inventory >= 0)").unwrap()
+            .with_action(ConstraintAction::Log)).unwrap();
+        model.add_constraint(Constraint::new("no shipments when empty", "IMPLIES(Inventory <= 0, shipments = 0)").unwrap()).unwrap();
+
+        let mut engine = SimulationEngine::new(model.clone(), SimulationConfig::default()).unwrap();
+        engine.run().unwrap();
+        let monitor = &engine.current_state().constraints;
+        assert!(!monitor.all_held());
+
+        let stocked = &monitor.summaries[0];
+        assert_eq!((stocked.checks, stocked.failures), (11, 5));
+        assert_eq!((stocked.first_failure, stocked.last_failure), (Some(6.0), Some(10.0)));
+        assert_eq!(monitor.log.iter().map(|v| v.time).collect::<Vec<_>>(), vec![6.0, 7.0, 8.0, 9.0, 10.0]);
+        // Inventory reaches zero at t=5 with shipments still at 10
+        assert_eq!(monitor.summaries[1].failures, 6);
+        assert_eq!(monitor.summaries[1].first_failure, Some(5.0));
+
+        model.constraints[0].action = ConstraintAction::Abort;
+        let error = SimulationEngine::new(model, SimulationConfig::default()).unwrap().run().unwrap_err();
+        assert!(error.to_string().contains("Reality check 'stocked' failed"), "{}", error);
+    }
+}
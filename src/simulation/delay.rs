@@ -4,12 +4,21 @@
 /// - DELAY1: First-order exponential delay
 /// - DELAY3: Third-order delay (smoother)
 /// - SMOOTH: Alias for DELAY1
-/// - DELAYP: Pipeline (pure time) delay
+/// - DELAYN / SMOOTHN: Delays of any order
+/// - DELAYP / DELAY FIXED: Pipeline (pure time) delay
+///
+/// Equations only record the input of an exponential delay; the engine
+/// advances the delays once per accepted step, with the exact response to the
+/// input held over the step.
 
 use std::collections::{HashMap, VecDeque};
+use serde::{Deserialize, Serialize};
+
+/// Most stages a DELAYN or SMOOTHN may have
+pub const MAX_DELAY_ORDER: usize = 1000;
 
 /// Represents a single delay instance (for DELAY1/DELAY3/SMOOTH)
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExponentialDelay {
     /// Current delayed value
     pub value: f64,
@@ -19,6 +28,12 @@ pub struct ExponentialDelay {
     pub order: usize,
     /// For DELAY3, we need intermediate stages
     pub stages: Vec<f64>,
+    /// Input recorded during the current step, held over the next one
+    #[serde(default)]
+    pub input: f64,
+    /// Time the delay last advanced to
+    #[serde(default)]
+    pub time: Option<f64>,
 }
 
 impl ExponentialDelay {
@@ -34,46 +49,76 @@ impl ExponentialDelay {
             delay_time,
             order,
             stages,
+            input: initial_value,
+            time: None,
         }
     }
 
-    /// Update the delay using Euler integration
-    /// For DELAY1: d(output)/dt = (input - output) / delay_time
-    /// For DELAY3: Chain of 3 first-order delays
+    /// Update the delay over `dt` with `input` held throughout
+    ///
+    /// Each stage relaxes towards the one before it, the first towards the
+    /// input, with time constant `delay_time / order`. The update is exact, so
+    /// it is stable for any step: with `x = dt * order / delay_time` stage
+    /// times elapsed, stage `i`'s distance from the input becomes
+    /// `e^-x * sum over j <= i of d_j * x^(i - j) / (i - j)!`.
     pub fn update(&mut self, input: f64, dt: f64) {
-        if self.order == 1 {
-            // First-order delay
-            let derivative = (input - self.value) / self.delay_time;
-            self.value += derivative * dt;
-            self.stages[0] = self.value;
-        } else {
-            // Higher-order delay (cascade of first-order delays)
-            let stage_delay = self.delay_time / self.order as f64;
-
-            // First stage gets input
-            let deriv0 = (input - self.stages[0]) / stage_delay;
-            let new_stage0 = self.stages[0] + deriv0 * dt;
-
-            // Subsequent stages cascade
-            let mut new_stages = vec![new_stage0];
-            for i in 1..self.order {
-                let deriv = (self.stages[i - 1] - self.stages[i]) / stage_delay;
-                new_stages.push(self.stages[i] + deriv * dt);
-            }
+        let x = dt * self.order as f64 / self.delay_time;
+        if self.delay_time <= 0.0 || x > 2.0 * self.order as f64 + 1000.0 {
+            // No delay, or so many stage times that every stage has caught up
+            self.stages.fill(input);
+            self.value = input;
+            return;
+        }
 
-            self.stages = new_stages;
-            self.value = self.stages[self.order - 1];
+        // Split long steps so e^-x stays representable
+        let pieces = (x / 500.0).ceil().max(1.0);
+        let x = x / pieces;
+        let mut weights = Vec::with_capacity(self.order);
+        let mut weight = (-x).exp();
+        for m in 0..self.order {
+            weights.push(weight);
+            weight *= x / (m + 1) as f64;
         }
+        for _ in 0..pieces as usize {
+            let distances: Vec<f64> = self.stages.iter().map(|stage| stage - input).collect();
+            for (i, stage) in self.stages.iter_mut().enumerate() {
+                *stage = input + (0..=i).map(|j| distances[j] * weights[i - j]).sum::<f64>();
+            }
+        }
+        self.value = self.stages[self.order - 1];
     }
 
     /// Get current delayed value
     pub fn get_value(&self) -> f64 {
         self.value
     }
+
+    /// Record `input` and `delay_time` for the next step and return the
+    /// delayed output, which only changes when the delay is
+    /// [advanced](Self::advance)
+    ///
+    /// Recording again before the delay advances replaces the recorded
+    /// values, so the delay time may change as the delay runs.
+    pub fn record_and_get(&mut self, time: f64, input: f64, delay_time: f64) -> f64 {
+        self.time.get_or_insert(time);
+        self.input = input;
+        self.delay_time = delay_time;
+        self.value
+    }
+
+    /// Step to `time` with the input and delay time last recorded
+    pub fn advance(&mut self, time: f64) {
+        if let Some(last) = self.time
+            && time > last
+        {
+            self.update(self.input, time - last);
+            self.time = Some(time);
+        }
+    }
 }
 
 /// Represents a pipeline delay (fixed time delay with history buffer)
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PipelineDelay {
     /// History buffer storing (time, value) pairs
     history: VecDeque<(f64, f64)>,
@@ -93,7 +138,13 @@ impl PipelineDelay {
     }
 
     /// Record a new value at the current time
+    ///
+    /// Re-recording at the same (or an earlier) time replaces the newer
+    /// entries, so evaluating an equation more than once per step is harmless.
     pub fn push(&mut self, time: f64, value: f64) {
+        while self.history.back().is_some_and(|&(t, _)| t >= time) {
+            self.history.pop_back();
+        }
         self.history.push_back((time, value));
 
         // Remove old values that are too far in the past
@@ -111,49 +162,47 @@ impl PipelineDelay {
     }
 
     /// Get the delayed value at the current time
-    /// Uses linear interpolation between history points
+    ///
+    /// Returns the initial value until `delay_time` has elapsed since the first
+    /// recorded input, then the input from `delay_time` ago, linearly
+    /// interpolated between recorded points.
     pub fn get_delayed_value(&self, current_time: f64) -> f64 {
         let target_time = current_time - self.delay_time;
 
-        // If target time is before any history, return initial value
-        if self.history.is_empty() {
+        let Some(&(first_time, _)) = self.history.front() else {
+            return self.initial_value;
+        };
+        if target_time < first_time {
             return self.initial_value;
         }
 
-        if let Some(&(first_time, _)) = self.history.front() {
-            if target_time <= first_time {
-                return self.initial_value;
-            }
+        // First recorded point at or after the target time
+        let i = self.history.partition_point(|&(t, _)| t < target_time);
+        if i == self.history.len() {
+            // Target is after all recorded points, use last value
+            return self.history[i - 1].1;
         }
 
-        // Find the two points to interpolate between
-        for i in 0..self.history.len() {
-            let (t, v) = self.history[i];
-
-            if t >= target_time {
-                if i == 0 {
-                    // Target is before first recorded point, use initial
-                    return self.initial_value;
-                }
-
-                // Interpolate between i-1 and i
-                let (t_prev, v_prev) = self.history[i - 1];
-                let alpha = (target_time - t_prev) / (t - t_prev);
-                return v_prev + alpha * (v - v_prev);
-            }
+        let (t, v) = self.history[i];
+        if t == target_time || i == 0 {
+            return v;
         }
 
-        // Target is after all recorded points, use last value
-        if let Some(&(_, v)) = self.history.back() {
-            v
-        } else {
-            self.initial_value
-        }
+        // Interpolate between i-1 and i
+        let (t_prev, v_prev) = self.history[i - 1];
+        let alpha = (target_time - t_prev) / (t - t_prev);
+        v_prev + alpha * (v - v_prev)
+    }
+
+    /// Record the current input and return the delayed output
+    pub fn record_and_get(&mut self, time: f64, input: f64) -> f64 {
+        self.push(time, input);
+        self.get_delayed_value(time)
     }
 }
 
 /// Manager for all delays in a simulation
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DelayManager {
     /// Exponential delays (DELAY1, DELAY3, SMOOTH) indexed by unique key
     pub exponential_delays: HashMap<String, ExponentialDelay>,
@@ -194,12 +243,10 @@ impl DelayManager {
             .or_insert_with(|| PipelineDelay::new(initial_value, delay_time))
     }
 
-    /// Update all exponential delays
-    pub fn update_all_exponential(&mut self, inputs: &HashMap<String, f64>, dt: f64) {
-        for (key, delay) in &mut self.exponential_delays {
-            if let Some(&input) = inputs.get(key) {
-                delay.update(input, dt);
-            }
+    /// Step every exponential delay to `time`, at the end of an accepted step
+    pub fn advance(&mut self, time: f64) {
+        for delay in self.exponential_delays.values_mut() {
+            delay.advance(time);
         }
     }
 }
@@ -229,6 +276,38 @@ mod tests {
         assert!((delay.get_value() - 1.0).abs() < 0.1, "Got: {}", delay.get_value());
     }
 
+    #[test]
+    fn test_delay_exact_response() {
+        // Recording does not move the delay; advancing steps it exactly
+        let mut delay = ExponentialDelay::new(0.0, 2.0, 1);
+        delay.record_and_get(0.0, 10.0, 2.0);
+        assert_eq!(delay.record_and_get(0.0, 10.0, 2.0), 0.0);
+        delay.advance(2.0);
+        assert!((delay.get_value() - 10.0 * (1.0 - (-1.0f64).exp())).abs() < 1e-12);
+
+        // Third order, in quarter steps: 10 * (1 - e^-3 * (1 + 3 + 9/2)) at one delay time
+        let mut delay3 = ExponentialDelay::new(0.0, 2.0, 3);
+        for step in 1..=8 {
+            delay3.record_and_get(0.0, 10.0, 2.0);
+            delay3.advance(step as f64 * 0.25);
+        }
+        assert!((delay3.get_value() - 10.0 * (1.0 - (-3.0f64).exp() * 8.5)).abs() < 1e-9);
+
+        // A long cascade stepped well past its stage time stays between its start and input
+        let mut cascade = ExponentialDelay::new(0.0, 6.0, 20);
+        for t in 1..=30 {
+            cascade.record_and_get(0.0, 10.0, 6.0);
+            cascade.advance(t as f64);
+            assert!(cascade.stages.iter().all(|stage| (0.0..=10.0).contains(stage)));
+        }
+        assert!((cascade.get_value() - 10.0).abs() < 1e-3);
+
+        // A zero delay time passes the input straight through
+        delay.record_and_get(2.0, 4.0, 0.0);
+        delay.advance(3.0);
+        assert_eq!(delay.get_value(), 4.0);
+    }
+
     #[test]
     fn test_pipeline_delay() {
         let mut delay = PipelineDelay::new(0.0, 5.0);
@@ -243,4 +322,24 @@ mod tests {
         let delayed = delay.get_delayed_value(10.0);
         assert!((delayed - 5.0).abs() < 0.1);
     }
+
+    #[test]
+    fn test_pipeline_delay_tracks_inputs() {
+        let mut delay = PipelineDelay::new(-1.0, 2.0);
+
+        let mut outputs = Vec::new();
+        for step in 0..=8 {
+            let time = step as f64 * 0.5;
+            // Evaluating twice in the same step must not duplicate history
+            delay.record_and_get(time, time * 10.0);
+            outputs.push(delay.record_and_get(time, time * 10.0));
+        }
+
+        // Initial value until the delay elapses, then the input from 2 time units earlier
+        assert_eq!(&outputs[..4], &[-1.0, -1.0, -1.0, -1.0]);
+        for (step, output) in outputs.iter().enumerate().skip(4) {
+            let time = step as f64 * 0.5;
+            assert!((output - (time - 2.0) * 10.0).abs() < 1e-12);
+        }
+    }
 }
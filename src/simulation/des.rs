@@ -0,0 +1,485 @@
+/// Discrete-event simulation of a model's entities
+///
+/// Entities move between generators, queues, servers and sinks in continuous
+/// time. Every step processes the arrivals and service completions up to and
+/// including its end in time order, completions first at equal times. An entity
+/// reaching a full queue is lost. Generators fed by a flow break the material
+/// it moves over the step, at its rate at the start of the step, into
+/// entities at evenly spread times. What sinks receive is added to their
+/// stocks at the end of the step.
+
+use std::collections::{HashMap, VecDeque};
+use serde::{Deserialize, Serialize};
+use crate::model::{Model, QueueDiscipline, TimeDistribution};
+use super::{SimulationState, StochasticManager};
+use super::integrator::evaluate_system;
+
+/// Entities and the generators, queues, servers and sinks they move through
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DesManager {
+    generators: Vec<Generator>,
+    queues: Vec<Queue>,
+    servers: Vec<Server>,
+    sinks: Vec<Sink>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+enum Node {
+    Queue(usize),
+    Sink(usize),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entity {
+    created: f64,
+    priority: f64,
+    size: f64,
+    /// When it joined the queue it is in
+    queued: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Generator {
+    target: Node,
+    interarrival: Option<TimeDistribution>,
+    flow: Option<String>,
+    size: f64,
+    priority: f64,
+    limit: Option<usize>,
+    created: usize,
+    /// Arrival times still to come, earliest first
+    pending: VecDeque<f64>,
+    /// Material from the flow not yet made into an entity
+    carry: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Queue {
+    name: String,
+    discipline: QueueDiscipline,
+    capacity: Option<usize>,
+    waiting: VecDeque<Entity>,
+    lost: usize,
+    /// Entities that have left for service, and their total time waiting
+    departed: usize,
+    total_wait: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Server {
+    name: String,
+    queue: usize,
+    service: TimeDistribution,
+    capacity: usize,
+    target: Node,
+    /// Entities in service and when each finishes
+    busy: Vec<(f64, Entity)>,
+    completed: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Sink {
+    name: String,
+    stock: Option<String>,
+    delivered: usize,
+    /// Total size of the entities delivered
+    amount: f64,
+    /// Total time the delivered entities spent in the system
+    total_time: f64,
+}
+
+enum Occurrence {
+    Completion { server: usize, slot: usize },
+    Arrival { generator: usize },
+}
+
+impl Queue {
+    fn is_full(&self) -> bool {
+        self.capacity.is_some_and(|capacity| self.waiting.len() >= capacity)
+    }
+
+    /// Next entity to serve
+    fn take(&mut self) -> Option<Entity> {
+        let index = match self.discipline {
+            QueueDiscipline::Fifo => 0,
+            QueueDiscipline::Priority => self.waiting.iter().enumerate()
+                .fold(None, |best: Option<(usize, f64)>, (i, entity)| match best {
+                    Some((_, priority)) if priority >= entity.priority => best,
+                    _ => Some((i, entity.priority)),
+                })
+                .map_or(0, |(i, _)| i),
+        };
+        self.waiting.remove(index)
+    }
+}
+
+impl Generator {
+    /// Schedule an entity for every `size` of material the flow moves at
+    /// `rate` over the `dt` from `time`
+    fn release(&mut self, rate: f64, time: f64, dt: f64) {
+        if rate.is_nan() || rate <= 0.0 || dt <= 0.0 {
+            return;
+        }
+        let total = self.carry + rate * dt;
+        let mut released = 0.0;
+        while released + self.size <= total * (1.0 + 1e-12) {
+            released += self.size;
+            self.pending.push_back(time + (released - self.carry) / rate);
+        }
+        self.carry = (total - released).max(0.0);
+    }
+}
+
+impl DesManager {
+    /// Set up the network in `model`'s `des` section, scheduling each
+    /// generator's first arrival
+    pub fn from_model(model: &Model, stochastic: &mut StochasticManager) -> Result<Self, String> {
+        let Some(config) = &model.des else { return Ok(Self::default()) };
+
+        let mut names: Vec<&String> = config.generators.iter().map(|generator| &generator.name)
+            .chain(config.node_names())
+            .collect();
+        names.sort();
+        if let Some(pair) = names.windows(2).find(|pair| pair[0] == pair[1]) {
+            return Err(format!("Discrete-event element '{}' is defined more than once", pair[0]));
+        }
+
+        let queue_index: HashMap<&str, usize> = config.queues.iter().enumerate().map(|(i, q)| (q.name.as_str(), i)).collect();
+        let sink_index: HashMap<&str, usize> = config.sinks.iter().enumerate().map(|(i, s)| (s.name.as_str(), i)).collect();
+        let node = |owner: &str, target: &str| {
+            queue_index.get(target).map(|&i| Node::Queue(i))
+                .or_else(|| sink_index.get(target).map(|&i| Node::Sink(i)))
+                .ok_or_else(|| format!("'{}' sends entities to '{}', which is not a queue or sink", owner, target))
+        };
+
+        let mut manager = Self::default();
+        for generator in &config.generators {
+            let flow = match (&generator.interarrival, &generator.flow) {
+                (Some(interarrival), None) => {
+                    interarrival.validate_interarrival().map_err(|e| format!("Generator '{}': {}", generator.name, e))?;
+                    None
+                }
+                (None, Some(flow)) if model.flows.contains_key(flow) => Some(flow.clone()),
+                (None, Some(flow)) => return Err(format!("Generator '{}' is fed by undefined flow '{}'", generator.name, flow)),
+                _ => return Err(format!("Generator '{}' needs exactly one of 'interarrival' or 'flow'", generator.name)),
+            };
+            if generator.size.is_nan() || generator.size <= 0.0 {
+                return Err(format!("Generator '{}' needs a positive entity size, got {}", generator.name, generator.size));
+            }
+            let mut pending = VecDeque::new();
+            if let Some(interarrival) = &generator.interarrival
+                && generator.limit != Some(0)
+            {
+                pending.push_back(model.time.start + interarrival.sample(stochastic)?);
+            }
+            manager.generators.push(Generator {
+                target: node(&generator.name, &generator.target)?,
+                interarrival: generator.interarrival.clone(),
+                flow,
+                size: generator.size,
+                priority: generator.priority,
+                limit: generator.limit,
+                created: 0,
+                pending,
+                carry: 0.0,
+            });
+        }
+
+        for queue in &config.queues {
+            manager.queues.push(Queue {
+                name: queue.name.clone(),
+                discipline: queue.discipline,
+                capacity: queue.capacity,
+                waiting: VecDeque::new(),
+                lost: 0,
+                departed: 0,
+                total_wait: 0.0,
+            });
+        }
+
+        for server in &config.servers {
+            let queue = *queue_index.get(server.queue.as_str())
+                .ok_or_else(|| format!("Server '{}' serves undefined queue '{}'", server.name, server.queue))?;
+            if server.capacity == 0 {
+                return Err(format!("Server '{}' needs a capacity of at least 1", server.name));
+            }
+            server.service.validate().map_err(|e| format!("Server '{}': {}", server.name, e))?;
+            manager.servers.push(Server {
+                name: server.name.clone(),
+                queue,
+                service: server.service.clone(),
+                capacity: server.capacity,
+                target: node(&server.name, &server.target)?,
+                busy: Vec::new(),
+                completed: 0,
+            });
+        }
+
+        for sink in &config.sinks {
+            if let Some(stock) = sink.stock.as_ref().filter(|stock| !model.stocks.contains_key(*stock)) {
+                return Err(format!("Sink '{}' delivers to undefined stock '{}'", sink.name, stock));
+            }
+            manager.sinks.push(Sink {
+                name: sink.name.clone(),
+                stock: sink.stock.clone(),
+                delivered: 0,
+                amount: 0.0,
+                total_time: 0.0,
+            });
+        }
+
+        Ok(manager)
+    }
+
+    /// Whether the model has no discrete-event elements
+    pub fn is_empty(&self) -> bool {
+        self.generators.is_empty() && self.queues.is_empty() && self.servers.is_empty() && self.sinks.is_empty()
+    }
+
+    /// Entities waiting in a queue
+    pub fn queue_length(&self, queue: &str) -> Option<usize> {
+        self.queue(queue).map(|queue| queue.waiting.len())
+    }
+
+    /// Entities turned away from a full queue so far
+    pub fn lost(&self, queue: &str) -> Option<usize> {
+        self.queue(queue).map(|queue| queue.lost)
+    }
+
+    /// Mean time entities that have left a queue spent waiting in it; 0 before any have
+    pub fn mean_wait(&self, queue: &str) -> Option<f64> {
+        self.queue(queue).map(|queue| {
+            if queue.departed == 0 { 0.0 } else { queue.total_wait / queue.departed as f64 }
+        })
+    }
+
+    /// Entities a server is serving
+    pub fn in_service(&self, server: &str) -> Option<usize> {
+        self.servers.iter().find(|s| s.name == server).map(|server| server.busy.len())
+    }
+
+    /// Entities a server has finished serving
+    pub fn completed(&self, server: &str) -> Option<usize> {
+        self.servers.iter().find(|s| s.name == server).map(|server| server.completed)
+    }
+
+    /// Entities delivered to a sink so far
+    pub fn delivered(&self, sink: &str) -> Option<usize> {
+        self.sink(sink).map(|sink| sink.delivered)
+    }
+
+    /// Total size of the entities delivered to a sink so far
+    pub fn delivered_amount(&self, sink: &str) -> Option<f64> {
+        self.sink(sink).map(|sink| sink.amount)
+    }
+
+    /// Mean time from creation to delivery of the entities a sink has received; 0 before any have
+    pub fn mean_time_in_system(&self, sink: &str) -> Option<f64> {
+        self.sink(sink).map(|sink| {
+            if sink.delivered == 0 { 0.0 } else { sink.total_time / sink.delivered as f64 }
+        })
+    }
+
+    fn queue(&self, name: &str) -> Option<&Queue> {
+        self.queues.iter().find(|queue| queue.name == name)
+    }
+
+    fn sink(&self, name: &str) -> Option<&Sink> {
+        self.sinks.iter().find(|sink| sink.name == name)
+    }
+
+    /// Process every event after `start` up to and including `end`, returning the amount
+    /// delivered to each sink
+    fn run(&mut self, start: f64, end: f64, stochastic: &mut StochasticManager) -> Result<Vec<f64>, String> {
+        let epsilon = (end - start).abs() * 1e-9;
+        let mut deliveries = vec![0.0; self.sinks.len()];
+        self.start_service(start, stochastic)?;
+
+        loop {
+            let completion = self.servers.iter().enumerate()
+                .flat_map(|(server, s)| s.busy.iter().enumerate().map(move |(slot, (at, _))| (*at, server, slot)))
+                .min_by(|a, b| a.0.total_cmp(&b.0));
+            let arrival = self.generators.iter().enumerate()
+                .filter_map(|(generator, g)| g.pending.front().map(|at| (*at, generator)))
+                .min_by(|a, b| a.0.total_cmp(&b.0));
+            let (time, occurrence) = match (completion, arrival) {
+                (Some((at, server, slot)), arrival) if arrival.is_none_or(|(next, _)| at <= next) => {
+                    (at, Occurrence::Completion { server, slot })
+                }
+                (_, Some((at, generator))) => (at, Occurrence::Arrival { generator }),
+                (None, None) => break,
+                _ => unreachable!(),
+            };
+            if time > end + epsilon {
+                break;
+            }
+
+            match occurrence {
+                Occurrence::Completion { server, slot } => {
+                    let server = &mut self.servers[server];
+                    let (_, entity) = server.busy.remove(slot);
+                    server.completed += 1;
+                    let target = server.target;
+                    self.route(target, entity, time, &mut deliveries);
+                }
+                Occurrence::Arrival { generator } => {
+                    let g = &mut self.generators[generator];
+                    g.pending.pop_front();
+                    if g.limit.is_some_and(|limit| g.created >= limit) {
+                        continue;
+                    }
+                    g.created += 1;
+                    if let Some(interarrival) = &g.interarrival
+                        && g.limit.is_none_or(|limit| g.created < limit)
+                    {
+                        g.pending.push_back(time + interarrival.sample(stochastic)?);
+                    }
+                    let entity = Entity { created: time, priority: g.priority, size: g.size, queued: time };
+                    let target = g.target;
+                    self.route(target, entity, time, &mut deliveries);
+                }
+            }
+            self.start_service(time, stochastic)?;
+        }
+
+        Ok(deliveries)
+    }
+
+    fn route(&mut self, node: Node, mut entity: Entity, time: f64, deliveries: &mut [f64]) {
+        match node {
+            Node::Queue(queue) => {
+                let queue = &mut self.queues[queue];
+                if queue.is_full() {
+                    queue.lost += 1;
+                } else {
+                    entity.queued = time;
+                    queue.waiting.push_back(entity);
+                }
+            }
+            Node::Sink(index) => {
+                let sink = &mut self.sinks[index];
+                sink.delivered += 1;
+                sink.amount += entity.size;
+                sink.total_time += time - entity.created;
+                deliveries[index] += entity.size;
+            }
+        }
+    }
+
+    /// Have every server with room take entities from its queue at `time`
+    fn start_service(&mut self, time: f64, stochastic: &mut StochasticManager) -> Result<(), String> {
+        for server in &mut self.servers {
+            let queue = &mut self.queues[server.queue];
+            while server.busy.len() < server.capacity {
+                let Some(entity) = queue.take() else { break };
+                queue.departed += 1;
+                queue.total_wait += time - entity.queued;
+                let done = time + server.service.sample(stochastic)?;
+                server.busy.push((done, entity));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Move the entities forward over the step from `before` to `after`, adding
+/// what the sinks receive to their stocks
+pub(crate) fn advance(model: &Model, before: &SimulationState, mut after: SimulationState) -> Result<SimulationState, String> {
+    if before.des.is_empty() {
+        return Ok(after);
+    }
+
+    let mut manager = before.des.clone();
+    let dt = after.time - before.time;
+    if manager.generators.iter().any(|generator| generator.flow.is_some()) {
+        let mut start = before.clone();
+        evaluate_system(model, &mut start, before.time)?;
+        for generator in &mut manager.generators {
+            if let Some(flow) = &generator.flow {
+                let rate = start.flows.get(flow).copied().unwrap_or(0.0);
+                generator.release(rate, before.time, dt);
+            }
+        }
+    }
+
+    let deliveries = manager.run(before.time, after.time, &mut after.stochastic)?;
+    for (sink, amount) in manager.sinks.iter().zip(deliveries) {
+        if let Some(stock) = &sink.stock
+            && let Some(value) = after.stocks.get_mut(stock)
+        {
+            *value += amount;
+        }
+    }
+
+    after.des = manager;
+    Ok(after)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::{DesConfig, Flow, GeneratorConfig, Model, QueueDiscipline, Stock, TimeDistribution};
+    use crate::simulation::{SimulationConfig, SimulationEngine};
+
+    #[test]
+    fn test_des_queue_and_server() {
+        // Orders arrive every 1.0 and take 1.5 to pick, so the queue grows by
+        // one every three arrivals; picked orders add 10 units to a stock
+        let mut model = Model::new("Picking");
+        model.time.stop = 10.0;
+        model.time.dt = 1.0;
+        model.add_stock(Stock::new("Shipped", "0")).unwrap();
+        model.add_auxiliary(crate::model::Auxiliary::new("waiting", "DES_QUEUE(picking)")).unwrap();
+        model.des = Some(DesConfig::new()
+            .with_generator(GeneratorConfig { size: 10.0, ..GeneratorConfig::new("orders", "picking", TimeDistribution::Constant { value: 1.0 }) })
+            .with_queue("picking", QueueDiscipline::Fifo, None)
+            .with_server("picker", "picking", TimeDistribution::Constant { value: 1.5 }, 1, "dock")
+            .with_sink("dock", Some("Shipped")));
+
+        let mut engine = SimulationEngine::new(model, SimulationConfig::default()).unwrap();
+        let results = engine.run().unwrap();
+        let des = &engine.current_state().des;
+
+        // Of the arrivals at 1..=10, six are picked by 2.5, 4, 5.5, 7, 8.5
+        // and 10, the seventh is being picked and the last three wait
+        assert_eq!(des.delivered("dock"), Some(6));
+        assert_eq!(des.in_service("picker"), Some(1));
+        assert_eq!(des.queue_length("picking"), Some(3));
+        assert_eq!(results.get_variable_series("Shipped").unwrap()[5], 20.0);
+        assert_eq!(results.get_variable_series("waiting").unwrap()[10], 3.0);
+        assert!((des.mean_wait("picking").unwrap() - 1.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_des_rejects_zero_interarrival() {
+        // Arrivals every 0 time units would never let the clock move on
+        for interarrival in [TimeDistribution::Constant { value: 0.0 }, TimeDistribution::Uniform { min: 0.0, max: 0.0 }] {
+            let mut model = Model::new("Flood");
+            model.add_stock(Stock::new("Served", "0")).unwrap();
+            model.des = Some(DesConfig::new()
+                .with_generator(GeneratorConfig::new("orders", "dock", interarrival))
+                .with_sink("dock", Some("Served")));
+            let err = SimulationEngine::new(model, SimulationConfig::default()).err().unwrap();
+            assert!(err.to_string().contains("never positive"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_des_from_flow() {
+        // A continuous release of 2.5 a step becomes pallets of 1
+        let mut model = Model::new("Pallets");
+        model.time.stop = 4.0;
+        model.time.dt = 1.0;
+        model.add_stock(Stock::new("Backlog", "100").with_outflows(vec!["release".to_string()])).unwrap();
+        model.add_stock(Stock::new("Stored", "0")).unwrap();
+        model.add_flow(Flow::new("release", "2.5")).unwrap();
+        model.des = Some(DesConfig::new()
+            .with_generator(GeneratorConfig::from_flow("pallets", "store", "release", 1.0))
+            .with_sink("store", Some("Stored")));
+
+        let mut engine = SimulationEngine::new(model, SimulationConfig::default()).unwrap();
+        let last = engine.run().unwrap().final_state().unwrap().clone();
+        assert_eq!(last.stocks["Backlog"], 90.0);
+        assert_eq!(last.stocks["Stored"], 10.0);
+        assert_eq!(engine.current_state().des.delivered("store"), Some(10));
+    }
+}
@@ -0,0 +1,461 @@
+/// Partitioned co-simulation of one model across A2A nodes
+///
+/// A model is split by stock groups. Each partition integrates its own stocks
+/// together with the flows attached to them and every auxiliary and parameter
+/// those flows need. Stocks owned by another partition but read locally are
+/// kept as read-only copies that are refreshed from their owner every step via
+/// A2A `StateSync` messages.
+///
+/// A flow between stocks of two partitions is computed only by the lower
+/// indexed one, which integrates it into a running total and sends the total
+/// with its stocks; the other side moves the same amount into or out of its
+/// stock, so no material is created or lost at the boundary.
+///
+/// Partitions advance in lock-step with a fixed-step integrator. With Euler
+/// the reassembled results match a single-process run exactly; multi-stage
+/// methods hold foreign stocks constant within a step.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::time::Duration;
+use crate::analysis::DependencyGraph;
+use crate::model::{Model, Stock};
+use crate::protocol::a2a::{A2aNode, A2aPayload, AgentId, LoopbackHub};
+use super::{IntegrationMethod, SimulationConfig, SimulationEngine, SimulationResults, SimulationState};
+
+/// One partition's share of a model
+#[derive(Debug, Clone)]
+pub struct ModelPartition {
+    pub index: usize,
+    /// Sub-model holding owned stocks, foreign stock copies and their inputs
+    pub model: Model,
+    /// Stocks integrated by this partition
+    pub owned: Vec<String>,
+    /// Foreign stocks read by this partition, with the index of their owner
+    pub imports: HashMap<String, usize>,
+    /// Owned stocks, and running totals of boundary flows, read by other partitions
+    pub exports: Vec<String>,
+    /// Flows into or out of owned stocks that another partition computes
+    pub received: Vec<BoundaryFlow>,
+}
+
+/// A flow into or out of an owned stock that another partition computes
+#[derive(Debug, Clone)]
+pub struct BoundaryFlow {
+    pub flow: String,
+    pub stock: String,
+    /// Whether the flow fills `stock` rather than drains it
+    pub inflow: bool,
+    /// Partition that computes the flow and sends its running total
+    pub owner: usize,
+}
+
+impl ModelPartition {
+    /// Partitions this one waits on each step
+    fn sources(&self) -> BTreeSet<usize> {
+        self.imports.values().chain(self.received.iter().map(|flow| &flow.owner)).copied().collect()
+    }
+}
+
+/// Name of the stock integrating boundary flow `flow` in the partition that computes it
+pub fn flow_total(flow: &str) -> String {
+    format!("{}#total", flow)
+}
+
+/// Split `model` into one partition per stock group
+///
+/// Every stock must appear in exactly one group.
+pub fn partition_model(model: &Model, groups: &[Vec<String>]) -> Result<Vec<ModelPartition>, String> {
+    if groups.is_empty() {
+        return Err("At least one stock group is required".to_string());
+    }
+
+    let mut owner: HashMap<&str, usize> = HashMap::new();
+    for (index, group) in groups.iter().enumerate() {
+        for name in group {
+            if !model.stocks.contains_key(name) {
+                return Err(format!("Stock '{}' in group {} not found in model", name, index));
+            }
+            if owner.insert(name, index).is_some() {
+                return Err(format!("Stock '{}' is assigned to more than one group", name));
+            }
+        }
+    }
+    let mut unassigned: Vec<&String> = model.stocks.keys()
+        .filter(|name| !owner.contains_key(name.as_str()))
+        .collect();
+    if !unassigned.is_empty() {
+        unassigned.sort();
+        return Err(format!("Stocks not assigned to any group: {:?}", unassigned));
+    }
+
+    // A flow attached to stocks of several partitions is computed by the lowest indexed one
+    let mut flow_owner: HashMap<&str, usize> = HashMap::new();
+    let mut crossing: HashSet<&str> = HashSet::new();
+    for (name, stock) in &model.stocks {
+        for flow in stock.inflows.iter().chain(&stock.outflows) {
+            let index = owner[name.as_str()];
+            let first = *flow_owner.entry(flow).or_insert(index);
+            if first != index {
+                crossing.insert(flow);
+                flow_owner.insert(flow, first.min(index));
+            }
+        }
+    }
+
+    let mut partitions = Vec::with_capacity(groups.len());
+    for (index, group) in groups.iter().enumerate() {
+        let needed = required_variables(model, group);
+
+        let mut sub_model = model.clone();
+        sub_model.evaluation_order = None;
+        sub_model.evaluation_strata = None;
+        sub_model.stocks.retain(|name, _| needed.contains(name));
+        sub_model.flows.retain(|name, _| needed.contains(name));
+        sub_model.auxiliaries.retain(|name, _| needed.contains(name));
+        sub_model.parameters.retain(|name, _| needed.contains(name));
+
+        let mut imports = HashMap::new();
+        let mut received = Vec::new();
+        let mut totals = BTreeSet::new();
+        for (name, stock) in sub_model.stocks.iter_mut() {
+            if owner[name.as_str()] != index {
+                // Foreign copy: never integrated here, only overwritten by its owner
+                stock.inflows.clear();
+                stock.outflows.clear();
+                imports.insert(name.clone(), owner[name.as_str()]);
+                continue;
+            }
+            for (flows, inflow) in [(&mut stock.inflows, true), (&mut stock.outflows, false)] {
+                flows.retain(|flow| {
+                    if !crossing.contains(flow.as_str()) {
+                        return true;
+                    }
+                    let flow_owner = flow_owner[flow.as_str()];
+                    if flow_owner == index {
+                        totals.insert(flow.clone());
+                        return true;
+                    }
+                    received.push(BoundaryFlow { flow: flow.clone(), stock: name.clone(), inflow, owner: flow_owner });
+                    false
+                });
+            }
+        }
+        for flow in totals {
+            sub_model.add_stock(Stock::new(&flow_total(&flow), "0").with_inflows(vec![flow]))?;
+        }
+        received.sort_by(|a, b| (&a.flow, &a.stock).cmp(&(&b.flow, &b.stock)));
+
+        partitions.push(ModelPartition {
+            index,
+            model: sub_model,
+            owned: group.clone(),
+            imports,
+            exports: Vec::new(),
+            received,
+        });
+    }
+
+    let mut exports: Vec<BTreeSet<String>> = vec![BTreeSet::new(); partitions.len()];
+    for partition in &partitions {
+        for (name, &source) in &partition.imports {
+            exports[source].insert(name.clone());
+        }
+        for flow in &partition.received {
+            exports[flow.owner].insert(flow_total(&flow.flow));
+        }
+    }
+    for (partition, exports) in partitions.iter_mut().zip(exports) {
+        partition.exports = exports.into_iter().collect();
+    }
+
+    Ok(partitions)
+}
+
+/// Owned stocks plus everything their flows and initial values depend on
+fn required_variables(model: &Model, stocks: &[String]) -> HashSet<String> {
+    let mut needed = HashSet::new();
+    let mut pending: Vec<String> = stocks.to_vec();
+
+    for name in stocks {
+        let stock = &model.stocks[name];
+        pending.extend(stock.inflows.iter().cloned());
+        pending.extend(stock.outflows.iter().cloned());
+    }
+
+    while let Some(name) = pending.pop() {
+        if !needed.insert(name.clone()) {
+            continue;
+        }
+        let equation = if let Some(flow) = model.flows.get(&name) {
+            &flow.equation
+        } else if let Some(aux) = model.auxiliaries.get(&name) {
+            &aux.equation
+        } else if let Some(stock) = model.stocks.get(&name) {
+            &stock.initial
+        } else {
+            continue;
+        };
+        pending.extend(DependencyGraph::extract_dependencies(equation));
+    }
+
+    needed
+}
+
+/// A2A agent id used by partition `index`
+pub fn partition_agent(model_name: &str, index: usize) -> AgentId {
+    AgentId::new(model_name, &format!("partition_{}", index))
+}
+
+/// Run one partition to completion, exchanging boundary stocks and flow totals through `node`
+///
+/// Waits at most `sync_timeout` for each step's boundary values. One output
+/// row is recorded per step.
+pub async fn run_partition(
+    partition: &ModelPartition,
+    node: &mut A2aNode,
+    config: SimulationConfig,
+    sync_timeout: Duration,
+) -> Result<SimulationResults, String> {
+    if let IntegrationMethod::RK45 = config.integration_method {
+        return Err("Partitioned simulation requires a fixed-step integrator".to_string());
+    }
+
+    let stop_time = partition.model.time.stop;
+    let epsilon = partition.model.time.dt * 1e-6;
+    let sources = partition.sources();
+    let mut engine = SimulationEngine::new(partition.model.clone(), config)?;
+    let mut results = SimulationResults::new();
+    // Boundary values received ahead of time, by (source partition, step)
+    let mut received: HashMap<(usize, u64), HashMap<String, f64>> = HashMap::new();
+    // Running total of each received boundary flow, as of the last step
+    let mut totals: HashMap<&str, f64> = HashMap::new();
+    let mut step = 0u64;
+
+    loop {
+        if !partition.exports.is_empty() {
+            let mut state = HashMap::new();
+            state.insert("partition".to_string(), serde_json::json!(partition.index));
+            state.insert("step".to_string(), serde_json::json!(step));
+            for name in &partition.exports {
+                let value = engine.current_state().stocks.get(name)
+                    .ok_or_else(|| format!("Partition {} has no stock '{}' to send", partition.index, name))?;
+                state.insert(name.clone(), serde_json::json!(value));
+            }
+            node.broadcast(A2aPayload::StateSync { state }).await
+                .map_err(|e| format!("Failed to send boundary values: {}", e))?;
+        }
+
+        let deadline = tokio::time::Instant::now() + sync_timeout;
+        while !sources.iter().all(|&source| received.contains_key(&(source, step))) {
+            let message = tokio::time::timeout_at(deadline, node.poll()).await
+                .map_err(|_| format!("Timed out waiting for boundary values at step {}", step))?
+                .map_err(|e| format!("Failed to receive boundary values: {}", e))?;
+
+            if let A2aPayload::StateSync { state } = message.payload
+                && let (Some(source), Some(at)) = (
+                    state.get("partition").and_then(|v| v.as_u64()),
+                    state.get("step").and_then(|v| v.as_u64()),
+                )
+            {
+                let values = state.iter()
+                    .filter_map(|(name, value)| value.as_f64().map(|v| (name.clone(), v)))
+                    .collect();
+                received.insert((source as usize, at), values);
+            }
+        }
+
+        for &source in &sources {
+            let values = received.remove(&(source, step)).unwrap_or_default();
+            let value_of = |name: &str| values.get(name).copied()
+                .ok_or_else(|| format!("Partition {} did not send '{}'", source, name));
+            for (name, &owner) in &partition.imports {
+                if owner == source {
+                    engine.set_stock(name, value_of(name)?)?;
+                }
+            }
+            // Move what each boundary flow moved on its owner's side over the last step
+            let mut moved = HashMap::new();
+            for flow in partition.received.iter().filter(|flow| flow.owner == source) {
+                let total = value_of(&flow_total(&flow.flow))?;
+                let previous = totals.insert(&flow.flow, total).unwrap_or(0.0);
+                moved.entry(&flow.flow).or_insert(total - previous);
+            }
+            for flow in partition.received.iter().filter(|flow| flow.owner == source) {
+                let amount = if flow.inflow { moved[&flow.flow] } else { -moved[&flow.flow] };
+                let current = engine.current_state().stocks[&flow.stock];
+                engine.set_stock(&flow.stock, current + amount)?;
+            }
+        }
+
+        let time = engine.current_time();
+        results.add_point(time.min(stop_time), engine.current_state());
+        if time >= stop_time - epsilon {
+            break;
+        }
+
+        engine.step()?;
+        step += 1;
+    }
+
+    Ok(results)
+}
+
+/// Reassemble per-partition results into results for the whole model
+///
+/// Stocks come from their owner; flows and auxiliaries computed by several
+/// partitions are taken from the lowest-indexed one.
+pub fn merge_results(partitions: &[ModelPartition], results: &[SimulationResults]) -> Result<SimulationResults, String> {
+    let Some(first) = results.first() else {
+        return Ok(SimulationResults::new());
+    };
+    if partitions.len() != results.len() {
+        return Err("Expected one result set per partition".to_string());
+    }
+    if results.iter().any(|r| r.times.len() != first.times.len()) {
+        return Err("Partition results have different lengths".to_string());
+    }
+
+    let mut merged = SimulationResults::new();
+    for (row, &time) in first.times.iter().enumerate() {
+        let mut state = SimulationState::new();
+        state.time = time;
+
+        for (partition, result) in partitions.iter().zip(results) {
+            for name in &partition.owned {
+                let series = result.stocks.get(name)
+                    .ok_or_else(|| format!("Partition results have no stock '{}'", name))?;
+                state.stocks.insert(name, series[row]);
+            }
+            for (name, series) in result.flows.iter() {
+                if !state.flows.contains_key(name) {
+                    state.flows.insert(name, series[row]);
+                }
+            }
+            for (name, series) in result.auxiliaries.iter() {
+                if !state.auxiliaries.contains_key(name) {
+                    state.auxiliaries.insert(name, series[row]);
+                }
+            }
+        }
+
+        merged.add_point(time, &state);
+    }
+
+    Ok(merged)
+}
+
+/// Run `model` split by `groups` on in-process A2A nodes and reassemble the results
+pub async fn run_partitioned(
+    model: &Model,
+    groups: &[Vec<String>],
+    config: SimulationConfig,
+) -> Result<SimulationResults, String> {
+    let partitions = partition_model(model, groups)?;
+    let hub = LoopbackHub::new();
+
+    let tasks: Vec<_> = partitions.iter()
+        .map(|partition| {
+            let agent = partition_agent(&model.metadata.name, partition.index);
+            let mut node = A2aNode::new(agent.clone())
+                .with_transport(Box::new(hub.connect(agent)));
+            let partition = partition.clone();
+            let config = config.clone();
+            tokio::spawn(async move {
+                run_partition(&partition, &mut node, config, Duration::from_secs(30)).await
+            })
+        })
+        .collect();
+
+    let mut results = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        results.push(task.await.map_err(|e| format!("Partition task failed: {}", e))??);
+    }
+
+    merge_results(&partitions, &results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Flow, Parameter, Stock};
+
+    fn predator_prey() -> Model {
+        let mut model = Model::new("PredatorPrey");
+        model.time.stop = 10.0;
+        model.time.dt = 0.05;
+        model.add_stock(Stock::new("Prey", "40").with_inflows(vec!["prey_births".to_string()])
+            .with_outflows(vec!["predation".to_string()])).unwrap();
+        model.add_stock(Stock::new("Predators", "9").with_inflows(vec!["predator_births".to_string()])
+            .with_outflows(vec!["predator_deaths".to_string()])).unwrap();
+        model.add_parameter(Parameter::new("prey_growth", 0.1)).unwrap();
+        model.add_parameter(Parameter::new("predation_rate", 0.02)).unwrap();
+        model.add_parameter(Parameter::new("efficiency", 0.01)).unwrap();
+        model.add_parameter(Parameter::new("predator_mortality", 0.106)).unwrap();
+        model.add_flow(Flow::new("prey_births", "prey_growth * Prey")).unwrap();
+        model.add_flow(Flow::new("predation", "predation_rate * Prey * Predators")).unwrap();
+        model.add_flow(Flow::new("predator_births", "efficiency * Prey * Predators")).unwrap();
+        model.add_flow(Flow::new("predator_deaths", "predator_mortality * Predators")).unwrap();
+        model
+    }
+
+    #[test]
+    fn test_partition_model() {
+        let model = predator_prey();
+        let partitions = partition_model(&model, &[vec!["Prey".to_string()], vec!["Predators".to_string()]]).unwrap();
+
+        let prey = &partitions[0];
+        assert_eq!(prey.imports.get("Predators"), Some(&1));
+        assert_eq!(prey.exports, vec!["Prey".to_string()]);
+        assert!(prey.model.flows.contains_key("predation"));
+        assert!(!prey.model.flows.contains_key("predator_deaths"));
+        assert!(!prey.model.parameters.contains_key("predator_mortality"));
+        assert!(prey.model.stocks["Predators"].inflows.is_empty());
+
+        assert!(partition_model(&model, &[vec!["Prey".to_string()]]).is_err());
+        assert!(partition_model(&model, &[vec!["Prey".to_string(), "Predators".to_string()], vec!["Prey".to_string()]]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_partitioned_run_matches_single_process() {
+        let model = predator_prey();
+        let expected = SimulationEngine::new(model.clone(), SimulationConfig::default()).unwrap().run().unwrap();
+
+        let groups = [vec!["Prey".to_string()], vec!["Predators".to_string()]];
+        let results = run_partitioned(&model, &groups, SimulationConfig::default()).await.unwrap();
+
+        assert_eq!(results.times.len(), expected.times.len());
+        for name in ["Prey", "Predators", "predation"] {
+            let actual = results.get_variable_series(name).unwrap();
+            let reference = expected.get_variable_series(name).unwrap();
+            for (a, b) in actual.iter().zip(&reference) {
+                assert!((a - b).abs() < 1e-9, "{}: {} vs {}", name, a, b);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_boundary_flows_conserve_material() {
+        // Shipping drains Factory into Market and reads both
+        let mut model = Model::new("Supply");
+        model.time.stop = 5.0;
+        model.time.dt = 0.5;
+        model.add_stock(Stock::new("Factory", "100").with_outflows(vec!["shipping".to_string()])).unwrap();
+        model.add_stock(Stock::new("Market", "0").with_inflows(vec!["shipping".to_string()])).unwrap();
+        model.add_flow(Flow::new("shipping", "0.3 * Factory * (1 + Market / 100)")).unwrap();
+
+        let groups = [vec!["Factory".to_string()], vec!["Market".to_string()]];
+        let partitions = partition_model(&model, &groups).unwrap();
+        assert_eq!(partitions[0].exports, vec!["Factory".to_string(), flow_total("shipping")]);
+        assert_eq!(partitions[1].received.len(), 1);
+        assert!(partitions[1].model.stocks["Market"].inflows.is_empty());
+
+        let config = SimulationConfig { integration_method: IntegrationMethod::RK4, ..SimulationConfig::default() };
+        let results = run_partitioned(&model, &groups, config).await.unwrap();
+        let factory = results.get_variable_series("Factory").unwrap();
+        let market = results.get_variable_series("Market").unwrap();
+        assert!(factory.last().unwrap() < &50.0);
+        for (f, m) in factory.iter().zip(&market) {
+            assert!((f + m - 100.0).abs() < 1e-9, "{} + {}", f, m);
+        }
+    }
+}
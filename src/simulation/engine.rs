@@ -1,35 +1,315 @@
 /// Simulation engine - orchestrates model execution
 
-use crate::model::Model;
-use super::{SimulationState, SimulationConfig, SimulationResults, Integrator};
-use super::integrator::{EulerIntegrator, RK4Integrator, HeunIntegrator, BackwardEulerIntegrator, RK45Integrator};
-use super::IntegrationMethod;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use crate::analysis::StabilityAnalyzer;
+use crate::error::{IoError, ModelError, ParseError, Result, SimulationError};
+use crate::model::{HybridPhase, Model};
+use crate::model::arrays::{element_combinations, element_name};
+use super::checkpoint::Checkpoint;
+use super::{constraints, conveyor, des, events, guard, hybrid, AgentRecorder, ConservationAudit};
+use super::{ArraySimulationState, SimulationState, SimulationConfig, VariableValues, SimulationResults, StepStatistics, Integrator, ResultSink};
+use super::integrator::{evaluate_system, EulerIntegrator, RK4Integrator, HeunIntegrator, BackwardEulerIntegrator, BdfIntegrator, RK45Integrator};
+use super::{IntegrationMethod, Initialization};
+use super::progress::{CancellationToken, Progress, ProgressCallback, ProgressTracker};
+use super::observer::SimulationObserver;
 
 pub struct SimulationEngine {
     model: Model,
     config: SimulationConfig,
     state: SimulationState,
+    statistics: Option<StepStatistics>,
+    progress: Option<ProgressCallback>,
+    cancellation: Option<CancellationToken>,
+    observers: Vec<Box<dyn SimulationObserver>>,
+    /// Threads for parallel evaluation, when `config.threads` asks for more than one
+    pool: Option<Arc<rayon::ThreadPool>>,
+    /// Whether `run_steps` has recorded the initial state of a run in decision intervals
+    interval_run_started: bool,
+    /// Time and stocks at the start of the current step, for the value guard
+    step_start: Option<(f64, VariableValues)>,
+    /// Balance of the conserved groups, when `config.audit_conservation` is set
+    audit: Option<ConservationAudit>,
+    /// Individual agents and their histograms, when the model asks for agent output
+    agent_output: Option<AgentRecorder>,
 }
 
 impl SimulationEngine {
-    pub fn new(model: Model, config: SimulationConfig) -> Result<Self, String> {
-        let state = SimulationState::initialize_from_model(&model)?;
+    /// Compile `model` and set up its initial state
+    pub fn new(mut model: Model, mut config: SimulationConfig) -> Result<Self> {
+        model.compile()?;
+        if let Some(calendar) = &model.time.calendar {
+            calendar.date_at(model.time.start).map_err(ModelError::Invalid)?;
+            calendar.date_at(model.time.stop).map_err(ModelError::Invalid)?;
+        }
+        let mut state = SimulationState::initialize_from_model(&model)?;
+
+        if config.initialization == Initialization::Equilibrium {
+            if !state.conveyors.is_empty() {
+                return Err(ModelError::Invalid("Equilibrium initialization is not supported for models with conveyors or ovens".to_string()).into());
+            }
+            state = StabilityAnalyzer::default()
+                .solve_equilibrium(&model, &state, config.atol.max(1e-9))
+                .map_err(|e| SimulationError::Failed {
+                    time: Some(model.time.start),
+                    message: format!("Equilibrium initialization: {}", e),
+                })?;
+            // Rates at the solved stocks, so the first output row is consistent
+            evaluate_system(&model, &mut state, model.time.start)?;
+        }
+
+        // Selecting an arrayed variable records all of its elements
+        if let Some(variables) = config.output_variables.take() {
+            let mut expanded = Vec::new();
+            for name in variables {
+                match model.expanded_arrays.get(&name) {
+                    Some(dimensions) => expanded.extend(
+                        element_combinations(dimensions, &model.dimensions).map_err(ModelError::Invalid)?
+                            .iter()
+                            .map(|elements| element_name(&name, elements)),
+                    ),
+                    None => expanded.push(name),
+                }
+            }
+            config.output_variables = Some(expanded);
+        }
+
+        for name in config.output_variables.iter().flatten() {
+            let known = name.eq_ignore_ascii_case("time")
+                || model.stocks.contains_key(name)
+                || model.flows.contains_key(name)
+                || model.auxiliaries.contains_key(name);
+            if !known {
+                return Err(ModelError::NotFound { kind: "Output variable", name: name.clone() }.into());
+            }
+        }
+
+        let pool = match config.threads {
+            Some(threads) if threads > 1 => {
+                model.evaluation_strata = Some(Model::compute_evaluation_strata(&model).map_err(ModelError::Invalid)?);
+                let pool = rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .build()
+                    .map_err(|e| SimulationError::Failed {
+                        time: None,
+                        message: format!("Failed to start {} evaluation threads: {}", threads, e),
+                    })?;
+                Some(Arc::new(pool))
+            }
+            _ => None,
+        };
+        let audit = config.audit_conservation.then(|| ConservationAudit::from_model(&model, &state));
+        let agent_output = match model.hybrid.as_ref().and_then(|hybrid| hybrid.agent_output.clone()) {
+            Some(output) => {
+                let mut recorder = AgentRecorder::new(output).map_err(ModelError::Invalid)?;
+                recorder.start(state.time, &state.agents);
+                Some(recorder)
+            }
+            None => None,
+        };
 
         Ok(Self {
             model,
             config,
             state,
+            statistics: None,
+            progress: None,
+            cancellation: None,
+            observers: Vec::new(),
+            pool,
+            interval_run_started: false,
+            step_start: None,
+            audit,
+            agent_output,
         })
     }
 
-    pub fn run(&mut self) -> Result<SimulationResults, String> {
+    /// Register an observer; observers are called in the order they were added
+    pub fn with_observer(mut self, observer: impl SimulationObserver + 'static) -> Self {
+        self.observers.push(Box::new(observer));
+        self
+    }
+
+    /// Call `callback` as the run progresses, about once per percent of the simulated time
+    pub fn with_progress(mut self, callback: impl FnMut(&Progress) + Send + 'static) -> Self {
+        self.progress = Some(Box::new(callback));
+        self
+    }
+
+    /// Stop running, with an error, once `token` is cancelled
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Run to the stop time, keeping every output point in memory
+    pub fn run(&mut self) -> Result<SimulationResults> {
         let mut results = SimulationResults::new();
+        results.calendar = self.model.time.calendar.clone();
+        self.run_into(&mut results)?;
+        Ok(results)
+    }
+
+    /// Run the simulation, passing each output point to `sink`
+    ///
+    /// Only the current state is held by the engine; returns the number of
+    /// points recorded. `sink.finish()` is called after the last point, and
+    /// also when the run is cancelled, so the points up to then are kept.
+    /// When `output_variables` is set, the sink only sees those variables.
+    pub fn run_into(&mut self, sink: &mut dyn ResultSink) -> Result<usize> {
+        let recorded = match self.config.output_variables.clone() {
+            Some(variables) => self.run_with(&mut SelectedVariables::new(sink, variables)),
+            None => self.run_with(sink),
+        };
+        if recorded.is_ok() || self.is_cancelled() {
+            sink.finish().map_err(IoError::Output)?;
+        }
+        Ok(recorded.map_err(|e| e.at(self.state.time))?)
+    }
 
+    /// Advance up to `steps` time steps, stopping early at the stop time,
+    /// passing each output point to `sink`
+    ///
+    /// Runs a model in decision intervals, as in a management flight
+    /// simulator: call repeatedly, changing parameters or stocks between
+    /// calls, until [`is_finished`](Self::is_finished). The first call also
+    /// records the initial state, and the end of each interval is always
+    /// recorded. `sink.finish()` is called once the stop time is reached.
+    /// Returns the number of points recorded by this call.
+    pub fn run_steps(&mut self, steps: usize, sink: &mut dyn ResultSink) -> Result<usize> {
+        if let IntegrationMethod::RK45 = self.config.integration_method {
+            return Err(SimulationError::from("Decision intervals need a fixed-step integration method".to_string()).into());
+        }
+        let until = (self.state.time + steps as f64 * self.model.time.dt).min(self.model.time.stop);
+        let recorded = match self.config.output_variables.clone() {
+            Some(variables) => self.run_interval(&mut SelectedVariables::new(sink, variables), until),
+            None => self.run_interval(sink, until),
+        };
+        if (recorded.is_ok() && self.is_finished()) || self.is_cancelled() {
+            sink.finish().map_err(IoError::Output)?;
+        }
+        Ok(recorded.map_err(|e| e.at(self.state.time))?)
+    }
+
+    /// Whether the run has reached the stop time
+    pub fn is_finished(&self) -> bool {
+        self.state.time >= self.model.time.stop - self.model.time.dt * 1e-6
+    }
+
+    fn run_interval(&mut self, sink: &mut dyn ResultSink, until: f64) -> Result<usize, SimulationError> {
+        let mut recorded = 0;
+        if !self.interval_run_started {
+            for observer in &mut self.observers {
+                observer.on_init(&self.model, &self.state)?;
+            }
+            self.check_values()?;
+            constraints::check(&self.model, &mut self.state)?;
+            sink.record(self.state.time, &self.state)?;
+            recorded += 1;
+            self.interval_run_started = true;
+        }
+        recorded += self.run_fixed_until(sink, until)?;
+        if self.is_finished() {
+            for observer in &mut self.observers {
+                observer.on_finish(&self.state)?;
+            }
+        }
+        Ok(recorded)
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancellation.as_ref().is_some_and(CancellationToken::is_cancelled)
+    }
+
+    /// Stop if cancelled, otherwise pass on any progress report that is due
+    fn report_progress(&mut self, tracker: &mut ProgressTracker, steps: usize) -> Result<(), SimulationError> {
+        if self.is_cancelled() {
+            return Err(SimulationError::Cancelled { time: self.state.time });
+        }
+        if let Some(callback) = &mut self.progress
+            && let Some(progress) = tracker.update(steps, self.state.time)
+        {
+            callback(&progress);
+        }
+        Ok(())
+    }
+
+    fn run_with(&mut self, sink: &mut dyn ResultSink) -> Result<usize, SimulationError> {
+        for observer in &mut self.observers {
+            observer.on_init(&self.model, &self.state)?;
+        }
+        self.check_values()?;
+        constraints::check(&self.model, &mut self.state)?;
+        let recorded = if let IntegrationMethod::RK45 = self.config.integration_method {
+            self.run_adaptive(sink)?
+        } else {
+            self.run_fixed(sink)?
+        };
+        for observer in &mut self.observers {
+            observer.on_finish(&self.state)?;
+        }
+        Ok(recorded)
+    }
+
+    fn notify_step_start(&mut self) -> Result<(), SimulationError> {
+        for observer in &mut self.observers {
+            observer.on_step_start(&self.state)?;
+        }
+        if self.config.max_magnitude.is_some() {
+            self.step_start = Some((self.state.time, self.state.stocks.clone()));
+        }
+        if let Some(audit) = &mut self.audit {
+            audit.step_start(&self.model, &self.state)?;
+        }
+        Ok(())
+    }
+
+    /// Call the observers, then check the values and the model's constraints against the state they leave
+    fn notify_step_end(&mut self) -> Result<(), SimulationError> {
+        for observer in &mut self.observers {
+            observer.on_step_end(&mut self.state)?;
+        }
+        self.check_values()?;
+        constraints::check(&self.model, &mut self.state)?;
+        if let Some(audit) = &mut self.audit {
+            audit.step_end(&self.state);
+        }
+        if let Some(recorder) = &mut self.agent_output {
+            recorder.step_end(self.state.time, &self.state.agents);
+        }
+        Ok(())
+    }
+
+    /// Stop on a NaN, infinite or too large value, tracing it back to the equation it came from
+    fn check_values(&mut self) -> Result<(), SimulationError> {
+        let Some(limit) = self.config.max_magnitude else { return Ok(()) };
+        let start = self.step_start.take();
+        guard::check(&self.model, &self.state, start.as_ref().map(|(time, stocks)| (*time, stocks)), limit)
+    }
+
+    fn run_fixed(&mut self, sink: &mut dyn ResultSink) -> Result<usize, SimulationError> {
         // Record initial state
-        results.add_point(self.state.time, self.state.clone());
+        sink.record(self.state.time, &self.state)?;
+        Ok(1 + self.run_fixed_until(sink, self.model.time.stop)?)
+    }
 
+    /// Step to `until`, recording output times on the way and the point reached
+    fn run_fixed_until(&mut self, sink: &mut dyn ResultSink, until: f64) -> Result<usize, SimulationError> {
+        let mut recorded = 0;
         let dt = self.model.time.dt;
+        let start_time = self.model.time.start;
         let stop_time = self.model.time.stop;
+        let interval = self.output_interval()?;
+        let epsilon = dt * 1e-6;
+        // Output times stay on the model's grid when resuming mid-run
+        let mut output_index = interval.map_or(1, |i| self.outputs_before(i) + 1);
+        let mut next_checkpoint = self.first_checkpoint()?;
+        let mut tracker = ProgressTracker::new(start_time, stop_time);
+        if self.progress.is_some() {
+            tracker.start_clock();
+        }
+        let mut steps = 0;
 
         // Create integrator
         let integrator: Box<dyn Integrator> = match self.config.integration_method {
@@ -38,72 +318,438 @@ impl SimulationEngine {
             IntegrationMethod::RK45 => Box::new(RK45Integrator::default()),
             IntegrationMethod::Heun => Box::new(HeunIntegrator),
             IntegrationMethod::BackwardEuler => Box::new(BackwardEulerIntegrator::default()),
+            IntegrationMethod::Bdf => Box::new(BdfIntegrator::default()),
         };
 
         // Main simulation loop
-        while self.state.time < stop_time {
+        while self.state.time < until - epsilon {
             // Take a step
-            self.state = integrator.step(&self.model, &self.state, dt)?;
+            self.notify_step_start()?;
+            self.advance_hybrid(integrator.as_ref(), dt)?;
 
             // Ensure we don't overshoot
-            if self.state.time > stop_time {
-                self.state.time = stop_time;
-            }
-
-            // Record state based on output interval
-            let should_record = if let Some(interval) = self.config.output_interval {
-                // Check if we've crossed an output interval boundary
-                let current_interval = (self.state.time / interval).floor();
-                let prev_interval = ((self.state.time - dt) / interval).floor();
-                current_interval > prev_interval
-            } else {
-                // Record every step
-                true
+            if self.state.time > until {
+                self.state.time = until;
+            }
+            self.notify_step_end()?;
+
+            // Record on reaching the next output time (SAVEPER), and always at the end
+            let at_stop = self.state.time >= until - epsilon;
+            let should_record = match interval {
+                Some(interval) => {
+                    let next_output = start_time + output_index as f64 * interval;
+                    if self.state.time >= next_output - epsilon {
+                        while start_time + output_index as f64 * interval <= self.state.time + epsilon {
+                            output_index += 1;
+                        }
+                        true
+                    } else {
+                        at_stop
+                    }
+                }
+                None => true,
             };
 
             if should_record {
-                results.add_point(self.state.time, self.state.clone());
+                sink.record(self.state.time, &self.state)?;
+                recorded += 1;
             }
+            self.checkpoint_if_due(&mut next_checkpoint)?;
+            steps += 1;
+            self.report_progress(&mut tracker, steps)?;
         }
 
-        Ok(results)
+        Ok(recorded)
+    }
+
+    /// Number of output times at or before the current time
+    fn outputs_before(&self, interval: f64) -> usize {
+        ((self.state.time - self.model.time.start) / interval + 1e-6).floor().max(0.0) as usize
+    }
+
+    fn first_checkpoint(&self) -> Result<Option<f64>, SimulationError> {
+        match &self.config.checkpoint {
+            Some(checkpoint) if checkpoint.interval <= 0.0 => {
+                Err("Checkpoint interval must be positive".to_string().into())
+            }
+            Some(checkpoint) => Ok(Some(self.state.time + checkpoint.interval)),
+            None => Ok(None),
+        }
+    }
+
+    fn checkpoint_if_due(&self, next_checkpoint: &mut Option<f64>) -> Result<(), SimulationError> {
+        if let (Some(checkpoint), Some(next)) = (&self.config.checkpoint, *next_checkpoint)
+            && self.state.time >= next - self.model.time.dt * 1e-6
+        {
+            self.save_checkpoint(&checkpoint.path)
+                .map_err(|e| SimulationError::Failed { time: Some(self.state.time), message: e.to_string() })?;
+            *next_checkpoint = Some(self.state.time + checkpoint.interval);
+        }
+        Ok(())
+    }
+
+    /// Write the full current state (including delays, RNG state and agents) to `path`
+    pub fn save_checkpoint<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        Checkpoint::new(&self.model, self.state.clone()).write(path).map_err(|e| IoError::Output(e).into())
+    }
+
+    /// Replace the current state with one written by `save_checkpoint`
+    ///
+    /// A following `run` continues from the checkpoint time, recording the
+    /// checkpointed state as its first point.
+    pub fn resume_from_checkpoint<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let checkpoint = Checkpoint::read(path)
+            .map_err(|message| ParseError::from(message).in_file(path))?;
+        checkpoint.check_model(&self.model).map_err(ModelError::Invalid)?;
+        self.state = checkpoint.state;
+        Ok(())
+    }
+
+    /// Effective output spacing: the config override, else the model's save interval
+    fn output_interval(&self) -> Result<Option<f64>, SimulationError> {
+        let interval = self.config.output_interval.or(self.model.time.save_interval);
+        match interval {
+            Some(interval) if interval <= 0.0 => Err("Output interval must be positive".to_string().into()),
+            _ => Ok(interval),
+        }
+    }
+
+    /// Run with the integrator choosing its own step sequence
+    ///
+    /// Results are recorded at `start + k * interval` (or every `dt` when no
+    /// output interval is set), interpolating within accepted steps. Steps
+    /// end at event times, and where a condition event triggers, located by
+    /// bisecting the interpolated step.
+    fn run_adaptive(&mut self, sink: &mut dyn ResultSink) -> Result<usize, SimulationError> {
+        if !self.state.conveyors.is_empty() {
+            return Err("Conveyor and oven stocks need a fixed-step integration method".to_string().into());
+        }
+        if !self.state.des.is_empty() {
+            return Err("Discrete-event elements need a fixed-step integration method".to_string().into());
+        }
+        sink.record(self.state.time, &self.state)?;
+        let mut recorded = 1;
+        let mut last_output = self.state.time;
+
+        let start_time = self.model.time.start;
+        let stop_time = self.model.time.stop;
+        let interval = self.output_interval()?.unwrap_or(self.model.time.dt);
+        let epsilon = interval * 1e-9;
+
+        let integrator = RK45Integrator::new(self.config.rtol, self.config.atol)
+            .with_step_limits(1e-10, stop_time - start_time);
+
+        let mut statistics = StepStatistics::default();
+        let mut h = self.model.time.dt;
+        let mut output_index = self.outputs_before(interval) + 1;
+        let mut next_output = start_time + output_index as f64 * interval;
+        let mut next_checkpoint = self.first_checkpoint()?;
+        let mut tracker = ProgressTracker::new(start_time, stop_time);
+        if self.progress.is_some() {
+            tracker.start_clock();
+        }
+
+        while self.state.time < stop_time - epsilon {
+            h = h.min(stop_time - self.state.time);
+            if let Some(at) = events::next_time(&self.model, &self.state, stop_time) {
+                h = h.min(at - self.state.time);
+            }
+            self.notify_step_start()?;
+            let (model, state) = (&self.model, &self.state);
+            let step = self.on_pool(|| integrator.adaptive_step(model, state, h))?;
+            statistics.accepted += 1;
+            statistics.rejected += step.rejected;
+
+            // End the step where a condition event triggers, using the dense output
+            let mut next = None;
+            if events::condition_triggered(&self.model, &self.state, &step.state)? {
+                let t0 = self.state.time;
+                let h = step.state.time - t0;
+                next = events::locate_crossing(&self.model, &self.state, h, self.model.time.dt * 1e-6, |s| {
+                    Ok::<_, SimulationError>(interpolate(&self.state, &step, t0 + s))
+                })?;
+            }
+            let t_end = next.as_ref().map_or(step.state.time, |state| state.time);
+
+            while next_output <= t_end + epsilon && next_output <= stop_time + epsilon {
+                if (next_output - t_end).abs() <= epsilon {
+                    sink.record(next_output, next.as_ref().unwrap_or(&step.state))?;
+                } else {
+                    sink.record(next_output, &interpolate(&self.state, &step, next_output))?;
+                }
+                recorded += 1;
+                last_output = next_output;
+                output_index += 1;
+                next_output = start_time + output_index as f64 * interval;
+            }
+
+            h = step.next_step;
+            let t0 = self.state.time;
+            self.state = next.unwrap_or(step.state);
+            // Delays only advance over accepted steps
+            self.state.delays.advance(self.state.time);
+            events::fire_due(&self.model, &mut self.state)?;
+            // Steps are only known once taken, so agents follow the SD model
+            self.run_agent_phases(self.state.time - t0)?;
+            self.notify_step_end()?;
+            self.checkpoint_if_due(&mut next_checkpoint)?;
+            self.report_progress(&mut tracker, statistics.accepted)?;
+        }
+
+        // Always end on the stop time, even when it is not an output time
+        if last_output < stop_time - epsilon {
+            sink.record(self.state.time, &self.state)?;
+            recorded += 1;
+        }
+
+        self.statistics = Some(statistics);
+        Ok(recorded)
+    }
+
+    /// Accepted/rejected step counts from the last adaptive run
+    pub fn step_statistics(&self) -> Option<StepStatistics> {
+        self.statistics
     }
 
-    pub fn step(&mut self) -> Result<(), String> {
+    /// Advance one `dt` with the configured integration method
+    pub fn step(&mut self) -> Result<()> {
+        Ok(self.try_step().map_err(|e| e.at(self.state.time))?)
+    }
+
+    fn try_step(&mut self) -> Result<(), SimulationError> {
         let integrator: Box<dyn Integrator> = match self.config.integration_method {
             IntegrationMethod::Euler => Box::new(EulerIntegrator),
             IntegrationMethod::RK4 => Box::new(RK4Integrator),
             IntegrationMethod::RK45 => Box::new(RK45Integrator::default()),
             IntegrationMethod::Heun => Box::new(HeunIntegrator),
             IntegrationMethod::BackwardEuler => Box::new(BackwardEulerIntegrator::default()),
+            IntegrationMethod::Bdf => Box::new(BdfIntegrator::default()),
         };
 
-        self.state = integrator.step(&self.model, &self.state, self.model.time.dt)?;
+        self.notify_step_start()?;
+        self.advance_hybrid(integrator.as_ref(), self.model.time.dt)?;
+        self.notify_step_end()
+    }
+
+    /// Step `dt` forward through the phases of a hybrid model's pipeline, or
+    /// only the SD model when there are no agents
+    fn advance_hybrid(&mut self, integrator: &dyn Integrator, dt: f64) -> Result<(), SimulationError> {
+        let Some(config) = &self.model.hybrid else { return self.advance(integrator, dt) };
+        let mut aggregated = HashMap::new();
+        for phase in config.pipeline.clone() {
+            match phase {
+                HybridPhase::Sd => self.advance(integrator, dt)?,
+                phase => hybrid::run_phase(&self.model, &mut self.state, phase, dt, &mut aggregated)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Run the agent phases of a hybrid model's pipeline in order, after a step of `dt`
+    fn run_agent_phases(&mut self, dt: f64) -> Result<(), SimulationError> {
+        let Some(config) = &self.model.hybrid else { return Ok(()) };
+        let mut aggregated = HashMap::new();
+        for phase in config.pipeline.iter().filter(|phase| **phase != HybridPhase::Sd) {
+            hybrid::run_phase(&self.model, &mut self.state, *phase, dt, &mut aggregated)?;
+        }
+        Ok(())
+    }
+
+    /// Step `dt` forward, stopping partway through to fire any event that triggers inside the step
+    ///
+    /// Time events are stepped to exactly; a condition becoming true is
+    /// located by bisecting the step. Conveyors move in whole steps, so with
+    /// them events fire at the end of the step instead.
+    fn advance(&mut self, integrator: &dyn Integrator, dt: f64) -> Result<(), SimulationError> {
+        if self.model.events.is_empty() {
+            self.state = self.integrate(integrator, dt)?;
+            return Ok(());
+        }
+
+        let end = self.state.time + dt;
+        let epsilon = dt * 1e-6;
+        let exact = self.state.conveyors.is_empty();
+        while self.state.time < end - epsilon {
+            let mut h = end - self.state.time;
+            if exact && let Some(at) = events::next_time(&self.model, &self.state, end) {
+                h = at - self.state.time;
+            }
+
+            let mut next = self.integrate(integrator, h)?;
+            if exact && events::condition_triggered(&self.model, &self.state, &next)? {
+                let located = events::locate_crossing(&self.model, &self.state, h, epsilon, |s| {
+                    self.integrate(integrator, s)
+                })?;
+                next = located.unwrap_or(next);
+            }
+
+            self.state = next;
+            events::fire_due(&self.model, &mut self.state)?;
+        }
+        self.state.time = end;
         Ok(())
     }
 
+    /// State after a step of `dt`; exponential delays advance over the step
+    /// here, so stage evaluations inside the integrator never move them
+    fn integrate(&self, integrator: &dyn Integrator, dt: f64) -> Result<SimulationState, SimulationError> {
+        let (model, state) = (&self.model, &self.state);
+        let mut next = self.on_pool(|| integrator.step(model, state, dt))?;
+        next.delays.advance(next.time);
+        let next = conveyor::advance(&self.model, &self.state, next)?;
+        Ok(des::advance(&self.model, &self.state, next)?)
+    }
+
+    /// Run `f` on the engine's thread pool, if it has one, so parallel evaluation uses its threads
+    fn on_pool<T: Send>(&self, f: impl FnOnce() -> T + Send) -> T {
+        match &self.pool {
+            Some(pool) => pool.install(f),
+            None => f(),
+        }
+    }
+
     pub fn current_state(&self) -> &SimulationState {
         &self.state
     }
 
+    /// Balance of the model's conserved groups so far, when the run audits them
+    pub fn conservation_audit(&self) -> Option<&ConservationAudit> {
+        self.audit.as_ref()
+    }
+
+    /// Agent snapshots and histograms so far, when the model asks for agent output
+    pub fn agent_output(&self) -> Option<&AgentRecorder> {
+        self.agent_output.as_ref()
+    }
+
+    /// Current state with arrayed variables gathered into arrays
+    pub fn array_state(&self) -> Result<ArraySimulationState> {
+        Ok(ArraySimulationState::from_state(&self.model, &self.state).map_err(ModelError::Invalid)?)
+    }
+
     pub fn current_time(&self) -> f64 {
         self.state.time
     }
 
-    pub fn set_parameter(&mut self, name: &str, value: f64) -> Result<(), String> {
+    /// Overwrite a stock's current value
+    pub fn set_stock(&mut self, name: &str, value: f64) -> Result<()> {
+        match self.state.stocks.get_mut(name) {
+            Some(stock) => {
+                *stock = value;
+                Ok(())
+            }
+            None => Err(ModelError::NotFound { kind: "Stock", name: name.to_string() }.into()),
+        }
+    }
+
+    pub fn set_parameter(&mut self, name: &str, value: f64) -> Result<()> {
         if let Some(param) = self.model.parameters.get_mut(name) {
             param.value = value;
             Ok(())
         } else {
-            Err(format!("Parameter '{}' not found", name))
+            Err(ModelError::NotFound { kind: "Parameter", name: name.to_string() }.into())
+        }
+    }
+
+    /// Apply a decision between intervals: set a parameter, or else a stock's current value
+    pub fn set_decision(&mut self, name: &str, value: f64) -> Result<()> {
+        if self.model.parameters.contains_key(name) {
+            return self.set_parameter(name, value);
         }
+        match self.set_stock(name, value) {
+            Ok(()) => Ok(()),
+            Err(_) => Err(ModelError::NotFound { kind: "Parameter or stock", name: name.to_string() }.into()),
+        }
+    }
+
+    /// Apply several decisions at once; none is applied unless every name is
+    /// a parameter or a stock
+    pub fn set_decisions(&mut self, changes: &[(&str, f64)]) -> Result<()> {
+        if let Some((name, _)) = changes.iter()
+            .find(|(name, _)| !self.model.parameters.contains_key(*name) && !self.state.stocks.contains_key(name))
+        {
+            return Err(ModelError::NotFound { kind: "Parameter or stock", name: name.to_string() }.into());
+        }
+        for (name, value) in changes {
+            self.set_decision(name, *value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Dense output inside an accepted step
+///
+/// Stocks use cubic Hermite interpolation on the step's end-point derivatives;
+/// auxiliaries and flows are interpolated linearly.
+fn interpolate(start: &SimulationState, step: &super::AdaptiveStep, time: f64) -> SimulationState {
+    let end = &step.state;
+    let h = end.time - start.time;
+    let s = (time - start.time) / h;
+    let mut state = start.clone();
+    state.time = time;
+
+    let h00 = 2.0 * s.powi(3) - 3.0 * s.powi(2) + 1.0;
+    let h10 = s.powi(3) - 2.0 * s.powi(2) + s;
+    let h01 = -2.0 * s.powi(3) + 3.0 * s.powi(2);
+    let h11 = s.powi(3) - s.powi(2);
+
+    for (name, value) in state.stocks.iter_mut() {
+        let y1 = end.stocks.get(name).copied().unwrap_or(*value);
+        let f0 = step.start_derivatives.get(name).copied().unwrap_or(0.0);
+        let f1 = step.end_derivatives.get(name).copied().unwrap_or(0.0);
+        *value = h00 * *value + h10 * h * f0 + h01 * y1 + h11 * h * f1;
+    }
+
+    let lerp = |from: &VariableValues, to: &VariableValues| {
+        let mut values = to.clone();
+        for (name, y1) in values.iter_mut() {
+            let y0 = from.get(name).copied().unwrap_or(*y1);
+            *y1 = y0 + s * (*y1 - y0);
+        }
+        values
+    };
+    state.auxiliaries = lerp(&start.auxiliaries, &end.auxiliaries);
+    state.flows = lerp(&start.flows, &end.flows);
+
+    state
+}
+
+/// Forwards only the selected variables to the wrapped sink
+struct SelectedVariables<'a> {
+    inner: &'a mut dyn ResultSink,
+    variables: Vec<String>,
+    selected: SimulationState,
+}
+
+impl<'a> SelectedVariables<'a> {
+    fn new(inner: &'a mut dyn ResultSink, variables: Vec<String>) -> Self {
+        Self { inner, variables, selected: SimulationState::new() }
+    }
+}
+
+impl ResultSink for SelectedVariables<'_> {
+    fn record(&mut self, time: f64, state: &SimulationState) -> Result<(), String> {
+        self.selected.time = state.time;
+        for name in &self.variables {
+            for (source, target) in [
+                (&state.stocks, &mut self.selected.stocks),
+                (&state.flows, &mut self.selected.flows),
+                (&state.auxiliaries, &mut self.selected.auxiliaries),
+            ] {
+                if let Some(value) = source.get(name) {
+                    target.insert(name.clone(), *value);
+                }
+            }
+        }
+        self.inner.record(time, &self.selected)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::model::{Model, Stock, Flow, Parameter};
+    use crate::model::{Model, Stock, Flow, Auxiliary, Parameter};
 
     #[test]
     fn test_simulation_engine_simple() {
@@ -127,4 +773,368 @@ mod tests {
         assert_eq!(results.times[0], 0.0);
         assert!(results.times.last().unwrap() <= &10.0);
     }
+
+    #[test]
+    fn test_equilibrium_initialization() {
+        let mut model = Model::new("Inventory");
+        model.time.stop = 5.0;
+        model.time.dt = 1.0;
+        model.add_stock(Stock::new("Inventory", "10").with_inflows(vec!["restock".to_string()])
+            .with_outflows(vec!["sales".to_string()])).unwrap();
+        model.add_parameter(Parameter::new("target", 80.0)).unwrap();
+        model.add_flow(Flow::new("restock", "(target - Inventory) / 4")).unwrap();
+        model.add_flow(Flow::new("sales", "5")).unwrap();
+
+        let config = SimulationConfig { initialization: Initialization::Equilibrium, ..SimulationConfig::default() };
+        let results = SimulationEngine::new(model, config).unwrap().run().unwrap();
+
+        // Restocking balances sales 20 units below target, and the run stays there
+        for level in results.get_variable_series("Inventory").unwrap() {
+            assert!((level - 60.0).abs() < 1e-6, "{}", level);
+        }
+        let restock = results.get_variable_series("restock").unwrap();
+        assert!((restock[0] - 5.0).abs() < 1e-6, "{}", restock[0]);
+    }
+
+    #[test]
+    fn test_save_interval_downsamples_output() {
+        let mut model = Model::new("Growth");
+        model.time.stop = 10.0;
+        model.time.dt = 0.01;
+        model.time.save_interval = Some(1.0);
+        model.add_stock(Stock::new("Population", "100")).unwrap();
+        model.add_parameter(Parameter::new("growth_rate", 0.1)).unwrap();
+        model.add_flow(Flow::new("growth", "Population * growth_rate")).unwrap();
+        model.stocks.get_mut("Population").unwrap().inflows.push("growth".to_string());
+
+        let results = SimulationEngine::new(model.clone(), SimulationConfig::default()).unwrap().run().unwrap();
+        assert_eq!(results.times.len(), 11);
+        for (k, &time) in results.times.iter().enumerate() {
+            assert!((time - k as f64).abs() < 1e-6, "t={}", time);
+        }
+
+        // An explicit output interval overrides the model's, and the stop time is always kept
+        let config = SimulationConfig { output_interval: Some(3.0), ..SimulationConfig::default() };
+        let results = SimulationEngine::new(model, config).unwrap().run().unwrap();
+        let times: Vec<f64> = results.times.iter().map(|t| t.round()).collect();
+        assert_eq!(times, vec![0.0, 3.0, 6.0, 9.0, 10.0]);
+    }
+
+    #[test]
+    fn test_decision_intervals() {
+        let mut model = Model::new("Growth");
+        model.time.stop = 10.0;
+        model.time.dt = 1.0;
+        model.add_stock(Stock::new("Population", "100").with_inflows(vec!["growth".to_string()])).unwrap();
+        model.add_parameter(Parameter::new("growth_rate", 0.1)).unwrap();
+        model.add_flow(Flow::new("growth", "Population * growth_rate")).unwrap();
+
+        let mut engine = SimulationEngine::new(model, SimulationConfig::default()).unwrap();
+        let mut results = SimulationResults::new();
+        assert_eq!(engine.run_steps(4, &mut results).unwrap(), 5);
+        assert_eq!(engine.current_time(), 4.0);
+        assert!(!engine.is_finished());
+
+        // The decision made at t=4 holds from then on
+        engine.set_parameter("growth_rate", 0.0).unwrap();
+        assert_eq!(engine.run_steps(4, &mut results).unwrap(), 4);
+        assert_eq!(engine.run_steps(4, &mut results).unwrap(), 2);
+        assert!(engine.is_finished());
+
+        assert_eq!(results.times, (0..=10).map(f64::from).collect::<Vec<_>>());
+        let population = results.get_variable_series("Population").unwrap();
+        let at_decision = 100.0 * 1.1f64.powi(4);
+        assert!((population[4] - at_decision).abs() < 1e-9);
+        assert!((population[10] - at_decision).abs() < 1e-9, "{:?}", population);
+    }
+
+    #[test]
+    fn test_progress_and_cancellation() {
+        use std::sync::{Arc, Mutex};
+
+        let mut model = Model::new("Growth");
+        model.time.stop = 10.0;
+        model.time.dt = 0.01;
+        model.add_stock(Stock::new("Population", "100")).unwrap();
+        model.add_parameter(Parameter::new("growth_rate", 0.1)).unwrap();
+        model.add_flow(Flow::new("growth", "Population * growth_rate")).unwrap();
+        model.stocks.get_mut("Population").unwrap().inflows.push("growth".to_string());
+
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let seen = reports.clone();
+        SimulationEngine::new(model.clone(), SimulationConfig::default()).unwrap()
+            .with_progress(move |progress| seen.lock().unwrap().push(*progress))
+            .run()
+            .unwrap();
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 101);
+        assert!(reports.windows(2).all(|w| w[1].fraction > w[0].fraction && w[1].steps > w[0].steps));
+        let last = reports.last().unwrap();
+        assert_eq!((last.steps, last.fraction, last.eta), (1000, 1.0, Some(std::time::Duration::ZERO)));
+
+        // Cancelling halfway stops the run and keeps what was recorded
+        let token = CancellationToken::new();
+        let canceller = token.clone();
+        let mut engine = SimulationEngine::new(model, SimulationConfig::default()).unwrap()
+            .with_cancellation(token)
+            .with_progress(move |progress| if progress.steps >= 500 { canceller.cancel() });
+        let mut results = SimulationResults::new();
+        let err = engine.run_into(&mut results).unwrap_err();
+        // The token is checked after the next step
+        assert!(matches!(err, crate::Error::Simulation(SimulationError::Cancelled { time }) if (time - 5.0).abs() < 0.1), "{}", err);
+        assert_eq!(results.times.len(), 502);
+    }
+
+    #[test]
+    fn test_set_decisions_all_or_nothing() {
+        let mut model = Model::new("Growth");
+        model.add_stock(Stock::new("Population", "100")).unwrap();
+        model.add_parameter(Parameter::new("growth_rate", 0.1)).unwrap();
+        let mut engine = SimulationEngine::new(model, SimulationConfig::default()).unwrap();
+
+        let err = engine.set_decisions(&[("Population", 50.0), ("nope", 1.0)]).unwrap_err();
+        assert!(err.to_string().contains("nope"), "{}", err);
+        assert_eq!(engine.current_state().stocks["Population"], 100.0);
+
+        engine.set_decisions(&[("Population", 50.0), ("growth_rate", 0.2)]).unwrap();
+        assert_eq!(engine.current_state().stocks["Population"], 50.0);
+        assert_eq!(engine.model.parameters["growth_rate"].value, 0.2);
+    }
+
+    #[test]
+    fn test_observers() {
+        use std::sync::{Arc, Mutex};
+        use crate::simulation::SimulationObserver;
+
+        /// Logs every callback
+        struct Log(Arc<Mutex<Vec<String>>>);
+        impl SimulationObserver for Log {
+            fn on_init(&mut self, model: &Model, _state: &SimulationState) -> Result<(), String> {
+                self.0.lock().unwrap().push(format!("init {}", model.metadata.name));
+                Ok(())
+            }
+            fn on_step_start(&mut self, state: &SimulationState) -> Result<(), String> {
+                self.0.lock().unwrap().push(format!("start {}", state.time));
+                Ok(())
+            }
+            fn on_step_end(&mut self, state: &mut SimulationState) -> Result<(), String> {
+                self.0.lock().unwrap().push(format!("end {}", state.time));
+                Ok(())
+            }
+            fn on_finish(&mut self, state: &SimulationState) -> Result<(), String> {
+                self.0.lock().unwrap().push(format!("finish {}", state.time));
+                Ok(())
+            }
+        }
+
+        /// Resets the stock to an observed value at t=1
+        struct Assimilate;
+        impl SimulationObserver for Assimilate {
+            fn on_step_end(&mut self, state: &mut SimulationState) -> Result<(), String> {
+                if state.time == 1.0 {
+                    state.stocks.insert("Population", 200.0);
+                }
+                Ok(())
+            }
+        }
+
+        let mut model = Model::new("Growth");
+        model.time.stop = 2.0;
+        model.time.dt = 1.0;
+        model.add_stock(Stock::new("Population", "100")).unwrap();
+        model.add_parameter(Parameter::new("growth_rate", 0.1)).unwrap();
+        model.add_flow(Flow::new("growth", "Population * growth_rate")).unwrap();
+        model.stocks.get_mut("Population").unwrap().inflows.push("growth".to_string());
+
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let results = SimulationEngine::new(model, SimulationConfig::default()).unwrap()
+            .with_observer(Assimilate)
+            .with_observer(Log(log.clone()))
+            .run()
+            .unwrap();
+
+        assert_eq!(*log.lock().unwrap(), vec!["init Growth", "start 0", "end 1", "start 1", "end 2", "finish 2"]);
+        // The nudged value is recorded and grows from there
+        assert_eq!(results.get_variable_series("Population").unwrap(), vec![100.0, 200.0, 220.0]);
+    }
+
+    #[test]
+    fn test_seeded_runs_are_reproducible() {
+        let mut model = Model::new("Noise");
+        model.time.stop = 5.0;
+        model.time.dt = 0.5;
+        model.time.seed = Some(7);
+        model.add_stock(Stock::new("Level", "RANDOM() * 10")).unwrap();
+        model.add_flow(Flow::new("shock", "NORMAL(0, 1)")).unwrap();
+        model.stocks.get_mut("Level").unwrap().inflows.push("shock".to_string());
+
+        let run = |model: Model| {
+            let results = SimulationEngine::new(model, SimulationConfig::default()).unwrap().run().unwrap();
+            results.get_variable_series("Level").unwrap()
+        };
+        let first = run(model.clone());
+        assert_eq!(first, run(model.clone()));
+
+        model.time.seed = Some(8);
+        assert_ne!(first, run(model));
+    }
+
+    #[test]
+    fn test_parallel_evaluation_matches_serial() {
+        let mut model = Model::new("Regions");
+        model.time.stop = 5.0;
+        model.time.dt = 0.25;
+        model.time.seed = Some(3);
+        model.add_parameter(Parameter::new("rate", 0.05)).unwrap();
+        for i in 0..40 {
+            model.add_stock(Stock::new(&format!("s{}", i), "100").with_outflows(vec![format!("out{}", i)])).unwrap();
+            model.add_auxiliary(Auxiliary::new(&format!("pressure{}", i), &format!("s{} / (1 + s{})", i, (i + 1) % 40))).unwrap();
+            let noise = if i % 10 == 0 { format!(" + NORMAL(0, 1) + DELAY1(pressure{}, 2)", i) } else { String::new() };
+            model.add_auxiliary(Auxiliary::new(&format!("loss{}", i), &format!("pressure{} * rate * s{}{}", i, i, noise))).unwrap();
+            model.add_flow(Flow::new(&format!("out{}", i), &format!("MAX(loss{}, 0) + RANDOM() * 0.1", i))).unwrap();
+        }
+
+        for method in [IntegrationMethod::Euler, IntegrationMethod::RK4, IntegrationMethod::RK45] {
+            let run = |threads| {
+                let config = SimulationConfig { integration_method: method, threads, ..Default::default() };
+                SimulationEngine::new(model.clone(), config).unwrap().run().unwrap()
+            };
+            let (serial, parallel) = (run(None), run(Some(4)));
+            assert_eq!(serial.times, parallel.times);
+            assert_eq!(serial.to_map(), parallel.to_map());
+        }
+    }
+
+    #[test]
+    fn test_output_variables_filter() {
+        let mut model = Model::new("Test");
+        model.time.stop = 5.0;
+        model.time.dt = 1.0;
+        model.add_stock(Stock::new("Population", "100")).unwrap();
+        model.add_parameter(Parameter::new("growth_rate", 0.1)).unwrap();
+        model.add_flow(Flow::new("growth", "Population * growth_rate")).unwrap();
+        model.add_auxiliary(Auxiliary::new("doubled", "Population * 2")).unwrap();
+        model.stocks.get_mut("Population").unwrap().inflows.push("growth".to_string());
+
+        let config = SimulationConfig {
+            output_variables: Some(vec!["Population".to_string(), "TIME".to_string()]),
+            ..SimulationConfig::default()
+        };
+        let results = SimulationEngine::new(model.clone(), config).unwrap().run().unwrap();
+        let full = SimulationEngine::new(model.clone(), SimulationConfig::default()).unwrap().run().unwrap();
+
+        assert_eq!(results.times, full.times);
+        assert_eq!(results.get_variable_series("Population"), full.get_variable_series("Population"));
+        assert!(results.flows.is_empty() && results.auxiliaries.is_empty());
+
+        let config = SimulationConfig {
+            output_variables: Some(vec!["growth_rate".to_string()]),
+            ..SimulationConfig::default()
+        };
+        assert!(SimulationEngine::new(model, config).is_err());
+    }
+
+    #[test]
+    fn test_delay_fixed_matches_shifted_input() {
+        // Euler records auxiliaries evaluated at the start of each step, one row
+        // behind RK4, so the first delayed input shows up one row later
+        for (method, first_output) in [(IntegrationMethod::Euler, 9), (IntegrationMethod::RK4, 8)] {
+            let mut model = Model::new("Delay");
+            model.time.start = 0.0;
+            model.time.stop = 10.0;
+            model.time.dt = 0.25;
+            model.add_auxiliary(Auxiliary::new("signal", "SIN(TIME)")).unwrap();
+            model.add_auxiliary(Auxiliary::new("delayed", "DELAY FIXED(signal, 2, -5)")).unwrap();
+
+            let config = SimulationConfig { integration_method: method, ..SimulationConfig::default() };
+            let results = SimulationEngine::new(model, config).unwrap().run().unwrap();
+            let signal = results.get_variable_series("signal").unwrap();
+            let delayed = results.get_variable_series("delayed").unwrap();
+
+            // 2 time units = 8 steps of 0.25
+            let shift = 8;
+            for i in 1..delayed.len() {
+                if i < first_output {
+                    assert_eq!(delayed[i], -5.0, "{:?} step {}", method, i);
+                } else {
+                    assert!((delayed[i] - signal[i - shift]).abs() < 1e-9, "{:?} step {}", method, i);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_rk45_adaptive_run_hits_output_times() {
+        let mut model = Model::new("Growth");
+        model.time.start = 0.0;
+        model.time.stop = 10.0;
+        model.time.dt = 0.1;
+
+        model.add_stock(Stock::new("Population", "100")).unwrap();
+        model.add_parameter(Parameter::new("growth_rate", 0.1)).unwrap();
+        model.add_flow(Flow::new("growth", "Population * growth_rate")).unwrap();
+        model.stocks.get_mut("Population").unwrap().inflows.push("growth".to_string());
+
+        let config = SimulationConfig {
+            integration_method: IntegrationMethod::RK45,
+            output_interval: Some(0.5),
+            ..SimulationConfig::default()
+        };
+        let mut engine = SimulationEngine::new(model, config).unwrap();
+        let results = engine.run().unwrap();
+
+        assert_eq!(results.times.len(), 21);
+        let population = results.series("Population").unwrap();
+        for (k, (&time, &value)) in results.times.iter().zip(population).enumerate() {
+            assert!((time - k as f64 * 0.5).abs() < 1e-9);
+            let exact = 100.0 * (0.1 * time).exp();
+            assert!((value - exact).abs() / exact < 1e-4, "t={}: {} vs {}", time, value, exact);
+        }
+
+        // Smooth growth lets the step grow well beyond the output interval
+        let stats = engine.step_statistics().unwrap();
+        assert!(stats.accepted > 0 && stats.accepted < 20, "{:?}", stats);
+    }
+
+    #[test]
+    fn test_delays_follow_analytic_response() {
+        let methods = [
+            IntegrationMethod::Euler,
+            IntegrationMethod::RK4,
+            IntegrationMethod::RK45,
+            IntegrationMethod::Heun,
+            IntegrationMethod::BackwardEuler,
+            IntegrationMethod::Bdf,
+        ];
+        for method in methods {
+            for stop in [2.0, 50.0] {
+                let mut model = Model::new("Delays");
+                model.time.start = 0.0;
+                model.time.stop = stop;
+                model.time.dt = 0.25;
+                model.add_stock(Stock::new("Level", "0")).unwrap();
+                model.add_auxiliary(Auxiliary::new("smoothed", "SMOOTH(10, 2, 0)")).unwrap();
+                model.add_auxiliary(Auxiliary::new("delayed", "DELAY3(10, 2, 0)")).unwrap();
+                model.add_auxiliary(Auxiliary::new("cascade", "DELAYN(10, 6, 20, 0)")).unwrap();
+
+                let config = SimulationConfig { integration_method: method, ..SimulationConfig::default() };
+                let mut engine = SimulationEngine::new(model, config).unwrap();
+                let results = engine.run().unwrap();
+
+                // Delays advance only over accepted steps, exactly, so every
+                // method ends on the analytic response to a step of 10
+                let delays = &engine.current_state().delays.exponential_delays;
+                let x = stop / 2.0;
+                let smoothed = 10.0 * (1.0 - (-x).exp());
+                let y = 3.0 * x;
+                let delayed = 10.0 * (1.0 - (-y).exp() * (1.0 + y + y * y / 2.0));
+                assert!((delays["smoothed#0"].value - smoothed).abs() < 1e-9, "{:?} to {}", method, stop);
+                assert!((delays["delayed#0"].value - delayed).abs() < 1e-9, "{:?} to {}", method, stop);
+                for name in ["smoothed", "delayed", "cascade"] {
+                    let series = results.series(name).unwrap();
+                    assert!(series.iter().all(|value| (0.0..=10.0).contains(value)), "{:?} {}", method, name);
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,291 @@
+/// Runtime support for discrete events
+///
+/// Tracks which events have fired, the last value of each condition trigger
+/// and the parameter values events have set, all as part of the simulation
+/// state so checkpoints resume with the same interventions in place.
+
+use std::collections::{HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+use crate::model::{Event, EventAction, EventTrigger, Expression, Model};
+use crate::model::arrays::{element_combinations, element_name};
+use crate::model::expression::EvaluationContext;
+use super::SimulationState;
+use super::integrator::evaluate_system;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EventManager {
+    /// Parameter values set by events, overriding the model's
+    pub parameters: HashMap<String, f64>,
+    /// Events that will not fire again
+    fired: HashSet<String>,
+    /// Value of each condition trigger when last checked
+    conditions: HashMap<String, bool>,
+}
+
+impl EventManager {
+    /// Check that every event action targets a parameter or an ordinary stock
+    pub fn from_model(model: &Model) -> Result<Self, String> {
+        for event in &model.events {
+            for action in &event.actions {
+                for target in targets(model, action.target())? {
+                    let valid = match action {
+                        EventAction::Set { .. } => model.parameters.contains_key(&target) || is_reservoir(model, &target),
+                        EventAction::Add { .. } => is_reservoir(model, &target),
+                    };
+                    if !valid {
+                        let expected = match action {
+                            EventAction::Set { .. } => "a parameter or stock",
+                            EventAction::Add { .. } => "a stock",
+                        };
+                        return Err(format!(
+                            "Event '{}' changes '{}', which is not {}",
+                            event.name, target, expected
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(Self::default())
+    }
+
+    pub fn has_fired(&self, event: &str) -> bool {
+        self.fired.contains(event)
+    }
+}
+
+fn is_reservoir(model: &Model, name: &str) -> bool {
+    model.stocks.get(name).is_some_and(|stock| stock.kind.is_reservoir())
+}
+
+/// Names an action applies to; an arrayed variable stands for all of its elements
+fn targets(model: &Model, name: &str) -> Result<Vec<String>, String> {
+    match model.expanded_arrays.get(name) {
+        Some(dimensions) => Ok(element_combinations(dimensions, &model.dimensions)?
+            .iter()
+            .map(|elements| element_name(name, elements))
+            .collect()),
+        None => Ok(vec![name.to_string()]),
+    }
+}
+
+/// Earliest time-triggered event strictly between the current time and `end`
+pub(crate) fn next_time(model: &Model, state: &SimulationState, end: f64) -> Option<f64> {
+    let epsilon = model.time.dt * 1e-6;
+    model.events.iter()
+        .filter(|event| !state.events.has_fired(&event.name))
+        .filter_map(|event| match event.trigger {
+            EventTrigger::At(at) => Some(at),
+            EventTrigger::When(_) => None,
+        })
+        .filter(|&at| at > state.time + epsilon && at < end - epsilon)
+        .min_by(f64::total_cmp)
+}
+
+/// Whether a condition that was false before the step holds in `state`
+pub(crate) fn condition_triggered(model: &Model, before: &SimulationState, state: &SimulationState) -> Result<bool, String> {
+    let mut current = None;
+    for event in &model.events {
+        let EventTrigger::When(condition) = &event.trigger else { continue };
+        if before.events.has_fired(&event.name) || before.events.conditions.get(&event.name) == Some(&true) {
+            continue;
+        }
+        let current = match &mut current {
+            Some(current) => current,
+            None => current.insert(evaluated(model, state)?),
+        };
+        if holds(model, current, condition)? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Locate where a condition trigger first becomes true within a step of `h`
+///
+/// `state_at(s)` gives the state `s` into the step, and the condition must
+/// hold at `s = h`. Bisects until the crossing is bracketed within
+/// `tolerance`, returning the state just after it.
+pub(crate) fn locate_crossing<F, E>(
+    model: &Model,
+    before: &SimulationState,
+    h: f64,
+    tolerance: f64,
+    mut state_at: F,
+) -> Result<Option<SimulationState>, E>
+where
+    F: FnMut(f64) -> Result<SimulationState, E>,
+    E: From<String>,
+{
+    let (mut low, mut high) = (0.0, h);
+    let mut crossed = None;
+    while high - low > tolerance {
+        let mid = (low + high) / 2.0;
+        let trial = state_at(mid)?;
+        if condition_triggered(model, before, &trial)? {
+            high = mid;
+            crossed = Some(trial);
+        } else {
+            low = mid;
+        }
+    }
+    Ok(crossed)
+}
+
+/// Fire every event due at the current time
+pub(crate) fn fire_due(model: &Model, state: &mut SimulationState) -> Result<(), String> {
+    if model.events.is_empty() {
+        return Ok(());
+    }
+
+    // Conditions and action values see auxiliaries for the current stocks
+    let mut current = evaluated(model, state)?;
+    let epsilon = model.time.dt * 1e-6;
+
+    let mut due: Vec<&Event> = Vec::new();
+    for event in &model.events {
+        if state.events.has_fired(&event.name) {
+            continue;
+        }
+        let triggered = match &event.trigger {
+            EventTrigger::At(at) => *at <= state.time + epsilon,
+            EventTrigger::When(condition) => {
+                let now = holds(model, &mut current, condition)?;
+                let before = state.events.conditions.insert(event.name.clone(), now);
+                now && before != Some(true)
+            }
+        };
+        if triggered {
+            due.push(event);
+        }
+    }
+
+    for &event in &due {
+        for action in &event.actions {
+            let (expr, add) = match action {
+                EventAction::Set { value, .. } => (value, false),
+                EventAction::Add { amount, .. } => (amount, true),
+            };
+            let time = current.time;
+            let value = expr.evaluate(&mut EvaluationContext::new(model, &mut current, time))
+                .map_err(|e| format!("Event '{}': {}", event.name, e))?;
+
+            for target in targets(model, action.target())? {
+                if add {
+                    let current = state.stocks.get(&target).copied().unwrap_or(0.0);
+                    state.stocks.insert(target, current + value);
+                } else if model.parameters.contains_key(&target) {
+                    state.events.parameters.insert(target, value);
+                } else {
+                    state.stocks.insert(target, value);
+                }
+            }
+        }
+
+        if !(event.repeat && matches!(event.trigger, EventTrigger::When(_))) {
+            state.events.fired.insert(event.name.clone());
+        }
+    }
+
+    // A repeating event whose action made its condition false can fire again
+    if due.iter().any(|event| event.repeat) {
+        let mut current = evaluated(model, state)?;
+        for event in due.iter().filter(|event| event.repeat) {
+            if let EventTrigger::When(condition) = &event.trigger {
+                let now = holds(model, &mut current, condition)?;
+                state.events.conditions.insert(event.name.clone(), now);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn evaluated(model: &Model, state: &SimulationState) -> Result<SimulationState, String> {
+    let mut current = state.clone();
+    evaluate_system(model, &mut current, state.time)?;
+    Ok(current)
+}
+
+fn holds(model: &Model, state: &mut SimulationState, condition: &Expression) -> Result<bool, String> {
+    let time = state.time;
+    Ok(condition.evaluate(&mut EvaluationContext::new(model, state, time))? != 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::{Event, EventAction, Flow, Model, Parameter, Stock};
+    use crate::simulation::{IntegrationMethod, SimulationConfig, SimulationEngine};
+
+    fn taxed_model() -> Model {
+        let mut model = Model::new("Policy");
+        model.time.stop = 10.0;
+        model.time.dt = 1.0;
+        model.add_stock(Stock::new("Revenue", "0").with_inflows(vec!["collection".to_string()])).unwrap();
+        model.add_parameter(Parameter::new("tax_rate", 0.1)).unwrap();
+        model.add_flow(Flow::new("collection", "100 * tax_rate")).unwrap();
+        model
+    }
+
+    #[test]
+    fn test_time_event_lands_inside_step() {
+        let mut model = taxed_model();
+        model.add_event(Event::at("raise_tax", 4.5).with_action(EventAction::set("tax_rate", "0.3").unwrap())).unwrap();
+
+        let results = SimulationEngine::new(model, SimulationConfig::default()).unwrap().run().unwrap();
+        let revenue = results.get_variable_series("Revenue").unwrap();
+
+        // Half of the step from 4 to 5 runs at the old rate and half at the new one
+        assert!((revenue[4] - 40.0).abs() < 1e-9);
+        assert!((revenue[5] - 60.0).abs() < 1e-9, "{:?}", revenue);
+        assert!((revenue[10] - 210.0).abs() < 1e-9);
+        assert_eq!(results.times, (0..=10).map(f64::from).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_condition_event_fires_at_crossing() {
+        let mut model = taxed_model();
+        model.add_event(Event::when("windfall", "Revenue >= 25").unwrap()
+            .with_action(EventAction::add("Revenue", "-20").unwrap())
+            .with_repeat(true))
+            .unwrap();
+
+        let results = SimulationEngine::new(model, SimulationConfig::default()).unwrap().run().unwrap();
+        let revenue = results.get_variable_series("Revenue").unwrap();
+
+        // Revenue grows by 10 per unit time; each time it reaches 25 it drops to 5
+        assert!((revenue[3] - 10.0).abs() < 1e-4, "{:?}", revenue);
+        assert!((revenue[5] - 10.0).abs() < 1e-4, "{:?}", revenue);
+        assert!((revenue[10] - 20.0).abs() < 1e-4, "{:?}", revenue);
+    }
+
+    #[test]
+    fn test_condition_crossing_independent_of_dt() {
+        for method in [IntegrationMethod::Euler, IntegrationMethod::RK4, IntegrationMethod::RK45] {
+            for dt in [1.0, 0.25] {
+                let mut model = Model::new("Spillway");
+                model.time.stop = 11.0;
+                model.time.dt = dt;
+                model.add_stock(Stock::new("Reservoir", "20").with_outflows(vec!["release".to_string()])).unwrap();
+                model.add_parameter(Parameter::new("spillway", 0.0)).unwrap();
+                model.add_flow(Flow::new("release", "1 + spillway * 5")).unwrap();
+                model.add_event(Event::when("open_spillway", "Reservoir < 10.5").unwrap()
+                    .with_action(EventAction::set("spillway", "1").unwrap()))
+                    .unwrap();
+
+                let config = SimulationConfig { integration_method: method, ..SimulationConfig::default() };
+                let results = SimulationEngine::new(model, config).unwrap().run().unwrap();
+                let level = *results.get_variable_series("Reservoir").unwrap().last().unwrap();
+
+                // The spillway opens at t = 9.5, wherever the step boundaries fall
+                assert!((level - 1.5).abs() < 1e-4, "{:?} dt={}: {}", method, dt, level);
+            }
+        }
+    }
+
+    #[test]
+    fn test_event_rejects_unknown_target() {
+        let mut model = taxed_model();
+        model.add_event(Event::at("bad", 1.0).with_action(EventAction::set("collection", "1").unwrap())).unwrap();
+        assert!(SimulationEngine::new(model, SimulationConfig::default()).is_err());
+    }
+}
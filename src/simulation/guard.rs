@@ -0,0 +1,163 @@
+/// Guard against NaN, infinite and explosive values
+///
+/// A value that is not a number or has overflowed spreads through every
+/// equation that uses it, so a run that carries on only writes garbage. Once
+/// any stock, flow or auxiliary goes bad the run stops, and the state is
+/// re-evaluated to find the first equation that produced a bad value from
+/// good inputs; the error names it with its inputs and the chain of
+/// variables that fed them.
+
+use std::collections::{HashSet, VecDeque};
+use crate::analysis::DependencyGraph;
+use crate::error::{NonFiniteValue, SimulationError};
+use crate::model::{Expression, Model};
+use super::{SimulationState, VariableValues};
+use super::integrator::evaluate_system;
+
+/// Check `state` after a step that started at `start` (time and stocks), or
+/// the initial state when `start` is `None`
+pub(crate) fn check(
+    model: &Model,
+    state: &SimulationState,
+    start: Option<(f64, &VariableValues)>,
+    limit: f64,
+) -> Result<(), SimulationError> {
+    let bad = |value: f64| !value.is_finite() || value.abs() > limit;
+    let stock_bad = state.stocks.iter().any(|(_, v)| bad(*v));
+    if !stock_bad && !state.flows.iter().chain(state.auxiliaries.iter()).any(|(_, v)| bad(*v)) {
+        return Ok(());
+    }
+
+    // When a stock went bad, look at the flows that took it there
+    let mut probe = state.clone();
+    if stock_bad && let Some((time, stocks)) = start {
+        probe.time = time;
+        probe.stocks = stocks.clone();
+    }
+    let time = probe.time;
+    evaluate_system(model, &mut probe, time)?;
+
+    let order = match &model.evaluation_order {
+        Some(order) => order.clone(),
+        None => Model::compute_evaluation_order(model)?,
+    };
+    for name in &order {
+        let value = probe.flows.get(name).or_else(|| probe.auxiliaries.get(name)).copied();
+        if let Some(value) = value && bad(value) {
+            return Err(diagnose(model, &probe, name, value, time));
+        }
+    }
+
+    // Every equation is fine: a stock overflowed while integrating, or started out bad
+    let mut stocks: Vec<(&String, f64)> = probe.stocks.iter().chain(state.stocks.iter())
+        .map(|(name, value)| (name, *value))
+        .filter(|(_, value)| bad(*value))
+        .collect();
+    stocks.sort_by(|a, b| a.0.cmp(b.0));
+    match stocks.first() {
+        Some(&(name, value)) => Err(diagnose(model, &probe, name, value, state.time)),
+        None => Ok(()),
+    }
+}
+
+/// Error naming `variable`'s equation, the values of its inputs in `state`
+/// and everything upstream of them
+fn diagnose(model: &Model, state: &SimulationState, variable: &str, value: f64, time: f64) -> SimulationError {
+    let (kind, equation, inputs) = if let Some(aux) = model.auxiliaries.get(variable) {
+        ("auxiliary", aux.equation.to_string(), dependencies(&aux.equation))
+    } else if let Some(flow) = model.flows.get(variable) {
+        ("flow", flow.equation.to_string(), dependencies(&flow.equation))
+    } else {
+        let stock = &model.stocks[variable];
+        let mut net = stock.inflows.join(" + ");
+        for outflow in &stock.outflows {
+            net.push_str(&format!(" - {}", outflow));
+        }
+        let net = if net.is_empty() { "0".to_string() } else { net.trim_start_matches(' ').to_string() };
+        let flows = stock.inflows.iter().chain(&stock.outflows).cloned().collect();
+        ("stock", format!("d/dt = {}", net), flows)
+    };
+    let value_of = |name: &str| model.get_variable(name, state).unwrap_or(f64::NAN);
+
+    // Breadth first from the inputs, back to stocks and parameters
+    let mut chain = Vec::new();
+    let mut seen: HashSet<String> = inputs.iter().cloned().collect();
+    seen.insert(variable.to_string());
+    let mut queue: VecDeque<String> = inputs.iter().cloned().collect();
+    while let Some(name) = queue.pop_front() {
+        let equation = model.auxiliaries.get(&name).map(|a| &a.equation)
+            .or_else(|| model.flows.get(&name).map(|f| &f.equation));
+        let Some(equation) = equation else { continue };
+        chain.push(format!("{} = {} from {}", name, value_of(&name), equation));
+        for dependency in dependencies(equation) {
+            if seen.insert(dependency.clone()) {
+                queue.push_back(dependency);
+            }
+        }
+    }
+
+    SimulationError::NonFinite(Box::new(NonFiniteValue {
+        kind,
+        variable: variable.to_string(),
+        equation,
+        time,
+        value,
+        inputs: inputs.iter().map(|name| (name.clone(), value_of(name))).collect(),
+        chain,
+    }))
+}
+
+/// Variables an equation reads, sorted
+fn dependencies(equation: &Expression) -> Vec<String> {
+    let mut names: Vec<String> = DependencyGraph::extract_dependencies(equation).into_iter()
+        .filter(|name| !name.eq_ignore_ascii_case("time"))
+        .collect();
+    names.sort();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::{Error, SimulationError};
+    use crate::model::{Auxiliary, Flow, Model, Parameter, Stock};
+    use crate::simulation::{SimulationConfig, SimulationEngine};
+
+    #[test]
+    fn test_non_finite_value_is_traced() {
+        // remaining reaches zero at t=4, where SQRT of a negative number is NaN
+        let mut model = Model::new("Drain");
+        model.time.stop = 10.0;
+        model.time.dt = 1.0;
+        model.add_stock(Stock::new("Tank", "4").with_outflows(vec!["outflow".to_string()])).unwrap();
+        model.add_parameter(Parameter::new("rate", 1.0)).unwrap();
+        model.add_auxiliary(Auxiliary::new("remaining", "Tank - 0.5")).unwrap();
+        model.add_auxiliary(Auxiliary::new("pressure", "SQRT(remaining)")).unwrap();
+        model.add_flow(Flow::new("outflow", "rate + 0 * pressure")).unwrap();
+
+        let error = SimulationEngine::new(model.clone(), SimulationConfig::default()).unwrap().run().unwrap_err();
+        match &error {
+            Error::Simulation(SimulationError::NonFinite(value)) => {
+                assert_eq!((value.kind, value.variable.as_str(), value.time), ("auxiliary", "pressure", 4.0));
+                assert_eq!(value.inputs, vec![("remaining".to_string(), -0.5)]);
+                assert_eq!(value.chain, vec!["remaining = -0.5 from (Tank - 0.5)".to_string()]);
+            }
+            other => panic!("expected a non-finite value error, got {}", other),
+        }
+        let message = error.to_string();
+        assert!(message.starts_with("auxiliary 'pressure' became NaN at t=4 in SQRT(remaining)\n  inputs: remaining = -0.5"), "{}", message);
+
+        // Growth that overflows is stopped at the limit
+        let mut model = Model::new("Explosion");
+        model.time.stop = 100.0;
+        model.time.dt = 1.0;
+        model.add_stock(Stock::new("X", "1").with_inflows(vec!["growth".to_string()])).unwrap();
+        model.add_auxiliary(Auxiliary::new("gain", "X * 9")).unwrap();
+        model.add_flow(Flow::new("growth", "gain")).unwrap();
+        let config = SimulationConfig { max_magnitude: Some(1e6), ..SimulationConfig::default() };
+        let error = SimulationEngine::new(model.clone(), config).unwrap().run().unwrap_err();
+        assert!(error.to_string().starts_with("auxiliary 'gain' became 9000000 at t=6 in (X * 9)\n  inputs: X = 1000000"), "{}", error);
+
+        let config = SimulationConfig { max_magnitude: None, ..SimulationConfig::default() };
+        assert!(SimulationEngine::new(model, config).unwrap().run().is_ok());
+    }
+}
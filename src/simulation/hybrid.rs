@@ -0,0 +1,447 @@
+/// Agent phases of hybrid SD/ABM steps
+///
+/// Runs the phases of a model's [`HybridConfig::pipeline`] other than the SD
+/// integration, which the engine does itself. Agent types apply their rules
+/// in order of name, each in its own [`UpdateOrder`]; agents are visited in
+/// order of id, or in an order drawn with the run's seed, so random numbers
+/// drawn by their rules are reproducible. An agent whose type has a state machine
+/// takes at most one transition from its state after its rules.
+///
+/// [`HybridConfig::pipeline`]: crate::model::HybridConfig::pipeline
+
+use std::collections::HashMap;
+use crate::error::ModelError;
+use crate::model::{Expression, HybridPhase, Model};
+use crate::model::expression::EvaluationContext;
+use super::{AgentManager, AgentRule, AgentSDBridge, AgentType, DelayManager, DesManager, EventManager, SimulationState, StateMachine, StochasticManager, UpdateOrder, VariableValues};
+
+/// Create the model's initial agents and pass their aggregates to the SD variables
+pub(crate) fn initialize(model: &Model, state: &mut SimulationState) -> Result<(), ModelError> {
+    let Some(hybrid) = &model.hybrid else { return Ok(()) };
+    if hybrid.pipeline.iter().filter(|phase| **phase == HybridPhase::Sd).count() != 1 {
+        return Err(ModelError::Invalid("Hybrid pipeline must integrate the SD model ('sd') exactly once".to_string()));
+    }
+
+    for population in &hybrid.agents {
+        let name = population.agent_type.name.clone();
+        parse_agent_type(&population.agent_type, &mut HashMap::new())
+            .map_err(|e| ModelError::Invalid(format!("Agent type '{}': {}", name, e)))?;
+        state.agents.register_type(population.agent_type.clone());
+        state.agents.create_agents_sampled(&name, population.count, &mut state.stochastic)?;
+    }
+    let mut coupled: Vec<&String> = hybrid.couplings.agent_couplings.keys().collect();
+    coupled.sort();
+    for type_name in coupled {
+        if !state.agents.agent_types.contains_key(type_name) {
+            return Err(ModelError::NotFound { kind: "Agent type", name: type_name.clone() });
+        }
+        for mapping in &hybrid.couplings.agent_couplings[type_name].attributes_to_sd {
+            let target = &mapping.sd_variable;
+            if !model.parameters.contains_key(target) && !model.stocks.contains_key(target) {
+                return Err(ModelError::Invalid(format!(
+                    "Agent type '{}' writes '{}', which is not a parameter or stock", type_name, target
+                )));
+            }
+        }
+    }
+
+    let mut aggregated = HashMap::new();
+    for phase in hybrid.pipeline.iter().filter(|phase| matches!(phase, HybridPhase::Aggregate | HybridPhase::Inject)) {
+        run_phase(model, state, *phase, 0.0, &mut aggregated)?;
+    }
+    Ok(())
+}
+
+/// Run one agent phase of a step of length `dt`; `aggregated` carries the
+/// values collected by `aggregate` to `inject`
+pub(crate) fn run_phase(
+    model: &Model,
+    state: &mut SimulationState,
+    phase: HybridPhase,
+    dt: f64,
+    aggregated: &mut HashMap<String, f64>,
+) -> Result<(), String> {
+    let Some(hybrid) = &model.hybrid else { return Ok(()) };
+    let bridge = AgentSDBridge::new(hybrid.couplings.clone());
+    match phase {
+        // Integrated by the engine
+        HybridPhase::Sd => {}
+        HybridPhase::Agents => {
+            let values = sd_values(model, state);
+            bridge.update_agents_from_sd(&mut state.agents, &values);
+            for (type_name, count) in bridge.agents_to_create(&values, dt) {
+                state.agents.create_agents_sampled(&type_name, count, &mut state.stochastic)?;
+            }
+            bridge.process_agent_destruction(&mut state.agents, &values, dt)?;
+            apply_rules(model, state, dt)?;
+        }
+        HybridPhase::Aggregate => *aggregated = bridge.calculate_sd_from_agents(&state.agents),
+        HybridPhase::Inject => {
+            for (name, &value) in aggregated.iter() {
+                if model.parameters.contains_key(name) {
+                    state.events.parameters.insert(name.clone(), value);
+                } else {
+                    state.stocks.insert(name, value);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Every stock, flow, auxiliary and parameter by name
+fn sd_values(model: &Model, state: &SimulationState) -> HashMap<String, f64> {
+    let mut values: HashMap<String, f64> = model.parameters.iter()
+        .map(|(name, parameter)| (name.clone(), state.events.parameters.get(name).copied().unwrap_or_else(|| parameter.value_at(state.time))))
+        .collect();
+    for (name, value) in state.stocks.iter().chain(state.flows.iter()).chain(state.auxiliaries.iter()) {
+        values.insert(name.clone(), *value);
+    }
+    values
+}
+
+/// The parts of a state agent rules are evaluated against
+struct Scope<'a> {
+    model: &'a Model,
+    stocks: &'a VariableValues,
+    flows: &'a VariableValues,
+    auxiliaries: &'a VariableValues,
+    agents: &'a AgentManager,
+    des: &'a DesManager,
+    events: &'a EventManager,
+    delays: &'a mut DelayManager,
+    stochastic: &'a mut StochasticManager,
+    time: f64,
+}
+
+impl<'a> Scope<'a> {
+    fn new(model: &'a Model, state: &'a mut SimulationState) -> Self {
+        Scope {
+            model,
+            stocks: &state.stocks,
+            flows: &state.flows,
+            auxiliaries: &state.auxiliaries,
+            agents: &state.agents,
+            des: &state.des,
+            events: &state.events,
+            delays: &mut state.delays,
+            stochastic: &mut state.stochastic,
+            time: state.time,
+        }
+    }
+
+    fn evaluate(&mut self, expression: &Expression, attributes: &HashMap<String, f64>) -> Result<f64, String> {
+        let mut context = EvaluationContext {
+            model: self.model,
+            stocks: self.stocks,
+            flows: self.flows,
+            auxiliaries: self.auxiliaries,
+            agents: self.agents,
+            des: self.des,
+            events: self.events,
+            delays: self.delays,
+            stochastic: self.stochastic,
+            time: self.time,
+            attributes: Some(attributes),
+            call_site: None,
+        };
+        expression.evaluate(&mut context)
+    }
+}
+
+/// Apply every active agent's rules and state transitions, removing the
+/// agents that die
+fn apply_rules(model: &Model, state: &mut SimulationState, dt: f64) -> Result<(), String> {
+    let mut type_names: Vec<String> = state.agents.agent_types.keys().cloned().collect();
+    type_names.sort();
+    for type_name in type_names {
+        let agent_type = state.agents.agent_types[&type_name].clone();
+        let Some(population) = state.agents.get_population(&type_name) else { continue };
+        if agent_type.rules.is_empty() && agent_type.state_machine.is_none() {
+            continue;
+        }
+        let mut expressions = HashMap::new();
+        parse_agent_type(&agent_type, &mut expressions)
+            .map_err(|e| format!("Agent type '{}': {}", type_name, e))?;
+
+        let mut ids: Vec<usize> = population.all_agents().filter(|agent| agent.active).map(|agent| agent.id).collect();
+        ids.sort_unstable();
+        let (ids, interval) = match agent_type.update_order {
+            UpdateOrder::Synchronous | UpdateOrder::Sequential => (ids, dt),
+            UpdateOrder::Random => {
+                // Fisher-Yates, drawing from the run's generator
+                for i in (1..ids.len()).rev() {
+                    let j = ((state.stochastic.random() * (i + 1) as f64) as usize).min(i);
+                    ids.swap(i, j);
+                }
+                (ids, dt)
+            }
+            UpdateOrder::Poisson => {
+                // Each agent's clock ticks at exponential intervals; every tick
+                // within the step is an activation, taken in order of time
+                let rate = agent_type.activation_rate;
+                let mut ticks = Vec::new();
+                for id in ids {
+                    let mut t = -(1.0 - state.stochastic.random()).ln() / rate;
+                    while t < dt {
+                        ticks.push((t, id));
+                        t -= (1.0 - state.stochastic.random()).ln() / rate;
+                    }
+                }
+                ticks.sort_by(|a, b| a.0.total_cmp(&b.0));
+                (ticks.into_iter().map(|(_, id)| id).collect(), 1.0 / rate)
+            }
+        };
+
+        let mut updates = Vec::new();
+        for id in ids {
+            let Some(agent) = state.agents.get_population(&type_name).and_then(|p| p.get_agent(id)) else { continue };
+            let mut attributes = agent.attributes.clone();
+            let mut agent_state = agent.state.clone();
+            let mut scope = Scope::new(model, state);
+            let alive = apply(&agent_type.rules, &expressions, &mut attributes, &mut scope)
+                .and_then(|alive| {
+                    if alive && let Some(machine) = &agent_type.state_machine {
+                        transition(machine, &expressions, &mut agent_state, &attributes, &mut scope, interval)?;
+                    }
+                    Ok(alive)
+                })
+                .map_err(|e| format!("Agent {} of type '{}': {}", id, type_name, e))?;
+            let agent = Update { id, attributes, state: agent_state, alive };
+            if agent_type.update_order == UpdateOrder::Synchronous {
+                updates.push(agent);
+            } else {
+                agent.apply(state, &type_name);
+            }
+        }
+        for agent in updates {
+            agent.apply(state, &type_name);
+        }
+    }
+    Ok(())
+}
+
+/// New attributes and state of one agent
+struct Update {
+    id: usize,
+    attributes: HashMap<String, f64>,
+    state: Option<String>,
+    alive: bool,
+}
+
+impl Update {
+    fn apply(self, state: &mut SimulationState, type_name: &str) {
+        let Some(population) = state.agents.get_population_mut(type_name) else { return };
+        if !self.alive {
+            population.remove_agent(self.id);
+        } else if let Some(agent) = population.get_agent_mut(self.id) {
+            agent.attributes = self.attributes;
+            agent.state = self.state;
+        }
+    }
+}
+
+/// Take the first transition out of `current` that fires, drawing one random
+/// number per transition checked
+fn transition(
+    machine: &StateMachine,
+    expressions: &HashMap<&str, Expression>,
+    current: &mut Option<String>,
+    attributes: &HashMap<String, f64>,
+    scope: &mut Scope,
+    dt: f64,
+) -> Result<(), String> {
+    for transition in machine.transitions.iter().filter(|t| current.as_deref() == Some(t.from.as_str())) {
+        let value = scope.evaluate(&expressions[transition.chance.expression()], attributes)?;
+        if scope.stochastic.random() < transition.chance.probability(value, dt) {
+            *current = Some(transition.to.clone());
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Check an agent type's state machine and parse its rule and transition
+/// expressions
+fn parse_agent_type<'a>(agent_type: &'a AgentType, expressions: &mut HashMap<&'a str, Expression>) -> Result<(), String> {
+    if agent_type.update_order == UpdateOrder::Poisson && !(agent_type.activation_rate > 0.0 && agent_type.activation_rate.is_finite()) {
+        return Err(format!("Activation rate must be positive, got {}", agent_type.activation_rate));
+    }
+    parse_rules(&agent_type.rules, expressions)?;
+    if let Some(machine) = &agent_type.state_machine {
+        machine.validate()?;
+        for transition in &machine.transitions {
+            let source = transition.chance.expression();
+            if !expressions.contains_key(source) {
+                expressions.insert(source, Expression::parse(source)?);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn parse_rules<'a>(rules: &'a [AgentRule], expressions: &mut HashMap<&'a str, Expression>) -> Result<(), String> {
+    for rule in rules {
+        let sources = match rule {
+            AgentRule::SetAttribute { expression, .. } => vec![expression],
+            AgentRule::Conditional { condition, then_rules, else_rules } => {
+                parse_rules(then_rules, expressions)?;
+                parse_rules(else_rules, expressions)?;
+                vec![condition]
+            }
+            AgentRule::Die => Vec::new(),
+        };
+        for source in sources {
+            if !expressions.contains_key(source.as_str()) {
+                expressions.insert(source, Expression::parse(source)?);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Apply `rules` to one agent's attributes in order; false once the agent dies
+fn apply(
+    rules: &[AgentRule],
+    expressions: &HashMap<&str, Expression>,
+    attributes: &mut HashMap<String, f64>,
+    scope: &mut Scope,
+) -> Result<bool, String> {
+    for rule in rules {
+        match rule {
+            AgentRule::SetAttribute { attribute, expression } => {
+                let value = scope.evaluate(&expressions[expression.as_str()], attributes)?;
+                attributes.insert(attribute.clone(), value);
+            }
+            AgentRule::Conditional { condition, then_rules, else_rules } => {
+                let branch = if scope.evaluate(&expressions[condition.as_str()], attributes)? != 0.0 {
+                    then_rules
+                } else {
+                    else_rules
+                };
+                if !apply(branch, expressions, attributes, scope)? {
+                    return Ok(false);
+                }
+            }
+            AgentRule::Die => return Ok(false),
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::validation::validate_model;
+    use crate::model::{Auxiliary, HybridConfig, Model, Parameter, Stock, Flow};
+    use crate::simulation::{AgentCoupling, AgentRule, AgentType, SimulationConfig, SimulationEngine, StateMachine, TransitionChance, UpdateOrder};
+    use crate::simulation::agent_sd_bridge::{AggregationType, AttributeMapping};
+
+    #[test]
+    fn test_hybrid_step() {
+        // Each agent saves a share of the SD income every step; their total
+        // wealth drives spending out of the economy's money stock
+        let mut model = Model::new("Savers");
+        model.time.stop = 5.0;
+        model.time.dt = 1.0;
+        model.add_stock(Stock::new("Money", "1000").with_outflows(vec!["spending".to_string()])).unwrap();
+        model.add_parameter(Parameter::new("income", 10.0)).unwrap();
+        model.add_parameter(Parameter::new("total_wealth", 0.0)).unwrap();
+        model.add_flow(Flow::new("spending", "total_wealth * 0.1")).unwrap();
+
+        let mut saver = AgentType::new("Saver".to_string());
+        saver.add_attribute("wealth".to_string(), 5.0);
+        saver.add_rule(AgentRule::SetAttribute { attribute: "wealth".to_string(), expression: "wealth + income * 0.5".to_string() });
+        saver.add_rule(AgentRule::Conditional {
+            condition: "wealth > 22".to_string(),
+            then_rules: vec![AgentRule::Die],
+            else_rules: Vec::new(),
+        });
+        let mut coupling = AgentCoupling::new();
+        coupling.attributes_to_sd.push(AttributeMapping {
+            attribute_name: "wealth".to_string(),
+            sd_variable: "total_wealth".to_string(),
+            aggregation: AggregationType::Sum,
+        });
+        let mut hybrid = HybridConfig::new().with_agents(saver, 4);
+        hybrid.couplings.add_coupling("Saver".to_string(), coupling);
+        model.hybrid = Some(hybrid);
+
+        let mut engine = SimulationEngine::new(model, SimulationConfig::default()).unwrap();
+        // The initial aggregate reaches the SD model before the first step
+        assert_eq!(engine.current_state().events.parameters["total_wealth"], 20.0);
+        let results = engine.run().unwrap();
+
+        // Wealth grows 5 -> 10 -> 15 -> 20 -> 25 by t=4, when every saver dies
+        let agents = &engine.current_state().agents;
+        assert_eq!(agents.count_agents("Saver"), 0);
+        // Spending follows the injected total: 2, 4, 6, 8, then 0
+        let money = results.series("Money").unwrap();
+        assert_eq!(money, &[1000.0, 998.0, 994.0, 988.0, 980.0, 980.0]);
+    }
+
+    #[test]
+    fn test_agent_state_machine() {
+        let mut model = Model::new("Agent SIR");
+        model.time.stop = 30.0;
+        model.time.dt = 1.0;
+        model.time.seed = Some(11);
+        model.add_parameter(Parameter::new("beta", 0.6)).unwrap();
+        model.add_auxiliary(Auxiliary::new("infected", "AGENT_COUNT(\"Infected\")")).unwrap();
+        model.add_auxiliary(Auxiliary::new("prevalence", "infected / AGENT_COUNT()")).unwrap();
+
+        let mut machine = StateMachine::new(vec!["Susceptible".to_string(), "Infected".to_string(), "Recovered".to_string()])
+            .with_initial("Susceptible", 0.9)
+            .with_initial("Infected", 0.1);
+        machine.add_transition("Susceptible", "Infected", TransitionChance::Rate("beta * prevalence".to_string()));
+        machine.add_transition("Infected", "Recovered", TransitionChance::Probability("0.2".to_string()));
+        let mut person = AgentType::new("Person".to_string());
+        person.state_machine = Some(machine);
+        model.hybrid = Some(HybridConfig::new().with_agents(person, 200));
+        assert!(validate_model(&model).is_valid(), "{:?}", validate_model(&model).errors);
+
+        let mut engine = SimulationEngine::new(model, SimulationConfig::default()).unwrap();
+        let infected_at_start = engine.current_state().agents.count_in_state("Infected");
+        assert!(infected_at_start > 0 && infected_at_start < 200);
+        engine.run().unwrap();
+
+        // Agents only move between states, and the epidemic spreads then burns out
+        let agents = &engine.current_state().agents;
+        let counts: Vec<usize> = ["Susceptible", "Infected", "Recovered"].iter()
+            .map(|state| agents.count_in_state(state))
+            .collect();
+        assert_eq!(counts.iter().sum::<usize>(), 200);
+        assert!(counts[2] > infected_at_start, "{:?}", counts);
+        assert!(counts[1] < infected_at_start, "{:?}", counts);
+        assert_eq!(agents.get_population("Person").unwrap().count_in_state("Recovered"), counts[2]);
+    }
+
+    #[test]
+    fn test_poisson_activation() {
+        // Agents count their own activations at 2 per unit time over 5 time units
+        let run = |seed| {
+            let mut model = Model::new("Clock");
+            model.time.stop = 5.0;
+            model.time.dt = 0.5;
+            model.time.seed = Some(seed);
+            let mut ticker = AgentType::new("Ticker".to_string());
+            ticker.add_attribute("activations".to_string(), 0.0);
+            ticker.add_rule(AgentRule::SetAttribute { attribute: "activations".to_string(), expression: "activations + 1".to_string() });
+            ticker.update_order = UpdateOrder::Poisson;
+            ticker.activation_rate = 2.0;
+            model.hybrid = Some(HybridConfig::new().with_agents(ticker, 200));
+            let mut engine = SimulationEngine::new(model, SimulationConfig::default()).unwrap();
+            engine.run().unwrap();
+            let population = engine.current_state().agents.get_population("Ticker").unwrap();
+            let mut counts: Vec<(usize, f64)> = population.all_agents().map(|a| (a.id, a.get("activations").unwrap())).collect();
+            counts.sort_by_key(|(id, _)| *id);
+            counts
+        };
+
+        let counts = run(5);
+        let mean = counts.iter().map(|(_, n)| n).sum::<f64>() / counts.len() as f64;
+        assert!((mean - 10.0).abs() < 1.0, "{}", mean);
+        assert!(counts.iter().any(|(_, n)| *n < 7.0) && counts.iter().any(|(_, n)| *n > 13.0));
+        assert_eq!(counts, run(5));
+        assert_ne!(counts, run(6));
+    }
+}
@@ -1,283 +1,221 @@
 /// Integration methods for numerical simulation
-
-use std::collections::HashMap;
+///
+/// Stock rates and stage values are dense vectors laid out like the state's
+/// stocks (see [`VariableValues`]). A stage is evaluated from the step's
+/// state and the stage's stock values into an [`EvaluationScratch`], so the
+/// state itself is cloned once per step rather than once per stage.
+
+use std::borrow::Cow;
+use crate::error::SimulationError;
 use crate::model::Model;
 use crate::model::expression::EvaluationContext;
-use super::SimulationState;
+use nalgebra::{DMatrix, DVector};
+use rayon::prelude::*;
+use super::{DelayManager, EvaluationScratch, SimulationState, StochasticManager, VariableValues};
 
-pub trait Integrator {
-    fn step(&self, model: &Model, state: &SimulationState, dt: f64) -> Result<SimulationState, String>;
+pub trait Integrator: Send + Sync {
+    fn step(&self, model: &Model, state: &SimulationState, dt: f64) -> Result<SimulationState, SimulationError>;
 }
 
-/// Euler (forward) integration method
-pub struct EulerIntegrator;
-
-impl Integrator for EulerIntegrator {
-    fn step(&self, model: &Model, state: &SimulationState, dt: f64) -> Result<SimulationState, String> {
-        let mut new_state = state.clone();
-        new_state.time += dt;
+/// Evaluate every auxiliary and flow exactly once, in dependency order, storing the results in `state`
+///
+/// Uses the order computed by [`Model::compile`] when available, otherwise derives it on the fly.
+pub fn evaluate_system(model: &Model, state: &mut SimulationState, time: f64) -> Result<(), SimulationError> {
+    let mut scratch = EvaluationScratch::take(state);
+    let result = evaluate_into(model, state, &state.stocks, &mut scratch, time);
+    scratch.restore(state);
+    result
+}
 
-        // 1. Evaluate auxiliaries (in dependency order - use fixed-point iteration)
-        // Since we don't have dependency sorting, evaluate multiple passes until stable
-        let mut new_auxiliaries = HashMap::new();
-        const MAX_PASSES: usize = 20;  // Prevent infinite loops (complex models need more passes)
-
-        for pass in 0..MAX_PASSES {
-            let mut changed = false;
-            let mut any_errors = false;
-
-            for (name, aux) in &model.auxiliaries {
-                let mut temp_state = new_state.clone();
-                let mut context_with_aux = EvaluationContext::new(model, &mut temp_state, state.time);
-
-                match aux.equation.evaluate(&mut context_with_aux) {
-                    Ok(value) => {
-                        // Check if value changed
-                        if let Some(&old_value) = new_auxiliaries.get(name) {
-                            let diff: f64 = value - old_value;
-                            if diff.abs() > 1e-10 {
-                                changed = true;
-                            }
-                        } else {
-                            changed = true; // New value added
-                        }
-                        new_auxiliaries.insert(name.clone(), value);
-
-                        // Merge back state changes
-                        new_state.delays = temp_state.delays;
-                        new_state.stochastic = temp_state.stochastic;
-                        new_state.agents = temp_state.agents;
-                    }
-                    Err(e) => {
-                        // On first few passes, errors are expected (missing dependencies)
-                        // On later passes, if we still have errors, fail
-                        if pass >= 5 {
-                            return Err(format!("Error evaluating auxiliary '{}' (pass {}): {}", name, pass + 1, e));
-                        }
-                        any_errors = true;
-                    }
-                }
+/// Evaluate every auxiliary and flow exactly once into `scratch`, with
+/// `stocks` in place of `state`'s
+///
+/// When the model has [`Model::evaluation_strata`], each stratum's
+/// independent equations are evaluated in parallel on the current rayon
+/// thread pool; the results are the same as evaluating in order.
+pub fn evaluate_into(
+    model: &Model,
+    state: &SimulationState,
+    stocks: &VariableValues,
+    scratch: &mut EvaluationScratch,
+    time: f64,
+) -> Result<(), SimulationError> {
+    if let Some(strata) = &model.evaluation_strata {
+        for stratum in strata {
+            for name in &stratum.sequential {
+                let mut context = EvaluationContext::with_scratch(model, state, stocks, scratch, time);
+                let value = evaluate_variable(model, name, &mut context)?;
+                store_variable(model, name, value, scratch);
             }
-            new_state.auxiliaries = new_auxiliaries.clone();
 
-            // Converged if nothing changed and no errors
-            if !changed && !any_errors && pass > 0 {
-                break;
+            // Equations in `parallel` use no delays or random numbers, so
+            // each task's context gets empty stand-ins
+            let values: Vec<f64> = stratum.parallel.par_iter()
+                .map_init(
+                    || (DelayManager::new(), StochasticManager::with_seed(0)),
+                    |(delays, stochastic), name| {
+                        let mut context = EvaluationContext {
+                            model,
+                            stocks,
+                            flows: &scratch.flows,
+                            auxiliaries: &scratch.auxiliaries,
+                            agents: &state.agents,
+                            des: &state.des,
+                            events: &state.events,
+                            delays,
+                            stochastic,
+                            time,
+                            attributes: None,
+                            call_site: None,
+                        };
+                        evaluate_variable(model, name, &mut context)
+                    },
+                )
+                .collect::<Result<_, SimulationError>>()?;
+            for (name, value) in stratum.parallel.iter().zip(values) {
+                store_variable(model, name, value, scratch);
             }
         }
-        new_state.auxiliaries = new_auxiliaries;
-
-        // 2. Evaluate flows
-        let mut new_flows = HashMap::new();
-        for (name, flow) in &model.flows {
-            let mut temp_state = new_state.clone();
-            let mut context = EvaluationContext::new(model, &mut temp_state, state.time);
-
-            let value = flow.equation.evaluate(&mut context)
-                .map_err(|e| format!("Error evaluating flow '{}': {}", name, e))?;
-            new_flows.insert(name.clone(), value);
-
-            // Merge back delay, stochastic, and agent state
-            new_state.delays = temp_state.delays;
-            new_state.stochastic = temp_state.stochastic;
-            new_state.agents = temp_state.agents;
-        }
-        new_state.flows = new_flows;
+        return Ok(());
+    }
 
-        // 3. Update stocks using d(stock)/dt = inflows - outflows
-        let mut stock_derivatives: HashMap<String, f64> = HashMap::new();
+    let order = match &model.evaluation_order {
+        Some(order) => Cow::Borrowed(order),
+        None => Cow::Owned(Model::compute_evaluation_order(model)?),
+    };
 
-        for (stock_name, stock) in &model.stocks {
-            let mut derivative = 0.0;
+    for name in order.iter() {
+        let mut context = EvaluationContext::with_scratch(model, state, stocks, scratch, time);
+        let value = evaluate_variable(model, name, &mut context)?;
+        store_variable(model, name, value, scratch);
+    }
 
-            // Add inflows
-            for inflow_name in &stock.inflows {
-                if let Some(flow_value) = new_state.flows.get(inflow_name) {
-                    derivative += flow_value;
-                } else {
-                    return Err(format!("Inflow '{}' not found for stock '{}'", inflow_name, stock_name));
-                }
-            }
+    Ok(())
+}
 
-            // Subtract outflows
-            for outflow_name in &stock.outflows {
-                if let Some(flow_value) = new_state.flows.get(outflow_name) {
-                    derivative -= flow_value;
-                } else {
-                    return Err(format!("Outflow '{}' not found for stock '{}'", outflow_name, stock_name));
-                }
-            }
+/// Value of the auxiliary or flow `name`; a non-negative flow is held at zero or above
+fn evaluate_variable<'a>(model: &'a Model, name: &'a str, context: &mut EvaluationContext<'a>) -> Result<f64, SimulationError> {
+    let (kind, equation, non_negative) = if let Some(aux) = model.auxiliaries.get(name) {
+        ("auxiliary", &aux.equation, false)
+    } else if let Some(flow) = model.flows.get(name) {
+        ("flow", &flow.equation, flow.non_negative)
+    } else {
+        return Err(format!("Variable '{}' is not an auxiliary or flow", name).into());
+    };
+    context.call_site = Some(name);
+    let value = equation.evaluate(context).map_err(|message| SimulationError::Evaluation {
+        kind,
+        variable: name.to_string(),
+        equation: equation.to_string(),
+        time: context.time,
+        message,
+    })?;
+    Ok(if non_negative && value < 0.0 { 0.0 } else { value })
+}
 
-            stock_derivatives.insert(stock_name.clone(), derivative);
-        }
+/// Write an evaluated auxiliary or flow into `scratch`, letting conveyors adjust flows
+fn store_variable(model: &Model, name: &str, value: f64, scratch: &mut EvaluationScratch) {
+    if model.flows.contains_key(name) {
+        let value = if scratch.conveyors.is_empty() { value } else { scratch.conveyors.constrain_flow(name, value) };
+        scratch.flows.insert(name, value);
+    } else {
+        scratch.auxiliaries.insert(name, value);
+    }
+}
 
-        // Apply Euler integration: stock(t+dt) = stock(t) + derivative * dt
-        for (stock_name, current_value) in &state.stocks {
-            if let Some(derivative) = stock_derivatives.get(stock_name) {
-                let new_value = current_value + derivative * dt;
-
-                // Enforce non-negative and max_value constraints if specified
-                let constrained_value = if let Some(stock) = model.stocks.get(stock_name) {
-                    let mut value = new_value;
-                    if stock.non_negative {
-                        value = value.max(0.0);
-                    }
-                    if let Some(max_val) = stock.max_value {
-                        value = value.min(max_val);
-                    }
-                    value
-                } else {
-                    new_value
-                };
-
-                new_state.stocks.insert(stock_name.clone(), constrained_value);
-            }
-        }
+/// Auxiliaries and flows at an intermediate stage with `stocks`, leaving `state` unchanged
+fn evaluate_stage(
+    model: &Model,
+    state: &SimulationState,
+    stocks: &VariableValues,
+    time: f64,
+) -> Result<(VariableValues, VariableValues), SimulationError> {
+    let mut scratch = EvaluationScratch::new(state);
+    evaluate_into(model, state, stocks, &mut scratch, time)?;
+    Ok((scratch.auxiliaries, scratch.flows))
+}
 
-        // Update exponential delays
-        let mut delay_inputs = HashMap::new();
-        for (key, delay) in &new_state.delays.exponential_delays {
-            // The input is stored in the delay itself from evaluation
-            // For now, we'll update delays based on their current values
-            delay_inputs.insert(key.clone(), delay.value);
-        }
-        new_state.delays.update_all_exponential(&delay_inputs, dt);
+/// A copy of `state` with its auxiliaries and flows evaluated, as a step's first stage
+///
+/// Evaluating it in place lets stateful functions (delays, random streams)
+/// advance once per step and persist into the step's result.
+fn evaluated_base(model: &Model, state: &SimulationState, time: f64) -> Result<SimulationState, SimulationError> {
+    let mut base = state.clone();
+    evaluate_system(model, &mut base, time)?;
+    Ok(base)
+}
 
-        // Update pipeline delays
-        for pipeline in new_state.delays.pipeline_delays.values_mut() {
-            pipeline.push(new_state.time, 0.0); // Placeholder - need to track actual inputs
+/// Net rate of change (inflows - outflows) of every stock in `stocks`, laid out like `stocks`
+///
+/// Stocks the model does not define have a rate of zero.
+pub fn stock_rates(model: &Model, stocks: &VariableValues, flows: &VariableValues) -> Result<VariableValues, SimulationError> {
+    let mut rates = VariableValues::with_symbols(stocks.symbols().clone());
+    for (stock_name, rate) in rates.iter_mut() {
+        let Some(stock) = model.stocks.get(stock_name) else { continue };
+
+        for inflow_name in &stock.inflows {
+            *rate += flows.get(inflow_name)
+                .ok_or_else(|| format!("Inflow '{}' not found for stock '{}'", inflow_name, stock_name))?;
+        }
+        for outflow_name in &stock.outflows {
+            *rate -= flows.get(outflow_name)
+                .ok_or_else(|| format!("Outflow '{}' not found for stock '{}'", outflow_name, stock_name))?;
         }
-
-        Ok(new_state)
     }
+    Ok(rates)
 }
 
-/// RK4 (Runge-Kutta 4th order) integration method
-pub struct RK4Integrator;
+/// `stocks` each moved by `h` times the weighted sum of `rates`
+fn offset_stocks(stocks: &VariableValues, rates: &[(f64, &VariableValues)], h: f64) -> VariableValues {
+    let mut stage = stocks.clone();
+    for (i, value) in stage.as_mut_slice().iter_mut().enumerate() {
+        *value += h * rates.iter().map(|(weight, k)| weight * k.as_slice()[i]).sum::<f64>();
+    }
+    stage
+}
 
-impl RK4Integrator {
-    /// Evaluate auxiliaries and flows at a given state
-    fn evaluate_system(
-        &self,
-        model: &Model,
-        state: &SimulationState,
-        time: f64,
-    ) -> Result<(HashMap<String, f64>, HashMap<String, f64>), String> {
-        // 1. Evaluate auxiliaries with fixed-point iteration
-        // Start with existing auxiliary values from state
-        let mut auxiliaries = state.auxiliaries.clone();
-        const MAX_PASSES: usize = 20;
-
-        for pass in 0..MAX_PASSES {
-            let mut changed = false;
-            let mut temp_state = state.clone();
-            temp_state.auxiliaries = auxiliaries.clone();
-            let mut any_errors = false;
-
-            for (name, aux) in &model.auxiliaries {
-                let mut eval_state = temp_state.clone();
-                let mut context = EvaluationContext::new(model, &mut eval_state, time);
-
-                match aux.equation.evaluate(&mut context) {
-                    Ok(value) => {
-                        if let Some(&old_value) = auxiliaries.get(name) {
-                            let diff: f64 = value - old_value;
-                            if diff.abs() > 1e-10 {
-                                changed = true;
-                            }
-                        } else {
-                            changed = true;
-                        }
-                        auxiliaries.insert(name.clone(), value);
-                    }
-                    Err(e) => {
-                        if pass >= 5 {
-                            return Err(format!("Error evaluating auxiliary '{}' (pass {}): {}", name, pass + 1, e));
-                        }
-                        any_errors = true;
-                    }
-                }
+/// Hold each stock within its non-negative and maximum-value constraints
+fn constrain_stocks(model: &Model, stocks: &mut VariableValues) {
+    for (stock_name, value) in stocks.iter_mut() {
+        if let Some(stock) = model.stocks.get(stock_name) {
+            if stock.non_negative {
+                *value = value.max(0.0);
             }
-
-            if !changed && !any_errors && pass > 0 {
-                break;
+            if let Some(max_val) = stock.max_value {
+                *value = value.min(max_val);
             }
         }
-
-        // 2. Evaluate flows
-        let mut eval_state = state.clone();
-        eval_state.auxiliaries = auxiliaries.clone();
-
-        let mut flows = HashMap::new();
-        for (name, flow) in &model.flows {
-            let mut temp_state = eval_state.clone();
-            let mut context = EvaluationContext::new(model, &mut temp_state, time);
-
-            let value = flow.equation.evaluate(&mut context)
-                .map_err(|e| format!("Error evaluating flow '{}': {}", name, e))?;
-            flows.insert(name.clone(), value);
-        }
-
-        Ok((auxiliaries, flows))
     }
+}
 
-    /// Compute derivatives (inflows - outflows) for all stocks
-    fn compute_derivatives(
-        &self,
-        model: &Model,
-        flows: &HashMap<String, f64>,
-    ) -> Result<HashMap<String, f64>, String> {
-        let mut derivatives = HashMap::new();
-
-        for (stock_name, stock) in &model.stocks {
-            let mut derivative = 0.0;
+/// Euler (forward) integration method
+pub struct EulerIntegrator;
 
-            // Add inflows
-            for inflow_name in &stock.inflows {
-                if let Some(flow_value) = flows.get(inflow_name) {
-                    derivative += flow_value;
-                } else {
-                    return Err(format!("Inflow '{}' not found for stock '{}'", inflow_name, stock_name));
-                }
-            }
+impl Integrator for EulerIntegrator {
+    fn step(&self, model: &Model, state: &SimulationState, dt: f64) -> Result<SimulationState, SimulationError> {
+        let mut new_state = state.clone();
+        new_state.time += dt;
 
-            // Subtract outflows
-            for outflow_name in &stock.outflows {
-                if let Some(flow_value) = flows.get(outflow_name) {
-                    derivative -= flow_value;
-                } else {
-                    return Err(format!("Outflow '{}' not found for stock '{}'", outflow_name, stock_name));
-                }
-            }
+        // 1-2. Evaluate auxiliaries and flows in dependency order
+        evaluate_system(model, &mut new_state, state.time)?;
 
-            derivatives.insert(stock_name.clone(), derivative);
+        // 3. Update stocks using d(stock)/dt = inflows - outflows:
+        // stock(t+dt) = stock(t) + derivative * dt
+        let rates = stock_rates(model, &new_state.stocks, &new_state.flows)?;
+        for (value, rate) in new_state.stocks.as_mut_slice().iter_mut().zip(rates.as_slice()) {
+            *value += rate * dt;
         }
+        constrain_stocks(model, &mut new_state.stocks);
 
-        Ok(derivatives)
-    }
-
-    /// Create a new state with modified stock values
-    /// Preserves auxiliaries and flows from base state as starting point
-    fn apply_stock_increments(
-        &self,
-        base_state: &SimulationState,
-        increments: &HashMap<String, f64>,
-    ) -> SimulationState {
-        let mut new_state = base_state.clone();
-        // Update stocks with increments
-        for (stock_name, increment) in increments {
-            if let Some(&current_value) = base_state.stocks.get(stock_name) {
-                new_state.stocks.insert(stock_name.clone(), current_value + increment);
-            }
-        }
-        // Preserve auxiliaries and flows from base state
-        // They will be updated by evaluate_system
-        new_state
+        Ok(new_state)
     }
 }
 
+/// RK4 (Runge-Kutta 4th order) integration method
+pub struct RK4Integrator;
+
 impl Integrator for RK4Integrator {
-    fn step(&self, model: &Model, state: &SimulationState, dt: f64) -> Result<SimulationState, String> {
+    fn step(&self, model: &Model, state: &SimulationState, dt: f64) -> Result<SimulationState, SimulationError> {
         // RK4 algorithm: y_{n+1} = y_n + (k1 + 2*k2 + 2*k3 + k4) * dt / 6
         // where:
         //   k1 = f(t_n, y_n)
@@ -287,63 +225,33 @@ impl Integrator for RK4Integrator {
 
         let t = state.time;
 
+        let base = evaluated_base(model, state, t)?;
+        let state = &base;
+
         // Stage 1: k1 = f(t, y)
-        let (aux1, flows1) = self.evaluate_system(model, state, t)?;
-        let k1 = self.compute_derivatives(model, &flows1)?;
+        let k1 = stock_rates(model, &state.stocks, &state.flows)?;
 
         // Stage 2: k2 = f(t + dt/2, y + k1*dt/2)
-        let increments2: HashMap<String, f64> = k1.iter()
-            .map(|(name, &deriv)| (name.clone(), deriv * dt / 2.0))
-            .collect();
-        let state2 = self.apply_stock_increments(state, &increments2);
-        let (aux2, flows2) = self.evaluate_system(model, &state2, t + dt / 2.0)?;
-        let k2 = self.compute_derivatives(model, &flows2)?;
+        let stocks2 = offset_stocks(&state.stocks, &[(0.5, &k1)], dt);
+        let (_, flows2) = evaluate_stage(model, state, &stocks2, t + dt / 2.0)?;
+        let k2 = stock_rates(model, &state.stocks, &flows2)?;
 
         // Stage 3: k3 = f(t + dt/2, y + k2*dt/2)
-        let increments3: HashMap<String, f64> = k2.iter()
-            .map(|(name, &deriv)| (name.clone(), deriv * dt / 2.0))
-            .collect();
-        let state3 = self.apply_stock_increments(state, &increments3);
-        let (aux3, flows3) = self.evaluate_system(model, &state3, t + dt / 2.0)?;
-        let k3 = self.compute_derivatives(model, &flows3)?;
+        let stocks3 = offset_stocks(&state.stocks, &[(0.5, &k2)], dt);
+        let (_, flows3) = evaluate_stage(model, state, &stocks3, t + dt / 2.0)?;
+        let k3 = stock_rates(model, &state.stocks, &flows3)?;
 
         // Stage 4: k4 = f(t + dt, y + k3*dt)
-        let increments4: HashMap<String, f64> = k3.iter()
-            .map(|(name, &deriv)| (name.clone(), deriv * dt))
-            .collect();
-        let state4 = self.apply_stock_increments(state, &increments4);
-        let (aux4, flows4) = self.evaluate_system(model, &state4, t + dt)?;
-        let k4 = self.compute_derivatives(model, &flows4)?;
-
-        // Combine stages with RK4 weights
-        let mut new_state = state.clone();
+        let stocks4 = offset_stocks(&state.stocks, &[(1.0, &k3)], dt);
+        let (aux4, flows4) = evaluate_stage(model, state, &stocks4, t + dt)?;
+        let k4 = stock_rates(model, &state.stocks, &flows4)?;
+
+        // Combine stages with RK4 weights: y_new = y + (k1 + 2*k2 + 2*k3 + k4) * dt / 6
+        let stocks = offset_stocks(&state.stocks, &[(1.0, &k1), (2.0, &k2), (2.0, &k3), (1.0, &k4)], dt / 6.0);
+        let mut new_state = base;
+        new_state.stocks = stocks;
         new_state.time += dt;
-
-        // Update stocks: y_new = y + (k1 + 2*k2 + 2*k3 + k4) * dt / 6
-        for (stock_name, &current_value) in &state.stocks {
-            let d1 = k1.get(stock_name).unwrap_or(&0.0);
-            let d2 = k2.get(stock_name).unwrap_or(&0.0);
-            let d3 = k3.get(stock_name).unwrap_or(&0.0);
-            let d4 = k4.get(stock_name).unwrap_or(&0.0);
-
-            let new_value = current_value + (d1 + 2.0 * d2 + 2.0 * d3 + d4) * dt / 6.0;
-
-            // Enforce non-negative and max_value constraints if specified
-            let constrained_value = if let Some(stock) = model.stocks.get(stock_name) {
-                let mut value = new_value;
-                if stock.non_negative {
-                    value = value.max(0.0);
-                }
-                if let Some(max_val) = stock.max_value {
-                    value = value.min(max_val);
-                }
-                value
-            } else {
-                new_value
-            };
-
-            new_state.stocks.insert(stock_name.clone(), constrained_value);
-        }
+        constrain_stocks(model, &mut new_state.stocks);
 
         // Use final stage values for auxiliaries and flows
         new_state.auxiliaries = aux4;
@@ -358,166 +266,33 @@ impl Integrator for RK4Integrator {
 /// More accurate than Euler but less expensive than RK4
 pub struct HeunIntegrator;
 
-impl HeunIntegrator {
-    /// Evaluate auxiliaries and flows at a given state
-    fn evaluate_system(
-        &self,
-        model: &Model,
-        state: &SimulationState,
-        time: f64,
-    ) -> Result<(HashMap<String, f64>, HashMap<String, f64>), String> {
-        // Evaluate auxiliaries with fixed-point iteration
-        let mut auxiliaries = state.auxiliaries.clone();
-        const MAX_PASSES: usize = 20;
-
-        for pass in 0..MAX_PASSES {
-            let mut changed = false;
-            let mut temp_state = state.clone();
-            temp_state.auxiliaries = auxiliaries.clone();
-            let mut any_errors = false;
-
-            for (name, aux) in &model.auxiliaries {
-                let mut eval_state = temp_state.clone();
-                let mut context = EvaluationContext::new(model, &mut eval_state, time);
-
-                match aux.equation.evaluate(&mut context) {
-                    Ok(value) => {
-                        if let Some(&old_value) = auxiliaries.get(name) {
-                            let diff: f64 = value - old_value;
-                            if diff.abs() > 1e-10 {
-                                changed = true;
-                            }
-                        } else {
-                            changed = true;
-                        }
-                        auxiliaries.insert(name.clone(), value);
-                    }
-                    Err(e) => {
-                        if pass >= 5 {
-                            return Err(format!("Error evaluating auxiliary '{}' (pass {}): {}", name, pass + 1, e));
-                        }
-                        any_errors = true;
-                    }
-                }
-            }
-
-            if !changed && !any_errors && pass > 0 {
-                break;
-            }
-        }
-
-        // Evaluate flows
-        let mut eval_state = state.clone();
-        eval_state.auxiliaries = auxiliaries.clone();
-
-        let mut flows = HashMap::new();
-        for (name, flow) in &model.flows {
-            let mut temp_state = eval_state.clone();
-            let mut context = EvaluationContext::new(model, &mut temp_state, time);
-
-            let value = flow.equation.evaluate(&mut context)
-                .map_err(|e| format!("Error evaluating flow '{}': {}", name, e))?;
-            flows.insert(name.clone(), value);
-        }
-
-        Ok((auxiliaries, flows))
-    }
-
-    fn compute_derivatives(
-        &self,
-        model: &Model,
-        flows: &HashMap<String, f64>,
-    ) -> Result<HashMap<String, f64>, String> {
-        let mut derivatives = HashMap::new();
-
-        for (stock_name, stock) in &model.stocks {
-            let mut derivative = 0.0;
-
-            for inflow_name in &stock.inflows {
-                if let Some(flow_value) = flows.get(inflow_name) {
-                    derivative += flow_value;
-                } else {
-                    return Err(format!("Inflow '{}' not found for stock '{}'", inflow_name, stock_name));
-                }
-            }
-
-            for outflow_name in &stock.outflows {
-                if let Some(flow_value) = flows.get(outflow_name) {
-                    derivative -= flow_value;
-                } else {
-                    return Err(format!("Outflow '{}' not found for stock '{}'", outflow_name, stock_name));
-                }
-            }
-
-            derivatives.insert(stock_name.clone(), derivative);
-        }
-
-        Ok(derivatives)
-    }
-
-    fn apply_stock_increments(
-        &self,
-        base_state: &SimulationState,
-        increments: &HashMap<String, f64>,
-    ) -> SimulationState {
-        let mut new_state = base_state.clone();
-        for (stock_name, increment) in increments {
-            if let Some(&current_value) = base_state.stocks.get(stock_name) {
-                new_state.stocks.insert(stock_name.clone(), current_value + increment);
-            }
-        }
-        new_state
-    }
-}
-
 impl Integrator for HeunIntegrator {
-    fn step(&self, model: &Model, state: &SimulationState, dt: f64) -> Result<SimulationState, String> {
+    fn step(&self, model: &Model, state: &SimulationState, dt: f64) -> Result<SimulationState, SimulationError> {
         // Heun's method (predictor-corrector):
         // 1. Predictor: y_pred = y_n + f(t_n, y_n) * dt
         // 2. Corrector: y_{n+1} = y_n + [f(t_n, y_n) + f(t_{n+1}, y_pred)] * dt / 2
 
         let t = state.time;
 
+        let base = evaluated_base(model, state, t)?;
+        let state = &base;
+
         // Predictor step: evaluate at current state
-        let (aux1, flows1) = self.evaluate_system(model, state, t)?;
-        let k1 = self.compute_derivatives(model, &flows1)?;
+        let k1 = stock_rates(model, &state.stocks, &state.flows)?;
 
         // Predicted state: y_pred = y + k1 * dt
-        let increments_pred: HashMap<String, f64> = k1.iter()
-            .map(|(name, &deriv)| (name.clone(), deriv * dt))
-            .collect();
-        let state_pred = self.apply_stock_increments(state, &increments_pred);
+        let stocks_pred = offset_stocks(&state.stocks, &[(1.0, &k1)], dt);
 
         // Corrector step: evaluate at predicted state
-        let (aux2, flows2) = self.evaluate_system(model, &state_pred, t + dt)?;
-        let k2 = self.compute_derivatives(model, &flows2)?;
+        let (aux2, flows2) = evaluate_stage(model, state, &stocks_pred, t + dt)?;
+        let k2 = stock_rates(model, &state.stocks, &flows2)?;
 
         // Final update: y_new = y + (k1 + k2) * dt / 2
-        let mut new_state = state.clone();
+        let stocks = offset_stocks(&state.stocks, &[(1.0, &k1), (1.0, &k2)], dt / 2.0);
+        let mut new_state = base;
+        new_state.stocks = stocks;
         new_state.time += dt;
-
-        for (stock_name, &current_value) in &state.stocks {
-            let d1 = k1.get(stock_name).unwrap_or(&0.0);
-            let d2 = k2.get(stock_name).unwrap_or(&0.0);
-
-            let new_value = current_value + (d1 + d2) * dt / 2.0;
-
-            // Enforce constraints
-            let constrained_value = if let Some(stock) = model.stocks.get(stock_name) {
-                let mut value = new_value;
-                if stock.non_negative {
-                    value = value.max(0.0);
-                }
-                if let Some(max_val) = stock.max_value {
-                    value = value.min(max_val);
-                }
-                value
-            } else {
-                new_value
-            };
-
-            new_state.stocks.insert(stock_name.clone(), constrained_value);
-        }
+        constrain_stocks(model, &mut new_state.stocks);
 
         // Use corrector values for auxiliaries and flows
         new_state.auxiliaries = aux2;
@@ -553,175 +328,260 @@ impl BackwardEulerIntegrator {
             tolerance,
         }
     }
+}
 
-    fn evaluate_system(
-        &self,
-        model: &Model,
-        state: &SimulationState,
-        time: f64,
-    ) -> Result<(HashMap<String, f64>, HashMap<String, f64>), String> {
-        let mut auxiliaries = state.auxiliaries.clone();
-        const MAX_PASSES: usize = 20;
-
-        for pass in 0..MAX_PASSES {
-            let mut changed = false;
-            let mut temp_state = state.clone();
-            temp_state.auxiliaries = auxiliaries.clone();
-            let mut any_errors = false;
-
-            for (name, aux) in &model.auxiliaries {
-                let mut eval_state = temp_state.clone();
-                let mut context = EvaluationContext::new(model, &mut eval_state, time);
-
-                match aux.equation.evaluate(&mut context) {
-                    Ok(value) => {
-                        if let Some(&old_value) = auxiliaries.get(name) {
-                            let diff: f64 = value - old_value;
-                            if diff.abs() > 1e-10 {
-                                changed = true;
-                            }
-                        } else {
-                            changed = true;
-                        }
-                        auxiliaries.insert(name.clone(), value);
-                    }
-                    Err(e) => {
-                        if pass >= 5 {
-                            return Err(format!("Error evaluating auxiliary '{}' (pass {}): {}", name, pass + 1, e));
-                        }
-                        any_errors = true;
-                    }
-                }
-            }
+impl Integrator for BackwardEulerIntegrator {
+    fn step(&self, model: &Model, state: &SimulationState, dt: f64) -> Result<SimulationState, SimulationError> {
+        // Backward Euler: y_{n+1} = y_n + f(t_{n+1}, y_{n+1}) * dt
+        // This is implicit, so we solve using fixed-point iteration:
+        // y^{k+1} = y_n + f(t_{n+1}, y^k) * dt
+
+        let t_next = state.time + dt;
+
+        let base = evaluated_base(model, state, state.time)?;
+        let state = &base;
+
+        // Initial guess: use forward Euler
+        let deriv0 = stock_rates(model, &state.stocks, &state.flows)?;
+        let mut current_stocks = offset_stocks(&state.stocks, &[(1.0, &deriv0)], dt);
+        let mut current_values = None;
+        let mut converged = false;
+
+        // Fixed-point iteration
+        for iteration in 0..self.max_iterations {
+            // Evaluate system at current estimate
+            let (auxiliaries, flows) = evaluate_stage(model, state, &current_stocks, t_next)?;
+            let derivatives = stock_rates(model, &state.stocks, &flows)?;
+
+            // Compute new estimate: y_new = y_old + f(t_next, y_current) * dt
+            let mut next_stocks = offset_stocks(&state.stocks, &[(1.0, &derivatives)], dt);
+            constrain_stocks(model, &mut next_stocks);
+
+            // Track convergence
+            let max_change = next_stocks.as_slice().iter()
+                .zip(current_stocks.as_slice())
+                .map(|(next, current)| (next - current).abs())
+                .fold(0.0, f64::max);
+
+            current_stocks = next_stocks;
+            current_values = Some((auxiliaries, flows));
 
-            if !changed && !any_errors && pass > 0 {
+            // Check convergence
+            if max_change < self.tolerance && iteration > 0 {
+                converged = true;
                 break;
             }
         }
 
-        let mut eval_state = state.clone();
-        eval_state.auxiliaries = auxiliaries.clone();
-
-        let mut flows = HashMap::new();
-        for (name, flow) in &model.flows {
-            let mut temp_state = eval_state.clone();
-            let mut context = EvaluationContext::new(model, &mut temp_state, time);
-
-            let value = flow.equation.evaluate(&mut context)
-                .map_err(|e| format!("Error evaluating flow '{}': {}", name, e))?;
-            flows.insert(name.clone(), value);
+        // If we didn't converge, use the best estimate with a warning
+        if !converged {
+            eprintln!("Warning: Backward Euler did not converge after {} iterations", self.max_iterations);
         }
 
-        Ok((auxiliaries, flows))
+        let mut new_state = base;
+        new_state.time = t_next;
+        new_state.stocks = current_stocks;
+        if let Some((auxiliaries, flows)) = current_values {
+            new_state.auxiliaries = auxiliaries;
+            new_state.flows = flows;
+        }
+        Ok(new_state)
     }
+}
 
-    fn compute_derivatives(
-        &self,
-        model: &Model,
-        flows: &HashMap<String, f64>,
-    ) -> Result<HashMap<String, f64>, String> {
-        let mut derivatives = HashMap::new();
+/// Stock derivatives (inflows - outflows) at `state`, ordered as `stock_names`
+pub fn stock_derivatives(
+    model: &Model,
+    state: &SimulationState,
+    time: f64,
+    stock_names: &[String],
+) -> Result<Vec<f64>, SimulationError> {
+    stage_derivatives(model, state, &state.stocks, time, stock_names)
+}
 
-        for (stock_name, stock) in &model.stocks {
-            let mut derivative = 0.0;
+/// As [`stock_derivatives`], with `stocks` in place of `state`'s
+fn stage_derivatives(
+    model: &Model,
+    state: &SimulationState,
+    stocks: &VariableValues,
+    time: f64,
+    stock_names: &[String],
+) -> Result<Vec<f64>, SimulationError> {
+    let (_, flows) = evaluate_stage(model, state, stocks, time)?;
+    net_flows(model, &flows, stock_names)
+}
 
+fn net_flows(model: &Model, flows: &VariableValues, stock_names: &[String]) -> Result<Vec<f64>, SimulationError> {
+    stock_names.iter()
+        .map(|stock_name| {
+            let stock = model.stocks.get(stock_name)
+                .ok_or_else(|| format!("Stock '{}' not found", stock_name))?;
+            let mut derivative = 0.0;
             for inflow_name in &stock.inflows {
-                if let Some(flow_value) = flows.get(inflow_name) {
-                    derivative += flow_value;
-                } else {
-                    return Err(format!("Inflow '{}' not found for stock '{}'", inflow_name, stock_name));
-                }
+                derivative += flows.get(inflow_name)
+                    .ok_or_else(|| format!("Inflow '{}' not found for stock '{}'", inflow_name, stock_name))?;
             }
-
             for outflow_name in &stock.outflows {
-                if let Some(flow_value) = flows.get(outflow_name) {
-                    derivative -= flow_value;
-                } else {
-                    return Err(format!("Outflow '{}' not found for stock '{}'", outflow_name, stock_name));
-                }
+                derivative -= flows.get(outflow_name)
+                    .ok_or_else(|| format!("Outflow '{}' not found for stock '{}'", outflow_name, stock_name))?;
             }
+            Ok(derivative)
+        })
+        .collect()
+}
 
-            derivatives.insert(stock_name.clone(), derivative);
+/// Finite-difference Jacobian of the stock derivatives with respect to the stocks
+///
+/// Entry `(i, j)` is d(dS_i/dt)/dS_j. Each stock is perturbed by
+/// `epsilon * max(|S_j|, 1)` so large stocks keep a usable difference.
+pub fn numerical_jacobian(
+    model: &Model,
+    state: &SimulationState,
+    stock_names: &[String],
+    epsilon: f64,
+) -> Result<DMatrix<f64>, SimulationError> {
+    let n = stock_names.len();
+    let mut jacobian = DMatrix::zeros(n, n);
+    let base = stock_derivatives(model, state, state.time, stock_names)?;
+
+    for (j, stock_name) in stock_names.iter().enumerate() {
+        let value = *state.stocks.get(stock_name)
+            .ok_or_else(|| format!("Stock '{}' not found", stock_name))?;
+        let h = epsilon * value.abs().max(1.0);
+
+        let mut perturbed = state.stocks.clone();
+        perturbed.insert(stock_name, value + h);
+        let derivatives = stage_derivatives(model, state, &perturbed, state.time, stock_names)?;
+
+        for i in 0..n {
+            jacobian[(i, j)] = (derivatives[i] - base[i]) / h;
         }
-
-        Ok(derivatives)
     }
-}
 
-impl Integrator for BackwardEulerIntegrator {
-    fn step(&self, model: &Model, state: &SimulationState, dt: f64) -> Result<SimulationState, String> {
-        // Backward Euler: y_{n+1} = y_n + f(t_{n+1}, y_{n+1}) * dt
-        // This is implicit, so we solve using fixed-point iteration:
-        // y^{k+1} = y_n + f(t_{n+1}, y^k) * dt
+    Ok(jacobian)
+}
 
-        let t_next = state.time + dt;
+/// TR-BDF2 stiff integrator
+/// A trapezoidal stage to t + γh followed by a BDF2 stage to t + h. Both
+/// stages solve y - (γ/2)h f(y) = rhs with Newton iterations on one
+/// finite-difference Jacobian, giving an L-stable second order method.
+pub struct BdfIntegrator {
+    /// Maximum Newton iterations per stage
+    pub max_iterations: usize,
+    /// Convergence tolerance on the scaled Newton update
+    pub tolerance: f64,
+    /// Relative perturbation for the numerical Jacobian
+    pub epsilon: f64,
+}
 
-        // Initial guess: use forward Euler
-        let (_, flows0) = self.evaluate_system(model, state, state.time)?;
-        let deriv0 = self.compute_derivatives(model, &flows0)?;
+impl Default for BdfIntegrator {
+    fn default() -> Self {
+        Self {
+            max_iterations: 20,
+            tolerance: 1e-8,
+            epsilon: 1e-7,
+        }
+    }
+}
 
-        let mut current_state = state.clone();
-        current_state.time = t_next;
+impl BdfIntegrator {
+    pub fn new(max_iterations: usize, tolerance: f64) -> Self {
+        Self {
+            max_iterations,
+            tolerance,
+            ..Default::default()
+        }
+    }
 
-        // Apply initial Euler step as starting guess
-        for (stock_name, &current_value) in &state.stocks {
-            if let Some(&derivative) = deriv0.get(stock_name) {
-                current_state.stocks.insert(stock_name.clone(), current_value + derivative * dt);
+    /// Solve `y - dh * f(time, y) = rhs` by simplified Newton iteration
+    #[allow(clippy::too_many_arguments)]
+    fn solve_stage(
+        &self,
+        model: &Model,
+        base: &SimulationState,
+        stock_names: &[String],
+        lu: &nalgebra::LU<f64, nalgebra::Dyn, nalgebra::Dyn>,
+        rhs: &DVector<f64>,
+        guess: DVector<f64>,
+        time: f64,
+        dh: f64,
+    ) -> Result<DVector<f64>, SimulationError> {
+        let mut y = guess;
+        let mut stocks = base.stocks.clone();
+
+        for _ in 0..self.max_iterations {
+            for (name, &value) in stock_names.iter().zip(y.iter()) {
+                stocks.insert(name, value);
+            }
+            let f = DVector::from_vec(stage_derivatives(model, base, &stocks, time, stock_names)?);
+
+            let residual = &y - &f * dh - rhs;
+            let delta = lu.solve(&(-residual))
+                .ok_or_else(|| "Singular Newton matrix in BDF integrator".to_string())?;
+            y += &delta;
+
+            let change = delta.iter().zip(y.iter())
+                .map(|(d, v)| d.abs() / (1.0 + v.abs()))
+                .fold(0.0, f64::max);
+            if change < self.tolerance {
+                return Ok(y);
             }
         }
 
-        // Fixed-point iteration
-        for iteration in 0..self.max_iterations {
-            // Evaluate system at current estimate
-            let (auxiliaries, flows) = self.evaluate_system(model, &current_state, t_next)?;
-            let derivatives = self.compute_derivatives(model, &flows)?;
+        Err(SimulationError::Failed {
+            time: Some(time),
+            message: format!("BDF Newton iteration did not converge within {} iterations", self.max_iterations),
+        })
+    }
+}
 
-            // Compute new estimate: y_new = y_old + f(t_next, y_current) * dt
-            let mut max_change: f64 = 0.0;
-            let mut next_state = current_state.clone();
-
-            for (stock_name, &old_value) in &state.stocks {
-                if let Some(&derivative) = derivatives.get(stock_name) {
-                    let new_value = old_value + derivative * dt;
-
-                    // Enforce constraints
-                    let constrained_value = if let Some(stock) = model.stocks.get(stock_name) {
-                        let mut value = new_value;
-                        if stock.non_negative {
-                            value = value.max(0.0);
-                        }
-                        if let Some(max_val) = stock.max_value {
-                            value = value.min(max_val);
-                        }
-                        value
-                    } else {
-                        new_value
-                    };
-
-                    // Track convergence
-                    if let Some(&current_val) = current_state.stocks.get(stock_name) {
-                        let change = (constrained_value - current_val).abs();
-                        max_change = max_change.max(change);
-                    }
-
-                    next_state.stocks.insert(stock_name.clone(), constrained_value);
-                }
+impl Integrator for BdfIntegrator {
+    fn step(&self, model: &Model, state: &SimulationState, dt: f64) -> Result<SimulationState, SimulationError> {
+        let t = state.time;
+        let gamma = 2.0 - std::f64::consts::SQRT_2;
+        let dh = gamma / 2.0 * dt;
+
+        let base = evaluated_base(model, state, t)?;
+        let state = &base;
+
+        let mut stock_names: Vec<String> = model.stocks.keys().cloned().collect();
+        stock_names.sort();
+        let n = stock_names.len();
+
+        let y0 = DVector::from_iterator(n, stock_names.iter().map(|name| state.stocks[name]));
+        let f0 = DVector::from_vec(net_flows(model, &state.flows, &stock_names)?);
+
+        // Newton matrix I - (γ/2)h J, shared by both stages
+        let jacobian = numerical_jacobian(model, state, &stock_names, self.epsilon)?;
+        let lu = (DMatrix::identity(n, n) - jacobian * dh).lu();
+
+        // Trapezoidal stage: y_γ - (γ/2)h f(y_γ) = y_n + (γ/2)h f(y_n)
+        let rhs = &y0 + &f0 * dh;
+        let guess = &y0 + &f0 * (gamma * dt);
+        let y_gamma = self.solve_stage(model, state, &stock_names, &lu, &rhs, guess, t + gamma * dt, dh)?;
+
+        // BDF2 stage: y_{n+1} - (γ/2)h f(y_{n+1}) = (y_γ - (1-γ)² y_n) / (γ(2-γ))
+        let scale = 1.0 / (gamma * (2.0 - gamma));
+        let rhs = &y_gamma * scale - &y0 * ((1.0 - gamma).powi(2) * scale);
+        let guess = &y0 + (&y_gamma - &y0) / gamma;
+        let y1 = self.solve_stage(model, state, &stock_names, &lu, &rhs, guess, t + dt, dh)?;
+
+        let mut new_state = base;
+        new_state.time = t + dt;
+
+        for (stock_name, &new_value) in stock_names.iter().zip(y1.iter()) {
+            let stock = &model.stocks[stock_name];
+            let mut value = new_value;
+            if stock.non_negative {
+                value = value.max(0.0);
             }
-
-            next_state.auxiliaries = auxiliaries;
-            next_state.flows = flows;
-
-            // Check convergence
-            if max_change < self.tolerance && iteration > 0 {
-                return Ok(next_state);
+            if let Some(max_val) = stock.max_value {
+                value = value.min(max_val);
             }
-
-            current_state = next_state;
+            new_state.stocks.insert(stock_name, value);
         }
 
-        // If we didn't converge, return best estimate with warning
-        eprintln!("Warning: Backward Euler did not converge after {} iterations", self.max_iterations);
-        Ok(current_state)
+        Ok(new_state)
     }
 }
 
@@ -768,134 +628,20 @@ impl RK45Integrator {
         self
     }
 
-    /// Evaluate system at a given state
-    fn evaluate_system(
-        &self,
-        model: &Model,
-        state: &SimulationState,
-        time: f64,
-    ) -> Result<(HashMap<String, f64>, HashMap<String, f64>), String> {
-        // Evaluate auxiliaries with fixed-point iteration
-        let mut auxiliaries = state.auxiliaries.clone();
-        const MAX_PASSES: usize = 20;
-
-        for pass in 0..MAX_PASSES {
-            let mut changed = false;
-            let mut temp_state = state.clone();
-            temp_state.auxiliaries = auxiliaries.clone();
-            let mut any_errors = false;
-
-            for (name, aux) in &model.auxiliaries {
-                let mut eval_state = temp_state.clone();
-                let mut context = EvaluationContext::new(model, &mut eval_state, time);
-
-                match aux.equation.evaluate(&mut context) {
-                    Ok(value) => {
-                        if let Some(&old_value) = auxiliaries.get(name) {
-                            let diff: f64 = value - old_value;
-                            if diff.abs() > 1e-10 {
-                                changed = true;
-                            }
-                        } else {
-                            changed = true;
-                        }
-                        auxiliaries.insert(name.clone(), value);
-                    }
-                    Err(e) => {
-                        if pass >= 5 {
-                            return Err(format!("Error evaluating auxiliary '{}' (pass {}): {}", name, pass + 1, e));
-                        }
-                        any_errors = true;
-                    }
-                }
-            }
-
-            if !changed && !any_errors && pass > 0 {
-                break;
-            }
-        }
-
-        // Evaluate flows
-        let mut eval_state = state.clone();
-        eval_state.auxiliaries = auxiliaries.clone();
-
-        let mut flows = HashMap::new();
-        for (name, flow) in &model.flows {
-            let mut temp_state = eval_state.clone();
-            let mut context = EvaluationContext::new(model, &mut temp_state, time);
-
-            let value = flow.equation.evaluate(&mut context)
-                .map_err(|e| format!("Error evaluating flow '{}': {}", name, e))?;
-            flows.insert(name.clone(), value);
-        }
-
-        Ok((auxiliaries, flows))
-    }
-
-    /// Compute derivatives (inflows - outflows) for all stocks
-    fn compute_derivatives(
-        &self,
-        model: &Model,
-        flows: &HashMap<String, f64>,
-    ) -> Result<HashMap<String, f64>, String> {
-        let mut derivatives = HashMap::new();
-
-        for (stock_name, stock) in &model.stocks {
-            let mut derivative = 0.0;
-
-            for inflow_name in &stock.inflows {
-                if let Some(flow_value) = flows.get(inflow_name) {
-                    derivative += flow_value;
-                } else {
-                    return Err(format!("Inflow '{}' not found for stock '{}'", inflow_name, stock_name));
-                }
-            }
-
-            for outflow_name in &stock.outflows {
-                if let Some(flow_value) = flows.get(outflow_name) {
-                    derivative -= flow_value;
-                } else {
-                    return Err(format!("Outflow '{}' not found for stock '{}'", outflow_name, stock_name));
-                }
-            }
-
-            derivatives.insert(stock_name.clone(), derivative);
-        }
-
-        Ok(derivatives)
-    }
-
-    /// Apply stock increments to create new state
-    fn apply_stock_increments(
-        &self,
-        base_state: &SimulationState,
-        increments: &HashMap<String, f64>,
-    ) -> SimulationState {
-        let mut new_state = base_state.clone();
-        for (stock_name, increment) in increments {
-            if let Some(&current_value) = base_state.stocks.get(stock_name) {
-                new_state.stocks.insert(stock_name.clone(), current_value + increment);
-            }
-        }
-        new_state
-    }
-
     /// Compute error estimate and optimal step size
     fn compute_error_and_step(
         &self,
-        y4: &HashMap<String, f64>,
-        y5: &HashMap<String, f64>,
+        y4: &VariableValues,
+        y5: &VariableValues,
         current_step: f64,
     ) -> (f64, f64) {
         let mut max_error: f64 = 0.0;
 
-        for (name, &val4) in y4 {
-            if let Some(&val5) = y5.get(name) {
-                let error = (val5 - val4).abs();
-                let scale = self.atol + self.rtol * val5.abs().max(val4.abs());
-                let normalized_error = error / scale;
-                max_error = max_error.max(normalized_error);
-            }
+        for (&val4, &val5) in y4.as_slice().iter().zip(y5.as_slice()) {
+            let error = (val5 - val4).abs();
+            let scale = self.atol + self.rtol * val5.abs().max(val4.abs());
+            let normalized_error = error / scale;
+            max_error = max_error.max(normalized_error);
         }
 
         // Compute new step size
@@ -911,8 +657,31 @@ impl RK45Integrator {
     }
 }
 
+/// Outcome of one accepted adaptive step
+#[derive(Debug, Clone)]
+pub struct AdaptiveStep {
+    /// State at `state.time`, the end of the accepted step
+    pub state: SimulationState,
+    /// Stock derivatives at the start of the step
+    pub start_derivatives: VariableValues,
+    /// Stock derivatives at the end of the step
+    pub end_derivatives: VariableValues,
+    /// Suggested size for the next step
+    pub next_step: f64,
+    /// Attempts rejected before this step was accepted
+    pub rejected: usize,
+}
+
 impl Integrator for RK45Integrator {
-    fn step(&self, model: &Model, state: &SimulationState, dt: f64) -> Result<SimulationState, String> {
+    fn step(&self, model: &Model, state: &SimulationState, dt: f64) -> Result<SimulationState, SimulationError> {
+        self.adaptive_step(model, state, dt).map(|step| step.state)
+    }
+}
+
+impl RK45Integrator {
+    /// Attempt a step of size `h`, shrinking it until the error estimate is
+    /// within tolerance
+    pub fn adaptive_step(&self, model: &Model, state: &SimulationState, h: f64) -> Result<AdaptiveStep, SimulationError> {
         // Dormand-Prince coefficients
         // Butcher tableau for DOPRI5
         let a21 = 1.0 / 5.0;
@@ -952,141 +721,73 @@ impl Integrator for RK45Integrator {
         let b7_star = 1.0 / 40.0;
 
         let t = state.time;
-        let mut h = dt.min(self.max_step);
+        let mut h = h.min(self.max_step);
         const MAX_ATTEMPTS: usize = 10;
 
-        for _attempt in 0..MAX_ATTEMPTS {
-            // Stage 1: k1 = f(t, y)
-            let (_, flows1) = self.evaluate_system(model, state, t)?;
-            let k1 = self.compute_derivatives(model, &flows1)?;
+        let base = evaluated_base(model, state, t)?;
+        let state = &base;
+
+        // Stage 1: k1 = f(t, y), independent of the step size
+        let k1 = stock_rates(model, &state.stocks, &state.flows)?;
 
+        for attempt in 0..MAX_ATTEMPTS {
             // Stage 2
-            let inc2: HashMap<String, f64> = k1.iter()
-                .map(|(name, &d)| (name.clone(), a21 * d * h))
-                .collect();
-            let state2 = self.apply_stock_increments(state, &inc2);
-            let (_, flows2) = self.evaluate_system(model, &state2, t + h / 5.0)?;
-            let k2 = self.compute_derivatives(model, &flows2)?;
+            let stocks2 = offset_stocks(&state.stocks, &[(a21, &k1)], h);
+            let (_, flows2) = evaluate_stage(model, state, &stocks2, t + h / 5.0)?;
+            let k2 = stock_rates(model, &state.stocks, &flows2)?;
 
             // Stage 3
-            let inc3: HashMap<String, f64> = k1.iter()
-                .map(|(name, &d1)| {
-                    let d2 = k2.get(name).unwrap_or(&0.0);
-                    (name.clone(), (a31 * d1 + a32 * d2) * h)
-                })
-                .collect();
-            let state3 = self.apply_stock_increments(state, &inc3);
-            let (_, flows3) = self.evaluate_system(model, &state3, t + 3.0 * h / 10.0)?;
-            let k3 = self.compute_derivatives(model, &flows3)?;
+            let stocks3 = offset_stocks(&state.stocks, &[(a31, &k1), (a32, &k2)], h);
+            let (_, flows3) = evaluate_stage(model, state, &stocks3, t + 3.0 * h / 10.0)?;
+            let k3 = stock_rates(model, &state.stocks, &flows3)?;
 
             // Stage 4
-            let inc4: HashMap<String, f64> = k1.iter()
-                .map(|(name, &d1)| {
-                    let d2 = k2.get(name).unwrap_or(&0.0);
-                    let d3 = k3.get(name).unwrap_or(&0.0);
-                    (name.clone(), (a41 * d1 + a42 * d2 + a43 * d3) * h)
-                })
-                .collect();
-            let state4 = self.apply_stock_increments(state, &inc4);
-            let (_, flows4) = self.evaluate_system(model, &state4, t + 4.0 * h / 5.0)?;
-            let k4 = self.compute_derivatives(model, &flows4)?;
+            let stocks4 = offset_stocks(&state.stocks, &[(a41, &k1), (a42, &k2), (a43, &k3)], h);
+            let (_, flows4) = evaluate_stage(model, state, &stocks4, t + 4.0 * h / 5.0)?;
+            let k4 = stock_rates(model, &state.stocks, &flows4)?;
 
             // Stage 5
-            let inc5: HashMap<String, f64> = k1.iter()
-                .map(|(name, &d1)| {
-                    let d2 = k2.get(name).unwrap_or(&0.0);
-                    let d3 = k3.get(name).unwrap_or(&0.0);
-                    let d4 = k4.get(name).unwrap_or(&0.0);
-                    (name.clone(), (a51 * d1 + a52 * d2 + a53 * d3 + a54 * d4) * h)
-                })
-                .collect();
-            let state5 = self.apply_stock_increments(state, &inc5);
-            let (_, flows5) = self.evaluate_system(model, &state5, t + 8.0 * h / 9.0)?;
-            let k5 = self.compute_derivatives(model, &flows5)?;
+            let stocks5 = offset_stocks(&state.stocks, &[(a51, &k1), (a52, &k2), (a53, &k3), (a54, &k4)], h);
+            let (_, flows5) = evaluate_stage(model, state, &stocks5, t + 8.0 * h / 9.0)?;
+            let k5 = stock_rates(model, &state.stocks, &flows5)?;
 
             // Stage 6
-            let inc6: HashMap<String, f64> = k1.iter()
-                .map(|(name, &d1)| {
-                    let d2 = k2.get(name).unwrap_or(&0.0);
-                    let d3 = k3.get(name).unwrap_or(&0.0);
-                    let d4 = k4.get(name).unwrap_or(&0.0);
-                    let d5 = k5.get(name).unwrap_or(&0.0);
-                    (name.clone(), (a61 * d1 + a62 * d2 + a63 * d3 + a64 * d4 + a65 * d5) * h)
-                })
-                .collect();
-            let state6 = self.apply_stock_increments(state, &inc6);
-            let (_, flows6) = self.evaluate_system(model, &state6, t + h)?;
-            let k6 = self.compute_derivatives(model, &flows6)?;
+            let stocks6 = offset_stocks(&state.stocks, &[(a61, &k1), (a62, &k2), (a63, &k3), (a64, &k4), (a65, &k5)], h);
+            let (_, flows6) = evaluate_stage(model, state, &stocks6, t + h)?;
+            let k6 = stock_rates(model, &state.stocks, &flows6)?;
 
             // Stage 7
-            let inc7: HashMap<String, f64> = k1.iter()
-                .map(|(name, &d1)| {
-                    let d3 = k3.get(name).unwrap_or(&0.0);
-                    let d4 = k4.get(name).unwrap_or(&0.0);
-                    let d5 = k5.get(name).unwrap_or(&0.0);
-                    let d6 = k6.get(name).unwrap_or(&0.0);
-                    (name.clone(), (a71 * d1 + a73 * d3 + a74 * d4 + a75 * d5 + a76 * d6) * h)
-                })
-                .collect();
-            let state7 = self.apply_stock_increments(state, &inc7);
-            let (aux7, flows7) = self.evaluate_system(model, &state7, t + h)?;
-            let k7 = self.compute_derivatives(model, &flows7)?;
-
-            // Compute 5th order solution
-            let mut y5 = HashMap::new();
-            for (name, &y0) in &state.stocks {
-                let d1 = k1.get(name).unwrap_or(&0.0);
-                let d3 = k3.get(name).unwrap_or(&0.0);
-                let d4 = k4.get(name).unwrap_or(&0.0);
-                let d5 = k5.get(name).unwrap_or(&0.0);
-                let d6 = k6.get(name).unwrap_or(&0.0);
-                let val = y0 + (b1 * d1 + b3 * d3 + b4 * d4 + b5 * d5 + b6 * d6) * h;
-                y5.insert(name.clone(), val);
-            }
-
-            // Compute 4th order solution for error estimation
-            let mut y4 = HashMap::new();
-            for (name, &y0) in &state.stocks {
-                let d1 = k1.get(name).unwrap_or(&0.0);
-                let d3 = k3.get(name).unwrap_or(&0.0);
-                let d4 = k4.get(name).unwrap_or(&0.0);
-                let d5 = k5.get(name).unwrap_or(&0.0);
-                let d6 = k6.get(name).unwrap_or(&0.0);
-                let d7 = k7.get(name).unwrap_or(&0.0);
-                let val = y0 + (b1_star * d1 + b3_star * d3 + b4_star * d4 + b5_star * d5 + b6_star * d6 + b7_star * d7) * h;
-                y4.insert(name.clone(), val);
-            }
+            let stocks7 = offset_stocks(&state.stocks, &[(a71, &k1), (a73, &k3), (a74, &k4), (a75, &k5), (a76, &k6)], h);
+            let (aux7, flows7) = evaluate_stage(model, state, &stocks7, t + h)?;
+            let k7 = stock_rates(model, &state.stocks, &flows7)?;
+
+            // 5th order solution, and the 4th order one for error estimation
+            let y5 = offset_stocks(&state.stocks, &[(b1, &k1), (b3, &k3), (b4, &k4), (b5, &k5), (b6, &k6)], h);
+            let y4 = offset_stocks(
+                &state.stocks,
+                &[(b1_star, &k1), (b3_star, &k3), (b4_star, &k4), (b5_star, &k5), (b6_star, &k6), (b7_star, &k7)],
+                h,
+            );
 
             // Check error and adjust step size
             let (error, new_h) = self.compute_error_and_step(&y4, &y5, h);
 
             if error <= 1.0 {
-                // Accept the step
-                let mut new_state = state.clone();
+                // Accept the step, applying constraints to the 5th order solution
+                let mut new_state = base;
+                new_state.stocks = y5;
                 new_state.time = t + h;
-
-                // Apply constraints and use 5th order solution
-                for (stock_name, &new_value) in &y5 {
-                    let constrained_value = if let Some(stock) = model.stocks.get(stock_name) {
-                        let mut value = new_value;
-                        if stock.non_negative {
-                            value = value.max(0.0);
-                        }
-                        if let Some(max_val) = stock.max_value {
-                            value = value.min(max_val);
-                        }
-                        value
-                    } else {
-                        new_value
-                    };
-
-                    new_state.stocks.insert(stock_name.clone(), constrained_value);
-                }
-
+                constrain_stocks(model, &mut new_state.stocks);
                 new_state.auxiliaries = aux7;
                 new_state.flows = flows7;
 
-                return Ok(new_state);
+                return Ok(AdaptiveStep {
+                    state: new_state,
+                    start_derivatives: k1,
+                    end_derivatives: k7,
+                    next_step: new_h,
+                    rejected: attempt,
+                });
             } else {
                 // Reject and retry with smaller step
                 h = new_h;
@@ -1094,12 +795,12 @@ impl Integrator for RK45Integrator {
                     return Err(format!(
                         "Step size ({}) below minimum ({}). Error: {}",
                         h, self.min_step, error
-                    ));
+                    ).into());
                 }
             }
         }
 
-        Err("RK45 failed to converge after maximum attempts".to_string())
+        Err("RK45 failed to converge after maximum attempts".to_string().into())
     }
 }
 
@@ -1159,6 +860,69 @@ mod tests {
         assert!(new_state.stocks.get("Population").unwrap() > &110.0);
     }
 
+    #[test]
+    fn test_evaluate_into_scratch() {
+        let mut model = Model::new("Growth");
+        model.add_stock(Stock::new("Population", "100").with_inflows(vec!["growth".to_string()])).unwrap();
+        model.add_parameter(Parameter::new("growth_rate", 0.1)).unwrap();
+        model.add_auxiliary(crate::model::Auxiliary::new("noisy_rate", "growth_rate + RANDOM() * 0")).unwrap();
+        model.add_flow(Flow::new("growth", "DELAY1(Population * noisy_rate, 2)")).unwrap();
+        model.time.seed = Some(1);
+
+        let state = SimulationState::initialize_from_model(&model).unwrap();
+        let mut stocks = state.stocks.clone();
+        stocks.insert("Population", 200.0);
+
+        // A stage reads the stage's stocks and leaves the state it borrows alone
+        let mut scratch = EvaluationScratch::new(&state);
+        evaluate_into(&model, &state, &stocks, &mut scratch, 0.0).unwrap();
+        assert_eq!(scratch.auxiliaries["noisy_rate"], 0.1);
+        assert_eq!(scratch.flows["growth"], 20.0);
+        assert_eq!(state.flows["growth"], 0.0);
+        assert!(state.delays.exponential_delays.is_empty());
+        assert_eq!(scratch.delays.exponential_delays.len(), 1);
+
+        // In place, the same pass writes into the state itself
+        let mut in_place = state.clone();
+        in_place.stocks = stocks;
+        evaluate_system(&model, &mut in_place, 0.0).unwrap();
+        assert_eq!(in_place.flows, scratch.flows);
+        assert_eq!(in_place.delays.exponential_delays.len(), 1);
+    }
+
+    #[test]
+    fn test_state_kept_per_call_site() {
+        let mut model = Model::new("Sites");
+        model.add_parameter(Parameter::new("target", 10.0)).unwrap();
+        for name in ["a", "b"] {
+            model.add_auxiliary(crate::model::Auxiliary::new(name, "SMOOTH(target, 2, 0)")).unwrap();
+        }
+        for name in ["walk_a", "walk_b"] {
+            model.add_auxiliary(crate::model::Auxiliary::new(name, "RANDOM_WALK(0, 1)")).unwrap();
+        }
+        model.add_auxiliary(crate::model::Auxiliary::new("both", "SMOOTH(target, 2, 0) + SMOOTH(target, 4, 0)")).unwrap();
+        model.time.seed = Some(1);
+        // Numbers the call sites
+        model.compile().unwrap();
+
+        let mut state = SimulationState::initialize_from_model(&model).unwrap();
+        for _ in 0..3 {
+            state = EulerIntegrator.step(&model, &state, 1.0).unwrap();
+            state.delays.advance(state.time);
+        }
+
+        // Identical calls in different variables each keep their own state;
+        // the last evaluation, at time 2, reads 10 * (1 - e^-1)
+        assert!(state.delays.exponential_delays.contains_key("a#0"));
+        assert!(state.delays.exponential_delays.contains_key("b#0"));
+        assert!(state.delays.exponential_delays.contains_key("both#0"));
+        assert!(state.delays.exponential_delays.contains_key("both#1"));
+        let expected = 10.0 * (1.0 - (-1.0f64).exp());
+        assert!((state.auxiliaries["a"] - expected).abs() < 1e-12);
+        assert!((state.auxiliaries["b"] - expected).abs() < 1e-12);
+        assert_ne!(state.auxiliaries["walk_a"], state.auxiliaries["walk_b"]);
+    }
+
     #[test]
     fn test_backward_euler_growth() {
         let mut model = Model::new("Growth");
@@ -1227,4 +991,73 @@ mod tests {
         // RK4 should be most accurate
         assert!(state_rk4.stocks.get("X").unwrap() > &1.0);
     }
+
+    #[test]
+    fn test_bdf_stiff_decay() {
+        // dX/dt = -1000 X with dt = 0.1: explicit methods and fixed-point
+        // backward Euler blow up, TR-BDF2 decays monotonically toward zero
+        let mut model = Model::new("Stiff");
+        model.time.dt = 0.1;
+
+        model.add_stock(Stock::new("X", "1.0")).unwrap();
+        model.add_parameter(Parameter::new("k", 1000.0)).unwrap();
+        model.add_flow(Flow::new("decay", "k * X")).unwrap();
+        model.stocks.get_mut("X").unwrap().outflows.push("decay".to_string());
+
+        let integrator = BdfIntegrator::default();
+        let mut state = SimulationState::initialize_from_model(&model).unwrap();
+        for _ in 0..10 {
+            let previous = state.stocks["X"].abs();
+            state = integrator.step(&model, &state, 0.1).unwrap();
+            assert!(state.stocks["X"].abs() < previous);
+        }
+        assert!(state.stocks["X"].abs() < 1e-6);
+
+        let euler = EulerIntegrator.step(&model, &SimulationState::initialize_from_model(&model).unwrap(), 0.1).unwrap();
+        assert!(euler.stocks["X"].abs() > 1.0);
+    }
+
+    #[test]
+    fn test_bdf_second_order_growth() {
+        let mut model = Model::new("Growth");
+        model.add_stock(Stock::new("X", "1.0")).unwrap();
+        model.add_flow(Flow::new("f", "X")).unwrap();
+        model.stocks.get_mut("X").unwrap().inflows.push("f".to_string());
+
+        let state = SimulationState::initialize_from_model(&model).unwrap();
+        let bdf = BdfIntegrator::default().step(&model, &state, 0.1).unwrap();
+        let euler = EulerIntegrator.step(&model, &state, 0.1).unwrap();
+
+        let exact = 0.1_f64.exp();
+        let bdf_error = (bdf.stocks["X"] - exact).abs();
+        assert!(bdf_error < 1e-3);
+        assert!(bdf_error < (euler.stocks["X"] - exact).abs());
+    }
+
+    #[test]
+    fn test_uniflow_clamped_by_every_integrator() {
+        // Demand exceeds the supply stock's level, so `shipments` would turn
+        // negative and pull material back unless it is a uniflow
+        let mut model = Model::new("Uniflow");
+        model.add_stock(Stock::new("Supply", "1").with_outflows(vec!["shipments".to_string()])).unwrap();
+        model.add_flow(Flow::new("shipments", "Supply - 2").with_non_negative(true)).unwrap();
+        model.add_flow(Flow::new("returns", "Supply - 2")).unwrap();
+        model.compile().unwrap();
+
+        let integrators: Vec<Box<dyn Integrator>> = vec![
+            Box::new(EulerIntegrator),
+            Box::new(RK4Integrator),
+            Box::new(RK45Integrator::default()),
+            Box::new(HeunIntegrator),
+            Box::new(BackwardEulerIntegrator::default()),
+            Box::new(BdfIntegrator::default()),
+        ];
+        for integrator in integrators {
+            let state = SimulationState::initialize_from_model(&model).unwrap();
+            let next = integrator.step(&model, &state, 0.5).unwrap();
+            assert!((next.stocks["Supply"] - 1.0).abs() < 1e-9);
+            assert_eq!(next.flows["shipments"], 0.0);
+            assert!(next.flows["returns"] < 0.0);
+        }
+    }
 }
@@ -1,8 +1,14 @@
 /// Lookup table (graphical functions) support
 ///
-/// Provides LOOKUP and WITH_LOOKUP functions for nonlinear relationships
+/// Provides LOOKUP and WITH_LOOKUP functions for nonlinear relationships.
+/// Each table interpolates linearly, in steps or along a cubic spline, and
+/// beyond its points holds the end values, extends the end segments or fails.
+/// LOOKUP2D reads response surfaces of two inputs, such as production as a
+/// function of labor and capital, interpolating bilinearly on a grid.
 
 use serde::{Deserialize, Serialize};
+use crate::model::{Extrapolation, Interpolation};
+use crate::model::data::interpolate;
 
 /// A lookup table / graphical function
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -10,6 +16,15 @@ pub struct LookupTable {
     pub name: String,
     /// Data points as (x, y) pairs, must be sorted by x
     pub points: Vec<(f64, f64)>,
+    /// Value between points
+    #[serde(default)]
+    pub interpolation: Interpolation,
+    /// Value before the first point or after the last
+    #[serde(default)]
+    pub extrapolation: Extrapolation,
+    /// Bounds results are clamped to, as the y scale of an XMILE graphical function
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub y_range: Option<(f64, f64)>,
 }
 
 impl LookupTable {
@@ -25,41 +40,100 @@ impl LookupTable {
             }
         }
 
-        Ok(Self { name, points })
+        Ok(Self {
+            name,
+            points,
+            interpolation: Interpolation::default(),
+            extrapolation: Extrapolation::default(),
+            y_range: None,
+        })
     }
 
-    /// Lookup a value with linear interpolation
-    /// Extrapolates flat (constant) outside the range
-    pub fn lookup(&self, x: f64) -> f64 {
-        if self.points.is_empty() {
-            return 0.0;
-        }
+    pub fn with_interpolation(mut self, interpolation: Interpolation) -> Self {
+        self.interpolation = interpolation;
+        self
+    }
 
-        // Before first point - return first y value
-        if x <= self.points[0].0 {
-            return self.points[0].1;
-        }
+    pub fn with_extrapolation(mut self, extrapolation: Extrapolation) -> Self {
+        self.extrapolation = extrapolation;
+        self
+    }
 
-        // After last point - return last y value
-        if x >= self.points[self.points.len() - 1].0 {
-            return self.points[self.points.len() - 1].1;
+    pub fn with_y_range(mut self, min: f64, max: f64) -> Self {
+        self.y_range = Some((min, max));
+        self
+    }
+
+    /// Lookup a value, interpolated and extrapolated as the table says
+    /// (linearly, and flat outside the range, by default)
+    pub fn lookup(&self, x: f64) -> Result<f64, String> {
+        if self.interpolation == Interpolation::Spline && self.points.windows(2).any(|w| w[0].0 == w[1].0) {
+            return Err(format!("Lookup table '{}' has two points at one x, so cannot be a spline", self.name));
         }
+        if x.is_nan() {
+            return Err(format!("Lookup table '{}' read at an input that is not a number", self.name));
+        }
+        let y = interpolate(&self.points, x, self.interpolation, self.extrapolation).ok_or_else(|| {
+            let (first, last) = (self.points[0].0, self.points[self.points.len() - 1].0);
+            format!("{} is outside lookup table '{}' ({} to {})", x, self.name, first, last)
+        })?;
+        Ok(match self.y_range {
+            Some((min, max)) => y.max(min).min(max),
+            None => y,
+        })
+    }
+}
 
-        // Find the two points to interpolate between
-        for i in 1..self.points.len() {
-            if x <= self.points[i].0 {
-                // Interpolate between i-1 and i
-                let (x1, y1) = self.points[i - 1];
-                let (x2, y2) = self.points[i];
+/// A two-dimensional lookup table: values on a grid of x and y points
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LookupTable2D {
+    pub name: String,
+    /// Grid x values, sorted
+    pub x: Vec<f64>,
+    /// Grid y values, sorted
+    pub y: Vec<f64>,
+    /// One row per x value, holding the value at each y
+    pub values: Vec<Vec<f64>>,
+}
+
+/// The grid points either side of `v` on `axis`, and how far `v` is between
+/// them; points beyond the axis take its end
+fn grid_cell(axis: &[f64], v: f64) -> (usize, usize, f64) {
+    let next = axis.partition_point(|p| *p <= v);
+    if next == 0 {
+        (0, 0, 0.0)
+    } else if next == axis.len() {
+        (next - 1, next - 1, 0.0)
+    } else {
+        (next - 1, next, (v - axis[next - 1]) / (axis[next] - axis[next - 1]))
+    }
+}
 
-                // Linear interpolation
-                let alpha = (x - x1) / (x2 - x1);
-                return y1 + alpha * (y2 - y1);
+impl LookupTable2D {
+    pub fn new(name: String, x: Vec<f64>, y: Vec<f64>, values: Vec<Vec<f64>>) -> Result<Self, String> {
+        if x.is_empty() || y.is_empty() {
+            return Err(format!("Lookup table '{}' must have at least one x and one y value", name));
+        }
+        for (axis, points) in [("x", &x), ("y", &y)] {
+            if points.windows(2).any(|w| w[0] >= w[1]) {
+                return Err(format!("Lookup table '{}' {} values must be increasing", name, axis));
             }
         }
+        if values.len() != x.len() || values.iter().any(|row| row.len() != y.len()) {
+            return Err(format!(
+                "Lookup table '{}' needs {} rows of {} values, one row per x value",
+                name, x.len(), y.len()
+            ));
+        }
+        Ok(Self { name, x, y, values })
+    }
 
-        // Should never reach here
-        self.points[self.points.len() - 1].1
+    /// Lookup a value, interpolated bilinearly and held at the grid's edges
+    pub fn lookup(&self, x: f64, y: f64) -> f64 {
+        let (i0, i1, tx) = grid_cell(&self.x, x);
+        let (j0, j1, ty) = grid_cell(&self.y, y);
+        let along_y = |i: usize| self.values[i][j0] + ty * (self.values[i][j1] - self.values[i][j0]);
+        along_y(i0) + tx * (along_y(i1) - along_y(i0))
     }
 }
 
@@ -76,16 +150,66 @@ mod tests {
         .unwrap();
 
         // Exact points
-        assert_eq!(table.lookup(0.0), 0.0);
-        assert_eq!(table.lookup(1.0), 10.0);
-        assert_eq!(table.lookup(2.0), 5.0);
+        assert_eq!(table.lookup(0.0).unwrap(), 0.0);
+        assert_eq!(table.lookup(1.0).unwrap(), 10.0);
+        assert_eq!(table.lookup(2.0).unwrap(), 5.0);
 
         // Interpolation
-        assert_eq!(table.lookup(0.5), 5.0);
-        assert_eq!(table.lookup(1.5), 7.5);
+        assert_eq!(table.lookup(0.5).unwrap(), 5.0);
+        assert_eq!(table.lookup(1.5).unwrap(), 7.5);
 
         // Extrapolation
-        assert_eq!(table.lookup(-1.0), 0.0);
-        assert_eq!(table.lookup(3.0), 5.0);
+        assert_eq!(table.lookup(-1.0).unwrap(), 0.0);
+        assert_eq!(table.lookup(3.0).unwrap(), 5.0);
+
+        // NaN has no place in the table
+        assert!(table.lookup(f64::NAN).unwrap_err().contains("not a number"));
+    }
+
+    #[test]
+    fn test_lookup_modes() {
+        let points = vec![(0.0, 0.0), (1.0, 10.0), (2.0, 5.0)];
+        let step = LookupTable::new("step".to_string(), points.clone()).unwrap().with_interpolation(Interpolation::Step);
+        assert_eq!(step.lookup(0.9).unwrap(), 0.0);
+        assert_eq!(step.lookup(1.0).unwrap(), 10.0);
+
+        let extended = LookupTable::new("extended".to_string(), points.clone()).unwrap()
+            .with_extrapolation(Extrapolation::Linear);
+        assert_eq!(extended.lookup(3.0).unwrap(), 0.0);
+        assert_eq!(extended.with_y_range(2.0, 8.0).lookup(3.0).unwrap(), 2.0);
+
+        let strict = LookupTable::new("strict".to_string(), points).unwrap().with_extrapolation(Extrapolation::Error);
+        assert!(strict.lookup(-0.5).unwrap_err().contains("outside lookup table 'strict'"));
+
+        // Natural spline through (0,0), (1,10), (2,5): curvature -22.5 at x = 1
+        let spline = LookupTable::new("spline".to_string(), vec![(0.0, 0.0), (1.0, 10.0), (2.0, 5.0)]).unwrap()
+            .with_interpolation(Interpolation::Spline);
+        assert_eq!(spline.lookup(1.0).unwrap(), 10.0);
+        assert!((spline.lookup(0.5).unwrap() - 6.40625).abs() < 1e-12);
+        assert!((spline.lookup(1.5).unwrap() - 8.90625).abs() < 1e-12);
+        assert_eq!(spline.lookup(3.0).unwrap(), 5.0);
+    }
+
+    #[test]
+    fn test_lookup_2d() {
+        // Production at labor 0, 10 and capital 0, 100, 200
+        let table = LookupTable2D::new(
+            "production".to_string(),
+            vec![0.0, 10.0],
+            vec![0.0, 100.0, 200.0],
+            vec![vec![0.0, 0.0, 0.0], vec![0.0, 50.0, 80.0]],
+        )
+        .unwrap();
+
+        assert_eq!(table.lookup(10.0, 100.0), 50.0);
+        assert_eq!(table.lookup(5.0, 100.0), 25.0);
+        assert_eq!(table.lookup(10.0, 150.0), 65.0);
+        assert_eq!(table.lookup(5.0, 150.0), 32.5);
+        // Held at the edges
+        assert_eq!(table.lookup(20.0, 300.0), 80.0);
+        assert_eq!(table.lookup(-1.0, 100.0), 0.0);
+
+        assert!(LookupTable2D::new("bad".to_string(), vec![0.0, 1.0], vec![0.0], vec![vec![1.0]]).is_err());
+        assert!(LookupTable2D::new("bad".to_string(), vec![1.0, 0.0], vec![0.0], vec![vec![1.0], vec![2.0]]).is_err());
     }
 }
@@ -1,6 +1,8 @@
 /// Simulation module - executes model simulations
 
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use crate::analysis::ElementType;
+use crate::error::ModelError;
 use crate::model::Model;
 
 pub mod engine;
@@ -12,62 +14,115 @@ pub mod stochastic;
 pub mod noise;
 pub mod abm;
 pub mod agent_sd_bridge;
+pub mod hybrid;
+pub mod agent_output;
+#[cfg(feature = "server")]
+pub mod distributed;
+pub mod checkpoint;
+pub mod conveyor;
+pub mod des;
+pub mod events;
+pub mod constraints;
+pub mod conservation;
+pub mod guard;
+pub mod progress;
+pub mod observer;
+pub mod values;
+pub mod results;
 
 pub use engine::SimulationEngine;
-pub use integrator::{Integrator, EulerIntegrator, RK4Integrator, HeunIntegrator, BackwardEulerIntegrator, RK45Integrator};
+pub use checkpoint::CheckpointConfig;
+pub use integrator::{Integrator, EulerIntegrator, RK4Integrator, HeunIntegrator, BackwardEulerIntegrator, BdfIntegrator, RK45Integrator, AdaptiveStep};
 pub use arrayvalue::{ArrayValue, ArraySimulationState};
 pub use delay::DelayManager;
-pub use lookup::LookupTable;
+pub use conveyor::ConveyorManager;
+pub use des::DesManager;
+pub use events::EventManager;
+pub use constraints::{ConstraintMonitor, ConstraintSummary, ConstraintViolation};
+pub use conservation::{ConservationAudit, GroupBalance};
+pub use progress::{CancellationToken, Progress, ProgressCallback, ProgressTracker};
+pub use observer::SimulationObserver;
+pub use values::VariableValues;
+pub use results::{ResultColumns, SimulationResults};
+pub use lookup::{LookupTable, LookupTable2D};
 pub use stochastic::StochasticManager;
-pub use abm::{AgentManager, AgentType, AgentState, AgentRule};
+pub use abm::{AgentManager, AgentType, AgentState, AgentRule, AttributeDistribution, UpdateOrder, StateMachine, StateTransition, TransitionChance};
+pub use agent_output::{AgentRecorder, AgentSnapshots, AttributeHistogram};
 pub use agent_sd_bridge::{AgentSDBridge, AgentSDConfig, AgentCoupling, SpatialAgent, AgentNetwork};
 
 /// Simulation state at a point in time
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SimulationState {
     pub time: f64,
-    pub stocks: HashMap<String, f64>,
-    pub flows: HashMap<String, f64>,
-    pub auxiliaries: HashMap<String, f64>,
+    pub stocks: VariableValues,
+    pub flows: VariableValues,
+    pub auxiliaries: VariableValues,
     pub delays: DelayManager,
     pub stochastic: StochasticManager,
     pub agents: AgentManager,
+    /// Contents of conveyor and oven stocks
+    #[serde(default)]
+    pub conveyors: ConveyorManager,
+    /// Entities of the discrete-event layer
+    #[serde(default)]
+    pub des: DesManager,
+    /// Events fired so far and the parameter values they set
+    #[serde(default)]
+    pub events: EventManager,
+    /// Checks and failures of the model's constraints so far
+    #[serde(default)]
+    pub constraints: ConstraintMonitor,
 }
 
 impl SimulationState {
     pub fn new() -> Self {
         Self {
             time: 0.0,
-            stocks: HashMap::new(),
-            flows: HashMap::new(),
-            auxiliaries: HashMap::new(),
+            stocks: VariableValues::new(),
+            flows: VariableValues::new(),
+            auxiliaries: VariableValues::new(),
             delays: DelayManager::new(),
             stochastic: StochasticManager::new(),
             agents: AgentManager::new(),
+            conveyors: ConveyorManager::default(),
+            des: DesManager::default(),
+            events: EventManager::default(),
+            constraints: ConstraintMonitor::default(),
         }
     }
 
-    pub fn initialize_from_model(model: &Model) -> Result<Self, String> {
+    pub fn initialize_from_model(model: &Model) -> Result<Self, ModelError> {
         let mut state = Self::new();
         state.time = model.time.start;
+        if let Some(seed) = model.time.seed {
+            state.stochastic = StochasticManager::with_seed(seed);
+        }
 
-        // Initialize stocks with their initial values
-        for (name, stock) in &model.stocks {
-            // Need to clone state for evaluation since evaluate requires &mut
-            let mut temp_state = state.clone();
-            let mut context = crate::model::expression::EvaluationContext::new(
-                model,
-                &mut temp_state,
-                model.time.start,
-            );
-            let initial_value = stock.initial.evaluate(&mut context)?;
-            state.stocks.insert(name.clone(), initial_value);
-            // Merge back any state changes (though initial values shouldn't have delays/random/agents)
-            state.delays = temp_state.delays;
-            state.stochastic = temp_state.stochastic;
-            state.agents = temp_state.agents;
+        // Initial values may use other stocks and auxiliaries, so evaluate
+        // stocks and whatever their initial values need in dependency order
+        for node in crate::analysis::DependencyGraph::initialization_order(model)? {
+            let expr = match node.element_type {
+                ElementType::Stock => &model.stocks[&node.name].initial,
+                ElementType::Flow => &model.flows[&node.name].equation,
+                _ => &model.auxiliaries[&node.name].equation,
+            };
+            let mut context = crate::model::expression::EvaluationContext::new(model, &mut state, model.time.start);
+            let value = expr.evaluate(&mut context)
+                .map_err(|message| ModelError::Initialization { variable: node.name.clone(), message })?;
+            let values = match node.element_type {
+                ElementType::Stock => &mut state.stocks,
+                ElementType::Flow => &mut state.flows,
+                _ => &mut state.auxiliaries,
+            };
+            values.insert(node.name, value);
         }
 
+        state.conveyors = ConveyorManager::from_model(model, &mut state)?;
+        state.des = DesManager::from_model(model, &mut state.stochastic)?;
+        state.events = EventManager::from_model(model)?;
+        state.constraints = ConstraintMonitor::from_model(model);
+        hybrid::initialize(model, &mut state)?;
+
         // Initialize flows to zero
         for name in model.flows.keys() {
             state.flows.insert(name.clone(), 0.0);
@@ -78,6 +133,9 @@ impl SimulationState {
             state.auxiliaries.insert(name.clone(), 0.0);
         }
 
+        // Events scheduled for the start time, or whose condition already holds
+        events::fire_due(model, &mut state)?;
+
         Ok(state)
     }
 }
@@ -88,11 +146,89 @@ impl Default for SimulationState {
     }
 }
 
+/// What one pass over the auxiliaries and flows writes: their values, and
+/// the delays, random streams and conveyors the equations advance
+///
+/// Integrator stages evaluate into a copy of these so the state they read
+/// is left untouched; the stocks, agents, entities and events are only borrowed.
+#[derive(Debug, Clone)]
+pub struct EvaluationScratch {
+    pub auxiliaries: VariableValues,
+    pub flows: VariableValues,
+    pub delays: DelayManager,
+    pub stochastic: StochasticManager,
+    pub conveyors: ConveyorManager,
+}
+
+impl EvaluationScratch {
+    /// Copies of `state`'s auxiliaries, flows, delays, random streams and conveyors
+    pub fn new(state: &SimulationState) -> Self {
+        Self {
+            auxiliaries: state.auxiliaries.clone(),
+            flows: state.flows.clone(),
+            delays: state.delays.clone(),
+            stochastic: state.stochastic.clone(),
+            conveyors: state.conveyors.clone(),
+        }
+    }
+
+    /// Move those parts out of `state`, to be put back by [`Self::restore`]
+    pub fn take(state: &mut SimulationState) -> Self {
+        Self {
+            auxiliaries: std::mem::take(&mut state.auxiliaries),
+            flows: std::mem::take(&mut state.flows),
+            delays: std::mem::take(&mut state.delays),
+            // A seeded placeholder, which unlike new() does not read OS entropy
+            stochastic: std::mem::replace(&mut state.stochastic, StochasticManager::with_seed(0)),
+            conveyors: std::mem::take(&mut state.conveyors),
+        }
+    }
+
+    pub fn restore(self, state: &mut SimulationState) {
+        state.auxiliaries = self.auxiliaries;
+        state.flows = self.flows;
+        state.delays = self.delays;
+        state.stochastic = self.stochastic;
+        state.conveyors = self.conveyors;
+    }
+}
+
 /// Simulation configuration
 #[derive(Debug, Clone)]
 pub struct SimulationConfig {
     pub integration_method: IntegrationMethod,
+    /// Spacing of recorded output; overrides the model's `time.save_interval`
     pub output_interval: Option<f64>,
+    /// Variables to record; `None` records every stock, flow and auxiliary
+    pub output_variables: Option<Vec<String>>,
+    /// Save a checkpoint periodically while running
+    pub checkpoint: Option<CheckpointConfig>,
+    /// Relative error tolerance for adaptive integrators
+    pub rtol: f64,
+    /// Absolute error tolerance for adaptive integrators
+    pub atol: f64,
+    /// How stocks get their values at the start of the run
+    pub initialization: Initialization,
+    /// Threads to evaluate independent auxiliaries and flows on within each
+    /// step; `None` or 1 evaluates them in order. Worth it for very large
+    /// (typically arrayed) models, and gives the same results either way.
+    pub threads: Option<usize>,
+    /// Stop with a diagnostic when a stock, flow or auxiliary becomes NaN,
+    /// infinite or larger in magnitude than this; the default stops only on
+    /// NaN and infinity, and `None` never stops
+    pub max_magnitude: Option<f64>,
+    /// Audit the balance of the model's conserved stock groups at every step
+    pub audit_conservation: bool,
+}
+
+/// Starting point of a simulation run
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Initialization {
+    /// Stocks start at their initial value expressions
+    #[default]
+    InitialValues,
+    /// Stocks start at a steady state found from their initial values
+    Equilibrium,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -102,6 +238,25 @@ pub enum IntegrationMethod {
     RK45,
     Heun,
     BackwardEuler,
+    /// TR-BDF2 for stiff models
+    Bdf,
+}
+
+impl std::str::FromStr for IntegrationMethod {
+    type Err = String;
+
+    /// Parse a method by its command-line name, such as `rk4` or `backward-euler`
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_lowercase().as_str() {
+            "euler" => Ok(IntegrationMethod::Euler),
+            "rk4" => Ok(IntegrationMethod::RK4),
+            "rk45" => Ok(IntegrationMethod::RK45),
+            "heun" => Ok(IntegrationMethod::Heun),
+            "backward-euler" => Ok(IntegrationMethod::BackwardEuler),
+            "bdf" => Ok(IntegrationMethod::Bdf),
+            _ => Err(format!("Unknown integrator '{}'", name)),
+        }
+    }
 }
 
 impl Default for SimulationConfig {
@@ -109,51 +264,34 @@ impl Default for SimulationConfig {
         Self {
             integration_method: IntegrationMethod::Euler,
             output_interval: None,
+            output_variables: None,
+            checkpoint: None,
+            rtol: 1e-6,
+            atol: 1e-8,
+            initialization: Initialization::InitialValues,
+            threads: None,
+            max_magnitude: Some(f64::INFINITY),
+            audit_conservation: false,
         }
     }
 }
 
-/// Complete simulation results
-#[derive(Debug, Clone)]
-pub struct SimulationResults {
-    pub times: Vec<f64>,
-    pub states: Vec<SimulationState>,
+/// Step counts reported by adaptive integrators
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StepStatistics {
+    pub accepted: usize,
+    pub rejected: usize,
 }
 
-impl SimulationResults {
-    pub fn new() -> Self {
-        Self {
-            times: Vec::new(),
-            states: Vec::new(),
-        }
-    }
-
-    pub fn add_point(&mut self, time: f64, state: SimulationState) {
-        self.times.push(time);
-        self.states.push(state);
-    }
-
-    pub fn get_variable_series(&self, var_name: &str) -> Option<Vec<f64>> {
-        let mut series = Vec::new();
-
-        for state in &self.states {
-            if let Some(val) = state.stocks.get(var_name) {
-                series.push(*val);
-            } else if let Some(val) = state.flows.get(var_name) {
-                series.push(*val);
-            } else if let Some(val) = state.auxiliaries.get(var_name) {
-                series.push(*val);
-            } else {
-                return None;
-            }
-        }
-
-        Some(series)
-    }
-}
+/// Destination for recorded simulation points
+///
+/// The engine calls `record` once per output time with a borrowed state, so
+/// streaming sinks can write each point out without keeping history in memory.
+pub trait ResultSink {
+    fn record(&mut self, time: f64, state: &SimulationState) -> Result<(), String>;
 
-impl Default for SimulationResults {
-    fn default() -> Self {
-        Self::new()
+    /// Flush buffered output once the run has finished
+    fn finish(&mut self) -> Result<(), String> {
+        Ok(())
     }
 }
@@ -3,13 +3,15 @@
 /// Implements various noise types:
 /// - White noise: Uncorrelated Gaussian noise
 /// - Pink noise: 1/f noise with power spectral density inversely proportional to frequency
+/// - Noise processes: correlated noise and random walks that step with model time
 
 use rand::prelude::*;
-use rand_distr::{Distribution, Normal};
+use rand_distr::{Distribution, Normal, StandardNormal};
+use serde::{Deserialize, Serialize};
 
 /// White noise generator
 /// Generates uncorrelated Gaussian random values
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WhiteNoiseGenerator {
     /// Mean of the distribution
     mean: f64,
@@ -30,14 +32,14 @@ impl WhiteNoiseGenerator {
     }
 
     /// Generate next sample
-    pub fn sample(&self, rng: &mut StdRng) -> f64 {
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
         let normal = Normal::new(self.mean, self.std_dev)
             .unwrap_or_else(|_| Normal::new(0.0, 1.0).unwrap());
         normal.sample(rng)
     }
 
     /// Generate samples scaled by time step
-    pub fn sample_dt(&self, rng: &mut StdRng, dt: f64) -> f64 {
+    pub fn sample_dt<R: Rng + ?Sized>(&self, rng: &mut R, dt: f64) -> f64 {
         // Scale by sqrt(dt) to maintain correct variance
         let scale = (dt * self.sample_rate).sqrt();
         let normal = Normal::new(0.0, self.std_dev * scale)
@@ -48,7 +50,7 @@ impl WhiteNoiseGenerator {
 
 /// Pink noise generator using Voss-McCartney algorithm
 /// Generates 1/f noise (power spectral density inversely proportional to frequency)
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PinkNoiseGenerator {
     /// Number of octaves (more = better quality, typically 16)
     num_octaves: usize,
@@ -80,7 +82,7 @@ impl PinkNoiseGenerator {
     }
 
     /// Generate next pink noise sample
-    pub fn sample(&mut self, rng: &mut StdRng) -> f64 {
+    pub fn sample<R: Rng + ?Sized>(&mut self, rng: &mut R) -> f64 {
         let mut sum = 0.0;
 
         // Update white noise values based on counter
@@ -108,7 +110,7 @@ impl PinkNoiseGenerator {
 
 /// Improved pink noise generator using Paul Kellet's method
 /// Better spectral characteristics than Voss-McCartney
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PinkNoiseKellet {
     b0: f64,
     b1: f64,
@@ -137,7 +139,7 @@ impl PinkNoiseKellet {
     }
 
     /// Generate next sample using Paul Kellet's algorithm
-    pub fn sample(&mut self, rng: &mut StdRng) -> f64 {
+    pub fn sample<R: Rng + ?Sized>(&mut self, rng: &mut R) -> f64 {
         let white = rng.sample::<f64, _>(rand::distributions::Standard) * 2.0 - 1.0;
 
         self.b0 = 0.99886 * self.b0 + white * 0.0555179;
@@ -165,6 +167,56 @@ impl PinkNoiseKellet {
     }
 }
 
+/// Value of a noise process that advances once per time step, so evaluating
+/// an equation again at the same time reads the same value
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NoiseProcess {
+    /// Current value
+    pub value: f64,
+    /// Time of the last step
+    pub time: f64,
+}
+
+impl NoiseProcess {
+    pub fn new(value: f64, time: f64) -> Self {
+        Self { value, time }
+    }
+
+    /// Time elapsed since the last step when `time` is later, making `time` the last step
+    pub fn advance(&mut self, time: f64) -> Option<f64> {
+        if time <= self.time {
+            return None;
+        }
+        let dt = time - self.time;
+        self.time = time;
+        Some(dt)
+    }
+}
+
+/// Ornstein-Uhlenbeck (continuous AR(1)) step over `dt`
+///
+/// Uses the exact transition, so the process keeps a standard deviation of
+/// `std_dev` about `mean` and an autocorrelation of `exp(-lag / correlation_time)`
+/// whatever the time step. A correlation time of 0 draws independent values.
+pub fn reverting_step<R: Rng + ?Sized>(
+    rng: &mut R,
+    value: f64,
+    mean: f64,
+    std_dev: f64,
+    correlation_time: f64,
+    dt: f64,
+) -> f64 {
+    let decay = if correlation_time > 0.0 { (-dt / correlation_time).exp() } else { 0.0 };
+    let z: f64 = rng.sample(StandardNormal);
+    mean + (value - mean) * decay + std_dev * (1.0 - decay * decay).sqrt() * z
+}
+
+/// Brownian random walk step over `dt`, with `std_dev` the spread after one time unit
+pub fn random_walk_step<R: Rng + ?Sized>(rng: &mut R, value: f64, std_dev: f64, dt: f64) -> f64 {
+    let z: f64 = rng.sample(StandardNormal);
+    value + std_dev * dt.sqrt() * z
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -250,6 +302,31 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_reverting_process() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut process = NoiseProcess::new(10.0, 0.0);
+
+        // Stepping twice at the same time changes nothing
+        assert_eq!(process.advance(0.5), Some(0.5));
+        assert_eq!(process.advance(0.5), None);
+
+        // The process forgets its start and settles around the mean
+        let mut samples = Vec::new();
+        for _ in 0..5000 {
+            process.value = reverting_step(&mut rng, process.value, 0.0, 1.0, 2.0, 0.5);
+            samples.push(process.value);
+        }
+        let mean = samples[100..].iter().sum::<f64>() / 4900.0;
+        let variance = samples[100..].iter().map(|x| (x - mean).powi(2)).sum::<f64>() / 4900.0;
+        assert!(mean.abs() < 0.15, "mean {}", mean);
+        assert!((variance - 1.0).abs() < 0.15, "variance {}", variance);
+
+        // Successive values are correlated by exp(-dt / correlation_time)
+        let lag: f64 = samples[100..].windows(2).map(|w| (w[0] - mean) * (w[1] - mean)).sum::<f64>() / 4899.0 / variance;
+        assert!((lag - (-0.25f64).exp()).abs() < 0.05, "lag-one correlation {}", lag);
+    }
+
     #[test]
     fn test_white_noise_dt_scaling() {
         let mut rng = StdRng::seed_from_u64(42);
@@ -0,0 +1,39 @@
+/// Hooks into a running simulation
+///
+/// Observers registered with [`SimulationEngine::with_observer`] are called
+/// as the engine runs, so logging, live plotting, data assimilation or
+/// coupling to another model can be added without changing the engine.
+///
+/// [`SimulationEngine::with_observer`]: super::SimulationEngine::with_observer
+
+use crate::model::Model;
+use super::SimulationState;
+
+/// Callbacks made by [`super::SimulationEngine`]; every method does nothing by default
+///
+/// An error returned from any callback stops the run with that error.
+pub trait SimulationObserver: Send {
+    /// Once, when `run` or `run_into` starts, before the initial state is recorded
+    fn on_init(&mut self, _model: &Model, _state: &SimulationState) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Before each step, with the state the step starts from
+    fn on_step_start(&mut self, _state: &SimulationState) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// After each step; with fixed-step methods, before the new state is recorded
+    ///
+    /// Changes to stocks carry into the next step, which is how data
+    /// assimilation nudges a run towards observations. Flows and auxiliaries
+    /// are not recomputed until then.
+    fn on_step_end(&mut self, _state: &mut SimulationState) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Once, after `run` or `run_into` reaches the stop time
+    fn on_finish(&mut self, _state: &SimulationState) -> Result<(), String> {
+        Ok(())
+    }
+}
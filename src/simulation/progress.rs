@@ -0,0 +1,88 @@
+/// Progress reporting and cooperative cancellation
+///
+/// A [`ProgressTracker`] turns (steps, simulated time) into [`Progress`]
+/// reports, at most one per percent of the run so callbacks stay cheap. A
+/// [`CancellationToken`] is shared between the code running a simulation and
+/// whoever may want to stop it; the engine checks it after every step.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// How far a run has got
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    /// Steps taken so far
+    pub steps: usize,
+    /// Current simulated time
+    pub time: f64,
+    /// Fraction of the simulated time span covered, from 0 to 1
+    pub fraction: f64,
+    /// Wall-clock time since the run started
+    pub elapsed: Duration,
+    /// Estimated wall-clock time to finish, once any progress has been made
+    pub eta: Option<Duration>,
+}
+
+/// Called with each progress report
+pub type ProgressCallback = Box<dyn FnMut(&Progress) + Send>;
+
+/// Builds progress reports for a run from `start` to `stop`
+///
+/// The wall clock is read by [`start_clock`](Self::start_clock), or else for
+/// the first report, so runs nobody asks about never touch it; there is no
+/// clock on wasm32-unknown-unknown.
+#[derive(Debug, Clone)]
+pub struct ProgressTracker {
+    start: f64,
+    stop: f64,
+    started: Option<Instant>,
+    last_percent: Option<u32>,
+}
+
+impl ProgressTracker {
+    pub fn new(start: f64, stop: f64) -> Self {
+        Self { start, stop, started: None, last_percent: None }
+    }
+
+    /// Measure elapsed time from now
+    pub fn start_clock(&mut self) {
+        self.started = Some(Instant::now());
+    }
+
+    /// Report for the run having reached `time` after `steps` steps, or None
+    /// if the run is still within the percent last reported
+    pub fn update(&mut self, steps: usize, time: f64) -> Option<Progress> {
+        let span = self.stop - self.start;
+        let fraction = if span > 0.0 { ((time - self.start) / span).clamp(0.0, 1.0) } else { 1.0 };
+        // The tolerance keeps steps that land just short of a percent from being skipped
+        let percent = (fraction * 100.0 + 1e-6).floor() as u32;
+        if self.last_percent.is_some_and(|last| percent <= last) {
+            return None;
+        }
+        self.last_percent = Some(percent);
+        let fraction = if percent >= 100 { 1.0 } else { fraction };
+
+        let elapsed = self.started.get_or_insert_with(Instant::now).elapsed();
+        let eta = (fraction > 0.0).then(|| elapsed.mul_f64((1.0 - fraction) / fraction));
+        Some(Progress { steps, time, fraction, elapsed, eta })
+    }
+}
+
+/// Shared flag asking a running simulation to stop
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
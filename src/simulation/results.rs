@@ -0,0 +1,212 @@
+/// Recorded simulation output, stored by column
+///
+/// Each variable's values over a run are kept in one `Vec<f64>`, with the
+/// names held once per kind of variable instead of in every recorded state.
+/// Series are borrowed straight from their column, and recording a point
+/// copies its numbers without cloning delays, random streams or agents.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use crate::model::SymbolTable;
+use super::{ResultSink, SimulationState, VariableValues};
+
+/// Recorded values of one kind of variable, one column per name
+///
+/// Names are in sorted order, followed by any that first appeared partway
+/// through a run. Rows where a variable was not recorded hold NaN.
+#[derive(Debug, Clone, Default)]
+pub struct ResultColumns {
+    symbols: SymbolTable,
+    columns: Vec<Vec<f64>>,
+    /// Column of each value for the layout of the states last recorded
+    layout: Option<(Arc<SymbolTable>, Vec<usize>)>,
+}
+
+impl ResultColumns {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Names in column order
+    pub fn names(&self) -> &[String] {
+        self.symbols.names()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&[f64]> {
+        self.symbols.get(name).map(|index| self.columns[index].as_slice())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.symbols.get(name).is_some()
+    }
+
+    /// Number of variables
+    pub fn len(&self) -> usize {
+        self.columns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.columns.is_empty()
+    }
+
+    /// `(name, series)` pairs in column order
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &[f64])> + '_ {
+        self.symbols.names().iter().zip(self.columns.iter().map(Vec::as_slice))
+    }
+
+    /// Every variable's value at `row`
+    pub fn row(&self, row: usize) -> VariableValues {
+        self.iter().filter_map(|(name, series)| Some((name, *series.get(row)?))).collect()
+    }
+
+    /// Append `values` as the row after the `rows` already recorded
+    fn push(&mut self, rows: usize, values: &VariableValues) {
+        let current = self.layout.as_ref()
+            .is_some_and(|(symbols, _)| Arc::ptr_eq(symbols, values.symbols()));
+        if !current {
+            let mut names: Vec<&String> = values.keys().collect();
+            names.sort();
+            for name in names {
+                if self.symbols.get(name).is_none() {
+                    self.symbols.insert(name);
+                    self.columns.push(vec![f64::NAN; rows]);
+                }
+            }
+            let layout = values.keys().filter_map(|name| self.symbols.get(name)).collect();
+            self.layout = Some((values.symbols().clone(), layout));
+        }
+
+        for column in &mut self.columns {
+            column.push(f64::NAN);
+        }
+        let (_, layout) = self.layout.as_ref().expect("layout set above");
+        for (&index, &value) in layout.iter().zip(values.as_slice()) {
+            self.columns[index][rows] = value;
+        }
+    }
+}
+
+/// Complete simulation results
+#[derive(Debug, Clone, Default)]
+pub struct SimulationResults {
+    pub times: Vec<f64>,
+    pub stocks: ResultColumns,
+    pub flows: ResultColumns,
+    pub auxiliaries: ResultColumns,
+    /// The model's calendar, for writing times as dates
+    pub calendar: Option<crate::model::Calendar>,
+}
+
+impl SimulationResults {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_point(&mut self, time: f64, state: &SimulationState) {
+        let rows = self.times.len();
+        self.stocks.push(rows, &state.stocks);
+        self.flows.push(rows, &state.flows);
+        self.auxiliaries.push(rows, &state.auxiliaries);
+        self.times.push(time);
+    }
+
+    /// Number of recorded points
+    pub fn len(&self) -> usize {
+        self.times.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.times.is_empty()
+    }
+
+    /// Names of every recorded variable: stocks, then flows, then auxiliaries
+    pub fn variables(&self) -> impl Iterator<Item = &String> + '_ {
+        self.columns().map(|(name, _)| name)
+    }
+
+    /// `(name, series)` for every recorded variable, in the order of [`Self::variables`]
+    pub fn columns(&self) -> impl Iterator<Item = (&String, &[f64])> + '_ {
+        self.stocks.iter().chain(self.flows.iter()).chain(self.auxiliaries.iter())
+    }
+
+    /// Values of a variable over the run
+    pub fn series(&self, var_name: &str) -> Option<&[f64]> {
+        self.stocks.get(var_name)
+            .or_else(|| self.flows.get(var_name))
+            .or_else(|| self.auxiliaries.get(var_name))
+    }
+
+    pub fn get_variable_series(&self, var_name: &str) -> Option<Vec<f64>> {
+        self.series(var_name).map(<[f64]>::to_vec)
+    }
+
+    /// Value of a variable at the last recorded point
+    pub fn final_value(&self, var_name: &str) -> Option<f64> {
+        self.series(var_name)?.last().copied()
+    }
+
+    /// Every series keyed by variable name
+    pub fn to_map(&self) -> HashMap<String, Vec<f64>> {
+        self.columns().map(|(name, series)| (name.clone(), series.to_vec())).collect()
+    }
+
+    /// Stocks, flows and auxiliaries recorded at `row`, as a state at that time
+    ///
+    /// Only the recorded values are filled in; delays, random streams and
+    /// agents are left empty.
+    pub fn state_at(&self, row: usize) -> Option<SimulationState> {
+        let mut state = SimulationState::new();
+        state.time = *self.times.get(row)?;
+        state.stocks = self.stocks.row(row);
+        state.flows = self.flows.row(row);
+        state.auxiliaries = self.auxiliaries.row(row);
+        Some(state)
+    }
+
+    /// The last recorded point, as [`Self::state_at`] builds it
+    pub fn final_state(&self) -> Option<SimulationState> {
+        self.state_at(self.times.len().checked_sub(1)?)
+    }
+}
+
+impl ResultSink for SimulationResults {
+    fn record(&mut self, time: f64, state: &SimulationState) -> Result<(), String> {
+        self.add_point(time, state);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_columns_follow_recorded_states() {
+        let mut state = SimulationState::new();
+        state.stocks.insert("Population", 100.0);
+        state.stocks.insert("Capital", 5.0);
+        state.flows.insert("births", 3.0);
+
+        let mut results = SimulationResults::new();
+        results.add_point(0.0, &state);
+        state.stocks.insert("Population", 110.0);
+        results.add_point(1.0, &state);
+
+        // A variable first recorded partway through is NaN before then
+        state.auxiliaries.insert("density", 0.5);
+        results.add_point(2.0, &state);
+
+        assert_eq!(results.len(), 3);
+        assert_eq!(results.stocks.names(), ["Capital", "Population"]);
+        assert_eq!(results.variables().collect::<Vec<_>>(), ["Capital", "Population", "births", "density"]);
+        assert_eq!(results.series("Population"), Some(&[100.0, 110.0, 110.0][..]));
+        assert_eq!(results.final_value("births"), Some(3.0));
+        assert!(results.series("density").unwrap()[0].is_nan());
+        assert_eq!(results.series("Land"), None);
+
+        let first = results.state_at(0).unwrap();
+        assert_eq!((first.time, first.stocks["Population"]), (0.0, 100.0));
+        assert_eq!(results.final_state().unwrap().auxiliaries["density"], 0.5);
+        assert_eq!(results.to_map()["Capital"], vec![5.0; 3]);
+    }
+}
@@ -8,17 +8,22 @@
 /// - POISSON: Poisson distribution
 /// - WHITE_NOISE: White noise (uncorrelated Gaussian)
 /// - PINK_NOISE: Pink noise (1/f noise, correlated)
+/// - RANDOM_WALK: Brownian random walk
+/// - AR1 / OU_PROCESS: Ornstein-Uhlenbeck process reverting to a mean
 
 use rand::prelude::*;
-use rand_distr::{Distribution, Normal, Poisson, LogNormal};
-use super::noise::{WhiteNoiseGenerator, PinkNoiseGenerator, PinkNoiseKellet};
+use rand_chacha::ChaCha12Rng;
+use rand_distr::{Distribution, Normal, Poisson, LogNormal, StandardNormal};
+use super::noise::{self, NoiseProcess, WhiteNoiseGenerator, PinkNoiseGenerator, PinkNoiseKellet};
 use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
 
 /// Manager for stochastic elements in simulation
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StochasticManager {
-    /// Random number generator
-    rng: StdRng,
+    /// Random number generator (the algorithm behind `StdRng`, but serializable
+    /// so checkpoints can resume the exact random sequence)
+    rng: ChaCha12Rng,
     /// Seed for reproducibility
     seed: Option<u64>,
     /// White noise generators (keyed by identifier)
@@ -27,26 +32,31 @@ pub struct StochasticManager {
     pink_noise_generators: HashMap<String, PinkNoiseGenerator>,
     /// Pink noise generators using Kellet algorithm (better quality)
     pink_noise_kellet_generators: HashMap<String, PinkNoiseKellet>,
+    /// Noise processes stepped with model time (keyed by identifier)
+    #[serde(default)]
+    processes: HashMap<String, NoiseProcess>,
 }
 
 impl StochasticManager {
     pub fn new() -> Self {
         Self {
-            rng: StdRng::from_entropy(),
+            rng: ChaCha12Rng::from_entropy(),
             seed: None,
             white_noise_generators: HashMap::new(),
             pink_noise_generators: HashMap::new(),
             pink_noise_kellet_generators: HashMap::new(),
+            processes: HashMap::new(),
         }
     }
 
     pub fn with_seed(seed: u64) -> Self {
         Self {
-            rng: StdRng::seed_from_u64(seed),
+            rng: ChaCha12Rng::seed_from_u64(seed),
             seed: Some(seed),
             white_noise_generators: HashMap::new(),
             pink_noise_generators: HashMap::new(),
             pink_noise_kellet_generators: HashMap::new(),
+            processes: HashMap::new(),
         }
     }
 
@@ -121,9 +131,65 @@ impl StochasticManager {
         generator.sample(&mut self.rng)
     }
 
+    /// Value at `time` of the Ornstein-Uhlenbeck process `identifier`
+    ///
+    /// The process starts at `initial`, or else at a draw from its stationary
+    /// distribution, and steps once for each new time it is read at.
+    pub fn reverting_process(
+        &mut self,
+        identifier: &str,
+        time: f64,
+        initial: Option<f64>,
+        mean: f64,
+        std_dev: f64,
+        correlation_time: f64,
+    ) -> Result<f64, String> {
+        if std_dev < 0.0 || correlation_time < 0.0 {
+            return Err("Noise standard deviation and correlation time must be non-negative".to_string());
+        }
+        let rng = &mut self.rng;
+        let process = self.processes.entry(identifier.to_string()).or_insert_with(|| {
+            let value = initial.unwrap_or_else(|| mean + std_dev * rng.sample::<f64, _>(StandardNormal));
+            NoiseProcess::new(value, time)
+        });
+        if let Some(dt) = process.advance(time) {
+            process.value = noise::reverting_step(rng, process.value, mean, std_dev, correlation_time, dt);
+        }
+        Ok(process.value)
+    }
+
+    /// Value at `time` of the random walk `identifier`, starting at `initial`
+    /// and spreading by `std_dev` per unit of time
+    pub fn random_walk(&mut self, identifier: &str, time: f64, initial: f64, std_dev: f64) -> Result<f64, String> {
+        if std_dev < 0.0 {
+            return Err("Random walk standard deviation must be non-negative".to_string());
+        }
+        let process = self.processes.entry(identifier.to_string())
+            .or_insert_with(|| NoiseProcess::new(initial, time));
+        if let Some(dt) = process.advance(time) {
+            process.value = noise::random_walk_step(&mut self.rng, process.value, std_dev, dt);
+        }
+        Ok(process.value)
+    }
+
+    /// Value at `time` of the pink noise source `identifier`, drawing one
+    /// sample per step
+    pub fn pink_process(&mut self, identifier: &str, time: f64, mean: f64, amplitude: f64) -> f64 {
+        let generator = self.pink_noise_kellet_generators
+            .entry(identifier.to_string())
+            .or_insert_with(|| PinkNoiseKellet::new(1.0, 0.0));
+        let rng = &mut self.rng;
+        let process = self.processes.entry(identifier.to_string())
+            .or_insert_with(|| NoiseProcess::new(generator.sample(rng), time));
+        if process.advance(time).is_some() {
+            process.value = generator.sample(rng);
+        }
+        mean + amplitude * process.value
+    }
+
     /// Reset RNG with a new seed
     pub fn reseed(&mut self, seed: u64) {
-        self.rng = StdRng::seed_from_u64(seed);
+        self.rng = ChaCha12Rng::seed_from_u64(seed);
         self.seed = Some(seed);
 
         // Reset all noise generators
@@ -133,6 +199,7 @@ impl StochasticManager {
         for generator in self.pink_noise_kellet_generators.values_mut() {
             generator.reset();
         }
+        self.processes.clear();
     }
 }
 
@@ -175,6 +242,20 @@ mod tests {
         assert!(val >= 0.0);
     }
 
+    #[test]
+    fn test_processes_step_with_time() {
+        let mut mgr = StochasticManager::with_seed(42);
+        let start = mgr.random_walk("walk", 0.0, 5.0, 1.0).unwrap();
+        assert_eq!(start, 5.0);
+        let next = mgr.random_walk("walk", 1.0, 5.0, 1.0).unwrap();
+        assert_ne!(next, start);
+        // Reading again within the same step does not move the walk
+        assert_eq!(mgr.random_walk("walk", 1.0, 5.0, 1.0).unwrap(), next);
+
+        assert_eq!(mgr.reverting_process("ou", 0.0, Some(2.0), 0.0, 1.0, 3.0).unwrap(), 2.0);
+        assert!(mgr.reverting_process("bad", 0.0, None, 0.0, -1.0, 3.0).is_err());
+    }
+
     #[test]
     fn test_reproducibility() {
         let mut mgr1 = StochasticManager::with_seed(123);
@@ -0,0 +1,220 @@
+/// Dense storage for the values in a simulation state
+///
+/// Stock, flow and auxiliary values live in a `Vec<f64>` indexed through a
+/// [`SymbolTable`]. The table is built as a state is initialized and shared by
+/// every state cloned from it, so copying a state copies plain numbers rather
+/// than a map of owned names. Lookup by name (`get`, `insert`, `[name]`,
+/// iteration as `(&String, &f64)` pairs) works as it did with a `HashMap`;
+/// hot loops can use the indices directly.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Index;
+use std::sync::Arc;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::ser::SerializeMap;
+use crate::model::SymbolTable;
+
+#[derive(Clone, Default)]
+pub struct VariableValues {
+    symbols: Arc<SymbolTable>,
+    values: Vec<f64>,
+}
+
+impl VariableValues {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Values for every name in `symbols`, all starting at zero
+    pub fn with_symbols(symbols: Arc<SymbolTable>) -> Self {
+        let values = vec![0.0; symbols.len()];
+        Self { symbols, values }
+    }
+
+    pub fn symbols(&self) -> &Arc<SymbolTable> {
+        &self.symbols
+    }
+
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.symbols.get(name)
+    }
+
+    /// Values in index order
+    pub fn as_slice(&self) -> &[f64] {
+        &self.values
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [f64] {
+        &mut self.values
+    }
+
+    pub fn get(&self, name: &str) -> Option<&f64> {
+        self.symbols.get(name).map(|index| &self.values[index])
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut f64> {
+        self.symbols.get(name).map(|index| &mut self.values[index])
+    }
+
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.symbols.get(name).is_some()
+    }
+
+    /// Set `name`, adding it if it is new; returns the previous value
+    ///
+    /// Adding a name copies the symbol table if other states share it, so
+    /// names should all be added before a state is cloned.
+    pub fn insert(&mut self, name: impl AsRef<str>, value: f64) -> Option<f64> {
+        let name = name.as_ref();
+        match self.symbols.get(name) {
+            Some(index) => Some(std::mem::replace(&mut self.values[index], value)),
+            None => {
+                Arc::make_mut(&mut self.symbols).insert(name);
+                self.values.push(value);
+                None
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// `(name, value)` pairs in index order
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &f64)> + '_ {
+        self.symbols.names().iter().zip(&self.values)
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&String, &mut f64)> + '_ {
+        self.symbols.names().iter().zip(self.values.iter_mut())
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &String> + '_ {
+        self.symbols.names().iter()
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &f64> + '_ {
+        self.values.iter()
+    }
+
+    /// Copy into a map keyed by name
+    pub fn to_map(&self) -> HashMap<String, f64> {
+        self.iter().map(|(name, value)| (name.clone(), *value)).collect()
+    }
+}
+
+impl Index<&str> for VariableValues {
+    type Output = f64;
+
+    fn index(&self, name: &str) -> &f64 {
+        self.get(name).unwrap_or_else(|| panic!("no value for '{}'", name))
+    }
+}
+
+impl Index<&String> for VariableValues {
+    type Output = f64;
+
+    fn index(&self, name: &String) -> &f64 {
+        &self[name.as_str()]
+    }
+}
+
+impl<'a> IntoIterator for &'a VariableValues {
+    type Item = (&'a String, &'a f64);
+    type IntoIter = std::iter::Zip<std::slice::Iter<'a, String>, std::slice::Iter<'a, f64>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.symbols.names().iter().zip(&self.values)
+    }
+}
+
+impl<S: AsRef<str>> FromIterator<(S, f64)> for VariableValues {
+    fn from_iter<I: IntoIterator<Item = (S, f64)>>(iter: I) -> Self {
+        let mut values = Self::new();
+        values.extend(iter);
+        values
+    }
+}
+
+impl<S: AsRef<str>> Extend<(S, f64)> for VariableValues {
+    fn extend<I: IntoIterator<Item = (S, f64)>>(&mut self, iter: I) {
+        for (name, value) in iter {
+            self.insert(name, value);
+        }
+    }
+}
+
+impl From<HashMap<String, f64>> for VariableValues {
+    /// Names are added in sorted order, so the layout does not depend on hashing
+    fn from(map: HashMap<String, f64>) -> Self {
+        let mut entries: Vec<(String, f64)> = map.into_iter().collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        entries.into_iter().collect()
+    }
+}
+
+/// Equal when every name has the same value, whatever the layout
+impl PartialEq for VariableValues {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().all(|(name, value)| other.get(name) == Some(value))
+    }
+}
+
+impl fmt::Debug for VariableValues {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+// Serialized as a name -> value map, as the HashMap it replaced was, so
+// checkpoints stay readable
+impl Serialize for VariableValues {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.len()))?;
+        for (name, value) in self {
+            map.serialize_entry(name, value)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for VariableValues {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        HashMap::<String, f64>::deserialize(deserializer).map(Self::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_variable_values() {
+        let mut values = VariableValues::new();
+        assert_eq!(values.insert("Population", 100.0), None);
+        assert_eq!(values.insert("Capital", 5.0), None);
+        assert_eq!(values.insert("Population", 110.0), Some(100.0));
+        assert_eq!(values["Population"], 110.0);
+        assert_eq!(values.get("Land"), None);
+        assert_eq!(values.as_slice(), &[110.0, 5.0]);
+
+        // Clones share the symbol table until a new name is added
+        let mut copy = values.clone();
+        assert!(Arc::ptr_eq(values.symbols(), copy.symbols()));
+        *copy.get_mut("Capital").unwrap() = 6.0;
+        assert!(Arc::ptr_eq(values.symbols(), copy.symbols()));
+        copy.insert("Land", 1.0);
+        assert!(!Arc::ptr_eq(values.symbols(), copy.symbols()));
+        assert_eq!((values.len(), copy.len()), (2, 3));
+
+        let json = serde_json::to_string(&values).unwrap();
+        let back: VariableValues = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, values);
+        assert_eq!(back.keys().collect::<Vec<_>>(), vec!["Capital", "Population"]);
+    }
+}
@@ -0,0 +1,302 @@
+/// Stock-and-flow diagrams rendered as SVG
+///
+/// Draws a [`LayoutResult`] the way modelling tools do: stocks as boxes,
+/// flows as pipes through a valve, clouds where a flow comes from or goes
+/// outside the model, and connectors from each input to the variable that
+/// reads it.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use super::layout::{EdgeType, LayoutResult, NodeLayout, NodeType};
+
+type Point = (f64, f64);
+
+const PIPE_COLOR: &str = "#4a5568";
+const CONNECTOR_COLOR: &str = "#c05621";
+const CLOUD_RADIUS: f64 = 13.0;
+const ARROW: f64 = 11.0;
+
+impl LayoutResult {
+    /// Render the layout as a standalone SVG stock-and-flow diagram
+    pub fn to_svg(&self) -> String {
+        let nodes: HashMap<&str, &NodeLayout> = self.nodes.iter().map(|n| (n.id.as_str(), n)).collect();
+        let mut canvas = Canvas::default();
+
+        // Connectors first, so pipes and shapes cover their ends
+        for edge in self.edges.iter().filter(|e| matches!(e.edge_type, EdgeType::Dependency)) {
+            if let (Some(from), Some(to)) = (nodes.get(edge.from.as_str()), nodes.get(edge.to.as_str())) {
+                canvas.connector(from, to);
+            }
+        }
+
+        for flow in self.nodes.iter().filter(|n| matches!(n.node_type, NodeType::Flow)) {
+            let source = self.edges.iter()
+                .find(|e| matches!(e.edge_type, EdgeType::Outflow) && e.to == flow.id)
+                .and_then(|e| nodes.get(e.from.as_str()).copied());
+            let target = self.edges.iter()
+                .find(|e| matches!(e.edge_type, EdgeType::Inflow) && e.from == flow.id)
+                .and_then(|e| nodes.get(e.to.as_str()).copied());
+            canvas.pipe(flow, source, target);
+        }
+
+        for node in &self.nodes {
+            canvas.node(node);
+        }
+        canvas.finish()
+    }
+}
+
+/// SVG elements drawn so far and the area they cover
+struct Canvas {
+    body: String,
+    min: Point,
+    max: Point,
+}
+
+impl Default for Canvas {
+    fn default() -> Self {
+        Self {
+            body: String::new(),
+            min: (f64::INFINITY, f64::INFINITY),
+            max: (f64::NEG_INFINITY, f64::NEG_INFINITY),
+        }
+    }
+}
+
+impl Canvas {
+    fn include(&mut self, (x, y): Point, (half_width, half_height): Point) {
+        self.min = (self.min.0.min(x - half_width), self.min.1.min(y - half_height));
+        self.max = (self.max.0.max(x + half_width), self.max.1.max(y + half_height));
+    }
+
+    /// A curved arrow from the edge of `from` to the edge of `to`
+    fn connector(&mut self, from: &NodeLayout, to: &NodeLayout) {
+        let start = boundary(from, (to.x, to.y));
+        let end = boundary(to, (from.x, from.y));
+        let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+        if dx.hypot(dy) < ARROW {
+            return;
+        }
+        // Bow the line a little to one side, as modelling tools draw connectors
+        let control = ((start.0 + end.0) / 2.0 - dy * 0.15, (start.1 + end.1) / 2.0 + dx * 0.15);
+        let tail = towards(end, control, ARROW * 0.8);
+        let _ = writeln!(
+            self.body,
+            r#"  <path d="M{:.1},{:.1} Q{:.1},{:.1} {:.1},{:.1}" fill="none" stroke="{}" stroke-width="1.2"/>"#,
+            start.0, start.1, control.0, control.1, tail.0, tail.1, CONNECTOR_COLOR,
+        );
+        self.body.push_str(&arrow(end, control, ARROW * 0.8, CONNECTOR_COLOR));
+    }
+
+    /// The pipe of `flow` from its source stock (or a cloud) through the valve to its target
+    fn pipe(&mut self, flow: &NodeLayout, source: Option<&NodeLayout>, target: Option<&NodeLayout>) {
+        let valve = (flow.x, flow.y);
+        let start = source.map(|stock| boundary(stock, valve));
+        let end = target.map(|stock| boundary(stock, valve));
+        let (start, end) = match (start, end) {
+            (Some(start), Some(end)) => (start, end),
+            (None, Some(end)) => (self.cloud(valve, end, (-1.0, 0.0)), end),
+            (Some(start), None) => (start, self.cloud(valve, start, (1.0, 0.0))),
+            (None, None) => return,
+        };
+
+        let before_end = if distance(valve, end) > ARROW { valve } else { start };
+        let tail = towards(end, before_end, ARROW);
+        let points = format!("{:.1},{:.1} {:.1},{:.1} {:.1},{:.1}", start.0, start.1, valve.0, valve.1, tail.0, tail.1);
+        let _ = writeln!(
+            self.body,
+            r#"  <polyline points="{points}" fill="none" stroke="{PIPE_COLOR}" stroke-width="7" stroke-linejoin="round"/>"#,
+        );
+        let _ = writeln!(
+            self.body,
+            r#"  <polyline points="{points}" fill="none" stroke="white" stroke-width="3.5" stroke-linejoin="round"/>"#,
+        );
+        self.body.push_str(&arrow(end, before_end, ARROW * 1.3, PIPE_COLOR));
+    }
+
+    /// A cloud on the far side of `valve` from `stock_edge`, returning where the pipe meets it
+    fn cloud(&mut self, valve: Point, stock_edge: Point, default: Point) -> Point {
+        let reach = distance(valve, stock_edge);
+        let direction = if reach > f64::EPSILON {
+            ((valve.0 - stock_edge.0) / reach, (valve.1 - stock_edge.1) / reach)
+        } else {
+            default
+        };
+        let offset = reach.max(3.0 * CLOUD_RADIUS);
+        let (x, y) = (valve.0 + direction.0 * offset, valve.1 + direction.1 * offset);
+        let _ = writeln!(
+            self.body,
+            "  <path d=\"M{:.1},{:.1} a6,6 0 0 1 1,-10 a7,7 0 0 1 11,-3 a6,6 0 0 1 10,5 a5,5 0 0 1 -1,8 z\" \
+             fill=\"white\" stroke=\"{}\" stroke-width=\"1.2\"/>",
+            x - 11.0, y + 5.0, PIPE_COLOR,
+        );
+        self.include((x, y), (CLOUD_RADIUS, CLOUD_RADIUS));
+        (x - direction.0 * CLOUD_RADIUS, y - direction.1 * CLOUD_RADIUS)
+    }
+
+    /// The shape of a node with its name underneath
+    fn node(&mut self, node: &NodeLayout) {
+        let (x, y) = (node.x, node.y);
+        let (half_width, half_height) = half_size(node);
+        let shape = match node.node_type {
+            NodeType::Stock => format!(
+                r##"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="#ebf4ff" stroke="#2b6cb0" stroke-width="1.5"/>"##,
+                x - half_width, y - half_height, 2.0 * half_width, 2.0 * half_height,
+            ),
+            // A valve: two triangles meeting at the centre of the pipe
+            NodeType::Flow => format!(
+                r#"<path d="M{:.1},{:.1} L{:.1},{:.1} L{:.1},{:.1} L{:.1},{:.1} z" fill="white" stroke="{}" stroke-width="1.5"/>"#,
+                x - half_width, y - half_height, x + half_width, y + half_height,
+                x + half_width, y - half_height, x - half_width, y + half_height, PIPE_COLOR,
+            ),
+            NodeType::Auxiliary => format!(
+                r##"<circle cx="{x:.1}" cy="{y:.1}" r="{half_width:.1}" fill="#fffff0" stroke="#718096" stroke-width="1.2"/>"##,
+            ),
+            NodeType::Parameter => format!(
+                r##"<circle cx="{x:.1}" cy="{y:.1}" r="{half_width:.1}" fill="#edf2f7" stroke="#718096" stroke-width="1.2"/>"##,
+            ),
+        };
+        let _ = writeln!(self.body, "  {}", shape);
+
+        let label = node.label.as_deref().unwrap_or(&node.id);
+        let baseline = y + half_height + 14.0;
+        let _ = writeln!(
+            self.body,
+            r#"  <text x="{:.1}" y="{:.1}" text-anchor="middle">{}</text>"#,
+            x, baseline, escape(label),
+        );
+        self.include((x, y), (half_width, half_height));
+        // Rough extent of the label at 12px
+        self.include((x, baseline - 4.0), (label.chars().count() as f64 * 3.5, 8.0));
+    }
+
+    fn finish(self) -> String {
+        let margin = 20.0;
+        let (min, max) = if self.min.0.is_finite() { (self.min, self.max) } else { ((0.0, 0.0), (0.0, 0.0)) };
+        let (width, height) = (max.0 - min.0 + 2.0 * margin, max.1 - min.1 + 2.0 * margin);
+        let (x, y) = (min.0 - margin, min.1 - margin);
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width:.0}" height="{height:.0}" viewBox="{x:.1} {y:.1} {width:.1} {height:.1}" font-family="sans-serif" font-size="12" fill="#1a202c">"##,
+        );
+        let _ = writeln!(svg, r#"  <rect x="{x:.1}" y="{y:.1}" width="100%" height="100%" fill="white"/>"#);
+        svg.push_str(&self.body);
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+/// Half the drawn width and height of a node
+///
+/// Valves and circles keep a fixed size however large the layout's cell is.
+fn half_size(node: &NodeLayout) -> Point {
+    let half = node.width.min(node.height) / 2.0;
+    match node.node_type {
+        NodeType::Stock => (node.width / 2.0, node.height / 2.0),
+        NodeType::Flow => (half.min(9.0), half.min(9.0)),
+        NodeType::Auxiliary | NodeType::Parameter => (half.min(12.0), half.min(12.0)),
+    }
+}
+
+/// Where the line from the centre of `node` towards `target` leaves its shape
+fn boundary(node: &NodeLayout, target: Point) -> Point {
+    let (dx, dy) = (target.0 - node.x, target.1 - node.y);
+    let length = dx.hypot(dy);
+    if length < f64::EPSILON {
+        return (node.x, node.y);
+    }
+    let (half_width, half_height) = half_size(node);
+    let scale = match node.node_type {
+        NodeType::Stock => (half_width / dx.abs()).min(half_height / dy.abs()),
+        _ => half_width / length,
+    };
+    let scale = scale.min(1.0);
+    (node.x + dx * scale, node.y + dy * scale)
+}
+
+fn distance(a: Point, b: Point) -> f64 {
+    (b.0 - a.0).hypot(b.1 - a.1)
+}
+
+/// The point `length` back from `tip` towards `from`
+fn towards(tip: Point, from: Point, length: f64) -> Point {
+    let d = distance(tip, from);
+    if d < f64::EPSILON {
+        return tip;
+    }
+    (tip.0 + (from.0 - tip.0) / d * length, tip.1 + (from.1 - tip.1) / d * length)
+}
+
+/// A filled arrowhead at `tip`, pointing away from `from`
+fn arrow(tip: Point, from: Point, length: f64, color: &str) -> String {
+    let base = towards(tip, from, length);
+    let (ux, uy) = ((tip.0 - base.0) / length, (tip.1 - base.1) / length);
+    let (nx, ny) = (-uy * length * 0.45, ux * length * 0.45);
+    format!(
+        "  <polygon points=\"{:.1},{:.1} {:.1},{:.1} {:.1},{:.1}\" fill=\"{}\"/>\n",
+        tip.0, tip.1, base.0 + nx, base.1 + ny, base.0 - nx, base.1 - ny, color,
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io::xmile::parse_xmile;
+    use crate::visualization::LayoutEngine;
+
+    const XMILE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<xmile version="1.0">
+  <header><name>Growth</name></header>
+  <model>
+    <variables>
+      <stock name="Population"><eqn>100</eqn><inflow>births</inflow></stock>
+      <flow name="births"><eqn>Population * birth_rate</eqn></flow>
+      <aux name="birth_rate"><eqn>0.03</eqn></aux>
+    </variables>
+    <views>
+      <view>
+        <stock name="Population" x="300" y="200"/>
+        <flow name="births" x="200" y="200"><pts><pt x="120" y="200"/><pt x="277" y="200"/></pts></flow>
+        <aux name="birth_rate" x="200" y="280"/>
+        <connector uid="1"><from>birth_rate</from><to>births</to></connector>
+      </view>
+    </views>
+  </model>
+</xmile>"#;
+
+    #[test]
+    fn test_diagram_from_xmile_view() {
+        let model = parse_xmile(XMILE).unwrap();
+        assert_eq!(model.positions.len(), 3);
+
+        let layout = LayoutEngine::auto_layout(&model);
+        let node = |id: &str| layout.nodes.iter().find(|n| n.id == id).unwrap();
+        // The view's arrangement survives, shifted to the margin
+        assert_eq!(node("births").y, node("Population").y);
+        assert_eq!(node("Population").x - node("births").x, 100.0);
+        assert_eq!(layout.edges.iter().filter(|e| e.from == "birth_rate").count(), 1);
+
+        let svg = layout.to_svg();
+        assert!(svg.starts_with("<svg"));
+        assert_eq!(svg.matches("<rect").count(), 2); // background and the stock
+        assert_eq!(svg.matches("<polyline").count(), 2); // the births pipe
+        assert_eq!(svg.matches("a7,7").count(), 1); // its source cloud
+        assert_eq!(svg.matches("<circle").count(), 1);
+        assert!(svg.contains(">birth_rate</text>"));
+    }
+
+    #[test]
+    fn test_diagram_without_view() {
+        let model = crate::io::load_model("examples/sir_epidemic.yaml").unwrap();
+        let svg = LayoutEngine::auto_layout(&model).to_svg();
+        // Stock to stock flows need no clouds
+        assert_eq!(svg.matches("a7,7").count(), 0);
+        assert_eq!(svg.matches("<polyline").count(), 2 * model.flows.len());
+        assert_eq!(svg.matches("<text").count(), model.stocks.len() + model.flows.len()
+            + model.auxiliaries.len() + model.parameters.len());
+    }
+}
@@ -2,7 +2,8 @@
 
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use crate::model::Model;
+use crate::analysis::structure::DependencyGraph;
+use crate::model::{Expression, Model};
 use super::graph::{build_graph_from_model, GraphNodeType, GraphEdgeType};
 
 /// Node type for visualization
@@ -103,6 +104,9 @@ impl LayoutEngine {
         for name in model.parameters.keys() {
             layers[2].push(name.clone());
         }
+        for layer in &mut layers {
+            layer.sort();
+        }
 
         // Compute positions with wrapping
         let mut nodes = Vec::new();
@@ -130,42 +134,7 @@ impl LayoutEngine {
                     let x = start_x + (i as f64) * node_spacing_x;
                     let y = current_y;
 
-                    // Determine node type and dimensions
-                    let (node_type, width, height) = if model.stocks.contains_key(name) {
-                        (NodeType::Stock, 120.0, 80.0)
-                    } else if model.flows.contains_key(name) {
-                        (NodeType::Flow, 100.0, 60.0)
-                    } else if model.auxiliaries.contains_key(name) {
-                        (NodeType::Auxiliary, 80.0, 80.0)
-                    } else {
-                        (NodeType::Parameter, 70.0, 70.0)
-                    };
-
-                    // Get additional info
-                    let (value, units, equation) = if let Some(stock) = model.stocks.get(name) {
-                        (None, stock.units.clone(), None)
-                    } else if let Some(flow) = model.flows.get(name) {
-                        (None, flow.units.clone(), Some(format!("{}", flow.equation)))
-                    } else if let Some(aux) = model.auxiliaries.get(name) {
-                        (None, aux.units.clone(), Some(format!("{}", aux.equation)))
-                    } else if let Some(param) = model.parameters.get(name) {
-                        (Some(param.value), param.units.clone(), None)
-                    } else {
-                        (None, None, None)
-                    };
-
-                    nodes.push(NodeLayout {
-                        id: name.clone(),
-                        node_type,
-                        x,
-                        y,
-                        width,
-                        height,
-                        label: Some(name.clone()),
-                        value,
-                        units,
-                        equation,
-                    });
+                    nodes.push(node_layout(model, name, x, y));
                 }
 
                 current_y += node_spacing_y;
@@ -175,68 +144,59 @@ impl LayoutEngine {
             current_y += 50.0;
         }
 
-        // Build edges
-        let mut edges = Vec::new();
-        let edge_id_counter = std::cell::Cell::new(0);
-
-        for (stock_name, stock) in &model.stocks {
-            for inflow in &stock.inflows {
-                if model.flows.contains_key(inflow) {
-                    edge_id_counter.set(edge_id_counter.get() + 1);
-                    edges.push(EdgeLayout {
-                        id: format!("edge_{}", edge_id_counter.get()),
-                        from: inflow.clone(),
-                        to: stock_name.clone(),
-                        edge_type: EdgeType::Inflow,
-                    });
-                }
-            }
-
-            for outflow in &stock.outflows {
-                if model.flows.contains_key(outflow) {
-                    edge_id_counter.set(edge_id_counter.get() + 1);
-                    edges.push(EdgeLayout {
-                        id: format!("edge_{}", edge_id_counter.get()),
-                        from: stock_name.clone(),
-                        to: outflow.clone(),
-                        edge_type: EdgeType::Outflow,
-                    });
-                }
-            }
+        let (width, height) = normalize(&mut nodes, target_width, target_height);
+        LayoutResult {
+            nodes,
+            edges: build_edges(model),
+            width,
+            height,
         }
+    }
 
-        // Calculate bounding box and normalize positions
-        let (actual_width, actual_height) = if nodes.is_empty() {
-            (target_width, target_height)
-        } else {
-            // Find current bounds
-            let min_x = nodes.iter().map(|n| n.x - n.width / 2.0).fold(f64::INFINITY, f64::min);
-            let max_x = nodes.iter().map(|n| n.x + n.width / 2.0).fold(f64::NEG_INFINITY, f64::max);
-            let min_y = nodes.iter().map(|n| n.y - n.height / 2.0).fold(f64::INFINITY, f64::min);
-            let max_y = nodes.iter().map(|n| n.y + n.height / 2.0).fold(f64::NEG_INFINITY, f64::max);
-
-            // Normalize positions to start from 50px margin
-            let margin = 50.0;
-            let offset_x = margin - min_x;
-            let offset_y = margin - min_y;
-
-            for node in &mut nodes {
-                node.x += offset_x;
-                node.y += offset_y;
-            }
-
-            let width = (max_x - min_x + 2.0 * margin).max(target_width);
-            let height = (max_y - min_y + 2.0 * margin).max(target_height);
-
-            (width, height)
-        };
+    /// Layout from the diagram positions stored in the model, or None if it has none
+    ///
+    /// Elements without a position are placed in a row below the diagram.
+    pub fn view_layout(model: &Model) -> Option<LayoutResult> {
+        if model.positions.is_empty() {
+            return None;
+        }
 
-        LayoutResult {
+        let mut names: Vec<&String> = model.stocks.keys()
+            .chain(model.flows.keys())
+            .chain(model.auxiliaries.keys())
+            .chain(model.parameters.keys())
+            .collect();
+        names.sort();
+
+        let bottom = model.positions.values().map(|p| p.y).fold(f64::NEG_INFINITY, f64::max);
+        let left = model.positions.values().map(|p| p.x).fold(f64::INFINITY, f64::min);
+        let mut unplaced = 0;
+        let mut nodes: Vec<NodeLayout> = names.into_iter()
+            .map(|name| {
+                let (x, y) = match model.positions.get(name) {
+                    Some(position) => (position.x, position.y),
+                    None => {
+                        unplaced += 1;
+                        (left + (unplaced - 1) as f64 * 100.0, bottom + 100.0)
+                    }
+                };
+                let mut node = node_layout(model, name, x, y);
+                // XMILE default sizes, which the view's coordinates are drawn for
+                (node.width, node.height) = match node.node_type {
+                    NodeType::Stock => (45.0, 35.0),
+                    _ => (18.0, 18.0),
+                };
+                node
+            })
+            .collect();
+
+        let (width, height) = normalize(&mut nodes, 0.0, 0.0);
+        Some(LayoutResult {
             nodes,
-            edges,
-            width: actual_width,
-            height: actual_height,
-        }
+            edges: build_edges(model),
+            width,
+            height,
+        })
     }
 
     /// Compute force-directed layout (placeholder for future enhancement)
@@ -247,6 +207,10 @@ impl LayoutEngine {
 
     /// Automatic layout selection based on model structure
     pub fn auto_layout(model: &Model) -> LayoutResult {
+        // Keep the modeller's own diagram when the model file has one
+        if let Some(layout) = Self::view_layout(model) {
+            return layout;
+        }
         // Simple heuristic: if mostly linear flow, use hierarchical
         // Otherwise could use force-directed
         Self::hierarchical_layout(model)
@@ -259,3 +223,117 @@ impl NodeLayout {
         (self.x, self.y)
     }
 }
+
+/// Layout of the element `name` centred at `(x, y)`, sized for the hierarchical layout
+fn node_layout(model: &Model, name: &str, x: f64, y: f64) -> NodeLayout {
+    // Determine node type and dimensions
+    let (node_type, width, height) = if model.stocks.contains_key(name) {
+        (NodeType::Stock, 120.0, 80.0)
+    } else if model.flows.contains_key(name) {
+        (NodeType::Flow, 100.0, 60.0)
+    } else if model.auxiliaries.contains_key(name) {
+        (NodeType::Auxiliary, 80.0, 80.0)
+    } else {
+        (NodeType::Parameter, 70.0, 70.0)
+    };
+
+    // Get additional info
+    let (value, units, equation) = if let Some(stock) = model.stocks.get(name) {
+        (None, stock.units.clone(), None)
+    } else if let Some(flow) = model.flows.get(name) {
+        (None, flow.units.clone(), Some(format!("{}", flow.equation)))
+    } else if let Some(aux) = model.auxiliaries.get(name) {
+        (None, aux.units.clone(), Some(format!("{}", aux.equation)))
+    } else if let Some(param) = model.parameters.get(name) {
+        (Some(param.value), param.units.clone(), None)
+    } else {
+        (None, None, None)
+    };
+
+    NodeLayout {
+        id: name.to_string(),
+        node_type,
+        x,
+        y,
+        width,
+        height,
+        label: Some(name.to_string()),
+        value,
+        units,
+        equation,
+    }
+}
+
+/// Flow edges between stocks and their flows, then a dependency edge from
+/// each variable a flow or auxiliary equation reads, in name order
+fn build_edges(model: &Model) -> Vec<EdgeLayout> {
+    let mut links: Vec<(String, String, EdgeType)> = Vec::new();
+
+    let mut stocks: Vec<_> = model.stocks.iter().collect();
+    stocks.sort_by_key(|(name, _)| *name);
+    for (stock_name, stock) in stocks {
+        for inflow in &stock.inflows {
+            if model.flows.contains_key(inflow) {
+                links.push((inflow.clone(), stock_name.clone(), EdgeType::Inflow));
+            }
+        }
+
+        for outflow in &stock.outflows {
+            if model.flows.contains_key(outflow) {
+                links.push((stock_name.clone(), outflow.clone(), EdgeType::Outflow));
+            }
+        }
+    }
+
+    let is_node = |name: &str| {
+        model.stocks.contains_key(name) || model.flows.contains_key(name)
+            || model.auxiliaries.contains_key(name) || model.parameters.contains_key(name)
+    };
+    let mut equations: Vec<(&String, &Expression)> = model.flows.iter().map(|(name, flow)| (name, &flow.equation))
+        .chain(model.auxiliaries.iter().map(|(name, aux)| (name, &aux.equation)))
+        .collect();
+    equations.sort_by_key(|(name, _)| *name);
+    for (name, equation) in equations {
+        let mut inputs: Vec<String> = DependencyGraph::extract_dependencies(equation).into_iter()
+            .filter(|input| input != name && is_node(input))
+            .collect();
+        inputs.sort();
+        for input in inputs {
+            links.push((input, name.clone(), EdgeType::Dependency));
+        }
+    }
+
+    links.into_iter()
+        .enumerate()
+        .map(|(i, (from, to, edge_type))| EdgeLayout { id: format!("edge_{}", i + 1), from, to, edge_type })
+        .collect()
+}
+
+/// Shift nodes so the diagram starts at a 50px margin, returning its size
+/// (at least the target size)
+fn normalize(nodes: &mut [NodeLayout], target_width: f64, target_height: f64) -> (f64, f64) {
+    if nodes.is_empty() {
+        return (target_width, target_height);
+    }
+
+    // Find current bounds
+    let min_x = nodes.iter().map(|n| n.x - n.width / 2.0).fold(f64::INFINITY, f64::min);
+    let max_x = nodes.iter().map(|n| n.x + n.width / 2.0).fold(f64::NEG_INFINITY, f64::max);
+    let min_y = nodes.iter().map(|n| n.y - n.height / 2.0).fold(f64::INFINITY, f64::min);
+    let max_y = nodes.iter().map(|n| n.y + n.height / 2.0).fold(f64::NEG_INFINITY, f64::max);
+
+    // Normalize positions to start from 50px margin
+    let margin = 50.0;
+    let offset_x = margin - min_x;
+    let offset_y = margin - min_y;
+
+    for node in nodes.iter_mut() {
+        node.x += offset_x;
+        node.y += offset_y;
+    }
+
+    let width = (max_x - min_x + 2.0 * margin).max(target_width);
+    let height = (max_y - min_y + 2.0 * margin).max(target_height);
+
+    (width, height)
+}
@@ -2,6 +2,13 @@
 
 pub mod layout;
 pub mod graph;
+pub mod diagram;
+#[cfg(feature = "plot")]
+pub mod plot;
+#[cfg(feature = "plot")]
+pub mod phase;
 
 pub use layout::{LayoutEngine, LayoutResult, NodeLayout, EdgeLayout, NodeType, EdgeType};
 pub use graph::{DependencyGraph, build_graph_from_model};
+#[cfg(feature = "plot")]
+pub use plot::{ChartOptions, Figure};
@@ -0,0 +1,135 @@
+/// Phase portraits rendered to SVG or PNG
+///
+/// Draws a [`PhasePortrait`] with one stock on each axis: the vector field as
+/// arrows of equal length showing direction, the nullclines as dotted curves
+/// and the simulated trajectories as lines from a marked starting point.
+
+use std::path::Path;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use crate::analysis::PhasePortrait;
+use super::plot::{format_tick, ChartOptions};
+
+/// Arrow length as a fraction of the spacing between vector field samples
+const ARROW_LENGTH: f64 = 0.7;
+
+impl PhasePortrait {
+    /// Render to `path`; the extension picks SVG (`.svg`) or PNG (`.png`).
+    /// The axes are labelled with the stocks, and `options.log_x` and
+    /// `options.log_y` are ignored.
+    pub fn render(&self, path: &Path, options: &ChartOptions) -> Result<(), String> {
+        let size = (options.width, options.height);
+        match path.extension().and_then(|e| e.to_str()).map(str::to_lowercase).as_deref() {
+            Some("svg") => self.draw(&SVGBackend::new(path, size).into_drawing_area(), options),
+            Some("png") => self.draw(&BitMapBackend::new(path, size).into_drawing_area(), options),
+            _ => Err(format!("Unsupported chart format '{}' (expected .svg or .png)", path.display())),
+        }
+    }
+
+    fn draw<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>, options: &ChartOptions) -> Result<(), String> {
+        let fail = |e: DrawingAreaErrorKind<DB::ErrorType>| format!("Failed to draw chart: {}", e);
+        root.fill(&WHITE).map_err(fail)?;
+
+        let ((x0, x1), (y0, y1)) = (self.x_range, self.y_range);
+        let mut builder = ChartBuilder::on(root);
+        builder.margin(16).x_label_area_size(40).y_label_area_size(70);
+        if let Some(title) = &options.title {
+            builder.caption(title, ("sans-serif", 24));
+        }
+        let mut chart = builder.build_cartesian_2d(x0..x1, y0..y1).map_err(fail)?;
+        chart.configure_mesh()
+            .x_desc(&self.x)
+            .y_desc(&self.y)
+            .x_label_formatter(&|value| format_tick(*value))
+            .y_label_formatter(&|value| format_tick(*value))
+            .draw()
+            .map_err(fail)?;
+
+        // Arrows are scaled in fractions of each axis so they look alike at any aspect ratio
+        let grid = (self.vectors.len() as f64).sqrt().max(1.0);
+        let length = ARROW_LENGTH / grid;
+        let arrow_color = RGBColor(120, 120, 120);
+        for v in &self.vectors {
+            let (u, w) = (v.dx / (x1 - x0), v.dy / (y1 - y0));
+            let norm = u.hypot(w);
+            if !norm.is_finite() || norm == 0.0 {
+                continue;
+            }
+            let to_data = |a: f64, b: f64| (v.x + a * (x1 - x0), v.y + b * (y1 - y0));
+            let (u, w) = (u / norm * length, w / norm * length);
+            let tip = to_data(u, w);
+            let head = |angle: f64| {
+                let (sin, cos) = (angle.sin(), angle.cos());
+                to_data(u - 0.35 * (u * cos - w * sin), w - 0.35 * (u * sin + w * cos))
+            };
+            let shapes = [
+                vec![(v.x, v.y), tip],
+                vec![head(0.45), tip, head(-0.45)],
+            ];
+            chart.draw_series(shapes.into_iter().map(|points| PathElement::new(points, arrow_color)))
+                .map_err(fail)?;
+        }
+
+        for (i, (name, points)) in [(&self.x, &self.x_nullcline), (&self.y, &self.y_nullcline)].into_iter().enumerate() {
+            let color = if i == 0 { RED } else { GREEN };
+            chart.draw_series(points.iter().map(|&point| Circle::new(point, 1, color.filled())))
+                .map_err(fail)?
+                .label(format!("{} nullcline", name))
+                .legend(move |(x, y)| Circle::new((x + 8, y), 3, color.filled()));
+        }
+
+        let trajectory_color = BLUE.mix(0.8);
+        let inside = |&(x, y): &(f64, f64)| (x0..=x1).contains(&x) && (y0..=y1).contains(&y);
+        for (i, trajectory) in self.trajectories.iter().enumerate() {
+            // Break the line where it leaves the window rather than running along the frame
+            let segments = trajectory.points.split(|point| !inside(point)).filter(|segment| segment.len() > 1);
+            let series = chart.draw_series(segments.map(|segment| PathElement::new(segment.to_vec(), trajectory_color.stroke_width(2))))
+                .map_err(fail)?;
+            if i == 0 {
+                series.label("trajectories")
+                    .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 16, y)], trajectory_color.stroke_width(2)));
+            }
+            if let Some(&start) = trajectory.points.first() {
+                chart.draw_series(std::iter::once(Circle::new(start, 3, trajectory_color.filled())))
+                    .map_err(fail)?;
+            }
+        }
+
+        chart.configure_series_labels()
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .position(SeriesLabelPosition::UpperRight)
+            .draw()
+            .map_err(fail)?;
+        root.present().map_err(fail)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::PhasePlane;
+    use crate::model::{Flow, Model, Stock};
+
+    #[test]
+    fn test_phase_portrait_svg() {
+        let mut model = Model::new("Rotation");
+        model.time.stop = 1.0;
+        model.add_stock(Stock::new("X", "1")).unwrap();
+        model.add_stock(Stock::new("Y", "0")).unwrap();
+        model.add_flow(Flow::new("dx", "-Y")).unwrap();
+        model.add_flow(Flow::new("dy", "X")).unwrap();
+        model.stocks.get_mut("X").unwrap().inflows.push("dx".to_string());
+        model.stocks.get_mut("Y").unwrap().inflows.push("dy".to_string());
+
+        let plane = PhasePlane { grid: 4, starts: 2, resolution: 10, ..PhasePlane::new("X", "Y", (-1.0, 1.0), (-1.0, 1.0)) };
+        let portrait = plane.run(&model).unwrap();
+
+        let path = std::env::temp_dir().join(format!("rsedsim-phase-{}.svg", std::process::id()));
+        portrait.render(&path, &ChartOptions::default()).unwrap();
+        let svg = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains("X nullcline") && svg.contains("trajectories"));
+    }
+}
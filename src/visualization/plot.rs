@@ -0,0 +1,266 @@
+/// Line and fan charts of simulation output, rendered to SVG or PNG
+///
+/// A [`Figure`] is built from result columns: one line per variable, or,
+/// for the statistics CSV that `rsedsim montecarlo` writes, a fan of the
+/// 5–95 and 25–75 percentile bands around the median.
+
+use std::path::Path;
+use plotters::coord::{ranged1d::{AsRangedCoord, ValueFormatter}, Shift};
+use plotters::prelude::*;
+use crate::analysis::ObservedData;
+
+/// Columns of a Monte Carlo statistics CSV used for a fan chart
+const FAN_COLUMNS: [&str; 5] = ["p5", "p25", "median", "p75", "p95"];
+
+/// Chart size, titles and axis scales
+#[derive(Debug, Clone)]
+pub struct ChartOptions {
+    pub title: Option<String>,
+    pub x_label: String,
+    pub y_label: Option<String>,
+    pub width: u32,
+    pub height: u32,
+    pub log_x: bool,
+    pub log_y: bool,
+    /// Label the time axis with dates: times are days since 1970-01-01, as
+    /// [`ObservedData`] reads ISO dates
+    pub dates: bool,
+}
+
+impl Default for ChartOptions {
+    fn default() -> Self {
+        Self {
+            title: None,
+            x_label: "Time".to_string(),
+            y_label: None,
+            width: 1024,
+            height: 640,
+            log_x: false,
+            log_y: false,
+            dates: false,
+        }
+    }
+}
+
+/// A named series of `(time, value)` points
+#[derive(Debug, Clone)]
+pub struct Line {
+    pub name: String,
+    pub points: Vec<(f64, f64)>,
+}
+
+/// A shaded band between a lower and an upper series
+#[derive(Debug, Clone)]
+pub struct Band {
+    pub name: String,
+    pub times: Vec<f64>,
+    pub lower: Vec<f64>,
+    pub upper: Vec<f64>,
+}
+
+/// What to draw: bands first, lines over them
+#[derive(Debug, Clone, Default)]
+pub struct Figure {
+    pub bands: Vec<Band>,
+    pub lines: Vec<Line>,
+}
+
+impl Figure {
+    /// One line per variable in `variables`, in that order
+    pub fn lines(data: &ObservedData, variables: &[String]) -> Result<Self, String> {
+        let lines = variables.iter()
+            .map(|name| {
+                let values = data.series.get(name)
+                    .ok_or_else(|| format!("Variable '{}' not found", name))?;
+                let points = data.times.iter().copied().zip(values.iter().copied())
+                    .filter(|(_, value)| value.is_finite())
+                    .collect();
+                Ok(Line { name: name.clone(), points })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self { bands: Vec::new(), lines })
+    }
+
+    /// A fan chart from Monte Carlo statistics, or None if `data` lacks the percentile columns
+    pub fn fan(data: &ObservedData) -> Option<Self> {
+        let [p5, p25, _, p75, p95] = FAN_COLUMNS.map(|name| data.series.get(name));
+        let band = |name: &str, lower: &Vec<f64>, upper: &Vec<f64>| Band {
+            name: name.to_string(),
+            times: data.times.clone(),
+            lower: lower.clone(),
+            upper: upper.clone(),
+        };
+        let mut figure = Self {
+            bands: vec![band("5–95%", p5?, p95?), band("25–75%", p25?, p75?)],
+            lines: Figure::lines(data, &["median".to_string()]).ok()?.lines,
+        };
+        if data.series.contains_key("mean") {
+            figure.lines.extend(Figure::lines(data, &["mean".to_string()]).ok()?.lines);
+        }
+        Some(figure)
+    }
+
+    /// Smallest and largest time and value, keeping only positive ones on log axes
+    fn bounds(&self, options: &ChartOptions) -> Option<((f64, f64), (f64, f64))> {
+        let band_points = self.bands.iter().flat_map(|band| {
+            band.times.iter().zip(&band.lower).chain(band.times.iter().zip(&band.upper))
+                .map(|(&t, &v)| (t, v))
+        });
+        let points = self.lines.iter().flat_map(|line| line.points.iter().copied()).chain(band_points)
+            .filter(|&(t, v)| t.is_finite() && v.is_finite())
+            .filter(|&(t, v)| (!options.log_x || t > 0.0) && (!options.log_y || v > 0.0));
+
+        let mut bounds: Option<((f64, f64), (f64, f64))> = None;
+        for (t, v) in points {
+            let ((t0, t1), (v0, v1)) = bounds.get_or_insert(((t, t), (v, v)));
+            *t0 = t0.min(t);
+            *t1 = t1.max(t);
+            *v0 = v0.min(v);
+            *v1 = v1.max(v);
+        }
+        bounds
+    }
+
+    /// Render to `path`; the extension picks SVG (`.svg`) or PNG (`.png`)
+    pub fn render(&self, path: &Path, options: &ChartOptions) -> Result<(), String> {
+        let size = (options.width, options.height);
+        match path.extension().and_then(|e| e.to_str()).map(str::to_lowercase).as_deref() {
+            Some("svg") => self.draw(&SVGBackend::new(path, size).into_drawing_area(), options),
+            Some("png") => self.draw(&BitMapBackend::new(path, size).into_drawing_area(), options),
+            _ => Err(format!("Unsupported chart format '{}' (expected .svg or .png)", path.display())),
+        }
+    }
+
+    fn draw<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>, options: &ChartOptions) -> Result<(), String> {
+        let ((t0, t1), (v0, v1)) = self.bounds(options).ok_or("Nothing to plot")?;
+        // Pad the value axis so lines do not run along the frame
+        let (v0, v1) = if options.log_y {
+            (v0 / 1.1, v1 * 1.1)
+        } else {
+            let pad = ((v1 - v0) * 0.05).max(f64::EPSILON.max(v1.abs() * 1e-6));
+            (if v0 >= 0.0 { (v0 - pad).max(0.0) } else { v0 - pad }, v1 + pad)
+        };
+        let t1 = if t1 > t0 { t1 } else { t0 + 1.0 };
+
+        match (options.log_x, options.log_y) {
+            (false, false) => self.draw_on(root, t0..t1, v0..v1, options),
+            (true, false) => self.draw_on(root, (t0..t1).log_scale(), v0..v1, options),
+            (false, true) => self.draw_on(root, t0..t1, (v0..v1).log_scale(), options),
+            (true, true) => self.draw_on(root, (t0..t1).log_scale(), (v0..v1).log_scale(), options),
+        }
+    }
+
+    fn draw_on<DB, X, Y>(&self, root: &DrawingArea<DB, Shift>, x: X, y: Y, options: &ChartOptions) -> Result<(), String>
+    where
+        DB: DrawingBackend,
+        X: AsRangedCoord<Value = f64>,
+        Y: AsRangedCoord<Value = f64>,
+        X::CoordDescType: ValueFormatter<f64>,
+        Y::CoordDescType: ValueFormatter<f64>,
+    {
+        let fail = |e: DrawingAreaErrorKind<DB::ErrorType>| format!("Failed to draw chart: {}", e);
+        root.fill(&WHITE).map_err(fail)?;
+
+        let mut builder = ChartBuilder::on(root);
+        builder.margin(16).x_label_area_size(40).y_label_area_size(70);
+        if let Some(title) = &options.title {
+            builder.caption(title, ("sans-serif", 24));
+        }
+        let mut chart = builder.build_cartesian_2d(x, y).map_err(fail)?;
+
+        let time_tick = |value: &f64| if options.dates { format_date_tick(*value) } else { format_tick(*value) };
+        let mut mesh = chart.configure_mesh();
+        mesh.x_desc(&options.x_label)
+            .x_label_formatter(&time_tick)
+            .y_label_formatter(&|value| format_tick(*value));
+        if let Some(label) = &options.y_label {
+            mesh.y_desc(label);
+        }
+        mesh.draw().map_err(fail)?;
+
+        for (i, band) in self.bands.iter().enumerate() {
+            let color = BLUE.mix(0.15 + 0.15 * i as f64);
+            let upper = band.times.iter().copied().zip(band.upper.iter().copied());
+            let lower = band.times.iter().copied().zip(band.lower.iter().copied()).rev();
+            let outline: Vec<(f64, f64)> = upper.chain(lower)
+                .filter(|&(t, v)| t.is_finite() && v.is_finite() && (!options.log_y || v > 0.0))
+                .collect();
+            chart.draw_series(std::iter::once(Polygon::new(outline, color.filled())))
+                .map_err(fail)?
+                .label(&band.name)
+                .legend(move |(x, y)| Rectangle::new([(x, y - 5), (x + 16, y + 5)], color.filled()));
+        }
+
+        for (i, line) in self.lines.iter().enumerate() {
+            let color = Palette99::pick(i).to_rgba();
+            let points = line.points.iter().copied()
+                .filter(|&(t, v)| (!options.log_x || t > 0.0) && (!options.log_y || v > 0.0));
+            chart.draw_series(LineSeries::new(points, color.stroke_width(2)))
+                .map_err(fail)?
+                .label(&line.name)
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 16, y)], color.stroke_width(2)));
+        }
+
+        chart.configure_series_labels()
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .position(SeriesLabelPosition::UpperRight)
+            .draw()
+            .map_err(fail)?;
+        root.present().map_err(fail)
+    }
+}
+
+/// Axis label for `value`: plain for moderate magnitudes, scientific otherwise
+pub(super) fn format_tick(value: f64) -> String {
+    let magnitude = value.abs();
+    if value == 0.0 || (1e-3..1e5).contains(&magnitude) {
+        let text = format!("{:.3}", value);
+        text.trim_end_matches('0').trim_end_matches('.').to_string()
+    } else {
+        format!("{:.1e}", value)
+    }
+}
+
+fn format_date_tick(days: f64) -> String {
+    crate::analysis::calibration::date_of_days(days).format("%Y-%m-%d").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_chart_svg() {
+        let data = ObservedData::from_csv("Time,Population,Resources\n0,10,100\n1,20,90\n2,40,70\n").unwrap();
+        let figure = Figure::lines(&data, &["Population".to_string(), "Resources".to_string()]).unwrap();
+        assert!(Figure::lines(&data, &["Missing".to_string()]).is_err());
+        assert!(Figure::fan(&data).is_none());
+
+        let path = std::env::temp_dir().join(format!("rsedsim-plot-{}.svg", std::process::id()));
+        let options = ChartOptions { title: Some("Growth".to_string()), log_y: true, ..ChartOptions::default() };
+        figure.render(&path, &options).unwrap();
+        let svg = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains("Growth") && svg.contains("Population"));
+    }
+
+    #[test]
+    fn test_fan_chart() {
+        let csv = "time,mean,p5,p25,median,p75,p95\n0,1,0.5,0.8,1,1.2,1.5\n1,2,1,1.5,2,2.5,3\n";
+        let data = ObservedData::from_csv(csv).unwrap();
+        let figure = Figure::fan(&data).unwrap();
+        assert_eq!(figure.bands.len(), 2);
+        assert_eq!(figure.lines.iter().map(|l| l.name.as_str()).collect::<Vec<_>>(), ["median", "mean"]);
+        assert_eq!(figure.bounds(&ChartOptions::default()), Some(((0.0, 1.0), (0.5, 3.0))));
+    }
+
+    #[test]
+    fn test_format_tick() {
+        assert_eq!(format_tick(0.0), "0");
+        assert_eq!(format_tick(2.5), "2.5");
+        assert_eq!(format_tick(150000.0), "1.5e5");
+        assert_eq!(format_date_tick(19723.0), "2024-01-01");
+    }
+}
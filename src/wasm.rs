@@ -0,0 +1,139 @@
+/// WebAssembly bindings for running models in a browser
+///
+/// Built with the `wasm` feature and without the default `server` feature,
+/// e.g. `wasm-pack build --no-default-features --features wasm`. From
+/// JavaScript:
+///
+/// ```js
+/// const sim = new Simulation();
+/// sim.loadModel(modelJson);
+/// sim.run({ integrator: "rk4", dt: 0.125 });
+/// const population = sim.getSeries("Population");
+/// ```
+
+use serde::Deserialize;
+use wasm_bindgen::prelude::*;
+use crate::model::Model;
+use crate::simulation::{IntegrationMethod, SimulationConfig, SimulationEngine, SimulationResults};
+
+/// Run settings passed to [`Simulation::run`]; every field is optional
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct RunOptions {
+    /// Integration method by name: euler, rk4, rk45, heun, backward-euler, bdf
+    integrator: Option<String>,
+    /// Overrides the model's time step
+    dt: Option<f64>,
+    /// Overrides the model's stop time
+    stop: Option<f64>,
+    /// Spacing of recorded output
+    output_interval: Option<f64>,
+    /// Variables to record; all of them when unset
+    variables: Option<Vec<String>>,
+}
+
+/// A loaded model and the results of its last run
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct Simulation {
+    model: Option<Model>,
+    results: Option<SimulationResults>,
+}
+
+#[wasm_bindgen]
+impl Simulation {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load a model from JSON, in the native or InsightMaker format
+    #[wasm_bindgen(js_name = loadModel)]
+    pub fn load_model(&mut self, json: &str) -> Result<(), JsError> {
+        self.model = Some(crate::io::parse_json(json)?);
+        self.results = None;
+        Ok(())
+    }
+
+    /// Run the loaded model, returning the number of recorded points
+    pub fn run(&mut self, options: JsValue) -> Result<usize, JsError> {
+        let options: RunOptions = if options.is_undefined() || options.is_null() {
+            RunOptions::default()
+        } else {
+            serde_wasm_bindgen::from_value(options)?
+        };
+        self.run_with(options).map_err(|e| JsError::new(&e))
+    }
+
+    /// Recorded times of the last run
+    pub fn times(&self) -> Vec<f64> {
+        self.results.as_ref().map(|results| results.times.clone()).unwrap_or_default()
+    }
+
+    /// Names of the variables recorded in the last run
+    pub fn variables(&self) -> Vec<String> {
+        self.results.as_ref()
+            .map(|results| results.variables().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Values of `name` at each recorded time, or `undefined` if it was not recorded
+    #[wasm_bindgen(js_name = getSeries)]
+    pub fn get_series(&self, name: &str) -> Option<Vec<f64>> {
+        self.results.as_ref()?.get_variable_series(name)
+    }
+}
+
+impl Simulation {
+    fn run_with(&mut self, options: RunOptions) -> Result<usize, String> {
+        let mut model = self.model.clone().ok_or("No model loaded")?;
+        if let Some(dt) = options.dt {
+            model.time.dt = dt;
+        }
+        if let Some(stop) = options.stop {
+            model.time.stop = stop;
+        }
+        let integration_method = match &options.integrator {
+            Some(name) => name.parse::<IntegrationMethod>()?,
+            None => IntegrationMethod::RK4,
+        };
+        let config = SimulationConfig {
+            integration_method,
+            output_interval: options.output_interval,
+            output_variables: options.variables,
+            ..SimulationConfig::default()
+        };
+
+        let results = SimulationEngine::new(model, config).and_then(|mut engine| engine.run())
+            .map_err(|e| e.to_string())?;
+        let points = results.len();
+        self.results = Some(results);
+        Ok(points)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_and_run() {
+        let mut sim = Simulation::new();
+        assert_eq!(sim.run_with(RunOptions::default()).unwrap_err(), "No model loaded");
+
+        sim.load_model(&std::fs::read_to_string("examples/bank_account.json").unwrap()).unwrap();
+        // Options arrive from JavaScript in camelCase
+        let options: RunOptions = serde_json::from_value(serde_json::json!({
+            "integrator": "euler", "dt": 1.0, "stop": 2.0, "outputInterval": 1.0, "variables": ["balance"],
+        })).unwrap();
+        assert_eq!(sim.run_with(options).unwrap(), 3);
+
+        assert_eq!(sim.times(), vec![0.0, 1.0, 2.0]);
+        assert_eq!(sim.variables(), vec!["balance".to_string()]);
+        assert_eq!(sim.get_series("balance"), Some(vec![1000.0, 1050.0, 1102.5]));
+        assert_eq!(sim.get_series("interest"), None);
+
+        let options = RunOptions { integrator: Some("nope".to_string()), ..RunOptions::default() };
+        assert!(sim.run_with(options).is_err());
+    }
+}
@@ -0,0 +1,76 @@
+/// Checks that the benchmark models behave as their analytic solutions say,
+/// so the benchmarks time correct runs
+
+#[path = "../benches/models/mod.rs"]
+mod models;
+
+use rssdsim::analysis::{MonteCarloConfig, ParallelMonteCarloSimulator, ParameterRange};
+use rssdsim::model::Model;
+use rssdsim::simulation::{IntegrationMethod, SimulationConfig, SimulationEngine, SimulationResults};
+
+fn run(model: Model, method: IntegrationMethod) -> SimulationResults {
+    let config = SimulationConfig { integration_method: method, ..SimulationConfig::default() };
+    SimulationEngine::new(model, config).unwrap().run().unwrap()
+}
+
+fn series(results: &SimulationResults, name: &str) -> Vec<f64> {
+    results.get_variable_series(name).unwrap()
+}
+
+const METHODS: [IntegrationMethod; 3] = [IntegrationMethod::Euler, IntegrationMethod::RK4, IntegrationMethod::RK45];
+
+#[test]
+fn test_logistic_and_bass_reach_analytic_values() {
+    for method in METHODS {
+        // K / (1 + (K / P0 - 1) e^(-r t)) at t = 100
+        let population = *series(&run(models::logistic(), method), "Population").last().unwrap();
+        let expected = 1000.0 / (1.0 + 99.0 * (-10.0_f64).exp());
+        assert!((population - expected).abs() / expected < 0.01, "{:?}: {}", method, population);
+
+        // m (1 - e^(-(p+q) t)) / (1 + (q/p) e^(-(p+q) t)) at t = 30
+        let bass = run(models::bass_diffusion(), method);
+        let decay = (-0.41_f64 * 30.0).exp();
+        let expected = 10000.0 * (1.0 - decay) / (1.0 + 0.38 / 0.03 * decay);
+        let adopters = series(&bass, "Adopters");
+        assert!((adopters.last().unwrap() - expected).abs() / expected < 0.001, "{:?}: {:?}", method, adopters.last());
+        for (adopters, potential) in adopters.iter().zip(series(&bass, "Potential")) {
+            assert!((adopters + potential - 10000.0).abs() < 1e-6);
+        }
+    }
+}
+
+#[test]
+fn test_sir_and_predator_prey_invariants() {
+    for method in METHODS {
+        let sir = run(models::sir(), method);
+        let (s, i, r) = (series(&sir, "Susceptible"), series(&sir, "Infected"), series(&sir, "Recovered"));
+        for t in 0..s.len() {
+            assert!((s[t] + i[t] + r[t] - 1000.0).abs() < 1e-6, "{:?} row {}", method, t);
+        }
+
+        // delta x - gamma ln x + beta y - alpha ln y is constant along exact
+        // solutions; Euler spirals outwards, so only bound it loosely
+        let cycles = run(models::predator_prey(), method);
+        let invariant = |x: f64, y: f64| 0.01 * x - 0.1 * x.ln() + 0.02 * y - 0.1 * y.ln();
+        let (prey, predators) = (series(&cycles, "Prey"), series(&cycles, "Predators"));
+        let start = invariant(prey[0], predators[0]);
+        let end = invariant(*prey.last().unwrap(), *predators.last().unwrap());
+        let tolerance = if matches!(method, IntegrationMethod::Euler) { 0.1 } else { 1e-5 };
+        assert!((end - start).abs() < tolerance, "{:?}: {} -> {}", method, start, end);
+    }
+}
+
+#[test]
+fn test_monte_carlo_independent_of_threads() {
+    let simulator = ParallelMonteCarloSimulator::new(
+        vec![ParameterRange::new("beta".to_string(), 0.2, 0.4, 0.3)],
+        MonteCarloConfig { n_runs: 16, seed: Some(42), ..MonteCarloConfig::default() },
+    );
+    let model = models::sir();
+    let infected = |threads: usize| {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+        let results = pool.install(|| simulator.run(&model, &SimulationConfig::default()).unwrap());
+        results.statistics["Infected"].mean.clone()
+    };
+    assert_eq!(infected(1), infected(4));
+}
@@ -0,0 +1,20 @@
+//! Runs a model through the JavaScript bindings on the wasm32 target, where
+//! some of std (the clock, threads, files) is missing
+
+#![cfg(all(target_arch = "wasm32", feature = "wasm"))]
+
+use rssdsim::wasm::Simulation;
+use wasm_bindgen::JsValue;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+#[wasm_bindgen_test]
+fn test_run_model() {
+    let mut sim = Simulation::new();
+    sim.load_model(include_str!("../examples/bank_account.json")).map_err(JsValue::from).unwrap();
+    let points = sim.run(JsValue::UNDEFINED).map_err(JsValue::from).unwrap();
+
+    let balance = sim.get_series("balance").unwrap();
+    assert_eq!(balance.len(), points);
+    assert_eq!(balance[0], 1000.0);
+    assert!(*balance.last().unwrap() > 2000.0);
+}